@@ -4,6 +4,7 @@ extern crate bart_derive;
 
 use std::env;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 #[cfg(feature = "build")]
 use std::time::Instant;
 
@@ -15,13 +16,257 @@ fn submodules() -> PathBuf {
     manifest_dir().join("submodules")
 }
 
+/// `tools/make` of TensorFlow Lite in a TensorFlow tree, with the Makefile,
+/// `download_dependencies.sh`, the `downloads` it fetches and the `gen` directory make builds
+/// into. The same in every 2.x release that still has the make build.
+const MAKE_DIR: &str = "tensorflow/lite/tools/make";
+
+/// The TensorFlow release `TFLITE_RS_TF_VERSION` selects, e.g. `2.3` or `2.3.1`.
+fn requested_tensorflow_version() -> Option<String> {
+    println!("cargo:rerun-if-env-changed=TFLITE_RS_TF_VERSION");
+    let requested = env::var("TFLITE_RS_TF_VERSION").ok()?;
+    let version = requested.trim().trim_start_matches('v');
+    let numbers: Vec<_> = version.split('.').collect();
+    if !(2..=3).contains(&numbers.len()) || numbers.iter().any(|n| n.parse::<u32>().is_err()) {
+        panic!("TFLITE_RS_TF_VERSION is {} but must be a release like 2.3 or 2.3.1", requested);
+    }
+    Some(version.to_string())
+}
+
+/// Whether `version` is the `requested` release, or one of its patch releases if it is
+/// `major.minor`.
+fn is_requested_version(version: &str, requested: &str) -> bool {
+    version == requested || version.starts_with(&format!("{}.", requested))
+}
+
+/// The TensorFlow checkout to build and generate bindings from: `TFLITE_SRC_DIR`, e.g. on
+/// machines without the submodules or network access, else `submodules/tensorflow`, or the
+/// release `TFLITE_RS_TF_VERSION` selects if the submodule holds another one.
+fn tensorflow_source() -> PathBuf {
+    println!("cargo:rerun-if-env-changed=TFLITE_SRC_DIR");
+    if let Some(dir) = env::var_os("TFLITE_SRC_DIR").map(PathBuf::from) {
+        if !dir.join("tensorflow/lite").is_dir() {
+            panic!("TFLITE_SRC_DIR is set but {} has no tensorflow/lite", dir.display());
+        }
+        // check_tensorflow_version checks it against TFLITE_RS_TF_VERSION
+        return dir;
+    }
+    let submodule = submodules().join("tensorflow");
+    match requested_tensorflow_version() {
+        Some(requested)
+            if !tensorflow_version(&submodule)
+                .is_some_and(|version| is_requested_version(&version, &requested)) =>
+        {
+            checkout_tensorflow(&submodule, &requested)
+        }
+        _ => submodule,
+    }
+}
+
+/// Exports the tag of TensorFlow `version` (`v<version>`, or `v<version>.0` for
+/// `major.minor`) from the git checkout `repo` into `OUT_DIR`, unless an earlier build did,
+/// fetching the tag from its origin if `repo` lacks it.
+fn checkout_tensorflow(repo: &Path, version: &str) -> PathBuf {
+    let tag = if version.matches('.').count() == 1 {
+        format!("v{}.0", version)
+    } else {
+        format!("v{}", version)
+    };
+    let dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join(format!("tensorflow-{}", tag));
+    let marker = dir.join(".tflite-rs-checkout");
+    if marker.exists() {
+        return dir;
+    }
+    if !repo.join(".git").exists() {
+        panic!(
+            "TFLITE_RS_TF_VERSION is {} but {} is no git checkout to take TensorFlow {} from; \
+             set TFLITE_SRC_DIR to a checkout of it",
+            version,
+            repo.display(),
+            tag
+        );
+    }
+
+    let git = || {
+        let mut git = Command::new("git");
+        git.arg("-C").arg(repo);
+        git
+    };
+    let commit = format!("{}^{{commit}}", tag);
+    let has_tag = git()
+        .args(["rev-parse", "--verify", "--quiet", &commit])
+        .output()
+        .is_ok_and(|output| output.status.success());
+    if !has_tag {
+        println!("Fetching TensorFlow {}", tag);
+        let fetched = git()
+            .args(["fetch", "--depth", "1", "origin", "tag", &tag])
+            .status()
+            .expect("failed to run git");
+        if !fetched.success() {
+            panic!("Unable to fetch TensorFlow {} into {}", tag, repo.display());
+        }
+    }
+
+    println!("Checking out TensorFlow {} to {}", tag, dir.display());
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Unable to create the tensorflow checkout");
+    let mut archive = git()
+        .args(["archive", "--format=tar", &tag])
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run git");
+    let extracted = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(&dir)
+        .stdin(archive.stdout.take().unwrap())
+        .status()
+        .expect("failed to run tar");
+    let archived = archive.wait().expect("failed to run git");
+    if !archived.success() || !extracted.success() {
+        panic!("Unable to check out TensorFlow {} to {}", tag, dir.display());
+    }
+    std::fs::write(&marker, &tag).expect("Unable to write the checkout marker");
+    dir
+}
+
+/// The dependencies `download_dependencies.sh` fetched into the TensorFlow checkout, if it was
+/// run there, else `submodules/downloads`. The script of a release `TFLITE_RS_TF_VERSION`
+/// checked out is run first, since the submodule holds the dependencies of another release.
+fn downloads() -> PathBuf {
+    let source = tensorflow_source();
+    let downloads = source.join(MAKE_DIR).join("downloads");
+    if !downloads.is_dir() && source.join(".tflite-rs-checkout").exists() {
+        let script = source.join(MAKE_DIR).join("download_dependencies.sh");
+        if !script.exists() {
+            panic!(
+                "{} has no {}/download_dependencies.sh; build it with CMake and link it through \
+                 TFLITE_LIB_DIR",
+                source.display(),
+                MAKE_DIR
+            );
+        }
+        println!("Running {}", script.display());
+        let status = Command::new("bash")
+            .arg(&script)
+            .current_dir(&source)
+            .status()
+            .expect("failed to run download_dependencies.sh");
+        if !status.success() {
+            let _ = std::fs::remove_dir_all(&downloads);
+            panic!("{} failed", script.display());
+        }
+    }
+    if downloads.is_dir() {
+        downloads
+    } else {
+        submodules().join("downloads")
+    }
+}
+
+/// A directory with `tensorflow/lite/c/common.h`, which the inline C++ includes, forwarding to
+/// the header declaring the C types in releases before 2.2: `c/c_api_internal.h`, or
+/// `context.h` before 1.14. `None` for trees that have it.
+fn c_types_compat_dir(root: &Path) -> Option<PathBuf> {
+    let lite = root.join("tensorflow/lite");
+    if lite.join("c/common.h").exists() || !lite.is_dir() {
+        return None;
+    }
+    let header = ["tensorflow/lite/c/c_api_internal.h", "tensorflow/lite/context.h"]
+        .iter()
+        .find(|header| root.join(header).exists())?;
+    let dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("compat");
+    let common = dir.join("tensorflow/lite/c/common.h");
+    std::fs::create_dir_all(common.parent().unwrap()).expect("Unable to create compat headers");
+    std::fs::write(&common, format!("#pragma once\n#include \"{}\"\n", header))
+        .expect("Unable to write compat headers");
+    Some(dir)
+}
+
+/// 32-bit ARM targets without ARMv7 (e.g. `arm-unknown-linux-gnueabihf` for Raspberry Pi Zero/1).
+#[cfg(feature = "build")]
+fn is_armv6() -> bool {
+    env::var("TARGET").map(|target| target.starts_with("arm-")).unwrap_or(false)
+}
+
+/// Makes bindgen lay out types for the target rather than the host, so pointer-width
+/// dependent structs match when cross compiling to 32-bit targets.
+fn target_clang_args() -> Vec<String> {
+    match (env::var("TARGET"), env::var("HOST")) {
+        (Ok(target), Ok(host)) if target != host => vec![format!("--target={}", target)],
+        _ => Vec::new(),
+    }
+}
+
+/// Downloaded dependencies that can be replaced with a local checkout through
+/// `TFLITE_RS_<NAME>_DIR`, e.g. to build with a patched eigen.
+#[cfg(feature = "build")]
+const OVERRIDABLE_DOWNLOADS: &[&str] = &["gemmlowp", "eigen", "farmhash"];
+
+#[cfg(feature = "build")]
+fn download_override(name: &str) -> Option<PathBuf> {
+    let var = format!("TFLITE_RS_{}_DIR", name.to_uppercase());
+    println!("cargo:rerun-if-env-changed={}", var);
+    let dir = PathBuf::from(env::var_os(&var)?);
+    if !dir.is_dir() {
+        panic!("{} is set but {} is not a directory", var, dir.display());
+    }
+    Some(dir)
+}
+
+/// Replaces downloaded dependencies with the overriding directories, restoring the
+/// submodule versions of those that are no longer overridden. A `.<name>-source` file
+/// records where each one was copied from. Returns whether anything changed.
+#[cfg(feature = "build")]
+fn apply_download_overrides(download_dir: &Path) -> bool {
+    let mut changed = false;
+    for name in OVERRIDABLE_DOWNLOADS {
+        let default = downloads().join(name);
+        let source = download_override(name).unwrap_or_else(|| default.clone());
+        let marker = download_dir.join(format!(".{}-source", name));
+        let copied_from =
+            std::fs::read_to_string(&marker).map(PathBuf::from).unwrap_or_else(|_| default.clone());
+        if copied_from != source {
+            println!("Using {} from {}", name, source.display());
+            let target = download_dir.join(name);
+            if target.exists() {
+                std::fs::remove_dir_all(&target)
+                    .unwrap_or_else(|_| panic!("Unable to remove downloaded {}", name));
+            }
+            let copy_inside = fs_extra::dir::CopyOptions {
+                overwrite: true,
+                skip_exist: false,
+                buffer_size: 65536,
+                copy_inside: true,
+                depth: 0,
+            };
+            fs_extra::dir::copy(&source, &target, &copy_inside)
+                .unwrap_or_else(|_| panic!("Unable to copy {} from {}", name, source.display()));
+            changed = true;
+        }
+        std::fs::write(&marker, source.to_string_lossy().as_bytes())
+            .expect("Unable to write download source");
+    }
+    changed
+}
+
 #[cfg(feature = "build")]
 fn prepare_tensorflow_source() -> PathBuf {
     println!("Moving tflite source");
     let start = Instant::now();
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let tf_src_dir = out_dir.join("tensorflow/tensorflow");
-    let submodules = submodules();
+    let source = tensorflow_source();
+
+    // a copy of another checkout must not be reused
+    let marker = out_dir.join("tensorflow-source");
+    let copied_from = std::fs::read_to_string(&marker).map(PathBuf::from).ok();
+    if tf_src_dir.exists() && copied_from.as_ref() != Some(&source) {
+        println!("Using tensorflow from {}", source.display());
+        std::fs::remove_dir_all(out_dir.join("tensorflow"))
+            .expect("Unable to remove the previous tensorflow copy");
+    }
 
     let copy_dir = fs_extra::dir::CopyOptions {
         overwrite: true,
@@ -32,39 +277,57 @@ fn prepare_tensorflow_source() -> PathBuf {
     };
 
     if !tf_src_dir.exists() {
-        fs_extra::dir::copy(submodules.join("tensorflow"), &out_dir, &copy_dir)
+        let copy_inside = fs_extra::dir::CopyOptions {
+            overwrite: true,
+            skip_exist: false,
+            buffer_size: 65536,
+            copy_inside: true,
+            depth: 0,
+        };
+        fs_extra::dir::copy(&source, out_dir.join("tensorflow"), &copy_inside)
             .expect("Unable to copy tensorflow");
+        std::fs::write(&marker, source.to_string_lossy().as_bytes())
+            .expect("Unable to write tensorflow source");
 
-        // TODO: remove these when we upgrade tensorflow far enough that they exist
-        for f in &["aarch64_makefile.inc", "linux_makefile.inc"] {
-            std::fs::copy(
-                manifest_dir().join("data").join(f),
-                tf_src_dir.join("lite/tools/make/targets").join(f),
-            )
-            .unwrap_or_else(|_| panic!("Unable to copy makefile {}", f));
+        let make_dir = out_dir.join("tensorflow").join(MAKE_DIR);
+        if cfg!(feature = "xnnpack") {
+            if !out_dir.join("tensorflow/tensorflow/lite/CMakeLists.txt").exists() {
+                panic!(
+                    "[feature = xnnpack] builds TensorFlow Lite with CMake, which TensorFlow {} \
+                     has no tensorflow/lite/CMakeLists.txt for; select 2.4 or later with \
+                     TFLITE_RS_TF_VERSION",
+                    tensorflow_version(&source).unwrap_or_default()
+                );
+            }
+        } else if !make_dir.join("Makefile").exists() {
+            panic!(
+                "TensorFlow {} has no {}/Makefile; build it with CMake and link it through \
+                 TFLITE_LIB_DIR",
+                tensorflow_version(&source).unwrap_or_default(),
+                MAKE_DIR
+            );
+        }
+        // older TensorFlow versions lack these targets, newer ones bring their own
+        for f in &["aarch64_makefile.inc", "armv6_makefile.inc", "linux_makefile.inc"] {
+            let target = make_dir.join("targets").join(f);
+            if !target.exists() {
+                std::fs::copy(manifest_dir().join("data").join(f), &target)
+                    .unwrap_or_else(|_| panic!("Unable to copy makefile {}", f));
+            }
         }
     }
 
-    let download_dir = tf_src_dir.join("lite/tools/make/downloads");
+    // a checkout with downloaded dependencies brought them along
+    let make_dir = out_dir.join("tensorflow").join(MAKE_DIR);
+    let download_dir = make_dir.join("downloads");
     if !download_dir.exists() {
-        fs_extra::dir::copy(
-            submodules.join("downloads"),
-            download_dir.parent().unwrap(),
-            &copy_dir,
-        )
-        .expect("Unable to copy download dir");
-
-        let flatbuffers_h = download_dir.join("flatbuffers/include/flatbuffers/flatbuffers.h");
-        let flatbuffers =
-            std::fs::read_to_string(&flatbuffers_h).expect("Unable to read flatbuffers.h");
-        std::fs::write(
-            flatbuffers_h,
-            flatbuffers.replace(
-                "struct NativeTable { virtual ~NativeTable() {} };",
-                "struct NativeTable {};",
-            ),
-        )
-        .expect("Unable to write to flatbuffers.h");
+        fs_extra::dir::copy(downloads(), download_dir.parent().unwrap(), &copy_dir)
+            .expect("Unable to copy download dir");
+    }
+    patch_flatbuffers(&download_dir.join("flatbuffers/include/flatbuffers/flatbuffers.h"));
+    if apply_download_overrides(&download_dir) {
+        // objects built against the previous versions are not tracked by make
+        let _ = std::fs::remove_dir_all(make_dir.join("gen"));
     }
 
     println!("Moving source took {:?}", start.elapsed());
@@ -72,6 +335,82 @@ fn prepare_tensorflow_source() -> PathBuf {
     tf_src_dir
 }
 
+/// Drops the virtual destructor of `flatbuffers::NativeTable`, which the bindings of the
+/// object API types do not have.
+#[cfg(feature = "build")]
+fn patch_flatbuffers(flatbuffers_h: &Path) {
+    let flatbuffers = std::fs::read_to_string(flatbuffers_h).expect("Unable to read flatbuffers.h");
+    let patched = flatbuffers
+        .replace("struct NativeTable { virtual ~NativeTable() {} };", "struct NativeTable {};");
+    if patched != flatbuffers {
+        std::fs::write(flatbuffers_h, patched).expect("Unable to write to flatbuffers.h");
+    }
+}
+
+/// `major.minor.patch` of the TensorFlow tree at `root`, from `core/public/version.h`.
+fn tensorflow_version(root: &Path) -> Option<String> {
+    let header = std::fs::read_to_string(root.join("tensorflow/core/public/version.h")).ok()?;
+    let number = |name: &str| {
+        header.lines().find_map(|line| {
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next(), words.next()) {
+                (Some("#define"), Some(n), Some(value)) if n == name => Some(value.to_string()),
+                _ => None,
+            }
+        })
+    };
+    Some(format!(
+        "{}.{}.{}",
+        number("TF_MAJOR_VERSION")?,
+        number("TF_MINOR_VERSION")?,
+        number("TF_PATCH_VERSION")?
+    ))
+}
+
+/// Checks the TensorFlow tree the bindings are generated from against `TFLITE_RS_TF_VERSION`,
+/// which only selects the release when the tree comes from the submodule, and passes its
+/// version on to the crate as `TFLITE_RS_DETECTED_TF_VERSION`.
+fn check_tensorflow_version() {
+    let root = include_dirs().remove(0);
+    if !root.join("tensorflow/lite").is_dir() && root.join("tensorflow/contrib/lite").is_dir() {
+        panic!(
+            "{} has the TensorFlow 1.x tensorflow/contrib/lite layout, which is not supported",
+            root.display()
+        );
+    }
+    let version = tensorflow_version(&root);
+    println!("cargo:rerun-if-changed={}", root.join("tensorflow/core/public/version.h").display());
+    match (requested_tensorflow_version(), &version) {
+        (Some(requested), Some(version)) if !is_requested_version(version, &requested) => {
+            panic!(
+                "TFLITE_RS_TF_VERSION is {} but {} holds TensorFlow {}; unset TFLITE_SRC_DIR \
+                 and TFLITE_INCLUDE_DIR to check out {} from the submodule, or point them at \
+                 that version",
+                requested,
+                root.display(),
+                version,
+                requested
+            )
+        }
+        (Some(requested), None) => println!(
+            "cargo:warning=cannot check TFLITE_RS_TF_VERSION={}: {} has no \
+             tensorflow/core/public/version.h",
+            requested,
+            root.display()
+        ),
+        _ => {}
+    }
+    println!(
+        "cargo:rustc-env=TFLITE_RS_DETECTED_TF_VERSION={}",
+        version.unwrap_or_else(|| "unknown".to_string())
+    );
+}
+
+/// Whether to build the NNAPI delegate, which only exists on Android.
+fn nnapi() -> bool {
+    cfg!(feature = "nnapi") && env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("android")
+}
+
 fn binary_changing_features() -> String {
     let mut features = String::new();
     if cfg!(feature = "debug_tflite") {
@@ -80,14 +419,381 @@ fn binary_changing_features() -> String {
     if cfg!(feature = "no_micro") {
         features.push_str("-no_micro");
     }
+    if nnapi() {
+        features.push_str("-nnapi");
+    }
+    if cfg!(feature = "xnnpack") {
+        features.push_str("-xnnpack");
+    }
+    #[cfg(feature = "build")]
+    {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        // overridden sources must not reuse a library built with other versions
+        let mut overrides: Vec<_> =
+            OVERRIDABLE_DOWNLOADS.iter().map(|name| download_override(name)).collect();
+        overrides.push(env::var_os("TFLITE_SRC_DIR").map(PathBuf::from));
+        overrides.push(requested_tensorflow_version().map(PathBuf::from));
+        if overrides.iter().any(Option::is_some) {
+            let mut hasher = DefaultHasher::new();
+            overrides.hash(&mut hasher);
+            features.push_str(&format!("-deps{:016x}", hasher.finish()));
+        }
+    }
     features
 }
 
+/// The directory of an already built TensorFlow Lite library, from `TFLITE_<ARCH>_LIB_DIR` or
+/// `TFLITE_LIB_DIR`. Setting either skips building TensorFlow Lite from source.
+fn prebuilt_library_dir(arch: &str) -> Option<(String, PathBuf)> {
+    let arch_var = format!("TFLITE_{}_LIB_DIR", arch.replace("-", "_").to_uppercase());
+    let all_var = "TFLITE_LIB_DIR".to_string();
+    println!("cargo:rerun-if-env-changed={}", arch_var);
+    println!("cargo:rerun-if-env-changed={}", all_var);
+    [arch_var, all_var].iter().find_map(|var| Some((var.clone(), PathBuf::from(env::var_os(var)?))))
+}
+
+/// Directories with the TensorFlow Lite and FlatBuffers headers. `TFLITE_INCLUDE_DIR` replaces
+/// the submodule sources for a prebuilt library; it must hold `tensorflow/lite` of the version
+/// this crate is pinned to and may hold `flatbuffers`, else the submodule copy is used.
+fn include_dirs() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-env-changed=TFLITE_INCLUDE_DIR");
+    let flatbuffers = downloads().join("flatbuffers/include");
+    let mut dirs = match env::var_os("TFLITE_INCLUDE_DIR").map(PathBuf::from) {
+        Some(dir) => {
+            if !dir.join("tensorflow/lite").is_dir() {
+                panic!("TFLITE_INCLUDE_DIR is set but {} has no tensorflow/lite", dir.display());
+            }
+            if dir.join("flatbuffers").is_dir() {
+                vec![dir]
+            } else {
+                vec![dir, flatbuffers]
+            }
+        }
+        None => vec![tensorflow_source(), flatbuffers],
+    };
+    dirs.extend(c_types_compat_dir(&dirs[0]));
+    dirs
+}
+
+/// Static libraries of a CMake build with `TFLITE_ENABLE_XNNPACK=ON` that a static
+/// `libtensorflow-lite.a` needs for the XNNPACK delegate, in link order.
+const XNNPACK_LIBRARIES: [&str; 4] = ["XNNPACK", "pthreadpool", "cpuinfo", "clog"];
+
+fn link_prebuilt_library(var: &str, lib_dir: &Path) {
+    if !lib_dir.is_dir() {
+        panic!("{} is set but {} is not a directory", var, lib_dir.display());
+    }
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    let static_dynamic =
+        if lib_dir.join("libtensorflow-lite.a").exists() { "static" } else { "dylib" };
+    println!("cargo:rustc-link-lib={}=tensorflow-lite", static_dynamic);
+    if cfg!(feature = "xnnpack") && static_dynamic == "static" {
+        // Missing ones may have been merged into libtensorflow-lite.a.
+        for name in XNNPACK_LIBRARIES {
+            if lib_dir.join(format!("lib{}.a", name)).exists() {
+                println!("cargo:rustc-link-lib=static={}", name);
+            }
+        }
+    }
+    println!("cargo:rerun-if-changed={}", lib_dir.display());
+}
+
+/// Links the GPU delegate, which TensorFlow Lite only builds with Bazel, from
+/// `TFLITE_GPU_LIB_DIR`.
+fn link_gpu_delegate() {
+    println!("cargo:rerun-if-env-changed=TFLITE_GPU_LIB_DIR");
+    let lib_dir = env::var_os("TFLITE_GPU_LIB_DIR").map(PathBuf::from).unwrap_or_else(|| {
+        panic!(
+            "[feature = gpu] needs TFLITE_GPU_LIB_DIR, a directory with \
+             libtensorflowlite_gpu_delegate.so built by `bazel build -c opt \
+             //tensorflow/lite/delegates/gpu:libtensorflowlite_gpu_delegate.so` from the \
+             submodule"
+        )
+    });
+    if !lib_dir.join("libtensorflowlite_gpu_delegate.so").exists() {
+        panic!(
+            "TFLITE_GPU_LIB_DIR is set but {} has no libtensorflowlite_gpu_delegate.so",
+            lib_dir.display()
+        );
+    }
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib=dylib=tensorflowlite_gpu_delegate");
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("android") {
+        println!("cargo:rustc-link-lib=dylib=EGL");
+        println!("cargo:rustc-link-lib=dylib=GLESv3");
+    }
+}
+
+/// SHA-256 of `data`.
+#[cfg(feature = "prebuilt")]
+fn sha256(data: &[u8]) -> String {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (h, v) in h.iter_mut().zip(&[a, b, c, d, e, f, g, hh]) {
+            *h = h.wrapping_add(*v);
+        }
+    }
+    h.iter().map(|h| format!("{:08x}", h)).collect()
+}
+
+/// Where the `prebuilt` feature downloads `libtensorflow-lite-<target>.a` from and the SHA-256
+/// it must have: `TFLITE_RS_PREBUILT_URL`, a base URL serving
+/// `v<version>/libtensorflow-lite-<target>.a`, and `TFLITE_RS_PREBUILT_SHA256`. No official
+/// libraries are published yet, so both are required.
+#[cfg(feature = "prebuilt")]
+fn prebuilt_source(target: &str) -> (String, String) {
+    println!("cargo:rerun-if-env-changed=TFLITE_RS_PREBUILT_URL");
+    println!("cargo:rerun-if-env-changed=TFLITE_RS_PREBUILT_SHA256");
+    let var =
+        |name: &str| env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    match (var("TFLITE_RS_PREBUILT_URL"), var("TFLITE_RS_PREBUILT_SHA256")) {
+        (Some(base), Some(checksum)) => {
+            if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+                panic!("TFLITE_RS_PREBUILT_SHA256 is not a hex SHA-256: {}", checksum);
+            }
+            let url = format!(
+                "{}/v{}/libtensorflow-lite-{}.a",
+                base.trim_end_matches('/'),
+                env::var("CARGO_PKG_VERSION").unwrap(),
+                target
+            );
+            (url, checksum.to_lowercase())
+        }
+        (url, checksum) => panic!(
+            "[feature = prebuilt] needs TFLITE_RS_PREBUILT_URL and TFLITE_RS_PREBUILT_SHA256 \
+             while no official prebuilt libraries are published, but {} not set. Serve \
+             v{}/libtensorflow-lite-{}.a from a base URL of your own, build from source with \
+             [feature = build] or set TFLITE_LIB_DIR",
+            match (url, checksum) {
+                (None, None) => "both are",
+                (None, _) => "TFLITE_RS_PREBUILT_URL is",
+                _ => "TFLITE_RS_PREBUILT_SHA256 is",
+            },
+            env::var("CARGO_PKG_VERSION").unwrap(),
+            target
+        ),
+    }
+}
+
+/// Downloads the prebuilt library of the target into `OUT_DIR/prebuilt` with curl, unless an
+/// earlier build did, and verifies its checksum. Returns the directory holding it.
+#[cfg(feature = "prebuilt")]
+fn download_prebuilt_library() -> PathBuf {
+    let target = env::var("TARGET").unwrap();
+    let (url, checksum) = prebuilt_source(&target);
+    let lib_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("prebuilt");
+    let library = lib_dir.join("libtensorflow-lite.a");
+    if std::fs::read(&library).map(|data| sha256(&data) == checksum).unwrap_or(false) {
+        return lib_dir;
+    }
+
+    println!("Downloading tflite from {}", url);
+    std::fs::create_dir_all(&lib_dir).expect("Unable to create the prebuilt directory");
+    let download = lib_dir.join("libtensorflow-lite.a.download");
+    let status = std::process::Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error", "--retry", "3", "--output"])
+        .arg(&download)
+        .arg(&url)
+        .status()
+        .expect("failed to run curl");
+    if !status.success() {
+        panic!("Failed to download {}", url);
+    }
+    let actual = sha256(&std::fs::read(&download).expect("Unable to read the download"));
+    if actual != checksum {
+        let _ = std::fs::remove_file(&download);
+        panic!("{} has SHA-256 {}, expected {}", url, actual, checksum);
+    }
+    std::fs::rename(&download, &library).expect("Unable to move the download");
+    lib_dir
+}
+
+/// How many jobs to build TensorFlow Lite with: `TFLITE_RS_MAKE_PARALLELISM`, else cargo's.
+#[cfg(feature = "build")]
+fn build_parallelism() -> String {
+    env::var("TFLITE_RS_MAKE_PARALLELISM")
+        .unwrap_or_else(|_| env::var("NUM_JOBS").unwrap_or_else(|_| "1".to_string()))
+}
+
+/// Builds TensorFlow Lite from the tree at `root` with CMake and `TFLITE_ENABLE_XNNPACK=ON`,
+/// which the make build cannot do, and merges `libtensorflow-lite.a` with the static
+/// libraries of XNNPACK and the other dependencies CMake fetched into `library`.
+#[cfg(feature = "build")]
+fn build_tensorflow_with_cmake(root: &Path, library: &Path) {
+    println!("Building tflite with XNNPACK");
+    let start = Instant::now();
+    let build_dir = library.with_extension("cmake");
+    let run = |command: &mut Command| {
+        eprintln!("cmake command = {:?}", command);
+        if !command.status().expect("failed to run cmake").success() {
+            panic!("Failed to build tensorflow with CMake");
+        }
+    };
+
+    let mut configure = Command::new("cmake");
+    configure
+        .arg("-S")
+        .arg(root.join("tensorflow/lite"))
+        .arg("-B")
+        .arg(&build_dir)
+        .arg("-DTFLITE_ENABLE_XNNPACK=ON")
+        .arg(format!("-DTFLITE_ENABLE_NNAPI={}", if nnapi() { "ON" } else { "OFF" }))
+        .arg("-DCMAKE_POSITION_INDEPENDENT_CODE=ON")
+        .arg(if cfg!(feature = "debug_tflite") {
+            "-DCMAKE_BUILD_TYPE=Debug"
+        } else {
+            "-DCMAKE_BUILD_TYPE=Release"
+        });
+    if env::var("TARGET") != env::var("HOST") {
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+        configure.arg(format!("-DCMAKE_SYSTEM_PROCESSOR={}", arch));
+        if let Ok(prefix) = env::var("TARGET_TOOLCHAIN_PREFIX") {
+            configure
+                .arg(format!("-DCMAKE_C_COMPILER={}gcc", prefix))
+                .arg(format!("-DCMAKE_CXX_COMPILER={}g++", prefix));
+        }
+    }
+    run(&mut configure);
+
+    // configuring fetched the dependencies, flatbuffers among them
+    let mut fetched = vec![build_dir.clone()];
+    while let Some(dir) = fetched.pop() {
+        for entry in std::fs::read_dir(&dir).expect("Unable to read the CMake build").flatten() {
+            let path = entry.path();
+            if path.ends_with("include/flatbuffers/flatbuffers.h") {
+                patch_flatbuffers(&path);
+            } else if path.is_dir() {
+                fetched.push(path);
+            }
+        }
+    }
+
+    run(Command::new("cmake")
+        .arg("--build")
+        .arg(&build_dir)
+        .arg("--target")
+        .arg("tensorflow-lite")
+        .arg("-j")
+        .arg(build_parallelism()));
+
+    let mut libraries = Vec::new();
+    let mut dirs = vec![build_dir];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).expect("Unable to read the CMake build").flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "a") {
+                libraries.push(path);
+            }
+        }
+    }
+    if !libraries.iter().any(|path| path.ends_with("libtensorflow-lite.a")) {
+        panic!("Unable to find libtensorflow-lite.a");
+    }
+    if !libraries.iter().any(|path| path.ends_with("libXNNPACK.a")) {
+        panic!("Unable to find libXNNPACK.a");
+    }
+    merge_static_libraries(&libraries, library);
+    println!("Building tflite with CMake took {:?}", start.elapsed());
+}
+
+/// Merges the static `libraries` into one at `library` with an `ar` MRI script, so the
+/// linker resolves symbols between them regardless of their order.
+#[cfg(feature = "build")]
+fn merge_static_libraries(libraries: &[PathBuf], library: &Path) {
+    let ar = env::var("AR").unwrap_or_else(|_| match env::var("TARGET_TOOLCHAIN_PREFIX") {
+        Ok(prefix) => format!("{}ar", prefix),
+        Err(_) => "ar".to_string(),
+    });
+    let merging = library.with_extension("merging.a");
+    let mut script = format!("CREATE {}\n", merging.display());
+    for path in libraries {
+        script.push_str(&format!("ADDLIB {}\n", path.display()));
+    }
+    script.push_str("SAVE\nEND\n");
+
+    let _ = std::fs::remove_file(&merging);
+    let mut merge = Command::new(&ar)
+        .arg("-M")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap_or_else(|_| panic!("failed to run {}", ar));
+    std::io::Write::write_all(&mut merge.stdin.take().unwrap(), script.as_bytes())
+        .expect("Unable to write the ar script");
+    if !merge.wait().expect("failed to run ar").success() {
+        panic!("Unable to merge the static libraries into {}", library.display());
+    }
+    std::fs::rename(&merging, library)
+        .unwrap_or_else(|_| panic!("Unable to move {}", merging.display()));
+}
+
 fn prepare_tensorflow_library() {
     let arch = env::var("CARGO_CFG_TARGET_ARCH").expect("Unable to get TARGET_ARCH");
 
+    let prebuilt = prebuilt_library_dir(&arch);
+    if let Some((var, lib_dir)) = &prebuilt {
+        println!("Linking prebuilt tflite from {}", lib_dir.display());
+        link_prebuilt_library(var, lib_dir);
+    }
+    if cfg!(feature = "gpu") {
+        link_gpu_delegate();
+    }
+    #[cfg(feature = "prebuilt")]
+    if prebuilt.is_none() {
+        link_prebuilt_library("[feature = prebuilt]", &download_prebuilt_library());
+    }
     #[cfg(feature = "build")]
-    {
+    if prebuilt.is_none() && !cfg!(feature = "prebuilt") {
         let tflite = prepare_tensorflow_source();
         let out_dir = env::var("OUT_DIR").unwrap();
         // append tf_lib_name with features that can change how it is built
@@ -96,7 +802,9 @@ fn prepare_tensorflow_library() {
         let tf_lib_name =
             Path::new(&out_dir).join(format!("libtensorflow-lite{}.a", binary_changing_features,));
         let os = env::var("CARGO_CFG_TARGET_OS").expect("Unable to get TARGET_OS");
-        if !tf_lib_name.exists() {
+        if !tf_lib_name.exists() && cfg!(feature = "xnnpack") {
+            build_tensorflow_with_cmake(tflite.parent().unwrap(), &tf_lib_name);
+        } else if !tf_lib_name.exists() {
             println!("Building tflite");
             let start = Instant::now();
             let mut make = std::process::Command::new("make");
@@ -105,12 +813,18 @@ fn prepare_tensorflow_library() {
             };
             // Use cargo's cross-compilation information while building tensorflow
             // Now that tensorflow has an aarch64_makefile.inc use theirs
-            let target = if &arch == "aarch64" { &arch } else { &os };
+            let target = if &arch == "aarch64" {
+                arch.as_str()
+            } else if is_armv6() {
+                "armv6"
+            } else {
+                os.as_str()
+            };
 
             #[cfg(feature = "debug_tflite")]
             {
                 println!("Feature debug_tflite enabled. Changing optimization to 0");
-                let makefile = tflite.join("lite/tools/make/Makefile");
+                let makefile = tflite.parent().unwrap().join(MAKE_DIR).join("Makefile");
                 let makefile_contents =
                     std::fs::read_to_string(&makefile).expect("Unable to read Makefile");
                 let replaced = makefile_contents.replace("-O3", "-Og -g").replace("-DNDEBUG", "");
@@ -126,14 +840,14 @@ fn prepare_tensorflow_library() {
                 .arg(format!("TARGET_ARCH={}", arch))
                 .arg("-j")
                 // allow parallelism to be overridden
-                .arg(
-                    env::var("TFLITE_RS_MAKE_PARALLELISM").unwrap_or_else(|_| {
-                        env::var("NUM_JOBS").unwrap_or_else(|_| "1".to_string())
-                    }),
-                )
-                .arg("BUILD_WITH_NNAPI=false")
+                .arg(build_parallelism())
+                .arg(format!("BUILD_WITH_NNAPI={}", nnapi()))
                 .arg("-f")
-                .arg("tensorflow/lite/tools/make/Makefile");
+                .arg(Path::new(MAKE_DIR).join("Makefile"));
+
+            if is_armv6() && env::var("TARGET").unwrap().ends_with("gnueabi") {
+                make.arg("ARMV6_FLOAT_ABI=soft");
+            }
 
             if cfg!(feature = "no_micro") {
                 println!("Building lib but no micro");
@@ -148,7 +862,7 @@ fn prepare_tensorflow_library() {
             }
 
             // find library
-            let library = std::fs::read_dir(tflite.join("lite/tools/make/gen"))
+            let library = std::fs::read_dir(make_dir.join(MAKE_DIR).join("gen"))
                 .expect("Make gen file should exist")
                 .filter_map(|de| Some(de.ok()?.path().join("lib/libtensorflow-lite.a")))
                 .find(|p| p.exists())
@@ -162,24 +876,13 @@ fn prepare_tensorflow_library() {
         println!("cargo:rustc-link-search=native={}", out_dir);
         println!("cargo:rustc-link-lib=static=tensorflow-lite{}", binary_changing_features);
     }
-    #[cfg(not(feature = "build"))]
-    {
-        let arch_var = format!("TFLITE_{}_LIB_DIR", arch.replace("-", "_").to_uppercase());
-        let all_var = "TFLITE_LIB_DIR".to_string();
-        let lib_dir = env::var(&arch_var).or(env::var(&all_var)).unwrap_or_else(|_| {
-            panic!(
-                "[feature = build] not set and environment variables {} and {} are not set",
-                arch_var, all_var
-            )
-        });
-        println!("cargo:rustc-link-search=native={}", lib_dir);
-        let static_dynamic = if Path::new(&lib_dir).join("libtensorflow-lite.a").exists() {
-            "static"
-        } else {
-            "dylib"
-        };
-        println!("cargo:rustc-link-lib={}=tensorflow-lite", static_dynamic);
-        println!("cargo:rerun-if-changed={}", lib_dir);
+    #[cfg(not(any(feature = "build", feature = "prebuilt")))]
+    if prebuilt.is_none() {
+        panic!(
+            "[feature = build] and [feature = prebuilt] not set and environment variables \
+             TFLITE_{}_LIB_DIR and TFLITE_LIB_DIR are not set",
+            arch.replace("-", "_").to_uppercase()
+        );
     }
     println!("cargo:rustc-link-lib=dylib=pthread");
     println!("cargo:rustc-link-lib=dylib=dl");
@@ -189,8 +892,6 @@ fn prepare_tensorflow_library() {
 fn import_tflite_types() {
     use bindgen::*;
 
-    let submodules = submodules();
-    let submodules_str = submodules.to_string_lossy();
     let bindings = Builder::default()
         .whitelist_recursively(true)
         .prepend_enum_name(false)
@@ -216,6 +917,7 @@ fn import_tflite_types() {
         .whitelist_type("tflite::OpResolver")
         .opaque_type("tflite::OpResolver")
         .whitelist_type("TfLiteTensor")
+        .whitelist_type("TfLiteAffineQuantization")
         .opaque_type("std::string")
         .opaque_type("flatbuffers::NativeTable")
         .blacklist_type("std")
@@ -225,14 +927,14 @@ fn import_tflite_types() {
         .derive_partialeq(true)
         .derive_eq(true)
         .header("csrc/tflite_wrapper.hpp")
-        .clang_arg(format!("-I{}/tensorflow", submodules_str))
-        .clang_arg(format!("-I{}/downloads/flatbuffers/include", submodules_str))
+        .clang_args(include_dirs().iter().map(|dir| format!("-I{}", dir.display())))
         .clang_arg("-DGEMMLOWP_ALLOW_SLOW_SCALAR_FALLBACK")
         .clang_arg("-x")
         .clang_arg("c++")
         .clang_arg("-std=c++11")
         // required to get cross compilation for aarch64 to work because of an issue in flatbuffers
-        .clang_arg("-fms-extensions");
+        .clang_arg("-fms-extensions")
+        .clang_args(target_clang_args());
 
     let bindings = bindings.generate().expect("Unable to generate bindings");
 
@@ -242,11 +944,11 @@ fn import_tflite_types() {
 }
 
 fn build_inline_cpp() {
-    let submodules = submodules();
-
-    cpp_build::Config::new()
-        .include(submodules.join("tensorflow"))
-        .include(submodules.join("downloads/flatbuffers/include"))
+    let mut config = cpp_build::Config::new();
+    for dir in include_dirs() {
+        config.include(dir);
+    }
+    config
         .flag("-fPIC")
         .flag("-std=c++14")
         .flag("-Wno-sign-compare")
@@ -274,6 +976,7 @@ fn import_stl_types() {
         .clang_arg("c++")
         .clang_arg("-std=c++14")
         .clang_arg("-fms-extensions")
+        .clang_args(target_clang_args())
         .generate()
         .expect("Unable to generate STL bindings");
 
@@ -505,15 +1208,91 @@ use super::{{BuiltinOptions, BuiltinOptionsUnion, NativeTable}};
     Ok(())
 }
 
+/// Bindings kept in `data/bindings` for docs builds, which cannot rely on libclang.
+const VENDORED_BINDINGS: &[&str] = &["tflite_types.rs", "stl_types.rs"];
+
+/// Whether to only build what rustdoc needs, on docs.rs or with the `docs_only` feature:
+/// the bindings come from `data/bindings`, no C++ is compiled and TensorFlow Lite is neither
+/// built nor linked.
+fn docs_only() -> bool {
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    cfg!(feature = "docs_only") || env::var_os("DOCS_RS").is_some()
+}
+
+/// Fails the build up front, naming every missing file, when the vendored bindings are not
+/// all there or are empty, rather than midway through compiling the inline C++.
+fn check_vendored_bindings() {
+    let vendored = manifest_dir().join("data/bindings");
+    println!("cargo:rerun-if-changed={}", vendored.display());
+    let missing: Vec<_> = VENDORED_BINDINGS
+        .iter()
+        .map(|name| vendored.join(name))
+        .filter(|path| std::fs::metadata(path).map_or(true, |m| m.len() == 0))
+        .map(|path| path.display().to_string())
+        .collect();
+    if !missing.is_empty() {
+        panic!(
+            "docs_only builds need the vendored bindings, but {} missing or empty; generate \
+             them with `TFLITE_RS_UPDATE_BINDINGS=1 cargo build` on x86_64-unknown-linux-gnu and \
+             commit data/bindings",
+            missing.join(", ") + if missing.len() == 1 { " is" } else { " are" }
+        );
+    }
+}
+
+fn use_vendored_bindings() {
+    check_vendored_bindings();
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    for name in VENDORED_BINDINGS {
+        let vendored = manifest_dir().join("data/bindings").join(name);
+        println!("cargo:rerun-if-changed={}", vendored.display());
+        std::fs::copy(&vendored, out_dir.join(name))
+            .unwrap_or_else(|e| panic!("Unable to copy {}: {}", vendored.display(), e));
+    }
+}
+
+/// Saves the generated bindings to `data/bindings` if `TFLITE_RS_UPDATE_BINDINGS` is set,
+/// before publishing a version whose TensorFlow headers changed.
+fn update_vendored_bindings() {
+    println!("cargo:rerun-if-env-changed=TFLITE_RS_UPDATE_BINDINGS");
+    if env::var_os("TFLITE_RS_UPDATE_BINDINGS").is_none() {
+        return;
+    }
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let vendored = manifest_dir().join("data/bindings");
+    std::fs::create_dir_all(&vendored).expect("Unable to create data/bindings");
+    for name in VENDORED_BINDINGS {
+        std::fs::copy(out_dir.join(name), vendored.join(name))
+            .unwrap_or_else(|_| panic!("Unable to save {}", name));
+    }
+}
+
 fn main() {
-    import_stl_types();
+    let docs_only = docs_only();
+    if docs_only {
+        check_vendored_bindings();
+    } else {
+        import_stl_types();
+    }
     #[cfg(feature = "generate_model_apis")]
     {
         generate_memory_impl().unwrap();
         generate_vector_impl().unwrap();
         generate_builtin_options_impl().unwrap();
     }
-    import_tflite_types();
-    build_inline_cpp();
-    prepare_tensorflow_library();
+    check_tensorflow_version();
+    if docs_only {
+        use_vendored_bindings();
+    } else {
+        import_tflite_types();
+        update_vendored_bindings();
+    }
+    // the crate's own `cpp!` stands in for the one of the cpp crate, which needs the inline C++
+    println!("cargo:rustc-check-cfg=cfg(docs_only)");
+    if docs_only {
+        println!("cargo:rustc-cfg=docs_only");
+    } else {
+        build_inline_cpp();
+        prepare_tensorflow_library();
+    }
 }
@@ -0,0 +1,48 @@
+// Custom operator plugins loaded with `tflite::ops::plugin::load_plugins`.
+//
+// A plugin is a shared library exporting `tflite_op_plugin`, which returns a descriptor of
+// its custom operators. The descriptor and everything it points to must stay valid while the
+// library is loaded. Build plugins against the TF Lite headers of the loading program.
+//
+//   static const TfLiteRegistration stft = {StftInit, StftFree, StftPrepare, StftEval};
+//   static const TfLitePluginOp ops[] = {{"Stft", 1, &stft}};
+//   static const TfLitePluginDescriptor descriptor = {
+//       TFLITE_OP_PLUGIN_ABI_VERSION, sizeof(TfLiteRegistration), "audio_ops", ops, 1};
+//
+//   extern "C" const TfLitePluginDescriptor* tflite_op_plugin(void) { return &descriptor; }
+
+#ifndef TFLITE_OP_PLUGIN_H_
+#define TFLITE_OP_PLUGIN_H_
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include "tensorflow/lite/c/common.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define TFLITE_OP_PLUGIN_ABI_VERSION 1
+
+typedef struct {
+  const char* name;
+  int version;
+  const TfLiteRegistration* registration;
+} TfLitePluginOp;
+
+typedef struct {
+  uint32_t abi_version;      // TFLITE_OP_PLUGIN_ABI_VERSION
+  size_t registration_size;  // sizeof(TfLiteRegistration)
+  const char* name;
+  const TfLitePluginOp* ops;
+  size_t num_ops;
+} TfLitePluginDescriptor;
+
+const TfLitePluginDescriptor* tflite_op_plugin(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif
+
+#endif  // TFLITE_OP_PLUGIN_H_
@@ -0,0 +1,337 @@
+//! Runs a model on the CPU and with each given delegate, reporting latency and the maximum
+//! divergence of every output from the CPU result.
+//!
+//! ```text
+//! tflite-compare <model> [--runs N] [--threads N] [--energy] [--sustain SECS]
+//!                [--cool-down CELSIUS] [--delegate <lib>[:key=value,...]]...
+//! ```
+//!
+//! Delegates are loaded from shared libraries implementing the TFLite external delegate
+//! interface, e.g. `--delegate libedgetpu.so.1:device=usb:0`. With `--energy`, the energy
+//! consumed by the timed runs is read from RAPL, an INA2xx power sensor or the battery and
+//! reported per inference.
+//!
+//! `--sustain` additionally invokes each configuration back to back for the given time and
+//! reports peak and sustained (last third) throughput, which differ once the device
+//! throttles, along with the highest thermal zone temperature. `--cool-down` waits before
+//! each configuration until all thermal zones are below the given temperature, so every
+//! configuration starts from the same thermal state.
+
+use std::env;
+use std::process;
+use std::time::{Duration, Instant};
+
+use tflite::context::ElementKind;
+use tflite::energy::{self, EnergyMeter};
+use tflite::ops::builtin::BuiltinOpResolver;
+use tflite::thermal::{ThermalMonitor, ThroughputTrace};
+use tflite::{Delegate, Error, FlatBufferModel, Interpreter, InterpreterBuilder, Result};
+
+const USAGE: &str = "usage: tflite-compare <model> [--runs N] [--threads N] [--energy] \
+                     [--sustain SECS] [--cool-down CELSIUS] [--delegate <lib>[:key=value,...]]...";
+
+struct Args {
+    model: String,
+    runs: usize,
+    threads: i32,
+    energy: bool,
+    sustain: Option<Duration>,
+    cool_down: Option<f64>,
+    delegates: Vec<(String, Vec<(String, String)>)>,
+}
+
+fn parse_args() -> std::result::Result<Args, String> {
+    let mut args = env::args().skip(1);
+    let mut model = None;
+    let mut runs = 50;
+    let mut threads = -1;
+    let mut energy = false;
+    let mut sustain = None;
+    let mut cool_down = None;
+    let mut delegates = Vec::new();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "--runs" => runs = value()?.parse().map_err(|e| format!("--runs: {}", e))?,
+            "--threads" => threads = value()?.parse().map_err(|e| format!("--threads: {}", e))?,
+            "--energy" => energy = true,
+            "--sustain" => {
+                let secs = value()?.parse().map_err(|e| format!("--sustain: {}", e))?;
+                sustain = Some(Duration::from_secs(secs));
+            }
+            "--cool-down" => {
+                cool_down = Some(value()?.parse().map_err(|e| format!("--cool-down: {}", e))?)
+            }
+            "--delegate" => {
+                let spec = value()?;
+                let mut parts = spec.splitn(2, ':');
+                let library = parts.next().unwrap().to_string();
+                let options = parts
+                    .next()
+                    .into_iter()
+                    .flat_map(|options| options.split(','))
+                    .map(|option| {
+                        let mut kv = option.splitn(2, '=');
+                        let key = kv.next().unwrap().to_string();
+                        let value = kv.next().ok_or_else(|| format!("bad option `{}`", option))?;
+                        Ok((key, value.to_string()))
+                    })
+                    .collect::<std::result::Result<_, String>>()?;
+                delegates.push((library, options));
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if model.is_none() && !arg.starts_with('-') => model = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`\n{}", arg, USAGE)),
+        }
+    }
+    let model = model.ok_or_else(|| USAGE.to_string())?;
+    if runs == 0 {
+        return Err("--runs must be positive".to_string());
+    }
+    Ok(Args { model, runs, threads, energy, sustain, cool_down, delegates })
+}
+
+/// Fills inputs with the same pseudo-random data for every configuration.
+fn fill_inputs(interpreter: &mut Interpreter<&BuiltinOpResolver>) -> Result<()> {
+    let mut state = 0x2545_f491_u32;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    for index in interpreter.inputs().to_vec() {
+        let kind = interpreter.tensor_info(index).map(|info| info.element_kind);
+        let buffer = interpreter
+            .tensor_buffer_mut(index)
+            .ok_or_else(|| Error::internal_error("input tensor has no buffer"))?;
+        match kind {
+            Some(ElementKind::kTfLiteFloat32) => {
+                for value in buffer.chunks_exact_mut(4) {
+                    let x = (next() >> 8) as f32 / (1 << 24) as f32;
+                    value.copy_from_slice(&x.to_ne_bytes());
+                }
+            }
+            Some(ElementKind::kTfLiteUInt8) | Some(ElementKind::kTfLiteInt8) => {
+                buffer.iter_mut().for_each(|b| *b = next() as u8);
+            }
+            _ => buffer.iter_mut().for_each(|b| *b = 0),
+        }
+    }
+    Ok(())
+}
+
+fn output_values(interpreter: &Interpreter<&BuiltinOpResolver>) -> Result<Vec<Vec<f64>>> {
+    interpreter
+        .outputs()
+        .iter()
+        .map(|&index| {
+            let kind = interpreter.tensor_info(index).map(|info| info.element_kind);
+            let buffer = interpreter
+                .tensor_buffer(index)
+                .ok_or_else(|| Error::internal_error("output tensor has no buffer"))?;
+            Ok(match kind {
+                Some(ElementKind::kTfLiteFloat32) => buffer
+                    .chunks_exact(4)
+                    .map(|b| f64::from(f32::from_ne_bytes([b[0], b[1], b[2], b[3]])))
+                    .collect(),
+                Some(ElementKind::kTfLiteInt32) => buffer
+                    .chunks_exact(4)
+                    .map(|b| f64::from(i32::from_ne_bytes([b[0], b[1], b[2], b[3]])))
+                    .collect(),
+                Some(ElementKind::kTfLiteUInt8) => buffer.iter().map(|&b| f64::from(b)).collect(),
+                Some(ElementKind::kTfLiteInt8) => {
+                    buffer.iter().map(|&b| f64::from(b as i8)).collect()
+                }
+                _ => Vec::new(),
+            })
+        })
+        .collect()
+}
+
+struct RunResult {
+    name: String,
+    mean: Duration,
+    min: Duration,
+    delegated: String,
+    /// Energy per inference in joules.
+    joules: Option<f64>,
+    trace: Option<ThroughputTrace>,
+    outputs: Vec<Vec<f64>>,
+}
+
+/// Optional measurements taken around the timed runs.
+struct Instruments {
+    meter: Option<Box<dyn EnergyMeter>>,
+    thermal: Option<ThermalMonitor>,
+}
+
+/// Longest wait for `--cool-down`.
+const MAX_COOL_DOWN: Duration = Duration::from_secs(600);
+
+fn run(
+    model: &FlatBufferModel,
+    resolver: &BuiltinOpResolver,
+    args: &Args,
+    name: String,
+    delegate: Option<&Delegate>,
+    instruments: &mut Instruments,
+) -> Result<RunResult> {
+    let mut interpreter =
+        InterpreterBuilder::new(model, resolver)?.build_with_threads(args.threads)?;
+    if let Some(delegate) = delegate {
+        interpreter.modify_graph_with_delegate(delegate)?;
+    }
+    interpreter.allocate_tensors()?;
+    fill_inputs(&mut interpreter)?;
+
+    if let (Some(celsius), Some(thermal)) = (args.cool_down, &instruments.thermal) {
+        let waited = thermal.cool_down(celsius, MAX_COOL_DOWN)?;
+        if !waited.is_zero() {
+            eprintln!("{}: cooled down for {:.0} s", name, waited.as_secs_f64());
+        }
+    }
+    // Warm-up, which also covers lazy delegate initialization.
+    interpreter.invoke()?;
+    let mut total = Duration::default();
+    let mut min = Duration::MAX;
+    let mut timed_runs = || {
+        for _ in 0..args.runs {
+            let start = Instant::now();
+            interpreter.invoke()?;
+            let elapsed = start.elapsed();
+            total += elapsed;
+            min = min.min(elapsed);
+        }
+        Ok(())
+    };
+    let joules = match instruments.meter.as_deref_mut() {
+        Some(meter) => Some(energy::measure(meter, timed_runs)?.1 / args.runs as f64),
+        None => {
+            timed_runs()?;
+            None
+        }
+    };
+    let trace = match args.sustain {
+        Some(duration) => Some(ThroughputTrace::record(
+            duration,
+            Duration::from_secs(1),
+            instruments.thermal.as_ref(),
+            || interpreter.invoke(),
+        )?),
+        None => None,
+    };
+
+    let report = interpreter.delegation_report();
+    let delegated = report.delegated_ops().count();
+    Ok(RunResult {
+        name,
+        mean: total / args.runs as u32,
+        min,
+        delegated: format!("{}/{}", delegated, delegated + report.cpu_ops().count()),
+        joules,
+        trace,
+        outputs: output_values(&interpreter)?,
+    })
+}
+
+#[cfg(unix)]
+fn load_delegate(library: &str, options: &[(&str, &str)]) -> Result<Delegate> {
+    Delegate::load_external(library, options)
+}
+
+#[cfg(not(unix))]
+fn load_delegate(_library: &str, _options: &[(&str, &str)]) -> Result<Delegate> {
+    Err(Error::internal_error("external delegates are only supported on Unix"))
+}
+
+fn max_divergence(reference: &[f64], values: &[f64]) -> Option<f64> {
+    if reference.len() != values.len() {
+        return None;
+    }
+    Some(reference.iter().zip(values).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max))
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(2);
+    });
+    if let Err(e) = compare(&args) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn compare(args: &Args) -> Result<()> {
+    let model = FlatBufferModel::build_from_file(&args.model)?;
+    let resolver = BuiltinOpResolver::default();
+
+    let meter = if args.energy {
+        let meter = energy::detect()?;
+        eprintln!("measuring energy with {}", meter.name());
+        Some(meter)
+    } else {
+        None
+    };
+    let thermal = match ThermalMonitor::detect() {
+        Ok(thermal) => Some(thermal),
+        Err(e) if args.cool_down.is_some() => return Err(e),
+        Err(_) => None,
+    };
+    let mut instruments = Instruments { meter, thermal };
+
+    let mut results =
+        vec![run(&model, &resolver, args, "cpu".to_string(), None, &mut instruments)?];
+    for (library, options) in &args.delegates {
+        let options: Vec<(&str, &str)> =
+            options.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        let result = load_delegate(library, &options).and_then(|delegate| {
+            run(&model, &resolver, args, library.clone(), Some(&delegate), &mut instruments)
+        });
+        match result {
+            Ok(result) => results.push(result),
+            Err(e) => eprintln!("{}: {}", library, e),
+        }
+    }
+
+    let reference = &results[0].outputs;
+    print!("{:<32} {:>10} {:>10} {:>10}", "configuration", "mean ms", "min ms", "delegated");
+    if args.energy {
+        print!(" {:>10}", "mJ/inf");
+    }
+    if args.sustain.is_some() {
+        print!(" {:>10} {:>10} {:>8}", "peak /s", "sust. /s", "max °C");
+    }
+    for i in 0..reference.len() {
+        print!(" {:>12}", format!("out{} max|d|", i));
+    }
+    println!();
+    for result in &results {
+        print!(
+            "{:<32} {:>10.3} {:>10.3} {:>10}",
+            result.name,
+            result.mean.as_secs_f64() * 1e3,
+            result.min.as_secs_f64() * 1e3,
+            result.delegated
+        );
+        if let Some(joules) = result.joules {
+            print!(" {:>10.3}", joules * 1e3);
+        }
+        if let Some(trace) = &result.trace {
+            print!(" {:>10.1} {:>10.1}", trace.peak(), trace.sustained());
+            match trace.max_temperature() {
+                Some(celsius) => print!(" {:>8.1}", celsius),
+                None => print!(" {:>8}", "-"),
+            }
+        }
+        for (reference, values) in reference.iter().zip(&result.outputs) {
+            match max_divergence(reference, values) {
+                Some(divergence) => print!(" {:>12.6}", divergence),
+                None => print!(" {:>12}", "shape differs"),
+            }
+        }
+        println!();
+    }
+    Ok(())
+}
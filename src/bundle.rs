@@ -0,0 +1,353 @@
+//! Several models shipped as one archive, a tar or zip file with a `manifest.json` naming
+//! them:
+//!
+//! ```json
+//! { "models": { "detector": "models/detector.tflite", "classifier": "classifier.tflite" } }
+//! ```
+//!
+//! Models are stored uncompressed, so that they are used in place from the memory-mapped
+//! archive:
+//!
+//! ```ignore
+//! let bundle = ModelBundle::open("assets/models.tar")?;
+//! let detector = bundle.model("detector")?;
+//! let classifier = bundle.model("classifier")?;
+//! ```
+
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::run_profile::{json, Value};
+use crate::{Allocation, Error, FlatBufferModel, Mmap, ModelSource, Result};
+
+const MANIFEST: &str = "manifest.json";
+
+/// Models at offsets not aligned to this are copied for the flatbuffer reads to be aligned.
+const ALIGNMENT: usize = 16;
+
+/// A model of a `ModelBundle`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BundledModel {
+    pub name: String,
+    /// Path of the model file in the archive.
+    pub path: String,
+    /// Byte range of the model in the archive.
+    pub range: Range<usize>,
+}
+
+/// An archive of models, each built by name without copying the archive.
+#[derive(Debug)]
+pub struct ModelBundle {
+    allocation: Allocation,
+    models: Vec<BundledModel>,
+}
+
+impl ModelBundle {
+    /// Memory-maps the archive at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_source(Mmap::open(path)?)
+    }
+
+    /// Reads the manifest of an archive, e.g. `include_bytes!("models.tar")`.
+    pub fn from_source<S: Into<ModelSource>>(source: S) -> Result<Self> {
+        let allocation = Allocation::new(source)?;
+        let entries = entries(allocation.as_slice())?;
+        let entry = |path: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.path == path)
+                .ok_or_else(|| invalid(&format!("no `{}` in the archive", path)))
+                .and_then(|entry| {
+                    entry.stored.clone().ok_or_else(|| {
+                        invalid(&format!("`{}` is compressed, store it uncompressed", path))
+                    })
+                })
+        };
+
+        let manifest = std::str::from_utf8(&allocation.as_slice()[entry(MANIFEST)?])
+            .map_err(|_| invalid("manifest is not UTF-8"))?;
+        let models = match json::parse(manifest)? {
+            Value::Table(entries) => {
+                entries.into_iter().find(|(key, _)| key == "models").map(|(_, models)| models)
+            }
+            _ => None,
+        };
+        let models = match models {
+            Some(Value::Table(models)) => models,
+            _ => return Err(invalid("manifest has no `models` table")),
+        };
+        let models = models
+            .into_iter()
+            .map(|(name, path)| match path {
+                Value::String(path) => Ok(BundledModel { range: entry(&path)?, name, path }),
+                _ => Err(invalid(&format!("path of model `{}` is not a string", name))),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { allocation, models })
+    }
+
+    /// Writes a tar archive of `models`, stored as `<name>.tflite`, and their manifest.
+    pub fn pack<W: Write>(mut writer: W, models: &[(&str, &[u8])]) -> Result<()> {
+        let paths: Vec<String> =
+            models.iter().map(|(name, _)| format!("{}.tflite", name)).collect();
+        let manifest = models
+            .iter()
+            .zip(&paths)
+            .map(|((name, _), path)| (name.to_string(), Value::String(path.clone())))
+            .collect();
+        let manifest = Value::Table(vec![("models".to_string(), Value::Table(manifest))]);
+        write_tar_entry(&mut writer, MANIFEST, json::write(&manifest).as_bytes())?;
+        for ((_, bytes), path) in models.iter().zip(&paths) {
+            write_tar_entry(&mut writer, path, bytes)?;
+        }
+        // The end of archive marker.
+        writer.write_all(&[0; 2 * BLOCK])?;
+        Ok(())
+    }
+
+    pub fn models(&self) -> &[BundledModel] {
+        &self.models
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.models.iter().map(|model| model.name.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.models.iter().any(|model| model.name == name)
+    }
+
+    /// The memory of the whole archive.
+    pub fn allocation(&self) -> &Allocation {
+        &self.allocation
+    }
+
+    fn find(&self, name: &str) -> Result<&BundledModel> {
+        self.models
+            .iter()
+            .find(|model| model.name == name)
+            .ok_or_else(|| Error::InternalError(format!("no model `{}` in the bundle", name)))
+    }
+
+    pub fn bytes(&self, name: &str) -> Result<&[u8]> {
+        Ok(&self.allocation.as_slice()[self.find(name)?.range.clone()])
+    }
+
+    /// The model as a source sharing the archive, or a copy if it is not aligned in it.
+    pub fn source(&self, name: &str) -> Result<ModelSource> {
+        let range = self.find(name)?.range.clone();
+        if !(self.allocation.base() as usize + range.start).is_multiple_of(ALIGNMENT) {
+            return Ok(ModelSource::Bytes(self.allocation.as_slice()[range].to_vec()));
+        }
+        Ok(ModelSource::Part(self.allocation.clone(), range))
+    }
+
+    pub fn model(&self, name: &str) -> Result<FlatBufferModel> {
+        FlatBufferModel::build(self.source(name)?)
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InternalError(format!("invalid model bundle: {}", message))
+}
+
+/// A file of an archive, with its data range unless it is compressed.
+struct Entry {
+    path: String,
+    stored: Option<Range<usize>>,
+}
+
+fn entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    if archive.starts_with(b"PK\x03\x04") || archive.starts_with(b"PK\x05\x06") {
+        zip_entries(archive)
+    } else if archive.get(257..262) == Some(b"ustar") {
+        tar_entries(archive)
+    } else {
+        Err(invalid("not a tar or zip archive"))
+    }
+}
+
+const BLOCK: usize = 512;
+
+fn tar_field(header: &[u8], range: Range<usize>) -> &[u8] {
+    let field = &header[range];
+    &field[..field.iter().position(|&b| b == 0).unwrap_or(field.len())]
+}
+
+fn tar_entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut position = 0;
+    while position + BLOCK <= archive.len() {
+        let header = &archive[position..position + BLOCK];
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        let size = std::str::from_utf8(tar_field(header, 124..136))
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.trim(), 8).ok())
+            .ok_or_else(|| invalid(&format!("bad tar entry size at byte {}", position)))?;
+        let data = position + BLOCK..position + BLOCK + size;
+        if data.end > archive.len() {
+            return Err(invalid("truncated tar archive"));
+        }
+        let name = |range| String::from_utf8_lossy(tar_field(header, range)).into_owned();
+        match header[156] {
+            // GNU long name of the next entry.
+            b'L' => {
+                long_name =
+                    Some(String::from_utf8_lossy(tar_field(archive, data.clone())).into_owned())
+            }
+            b'0' | 0 => {
+                let path = long_name.take().unwrap_or_else(|| match name(345..500) {
+                    prefix if prefix.is_empty() => name(0..100),
+                    prefix => format!("{}/{}", prefix, name(0..100)),
+                });
+                entries.push(Entry { path, stored: Some(data.clone()) });
+            }
+            _ => long_name = None,
+        }
+        position = data.start + size.div_ceil(BLOCK) * BLOCK;
+    }
+    Ok(entries)
+}
+
+fn write_tar_entry<W: Write>(writer: &mut W, path: &str, data: &[u8]) -> Result<()> {
+    if path.len() > 100 {
+        return Err(invalid(&format!("path `{}` longer than 100 bytes", path)));
+    }
+    let mut header = [0u8; BLOCK];
+    header[..path.len()].copy_from_slice(path.as_bytes());
+    header[100..107].copy_from_slice(b"0000644");
+    header[108..115].copy_from_slice(b"0000000");
+    header[116..123].copy_from_slice(b"0000000");
+    header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+    header[136..147].copy_from_slice(b"00000000000");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field as spaces.
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| u32::from(b)).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    writer.write_all(&header)?;
+    writer.write_all(data)?;
+    writer.write_all(&vec![0; (BLOCK - data.len() % BLOCK) % BLOCK])?;
+    Ok(())
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> usize {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]]) as usize
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> usize {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize
+}
+
+fn zip_entries(archive: &[u8]) -> Result<Vec<Entry>> {
+    const END_OF_DIRECTORY: usize = 22;
+    // The end of central directory record is followed by a comment of up to 64 KiB.
+    let end = (0..=archive.len().saturating_sub(END_OF_DIRECTORY))
+        .rev()
+        .take(END_OF_DIRECTORY + 0xffff)
+        .find(|&pos| archive[pos..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| invalid("no zip central directory"))?;
+    let count = read_u16(archive, end + 10);
+    let mut position = read_u32(archive, end + 16);
+
+    let truncated = || invalid("truncated zip archive");
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if position + 46 > archive.len() || !archive[position..].starts_with(b"PK\x01\x02") {
+            return Err(invalid("bad zip central directory entry"));
+        }
+        let method = read_u16(archive, position + 10);
+        let size = read_u32(archive, position + 20);
+        let name_len = read_u16(archive, position + 28);
+        let local = read_u32(archive, position + 42);
+        let name = archive.get(position + 46..position + 46 + name_len).ok_or_else(truncated)?;
+        let path = String::from_utf8_lossy(name).into_owned();
+        position +=
+            46 + name_len + read_u16(archive, position + 30) + read_u16(archive, position + 32);
+
+        if local + 30 > archive.len() || !archive[local..].starts_with(b"PK\x03\x04") {
+            return Err(invalid(&format!("bad zip local header of `{}`", path)));
+        }
+        let start = local + 30 + read_u16(archive, local + 26) + read_u16(archive, local + 28);
+        if start + size > archive.len() {
+            return Err(truncated());
+        }
+        let stored = if method == 0 { Some(start..start + size) } else { None };
+        entries.push(Entry { path, stored });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zip archive of stored `files`, without checksums.
+    fn zip(files: &[(&str, &[u8], u16)]) -> Vec<u8> {
+        let (mut archive, mut directory) = (Vec::new(), Vec::new());
+        for &(path, data, method) in files {
+            let local = archive.len() as u32;
+            let sizes = [(data.len() as u32).to_le_bytes(); 2].concat();
+            let mut header = [&[20, 0, 0, 0][..], &method.to_le_bytes(), &[0; 8], &sizes].concat();
+            header.extend(&(path.len() as u16).to_le_bytes());
+            header.extend(&[0, 0]);
+            archive.extend(b"PK\x03\x04");
+            archive.extend(&header);
+            archive.extend(path.as_bytes());
+            archive.extend(data);
+            directory.extend(b"PK\x01\x02\x14\x00");
+            directory.extend(&header);
+            directory.extend(&[0; 10]);
+            directory.extend(&local.to_le_bytes());
+            directory.extend(path.as_bytes());
+        }
+        let directory_start = archive.len() as u32;
+        archive.extend(&directory);
+        archive.extend(b"PK\x05\x06\0\0\0\0");
+        archive.extend(&[(files.len() as u16).to_le_bytes(); 2].concat());
+        archive.extend(&(directory.len() as u32).to_le_bytes());
+        archive.extend(&directory_start.to_le_bytes());
+        archive.extend(&[0, 0]);
+        archive
+    }
+
+    #[test]
+    fn unittest_model_bundle() {
+        let mnist = std::fs::read("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let mut tar = Vec::new();
+        ModelBundle::pack(&mut tar, &[("digits", &mnist[..]), ("digits_v2", &mnist[..16])])
+            .unwrap();
+        let bundle = ModelBundle::from_source(tar).unwrap();
+        assert_eq!(bundle.names().collect::<Vec<_>>(), vec!["digits", "digits_v2"]);
+        assert_eq!(bundle.models()[1].path, "digits_v2.tflite");
+        assert_eq!(bundle.bytes("digits").unwrap(), &mnist[..]);
+        assert_eq!(bundle.bytes("digits_v2").unwrap(), &mnist[..16]);
+        assert!(bundle.bytes("letters").is_err());
+        match bundle.source("digits").unwrap() {
+            ModelSource::Part(allocation, range) => {
+                assert!(allocation.ptr_eq(bundle.allocation()));
+                assert_eq!(range.start % BLOCK, 0);
+            }
+            source => panic!("{:?} copied from the bundle", source),
+        }
+
+        let manifest = br#"{"models": {"digits": "models/mnist.tflite"}}"#;
+        let archive = zip(&[("manifest.json", manifest, 0), ("models/mnist.tflite", &mnist, 0)]);
+        let bundle = ModelBundle::from_source(archive).unwrap();
+        assert_eq!(bundle.bytes("digits").unwrap(), &mnist[..]);
+        let compressed = zip(&[("manifest.json", manifest, 0), ("models/mnist.tflite", &mnist, 8)]);
+        let err = ModelBundle::from_source(compressed).unwrap_err();
+        assert!(err.to_string().contains("store it uncompressed"), "{}", err);
+        let missing = zip(&[("manifest.json", manifest, 0)]);
+        assert!(ModelBundle::from_source(missing).is_err());
+        assert!(ModelBundle::from_source(mnist).is_err());
+
+        assert_eq!(bundle.model("digits").unwrap().buffer(), bundle.bytes("digits").unwrap());
+    }
+}
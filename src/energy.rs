@@ -0,0 +1,313 @@
+//! Sampling platform energy counters around invocations, to report joules per inference
+//! next to latency.
+//!
+//! ```ignore
+//! let mut meter = energy::detect()?;
+//! let (_, joules) = energy::measure(meter.as_mut(), || {
+//!     (0..100).try_for_each(|_| interpreter.invoke())
+//! })?;
+//! println!("{:.3} J/inference ({})", joules / 100.0, meter.name());
+//! ```
+//!
+//! The counters cover a whole CPU package or board, so concurrent work and idle power are
+//! included; measure over many invocations on an otherwise idle system.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+/// A source of energy consumption measurements.
+pub trait EnergyMeter: Send {
+    /// Short description of what is measured, e.g. `rapl:package-0`.
+    fn name(&self) -> String;
+
+    /// Starts a measurement.
+    fn start(&mut self) -> Result<()>;
+
+    /// Energy in joules consumed since `start`.
+    fn stop(&mut self) -> Result<f64>;
+}
+
+/// Runs `f` between `meter.start()` and `meter.stop()`, returning its result and the
+/// consumed energy in joules.
+pub fn measure<T, F>(meter: &mut dyn EnergyMeter, f: F) -> Result<(T, f64)>
+where
+    F: FnOnce() -> Result<T>,
+{
+    meter.start()?;
+    let result = f();
+    let joules = meter.stop()?;
+    Ok((result?, joules))
+}
+
+/// The first available meter: RAPL, an `ina2xx` hwmon sensor, then a battery.
+pub fn detect() -> Result<Box<dyn EnergyMeter>> {
+    if let Ok(rapl) = RaplMeter::detect() {
+        return Ok(Box::new(rapl));
+    }
+    for sensor in &["ina226", "ina219", "ina3221"] {
+        if let Ok(meter) = SampledPowerMeter::hwmon(sensor) {
+            return Ok(Box::new(meter));
+        }
+    }
+    SampledPowerMeter::battery().map(|meter| Box::new(meter) as Box<dyn EnergyMeter>)
+}
+
+fn read_u64(path: &Path) -> Result<u64> {
+    fs::read_to_string(path)?.trim().parse().map_err(|e| {
+        Error::InternalError(format!("unexpected content of {}: {}", path.display(), e))
+    })
+}
+
+struct RaplDomain {
+    name: String,
+    energy: PathBuf,
+    // The counter wraps around after this many microjoules.
+    range: u64,
+    start: u64,
+}
+
+/// Intel/AMD RAPL package energy counters of Linux' powercap interface. Reading them usually
+/// requires root.
+pub struct RaplMeter {
+    domains: Vec<RaplDomain>,
+}
+
+impl RaplMeter {
+    pub const POWERCAP: &'static str = "/sys/class/powercap";
+
+    /// Uses all package domains (`intel-rapl:<N>`).
+    pub fn detect() -> Result<Self> {
+        Self::from_dir(Path::new(Self::POWERCAP))
+    }
+
+    fn from_dir(powercap: &Path) -> Result<Self> {
+        let mut packages: Vec<PathBuf> = fs::read_dir(powercap)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                // Subdomains are named `intel-rapl:<N>:<M>`.
+                name.starts_with("intel-rapl:") && name.matches(':').count() == 1
+            })
+            .collect();
+        packages.sort();
+        let domains = packages
+            .into_iter()
+            .map(|dir| {
+                let energy = dir.join("energy_uj");
+                let start = read_u64(&energy)?;
+                Ok(RaplDomain {
+                    name: fs::read_to_string(dir.join("name"))
+                        .map(|name| name.trim().to_string())
+                        .unwrap_or_default(),
+                    range: read_u64(&dir.join("max_energy_range_uj")).unwrap_or(u64::MAX),
+                    energy,
+                    start,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if domains.is_empty() {
+            return Err(Error::internal_error("no RAPL package domains found"));
+        }
+        Ok(Self { domains })
+    }
+}
+
+impl EnergyMeter for RaplMeter {
+    fn name(&self) -> String {
+        let names: Vec<&str> = self.domains.iter().map(|domain| domain.name.as_str()).collect();
+        format!("rapl:{}", names.join("+"))
+    }
+
+    fn start(&mut self) -> Result<()> {
+        for domain in &mut self.domains {
+            domain.start = read_u64(&domain.energy)?;
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<f64> {
+        let mut microjoules = 0;
+        for domain in &self.domains {
+            let end = read_u64(&domain.energy)?;
+            microjoules += if end >= domain.start {
+                end - domain.start
+            } else {
+                domain.range - domain.start + end
+            };
+        }
+        Ok(microjoules as f64 * 1e-6)
+    }
+}
+
+/// Where a `SampledPowerMeter` reads the instantaneous power.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PowerSource {
+    /// A file holding microwatts, e.g. hwmon `power1_input` or power supply `power_now`.
+    Microwatts(PathBuf),
+    /// Files holding microamperes and microvolts, e.g. power supply `current_now` and
+    /// `voltage_now`.
+    CurrentVoltage { current: PathBuf, voltage: PathBuf },
+}
+
+impl PowerSource {
+    /// Power in watts.
+    pub fn read(&self) -> Result<f64> {
+        Ok(match self {
+            PowerSource::Microwatts(power) => read_u64(power)? as f64 * 1e-6,
+            PowerSource::CurrentVoltage { current, voltage } => {
+                read_u64(current)? as f64 * 1e-6 * read_u64(voltage)? as f64 * 1e-6
+            }
+        })
+    }
+}
+
+/// Integrates a power reading sampled on a background thread, for boards with power
+/// sensors (e.g. an INA226 behind hwmon) or batteries instead of energy counters.
+pub struct SampledPowerMeter {
+    source: PowerSource,
+    interval: Duration,
+    running: Option<(Arc<AtomicBool>, JoinHandle<Result<f64>>)>,
+}
+
+impl SampledPowerMeter {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn new(source: PowerSource) -> Self {
+        Self { source, interval: Self::DEFAULT_INTERVAL, running: None }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The `power1_input` of the hwmon device named `name`, e.g. `ina226`.
+    pub fn hwmon(name: &str) -> Result<Self> {
+        for entry in fs::read_dir("/sys/class/hwmon")?.filter_map(|entry| entry.ok()) {
+            let dir = entry.path();
+            let matches =
+                fs::read_to_string(dir.join("name")).is_ok_and(|device| device.trim() == name);
+            if matches && dir.join("power1_input").exists() {
+                return Ok(Self::new(PowerSource::Microwatts(dir.join("power1_input"))));
+            }
+        }
+        Err(Error::InternalError(format!("no hwmon power sensor named `{}`", name)))
+    }
+
+    /// The discharge power of the first battery.
+    pub fn battery() -> Result<Self> {
+        for entry in fs::read_dir("/sys/class/power_supply")?.filter_map(|entry| entry.ok()) {
+            let dir = entry.path();
+            let is_battery =
+                fs::read_to_string(dir.join("type")).is_ok_and(|kind| kind.trim() == "Battery");
+            if !is_battery {
+                continue;
+            }
+            if dir.join("power_now").exists() {
+                return Ok(Self::new(PowerSource::Microwatts(dir.join("power_now"))));
+            }
+            if dir.join("current_now").exists() && dir.join("voltage_now").exists() {
+                return Ok(Self::new(PowerSource::CurrentVoltage {
+                    current: dir.join("current_now"),
+                    voltage: dir.join("voltage_now"),
+                }));
+            }
+        }
+        Err(Error::internal_error("no battery with power readings found"))
+    }
+}
+
+impl EnergyMeter for SampledPowerMeter {
+    fn name(&self) -> String {
+        match &self.source {
+            PowerSource::Microwatts(path) => path.display().to_string(),
+            PowerSource::CurrentVoltage { current, .. } => current.display().to_string(),
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        self.stop().ok();
+        let source = self.source.clone();
+        let interval = self.interval;
+        let first = source.read()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let sampler = thread::spawn(move || {
+            // Trapezoidal integration over the actual sample times.
+            let (mut joules, mut last, mut power) = (0.0, Instant::now(), first);
+            loop {
+                let done = stopped.load(Ordering::SeqCst);
+                if !done {
+                    thread::sleep(interval);
+                }
+                let now = Instant::now();
+                let next = source.read()?;
+                joules += (power + next) / 2.0 * (now - last).as_secs_f64();
+                last = now;
+                power = next;
+                if done {
+                    return Ok(joules);
+                }
+            }
+        });
+        self.running = Some((stop, sampler));
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<f64> {
+        let (stop, sampler) =
+            self.running.take().ok_or_else(|| Error::internal_error("meter is not running"))?;
+        stop.store(true, Ordering::SeqCst);
+        sampler.join().unwrap_or_else(|_| Err(Error::internal_error("power sampler panicked")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_energy_meters() {
+        let dir = std::env::temp_dir().join(format!("tflite-energy-{}", std::process::id()));
+        let package = dir.join("intel-rapl:0");
+        fs::create_dir_all(package.join("intel-rapl:0:0")).unwrap();
+        fs::create_dir_all(dir.join("intel-rapl:0:0")).unwrap();
+        fs::write(package.join("name"), "package-0\n").unwrap();
+        fs::write(package.join("max_energy_range_uj"), "1000000\n").unwrap();
+        fs::write(package.join("energy_uj"), "900000\n").unwrap();
+
+        let mut rapl = RaplMeter::from_dir(&dir).unwrap();
+        assert_eq!(rapl.name(), "rapl:package-0");
+        let (value, joules) = measure(&mut rapl, || {
+            // Wraps around.
+            fs::write(package.join("energy_uj"), "400000\n")?;
+            Ok(7)
+        })
+        .unwrap();
+        assert_eq!(value, 7);
+        assert!((joules - 0.5).abs() < 1e-9);
+
+        let power = dir.join("power1_input");
+        fs::write(&power, "2000000\n").unwrap();
+        let mut sampled = SampledPowerMeter::new(PowerSource::Microwatts(power))
+            .with_interval(Duration::from_millis(1));
+        let start = Instant::now();
+        let (_, joules) = measure(&mut sampled, || {
+            thread::sleep(Duration::from_millis(50));
+            Ok(())
+        })
+        .unwrap();
+        let seconds = start.elapsed().as_secs_f64();
+        assert!(joules > 2.0 * 0.05 * 0.9 && joules <= 2.0 * seconds * 1.1, "{}", joules);
+        assert!(sampled.stop().is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
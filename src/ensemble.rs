@@ -0,0 +1,221 @@
+//! Running several interpreters over the same input and combining their outputs.
+
+use std::thread;
+
+use crate::context::ElemKindOf;
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result};
+
+/// How the members of an `Ensemble` are invoked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Execution {
+    /// Invoke the members one after another on the calling thread.
+    Sequential,
+    /// Invoke every member on its own thread.
+    Parallel,
+}
+
+/// A set of interpreters fed with the same input whose first outputs are combined by a reducer.
+pub struct Ensemble<'a, Op>
+where
+    Op: OpResolver,
+{
+    members: Vec<Interpreter<'a, Op>>,
+    execution: Execution,
+}
+
+impl<'a, Op> Ensemble<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn new(members: Vec<Interpreter<'a, Op>>) -> Result<Self> {
+        if members.is_empty() {
+            return Err(Error::internal_error("ensemble requires at least one interpreter"));
+        }
+        Ok(Self { members, execution: Execution::Sequential })
+    }
+
+    pub fn with_execution(mut self, execution: Execution) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    pub fn members(&self) -> &[Interpreter<'a, Op>] {
+        &self.members
+    }
+
+    pub fn members_mut(&mut self) -> &mut [Interpreter<'a, Op>] {
+        &mut self.members
+    }
+
+    pub fn into_members(self) -> Vec<Interpreter<'a, Op>> {
+        self.members
+    }
+
+    /// Copies `input` into the first input tensor of every member, invokes them and
+    /// passes the first output tensor of each member, in member order, to `reducer`.
+    pub fn run<T, U, R>(&mut self, input: &[T], reducer: R) -> Result<U>
+    where
+        T: ElemKindOf + Copy + Sync,
+        R: FnOnce(&[&[T]]) -> U,
+    {
+        match self.execution {
+            Execution::Sequential => {
+                for member in &mut self.members {
+                    feed_and_invoke(member, input)?;
+                }
+            }
+            Execution::Parallel => {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = self
+                        .members
+                        .iter_mut()
+                        .map(|member| scope.spawn(move || feed_and_invoke(member, input)))
+                        .collect();
+                    handles.into_iter().try_for_each(|handle| {
+                        handle.join().unwrap_or_else(|_| {
+                            Err(Error::internal_error("ensemble member panicked"))
+                        })
+                    })
+                })?;
+            }
+        }
+
+        let outputs = self
+            .members
+            .iter()
+            .map(|member| {
+                let index = *member
+                    .outputs()
+                    .first()
+                    .ok_or_else(|| Error::internal_error("model has no outputs"))?;
+                member.tensor_data::<T>(index)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(reducer(&outputs))
+    }
+}
+
+fn feed_and_invoke<Op, T>(interpreter: &mut Interpreter<'_, Op>, input: &[T]) -> Result<()>
+where
+    Op: OpResolver,
+    T: ElemKindOf + Copy,
+{
+    let index = *interpreter
+        .inputs()
+        .first()
+        .ok_or_else(|| Error::internal_error("model has no inputs"))?;
+    let data = interpreter.tensor_data_mut::<T>(index)?;
+    if data.len() != input.len() {
+        return Err(Error::InternalError(format!(
+            "input has {} elements, but the tensor expects {}",
+            input.len(),
+            data.len()
+        )));
+    }
+    data.copy_from_slice(input);
+    interpreter.invoke()
+}
+
+/// Common reducers for `Ensemble::run`.
+pub mod reducers {
+    /// Element-wise mean of the member outputs.
+    pub fn mean<T>(outputs: &[&[T]]) -> Vec<f32>
+    where
+        T: Copy + Into<f32>,
+    {
+        let len = outputs.iter().map(|o| o.len()).min().unwrap_or(0);
+        (0..len)
+            .map(|i| outputs.iter().map(|o| o[i].into()).sum::<f32>() / outputs.len() as f32)
+            .collect()
+    }
+
+    /// Element-wise maximum of the member outputs.
+    pub fn max<T>(outputs: &[&[T]]) -> Vec<T>
+    where
+        T: Copy + PartialOrd,
+    {
+        let len = outputs.iter().map(|o| o.len()).min().unwrap_or(0);
+        (0..len)
+            .map(|i| {
+                outputs
+                    .iter()
+                    .map(|o| o[i])
+                    .fold(outputs[0][i], |acc, x| if x > acc { x } else { acc })
+            })
+            .collect()
+    }
+
+    /// Majority vote over the arg-max class of each member.
+    /// Returns the winning class; ties are resolved in favor of the lower class index.
+    pub fn vote<T>(outputs: &[&[T]]) -> Option<usize>
+    where
+        T: Copy + PartialOrd,
+    {
+        let classes = outputs.iter().map(|o| o.len()).max()?;
+        let mut votes = vec![0usize; classes];
+        for output in outputs {
+            if let Some(class) = argmax(output) {
+                votes[class] += 1;
+            }
+        }
+        argmax(&votes).filter(|&class| votes[class] > 0)
+    }
+
+    fn argmax<T: Copy + PartialOrd>(values: &[T]) -> Option<usize> {
+        values
+            .iter()
+            .enumerate()
+            .fold(None, |best: Option<(usize, T)>, (i, &v)| match best {
+                Some((_, b)) if b >= v => best,
+                _ => Some((i, v)),
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_ensemble_vote() {
+        let resolver = BuiltinOpResolver::default();
+        let mut members =
+            ["data/MNISTnet_uint8_quant.tflite", "data/MNISTnet_v2_uint8_quant.tflite"]
+                .iter()
+                .map(|path| {
+                    let model = FlatBufferModel::build_from_file(path)?;
+                    InterpreterBuilder::new(model, &resolver)?.build()
+                })
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+
+        for &execution in &[Execution::Sequential, Execution::Parallel] {
+            let mut ensemble = Ensemble::new(members).unwrap().with_execution(execution);
+            let mut input_file = File::open("data/mnist10.bin").unwrap();
+            let mut image = vec![0u8; 28 * 28];
+            for i in 0..10 {
+                input_file.read_exact(&mut image).unwrap();
+                assert_eq!(ensemble.run(&image, reducers::vote::<u8>).unwrap(), Some(i));
+            }
+            members = ensemble.into_members();
+        }
+    }
+
+    #[test]
+    fn unittest_reducers() {
+        let outputs: &[&[u8]] = &[&[1, 9, 3], &[4, 2, 6]];
+        #[allow(clippy::float_cmp)]
+        {
+            assert_eq!(reducers::mean(outputs), vec![2.5, 5.5, 4.5]);
+        }
+        assert_eq!(reducers::max(outputs), vec![4, 9, 6]);
+        assert_eq!(reducers::vote(&[&[1u8, 9, 3], &[4, 2, 6], &[0, 5, 1]]), Some(1));
+    }
+}
@@ -1,13 +1,40 @@
 use std::io::Error as IoError;
+use std::time::Duration;
 
 use thiserror::Error;
 
+use crate::{BuildDiagnostics, NumericIssue};
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error(transparent)]
     IoError(#[from] IoError),
     #[error("`{0}`")]
     InternalError(String),
+    #[error("invocation was cancelled")]
+    Cancelled,
+    #[error("invocation timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("failed to build interpreter: {0}")]
+    BuildFailed(BuildDiagnostics),
+    #[error("failed to apply delegate: {0}")]
+    DelegateFailed(DelegateFailure),
+    #[error("non-finite values: {0}")]
+    NonFinite(NumericIssue),
+    #[error("model signature rejected: {0}")]
+    SignatureRejected(String),
+}
+
+/// Why `Interpreter::modify_graph_with_delegate` failed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DelegateFailure {
+    /// The delegate rejected the graph. The interpreter was rebuilt and runs on the CPU
+    /// (with any previously applied delegates); input tensor data was kept.
+    #[error("delegate rejected the graph, restored CPU execution")]
+    Rejected,
+    /// The delegate failed and the interpreter could not be restored. It must be rebuilt.
+    #[error("delegate failed and the interpreter could not be restored: {0}")]
+    Unrecoverable(String),
 }
 
 impl Error {
@@ -0,0 +1,284 @@
+//! Measuring model accuracy on a labeled dataset, e.g. to catch regressions caused by
+//! quantization or delegates.
+
+use std::collections::BTreeMap;
+
+use crate::op_resolver::OpResolver;
+use crate::postprocess::{top_k, BoundingBox, Detection};
+use crate::{Error, Interpreter, Result};
+
+/// Copies each sample into the first input tensor of `interpreter`, invokes it and passes
+/// the interpreter and the sample's label to `score`.
+pub fn run<'a, Op, D, I, L, F>(
+    interpreter: &mut Interpreter<'a, Op>,
+    dataset: D,
+    mut score: F,
+) -> Result<()>
+where
+    Op: OpResolver,
+    D: IntoIterator<Item = (I, L)>,
+    I: AsRef<[u8]>,
+    F: FnMut(&Interpreter<'a, Op>, L) -> Result<()>,
+{
+    let index = *interpreter
+        .inputs()
+        .first()
+        .ok_or_else(|| Error::internal_error("model has no inputs"))?;
+    for (input, label) in dataset {
+        let input = input.as_ref();
+        let buffer = interpreter
+            .tensor_buffer_mut(index)
+            .ok_or_else(|| Error::internal_error("invalid input tensor"))?;
+        if buffer.len() != input.len() {
+            return Err(Error::InternalError(format!(
+                "sample has {} bytes, the input tensor {}",
+                input.len(),
+                buffer.len()
+            )));
+        }
+        buffer.copy_from_slice(input);
+        interpreter.invoke()?;
+        score(interpreter, label)?;
+    }
+    Ok(())
+}
+
+/// Top-1 and top-5 accuracy of a classifier.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClassificationAccuracy {
+    samples: usize,
+    top1: usize,
+    top5: usize,
+}
+
+impl ClassificationAccuracy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the scores predicted for a sample of class `label`.
+    pub fn add<T>(&mut self, scores: &[T], label: usize)
+    where
+        T: Copy + Into<f32>,
+    {
+        let ranked = top_k(scores, 5);
+        self.samples += 1;
+        if ranked.first().map(|&(i, _)| i) == Some(label) {
+            self.top1 += 1;
+        }
+        if ranked.iter().any(|&(i, _)| i == label) {
+            self.top5 += 1;
+        }
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn top1(&self) -> f64 {
+        ratio(self.top1, self.samples)
+    }
+
+    pub fn top5(&self) -> f64 {
+        ratio(self.top5, self.samples)
+    }
+}
+
+fn ratio(n: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        n as f64 / total as f64
+    }
+}
+
+/// Evaluates a classifier whose first output holds `f32` or `u8` scores. Labels are class
+/// indices.
+pub fn evaluate_classification<'a, Op, D, I>(
+    interpreter: &mut Interpreter<'a, Op>,
+    dataset: D,
+) -> Result<ClassificationAccuracy>
+where
+    Op: OpResolver,
+    D: IntoIterator<Item = (I, usize)>,
+    I: AsRef<[u8]>,
+{
+    let mut accuracy = ClassificationAccuracy::new();
+    run(interpreter, dataset, |interpreter, label| {
+        let index = *interpreter
+            .outputs()
+            .first()
+            .ok_or_else(|| Error::internal_error("model has no outputs"))?;
+        match interpreter.tensor_data::<f32>(index) {
+            Ok(scores) => accuracy.add(scores, label),
+            Err(_) => accuracy.add(interpreter.tensor_data::<u8>(index)?, label),
+        }
+        Ok(())
+    })?;
+    Ok(accuracy)
+}
+
+/// A labeled object of the dataset.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GroundTruth {
+    pub class: usize,
+    pub bbox: BoundingBox,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ClassRecords {
+    // (score, true positive) of every detection
+    detections: Vec<(f32, bool)>,
+    ground_truths: usize,
+}
+
+/// Mean average precision of a detector at one IoU threshold (e.g. 0.5 for PASCAL VOC).
+#[derive(Clone, Debug)]
+pub struct MeanAveragePrecision {
+    iou_threshold: f32,
+    classes: BTreeMap<usize, ClassRecords>,
+}
+
+impl MeanAveragePrecision {
+    pub fn new(iou_threshold: f32) -> Self {
+        Self { iou_threshold, classes: BTreeMap::new() }
+    }
+
+    /// Records the detections of one image. Each ground truth object matches at most one
+    /// detection, the best scored one with enough overlap.
+    pub fn add(&mut self, detections: &[Detection], ground_truths: &[GroundTruth]) {
+        for truth in ground_truths {
+            self.classes.entry(truth.class).or_default().ground_truths += 1;
+        }
+        let mut detections: Vec<&Detection> = detections.iter().collect();
+        detections
+            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut matched = vec![false; ground_truths.len()];
+        for detection in detections {
+            let best = ground_truths
+                .iter()
+                .enumerate()
+                .filter(|(i, truth)| truth.class == detection.class && !matched[*i])
+                .map(|(i, truth)| (i, truth.bbox.iou(&detection.bbox)))
+                .filter(|&(_, iou)| iou >= self.iou_threshold)
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+            if let Some((i, _)) = best {
+                matched[i] = true;
+            }
+            let records = self.classes.entry(detection.class).or_default();
+            records.detections.push((detection.score, best.is_some()));
+        }
+    }
+
+    /// Area under the interpolated precision/recall curve of `class`, or `None` if the
+    /// class has no ground truth objects.
+    pub fn average_precision(&self, class: usize) -> Option<f64> {
+        let records = self.classes.get(&class)?;
+        if records.ground_truths == 0 {
+            return None;
+        }
+        let mut detections = records.detections.clone();
+        detections.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut points = Vec::with_capacity(detections.len());
+        let mut true_positives = 0;
+        for (n, &(_, is_true)) in detections.iter().enumerate() {
+            if is_true {
+                true_positives += 1;
+            }
+            let recall = true_positives as f64 / records.ground_truths as f64;
+            let precision = true_positives as f64 / (n + 1) as f64;
+            points.push((recall, precision));
+        }
+        // Make precision monotonically decreasing, then integrate over recall.
+        for i in (0..points.len().saturating_sub(1)).rev() {
+            points[i].1 = points[i].1.max(points[i + 1].1);
+        }
+        let mut ap = 0.0;
+        let mut last_recall = 0.0;
+        for (recall, precision) in points {
+            ap += (recall - last_recall) * precision;
+            last_recall = recall;
+        }
+        Some(ap)
+    }
+
+    /// Mean of the average precisions of all classes with ground truth objects.
+    pub fn mean(&self) -> f64 {
+        let aps: Vec<f64> =
+            self.classes.keys().filter_map(|&c| self.average_precision(c)).collect();
+        if aps.is_empty() {
+            0.0
+        } else {
+            aps.iter().sum::<f64>() / aps.len() as f64
+        }
+    }
+}
+
+/// Evaluates a detector. `decode` turns the outputs of the interpreter into detections,
+/// which depends on the model.
+pub fn evaluate_detection<'a, Op, D, I, F>(
+    interpreter: &mut Interpreter<'a, Op>,
+    dataset: D,
+    iou_threshold: f32,
+    mut decode: F,
+) -> Result<MeanAveragePrecision>
+where
+    Op: OpResolver,
+    D: IntoIterator<Item = (I, Vec<GroundTruth>)>,
+    I: AsRef<[u8]>,
+    F: FnMut(&Interpreter<'a, Op>) -> Result<Vec<Detection>>,
+{
+    let mut map = MeanAveragePrecision::new(iou_threshold);
+    run(interpreter, dataset, |interpreter, ground_truths| {
+        map.add(&decode(interpreter)?, &ground_truths);
+        Ok(())
+    })?;
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_mean_average_precision() {
+        let bbox = |x: f32| BoundingBox::new(0.0, x, 1.0, x + 1.0);
+        let mut map = MeanAveragePrecision::new(0.5);
+        map.add(
+            &[
+                Detection { class: 0, score: 0.9, bbox: bbox(0.0) },
+                Detection { class: 0, score: 0.8, bbox: bbox(5.0) },
+                Detection { class: 0, score: 0.7, bbox: bbox(0.1) },
+                Detection { class: 1, score: 0.6, bbox: bbox(2.0) },
+            ],
+            &[GroundTruth { class: 0, bbox: bbox(0.0) }, GroundTruth { class: 1, bbox: bbox(2.0) }],
+        );
+        assert_eq!(map.average_precision(0), Some(1.0));
+        assert_eq!(map.average_precision(1), Some(1.0));
+
+        map.add(&[], &[GroundTruth { class: 1, bbox: bbox(3.0) }]);
+        assert_eq!(map.average_precision(1), Some(0.5));
+        assert_eq!(map.mean(), 0.75);
+        assert_eq!(map.average_precision(2), None);
+    }
+
+    #[test]
+    fn unittest_evaluate_classification() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let mut interpreter =
+            InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap().build().unwrap();
+
+        let images = fs::read("data/mnist10.bin").unwrap();
+        let dataset = images.chunks(28 * 28).enumerate().map(|(digit, image)| (image, digit));
+        let accuracy = evaluate_classification(&mut interpreter, dataset).unwrap();
+        assert_eq!(accuracy.samples(), 10);
+        assert_eq!(accuracy.top1(), 1.0);
+        assert_eq!(accuracy.top5(), 1.0);
+    }
+}
@@ -0,0 +1,84 @@
+//! `ndarray` views of tensors, shaped by the tensor dims:
+//!
+//! ```ignore
+//! interpreter.copy_from_array(interpreter.inputs()[0], &image.insert_axis(Axis(0)))?;
+//! interpreter.invoke()?;
+//! let scores = interpreter.tensor_view::<f32>(interpreter.outputs()[0])?;
+//! let best = scores.index_axis(Axis(0), 0).iter().cloned().fold(f32::MIN, f32::max);
+//! ```
+
+use ndarray::{ArrayBase, ArrayViewD, ArrayViewMutD, Data, Dimension, IxDyn};
+
+use super::context::ElemKindOf;
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+use crate::{Error, Result};
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn tensor_dims(&self, tensor_index: TensorIndex) -> Result<Vec<usize>> {
+        self.tensor_info(tensor_index)
+            .map(|info| info.dims)
+            .ok_or_else(|| Error::InternalError(format!("invalid tensor index {}", tensor_index)))
+    }
+
+    /// The data of a tensor as an array of its dims. `T` must be its element type.
+    pub fn tensor_view<T: ElemKindOf>(
+        &self,
+        tensor_index: TensorIndex,
+    ) -> Result<ArrayViewD<'_, T>> {
+        let dims = self.tensor_dims(tensor_index)?;
+        let data = self.tensor_data(tensor_index)?;
+        ArrayViewD::from_shape(IxDyn(&dims), data).map_err(|e| not_allocated(tensor_index, e))
+    }
+
+    pub fn tensor_view_mut<T: ElemKindOf>(
+        &mut self,
+        tensor_index: TensorIndex,
+    ) -> Result<ArrayViewMutD<'_, T>> {
+        let dims = self.tensor_dims(tensor_index)?;
+        let data = self.tensor_data_mut(tensor_index)?;
+        ArrayViewMutD::from_shape(IxDyn(&dims), data).map_err(|e| not_allocated(tensor_index, e))
+    }
+
+    /// Copies `array` into a tensor, failing unless its shape is the dims of the tensor.
+    /// Resize inputs with `resize_input_tensor` and `allocate_tensors` first to change them.
+    pub fn copy_from_array<T, S, D>(
+        &mut self,
+        tensor_index: TensorIndex,
+        array: &ArrayBase<S, D>,
+    ) -> Result<()>
+    where
+        T: ElemKindOf + Clone,
+        S: Data<Elem = T>,
+        D: Dimension,
+    {
+        let dims = self.tensor_dims(tensor_index)?;
+        if array.shape() != dims.as_slice() {
+            return Err(Error::InternalError(format!(
+                "array of shape {:?} does not match tensor {} of dims {:?}",
+                array.shape(),
+                tensor_index,
+                dims
+            )));
+        }
+        let data = self.tensor_data_mut::<T>(tensor_index)?;
+        if data.len() != array.len() {
+            return Err(not_allocated(tensor_index, "no data"));
+        }
+        match array.as_slice() {
+            Some(values) => data.clone_from_slice(values),
+            None => data.iter_mut().zip(array.iter()).for_each(|(x, value)| *x = value.clone()),
+        }
+        Ok(())
+    }
+}
+
+fn not_allocated(tensor_index: TensorIndex, e: impl std::fmt::Display) -> Error {
+    Error::InternalError(format!(
+        "tensor {} has no data for its dims, allocate tensors first: {}",
+        tensor_index, e
+    ))
+}
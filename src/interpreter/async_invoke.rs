@@ -0,0 +1,72 @@
+//! Running interpreters from async code, on the tokio blocking pool so that runtime threads
+//! keep serving other tasks during inference:
+//!
+//! ```ignore
+//! interpreter.copy_from_array(input, &image)?;
+//! let (interpreter, result) = interpreter.invoke_async().await;
+//! result?;
+//! ```
+
+use tokio::task;
+
+use super::op_resolver::OpResolver;
+use super::Interpreter;
+use crate::Result;
+
+impl<Op> Interpreter<'static, Op>
+where
+    Op: OpResolver + 'static,
+{
+    /// Runs `f` on the interpreter in a blocking pool thread. The interpreter moves there and
+    /// comes back with the result, so the future borrows nothing and can be spawned. Panics of
+    /// `f` resume in the awaiting task.
+    pub async fn run_async<T, F>(mut self, f: F) -> (Self, T)
+    where
+        F: FnOnce(&mut Self) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let run = task::spawn_blocking(move || {
+            let result = f(&mut self);
+            (self, result)
+        });
+        match run.await {
+            Ok(done) => done,
+            Err(e) => match e.try_into_panic() {
+                Ok(payload) => std::panic::resume_unwind(payload),
+                Err(_) => panic!("tokio runtime shut down before the interpreter ran"),
+            },
+        }
+    }
+
+    /// `invoke` in a blocking pool thread, returning the interpreter with its result.
+    pub async fn invoke_async(self) -> (Self, Result<()>) {
+        self.run_async(Self::invoke).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_invoke_async() {
+        fn send<T: Send>(_t: &T) {}
+
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let mut interpreter =
+            InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap().build().unwrap();
+        interpreter.allocate_tensors().unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let invoke = interpreter.invoke_async();
+        send(&invoke);
+        let (interpreter, result) = runtime.block_on(invoke);
+        result.unwrap();
+        let output = interpreter.outputs()[0];
+        let (_, scores) = runtime.block_on(interpreter.run_async(move |interpreter| {
+            interpreter.tensor_data::<u8>(output).map(<[u8]>::to_vec)
+        }));
+        assert_eq!(scores.unwrap().len(), 10);
+    }
+}
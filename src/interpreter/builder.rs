@@ -1,40 +1,36 @@
+use std::sync::Arc;
+
 use maybe_owned::MaybeOwned;
 
+use super::diagnostics::ErrorCollector;
 use super::op_resolver::OpResolver;
 use super::FlatBufferModel;
-use super::Interpreter;
+use super::{Interpreter, Telemetry};
 use crate::bindings::tflite as bindings;
-use crate::{Error, Result};
+use crate::Result;
 
 cpp! {{
+    #include <algorithm>
+
     #include "tensorflow/lite/model.h"
     #include "tensorflow/lite/kernels/register.h"
 
     using namespace tflite;
 }}
 
+/// Builds interpreters for a model.
+///
+/// `build` consumes the builder. To create many interpreters for the same model without
+/// parsing it again, use `into_shared`. Every build uses a fresh native
+/// `tflite::InterpreterBuilder`, since reusing one is not supported by TFLite.
 pub struct InterpreterBuilder<'a, Op>
 where
     Op: OpResolver,
 {
-    handle: Box<bindings::InterpreterBuilder>,
-    _model: MaybeOwned<'a, FlatBufferModel>,
-    _resolver: Op,
-}
-
-impl<'a, Op> Drop for InterpreterBuilder<'a, Op>
-where
-    Op: OpResolver,
-{
-    fn drop(&mut self) {
-        let handle = Box::into_raw(std::mem::take(&mut self.handle));
-        #[allow(clippy::forget_copy, clippy::useless_transmute, deprecated)]
-        unsafe {
-            cpp!([handle as "InterpreterBuilder*"] {
-                delete handle;
-            });
-        }
-    }
+    model: MaybeOwned<'a, FlatBufferModel>,
+    resolver: Op,
+    preserve_all_tensors: bool,
+    pub(super) telemetry: Option<Telemetry>,
 }
 
 impl<'a, Op> InterpreterBuilder<'a, Op>
@@ -43,58 +39,112 @@ where
 {
     #[allow(clippy::new_ret_no_self)]
     pub fn new<M: Into<MaybeOwned<'a, FlatBufferModel>>>(model: M, resolver: Op) -> Result<Self> {
-        use std::ops::Deref;
-        let model = model.into();
-        let handle = {
-            let model_handle = model.as_ref().handle.deref();
-            let resolver_handle = resolver.get_resolver_handle();
-
-            #[allow(clippy::forget_copy, deprecated)]
-            unsafe {
-                cpp!([model_handle as "const FlatBufferModel*",
-                    resolver_handle as "const OpResolver*"
-                ] -> *mut bindings::InterpreterBuilder as "InterpreterBuilder*" {
-                    return new InterpreterBuilder(*model_handle, *resolver_handle);
-                })
-            }
-        };
-        if handle.is_null() {
-            return Err(Error::InternalError("failed to create InterpreterBuilder".to_string()));
-        }
-        let handle = unsafe { Box::from_raw(handle) };
-        Ok(Self { handle, _model: model, _resolver: resolver })
+        Ok(Self { model: model.into(), resolver, preserve_all_tensors: false, telemetry: None })
     }
 
-    pub fn build(mut self) -> Result<Interpreter<'a, Op>> {
-        #[allow(clippy::forget_copy, deprecated)]
-        let handle = {
-            let builder = &mut *self.handle;
-            unsafe {
-                cpp!([builder as "InterpreterBuilder*"] -> *mut bindings::Interpreter as "Interpreter*" {
-                    std::unique_ptr<Interpreter> interpreter;
-                    (*builder)(&interpreter);
-                    return interpreter.release();
-                })
-            }
-        };
-        Interpreter::new(handle, self)
+    /// Gives every intermediate tensor of the main subgraph its own buffer instead of a
+    /// shared arena slot, so that its values stay readable after `invoke`, e.g. through
+    /// `Interpreter::tensor_by_name`. This costs the memory of all intermediates and
+    /// prevents delegates from taking over the graph.
+    pub fn preserve_all_tensors(mut self, preserve: bool) -> Self {
+        self.preserve_all_tensors = preserve;
+        self
+    }
+
+    /// Reports the invocations of every interpreter built to `telemetry`.
+    pub fn telemetry(mut self, telemetry: Telemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    pub fn model(&self) -> &FlatBufferModel {
+        &self.model
+    }
+
+    pub fn build(self) -> Result<Interpreter<'a, Op>> {
+        Interpreter::new(Arc::new(self), -1)
+    }
+
+    pub fn build_with_threads(self, threads: std::os::raw::c_int) -> Result<Interpreter<'a, Op>> {
+        Interpreter::new(Arc::new(self), threads)
+    }
+
+    /// Turns the builder into one that can build any number of interpreters.
+    pub fn into_shared(self) -> SharedInterpreterBuilder<'a, Op> {
+        SharedInterpreterBuilder(Arc::new(self))
     }
 
-    pub fn build_with_threads(
-        mut self,
+    /// Builds a fresh native interpreter, reporting to the returned collector which it
+    /// must not outlive. The handle is null on failure.
+    pub(crate) fn build_handle(
+        &self,
         threads: std::os::raw::c_int,
-    ) -> Result<Interpreter<'a, Op>> {
+    ) -> (*mut bindings::Interpreter, ErrorCollector) {
+        use std::ops::Deref;
+        let errors = ErrorCollector::new();
+        let model_handle = self.model.as_ref().handle.deref();
+        let resolver_handle = self.resolver.get_resolver_handle();
+        let reporter = errors.as_ptr();
+        let preserve = self.preserve_all_tensors;
+
         #[allow(clippy::forget_copy, deprecated)]
-        let handle = {
-            let builder = &mut *self.handle;
-            unsafe {
-                cpp!([builder as "InterpreterBuilder*", threads as "int"] -> *mut bindings::Interpreter as "Interpreter*" {
-                    std::unique_ptr<Interpreter> interpreter;
-                    (*builder)(&interpreter, threads);
-                    return interpreter.release();
-                })
-            }
+        let handle = unsafe {
+            cpp!([model_handle as "const FlatBufferModel*",
+                resolver_handle as "const OpResolver*",
+                reporter as "CollectingErrorReporter*",
+                threads as "int",
+                preserve as "bool"
+            ] -> *mut bindings::Interpreter as "Interpreter*" {
+                InterpreterBuilder builder(model_handle->GetModel(), *resolver_handle, reporter);
+                std::unique_ptr<Interpreter> interpreter;
+                builder(&interpreter, threads);
+                if (interpreter && preserve) {
+                    // Dynamic tensors are allocated individually when their producer
+                    // resizes them, and never reused. Inputs are never resized.
+                    const auto& inputs = interpreter->inputs();
+                    for (size_t i = 0; i < interpreter->tensors_size(); ++i) {
+                        TfLiteTensor* tensor = interpreter->tensor(i);
+                        if (tensor->allocation_type == kTfLiteArenaRw &&
+                            std::find(inputs.begin(), inputs.end(), static_cast<int>(i)) == inputs.end()) {
+                            tensor->allocation_type = kTfLiteDynamic;
+                        }
+                    }
+                }
+                return interpreter.release();
+            })
         };
-        Interpreter::new(handle, self)
+        (handle, errors)
+    }
+}
+
+/// An `InterpreterBuilder` that builds interpreters by reference. Clones share the model
+/// and resolver, and may be used from several threads if those are `Sync`.
+pub struct SharedInterpreterBuilder<'a, Op>(Arc<InterpreterBuilder<'a, Op>>)
+where
+    Op: OpResolver;
+
+impl<'a, Op> Clone for SharedInterpreterBuilder<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn clone(&self) -> Self {
+        SharedInterpreterBuilder(self.0.clone())
+    }
+}
+
+impl<'a, Op> SharedInterpreterBuilder<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn build(&self) -> Result<Interpreter<'a, Op>> {
+        Interpreter::new(self.0.clone(), -1)
+    }
+
+    pub fn build_with_threads(&self, threads: std::os::raw::c_int) -> Result<Interpreter<'a, Op>> {
+        Interpreter::new(self.0.clone(), threads)
+    }
+
+    pub fn model(&self) -> &FlatBufferModel {
+        &self.0.model
     }
 }
@@ -0,0 +1,524 @@
+//! Parameters of builtin operators, read from the `builtin_data` TF Lite parsed for a node:
+//!
+//! ```ignore
+//! for &node in interpreter.execution_plan() {
+//!     if let Some(BuiltinParams::Conv2d(conv)) = interpreter.builtin_params(node as usize) {
+//!         println!("#{} strides {}x{}", node, conv.stride_width, conv.stride_height);
+//!     }
+//! }
+//! ```
+//!
+//! Operators without parameters, custom operators and delegate kernels have none.
+
+use super::op_resolver::OpResolver;
+use super::planning::FusedActivation;
+use super::Interpreter;
+
+cpp! {{
+    #include <algorithm>
+
+    #include "tensorflow/lite/interpreter.h"
+    #include "tensorflow/lite/builtin_ops.h"
+    #include "tensorflow/lite/c/builtin_op_data.h"
+
+    using namespace tflite;
+}}
+
+/// `TfLitePadding`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    Unknown,
+    Same,
+    Valid,
+}
+
+impl Padding {
+    fn from_raw(padding: i32) -> Self {
+        match padding {
+            1 => Padding::Same,
+            2 => Padding::Valid,
+            _ => Padding::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConvParams {
+    pub padding: Padding,
+    pub stride_width: i32,
+    pub stride_height: i32,
+    pub dilation_width_factor: i32,
+    pub dilation_height_factor: i32,
+    pub activation: FusedActivation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepthwiseConvParams {
+    pub conv: ConvParams,
+    pub depth_multiplier: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolParams {
+    pub padding: Padding,
+    pub stride_width: i32,
+    pub stride_height: i32,
+    pub filter_width: i32,
+    pub filter_height: i32,
+    pub activation: FusedActivation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FullyConnectedParams {
+    pub activation: FusedActivation,
+    /// Whether the weights are in the shuffled 4x16 int8 format instead of the default one.
+    pub shuffled_weights: bool,
+    pub keep_num_dims: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LstmParams {
+    pub activation: FusedActivation,
+    /// Clipping of the cell state, 0 for none.
+    pub cell_clip: f32,
+    /// Clipping of the projection, 0 for none.
+    pub proj_clip: f32,
+    /// Whether the kernel is the basic 4-input LSTM instead of the full one.
+    pub basic_kernel: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StridedSliceParams {
+    pub begin_mask: i32,
+    pub end_mask: i32,
+    pub ellipsis_mask: i32,
+    pub new_axis_mask: i32,
+    pub shrink_axis_mask: i32,
+}
+
+/// The parameters of a node, by operator.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuiltinParams {
+    Conv2d(ConvParams),
+    DepthwiseConv2d(DepthwiseConvParams),
+    /// `TRANSPOSE_CONV`, whose dilation factors are always 1 and activation `None`.
+    TransposeConv(ConvParams),
+    /// `AVERAGE_POOL_2D`, `MAX_POOL_2D` and `L2_POOL_2D`.
+    Pool2d(PoolParams),
+    FullyConnected(FullyConnectedParams),
+    /// Operators whose only parameter is a fused activation: `ADD`, `SUB`, `MUL`, `DIV`,
+    /// `L2_NORMALIZATION` and `RNN`.
+    Activation(FusedActivation),
+    Concatenation {
+        axis: i32,
+        activation: FusedActivation,
+    },
+    Softmax {
+        beta: f32,
+    },
+    LeakyRelu {
+        alpha: f32,
+    },
+    /// The new shape, if given as a parameter rather than as an input tensor.
+    Reshape {
+        shape: Vec<i32>,
+    },
+    Svdf {
+        rank: i32,
+        activation: FusedActivation,
+    },
+    Lstm(LstmParams),
+    StridedSlice(StridedSliceParams),
+    ResizeBilinear {
+        align_corners: bool,
+        half_pixel_centers: bool,
+    },
+    /// `MEAN`, `SUM` and the other reductions.
+    Reducer {
+        keep_dims: bool,
+    },
+    Gather {
+        axis: i32,
+    },
+}
+
+impl BuiltinParams {
+    /// The activation the kernel applies to its output, for operators with the option.
+    pub fn fused_activation(&self) -> Option<FusedActivation> {
+        match self {
+            BuiltinParams::Conv2d(conv) => Some(conv.activation),
+            BuiltinParams::DepthwiseConv2d(depthwise) => Some(depthwise.conv.activation),
+            BuiltinParams::Pool2d(pool) => Some(pool.activation),
+            BuiltinParams::FullyConnected(fully_connected) => Some(fully_connected.activation),
+            BuiltinParams::Activation(activation) => Some(*activation),
+            BuiltinParams::Concatenation { activation, .. } => Some(*activation),
+            BuiltinParams::Svdf { activation, .. } => Some(*activation),
+            BuiltinParams::Lstm(lstm) => Some(lstm.activation),
+            _ => None,
+        }
+    }
+
+    fn from_raw(raw: &RawParams) -> Option<Self> {
+        let ints = &raw.ints;
+        let activation = |i: usize| FusedActivation::from_raw(ints[i]);
+        let conv = || -> Option<ConvParams> {
+            Some(ConvParams {
+                padding: Padding::from_raw(ints[0]),
+                stride_width: ints[1],
+                stride_height: ints[2],
+                dilation_width_factor: ints[3],
+                dilation_height_factor: ints[4],
+                activation: activation(5)?,
+            })
+        };
+        Some(match raw.kind {
+            KIND_CONV => BuiltinParams::Conv2d(conv()?),
+            KIND_DEPTHWISE_CONV => BuiltinParams::DepthwiseConv2d(DepthwiseConvParams {
+                conv: conv()?,
+                depth_multiplier: ints[6],
+            }),
+            KIND_TRANSPOSE_CONV => BuiltinParams::TransposeConv(conv()?),
+            KIND_POOL => BuiltinParams::Pool2d(PoolParams {
+                padding: Padding::from_raw(ints[0]),
+                stride_width: ints[1],
+                stride_height: ints[2],
+                filter_width: ints[3],
+                filter_height: ints[4],
+                activation: activation(5)?,
+            }),
+            KIND_FULLY_CONNECTED => BuiltinParams::FullyConnected(FullyConnectedParams {
+                activation: activation(0)?,
+                shuffled_weights: ints[1] != 0,
+                keep_num_dims: ints[2] != 0,
+            }),
+            KIND_ACTIVATION => BuiltinParams::Activation(activation(0)?),
+            KIND_CONCATENATION => {
+                BuiltinParams::Concatenation { axis: ints[0], activation: activation(1)? }
+            }
+            KIND_SOFTMAX => BuiltinParams::Softmax { beta: raw.floats[0] },
+            KIND_LEAKY_RELU => BuiltinParams::LeakyRelu { alpha: raw.floats[0] },
+            KIND_RESHAPE => {
+                let len = (ints[0].max(0) as usize).min(ints.len() - 1);
+                BuiltinParams::Reshape { shape: ints[1..=len].to_vec() }
+            }
+            KIND_SVDF => BuiltinParams::Svdf { rank: ints[0], activation: activation(1)? },
+            KIND_LSTM => BuiltinParams::Lstm(LstmParams {
+                activation: activation(0)?,
+                cell_clip: raw.floats[0],
+                proj_clip: raw.floats[1],
+                basic_kernel: ints[1] != 0,
+            }),
+            KIND_STRIDED_SLICE => BuiltinParams::StridedSlice(StridedSliceParams {
+                begin_mask: ints[0],
+                end_mask: ints[1],
+                ellipsis_mask: ints[2],
+                new_axis_mask: ints[3],
+                shrink_axis_mask: ints[4],
+            }),
+            KIND_RESIZE_BILINEAR => BuiltinParams::ResizeBilinear {
+                align_corners: ints[0] != 0,
+                half_pixel_centers: ints[1] != 0,
+            },
+            KIND_REDUCER => BuiltinParams::Reducer { keep_dims: ints[0] != 0 },
+            KIND_GATHER => BuiltinParams::Gather { axis: ints[0] },
+            _ => return None,
+        })
+    }
+}
+
+// Kinds of `RawParams`, passed to the C++ side.
+const KIND_CONV: i32 = 1;
+const KIND_DEPTHWISE_CONV: i32 = 2;
+const KIND_TRANSPOSE_CONV: i32 = 3;
+const KIND_POOL: i32 = 4;
+const KIND_FULLY_CONNECTED: i32 = 5;
+const KIND_ACTIVATION: i32 = 6;
+const KIND_CONCATENATION: i32 = 7;
+const KIND_SOFTMAX: i32 = 8;
+const KIND_LEAKY_RELU: i32 = 9;
+const KIND_RESHAPE: i32 = 10;
+const KIND_SVDF: i32 = 11;
+const KIND_LSTM: i32 = 12;
+const KIND_STRIDED_SLICE: i32 = 13;
+const KIND_RESIZE_BILINEAR: i32 = 14;
+const KIND_REDUCER: i32 = 15;
+const KIND_GATHER: i32 = 16;
+
+/// The parameters of a node flattened by the C++ side, 0 `kind` for none.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct RawParams {
+    kind: i32,
+    ints: [i32; 9],
+    floats: [f32; 2],
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// The parameters of `node`, if it is a builtin operator this crate knows the
+    /// parameters of.
+    pub fn builtin_params(&self, node: usize) -> Option<BuiltinParams> {
+        let interpreter = self.handle();
+        let node = node as i32;
+        let mut raw = RawParams::default();
+        let raw_ptr = &mut raw as *mut RawParams;
+        let (kind_conv, kind_depthwise_conv, kind_transpose_conv, kind_pool) =
+            (KIND_CONV, KIND_DEPTHWISE_CONV, KIND_TRANSPOSE_CONV, KIND_POOL);
+        let (kind_fully_connected, kind_activation, kind_concatenation, kind_softmax) =
+            (KIND_FULLY_CONNECTED, KIND_ACTIVATION, KIND_CONCATENATION, KIND_SOFTMAX);
+        let (kind_leaky_relu, kind_reshape, kind_svdf, kind_lstm) =
+            (KIND_LEAKY_RELU, KIND_RESHAPE, KIND_SVDF, KIND_LSTM);
+        let (kind_strided_slice, kind_resize_bilinear, kind_reducer, kind_gather) =
+            (KIND_STRIDED_SLICE, KIND_RESIZE_BILINEAR, KIND_REDUCER, KIND_GATHER);
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([interpreter as "const Interpreter*", node as "int", raw_ptr as "void*",
+                  kind_conv as "int", kind_depthwise_conv as "int", kind_transpose_conv as "int",
+                  kind_pool as "int", kind_fully_connected as "int", kind_activation as "int",
+                  kind_concatenation as "int", kind_softmax as "int", kind_leaky_relu as "int",
+                  kind_reshape as "int", kind_svdf as "int", kind_lstm as "int",
+                  kind_strided_slice as "int", kind_resize_bilinear as "int", kind_reducer as "int",
+                  kind_gather as "int"] {
+                const auto* pair = interpreter->node_and_registration(node);
+                if (pair == nullptr || pair->first.builtin_data == nullptr) {
+                    return;
+                }
+                struct Raw { int kind; int ints[9]; float floats[2]; };
+                Raw* raw = static_cast<Raw*>(raw_ptr);
+                int* ints = raw->ints;
+                const void* data = pair->first.builtin_data;
+                switch (pair->second.builtin_code) {
+                    case kTfLiteBuiltinConv2d: {
+                        const auto* p = static_cast<const TfLiteConvParams*>(data);
+                        raw->kind = kind_conv;
+                        ints[0] = p->padding;
+                        ints[1] = p->stride_width;
+                        ints[2] = p->stride_height;
+                        ints[3] = p->dilation_width_factor;
+                        ints[4] = p->dilation_height_factor;
+                        ints[5] = p->activation;
+                        break;
+                    }
+                    case kTfLiteBuiltinDepthwiseConv2d: {
+                        const auto* p = static_cast<const TfLiteDepthwiseConvParams*>(data);
+                        raw->kind = kind_depthwise_conv;
+                        ints[0] = p->padding;
+                        ints[1] = p->stride_width;
+                        ints[2] = p->stride_height;
+                        ints[3] = p->dilation_width_factor;
+                        ints[4] = p->dilation_height_factor;
+                        ints[5] = p->activation;
+                        ints[6] = p->depth_multiplier;
+                        break;
+                    }
+                    case kTfLiteBuiltinTransposeConv: {
+                        const auto* p = static_cast<const TfLiteTransposeConvParams*>(data);
+                        raw->kind = kind_transpose_conv;
+                        ints[0] = p->padding;
+                        ints[1] = p->stride_width;
+                        ints[2] = p->stride_height;
+                        ints[3] = 1;
+                        ints[4] = 1;
+                        ints[5] = kTfLiteActNone;
+                        break;
+                    }
+                    case kTfLiteBuiltinAveragePool2d:
+                    case kTfLiteBuiltinMaxPool2d:
+                    case kTfLiteBuiltinL2Pool2d: {
+                        const auto* p = static_cast<const TfLitePoolParams*>(data);
+                        raw->kind = kind_pool;
+                        ints[0] = p->padding;
+                        ints[1] = p->stride_width;
+                        ints[2] = p->stride_height;
+                        ints[3] = p->filter_width;
+                        ints[4] = p->filter_height;
+                        ints[5] = p->activation;
+                        break;
+                    }
+                    case kTfLiteBuiltinFullyConnected: {
+                        const auto* p = static_cast<const TfLiteFullyConnectedParams*>(data);
+                        raw->kind = kind_fully_connected;
+                        ints[0] = p->activation;
+                        ints[1] = p->weights_format != kTfLiteFullyConnectedWeightsFormatDefault;
+                        ints[2] = p->keep_num_dims;
+                        break;
+                    }
+                    case kTfLiteBuiltinAdd:
+                        raw->kind = kind_activation;
+                        ints[0] = static_cast<const TfLiteAddParams*>(data)->activation;
+                        break;
+                    case kTfLiteBuiltinSub:
+                        raw->kind = kind_activation;
+                        ints[0] = static_cast<const TfLiteSubParams*>(data)->activation;
+                        break;
+                    case kTfLiteBuiltinMul:
+                        raw->kind = kind_activation;
+                        ints[0] = static_cast<const TfLiteMulParams*>(data)->activation;
+                        break;
+                    case kTfLiteBuiltinDiv:
+                        raw->kind = kind_activation;
+                        ints[0] = static_cast<const TfLiteDivParams*>(data)->activation;
+                        break;
+                    case kTfLiteBuiltinL2Normalization:
+                        raw->kind = kind_activation;
+                        ints[0] = static_cast<const TfLiteL2NormParams*>(data)->activation;
+                        break;
+                    case kTfLiteBuiltinRnn:
+                        raw->kind = kind_activation;
+                        ints[0] = static_cast<const TfLiteRNNParams*>(data)->activation;
+                        break;
+                    case kTfLiteBuiltinConcatenation: {
+                        const auto* p = static_cast<const TfLiteConcatenationParams*>(data);
+                        raw->kind = kind_concatenation;
+                        ints[0] = p->axis;
+                        ints[1] = p->activation;
+                        break;
+                    }
+                    case kTfLiteBuiltinSoftmax:
+                        raw->kind = kind_softmax;
+                        raw->floats[0] = static_cast<const TfLiteSoftmaxParams*>(data)->beta;
+                        break;
+                    case kTfLiteBuiltinLeakyRelu:
+                        raw->kind = kind_leaky_relu;
+                        raw->floats[0] = static_cast<const TfLiteLeakyReluParams*>(data)->alpha;
+                        break;
+                    case kTfLiteBuiltinReshape: {
+                        const auto* p = static_cast<const TfLiteReshapeParams*>(data);
+                        raw->kind = kind_reshape;
+                        ints[0] = std::min(p->num_dimensions, 8);
+                        for (int i = 0; i < ints[0]; ++i) {
+                            ints[i + 1] = p->shape[i];
+                        }
+                        break;
+                    }
+                    case kTfLiteBuiltinSvdf: {
+                        const auto* p = static_cast<const TfLiteSVDFParams*>(data);
+                        raw->kind = kind_svdf;
+                        ints[0] = p->rank;
+                        ints[1] = p->activation;
+                        break;
+                    }
+                    case kTfLiteBuiltinLstm: {
+                        const auto* p = static_cast<const TfLiteLSTMParams*>(data);
+                        raw->kind = kind_lstm;
+                        ints[0] = p->activation;
+                        ints[1] = p->kernel_type == kTfLiteLSTMBasicKernel;
+                        raw->floats[0] = p->cell_clip;
+                        raw->floats[1] = p->proj_clip;
+                        break;
+                    }
+                    case kTfLiteBuiltinStridedSlice: {
+                        const auto* p = static_cast<const TfLiteStridedSliceParams*>(data);
+                        raw->kind = kind_strided_slice;
+                        ints[0] = p->begin_mask;
+                        ints[1] = p->end_mask;
+                        ints[2] = p->ellipsis_mask;
+                        ints[3] = p->new_axis_mask;
+                        ints[4] = p->shrink_axis_mask;
+                        break;
+                    }
+                    case kTfLiteBuiltinResizeBilinear: {
+                        const auto* p = static_cast<const TfLiteResizeBilinearParams*>(data);
+                        raw->kind = kind_resize_bilinear;
+                        ints[0] = p->align_corners;
+                        ints[1] = p->half_pixel_centers;
+                        break;
+                    }
+                    case kTfLiteBuiltinMean:
+                    case kTfLiteBuiltinSum:
+                    case kTfLiteBuiltinReduceProd:
+                    case kTfLiteBuiltinReduceMax:
+                    case kTfLiteBuiltinReduceMin:
+                    case kTfLiteBuiltinReduceAny:
+                        raw->kind = kind_reducer;
+                        ints[0] = static_cast<const TfLiteReducerParams*>(data)->keep_dims;
+                        break;
+                    case kTfLiteBuiltinGather:
+                        raw->kind = kind_gather;
+                        ints[0] = static_cast<const TfLiteGatherParams*>(data)->axis;
+                        break;
+                    default:
+                        break;
+                }
+            })
+        };
+        BuiltinParams::from_raw(&raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_builtin_params() {
+        let mut raw = RawParams { kind: KIND_DEPTHWISE_CONV, ..RawParams::default() };
+        raw.ints[..7].copy_from_slice(&[1, 2, 2, 1, 1, 3, 4]);
+        let params = BuiltinParams::from_raw(&raw).unwrap();
+        let conv = ConvParams {
+            padding: Padding::Same,
+            stride_width: 2,
+            stride_height: 2,
+            dilation_width_factor: 1,
+            dilation_height_factor: 1,
+            activation: FusedActivation::Relu6,
+        };
+        assert_eq!(
+            params,
+            BuiltinParams::DepthwiseConv2d(DepthwiseConvParams { conv, depth_multiplier: 4 })
+        );
+        assert_eq!(params.fused_activation(), Some(FusedActivation::Relu6));
+
+        let mut raw = RawParams { kind: KIND_RESHAPE, ..RawParams::default() };
+        raw.ints[..4].copy_from_slice(&[3, 1, -1, 10]);
+        let params = BuiltinParams::from_raw(&raw).unwrap();
+        assert_eq!(params, BuiltinParams::Reshape { shape: vec![1, -1, 10] });
+        assert_eq!(params.fused_activation(), None);
+
+        raw.kind = KIND_CONCATENATION;
+        raw.ints[1] = 42;
+        assert!(BuiltinParams::from_raw(&raw).is_none());
+        assert!(BuiltinParams::from_raw(&RawParams::default()).is_none());
+    }
+
+    #[test]
+    fn unittest_mnist_builtin_params() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let interpreter = builder.build().unwrap();
+
+        let conv = |stride: i32| ConvParams {
+            padding: Padding::Same,
+            stride_width: stride,
+            stride_height: stride,
+            dilation_width_factor: 1,
+            dilation_height_factor: 1,
+            activation: FusedActivation::None,
+        };
+        assert_eq!(
+            interpreter.builtin_params(0),
+            Some(BuiltinParams::DepthwiseConv2d(DepthwiseConvParams {
+                conv: conv(2),
+                depth_multiplier: 32,
+            }))
+        );
+        assert_eq!(interpreter.builtin_params(2), Some(BuiltinParams::Conv2d(conv(1))));
+        assert_eq!(
+            interpreter.builtin_params(5),
+            Some(BuiltinParams::Pool2d(PoolParams {
+                padding: Padding::Valid,
+                stride_width: 2,
+                stride_height: 2,
+                filter_width: 7,
+                filter_height: 7,
+                activation: FusedActivation::None,
+            }))
+        );
+        assert_eq!(interpreter.builtin_params(8), Some(BuiltinParams::Softmax { beta: 1.0 }));
+    }
+}
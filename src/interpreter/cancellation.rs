@@ -0,0 +1,170 @@
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+#[derive(Clone, Debug)]
+enum Flag {
+    Shared(Arc<AtomicBool>),
+    Static(&'static AtomicBool),
+}
+
+/// A shared flag queried by the interpreter between op invocations.
+///
+/// Once cancelled, an in-flight `Interpreter::invoke` stops at the next op boundary
+/// and returns `Error::Cancelled`. The token stays cancelled until `reset` is called.
+///
+/// `cancel` is a single atomic store; it takes no locks and never allocates, so it may be
+/// called from a signal handler or a `Drop` implementation. For signal handlers, back the
+/// token with a `static` flag via `from_static` and set that flag from the handler.
+#[derive(Clone, Debug)]
+pub struct CancellationToken {
+    flag: Flag,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self { flag: Flag::Shared(Arc::new(AtomicBool::new(false))) }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token backed by a `static` flag, e.g. one set by a SIGTERM handler.
+    pub fn from_static(flag: &'static AtomicBool) -> Self {
+        Self { flag: Flag::Static(flag) }
+    }
+
+    fn flag(&self) -> &AtomicBool {
+        match &self.flag {
+            Flag::Shared(flag) => flag,
+            Flag::Static(flag) => flag,
+        }
+    }
+
+    /// Requests cancellation of the current and any following invocations.
+    /// Async-signal-safe.
+    pub fn cancel(&self) {
+        self.flag().store(true, Ordering::SeqCst);
+    }
+
+    /// Clears a previous cancellation request.
+    pub fn reset(&self) {
+        self.flag().store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag().load(Ordering::SeqCst)
+    }
+
+    /// Returns a guard which cancels this token when dropped, unless disarmed.
+    pub fn cancel_on_drop(&self) -> CancelGuard {
+        CancelGuard { token: Some(self.clone()) }
+    }
+}
+
+/// Cancels its token when dropped, so in-flight inference stops during unwinding or shutdown.
+#[derive(Debug)]
+pub struct CancelGuard {
+    token: Option<CancellationToken>,
+}
+
+impl CancelGuard {
+    /// Drops the guard without cancelling the token.
+    pub fn disarm(mut self) {
+        self.token = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(token) = &self.token {
+            token.cancel();
+        }
+    }
+}
+
+/// What the cancellation function of an interpreter checks between ops: its token, and the
+/// deadline of `Interpreter::invoke_with_timeout`, kept apart so that timeouts never touch the
+/// token.
+#[derive(Debug, Default)]
+pub(crate) struct Interrupt {
+    pub(crate) token: Option<CancellationToken>,
+    deadline: Option<Instant>,
+    timed_out: AtomicBool,
+}
+
+impl Interrupt {
+    /// Interrupts invocations once `deadline` passes, until set to `None`.
+    pub(crate) fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        *self.timed_out.get_mut() = false;
+    }
+
+    /// Whether the deadline interrupted an invocation since it was set.
+    pub(crate) fn timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.token.as_ref().is_some_and(CancellationToken::is_cancelled)
+    }
+
+    fn check(&self) -> bool {
+        if self.is_cancelled() {
+            return true;
+        }
+        let timed_out = self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if timed_out {
+            self.timed_out.store(true, Ordering::SeqCst);
+        }
+        timed_out
+    }
+}
+
+/// Cancellation callback handed to `Interpreter::SetCancellationFunction`.
+/// `data` points to an `Interrupt` kept alive by the interpreter, which only changes it while
+/// not invoked. Only performs atomic operations and reads the clock, so it cannot panic across
+/// the FFI boundary.
+pub(crate) extern "C" fn check_interrupted(data: *mut c_void) -> bool {
+    let interrupt = unsafe { &*(data as *const Interrupt) };
+    interrupt.check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_cancellation_token() {
+        static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+        let token = CancellationToken::from_static(&SHUTDOWN);
+        assert!(!token.is_cancelled());
+        SHUTDOWN.store(true, Ordering::SeqCst);
+        assert!(token.is_cancelled());
+        let mut interrupt = Interrupt { token: Some(token.clone()), ..Interrupt::default() };
+        let data = &mut interrupt as *mut Interrupt as *mut c_void;
+        assert!(check_interrupted(data));
+        token.reset();
+        assert!(!SHUTDOWN.load(Ordering::SeqCst));
+        assert!(!check_interrupted(data));
+
+        interrupt.set_deadline(Some(Instant::now()));
+        assert!(check_interrupted(data));
+        assert!(interrupt.timed_out() && !token.is_cancelled());
+        interrupt.set_deadline(None);
+        assert!(!check_interrupted(data) && !interrupt.timed_out());
+
+        let token = CancellationToken::new();
+        token.cancel_on_drop().disarm();
+        assert!(!token.is_cancelled());
+        {
+            let _guard = token.cancel_on_drop();
+        }
+        assert!(token.is_cancelled());
+    }
+}
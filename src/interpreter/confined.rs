@@ -0,0 +1,145 @@
+//! Keeping a value on the thread that created it, for interpreters whose delegates are bound
+//! to their thread, like the OpenGL backend of the GPU delegate:
+//!
+//! ```ignore
+//! let mut interpreter = ThreadConfined::new(interpreter);
+//! interpreter.get_mut()?.modify_graph_with_delegate(&gpu)?;
+//! // Fails instead of crashing the GL driver on another thread.
+//! interpreter.get_mut()?.invoke()?;
+//! ```
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::thread::{self, ThreadId};
+
+use crate::{Error, Result};
+
+/// A value only reachable from the thread that created the wrapper.
+///
+/// The wrapper is not `Sync`, and `Send` if the value is, so that it can be stored in
+/// structures moved between threads; reaching the value from another thread fails. Dropping
+/// it on another thread leaks the value rather than dropping it there, so drop it on its
+/// thread to free it.
+#[derive(Debug)]
+pub struct ThreadConfined<T> {
+    value: ManuallyDrop<T>,
+    owner: ThreadId,
+    not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> ThreadConfined<T> {
+    /// Confines `value` to the current thread.
+    pub fn new(value: T) -> Self {
+        Self {
+            value: ManuallyDrop::new(value),
+            owner: thread::current().id(),
+            not_sync: PhantomData,
+        }
+    }
+
+    pub fn owner(&self) -> ThreadId {
+        self.owner
+    }
+
+    /// Whether the value is reachable from the current thread.
+    pub fn is_owner(&self) -> bool {
+        thread::current().id() == self.owner
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.is_owner() {
+            Ok(())
+        } else {
+            Err(Error::InternalError(format!(
+                "value confined to thread {:?} used from thread {:?}",
+                self.owner,
+                thread::current().id()
+            )))
+        }
+    }
+
+    pub fn get(&self) -> Result<&T> {
+        self.check().map(|()| &*self.value)
+    }
+
+    pub fn get_mut(&mut self) -> Result<&mut T> {
+        self.check()?;
+        Ok(&mut self.value)
+    }
+
+    /// The value, on its thread.
+    pub fn into_inner(self) -> std::result::Result<T, Self> {
+        if self.is_owner() {
+            let mut confined = ManuallyDrop::new(self);
+            // Safety: `confined` is never used or dropped again.
+            Ok(unsafe { ManuallyDrop::take(&mut confined.value) })
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl<T> Drop for ThreadConfined<T> {
+    fn drop(&mut self) {
+        if self.is_owner() {
+            // Safety: `value` is never used again.
+            unsafe { ManuallyDrop::drop(&mut self.value) }
+        } else {
+            #[cfg(feature = "log")]
+            log::warn!(
+                target: "tflite",
+                "leaking a value confined to thread {:?} dropped on thread {:?}",
+                self.owner,
+                thread::current().id()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn unittest_thread_confined() {
+        let mut confined = ThreadConfined::new(vec![1, 2]);
+        confined.get_mut().unwrap().push(3);
+        assert_eq!(confined.get().unwrap(), &[1, 2, 3]);
+
+        let confined = thread::spawn(move || {
+            let err = confined.get().unwrap_err();
+            assert!(err.to_string().contains("used from thread"), "{}", err);
+            confined.into_inner().unwrap_err()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(confined.into_inner().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn unittest_thread_confined_drop() {
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let confined = ThreadConfined::new(Counted(drops.clone()));
+        thread::spawn(move || drop(confined)).join().unwrap();
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+
+        drop(ThreadConfined::new(Counted(drops.clone())));
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+        let value = ThreadConfined::new(Counted(drops.clone())).into_inner().ok().unwrap();
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+        drop(value);
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,33 +1,159 @@
+use std::convert::{TryFrom, TryInto};
 use std::ffi::CStr;
-use std::fmt;
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::{fmt, mem};
 
 use crate::bindings;
+pub use crate::bindings::TfLiteIntArray;
+use crate::leak_tracking::{self, NativeObject};
+use crate::{Error, Result};
 
 pub type ElementKind = bindings::TfLiteType;
 pub type QuantizationParams = bindings::TfLiteQuantizationParams;
 
+/// Element types typed tensor accessors hand out slices of. `bool` is not one, as TF Lite
+/// tensors may hold bytes other than 0 and 1: read boolean tensors as bytes with
+/// `tensor_buffer`.
 pub trait ElemKindOf {
     fn elem_kind_of() -> ElementKind;
 }
 
-impl ElemKindOf for f32 {
-    fn elem_kind_of() -> ElementKind {
-        bindings::TfLiteType::kTfLiteFloat32
+macro_rules! elem_kind_of {
+    ($($t:ty => $kind:ident),* $(,)?) => {$(
+        impl ElemKindOf for $t {
+            fn elem_kind_of() -> ElementKind {
+                bindings::TfLiteType::$kind
+            }
+        }
+    )*};
+}
+
+elem_kind_of! {
+    f32 => kTfLiteFloat32,
+    f64 => kTfLiteFloat64,
+    F16 => kTfLiteFloat16,
+    u8 => kTfLiteUInt8,
+    i8 => kTfLiteInt8,
+    i16 => kTfLiteInt16,
+    i32 => kTfLiteInt32,
+    i64 => kTfLiteInt64,
+}
+
+/// Element types with a fixed byte representation, so that they can be decoded from bytes at
+/// any alignment and in either byte order.
+pub trait Element: ElemKindOf + Copy {
+    const SIZE: usize;
+
+    /// Decodes `SIZE` bytes in the byte order of the target, as TF Lite stores tensors.
+    fn from_ne_bytes(bytes: &[u8]) -> Self;
+
+    /// Decodes `SIZE` bytes in little-endian order, as TF Lite models store constants.
+    fn from_le_bytes(bytes: &[u8]) -> Self;
+
+    /// Encodes into `SIZE` bytes in little-endian order.
+    fn write_le_bytes(self, out: &mut [u8]);
+}
+
+macro_rules! element {
+    ($($t:ty),*) => {$(
+        impl Element for $t {
+            const SIZE: usize = mem::size_of::<$t>();
+
+            fn from_ne_bytes(bytes: &[u8]) -> Self {
+                <$t>::from_ne_bytes(bytes.try_into().expect("element size"))
+            }
+
+            fn from_le_bytes(bytes: &[u8]) -> Self {
+                <$t>::from_le_bytes(bytes.try_into().expect("element size"))
+            }
+
+            fn write_le_bytes(self, out: &mut [u8]) {
+                out.copy_from_slice(&self.to_le_bytes());
+            }
+        }
+    )*};
+}
+
+element!(f32, f64, u8, i8, i16, i32, i64);
+
+impl Element for F16 {
+    const SIZE: usize = 2;
+
+    fn from_ne_bytes(bytes: &[u8]) -> Self {
+        F16(u16::from_ne_bytes(bytes.try_into().expect("element size")))
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        F16(u16::from_le_bytes(bytes.try_into().expect("element size")))
+    }
+
+    fn write_le_bytes(self, out: &mut [u8]) {
+        out.copy_from_slice(&self.0.to_le_bytes());
     }
 }
 
-impl ElemKindOf for u8 {
-    fn elem_kind_of() -> ElementKind {
-        bindings::TfLiteType::kTfLiteUInt8
+/// An IEEE 754 half-precision float, the element type of `kTfLiteFloat16` tensors.
+#[repr(transparent)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct F16(pub u16);
+
+impl F16 {
+    pub fn to_f32(self) -> f32 {
+        let sign = u32::from(self.0 >> 15) << 31;
+        let (exponent, fraction) = (u32::from(self.0 >> 10) & 0x1f, u32::from(self.0) & 0x3ff);
+        match exponent {
+            0 => {
+                // Zero or subnormal, fraction * 2^-24.
+                let value = fraction as f32 / 16_777_216.0;
+                f32::from_bits(sign | value.to_bits())
+            }
+            0x1f => f32::from_bits(sign | 0x7f80_0000 | (fraction << 13)),
+            _ => f32::from_bits(sign | ((exponent + 112) << 23) | (fraction << 13)),
+        }
+    }
+
+    /// Rounds to the nearest half-precision value, ties to even.
+    pub fn from_f32(value: f32) -> Self {
+        let bits = value.to_bits();
+        let sign = ((bits >> 16) & 0x8000) as u16;
+        let exponent = ((bits >> 23) & 0xff) as i32;
+        let fraction = bits & 0x7f_ffff;
+        if exponent == 0xff {
+            let nan = if fraction != 0 { 0x200 | (fraction >> 13) as u16 } else { 0 };
+            return F16(sign | 0x7c00 | nan);
+        }
+        let exponent = exponent - 127 + 15;
+        if exponent >= 0x1f {
+            return F16(sign | 0x7c00);
+        }
+        // Bits dropped from the f32 mantissa, including the implicit one for subnormals.
+        let (mantissa, shift, exponent_bits) = if exponent <= 0 {
+            if exponent < -10 {
+                return F16(sign);
+            }
+            (fraction | 0x80_0000, (14 - exponent) as u32, 0)
+        } else {
+            (fraction, 13, (exponent as u32) << 10)
+        };
+        let mut rounded = exponent_bits | (mantissa >> shift);
+        let rest = mantissa & ((1 << shift) - 1);
+        let midpoint = 1 << (shift - 1);
+        if rest > midpoint || (rest == midpoint && rounded & 1 == 1) {
+            // May carry into the exponent, up to infinity.
+            rounded += 1;
+        }
+        F16(sign | rounded as u16)
     }
 }
 
-impl ElemKindOf for i32 {
-    fn elem_kind_of() -> ElementKind {
-        bindings::TfLiteType::kTfLiteInt32
+impl From<F16> for f32 {
+    fn from(value: F16) -> Self {
+        value.to_f32()
     }
 }
 
+#[derive(Clone, PartialEq, Eq)]
 pub struct TensorInfo {
     pub name: String,
     pub element_kind: ElementKind,
@@ -46,16 +172,318 @@ impl fmt::Debug for TensorInfo {
 
 impl<'a> From<&'a bindings::TfLiteTensor> for TensorInfo {
     fn from(t: &'a bindings::TfLiteTensor) -> Self {
-        Self {
-            name: unsafe { CStr::from_ptr(t.name) }.to_str().unwrap().to_string(),
-            element_kind: t.type_,
-            dims: {
-                let slice = unsafe {
-                    let dims = &*t.dims;
-                    dims.data.as_slice(dims.size as usize)
-                };
-                slice.iter().map(|n| *n as usize).collect()
-            },
+        // Tensors added at runtime, e.g. by control flow kernels, have neither.
+        let name = if t.name.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(t.name) }.to_string_lossy().into_owned()
+        };
+        let dims = if t.dims.is_null() {
+            Vec::new()
+        } else {
+            unsafe { int_array_as_slice(&*t.dims) }.iter().map(|&n| n.max(0) as usize).collect()
+        };
+        Self { name, element_kind: t.type_, dims }
+    }
+}
+
+/// Size in bytes of one element, or `None` for variable-length strings.
+pub fn element_size(kind: ElementKind) -> Option<usize> {
+    use bindings::TfLiteType::*;
+    match kind {
+        kTfLiteUInt8 | kTfLiteInt8 | kTfLiteBool => Some(1),
+        kTfLiteInt16 | kTfLiteFloat16 => Some(2),
+        kTfLiteFloat32 | kTfLiteInt32 => Some(4),
+        kTfLiteInt64 | kTfLiteFloat64 | kTfLiteComplex64 => Some(8),
+        kTfLiteString | kTfLiteNoType => None,
+    }
+}
+
+/// Byte strides of a dense row-major tensor with the given dims.
+pub fn row_major_strides(dims: &[usize], element_size: usize) -> Vec<usize> {
+    let mut strides = vec![element_size; dims.len()];
+    for i in (0..dims.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * dims[i + 1];
+    }
+    strides
+}
+
+/// The number of elements of a tensor with `dims`, failing rather than wrapping around for
+/// tensors too large to address.
+pub fn element_count(dims: &[usize]) -> Result<usize> {
+    dims.iter().try_fold(1usize, |count, &dim| count.checked_mul(dim)).ok_or_else(|| {
+        Error::InternalError(format!("tensor of dims {:?} exceeds the address space", dims))
+    })
+}
+
+/// The size in bytes of a dense tensor with `dims`, failing rather than wrapping around.
+pub fn byte_size(dims: &[usize], element_size: usize) -> Result<usize> {
+    element_count(dims)?.checked_mul(element_size).ok_or_else(|| {
+        Error::InternalError(format!("tensor of dims {:?} exceeds the address space", dims))
+    })
+}
+
+/// `value` as the `int` TF Lite stores dims in. A single dim or buffer of 2 GB or more
+/// cannot be represented, though the total size of a tensor can be larger.
+pub fn to_dim(value: usize) -> Result<i32> {
+    i32::try_from(value).map_err(|_| {
+        Error::InternalError(format!("dim {} exceeds the i32 range of TF Lite dims", value))
+    })
+}
+
+/// `dims` as TF Lite dims, see `to_dim`.
+pub fn to_dims(dims: &[usize]) -> Result<Vec<i32>> {
+    dims.iter().map(|&dim| to_dim(dim)).collect()
+}
+
+/// How the data behind a tensor's CPU pointer is arranged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Dense row-major data covering exactly the tensor's dims.
+    Contiguous,
+    /// Compressed according to the tensor's sparsity parameters.
+    Sparse,
+    /// The current data lives in a delegate buffer; the CPU copy is stale.
+    DelegateBuffer,
+    /// Variable-length elements such as strings.
+    Variable,
+    /// No CPU data, or a size that does not match the dims.
+    Unallocated,
+}
+
+/// Shape and byte strides of a tensor, for zero-copy views of its data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TensorLayout {
+    pub layout: Layout,
+    pub dims: Vec<usize>,
+    /// Byte strides the data would have when dense. Only describes the data when
+    /// `layout` is `Contiguous`.
+    pub strides: Vec<usize>,
+    pub element_size: Option<usize>,
+    pub bytes: usize,
+}
+
+impl TensorLayout {
+    pub fn is_contiguous(&self) -> bool {
+        self.layout == Layout::Contiguous
+    }
+}
+
+impl<'a> From<&'a bindings::TfLiteTensor> for TensorLayout {
+    fn from(t: &'a bindings::TfLiteTensor) -> Self {
+        const NULL_BUFFER_HANDLE: i32 = -1;
+
+        let dims: Vec<usize> = if t.dims.is_null() {
+            Vec::new()
+        } else {
+            unsafe { int_array_as_slice(&*t.dims) }.iter().map(|&n| n.max(0) as usize).collect()
+        };
+        let element_size = element_size(t.type_);
+        let strides = row_major_strides(&dims, element_size.unwrap_or(0));
+        let layout = if !t.sparsity.is_null() {
+            Layout::Sparse
+        } else if t.buffer_handle != NULL_BUFFER_HANDLE && t.data_is_stale {
+            Layout::DelegateBuffer
+        } else if unsafe { t.data.raw_const }.is_null() {
+            Layout::Unallocated
+        } else {
+            match element_size {
+                None => Layout::Variable,
+                Some(size) if byte_size(&dims, size).ok() == Some(t.bytes) => Layout::Contiguous,
+                Some(_) => Layout::Unallocated,
+            }
+        };
+        Self { layout, dims, strides, element_size, bytes: t.bytes }
+    }
+}
+
+/// Views the elements of a `TfLiteIntArray`.
+///
+/// # Safety
+/// The array must be followed by `size` elements, as every array allocated by TFLite or
+/// `IntArray` is.
+pub unsafe fn int_array_as_slice(array: &TfLiteIntArray) -> &[i32] {
+    array.data.as_slice(array.size.max(0) as usize)
+}
+
+/// An owned `TfLiteIntArray`, allocated the way `TfLiteIntArrayCreate` does so that TFLite
+/// may take ownership of it (e.g. `ResizeTensor`, or node temporaries in custom ops).
+pub struct IntArray(NonNull<TfLiteIntArray>);
+
+unsafe impl Send for IntArray {}
+unsafe impl Sync for IntArray {}
+
+impl IntArray {
+    pub fn new(values: &[i32]) -> Self {
+        let size = mem::size_of::<TfLiteIntArray>() + mem::size_of_val(values);
+        let array = unsafe { libc::malloc(size) as *mut TfLiteIntArray };
+        let mut array = NonNull::new(array).expect("failed to allocate TfLiteIntArray");
+        leak_tracking::created(NativeObject::IntArray);
+        unsafe {
+            let array = array.as_mut();
+            array.size = i32::try_from(values.len()).expect("too many values for TfLiteIntArray");
+            array.data.as_mut_slice(values.len()).copy_from_slice(values);
+        }
+        IntArray(array)
+    }
+
+    /// Copies an array of TFLite.
+    ///
+    /// # Safety
+    /// As for `int_array_as_slice`, the array must be followed by `size` elements.
+    pub unsafe fn copy_from(array: &TfLiteIntArray) -> Self {
+        IntArray::new(int_array_as_slice(array))
+    }
+
+    pub fn as_ptr(&self) -> *const TfLiteIntArray {
+        self.0.as_ptr()
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [i32] {
+        unsafe {
+            let array = self.0.as_mut();
+            array.data.as_mut_slice(array.size as usize)
+        }
+    }
+
+    /// Releases ownership. The array must be freed with `TfLiteIntArrayFree` or
+    /// `IntArray::from_raw`.
+    pub fn into_raw(self) -> *mut TfLiteIntArray {
+        let ptr = self.0.as_ptr();
+        leak_tracking::destroyed(NativeObject::IntArray);
+        mem::forget(self);
+        ptr
+    }
+
+    /// Takes ownership of an array allocated by TFLite or `IntArray::into_raw`.
+    ///
+    /// # Safety
+    /// `ptr` must be non-null, allocated with `malloc`, and not be freed elsewhere.
+    pub unsafe fn from_raw(ptr: *mut TfLiteIntArray) -> Self {
+        leak_tracking::created(NativeObject::IntArray);
+        IntArray(NonNull::new_unchecked(ptr))
+    }
+}
+
+impl Drop for IntArray {
+    fn drop(&mut self) {
+        unsafe { libc::free(self.0.as_ptr() as *mut libc::c_void) };
+        leak_tracking::destroyed(NativeObject::IntArray);
+    }
+}
+
+impl Deref for IntArray {
+    type Target = [i32];
+
+    fn deref(&self) -> &[i32] {
+        unsafe { int_array_as_slice(self.0.as_ref()) }
+    }
+}
+
+impl Clone for IntArray {
+    fn clone(&self) -> Self {
+        IntArray::new(self)
+    }
+}
+
+impl fmt::Debug for IntArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl PartialEq for IntArray {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for IntArray {}
+
+impl<'a> From<&'a [i32]> for IntArray {
+    fn from(values: &'a [i32]) -> Self {
+        IntArray::new(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_int_array() {
+        let mut array = IntArray::new(&[1, 28, 28, 3]);
+        assert_eq!(&*array, &[1, 28, 28, 3]);
+        array.as_mut_slice()[0] = 4;
+
+        let raw = array.clone().into_raw();
+        assert_eq!(unsafe { int_array_as_slice(&*raw) }, &[4, 28, 28, 3]);
+        assert_eq!(unsafe { IntArray::copy_from(&*raw) }, array);
+        assert_eq!(unsafe { IntArray::from_raw(raw) }, array);
+
+        assert!(IntArray::new(&[]).is_empty());
+    }
+
+    #[test]
+    fn unittest_f16() {
+        for (value, bits) in [
+            (1.0f32, 0x3c00),
+            (-2.0, 0xc000),
+            (0.1, 0x2e66),
+            (65504.0, 0x7bff),
+            (1.0e5, 0x7c00),
+            (5.960_464_5e-8, 0x0001),
+            (1.0e-9, 0x0000),
+            (f32::NEG_INFINITY, 0xfc00),
+        ] {
+            assert_eq!(F16::from_f32(value), F16(bits), "{}", value);
+        }
+        // Ties to even: 1 + 2^-11 is halfway between 1 and the next value.
+        assert_eq!(F16::from_f32(1.0 + 1.0 / 2048.0), F16(0x3c00));
+        assert_eq!(F16::from_f32(1.0 + 3.0 / 2048.0), F16(0x3c02));
+        for bits in [0x0001u16, 0x03ff, 0x3555, 0x7bff, 0x8400, 0xfc00] {
+            assert_eq!(F16::from_f32(F16(bits).to_f32()), F16(bits));
         }
+        assert!(F16(0x7e00).to_f32().is_nan());
+        assert_eq!(F16::elem_kind_of(), bindings::TfLiteType::kTfLiteFloat16);
+    }
+
+    #[test]
+    fn unittest_size_math() {
+        assert_eq!(element_count(&[1, 28, 28, 3]).unwrap(), 2352);
+        assert_eq!(element_count(&[]).unwrap(), 1);
+        // A 200k x 4096 f32 embedding is larger than 2 GB but fits i32 dims.
+        assert_eq!(byte_size(&[200_000, 4096], 4).unwrap(), 3_276_800_000);
+        assert_eq!(to_dims(&[200_000, 4096]).unwrap(), vec![200_000, 4096]);
+        assert!(byte_size(&[usize::MAX / 2, 3], 1).is_err());
+        assert!(element_count(&[usize::MAX, 2]).is_err());
+        assert!(to_dim(3_000_000_000).is_err());
+        assert_eq!(to_dim(i32::MAX as usize).unwrap(), i32::MAX);
+    }
+
+    #[test]
+    fn unittest_tensor_layout() {
+        assert_eq!(row_major_strides(&[1, 28, 28, 3], 4), vec![9408, 336, 12, 4]);
+        assert_eq!(row_major_strides(&[], 4), Vec::<usize>::new());
+
+        let dims = IntArray::new(&[2, 3]);
+        let mut data = [0f32; 6];
+        let mut tensor: bindings::TfLiteTensor = unsafe { mem::zeroed() };
+        tensor.type_ = bindings::TfLiteType::kTfLiteFloat32;
+        tensor.dims = dims.as_ptr() as *mut _;
+        tensor.data.f = data.as_mut_ptr();
+        tensor.bytes = mem::size_of_val(&data);
+        tensor.buffer_handle = -1;
+
+        let layout = TensorLayout::from(&tensor);
+        assert!(layout.is_contiguous());
+        assert_eq!(layout.strides, vec![12, 4]);
+
+        tensor.bytes = 12;
+        assert_eq!(TensorLayout::from(&tensor).layout, Layout::Unallocated);
+        tensor.bytes = 24;
+        tensor.buffer_handle = 0;
+        tensor.data_is_stale = true;
+        assert_eq!(TensorLayout::from(&tensor).layout, Layout::DelegateBuffer);
     }
 }
@@ -0,0 +1,270 @@
+//! Tensors reading and writing memory owned outside of TF Lite, e.g. camera frames or shared
+//! memory, instead of copying to and from the arena on every frame:
+//!
+//! ```ignore
+//! let frame = unsafe { CustomAllocation::from_raw(buffer.ptr, buffer.len, buffer)? };
+//! interpreter.set_custom_allocation(interpreter.inputs()[0], frame)?;
+//! loop {
+//!     camera.wait_frame()?; // written in place by the driver
+//!     interpreter.invoke()?;
+//! }
+//! ```
+//!
+//! Needs TensorFlow Lite 2.4 or newer. Only inputs and outputs can use custom allocations.
+
+use std::alloc::{self, Layout};
+use std::ptr::NonNull;
+use std::slice;
+
+use libc::size_t;
+
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+use crate::{Error, Result, TENSORFLOW_VERSION};
+
+cpp! {{
+    #include "tensorflow/core/public/version.h"
+    #include "tensorflow/lite/interpreter.h"
+
+    using namespace tflite;
+
+    // 1 on success, 0 on failure, -1 if the runtime predates custom allocations, -2 if the
+    // tensor is neither in the arena nor already custom-allocated.
+    static int set_custom_allocation(Interpreter* interpreter, int tensor, void* data, size_t bytes) {
+    #if TF_MAJOR_VERSION > 2 || (TF_MAJOR_VERSION == 2 && TF_MINOR_VERSION >= 4)
+        TfLiteAllocationType type = interpreter->tensor(tensor)->allocation_type;
+        if (type != kTfLiteArenaRw && type != kTfLiteCustom) {
+            return -2;
+        }
+        TfLiteCustomAllocation allocation{data, bytes};
+        return interpreter->SetCustomAllocationForTensor(tensor, allocation) == kTfLiteOk;
+    #else
+        return -1;
+    #endif
+    }
+}}
+
+/// Memory used as the data of a tensor; see `Interpreter::set_custom_allocation`.
+pub struct CustomAllocation {
+    ptr: NonNull<u8>,
+    bytes: usize,
+    // Keeps the memory alive, dropped with the allocation.
+    _owner: Box<dyn Send + Sync>,
+}
+
+// # Safety
+// The memory is only reached through `&self`/`&mut self` or by the interpreter owning the
+// allocation, and `from_raw` requires the owner to keep it valid from any thread.
+unsafe impl Send for CustomAllocation {}
+unsafe impl Sync for CustomAllocation {}
+
+/// A zeroed heap buffer aligned for tensors.
+struct AlignedBytes(NonNull<u8>, Layout);
+
+unsafe impl Send for AlignedBytes {}
+unsafe impl Sync for AlignedBytes {}
+
+impl Drop for AlignedBytes {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.0.as_ptr(), self.1) };
+    }
+}
+
+impl CustomAllocation {
+    /// Alignment TF Lite requires of tensor data, `kDefaultTensorAlignment`.
+    pub const ALIGNMENT: usize = 64;
+
+    /// A zeroed buffer of `bytes`, e.g. to map into another process or hand to a driver.
+    pub fn new(bytes: usize) -> Self {
+        let layout = Layout::from_size_align(bytes.max(1), Self::ALIGNMENT)
+            .expect("allocation size overflows");
+        let ptr = NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self { ptr, bytes, _owner: Box::new(AlignedBytes(ptr, layout)) }
+    }
+
+    /// The `bytes` at `ptr`, kept valid by `owner`, e.g. a memory mapping or a camera buffer
+    /// handle. Fails unless `ptr` is aligned to `ALIGNMENT`.
+    ///
+    /// # Safety
+    /// The memory must stay valid for reads and writes until `owner` is dropped, on any
+    /// thread, and not be written by others while the interpreter is invoked.
+    pub unsafe fn from_raw<O>(ptr: *mut u8, bytes: usize, owner: O) -> Result<Self>
+    where
+        O: Send + Sync + 'static,
+    {
+        let ptr = NonNull::new(ptr).ok_or_else(|| Error::internal_error("null allocation"))?;
+        if !(ptr.as_ptr() as usize).is_multiple_of(Self::ALIGNMENT) {
+            return Err(Error::InternalError(format!(
+                "allocation at {:p} is not aligned to {} bytes",
+                ptr,
+                Self::ALIGNMENT
+            )));
+        }
+        Ok(Self { ptr, bytes, _owner: Box::new(owner) })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.bytes) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.bytes) }
+    }
+}
+
+impl std::fmt::Debug for CustomAllocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomAllocation")
+            .field("ptr", &self.ptr)
+            .field("bytes", &self.bytes)
+            .finish()
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Uses `allocation` as the data of the input or output `tensor_index` from now on, and
+    /// reallocates tensors. The interpreter owns the allocation until it is dropped or
+    /// replaced, so the memory outlives every use; replacing it, e.g. to swap between two
+    /// frame buffers, drops the previous one. Resizing the tensor beyond the allocation
+    /// makes `allocate_tensors` fail.
+    pub fn set_custom_allocation(
+        &mut self,
+        tensor_index: TensorIndex,
+        allocation: CustomAllocation,
+    ) -> Result<()> {
+        if !self.inputs().contains(&tensor_index) && !self.outputs().contains(&tensor_index) {
+            return Err(Error::InternalError(format!(
+                "tensor {} is neither an input nor an output",
+                tensor_index
+            )));
+        }
+        let inner = self
+            .tensor_inner(tensor_index)
+            .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        if allocation.bytes() < inner.bytes {
+            return Err(Error::InternalError(format!(
+                "allocation of {} bytes is smaller than the {} bytes of tensor {}",
+                allocation.bytes(),
+                inner.bytes,
+                tensor_index
+            )));
+        }
+        self.errors.flush();
+        self.apply_custom_allocation(tensor_index, &allocation)?;
+        self.custom_allocations.retain(|(index, _)| *index != tensor_index);
+        self.custom_allocations.push((tensor_index, allocation));
+        self.allocate_tensors()
+    }
+
+    /// The custom allocation of a tensor, if any.
+    pub fn custom_allocation(&self, tensor_index: TensorIndex) -> Option<&CustomAllocation> {
+        self.custom_allocations
+            .iter()
+            .find(|(index, _)| *index == tensor_index)
+            .map(|(_, allocation)| allocation)
+    }
+
+    pub(crate) fn apply_custom_allocation(
+        &mut self,
+        tensor_index: TensorIndex,
+        allocation: &CustomAllocation,
+    ) -> Result<()> {
+        let interpreter = self.handle_mut();
+        let data = allocation.as_ptr();
+        let bytes = allocation.bytes() as size_t;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                tensor_index as "int",
+                data as "void*",
+                bytes as "size_t"
+            ] -> i32 as "int" {
+                return set_custom_allocation(interpreter, tensor_index, data, bytes);
+            })
+        };
+        match r {
+            1 => Ok(()),
+            0 => Err(self.reported_error(format!(
+                "failed to set the custom allocation of tensor {}",
+                tensor_index
+            ))),
+            -2 => Err(Error::InternalError(format!(
+                "tensor {} is not allocated in the arena",
+                tensor_index
+            ))),
+            _ => Err(Error::InternalError(format!(
+                "custom allocations need TensorFlow Lite 2.4 or newer, not {}",
+                TENSORFLOW_VERSION
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_custom_allocation() {
+        let mut allocation = CustomAllocation::new(28 * 28);
+        assert_eq!(allocation.as_ptr() as usize % CustomAllocation::ALIGNMENT, 0);
+        assert!(allocation.as_slice().iter().all(|&b| b == 0));
+        allocation.as_mut_slice()[0] = 255;
+
+        let mut owner = vec![0u8; 2 * CustomAllocation::ALIGNMENT];
+        let offset = owner.as_ptr().align_offset(CustomAllocation::ALIGNMENT) + 1;
+        let misaligned = unsafe { owner.as_mut_ptr().add(offset) };
+        assert!(unsafe { CustomAllocation::from_raw(misaligned, 8, owner) }.is_err());
+
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let mut interpreter =
+            InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap().build().unwrap();
+        let (input, output) = (interpreter.inputs()[0], interpreter.outputs()[0]);
+        assert!(interpreter.set_custom_allocation(input, CustomAllocation::new(10)).is_err());
+        let other = (0..).find(|i| ![input, output].contains(i)).unwrap();
+        assert!(interpreter.set_custom_allocation(other, CustomAllocation::new(1 << 20)).is_err());
+
+        let version: Vec<u32> =
+            TENSORFLOW_VERSION.split('.').map(|n| n.parse().unwrap_or(0)).take(2).collect();
+        if version < vec![2, 4] {
+            let err = interpreter.set_custom_allocation(input, allocation).unwrap_err();
+            assert!(err.to_string().contains("need TensorFlow Lite 2.4"), "{}", err);
+            return;
+        }
+
+        let ptr = allocation.as_ptr();
+        interpreter.set_custom_allocation(input, allocation).unwrap();
+        interpreter.set_custom_allocation(output, CustomAllocation::new(10)).unwrap();
+        assert_eq!(interpreter.tensor_buffer(input).unwrap().as_ptr(), ptr as *const u8);
+        assert_eq!(interpreter.tensor_data::<u8>(input).unwrap()[0], 255);
+        interpreter.invoke().unwrap();
+        let scores = interpreter.custom_allocation(output).unwrap().as_slice();
+        assert_eq!(scores, interpreter.tensor_data::<u8>(output).unwrap());
+
+        // Double buffering: the next frame replaces the custom allocation of the input.
+        let mut next = CustomAllocation::new(28 * 28);
+        next.as_mut_slice()[0] = 7;
+        let next_ptr = next.as_ptr();
+        interpreter.set_custom_allocation(input, next).unwrap();
+        assert_eq!(interpreter.tensor_buffer(input).unwrap().as_ptr(), next_ptr as *const u8);
+        assert_eq!(interpreter.custom_allocation(input).unwrap().as_ptr(), next_ptr);
+        assert_eq!(interpreter.tensor_data::<u8>(input).unwrap()[0], 7);
+        interpreter.invoke().unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+//! Delegates shared between interpreters.
+//!
+//! A `Delegate` is reference counted and carries a lock. Each interpreter it is applied to
+//! keeps a reference and holds the lock while invoking, so one accelerator context (e.g. an
+//! Edge TPU) can back interpreters on several threads without concurrent device access:
+//!
+//! ```ignore
+//! let delegate = unsafe { Delegate::from_raw(edgetpu_delegate, Some(free_edgetpu_delegate)) };
+//! std::thread::scope(|s| {
+//!     for model in &models {
+//!         let delegate = delegate.clone();
+//!         s.spawn(move || -> Result<()> {
+//!             let mut interpreter = InterpreterBuilder::new(model, BuiltinOpResolver::default())?.build()?;
+//!             interpreter.modify_graph_with_delegate(&delegate)?;
+//!             interpreter.invoke()
+//!         });
+//!     }
+//! });
+//! ```
+//!
+//! Invocations of different interpreters sharing a delegate are serialized; CPU-only work
+//! such as filling inputs still runs in parallel.
+
+use std::fmt;
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::bindings::TfLiteDelegate;
+use crate::leak_tracking::{self, NativeObject};
+#[cfg(unix)]
+use crate::{Error, Result};
+
+/// Frees a delegate created by a vendor library, e.g. `TfLiteGpuDelegateV2Delete`.
+pub type DelegateDeleter = unsafe extern "C" fn(*mut TfLiteDelegate);
+
+struct Inner {
+    handle: *mut TfLiteDelegate,
+    deleter: Option<DelegateDeleter>,
+    lock: Mutex<()>,
+}
+
+// The handle is only used by interpreters while `lock` is held.
+unsafe impl Send for Inner {}
+unsafe impl Sync for Inner {}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(deleter) = self.deleter {
+            unsafe { deleter(self.handle) };
+        }
+        leak_tracking::destroyed(NativeObject::Delegate);
+    }
+}
+
+/// A TFLite delegate that can be applied to any number of interpreters on any threads.
+/// It is freed once the last clone and the last interpreter using it are dropped.
+#[derive(Clone)]
+pub struct Delegate(Arc<Inner>);
+
+impl Delegate {
+    /// Takes ownership of `handle`, freeing it with `deleter` when no longer used.
+    ///
+    /// # Safety
+    /// `handle` must point to a valid delegate which stays valid until `deleter` is called,
+    /// or for the rest of the program if `deleter` is `None`.
+    pub unsafe fn from_raw(handle: *mut TfLiteDelegate, deleter: Option<DelegateDeleter>) -> Self {
+        leak_tracking::created(NativeObject::Delegate);
+        Delegate(Arc::new(Inner { handle, deleter, lock: Mutex::new(()) }))
+    }
+
+    /// Loads a delegate from a shared library implementing the TFLite external delegate
+    /// interface (`tflite_plugin_create_delegate`/`tflite_plugin_destroy_delegate`), such as
+    /// `libedgetpu.so`. `options` are passed to the library as key/value pairs.
+    ///
+    /// The library stays loaded for the rest of the program.
+    #[cfg(unix)]
+    pub fn load_external<P: AsRef<Path>>(library: P, options: &[(&str, &str)]) -> Result<Self> {
+        use std::ffi::CStr;
+        use std::os::raw::c_char;
+
+        type Create = unsafe extern "C" fn(
+            *const *const c_char,
+            *const *const c_char,
+            usize,
+            Option<unsafe extern "C" fn(*const c_char)>,
+        ) -> *mut TfLiteDelegate;
+
+        let handle = dl::open(library.as_ref(), "delegate")?;
+        let create = dl::symbol(
+            handle,
+            CStr::from_bytes_with_nul(b"tflite_plugin_create_delegate\0").unwrap(),
+            "delegate",
+        )?;
+        let destroy = dl::symbol(
+            handle,
+            CStr::from_bytes_with_nul(b"tflite_plugin_destroy_delegate\0").unwrap(),
+            "delegate",
+        )?;
+        let (create, destroy) = unsafe {
+            (
+                std::mem::transmute::<*mut libc::c_void, Create>(create),
+                std::mem::transmute::<*mut libc::c_void, DelegateDeleter>(destroy),
+            )
+        };
+
+        let keys =
+            options.iter().map(|(k, _)| dl::c_string(k.as_bytes())).collect::<Result<Vec<_>>>()?;
+        let values =
+            options.iter().map(|(_, v)| dl::c_string(v.as_bytes())).collect::<Result<Vec<_>>>()?;
+        let key_ptrs: Vec<_> = keys.iter().map(|k| k.as_ptr()).collect();
+        let value_ptrs: Vec<_> = values.iter().map(|v| v.as_ptr()).collect();
+        let delegate =
+            unsafe { create(key_ptrs.as_ptr(), value_ptrs.as_ptr(), options.len(), None) };
+        if delegate.is_null() {
+            return Err(Error::internal_error("external delegate library returned no delegate"));
+        }
+        Ok(unsafe { Self::from_raw(delegate, Some(destroy)) })
+    }
+
+    pub fn as_ptr(&self) -> *mut TfLiteDelegate {
+        self.0.handle
+    }
+
+    /// Number of live handles, including those held by interpreters.
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Locks the delegate for exclusive use by the calling interpreter.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ()> {
+        self.0.lock.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl PartialEq for Delegate {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Delegate {}
+
+impl fmt::Debug for Delegate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Delegate").field(&self.0.handle).finish()
+    }
+}
+
+/// Loading delegate libraries at runtime.
+#[cfg(unix)]
+pub(crate) mod dl {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use libc::c_void;
+
+    use crate::{Error, Result};
+
+    pub(crate) fn c_string(s: &[u8]) -> Result<CString> {
+        CString::new(s).map_err(|_| Error::internal_error("unexpected NUL byte"))
+    }
+
+    fn dl_error(what: &str) -> Error {
+        let message = unsafe { libc::dlerror() };
+        let message = if message.is_null() {
+            "unknown error".into()
+        } else {
+            unsafe { CStr::from_ptr(message) }.to_string_lossy()
+        };
+        Error::InternalError(format!("{}: {}", what, message))
+    }
+
+    /// Loads `library`, a `kind` library such as a delegate, for the rest of the program.
+    pub(crate) fn open(library: &Path, kind: &str) -> Result<*mut c_void> {
+        let path = c_string(library.as_os_str().as_bytes())?;
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(dl_error(&format!("failed to load {} library", kind)));
+        }
+        Ok(handle)
+    }
+
+    pub(crate) fn symbol(handle: *mut c_void, name: &CStr, kind: &str) -> Result<*mut c_void> {
+        let symbol = unsafe { libc::dlsym(handle, name.as_ptr()) };
+        if symbol.is_null() {
+            Err(dl_error(&format!("missing {} symbol", kind)))
+        } else {
+            Ok(symbol)
+        }
+    }
+}
+
+/// Locks all `delegates` in address order, so interpreters sharing several delegates
+/// cannot deadlock each other.
+pub(crate) fn lock_all(delegates: &[Delegate]) -> Vec<MutexGuard<'_, ()>> {
+    let mut sorted: Vec<&Delegate> = delegates.iter().collect();
+    sorted.sort_by_key(|delegate| delegate.as_ptr() as usize);
+    sorted.dedup();
+    sorted.into_iter().map(Delegate::lock).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn free_delegate(handle: *mut TfLiteDelegate) {
+        drop(Box::from_raw(handle));
+        FREED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn unittest_shared_delegate() {
+        let handle = Box::into_raw(Box::new(unsafe { std::mem::zeroed::<TfLiteDelegate>() }));
+        let delegate = unsafe { Delegate::from_raw(handle, Some(free_delegate)) };
+
+        let counter = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let delegate = delegate.clone();
+                let counter = &counter;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        let shared = [delegate.clone(), delegate.clone()];
+                        let _guards = lock_all(&shared);
+                        let value = counter.load(Ordering::SeqCst);
+                        thread::yield_now();
+                        counter.store(value + 1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.load(Ordering::SeqCst), 400);
+
+        assert_eq!(delegate.ref_count(), 1);
+        drop(delegate);
+        assert_eq!(FREED.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unittest_load_external_missing_library() {
+        let err = Delegate::load_external("libdoes_not_exist.so", &[("device", "usb")]);
+        assert!(err.unwrap_err().to_string().contains("failed to load delegate library"));
+    }
+}
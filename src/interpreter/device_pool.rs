@@ -0,0 +1,145 @@
+//! Distributing interpreters across several accelerators of the same kind, e.g. the Edge
+//! TPUs attached to one host:
+//!
+//! ```ignore
+//! let pool = DevicePool::load_edgetpu("libedgetpu.so.1", &["usb:0", "usb:1", "pci:0", "pci:1"])?
+//!     .with_policy(SchedulingPolicy::LeastLoaded);
+//! for model in &models {
+//!     let mut interpreter = InterpreterBuilder::new(model, BuiltinOpResolver::default())?.build()?;
+//!     let device = pool.assign(&mut interpreter)?;
+//!     println!("{} runs on device {}", model.name, device);
+//! }
+//! ```
+
+#[cfg(unix)]
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use super::op_resolver::OpResolver;
+use super::{Delegate, Interpreter};
+use crate::{Error, Result};
+
+/// How `DevicePool` picks the device of the next interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SchedulingPolicy {
+    /// Cycle through the devices in order.
+    RoundRobin,
+    /// Pick the device used by the fewest live interpreters, the first one on ties.
+    LeastLoaded,
+}
+
+/// A set of delegates, one per device, handed out to interpreters by a `SchedulingPolicy`.
+pub struct DevicePool {
+    devices: Vec<Delegate>,
+    policy: SchedulingPolicy,
+    next: AtomicUsize,
+    // Serializes least-loaded picks, so concurrent ones see each other's assignments.
+    select_lock: Mutex<()>,
+}
+
+impl DevicePool {
+    pub fn new(devices: Vec<Delegate>) -> Result<Self> {
+        if devices.is_empty() {
+            return Err(Error::internal_error("device pool requires at least one device"));
+        }
+        Ok(Self {
+            devices,
+            policy: SchedulingPolicy::RoundRobin,
+            next: AtomicUsize::new(0),
+            select_lock: Mutex::new(()),
+        })
+    }
+
+    /// Loads one Edge TPU delegate per entry of `devices` (e.g. `"usb:0"`, `"pci:1"`) from
+    /// the external delegate `library`.
+    #[cfg(unix)]
+    pub fn load_edgetpu<P: AsRef<Path>>(library: P, devices: &[&str]) -> Result<Self> {
+        let devices = devices
+            .iter()
+            .map(|device| Delegate::load_external(library.as_ref(), &[("device", device)]))
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(devices)
+    }
+
+    pub fn with_policy(mut self, policy: SchedulingPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn devices(&self) -> &[Delegate] {
+        &self.devices
+    }
+
+    /// Number of interpreters (and other handles outside the pool) using each device.
+    pub fn loads(&self) -> Vec<usize> {
+        self.devices.iter().map(|device| device.ref_count() - 1).collect()
+    }
+
+    /// Picks a device and returns its index and delegate. The device counts as loaded for
+    /// `LeastLoaded` until the returned delegate and all interpreters it is applied to are
+    /// dropped.
+    pub fn select(&self) -> (usize, Delegate) {
+        match self.policy {
+            SchedulingPolicy::RoundRobin => {
+                let index = self.next.fetch_add(1, Ordering::Relaxed) % self.devices.len();
+                (index, self.devices[index].clone())
+            }
+            SchedulingPolicy::LeastLoaded => {
+                let _guard = self.select_lock.lock().unwrap_or_else(PoisonError::into_inner);
+                let (index, device) = self
+                    .devices
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, device)| device.ref_count())
+                    .unwrap();
+                (index, device.clone())
+            }
+        }
+    }
+
+    /// Applies the delegate of a selected device to `interpreter` and returns the device
+    /// index.
+    pub fn assign<Op: OpResolver>(&self, interpreter: &mut Interpreter<'_, Op>) -> Result<usize> {
+        let (index, device) = self.select();
+        interpreter.modify_graph_with_delegate(&device)?;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::TfLiteDelegate;
+
+    unsafe extern "C" fn free_delegate(handle: *mut TfLiteDelegate) {
+        drop(Box::from_raw(handle));
+    }
+
+    fn delegate() -> Delegate {
+        let handle = Box::into_raw(Box::new(unsafe { std::mem::zeroed::<TfLiteDelegate>() }));
+        unsafe { Delegate::from_raw(handle, Some(free_delegate)) }
+    }
+
+    #[test]
+    fn unittest_device_pool() {
+        assert!(DevicePool::new(Vec::new()).is_err());
+
+        let pool = DevicePool::new(vec![delegate(), delegate(), delegate()]).unwrap();
+        let picks: Vec<usize> = (0..4).map(|_| pool.select().0).collect();
+        assert_eq!(picks, vec![0, 1, 2, 0]);
+
+        let pool = pool.with_policy(SchedulingPolicy::LeastLoaded);
+        let (first, a) = pool.select();
+        let (second, b) = pool.select();
+        let (third, c) = pool.select();
+        assert_eq!((first, second, third), (0, 1, 2));
+        assert_eq!(pool.loads(), vec![1, 1, 1]);
+        drop(b);
+        let (index, d) = pool.select();
+        assert_eq!(index, 1);
+        assert_eq!(pool.select().0, 0);
+        drop((a, c, d));
+        assert_eq!(pool.loads(), vec![0, 0, 0]);
+    }
+}
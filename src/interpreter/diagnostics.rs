@@ -0,0 +1,204 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::{c_char, c_void};
+
+cpp! {{
+    #include <cstdarg>
+    #include <cstdio>
+    #include <mutex>
+    #include <string>
+    #include <vector>
+
+    #include "tensorflow/lite/core/api/error_reporter.h"
+
+    // Keeps every reported message so failures can be surfaced as typed errors.
+    struct CollectingErrorReporter : public tflite::ErrorReporter {
+        std::mutex lock;
+        std::vector<std::string> messages;
+
+        int Report(const char* format, va_list args) override {
+            char buffer[1024];
+            int n = vsnprintf(buffer, sizeof(buffer), format, args);
+            std::string message(buffer);
+            while (!message.empty() && message.back() == '\n') {
+                message.pop_back();
+            }
+            std::lock_guard<std::mutex> guard(lock);
+            messages.push_back(message);
+            return n;
+        }
+    };
+}}
+
+/// Category of a message reported while building or allocating an interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// An operator has no registration in the op resolver.
+    UnresolvedOp,
+    /// An operator refers to an opcode index outside the model's operator codes.
+    InvalidOpcodeIndex,
+    /// A tensor is malformed or referenced out of range.
+    InvalidTensor,
+    /// Tensor memory could not be allocated or planned.
+    Allocation,
+    Other,
+}
+
+impl DiagnosticKind {
+    fn classify(message: &str) -> Self {
+        let message = message.to_lowercase();
+        if message.contains("didn't find op") || message.contains("didn't find custom op") {
+            DiagnosticKind::UnresolvedOp
+        } else if message.contains("opcode_index") || message.contains("opcode index") {
+            DiagnosticKind::InvalidOpcodeIndex
+        } else if message.contains("tensor") && message.contains("invalid")
+            || message.contains("invalidly specified")
+        {
+            DiagnosticKind::InvalidTensor
+        } else if message.contains("alloc") || message.contains("arena") {
+            DiagnosticKind::Allocation
+        } else {
+            DiagnosticKind::Other
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(message: S) -> Self {
+        let message = message.into();
+        Self { kind: DiagnosticKind::classify(&message), message }
+    }
+}
+
+/// Messages TFLite reported for a failed interpreter build.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildDiagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl BuildDiagnostics {
+    pub fn of_kind(&self, kind: DiagnosticKind) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(move |d| d.kind == kind)
+    }
+}
+
+impl fmt::Display for BuildDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.diagnostics.is_empty() {
+            return write!(f, "no diagnostics reported");
+        }
+        let messages: Vec<&str> = self.diagnostics.iter().map(|d| d.message.as_str()).collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+/// A native error reporter collecting messages.
+pub(crate) struct ErrorCollector {
+    handle: *mut c_void,
+}
+
+unsafe impl Send for ErrorCollector {}
+unsafe impl Sync for ErrorCollector {}
+
+impl ErrorCollector {
+    pub(crate) fn new() -> Self {
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([] -> *mut c_void as "void*" {
+                return new CollectingErrorReporter();
+            })
+        };
+        Self { handle }
+    }
+
+    /// Pointer to the native `CollectingErrorReporter`.
+    pub(crate) fn as_ptr(&self) -> *mut c_void {
+        self.handle
+    }
+
+    /// Removes and returns the collected messages.
+    pub(crate) fn take(&self) -> BuildDiagnostics {
+        let handle = self.handle;
+        let mut diagnostics = Vec::new();
+        let diagnostics_ptr = &mut diagnostics;
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "CollectingErrorReporter*", diagnostics_ptr as "void*"] {
+                std::lock_guard<std::mutex> guard(handle->lock);
+                for (const auto& message : handle->messages) {
+                    const char* ptr = message.c_str();
+                    rust!(ErrorCollector_take [
+                        diagnostics_ptr: &mut Vec<Diagnostic> as "void*",
+                        ptr: *const c_char as "const char*"
+                    ] {
+                        crate::panic_guard::abort_on_panic("ErrorCollector_take", || {
+                            let message = unsafe { CStr::from_ptr(ptr) }.to_string_lossy();
+                            diagnostics_ptr.push(Diagnostic::new(message));
+                        });
+                    });
+                }
+                handle->messages.clear();
+            });
+        }
+        BuildDiagnostics { diagnostics }
+    }
+
+    /// Drops the collected messages, logging them as warnings with the `log` feature.
+    pub(crate) fn flush(&self) {
+        let diagnostics = self.take();
+        #[cfg(feature = "log")]
+        for diagnostic in diagnostics.diagnostics {
+            log::warn!(target: "tflite", "{}", diagnostic.message);
+        }
+        #[cfg(not(feature = "log"))]
+        drop(diagnostics);
+    }
+}
+
+impl Drop for ErrorCollector {
+    fn drop(&mut self) {
+        let handle = self.handle;
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "CollectingErrorReporter*"] {
+                delete handle;
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_classify_diagnostics() {
+        let diagnostics = BuildDiagnostics {
+            diagnostics: vec![
+                Diagnostic::new("Didn't find op for builtin opcode 'CONV_2D' version '5'"),
+                Diagnostic::new("Registration failed."),
+                Diagnostic::new("Missing registration for opcode_index 7"),
+                Diagnostic::new("Tensor 3 is invalidly specified in schema."),
+                Diagnostic::new("Failed to allocate memory for tensor 4"),
+            ],
+        };
+        let kinds: Vec<_> = diagnostics.diagnostics.iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::UnresolvedOp,
+                DiagnosticKind::Other,
+                DiagnosticKind::InvalidOpcodeIndex,
+                DiagnosticKind::InvalidTensor,
+                DiagnosticKind::Allocation,
+            ]
+        );
+        assert_eq!(diagnostics.of_kind(DiagnosticKind::UnresolvedOp).count(), 1);
+    }
+}
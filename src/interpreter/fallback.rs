@@ -0,0 +1,304 @@
+//! Running a model on an accelerated interpreter, retrying on a CPU interpreter of the same
+//! model when an invocation fails, e.g. with flaky NNAPI drivers:
+//!
+//! ```ignore
+//! let mut runner = Fallback::new(accelerated, cpu)?.with_max_consecutive_failures(3);
+//! for frame in frames {
+//!     fill(runner.inputs_mut(), frame)?;
+//!     runner.invoke()?;
+//!     use_result(runner.last())?;
+//! }
+//! print!("{}", runner.stats());
+//! ```
+//!
+//! Inputs are always filled on the accelerated interpreter and copied to the CPU interpreter
+//! when it runs; outputs are read from `last`, the interpreter that completed the invocation.
+
+use std::fmt;
+
+use super::op_resolver::OpResolver;
+use super::Interpreter;
+use crate::{Error, Result};
+
+/// Fallback statistics accumulated by a `Fallback`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FallbackStats {
+    pub invocations: u64,
+    /// Invocations the accelerated interpreter completed.
+    pub accelerated: u64,
+    /// Invocations the accelerated interpreter failed, retried on the CPU.
+    pub fallbacks: u64,
+    /// Invocations run on the CPU only, while the accelerator was disabled.
+    pub cpu_only: u64,
+    /// Invocations failed on the CPU too.
+    pub failures: u64,
+    /// The last error of the accelerated interpreter.
+    pub last_accelerator_error: Option<String>,
+}
+
+impl fmt::Display for FallbackStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} invocations, {} accelerated, {} fallbacks, {} CPU only, {} failures",
+            self.invocations, self.accelerated, self.fallbacks, self.cpu_only, self.failures
+        )?;
+        if let Some(error) = &self.last_accelerator_error {
+            writeln!(f, "  last accelerator error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Disables the accelerator after a number of consecutive failures.
+#[derive(Clone, Copy, Debug, Default)]
+struct Breaker {
+    limit: Option<u32>,
+    consecutive: u32,
+}
+
+impl Breaker {
+    fn is_open(&self) -> bool {
+        self.limit.is_some_and(|limit| self.consecutive >= limit)
+    }
+
+    fn record(&mut self, ok: bool) {
+        self.consecutive = if ok { 0 } else { self.consecutive.saturating_add(1) };
+    }
+}
+
+/// Which interpreter completed the last invocation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Executor {
+    Accelerated,
+    Cpu,
+}
+
+/// An accelerated interpreter backed by a CPU interpreter of the same model.
+pub struct Fallback<'a, Op>
+where
+    Op: OpResolver,
+{
+    accelerated: Interpreter<'a, Op>,
+    cpu: Interpreter<'a, Op>,
+    breaker: Breaker,
+    last: Executor,
+    stats: FallbackStats,
+}
+
+impl<'a, Op> Fallback<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Both interpreters must have allocated tensors, the same inputs (element kinds and
+    /// dims) and as many outputs.
+    pub fn new(accelerated: Interpreter<'a, Op>, cpu: Interpreter<'a, Op>) -> Result<Self> {
+        cpu.check_same_inputs(&accelerated, "CPU interpreter")?;
+        Ok(Self {
+            accelerated,
+            cpu,
+            breaker: Breaker::default(),
+            last: Executor::Accelerated,
+            stats: FallbackStats::default(),
+        })
+    }
+
+    /// Stops trying the accelerator after `failures` consecutive failed invocations, until
+    /// `enable_accelerator`.
+    pub fn with_max_consecutive_failures(mut self, failures: u32) -> Self {
+        self.breaker.limit = Some(failures.max(1));
+        self
+    }
+
+    pub fn accelerated(&self) -> &Interpreter<'a, Op> {
+        &self.accelerated
+    }
+
+    /// The accelerated interpreter, whose inputs are to be filled before `invoke`.
+    pub fn inputs_mut(&mut self) -> &mut Interpreter<'a, Op> {
+        &mut self.accelerated
+    }
+
+    pub fn cpu(&self) -> &Interpreter<'a, Op> {
+        &self.cpu
+    }
+
+    /// The interpreter that completed the last invocation, to read outputs from.
+    pub fn last(&self) -> &Interpreter<'a, Op> {
+        match self.last {
+            Executor::Accelerated => &self.accelerated,
+            Executor::Cpu => &self.cpu,
+        }
+    }
+
+    pub fn last_executor(&self) -> Executor {
+        self.last
+    }
+
+    pub fn is_accelerator_disabled(&self) -> bool {
+        self.breaker.is_open()
+    }
+
+    /// Tries the accelerator again, e.g. after the driver was updated.
+    pub fn enable_accelerator(&mut self) {
+        self.breaker.consecutive = 0;
+    }
+
+    pub fn stats(&self) -> &FallbackStats {
+        &self.stats
+    }
+
+    /// Returns the statistics so far and starts over.
+    pub fn take_stats(&mut self) -> FallbackStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    pub fn into_parts(self) -> (Interpreter<'a, Op>, Interpreter<'a, Op>) {
+        (self.accelerated, self.cpu)
+    }
+
+    /// Invokes the accelerated interpreter, and the CPU interpreter on the same inputs if it
+    /// fails. Cancellations and timeouts are returned, not retried.
+    pub fn invoke(&mut self) -> Result<()> {
+        self.stats.invocations += 1;
+        if self.breaker.is_open() {
+            self.stats.cpu_only += 1;
+        } else {
+            match self.accelerated.invoke() {
+                Ok(()) => {
+                    self.breaker.record(true);
+                    self.stats.accelerated += 1;
+                    self.last = Executor::Accelerated;
+                    return Ok(());
+                }
+                Err(e @ Error::Cancelled) | Err(e @ Error::Timeout(_)) => return Err(e),
+                Err(e) => {
+                    self.breaker.record(false);
+                    self.stats.fallbacks += 1;
+                    self.stats.last_accelerator_error = Some(e.to_string());
+                }
+            }
+        }
+        let result = self.cpu.copy_inputs_from(&self.accelerated).and_then(|()| self.cpu.invoke());
+        if result.is_err() {
+            self.stats.failures += 1;
+        }
+        self.last = Executor::Cpu;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::TfLiteDelegate;
+    use crate::interpreter::Delegate;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    cpp! {{
+        #include "tensorflow/lite/c/common.h"
+    }}
+
+    #[allow(clippy::forget_copy, deprecated)]
+    unsafe extern "C" fn free_failing_delegate(delegate: *mut TfLiteDelegate) {
+        cpp!([delegate as "TfLiteDelegate*"] {
+            delete delegate;
+        });
+    }
+
+    /// A delegate taking over every node with a kernel that fails to invoke.
+    fn failing_delegate() -> Delegate {
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([] -> *mut TfLiteDelegate as "TfLiteDelegate*" {
+                static TfLiteRegistration failing = [] {
+                    TfLiteRegistration registration = {};
+                    registration.invoke = [](TfLiteContext*, TfLiteNode*) { return kTfLiteError; };
+                    registration.custom_name = "FailingDelegate";
+                    return registration;
+                }();
+                auto delegate = new TfLiteDelegate{};
+                delegate->flags = kTfLiteDelegateFlagsNone;
+                delegate->Prepare = [](TfLiteContext* context, TfLiteDelegate* delegate) {
+                    TfLiteIntArray* plan = nullptr;
+                    TF_LITE_ENSURE_STATUS(context->GetExecutionPlan(context, &plan));
+                    return context->ReplaceNodeSubsetsWithDelegateKernels(
+                        context, failing, plan, delegate);
+                };
+                return delegate;
+            })
+        };
+        unsafe { Delegate::from_raw(handle, Some(free_failing_delegate)) }
+    }
+
+    #[test]
+    fn unittest_fallback_invoke() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let interpreter = || {
+            let builder = InterpreterBuilder::new(&model, BuiltinOpResolver::default()).unwrap();
+            builder.build().unwrap()
+        };
+        let delegate = failing_delegate();
+        let mut accelerated = interpreter();
+        accelerated.modify_graph_with_delegate(&delegate).unwrap();
+        accelerated.allocate_tensors().unwrap();
+        assert!(accelerated.invoke().is_err());
+        let mut cpu = interpreter();
+        cpu.allocate_tensors().unwrap();
+        let mut reference = interpreter();
+        reference.allocate_tensors().unwrap();
+
+        let input: Vec<u8> = (0..28 * 28).map(|i| (i % 251) as u8).collect();
+        reference.tensor_data_mut::<u8>(reference.inputs()[0]).unwrap().copy_from_slice(&input);
+        reference.invoke().unwrap();
+        let expected = reference.tensor_data::<u8>(reference.outputs()[0]).unwrap().to_vec();
+
+        let mut runner = Fallback::new(accelerated, cpu).unwrap().with_max_consecutive_failures(2);
+        let index = runner.accelerated().inputs()[0];
+        runner.inputs_mut().tensor_data_mut::<u8>(index).unwrap().copy_from_slice(&input);
+        for _ in 0..3 {
+            runner.invoke().unwrap();
+            assert_eq!(runner.last_executor(), Executor::Cpu);
+            let output = runner.last().outputs()[0];
+            assert_eq!(runner.last().tensor_data::<u8>(output).unwrap(), expected.as_slice());
+        }
+        // Two failures open the breaker, so the third invocation skips the accelerator.
+        assert!(runner.is_accelerator_disabled());
+        let stats = runner.take_stats();
+        assert_eq!((stats.invocations, stats.accelerated), (3, 0));
+        assert_eq!((stats.fallbacks, stats.cpu_only, stats.failures), (2, 1, 0));
+        assert!(stats.last_accelerator_error.is_some());
+
+        runner.enable_accelerator();
+        assert!(!runner.is_accelerator_disabled());
+        runner.invoke().unwrap();
+        assert_eq!(runner.stats().fallbacks, 1);
+    }
+
+    #[test]
+    fn unittest_fallback_stats() {
+        let mut breaker = Breaker { limit: Some(2), consecutive: 0 };
+        breaker.record(false);
+        assert!(!breaker.is_open());
+        breaker.record(true);
+        breaker.record(false);
+        breaker.record(false);
+        assert!(breaker.is_open());
+        assert!(!Breaker { limit: None, consecutive: 100 }.is_open());
+
+        let stats = FallbackStats {
+            invocations: 5,
+            accelerated: 3,
+            fallbacks: 1,
+            cpu_only: 1,
+            failures: 0,
+            last_accelerator_error: Some("failed to invoke interpreter".to_string()),
+        };
+        assert_eq!(
+            stats.to_string(),
+            "5 invocations, 3 accelerated, 1 fallbacks, 1 CPU only, 0 failures\n  last \
+             accelerator error: failed to invoke interpreter\n"
+        );
+    }
+}
@@ -1,9 +1,10 @@
+use std::mem;
 use std::path::Path;
-use std::{fs, mem};
 
 use crate::bindings::tflite as bindings;
+use crate::leak_tracking::{self, NativeObject};
 use crate::model::Model;
-use crate::{Error, Result};
+use crate::{Allocation, AllocationKind, Error, ModelSource, Result};
 
 cpp! {{
     #include "tensorflow/lite/model.h"
@@ -15,7 +16,7 @@ cpp! {{
 #[derive(Default)]
 pub struct FlatBufferModel {
     pub(crate) handle: Box<bindings::FlatBufferModel>,
-    model_buffer: Vec<u8>,
+    allocation: Allocation,
 }
 
 impl Drop for FlatBufferModel {
@@ -28,17 +29,48 @@ impl Drop for FlatBufferModel {
                 delete handle;
             });
         }
+        leak_tracking::destroyed(NativeObject::FlatBufferModel);
     }
 }
 
 impl FlatBufferModel {
     pub fn build_from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        Self::build_from_buffer(fs::read(path)?)
+        Self::build(path.as_ref())
     }
 
     pub fn build_from_buffer(model_buffer: Vec<u8>) -> Result<Self> {
-        let ptr = model_buffer.as_ptr();
-        let size = model_buffer.len();
+        Self::build(model_buffer)
+    }
+
+    /// Builds a model from bytes that live as long as the program, e.g.
+    /// `include_bytes!("model.tflite")`, without copying them.
+    pub fn build_from_static(model_buffer: &'static [u8]) -> Result<Self> {
+        Self::build(model_buffer)
+    }
+
+    /// Builds a model from a copy of `model_buffer`, which can be dropped afterwards.
+    pub fn build_from_slice(model_buffer: &[u8]) -> Result<Self> {
+        Self::build(model_buffer.to_vec())
+    }
+
+    /// Builds a model from any source. `Static`, `Mmap` and `Shared` sources are used in
+    /// place, and the model keeps them alive.
+    ///
+    /// On big-endian targets, constant tensor data is converted to native byte order first,
+    /// so `buffer` returns the converted bytes and shared allocations are copied.
+    pub fn build<S: Into<ModelSource>>(source: S) -> Result<Self> {
+        let allocation = Allocation::new(source)?;
+
+        #[cfg(target_endian = "big")]
+        let allocation = {
+            let mut model = Model::from_source(ModelSource::Shared(allocation))?;
+            model.byte_swap_buffers(true);
+            Allocation::new(model.to_buffer())?
+        };
+
+        let bytes = allocation.as_slice();
+        let ptr = bytes.as_ptr();
+        let size = bytes.len();
 
         #[allow(clippy::forget_copy, deprecated)]
         let handle = unsafe {
@@ -51,18 +83,59 @@ impl FlatBufferModel {
             return Err(Error::internal_error("failed to build model"));
         }
         let handle = unsafe { Box::from_raw(handle) };
-        Ok(Self { handle, model_buffer })
+        leak_tracking::created(NativeObject::FlatBufferModel);
+        Ok(Self { handle, allocation })
     }
 
     pub fn build_from_model(model: &Model) -> Result<Self> {
         FlatBufferModel::build_from_buffer(model.to_buffer())
     }
 
+    /// A model reading from a heap copy of the bytes of a memory-mapped model, so that its
+    /// read-only weights are served from RAM instead of being paged in from the file, e.g.
+    /// on slow flash. Other models share their allocation with the returned one.
+    pub fn copy_to_heap(&self) -> Result<Self> {
+        match self.allocation.kind() {
+            AllocationKind::Mmap => Self::build(self.buffer().to_vec()),
+            _ => Self::build(self.allocation.clone()),
+        }
+    }
+
     pub fn buffer(&self) -> &[u8] {
-        &self.model_buffer
+        self.allocation.as_slice()
+    }
+
+    /// The memory backing this model. Build other models from a clone to share it.
+    pub fn allocation(&self) -> &Allocation {
+        &self.allocation
+    }
+
+    /// The bytes TFLite reads the model from, for checking that they are `allocation`'s.
+    pub fn native_allocation(&self) -> &[u8] {
+        let handle = &*self.handle;
+        let mut size = 0usize;
+        let size_ptr = &mut size;
+        #[allow(clippy::forget_copy, deprecated)]
+        let base = unsafe {
+            cpp!([handle as "const FlatBufferModel*", size_ptr as "size_t*"] -> *const u8 as "const void*" {
+                const Allocation* allocation = handle->allocation();
+                if (allocation == nullptr) {
+                    *size_ptr = 0;
+                    return nullptr;
+                }
+                *size_ptr = allocation->bytes();
+                return allocation->base();
+            })
+        };
+        if base.is_null() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(base, size) }
     }
 
+    /// Returns the model bytes, copying them if the model was built from a static,
+    /// memory-mapped or still shared buffer.
     pub fn release_buffer(mut self) -> Vec<u8> {
-        mem::replace(&mut self.model_buffer, Vec::new())
+        ModelSource::Shared(mem::take(&mut self.allocation)).into_bytes().unwrap_or_default()
     }
 }
@@ -0,0 +1,368 @@
+//! Options of the TFLite GPU delegate (`TfLiteGpuDelegateV2Create`).
+//!
+//! By default the delegate computes in fp32 like the CPU kernels. `GpuPrecision::Fp16` lets
+//! it store and compute activations in half precision, which is about twice as fast on most
+//! mobile GPUs, at the cost of accuracy:
+//!
+//! - values keep 11 significant bits, about 3 decimal digits, so outputs typically differ
+//!   from the CPU ones by a relative 1e-3, more after long chains of accumulations;
+//! - magnitudes above 65504 overflow to infinity, e.g. in unnormalized activations or large
+//!   logits before a softmax.
+//!
+//! Classification models usually keep their top results; regression and detection outputs
+//! should be checked, e.g. with `tflite-compare`, before opting in.
+//!
+//! ```ignore
+//! let options = GpuDelegateOptions::default().with_precision(GpuPrecision::Fp16);
+//! let delegate = Delegate::load_gpu("libtensorflowlite_gpu_delegate.so", &options)?;
+//! interpreter.modify_graph_with_delegate(&delegate)?;
+//! ```
+//!
+//! With the `gpu` feature the delegate is linked instead, as `GpuDelegate::new(&options)`.
+//! Compiling its GPU programs can take seconds on first use; `with_serialization` caches them
+//! on disk for later startups.
+
+use std::ffi::CString;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(any(unix, feature = "gpu"))]
+use std::ptr;
+
+#[cfg(any(unix, feature = "gpu"))]
+use super::Delegate;
+#[cfg(any(unix, feature = "gpu"))]
+use crate::bindings::TfLiteDelegate;
+#[cfg(any(unix, feature = "gpu"))]
+use crate::TENSORFLOW_VERSION;
+use crate::{Error, Result};
+
+cpp! {{
+    #include "tensorflow/core/public/version.h"
+    #include "tensorflow/lite/delegates/gpu/delegate.h"
+
+    #if TF_MAJOR_VERSION > 2 || (TF_MAJOR_VERSION == 2 && TF_MINOR_VERSION >= 5)
+    #define TFLITE_RS_GPU_SERIALIZATION 1
+    #endif
+
+    // Creates a delegate with `create` from the options returned by `defaults`, both from the
+    // delegate library, so that the options have the layout of the headers built against.
+    static TfLiteDelegate* create_gpu_delegate(
+        TfLiteGpuDelegateOptionsV2 (*defaults)(),
+        TfLiteDelegate* (*create)(const TfLiteGpuDelegateOptionsV2*),
+        int32_t is_precision_loss_allowed,
+        int32_t inference_preference,
+        int32_t inference_priority1,
+        int64_t experimental_flags,
+        int32_t max_delegated_partitions,
+        const char* serialization_dir,
+        const char* model_token
+    ) {
+        TfLiteGpuDelegateOptionsV2 options = defaults();
+        options.is_precision_loss_allowed = is_precision_loss_allowed;
+        options.inference_preference = inference_preference;
+        options.inference_priority1 = static_cast<TfLiteGpuInferencePriority>(inference_priority1);
+        options.inference_priority2 = TFLITE_GPU_INFERENCE_PRIORITY_AUTO;
+        options.inference_priority3 = TFLITE_GPU_INFERENCE_PRIORITY_AUTO;
+        options.experimental_flags = experimental_flags;
+        options.max_delegated_partitions = max_delegated_partitions;
+    #ifdef TFLITE_RS_GPU_SERIALIZATION
+        options.serialization_dir = serialization_dir;
+        options.model_token = model_token;
+    #endif
+        return create(&options);
+    }
+}}
+
+/// Numeric precision of the GPU delegate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpuPrecision {
+    /// fp32, matching the CPU kernels.
+    Full,
+    /// fp16 storage and arithmetic where the GPU supports it. See the module documentation
+    /// for the accuracy implications.
+    Fp16,
+}
+
+/// What the GPU delegate optimizes its setup for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpuInferencePreference {
+    /// The interpreter is invoked once or a few times; favors a fast initialization.
+    FastSingleAnswer,
+    /// The interpreter is invoked repeatedly, e.g. on camera frames; favors throughput.
+    SustainedSpeed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpuDelegateOptions {
+    pub precision: GpuPrecision,
+    pub inference_preference: GpuInferencePreference,
+    /// Run quantized models by dequantizing their weights.
+    pub quantized_models: bool,
+    /// Maximum number of partitions delegated to the GPU; others run on the CPU.
+    pub max_delegated_partitions: i32,
+    /// Directory caching compiled GPU programs, with the token identifying the model, so
+    /// that later startups skip compiling them. Needs TF Lite 2.5 or later.
+    pub serialization: Option<(PathBuf, String)>,
+}
+
+impl Default for GpuDelegateOptions {
+    fn default() -> Self {
+        Self {
+            precision: GpuPrecision::Full,
+            inference_preference: GpuInferencePreference::FastSingleAnswer,
+            quantized_models: true,
+            max_delegated_partitions: 1,
+            serialization: None,
+        }
+    }
+}
+
+/// The fields of `TfLiteGpuDelegateOptionsV2` set from `GpuDelegateOptions`. The struct
+/// itself is only built in C++, from `TfLiteGpuDelegateOptionsV2Default`, as its layout
+/// depends on the TF Lite release.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct NativeGpuOptions {
+    is_precision_loss_allowed: i32,
+    inference_preference: i32,
+    inference_priority1: i32,
+    experimental_flags: i64,
+    max_delegated_partitions: i32,
+    serialization: Option<(CString, CString)>,
+}
+
+/// `TfLiteGpuDelegateOptionsV2Default`.
+#[cfg(any(unix, feature = "gpu"))]
+type DefaultsFn = *mut libc::c_void;
+/// `TfLiteGpuDelegateV2Create`.
+#[cfg(any(unix, feature = "gpu"))]
+type CreateFn = *mut libc::c_void;
+
+// TfLiteGpuInferencePriority
+const PRIORITY_MAX_PRECISION: i32 = 1;
+const PRIORITY_MIN_LATENCY: i32 = 2;
+// TfLiteGpuExperimentalFlags
+const FLAG_ENABLE_QUANT: i64 = 1;
+
+#[cfg(any(unix, feature = "gpu"))]
+fn serialization_supported() -> bool {
+    #[allow(clippy::forget_copy, deprecated)]
+    unsafe {
+        cpp!([] -> bool as "bool" {
+        #ifdef TFLITE_RS_GPU_SERIALIZATION
+            return true;
+        #else
+            return false;
+        #endif
+        })
+    }
+}
+
+impl GpuDelegateOptions {
+    pub fn with_precision(mut self, precision: GpuPrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    pub fn with_inference_preference(mut self, preference: GpuInferencePreference) -> Self {
+        self.inference_preference = preference;
+        self
+    }
+
+    /// Caches compiled GPU programs in `dir` under `model_token`, which must change whenever
+    /// the model does, e.g. a hash of the model file.
+    pub fn with_serialization<P: Into<PathBuf>>(mut self, dir: P, model_token: &str) -> Self {
+        self.serialization = Some((dir.into(), model_token.to_string()));
+        self
+    }
+
+    pub(crate) fn to_native(&self) -> Result<NativeGpuOptions> {
+        let (is_precision_loss_allowed, inference_priority1) = match self.precision {
+            GpuPrecision::Full => (0, PRIORITY_MAX_PRECISION),
+            GpuPrecision::Fp16 => (1, PRIORITY_MIN_LATENCY),
+        };
+        let serialization = match &self.serialization {
+            Some((dir, token)) => {
+                let string = |value: &str| {
+                    CString::new(value).map_err(|_| {
+                        Error::InternalError(format!("`{}` contains a NUL byte", value))
+                    })
+                };
+                Some((string(&dir.to_string_lossy())?, string(token)?))
+            }
+            None => None,
+        };
+        Ok(NativeGpuOptions {
+            is_precision_loss_allowed,
+            inference_preference: match self.inference_preference {
+                GpuInferencePreference::FastSingleAnswer => 0,
+                GpuInferencePreference::SustainedSpeed => 1,
+            },
+            inference_priority1,
+            experimental_flags: if self.quantized_models { FLAG_ENABLE_QUANT } else { 0 },
+            max_delegated_partitions: self.max_delegated_partitions,
+            serialization,
+        })
+    }
+}
+
+impl NativeGpuOptions {
+    /// Creates a delegate with the `TfLiteGpuDelegateOptionsV2Default` and
+    /// `TfLiteGpuDelegateV2Create` functions of a delegate library.
+    #[cfg(any(unix, feature = "gpu"))]
+    fn create(&self, defaults: DefaultsFn, create: CreateFn) -> Result<*mut TfLiteDelegate> {
+        if self.serialization.is_some() && !serialization_supported() {
+            return Err(Error::InternalError(format!(
+                "GPU program serialization needs TensorFlow Lite 2.5 or newer, not {}",
+                TENSORFLOW_VERSION
+            )));
+        }
+        let NativeGpuOptions {
+            is_precision_loss_allowed,
+            inference_preference,
+            inference_priority1,
+            experimental_flags,
+            max_delegated_partitions,
+            ..
+        } = *self;
+        let (serialization_dir, model_token) = match &self.serialization {
+            Some((dir, token)) => (dir.as_ptr(), token.as_ptr()),
+            None => (ptr::null(), ptr::null()),
+        };
+        #[allow(clippy::forget_copy, deprecated)]
+        let delegate = unsafe {
+            cpp!([
+                defaults as "void*",
+                create as "void*",
+                is_precision_loss_allowed as "int32_t",
+                inference_preference as "int32_t",
+                inference_priority1 as "int32_t",
+                experimental_flags as "int64_t",
+                max_delegated_partitions as "int32_t",
+                serialization_dir as "const char*",
+                model_token as "const char*"
+            ] -> *mut TfLiteDelegate as "TfLiteDelegate*" {
+                return create_gpu_delegate(
+                    reinterpret_cast<TfLiteGpuDelegateOptionsV2 (*)()>(defaults),
+                    reinterpret_cast<TfLiteDelegate* (*)(const TfLiteGpuDelegateOptionsV2*)>(create),
+                    is_precision_loss_allowed,
+                    inference_preference,
+                    inference_priority1,
+                    experimental_flags,
+                    max_delegated_partitions,
+                    serialization_dir,
+                    model_token);
+            })
+        };
+        if delegate.is_null() {
+            return Err(Error::internal_error("failed to create GPU delegate"));
+        }
+        Ok(delegate)
+    }
+}
+
+impl Delegate {
+    /// Creates a GPU delegate with `options` from `library`, a build of TFLite's
+    /// `libtensorflowlite_gpu_delegate.so` from the TensorFlow version of this crate. The
+    /// library stays loaded for the rest of the program.
+    #[cfg(unix)]
+    pub fn load_gpu<P: AsRef<Path>>(library: P, options: &GpuDelegateOptions) -> Result<Self> {
+        use std::ffi::CStr;
+
+        use super::delegate::{dl, DelegateDeleter};
+
+        let handle = dl::open(library.as_ref(), "delegate")?;
+        let symbol =
+            |name: &[u8]| dl::symbol(handle, CStr::from_bytes_with_nul(name).unwrap(), "delegate");
+        let defaults = symbol(b"TfLiteGpuDelegateOptionsV2Default\0")?;
+        let create = symbol(b"TfLiteGpuDelegateV2Create\0")?;
+        let delete = symbol(b"TfLiteGpuDelegateV2Delete\0")?;
+        let delete = unsafe { std::mem::transmute::<*mut libc::c_void, DelegateDeleter>(delete) };
+        let delegate = options.to_native()?.create(defaults, create)?;
+        Ok(unsafe { Self::from_raw(delegate, Some(delete)) })
+    }
+}
+
+/// The GPU delegate linked into the program with the `gpu` feature; apply it with
+/// `Interpreter::modify_graph_with_delegate`. Use `Delegate::load_gpu` to load it at runtime
+/// instead.
+#[cfg(feature = "gpu")]
+#[derive(Clone)]
+pub struct GpuDelegate(Delegate);
+
+#[cfg(feature = "gpu")]
+unsafe extern "C" fn delete_gpu_delegate(handle: *mut TfLiteDelegate) {
+    #[allow(clippy::forget_copy, clippy::useless_transmute, deprecated)]
+    unsafe {
+        cpp!([handle as "TfLiteDelegate*"] {
+            TfLiteGpuDelegateV2Delete(handle);
+        })
+    };
+}
+
+#[cfg(feature = "gpu")]
+impl GpuDelegate {
+    /// Creates the delegate, failing e.g. on devices without OpenCL or OpenGL ES 3.1.
+    pub fn new(options: &GpuDelegateOptions) -> Result<Self> {
+        #[allow(clippy::forget_copy, deprecated)]
+        let (defaults, create) = unsafe {
+            (
+                cpp!([] -> DefaultsFn as "void*" {
+                    return reinterpret_cast<void*>(&TfLiteGpuDelegateOptionsV2Default);
+                }),
+                cpp!([] -> CreateFn as "void*" {
+                    return reinterpret_cast<void*>(&TfLiteGpuDelegateV2Create);
+                }),
+            )
+        };
+        let handle = options.to_native()?.create(defaults, create)?;
+        Ok(GpuDelegate(unsafe { Delegate::from_raw(handle, Some(delete_gpu_delegate)) }))
+    }
+}
+
+#[cfg(feature = "gpu")]
+impl std::ops::Deref for GpuDelegate {
+    type Target = Delegate;
+
+    fn deref(&self) -> &Delegate {
+        &self.0
+    }
+}
+
+#[cfg(feature = "gpu")]
+impl From<GpuDelegate> for Delegate {
+    fn from(delegate: GpuDelegate) -> Self {
+        delegate.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_gpu_options() {
+        let options = GpuDelegateOptions::default();
+        let native = options.to_native().unwrap();
+        assert_eq!((native.is_precision_loss_allowed, native.inference_priority1), (0, 1));
+        assert_eq!(native.experimental_flags, FLAG_ENABLE_QUANT);
+        assert!(native.serialization.is_none());
+
+        let native = options
+            .clone()
+            .with_precision(GpuPrecision::Fp16)
+            .with_inference_preference(GpuInferencePreference::SustainedSpeed)
+            .to_native()
+            .unwrap();
+        assert_eq!(
+            (native.is_precision_loss_allowed, native.inference_priority1),
+            (1, PRIORITY_MIN_LATENCY)
+        );
+        assert_eq!(native.inference_preference, 1);
+
+        let native = options.clone().with_serialization("/cache", "mnist-1").to_native().unwrap();
+        let (_, token) = native.serialization.unwrap();
+        assert_eq!(token.to_str(), Ok("mnist-1"));
+        assert!(options.with_serialization("/cache", "a\0b").to_native().is_err());
+    }
+}
@@ -0,0 +1,443 @@
+//! Observer callbacks around the execution of each node.
+//!
+//! An `OpObserver` installed with `Interpreter::set_op_observer` is called before and after
+//! every node runs, including delegate kernels, with access to the node's tensors:
+//!
+//! ```ignore
+//! struct Timer(Instant);
+//!
+//! impl OpObserver for Timer {
+//!     fn before_op(&mut self, _op: &OpContext<'_>) {
+//!         self.0 = Instant::now();
+//!     }
+//!
+//!     fn after_op(&mut self, op: &OpContext<'_>) {
+//!         println!("{} #{}: {:?}", op.op_name, op.node, self.0.elapsed());
+//!     }
+//! }
+//!
+//! interpreter.set_op_observer(Timer(Instant::now()));
+//! ```
+//!
+//! Hooks are driven by the interpreter's profiler interface and coexist with
+//! `enable_profiling`; time spent in hooks is not included in profiles. They also drive
+//! `Interpreter::enable_numeric_checks`.
+
+use std::any::Any;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::{mem, ptr, slice};
+
+use super::context::{int_array_as_slice, ElemKindOf, TensorInfo};
+use super::numeric::{self, NumericIssue};
+use super::op_resolver::OpResolver;
+use super::profiler::Profiler;
+use super::{Interpreter, TensorIndex};
+use crate::bindings::{TfLiteContext, TfLiteNode, TfLiteTensor};
+
+cpp! {{
+    #include <vector>
+
+    #include "tensorflow/lite/core/api/profiler.h"
+    #include "tensorflow/lite/interpreter.h"
+    #include "tensorflow/lite/profiling/buffered_profiler.h"
+
+    using namespace tflite;
+
+    // Calls `callback` before and after each operator and forwards all events to `inner`,
+    // the profiler of `Interpreter::enable_profiling` if any.
+    class HookProfiler : public Profiler {
+      public:
+        typedef void (*Callback)(void* data, bool after, const char* tag, int node,
+                                 int subgraph, TfLiteContext* context, TfLiteNode* node_ptr);
+
+        HookProfiler(Callback callback, void* data) : callback(callback), data(data) {}
+
+        uint32_t BeginEvent(const char* tag, EventType event_type, int64_t metadata1,
+                            int64_t metadata2) override {
+            Event event = {tag, event_type == EventType::OPERATOR_INVOKE_EVENT,
+                           static_cast<int>(metadata1), static_cast<int>(metadata2), 0};
+            if (event.is_op) {
+                Notify(event, false);
+            }
+            if (inner) {
+                event.inner_handle = inner->BeginEvent(tag, event_type, metadata1, metadata2);
+            }
+            events.push_back(event);
+            return events.size();
+        }
+
+        void EndEvent(uint32_t handle) override {
+            if (handle == 0 || handle > events.size()) {
+                return;
+            }
+            Event event = events[handle - 1];
+            events.resize(handle - 1);
+            if (inner) {
+                inner->EndEvent(event.inner_handle);
+            }
+            if (event.is_op) {
+                Notify(event, true);
+            }
+        }
+
+        Interpreter* interpreter = nullptr;
+        Profiler* inner = nullptr;
+
+      private:
+        struct Event {
+            const char* tag;
+            bool is_op;
+            int node;
+            int subgraph;
+            uint32_t inner_handle;
+        };
+
+        void Notify(const Event& event, bool after) {
+            Subgraph* subgraph = interpreter ? interpreter->subgraph(event.subgraph) : nullptr;
+            if (!subgraph) {
+                return;
+            }
+            const auto* pair = subgraph->node_and_registration(event.node);
+            if (!pair) {
+                return;
+            }
+            callback(data, after, event.tag, event.node, event.subgraph, subgraph->context(),
+                     const_cast<TfLiteNode*>(&pair->first));
+        }
+
+        Callback callback;
+        void* data;
+        std::vector<Event> events;
+    };
+}}
+
+/// A node about to run, or that has just run, as seen by an `OpObserver`.
+///
+/// Before the node runs, its outputs hold stale data or are not allocated yet.
+pub struct OpContext<'a> {
+    /// Node index in its subgraph.
+    pub node: usize,
+    pub subgraph: usize,
+    /// Builtin operator name, custom op name or delegate kernel name.
+    pub op_name: &'a str,
+    context: &'a TfLiteContext,
+    node_data: &'a TfLiteNode,
+}
+
+impl<'a> OpContext<'a> {
+    fn int_array(array: *const crate::bindings::TfLiteIntArray) -> &'a [TensorIndex] {
+        if array.is_null() {
+            &[]
+        } else {
+            unsafe { int_array_as_slice(&*array) }
+        }
+    }
+
+    /// Tensor indices of the node's inputs; optional inputs are `-1`.
+    pub fn inputs(&self) -> &'a [TensorIndex] {
+        Self::int_array(self.node_data.inputs)
+    }
+
+    pub fn outputs(&self) -> &'a [TensorIndex] {
+        Self::int_array(self.node_data.outputs)
+    }
+
+    pub fn tensor(&self, index: TensorIndex) -> Option<&'a TfLiteTensor> {
+        if index < 0 || index as usize >= self.context.tensors_size {
+            return None;
+        }
+        Some(unsafe { &*self.context.tensors.add(index as usize) })
+    }
+
+    pub fn tensor_info(&self, index: TensorIndex) -> Option<TensorInfo> {
+        Some(self.tensor(index)?.into())
+    }
+
+    /// The data of a tensor as bytes, or `None` if it is not allocated.
+    pub fn tensor_buffer(&self, index: TensorIndex) -> Option<&'a [u8]> {
+        let tensor = self.tensor(index)?;
+        let data = unsafe { tensor.data.raw_const };
+        if data.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(data as *const u8, tensor.bytes) })
+    }
+
+    /// The data of a tensor, or `None` if it is not allocated or not of type `T`.
+    pub fn tensor_data<T: ElemKindOf>(&self, index: TensorIndex) -> Option<&'a [T]> {
+        let tensor = self.tensor(index)?;
+        if tensor.type_ != T::elem_kind_of() {
+            return None;
+        }
+        let buffer = self.tensor_buffer(index)?;
+        Some(unsafe {
+            slice::from_raw_parts(buffer.as_ptr() as *const T, buffer.len() / mem::size_of::<T>())
+        })
+    }
+}
+
+/// Callbacks invoked around each node executed by `Interpreter::invoke`.
+pub trait OpObserver: Send {
+    fn before_op(&mut self, _op: &OpContext<'_>) {}
+
+    fn after_op(&mut self, _op: &OpContext<'_>) {}
+}
+
+struct HookState {
+    observer: Option<Box<dyn OpObserver>>,
+    check_numerics: bool,
+    // The first non-finite output of the current invocation.
+    issue: Option<NumericIssue>,
+    // A panic raised by the observer, resumed once control is back in Rust.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+// The observer is only reachable through `&mut Interpreter`.
+unsafe impl Sync for HookState {}
+
+/// A native `HookProfiler`, which refers to the state of its `OpHooks`.
+struct NativeHooks(*mut c_void);
+
+// The native hooks are only touched through the interpreter owning them.
+unsafe impl Send for NativeHooks {}
+unsafe impl Sync for NativeHooks {}
+
+impl Drop for NativeHooks {
+    fn drop(&mut self) {
+        let handle = self.0;
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "HookProfiler*"] {
+                delete handle;
+            });
+        }
+    }
+}
+
+/// Dispatches the events of a native `HookProfiler` to an `OpObserver` and the numeric
+/// checks.
+pub(crate) struct OpHooks {
+    // Declared first so it is dropped before the state it refers to.
+    native: NativeHooks,
+    state: Box<HookState>,
+}
+
+impl OpHooks {
+    fn new() -> Self {
+        let mut state =
+            Box::new(HookState { observer: None, check_numerics: false, issue: None, panic: None });
+        let data = &mut *state as *mut HookState as *mut c_void;
+        let callback: extern "C" fn(
+            *mut c_void,
+            bool,
+            *const c_char,
+            i32,
+            i32,
+            *mut TfLiteContext,
+            *mut TfLiteNode,
+        ) = op_hook;
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([callback as "HookProfiler::Callback", data as "void*"] -> *mut c_void as "void*" {
+                return new HookProfiler(callback, data);
+            })
+        };
+        Self { native: NativeHooks(handle), state }
+    }
+
+    fn is_unused(&self) -> bool {
+        self.state.observer.is_none() && !self.state.check_numerics
+    }
+
+    /// The panic raised by the observer during the last invocation, if any.
+    pub(crate) fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.state.panic.take()
+    }
+
+    /// The first non-finite output found during the last invocation, if any.
+    pub(crate) fn take_issue(&mut self) -> Option<NumericIssue> {
+        self.state.issue.take()
+    }
+}
+
+extern "C" fn op_hook(
+    data: *mut c_void,
+    after: bool,
+    tag: *const c_char,
+    node: i32,
+    subgraph: i32,
+    context: *mut TfLiteContext,
+    node_data: *mut TfLiteNode,
+) {
+    let state = unsafe { &mut *(data as *mut HookState) };
+    if state.panic.is_some() {
+        return;
+    }
+    let op_name = if tag.is_null() {
+        Default::default()
+    } else {
+        unsafe { CStr::from_ptr(tag) }.to_string_lossy()
+    };
+    let op = OpContext {
+        node: node as usize,
+        subgraph: subgraph as usize,
+        op_name: &op_name,
+        context: unsafe { &*context },
+        node_data: unsafe { &*node_data },
+    };
+    // A panic is resumed by `invoke` once TF Lite returned.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        if after && state.check_numerics && state.issue.is_none() {
+            state.issue = numeric::check_outputs(&op);
+        }
+        if let Some(observer) = state.observer.as_mut() {
+            if after {
+                observer.after_op(&op);
+            } else {
+                observer.before_op(&op);
+            }
+        }
+    }));
+    if let Err(payload) = result {
+        state.panic = Some(payload);
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn op_hooks_mut(&mut self) -> &mut OpHooks {
+        if self.op_hooks.is_none() {
+            self.op_hooks = Some(OpHooks::new());
+            self.install_profiler();
+        }
+        self.op_hooks.as_mut().unwrap()
+    }
+
+    /// Uninstalls the hooks once neither an observer nor numeric checks use them.
+    fn release_op_hooks(&mut self) {
+        if self.op_hooks.as_ref().is_some_and(OpHooks::is_unused) {
+            let hooks = self.op_hooks.take();
+            self.install_profiler();
+            drop(hooks);
+        }
+    }
+
+    /// Installs `observer`, replacing any previous one. It is called around every node of
+    /// the following invocations. A panic in the observer is resumed when `invoke` returns.
+    pub fn set_op_observer<O>(&mut self, observer: O)
+    where
+        O: OpObserver + 'static,
+    {
+        self.op_hooks_mut().state.observer = Some(Box::new(observer));
+    }
+
+    /// Removes and returns the installed observer.
+    pub fn remove_op_observer(&mut self) -> Option<Box<dyn OpObserver>> {
+        let observer = self.op_hooks.as_mut()?.state.observer.take();
+        self.release_op_hooks();
+        observer
+    }
+
+    pub fn op_observer_mut(&mut self) -> Option<&mut dyn OpObserver> {
+        Some(&mut **self.op_hooks.as_mut()?.state.observer.as_mut()?)
+    }
+
+    /// Scans the float outputs of every node for NaN and infinite values while invoking.
+    /// `invoke` then fails with `Error::NonFinite`, naming the first offending node.
+    ///
+    /// Scanning reads every output once, so leave this off in production.
+    pub fn enable_numeric_checks(&mut self) {
+        self.op_hooks_mut().state.check_numerics = true;
+    }
+
+    pub fn disable_numeric_checks(&mut self) {
+        if let Some(hooks) = self.op_hooks.as_mut() {
+            hooks.state.check_numerics = false;
+        }
+        self.release_op_hooks();
+    }
+
+    /// Installs the hooks and the profiler of `enable_profiling`, whichever are set.
+    pub(crate) fn install_profiler(&mut self) {
+        let interpreter = &mut *self.handle;
+        let buffered = self.profiler.as_ref().map_or(ptr::null_mut(), Profiler::as_ptr);
+        let hooks = self.op_hooks.as_ref().map_or(ptr::null_mut(), |hooks| hooks.native.0);
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                buffered as "profiling::BufferedProfiler*",
+                hooks as "HookProfiler*"
+            ] {
+                if (hooks) {
+                    hooks->interpreter = interpreter;
+                    hooks->inner = buffered;
+                    interpreter->SetProfiler(hooks);
+                } else {
+                    interpreter->SetProfiler(buffered);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder, ProfileEventKind};
+    use std::sync::{Arc, Mutex};
+
+    // (after, node, op name, output count) per event.
+    type Events = Arc<Mutex<Vec<(bool, usize, String, usize)>>>;
+
+    struct Recorder(Events);
+
+    impl OpObserver for Recorder {
+        fn before_op(&mut self, op: &OpContext<'_>) {
+            let outputs = op.outputs().len();
+            self.0.lock().unwrap().push((false, op.node, op.op_name.to_string(), outputs));
+        }
+
+        fn after_op(&mut self, op: &OpContext<'_>) {
+            let output = op.outputs()[0];
+            assert!(op.tensor_buffer(output).is_some());
+            let outputs = op.outputs().len();
+            self.0.lock().unwrap().push((true, op.node, op.op_name.to_string(), outputs));
+        }
+    }
+
+    #[test]
+    fn unittest_op_observer() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        interpreter.enable_profiling(64);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        interpreter.set_op_observer(Recorder(events.clone()));
+        let profile = interpreter.invoke_profiled().unwrap();
+
+        let events = events.lock().unwrap().clone();
+        let nodes = interpreter.nodes_size();
+        assert_eq!(events.len(), 2 * nodes);
+        for (i, pair) in events.chunks(2).enumerate() {
+            assert_eq!((pair[0].0, pair[1].0), (false, true));
+            assert_eq!(pair[0].1, i);
+            assert_eq!(pair[0].2, pair[1].2);
+            assert!(pair[0].3 >= 1);
+        }
+        assert_eq!(
+            profile.events.iter().filter(|e| e.kind == ProfileEventKind::Operator).count(),
+            nodes
+        );
+
+        assert!(interpreter.remove_op_observer().is_some());
+        interpreter.invoke().unwrap();
+        assert!(interpreter.op_observer_mut().is_none());
+    }
+}
@@ -0,0 +1,185 @@
+//! Inspecting an interpreter from other threads while it is invoked, e.g. by a monitoring
+//! endpoint:
+//!
+//! ```ignore
+//! let inspector = interpreter.inspector();
+//! thread::spawn(move || loop {
+//!     let snapshot = inspector.snapshot();
+//!     report(inspector.invocations(), &snapshot.inputs);
+//!     thread::sleep(Duration::from_secs(10));
+//! });
+//! loop {
+//!     fill(&mut interpreter)?;
+//!     interpreter.invoke()?;
+//! }
+//! ```
+//!
+//! `invoke` borrows the interpreter mutably, so no other thread can read it while it runs. An
+//! `Inspector` is `Send + Sync` and only ever sees metadata copied out of the interpreter:
+//! inputs, outputs and the name, element kind, dims and quantization of each tensor. Tensor
+//! data, which `invoke` writes, stays reachable only through the interpreter. The snapshot is
+//! refreshed by `allocate_tensors` and `modify_graph_with_delegate`, the calls that change the
+//! metadata.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::context::TensorInfo;
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+
+/// Metadata of one tensor, as of the last snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorMetadata {
+    pub index: TensorIndex,
+    pub info: TensorInfo,
+    /// Scale of a quantized tensor, 0 for float tensors.
+    pub scale: f32,
+    pub zero_point: i32,
+    /// Size of the data in bytes.
+    pub bytes: usize,
+}
+
+/// The metadata of an interpreter at one point in time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelSnapshot {
+    /// Counts the snapshots published, starting at 1.
+    pub generation: u64,
+    pub inputs: Vec<TensorIndex>,
+    pub outputs: Vec<TensorIndex>,
+    /// All tensors of the main subgraph, by index.
+    pub tensors: Vec<TensorMetadata>,
+    pub nodes: usize,
+}
+
+impl ModelSnapshot {
+    pub fn tensor(&self, index: TensorIndex) -> Option<&TensorMetadata> {
+        self.tensors.get(usize::try_from(index).ok()?)
+    }
+
+    pub fn tensor_by_name(&self, name: &str) -> Option<&TensorMetadata> {
+        self.tensors.iter().find(|tensor| tensor.info.name == name)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    snapshot: Mutex<Arc<ModelSnapshot>>,
+    invocations: AtomicU64,
+}
+
+/// A handle reading an interpreter's metadata from any thread; see the module documentation.
+#[derive(Clone, Debug, Default)]
+pub struct Inspector {
+    shared: Arc<Shared>,
+}
+
+impl Inspector {
+    /// The latest metadata. Cheap; the snapshot is shared, not copied.
+    pub fn snapshot(&self) -> Arc<ModelSnapshot> {
+        self.shared.snapshot.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Invocations of the interpreter since the inspector was created, failed ones included.
+    pub fn invocations(&self) -> u64 {
+        self.shared.invocations.load(Ordering::Relaxed)
+    }
+
+    fn publish(&self, mut snapshot: ModelSnapshot) {
+        let mut current = self.shared.snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        snapshot.generation = current.generation + 1;
+        *current = Arc::new(snapshot);
+    }
+
+    pub(crate) fn invoked(&self) {
+        self.shared.invocations.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// A `Send + Sync` handle to the metadata of this interpreter, for other threads.
+    pub fn inspector(&mut self) -> Inspector {
+        if let Some(inspector) = &self.inspector {
+            return inspector.clone();
+        }
+        let inspector = Inspector::default();
+        self.inspector = Some(inspector.clone());
+        self.publish_snapshot();
+        inspector
+    }
+
+    pub(crate) fn publish_snapshot(&self) {
+        let inspector = match &self.inspector {
+            Some(inspector) => inspector,
+            None => return,
+        };
+        let tensors = (0..self.tensors_size() as TensorIndex)
+            .filter_map(|index| {
+                let inner = self.tensor_inner(index)?;
+                Some(TensorMetadata {
+                    index,
+                    info: inner.into(),
+                    scale: inner.params.scale,
+                    zero_point: inner.params.zero_point,
+                    bytes: inner.bytes,
+                })
+            })
+            .collect();
+        inspector.publish(ModelSnapshot {
+            generation: 0,
+            inputs: self.inputs().to_vec(),
+            outputs: self.outputs().to_vec(),
+            tensors,
+            nodes: self.nodes_size(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::ElementKind;
+
+    #[test]
+    fn unittest_inspector() {
+        fn shareable<T: Send + Sync>(_: &T) {}
+
+        let inspector = Inspector::default();
+        shareable(&inspector);
+        let reader = inspector.clone();
+        assert_eq!(reader.snapshot().generation, 0);
+
+        let tensor = TensorMetadata {
+            index: 0,
+            info: TensorInfo {
+                name: "image".to_string(),
+                element_kind: ElementKind::kTfLiteUInt8,
+                dims: vec![1, 28, 28],
+            },
+            scale: 1.0 / 255.0,
+            zero_point: 0,
+            bytes: 784,
+        };
+        let snapshot = ModelSnapshot {
+            inputs: vec![0],
+            tensors: vec![tensor.clone()],
+            ..ModelSnapshot::default()
+        };
+        let old = reader.snapshot();
+        inspector.publish(snapshot.clone());
+        inspector.publish(snapshot);
+        inspector.invoked();
+
+        let current =
+            std::thread::spawn(move || (reader.snapshot(), reader.invocations())).join().unwrap();
+        assert_eq!((current.0.generation, current.1), (2, 1));
+        assert_eq!(current.0.tensor_by_name("image"), Some(&tensor));
+        assert_eq!(current.0.tensor(0).map(|t| t.bytes), Some(784));
+        assert!(current.0.tensor(-1).is_none());
+        assert!(old.tensors.is_empty());
+    }
+}
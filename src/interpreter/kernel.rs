@@ -0,0 +1,659 @@
+//! Building blocks for kernels of custom operators.
+//!
+//! A kernel receives a `TfLiteContext` in its callbacks. Wrap it in a `KernelContext` to add
+//! tensors the kernel needs beyond its inputs and outputs. Like in C++ kernels, they become
+//! temporaries of the node, so the interpreter plans their memory:
+//!
+//! ```ignore
+//! unsafe extern "C" fn prepare(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+//!     let mut context = KernelContext::from_raw(context);
+//!     // Running statistics kept across invocations.
+//!     let state = context.add_persistent_buffer(&mut *node, 256)?;
+//!     (*((*node).user_data as *mut MyKernel)).state = state;
+//!     ...
+//! }
+//!
+//! unsafe extern "C" fn invoke(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+//!     let mut context = KernelContext::from_raw(context);
+//!     let state = context.buffer_mut((*((*node).user_data as *mut MyKernel)).state).unwrap();
+//!     ...
+//! }
+//! ```
+//!
+//! Workspace that is only needed during `invoke` should be a scratch temporary instead, which
+//! the interpreter may share between nodes.
+//!
+//! A panic must not unwind out of a callback into TF Lite's C++ frames; run the body of
+//! `prepare` and `invoke` with `guard`, which turns errors and panics into `kTfLiteError`.
+//!
+//! Most kernels need none of the raw callbacks: implement `CustomOp` and register it with
+//! `BuiltinOpResolver::add_custom_op`:
+//!
+//! ```ignore
+//! struct Clip { max: f32 }
+//!
+//! impl CustomOp for Clip {
+//!     fn init(_options: &[u8]) -> Result<Self> {
+//!         Ok(Clip { max: 6.0 })
+//!     }
+//!
+//!     fn prepare(&mut self, context: &mut KernelContext<'_>, node: &mut TfLiteNode) -> Result<()> {
+//!         let input = context.tensor(inputs(node)[0]).unwrap();
+//!         let dims = unsafe { int_array_as_slice(&*input.dims) }.to_vec();
+//!         context.resize_tensor(outputs(node)[0], &dims)
+//!     }
+//!
+//!     fn eval(&mut self, context: &mut KernelContext<'_>, node: &TfLiteNode) -> Result<()> {
+//!         ...
+//!     }
+//! }
+//!
+//! resolver.add_custom_op::<Clip>("Clip", 1)?;
+//! ```
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use super::context::{int_array_as_slice, to_dim, ElementKind, IntArray};
+use super::TensorIndex;
+pub use crate::bindings::{
+    TfLiteAllocationType, TfLiteContext, TfLiteNode, TfLiteRegistration, TfLiteStatus, TfLiteTensor,
+};
+use crate::panic_guard::panic_message;
+use crate::{Error, Result};
+
+cpp! {{
+    #include "tensorflow/lite/c/common.h"
+    #include "tensorflow/lite/interpreter.h"
+    #include "tensorflow/lite/string_util.h"
+}}
+
+/// The context passed to a kernel's `prepare` and `invoke` callbacks.
+pub struct KernelContext<'a> {
+    handle: *mut TfLiteContext,
+    _context: PhantomData<&'a mut TfLiteContext>,
+}
+
+impl<'a> KernelContext<'a> {
+    /// # Safety
+    /// `context` must be the valid context passed to a kernel callback, used only for the
+    /// duration of that callback.
+    pub unsafe fn from_raw(context: *mut TfLiteContext) -> Self {
+        Self { handle: context, _context: PhantomData }
+    }
+
+    pub fn as_ptr(&self) -> *mut TfLiteContext {
+        self.handle
+    }
+
+    pub fn tensors_size(&self) -> usize {
+        unsafe { (*self.handle).tensors_size }
+    }
+
+    pub fn tensor(&self, index: TensorIndex) -> Option<&TfLiteTensor> {
+        if index < 0 || index as usize >= self.tensors_size() {
+            return None;
+        }
+        Some(unsafe { &*(*self.handle).tensors.add(index as usize) })
+    }
+
+    pub fn tensor_mut(&mut self, index: TensorIndex) -> Option<&mut TfLiteTensor> {
+        if index < 0 || index as usize >= self.tensors_size() {
+            return None;
+        }
+        Some(unsafe { &mut *(*self.handle).tensors.add(index as usize) })
+    }
+
+    /// Adds `count` tensors to the graph, returning the index of the first. Previously
+    /// obtained tensor references are invalidated.
+    pub fn add_tensors(&mut self, count: usize) -> Result<TensorIndex> {
+        let context = self.handle;
+        let mut first: TensorIndex = -1;
+        let first_ptr = &mut first;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let added = unsafe {
+            cpp!([context as "TfLiteContext*", count as "size_t", first_ptr as "int*"]
+                  -> bool as "bool" {
+                return context->AddTensors(context, count, first_ptr) == kTfLiteOk;
+            })
+        };
+        if added {
+            Ok(first)
+        } else {
+            Err(Error::internal_error("failed to add tensors"))
+        }
+    }
+
+    /// Resizes a tensor. Its memory is planned when the interpreter allocates tensors.
+    pub fn resize_tensor(&mut self, index: TensorIndex, dims: &[i32]) -> Result<()> {
+        let context = self.handle;
+        let tensor =
+            self.tensor_mut(index).ok_or_else(|| Error::internal_error("invalid tensor index"))?
+                as *mut TfLiteTensor;
+        let dims = IntArray::new(dims).into_raw();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let resized = unsafe {
+            cpp!([context as "TfLiteContext*", tensor as "TfLiteTensor*", dims as "TfLiteIntArray*"]
+                  -> bool as "bool" {
+                return context->ResizeTensor(context, tensor, dims) == kTfLiteOk;
+            })
+        };
+        if resized {
+            Ok(())
+        } else {
+            Err(Error::internal_error("failed to resize tensor"))
+        }
+    }
+
+    /// Adds a temporary tensor to `node` with the given allocation type, element kind and
+    /// dims. Call this in `prepare`; the data is available once tensors are allocated.
+    ///
+    /// `prepare` runs again whenever tensors are reallocated, so add temporaries only once
+    /// (e.g. while `temporaries(node)` is empty) and resize them afterwards.
+    pub fn add_temporary(
+        &mut self,
+        node: &mut TfLiteNode,
+        allocation_type: TfLiteAllocationType,
+        kind: ElementKind,
+        dims: &[i32],
+    ) -> Result<TensorIndex> {
+        let index = self.add_tensors(1)?;
+        let tensor = self.tensor_mut(index).unwrap();
+        tensor.type_ = kind;
+        tensor.allocation_type = allocation_type;
+        self.resize_tensor(index, dims)?;
+
+        let mut temporaries = temporaries(node).to_vec();
+        temporaries.push(index);
+        let old = std::mem::replace(&mut node.temporaries, IntArray::new(&temporaries).into_raw());
+        if !old.is_null() {
+            drop(unsafe { IntArray::from_raw(old) });
+        }
+        Ok(index)
+    }
+
+    /// Adds a temporary placed in the persistent part of the arena: its contents are kept
+    /// between invocations, like `kTfLiteArenaRwPersistent` temporaries of C++ kernels.
+    pub fn add_persistent_tensor(
+        &mut self,
+        node: &mut TfLiteNode,
+        kind: ElementKind,
+        dims: &[i32],
+    ) -> Result<TensorIndex> {
+        self.add_temporary(node, TfLiteAllocationType::kTfLiteArenaRwPersistent, kind, dims)
+    }
+
+    /// Requests `bytes` of persistent memory, the counterpart of `AllocatePersistentBuffer`.
+    /// Access it with `buffer_mut` in `invoke`.
+    pub fn add_persistent_buffer(
+        &mut self,
+        node: &mut TfLiteNode,
+        bytes: usize,
+    ) -> Result<TensorIndex> {
+        self.add_persistent_tensor(node, ElementKind::kTfLiteUInt8, &[to_dim(bytes)?])
+    }
+
+    /// Adds a scratch temporary: arena memory valid only during the node's `invoke` and
+    /// shared with other nodes' scratch space, like `kTfLiteArenaRw` temporaries.
+    pub fn add_scratch_tensor(
+        &mut self,
+        node: &mut TfLiteNode,
+        kind: ElementKind,
+        dims: &[i32],
+    ) -> Result<TensorIndex> {
+        self.add_temporary(node, TfLiteAllocationType::kTfLiteArenaRw, kind, dims)
+    }
+
+    /// Requests `bytes` of scratch memory, the counterpart of `RequestScratchBufferInArena`.
+    /// Access it with `buffer_mut` in `invoke`.
+    pub fn add_scratch_buffer(
+        &mut self,
+        node: &mut TfLiteNode,
+        bytes: usize,
+    ) -> Result<TensorIndex> {
+        self.add_scratch_tensor(node, ElementKind::kTfLiteUInt8, &[to_dim(bytes)?])
+    }
+
+    /// Adds a temporary whose size is only known in `invoke`. Resizing it there with
+    /// `resize_tensor` allocates its memory right away, like `kTfLiteDynamic` tensors.
+    pub fn add_dynamic_tensor(
+        &mut self,
+        node: &mut TfLiteNode,
+        kind: ElementKind,
+        dims: &[i32],
+    ) -> Result<TensorIndex> {
+        self.add_temporary(node, TfLiteAllocationType::kTfLiteDynamic, kind, dims)
+    }
+
+    /// Resizes a buffer added with `add_persistent_buffer`, `add_scratch_buffer` or as a
+    /// dynamic `u8` tensor.
+    pub fn resize_buffer(&mut self, index: TensorIndex, bytes: usize) -> Result<()> {
+        self.resize_tensor(index, &[to_dim(bytes)?])
+    }
+
+    /// The data of a tensor as bytes, or `None` if it is not allocated.
+    pub fn buffer(&self, index: TensorIndex) -> Option<&[u8]> {
+        let tensor = self.tensor(index)?;
+        let data = unsafe { tensor.data.raw_const };
+        if data.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(data as *const u8, tensor.bytes) })
+    }
+
+    pub fn buffer_mut(&mut self, index: TensorIndex) -> Option<&mut [u8]> {
+        let tensor = self.tensor_mut(index)?;
+        let data = unsafe { tensor.data.raw };
+        if data.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts_mut(data as *mut u8, tensor.bytes) })
+    }
+
+    /// Lets `invoke` size an output, like `SetTensorToDynamic`. Call this in `prepare`.
+    pub fn set_dynamic(&mut self, index: TensorIndex) -> Result<()> {
+        let tensor =
+            self.tensor_mut(index).ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        tensor.allocation_type = TfLiteAllocationType::kTfLiteDynamic;
+        Ok(())
+    }
+
+    /// The strings of a `kTfLiteString` tensor, or `None` if it is not one or not allocated.
+    pub fn strings(&self, index: TensorIndex) -> Option<Vec<&[u8]>> {
+        if self.tensor(index)?.type_ != ElementKind::kTfLiteString {
+            return None;
+        }
+        parse_strings(self.buffer(index)?)
+    }
+
+    /// Replaces the contents of a dynamic string tensor with `strings` and resizes it to
+    /// `dims`.
+    pub fn write_strings(
+        &mut self,
+        index: TensorIndex,
+        strings: &[&[u8]],
+        dims: &[i32],
+    ) -> Result<()> {
+        let tensor =
+            self.tensor_mut(index).ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        write_dynamic_strings(tensor, strings, Some(dims))
+    }
+
+    /// Reports an error through the interpreter's error reporter.
+    pub fn report_error(&mut self, message: &str) {
+        let context = self.handle;
+        let message = CString::new(message.replace('\0', " ")).unwrap();
+        let message = message.as_ptr();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([context as "TfLiteContext*", message as "const char*"] {
+                context->ReportError(context, "%s", message);
+            });
+        }
+    }
+}
+
+/// Packs `strings` into a dynamic `kTfLiteString` tensor with `tflite::DynamicBuffer`, resizing
+/// it to `dims`, or keeping its dims with `None`.
+pub(crate) fn write_dynamic_strings(
+    tensor: &mut TfLiteTensor,
+    strings: &[&[u8]],
+    dims: Option<&[i32]>,
+) -> Result<()> {
+    if tensor.type_ != ElementKind::kTfLiteString
+        || tensor.allocation_type != TfLiteAllocationType::kTfLiteDynamic
+    {
+        return Err(Error::internal_error("tensor is not a dynamic string tensor"));
+    }
+    // The count, the offsets of the strings and of their end, then the data, offsets as int32.
+    let bytes = strings.iter().map(|s| s.len()).sum::<usize>() + 4 * (strings.len() + 2);
+    if i32::try_from(bytes).is_err() {
+        return Err(Error::internal_error("strings exceed the 2 GB of a string tensor"));
+    }
+    let tensor = tensor as *mut TfLiteTensor;
+    let pointers: Vec<*const u8> = strings.iter().map(|s| s.as_ptr()).collect();
+    let lengths: Vec<usize> = strings.iter().map(|s| s.len()).collect();
+    let (pointers, lengths, count) = (pointers.as_ptr(), lengths.as_ptr(), strings.len());
+    let dims = dims.map_or(ptr::null_mut(), |dims| IntArray::new(dims).into_raw());
+
+    #[allow(clippy::forget_copy, deprecated)]
+    unsafe {
+        cpp!([tensor as "TfLiteTensor*", pointers as "const char* const*",
+              lengths as "const size_t*", count as "size_t", dims as "TfLiteIntArray*"] {
+            tflite::DynamicBuffer buffer;
+            for (size_t i = 0; i < count; ++i) {
+                buffer.AddString(pointers[i], lengths[i]);
+            }
+            buffer.WriteToTensor(tensor, dims ? dims : TfLiteIntArrayCopy(tensor->dims));
+        });
+    }
+    Ok(())
+}
+
+/// Runs the body of a kernel callback of `kernel`, e.g. the operator name. An error or a panic
+/// is reported through the interpreter's error reporter and returned as `kTfLiteError`.
+pub fn guard<F>(context: &mut KernelContext<'_>, kernel: &str, f: F) -> TfLiteStatus
+where
+    F: FnOnce(&mut KernelContext<'_>) -> Result<()>,
+{
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(context))).unwrap_or_else(|payload| {
+        Err(Error::InternalError(format!("{} panicked: {}", kernel, panic_message(&*payload))))
+    });
+    match result {
+        Ok(()) => TfLiteStatus::kTfLiteOk,
+        Err(e) => {
+            context.report_error(&format!("{}: {}", kernel, e));
+            TfLiteStatus::kTfLiteError
+        }
+    }
+}
+
+/// A custom operator implemented in Rust. Each node of the operator owns a value of the
+/// implementing type, created by `init` and dropped with the interpreter. The values move
+/// with the interpreter to other threads, hence `Send`.
+pub trait CustomOp: Sized + Send + 'static {
+    /// Creates the state of a node from its custom options, e.g. flexbuffer-encoded
+    /// attributes. An error fails `prepare` of the node.
+    fn init(options: &[u8]) -> Result<Self>;
+
+    /// Checks the inputs and sizes the outputs and temporaries. Called by
+    /// `allocate_tensors`, again after inputs were resized.
+    fn prepare(&mut self, _context: &mut KernelContext<'_>, _node: &mut TfLiteNode) -> Result<()> {
+        Ok(())
+    }
+
+    /// Computes the outputs, on every `invoke`.
+    fn eval(&mut self, context: &mut KernelContext<'_>, node: &TfLiteNode) -> Result<()>;
+}
+
+/// The registration running `T`, for `BuiltinOpResolver::add_custom`.
+pub fn registration<T: CustomOp>() -> TfLiteRegistration {
+    TfLiteRegistration {
+        init: Some(custom_init::<T>),
+        free: Some(custom_free::<T>),
+        prepare: Some(custom_prepare::<T>),
+        invoke: Some(custom_invoke::<T>),
+        profiling_string: None,
+        builtin_code: 0,
+        custom_name: ptr::null::<c_char>(),
+        version: 1,
+    }
+}
+
+unsafe extern "C" fn custom_init<T: CustomOp>(
+    _context: *mut TfLiteContext,
+    buffer: *const c_char,
+    length: usize,
+) -> *mut c_void {
+    let options =
+        if buffer.is_null() { &[][..] } else { slice::from_raw_parts(buffer as *const u8, length) };
+    let op = panic::catch_unwind(|| T::init(options)).unwrap_or_else(|payload| {
+        Err(Error::InternalError(format!("init panicked: {}", panic_message(&*payload))))
+    });
+    Box::into_raw(Box::new(op)) as *mut c_void
+}
+
+unsafe extern "C" fn custom_free<T: CustomOp>(_context: *mut TfLiteContext, buffer: *mut c_void) {
+    let op = Box::from_raw(buffer as *mut Result<T>);
+    crate::panic_guard::abort_on_panic(std::any::type_name::<T>(), || drop(op));
+}
+
+unsafe fn custom_run<T, F>(context: *mut TfLiteContext, node: *mut TfLiteNode, f: F) -> TfLiteStatus
+where
+    T: CustomOp,
+    F: FnOnce(&mut T, &mut KernelContext<'_>, &mut TfLiteNode) -> Result<()>,
+{
+    let mut context = KernelContext::from_raw(context);
+    let op = &mut *((*node).user_data as *mut Result<T>);
+    guard(&mut context, std::any::type_name::<T>(), |context| match op {
+        Ok(op) => f(op, context, &mut *node),
+        Err(e) => Err(Error::InternalError(e.to_string())),
+    })
+}
+
+unsafe extern "C" fn custom_prepare<T: CustomOp>(
+    context: *mut TfLiteContext,
+    node: *mut TfLiteNode,
+) -> TfLiteStatus {
+    custom_run(context, node, |op: &mut T, context, node| op.prepare(context, node))
+}
+
+unsafe extern "C" fn custom_invoke<T: CustomOp>(
+    context: *mut TfLiteContext,
+    node: *mut TfLiteNode,
+) -> TfLiteStatus {
+    custom_run(context, node, |op: &mut T, context, node| op.eval(context, node))
+}
+
+/// Splits the buffer of a string tensor: the string count, the offsets of all strings and
+/// of their end as `i32`, then the string data.
+pub fn parse_strings(buffer: &[u8]) -> Option<Vec<&[u8]>> {
+    let int = |i: usize| {
+        let bytes = buffer.get(i * 4..i * 4 + 4)?;
+        let value = i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if value < 0 {
+            None
+        } else {
+            Some(value as usize)
+        }
+    };
+    let count = int(0)?;
+    let offsets = (1..count + 2).map(int).collect::<Option<Vec<_>>>()?;
+    offsets.windows(2).map(|range| buffer.get(range[0]..range[1])).collect()
+}
+
+/// Input tensor indices of `node`; optional inputs that are absent are `-1`.
+pub fn inputs(node: &TfLiteNode) -> &[TensorIndex] {
+    unsafe { int_array_as_slice(&*node.inputs) }
+}
+
+pub fn outputs(node: &TfLiteNode) -> &[TensorIndex] {
+    unsafe { int_array_as_slice(&*node.outputs) }
+}
+
+/// Temporary tensor indices of `node`.
+pub fn temporaries(node: &TfLiteNode) -> &[TensorIndex] {
+    if node.temporaries.is_null() {
+        &[]
+    } else {
+        unsafe { int_array_as_slice(&*node.temporaries) }
+    }
+}
+
+/// The `i`th temporary of `node`, in the order they were added.
+pub fn temporary(node: &TfLiteNode, i: usize) -> Option<TensorIndex> {
+    temporaries(node).get(i).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::stl::memory::UniquePtr;
+    use crate::model::stl::vector::VectorInsert;
+    use crate::model::{
+        BufferT, BuiltinOperator, Model, OperatorCodeT, OperatorT, SubGraphT, TensorT, TensorType,
+    };
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_kernel_context() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        let handle = interpreter.handle_mut() as *mut crate::bindings::tflite::Interpreter;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let context = unsafe {
+            cpp!([handle as "tflite::Interpreter*"] -> *mut TfLiteContext as "TfLiteContext*" {
+                return handle->primary_subgraph().context();
+            })
+        };
+        let mut context = unsafe { KernelContext::from_raw(context) };
+
+        let count = context.tensors_size();
+        let index = context.add_tensors(1).unwrap();
+        assert_eq!(index as usize, count);
+        context.tensor_mut(index).unwrap().type_ = ElementKind::kTfLiteFloat32;
+        context.resize_tensor(index, &[2, 3]).unwrap();
+        assert_eq!(context.tensor(index).unwrap().bytes, 24);
+        assert!(context.tensor(index + 1).is_none());
+
+        let mut node: TfLiteNode = unsafe { std::mem::zeroed() };
+        assert!(temporaries(&node).is_empty());
+        let scratch = context.add_scratch_buffer(&mut node, 64).unwrap();
+        let dynamic =
+            context.add_dynamic_tensor(&mut node, ElementKind::kTfLiteInt32, &[0]).unwrap();
+        assert_eq!(temporaries(&node), &[scratch, dynamic]);
+        assert_eq!(temporary(&node, 1), Some(dynamic));
+        assert_eq!(context.tensor(scratch).unwrap().bytes, 64);
+        context.resize_buffer(scratch, 128).unwrap();
+        assert_eq!(context.tensor(scratch).unwrap().bytes, 128);
+        drop(unsafe { IntArray::from_raw(node.temporaries) });
+
+        assert_eq!(guard(&mut context, "Test", |_| Ok(())), TfLiteStatus::kTfLiteOk);
+        let status = guard(&mut context, "Test", |_| panic!("bad tensor"));
+        assert_eq!(status, TfLiteStatus::kTfLiteError);
+
+        let mut strings = Vec::new();
+        for value in [3i32, 20, 22, 22, 25] {
+            strings.extend_from_slice(&value.to_ne_bytes());
+        }
+        strings.extend_from_slice(b"hiyes");
+        assert_eq!(parse_strings(&strings).unwrap(), vec![&b"hi"[..], b"", b"yes"]);
+        assert!(parse_strings(&strings[..24]).is_none());
+    }
+
+    #[test]
+    fn unittest_custom_op() {
+        struct Counter {
+            step: u32,
+            count: u32,
+        }
+
+        impl CustomOp for Counter {
+            fn init(options: &[u8]) -> Result<Self> {
+                Ok(Counter { step: u32::from(options[0]), count: 0 })
+            }
+
+            fn eval(&mut self, _context: &mut KernelContext<'_>, _node: &TfLiteNode) -> Result<()> {
+                self.count += self.step;
+                Ok(())
+            }
+        }
+
+        let registration = registration::<Counter>();
+        let context = std::ptr::null_mut();
+        let options = [3u8];
+        let mut node: TfLiteNode = unsafe { std::mem::zeroed() };
+        unsafe {
+            node.user_data = registration.init.unwrap()(context, options.as_ptr() as _, 1);
+            let ok = TfLiteStatus::kTfLiteOk;
+            assert_eq!(registration.prepare.unwrap()(context, &mut node), ok);
+            assert_eq!(registration.invoke.unwrap()(context, &mut node), ok);
+            assert_eq!(registration.invoke.unwrap()(context, &mut node), ok);
+            let op = &*(node.user_data as *const Result<Counter>);
+            assert_eq!(op.as_ref().map(|op| op.count).ok(), Some(6));
+            registration.free.unwrap()(context, node.user_data);
+        }
+    }
+
+    /// Clips float inputs to the maximum in its 4 bytes of options; fails on negative inputs.
+    struct Clip {
+        max: f32,
+    }
+
+    impl CustomOp for Clip {
+        fn init(options: &[u8]) -> Result<Self> {
+            match options {
+                &[a, b, c, d] => Ok(Clip { max: f32::from_ne_bytes([a, b, c, d]) }),
+                _ => Err(Error::internal_error("expects a 4-byte maximum")),
+            }
+        }
+
+        fn prepare(
+            &mut self,
+            context: &mut KernelContext<'_>,
+            node: &mut TfLiteNode,
+        ) -> Result<()> {
+            let input = context.tensor(inputs(node)[0]).unwrap();
+            let dims = unsafe { int_array_as_slice(&*input.dims) }.to_vec();
+            context.resize_tensor(outputs(node)[0], &dims)
+        }
+
+        fn eval(&mut self, context: &mut KernelContext<'_>, node: &TfLiteNode) -> Result<()> {
+            let input = context.buffer(inputs(node)[0]).unwrap().to_vec();
+            let output = context.buffer_mut(outputs(node)[0]).unwrap();
+            for (input, output) in input.chunks_exact(4).zip(output.chunks_exact_mut(4)) {
+                let value = f32::from_ne_bytes([input[0], input[1], input[2], input[3]]);
+                if value < 0.0 {
+                    return Err(Error::internal_error("negative input"));
+                }
+                output.copy_from_slice(&value.min(self.max).to_ne_bytes());
+            }
+            Ok(())
+        }
+    }
+
+    /// A model of a single `Clip` node with `options`, from a `[4]` float input to an output.
+    fn clip_model(options: &[u8]) -> FlatBufferModel {
+        let mut model = Model::default();
+        model.version = 3;
+        let mut code: UniquePtr<OperatorCodeT> = Default::default();
+        code.builtin_code = BuiltinOperator::BuiltinOperator_CUSTOM;
+        code.custom_code.assign(&CString::new("Clip").unwrap());
+        code.version = 1;
+        model.operator_codes.push_back(code);
+        model.buffers.assign(vec![UniquePtr::<BufferT>::default()]);
+
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        for _ in 0..2 {
+            let mut tensor: UniquePtr<TensorT> = Default::default();
+            tensor.shape.assign(vec![4]);
+            tensor.typ = TensorType::TensorType_FLOAT32;
+            subgraph.tensors.push_back(tensor);
+        }
+        let mut operator: UniquePtr<OperatorT> = Default::default();
+        operator.inputs.assign(vec![0]);
+        operator.outputs.assign(vec![1]);
+        operator.custom_options.assign(options.iter().copied());
+        subgraph.operators.push_back(operator);
+        subgraph.inputs.assign(vec![0]);
+        subgraph.outputs.assign(vec![1]);
+        model.subgraphs.push_back(subgraph);
+        FlatBufferModel::build_from_model(&model).unwrap()
+    }
+
+    #[test]
+    fn unittest_custom_op_interpreter() {
+        let build = |options: &[u8]| {
+            let mut resolver = BuiltinOpResolver::default();
+            resolver.add_custom_op::<Clip>("Clip", 1).unwrap();
+            InterpreterBuilder::new(clip_model(options), resolver).unwrap().build().unwrap()
+        };
+
+        let mut interpreter = build(&6f32.to_ne_bytes());
+        interpreter.allocate_tensors().unwrap();
+        interpreter.tensor_data_mut::<f32>(0).unwrap().copy_from_slice(&[1.0, 5.5, 6.0, 9.0]);
+        interpreter.invoke().unwrap();
+        assert_eq!(interpreter.tensor_data::<f32>(1).unwrap(), &[1.0, 5.5, 6.0, 6.0]);
+        // An error of `eval` fails the invocation, not the program.
+        interpreter.tensor_data_mut::<f32>(0).unwrap()[0] = -1.0;
+        assert!(interpreter.invoke().is_err());
+
+        // An error of `init` fails `prepare`.
+        let mut interpreter = build(&[1, 2]);
+        assert!(interpreter.allocate_tensors().is_err());
+
+        // Without the registration, the model has no kernel for `Clip`.
+        let builder = InterpreterBuilder::new(clip_model(&[]), BuiltinOpResolver::default());
+        assert!(builder.and_then(|builder| builder.build()).is_err());
+    }
+}
@@ -1,19 +1,104 @@
+#[cfg(feature = "ndarray")]
+mod array;
+#[cfg(feature = "tokio")]
+mod async_invoke;
 mod builder;
+mod builtin_params;
+mod cancellation;
+mod confined;
 pub mod context;
+mod custom_allocation;
+mod delegate;
+mod device_pool;
+mod diagnostics;
+mod fallback;
 mod fbmodel;
+mod gpu;
+mod hooks;
+mod inspector;
+pub mod kernel;
+#[cfg(all(feature = "nnapi", target_os = "android"))]
+mod nnapi;
+mod numeric;
 pub mod op_resolver;
 pub mod ops;
+mod partition;
+mod planning;
+mod pool;
+mod profiler;
+mod quantization;
+mod scheduler;
+mod shadow;
+mod shared_arena;
+mod signature;
+mod slot;
+mod stats;
+mod strings;
+mod telemetry;
+mod tensor;
+#[cfg(feature = "xnnpack")]
+mod xnnpack;
 
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::marker::PhantomData;
 use std::mem;
+use std::os::raw::c_void;
 use std::slice;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use libc::{c_int, size_t};
 
-use crate::{bindings, Error, Result};
-pub use builder::InterpreterBuilder;
-use context::{ElemKindOf, ElementKind, QuantizationParams, TensorInfo};
+use crate::leak_tracking::{self, NativeObject};
+use crate::{bindings, DelegateFailure, Error, Result};
+pub use builder::{InterpreterBuilder, SharedInterpreterBuilder};
+pub use builtin_params::{
+    BuiltinParams, ConvParams, DepthwiseConvParams, FullyConnectedParams, LstmParams, Padding,
+    PoolParams, StridedSliceParams,
+};
+use cancellation::Interrupt;
+pub use cancellation::{CancelGuard, CancellationToken};
+pub use confined::ThreadConfined;
+use context::{ElemKindOf, ElementKind, QuantizationParams, TensorInfo, TensorLayout};
+pub use custom_allocation::CustomAllocation;
+pub use delegate::{Delegate, DelegateDeleter};
+pub use device_pool::{DevicePool, SchedulingPolicy};
+use diagnostics::ErrorCollector;
+pub use diagnostics::{BuildDiagnostics, Diagnostic, DiagnosticKind};
+pub use fallback::{Executor, Fallback, FallbackStats};
 pub use fbmodel::FlatBufferModel;
+#[cfg(feature = "gpu")]
+pub use gpu::GpuDelegate;
+pub use gpu::{GpuDelegateOptions, GpuInferencePreference, GpuPrecision};
+use hooks::OpHooks;
+pub use hooks::{OpContext, OpObserver};
+pub use inspector::{Inspector, ModelSnapshot, TensorMetadata};
+#[cfg(all(feature = "nnapi", target_os = "android"))]
+pub use nnapi::{NnapiDelegate, NnapiDelegateOptions, NnapiExecutionPreference};
+pub use numeric::NumericIssue;
 use op_resolver::OpResolver;
+pub use partition::{DelegationReport, OpInfo, Partition};
+pub use planning::{FusedActivation, NodePlan, PlanReport, TensorPlacement};
+pub use pool::{InterpreterPool, PooledInterpreter};
+use profiler::Profiler;
+pub use profiler::{
+    ChromeTrace, NodeTiming, OpProfile, PartitionTiming, Profile, ProfileEvent, ProfileEventKind,
+    DEFAULT_MAX_PROFILE_EVENTS,
+};
+pub use quantization::{QuantizationInfo, Quantized};
+pub use scheduler::{RunRequest, Scheduler};
+pub use shadow::{OutputDivergence, Shadow, ShadowStats};
+pub use shared_arena::SharedArena;
+pub use signature::{SignatureDef, SignatureRunner};
+pub use slot::InterpreterSlot;
+pub(crate) use stats::json_string;
+pub use stats::{ActivationCollector, ActivationStats, TensorStats};
+pub use strings::StringBuffer;
+pub use telemetry::{InvokeRecord, InvokeStatus, Telemetry};
+pub use tensor::{Tensor, TensorMut};
+#[cfg(feature = "xnnpack")]
+pub use xnnpack::XnnpackDelegate;
 
 cpp! {{
     #include "tensorflow/lite/interpreter.h"
@@ -24,14 +109,47 @@ cpp! {{
 
 pub type TensorIndex = c_int;
 
+/// An interpreter of a model.
+///
+/// Interpreters are `Send` but not `Sync`: they can move to another thread, but only one thread
+/// at a time uses them. To read an interpreter's metadata from other threads while it runs, use
+/// its `Inspector`; to serve requests from many threads, put interpreters in an
+/// `InterpreterPool`.
+///
+/// Delegates bound to the thread that applied them, like the OpenGL backend of the GPU
+/// delegate, require the interpreter to stay on that thread: keep it in a `ThreadConfined`.
 pub struct Interpreter<'a, Op>
 where
     Op: OpResolver,
 {
     handle: Box<bindings::tflite::Interpreter>,
-    _builder: InterpreterBuilder<'a, Op>,
+    // Receives the messages of `handle`, so it is only dropped after `handle` was deleted.
+    errors: ErrorCollector,
+    builder: Arc<InterpreterBuilder<'a, Op>>,
+    // Checked by the native interpreter between ops, so boxed to keep its address.
+    interrupt: Box<Interrupt>,
+    // Declared after `handle` so delegates outlive the native interpreter.
+    delegates: Vec<Delegate>,
+    // Memory of custom-allocated tensors, also outliving the native interpreter.
+    custom_allocations: Vec<(TensorIndex, CustomAllocation)>,
+    num_threads: c_int,
+    allow_fp16: bool,
+    profiler: Option<Profiler>,
+    op_hooks: Option<OpHooks>,
+    inspector: Option<Inspector>,
+    telemetry: Option<Telemetry>,
+    // Native interpreters are not safe to read while another thread changes them.
+    not_sync: PhantomData<Cell<()>>,
 }
 
+// # Safety
+// TF Lite interpreters have no thread affinity of their own: native calls may come from any
+// thread as long as no two run concurrently on one interpreter, which not being `Sync` ensures.
+// The other native state an interpreter refers to is `Send`: the model is immutable, resolvers
+// are `OpResolver: Send + Sync`, `CustomOp` node states are `Send`, observers are
+// `OpObserver: Send` and delegates are reference counted atomically.
+unsafe impl<'a, Op> Send for Interpreter<'a, Op> where Op: OpResolver {}
+
 impl<'a, Op> Drop for Interpreter<'a, Op>
 where
     Op: OpResolver,
@@ -44,6 +162,7 @@ where
                 delete handle;
             });
         }
+        leak_tracking::destroyed(NativeObject::Interpreter);
     }
 }
 
@@ -60,23 +179,60 @@ where
         self.handle.deref_mut()
     }
     pub(crate) fn new(
-        handle: *mut bindings::tflite::Interpreter,
-        builder: InterpreterBuilder<'a, Op>,
+        builder: Arc<InterpreterBuilder<'a, Op>>,
+        num_threads: c_int,
     ) -> Result<Self> {
+        let (handle, errors) = builder.build_handle(num_threads);
+        if handle.is_null() {
+            return Err(Error::BuildFailed(errors.take()));
+        }
         let handle = unsafe { Box::from_raw(handle) };
-        let mut interpreter = Self { handle, _builder: builder };
+        leak_tracking::created(NativeObject::Interpreter);
+        let telemetry = builder.telemetry.clone();
+        let mut interpreter = Self {
+            handle,
+            errors,
+            builder,
+            interrupt: Box::default(),
+            delegates: Vec::new(),
+            custom_allocations: Vec::new(),
+            num_threads,
+            allow_fp16: false,
+            profiler: None,
+            op_hooks: None,
+            inspector: None,
+            telemetry,
+            not_sync: PhantomData,
+        };
         // # Safety
         // Always allocate tensors so we don't get into a state
         // where we try to read from or write to unallocated memory
         // without doing this it is possible to have undefined behavior
         // outside of an unsafe block
-        interpreter.allocate_tensors()?;
+        if !interpreter.allocate() {
+            let mut diagnostics = interpreter.errors.take();
+            if diagnostics.of_kind(DiagnosticKind::Allocation).next().is_none() {
+                let mut diagnostic = Diagnostic::new("failed to allocate tensors");
+                diagnostic.kind = DiagnosticKind::Allocation;
+                diagnostics.diagnostics.push(diagnostic);
+            }
+            return Err(Error::BuildFailed(diagnostics));
+        }
         Ok(interpreter)
     }
     /// Update allocations for all tensors. This will redim dependent tensors using
     /// the input tensor dimensionality as given. This is relatively expensive.
     /// If you know that your sizes are not changing, you need not call this.
     pub fn allocate_tensors(&mut self) -> Result<()> {
+        self.errors.flush();
+        if self.allocate() {
+            Ok(())
+        } else {
+            Err(self.reported_error("failed to allocate tensors".to_string()))
+        }
+    }
+
+    fn allocate(&mut self) -> bool {
         let interpreter = self.handle_mut();
 
         #[allow(clippy::forget_copy, deprecated)]
@@ -85,10 +241,61 @@ where
                 return interpreter->AllocateTensors() == kTfLiteOk;
             })
         };
+        if r {
+            self.publish_snapshot();
+        }
+        r
+    }
+
+    /// Messages TF Lite reported during the last operation that succeeded, e.g. warnings of
+    /// delegates. Those of failed operations are in their errors, and older ones are dropped,
+    /// or logged with the `log` feature.
+    pub fn take_diagnostics(&self) -> BuildDiagnostics {
+        self.errors.take()
+    }
+
+    /// The error of a failed operation, with the messages TF Lite reported for it.
+    fn reported_error(&self, operation: String) -> Error {
+        let diagnostics = self.errors.take();
+        if diagnostics.diagnostics.is_empty() {
+            Error::InternalError(operation)
+        } else {
+            Error::InternalError(format!("{}: {}", operation, diagnostics))
+        }
+    }
+
+    /// Changes the dims of input `tensor_index`, e.g. the batch size or image resolution of a
+    /// model with dynamic inputs. Takes effect, and sizes dependent tensors, on the next
+    /// `allocate_tensors`, which must be called before `invoke`.
+    pub fn resize_input_tensor(&mut self, tensor_index: TensorIndex, dims: &[i32]) -> Result<()> {
+        if !self.inputs().contains(&tensor_index) {
+            return Err(Error::InternalError(format!("tensor {} is not an input", tensor_index)));
+        }
+        if dims.iter().any(|&dim| dim < 0) {
+            return Err(Error::InternalError(format!("invalid input dims {:?}", dims)));
+        }
+        self.errors.flush();
+        let interpreter = self.handle_mut();
+        let dims_ptr = dims.as_ptr();
+        let dims_len = dims.len() as size_t;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                tensor_index as "int",
+                dims_ptr as "const int*",
+                dims_len as "size_t"
+            ] -> bool as "bool" {
+                std::vector<int> dims(dims_ptr, dims_ptr + dims_len);
+                return interpreter->ResizeInputTensor(tensor_index, dims) == kTfLiteOk;
+            })
+        };
         if r {
             Ok(())
         } else {
-            Err(Error::internal_error("failed to allocate tensors"))
+            Err(self
+                .reported_error(format!("failed to resize input {} to {:?}", tensor_index, dims)))
         }
     }
 
@@ -104,9 +311,39 @@ where
         };
     }
 
+    /// Resets all variable tensors, e.g. the state of recurrent layers, to their defaults.
+    pub fn reset_variable_tensors(&mut self) -> Result<()> {
+        self.errors.flush();
+        let interpreter = self.handle_mut();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([interpreter as "Interpreter*"] -> bool as "bool" {
+                return interpreter->ResetVariableTensors() == kTfLiteOk;
+            })
+        };
+        if r {
+            Ok(())
+        } else {
+            Err(self.reported_error("failed to reset variable tensors".to_string()))
+        }
+    }
+
     /// Invoke the interpreter (run the whole graph in dependency order).
+    ///
+    /// Holds the locks of all applied delegates, serializing invocations of interpreters
+    /// that share a delegate.
     pub fn invoke(&mut self) -> Result<()> {
-        let interpreter = self.handle_mut();
+        let started = Instant::now();
+        let result = self.invoke_graph();
+        self.record_invoke(None, started, &result);
+        result
+    }
+
+    fn invoke_graph(&mut self) -> Result<()> {
+        self.errors.flush();
+        let _guards = delegate::lock_all(&self.delegates);
+        let interpreter = &mut *self.handle;
 
         #[allow(deprecated)]
         let r = unsafe {
@@ -114,11 +351,170 @@ where
                 return interpreter->Invoke() == kTfLiteOk;
             })
         };
+        if let Some(inspector) = &self.inspector {
+            inspector.invoked();
+        }
+        if let Some(payload) = self.op_hooks.as_mut().and_then(OpHooks::take_panic) {
+            std::panic::resume_unwind(payload);
+        }
+        if let Some(issue) = self.op_hooks.as_mut().and_then(OpHooks::take_issue) {
+            return Err(Error::NonFinite(issue));
+        }
         if r {
             Ok(())
+        } else if self.interrupt.is_cancelled() {
+            Err(Error::Cancelled)
         } else {
-            Err(Error::internal_error("failed to invoke interpreter"))
+            Err(self.reported_error("failed to invoke interpreter".to_string()))
+        }
+    }
+
+    /// Invoke the interpreter, aborting it at the next op boundary once `timeout` elapses.
+    /// Returns `Error::Timeout` if the invocation was aborted for taking too long.
+    ///
+    /// The deadline is checked on the invoking thread between ops, apart from the cancellation
+    /// token: a cancellation requested before or during the call (e.g. by a shutdown signal)
+    /// is honored and left in place.
+    pub fn invoke_with_timeout(&mut self, timeout: Duration) -> Result<()> {
+        let started = Instant::now();
+        self.install_interrupt();
+        self.interrupt.set_deadline(Some(started + timeout));
+        let result = self.invoke_graph();
+        let timed_out = self.interrupt.timed_out();
+        self.interrupt.set_deadline(None);
+
+        let result = match result {
+            Err(_) if timed_out => Err(Error::Timeout(timeout)),
+            result => result,
+        };
+        self.record_invoke(None, started, &result);
+        result
+    }
+
+    /// Installs `token` as the interpreter's cancellation function.
+    /// The interpreter checks it between op invocations during `invoke`.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.interrupt.token = Some(token);
+        self.install_interrupt();
+    }
+
+    /// Returns the installed cancellation token, if any.
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.interrupt.token.as_ref()
+    }
+
+    fn install_interrupt(&mut self) {
+        let data = &*self.interrupt as *const Interrupt as *mut c_void;
+        let check_interrupted: extern "C" fn(*mut c_void) -> bool = cancellation::check_interrupted;
+        let interpreter = self.handle_mut();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                data as "void*",
+                check_interrupted as "bool (*)(void*)"
+            ] {
+                interpreter->SetCancellationFunction(data, check_interrupted);
+            })
+        };
+    }
+
+    /// Applies `delegate` to the graph. The interpreter keeps a reference to it, so the
+    /// same delegate can be applied to several interpreters, also on different threads.
+    ///
+    /// If the delegate fails, the interpreter is rebuilt without it instead of being left
+    /// partially delegated, and `Error::DelegateFailed` tells whether that succeeded.
+    pub fn modify_graph_with_delegate(&mut self, delegate: &Delegate) -> Result<()> {
+        self.errors.flush();
+        if self.apply_delegate(delegate) {
+            self.delegates.push(delegate.clone());
+            self.publish_snapshot();
+            return Ok(());
+        }
+        // Logs why the delegate failed, as the rebuilt interpreter reports to a new collector.
+        self.errors.flush();
+        let failure = match self.restore() {
+            Ok(()) => DelegateFailure::Rejected,
+            Err(e) => DelegateFailure::Unrecoverable(e.to_string()),
+        };
+        Err(Error::DelegateFailed(failure))
+    }
+
+    fn apply_delegate(&mut self, delegate: &Delegate) -> bool {
+        let _guard = delegate.lock();
+        let interpreter = &mut *self.handle;
+        let delegate_ptr = delegate.as_ptr();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                delegate_ptr as "TfLiteDelegate*"
+            ] -> bool as "bool" {
+                return interpreter->ModifyGraphWithDelegate(delegate_ptr) == kTfLiteOk;
+            })
+        }
+    }
+
+    /// Replaces the native interpreter with a fresh one carrying over the fp16 setting, the
+    /// delegates applied so far, custom allocations, the cancellation token and the contents of
+    /// equally sized input tensors.
+    fn restore(&mut self) -> Result<()> {
+        let (handle, errors) = self.builder.build_handle(self.num_threads);
+        if handle.is_null() {
+            return Err(Error::internal_error("failed to rebuild interpreter"));
+        }
+        let inputs: Vec<(TensorIndex, Vec<u8>)> = self
+            .inputs()
+            .iter()
+            .filter_map(|&i| Some((i, self.tensor_buffer(i)?.to_vec())))
+            .collect();
+
+        let old = mem::replace(&mut self.handle, unsafe { Box::from_raw(handle) });
+        let old = Box::into_raw(old);
+        #[allow(clippy::forget_copy, clippy::useless_transmute, deprecated)]
+        unsafe {
+            cpp!([old as "Interpreter*"] {
+                delete old;
+            });
         }
+        leak_tracking::destroyed(NativeObject::Interpreter);
+        leak_tracking::created(NativeObject::Interpreter);
+        self.errors = errors;
+
+        // Before the delegates, which read it when they are applied.
+        self.set_allow_fp16_precision_for_fp32(self.allow_fp16);
+        for delegate in self.delegates.clone() {
+            if !self.apply_delegate(&delegate) {
+                return Err(Error::internal_error("failed to reapply a previous delegate"));
+            }
+        }
+        let custom_allocations = mem::take(&mut self.custom_allocations);
+        let applied = custom_allocations
+            .iter()
+            .try_for_each(|(index, allocation)| self.apply_custom_allocation(*index, allocation));
+        // Kept even on failure: the native interpreter may point at those already applied.
+        self.custom_allocations = custom_allocations;
+        applied?;
+        if self.interrupt.token.is_some() {
+            self.install_interrupt();
+        }
+        self.install_profiler();
+        self.allocate_tensors()?;
+        for (index, data) in inputs {
+            if let Some(buffer) = self.tensor_buffer_mut(index) {
+                if buffer.len() == data.len() {
+                    buffer.copy_from_slice(&data);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delegates applied to this interpreter, in application order.
+    pub fn delegates(&self) -> &[Delegate] {
+        &self.delegates
     }
 
     /// Sets the number of threads available to the interpreter
@@ -126,17 +522,60 @@ where
     /// Passing in a value of -1 will let the interpreter set the number
     /// of threads available to itself.
     ///
+    /// Set it before `allocate_tensors`, since some kernels size their scratch buffers by the
+    /// thread count in `prepare`. It is kept when a failed delegate forces a rebuild.
+    ///
     /// Note that increasing the number of threads does not always speed up inference
-    pub fn set_num_threads(&mut self, threads: c_int) {
+    pub fn set_num_threads(&mut self, threads: c_int) -> Result<()> {
+        if threads < -1 {
+            return Err(Error::InternalError(format!("invalid number of threads {}", threads)));
+        }
+        let interpreter = self.handle_mut();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([interpreter as "Interpreter*", threads as "int"] -> bool as "bool" {
+                return interpreter->SetNumThreads(threads) == kTfLiteOk;
+            })
+        };
+        if !r {
+            return Err(Error::InternalError(format!("failed to set {} threads", threads)));
+        }
+        self.num_threads = threads;
+        Ok(())
+    }
+
+    /// The number of threads set by `set_num_threads` or the builder, -1 for the default.
+    pub fn num_threads(&self) -> c_int {
+        self.num_threads
+    }
+
+    /// Lets float kernels compute in fp16 where supported, trading accuracy for speed.
+    ///
+    /// Set it before applying delegates, which read it. It is kept when a failed delegate
+    /// forces a rebuild.
+    pub fn set_allow_fp16_precision_for_fp32(&mut self, allow: bool) {
         let interpreter = self.handle_mut();
 
         #[allow(clippy::forget_copy, deprecated)]
         unsafe {
-            cpp!([interpreter as "Interpreter*", threads as "int"] {
-                interpreter->SetNumThreads(threads);
+            cpp!([interpreter as "Interpreter*", allow as "bool"] {
+                interpreter->SetAllowFp16PrecisionForFp32(allow);
             })
         };
-        println!("Set num threads to {}", threads);
+        self.allow_fp16 = allow;
+    }
+
+    /// Whether float kernels may compute in fp16.
+    pub fn allow_fp16_precision_for_fp32(&self) -> bool {
+        let interpreter = self.handle();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([interpreter as "const Interpreter*"] -> bool as "bool" {
+                return interpreter->GetAllowFp16PrecisionForFp32();
+            })
+        }
     }
 
     /// Read only access to list of inputs.
@@ -196,6 +635,11 @@ where
         unsafe { slice::from_raw_parts(ptr, count) }
     }
 
+    /// The model the interpreter was built from.
+    pub fn model(&self) -> &FlatBufferModel {
+        self.builder.model()
+    }
+
     /// Return the number of tensors in the model.
     pub fn tensors_size(&self) -> size_t {
         let interpreter = self.handle();
@@ -208,6 +652,19 @@ where
         }
     }
 
+    /// Return the number of subgraphs, including those invoked by control flow ops such as
+    /// WHILE and IF.
+    pub fn subgraphs_size(&self) -> size_t {
+        let interpreter = self.handle();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([interpreter as "const Interpreter*"] -> size_t as "size_t" {
+                return interpreter->subgraphs_size();
+            })
+        }
+    }
+
     /// Return the number of ops in the model.
     pub fn nodes_size(&self) -> size_t {
         let interpreter = self.handle();
@@ -223,6 +680,7 @@ where
     /// Adds `count` tensors, preserving pre-existing Tensor entries.
     /// Return the index of the first new tensor.
     pub fn add_tensors(&mut self, count: size_t) -> Result<TensorIndex> {
+        self.errors.flush();
         let interpreter = self.handle();
         let mut index: TensorIndex = 0;
 
@@ -239,7 +697,7 @@ where
         if result == bindings::TfLiteStatus::kTfLiteOk {
             Ok(index)
         } else {
-            Err(Error::internal_error("failed to add tensors"))
+            Err(self.reported_error("failed to add tensors".to_string()))
         }
     }
 
@@ -247,6 +705,7 @@ where
     /// Each index is bound check and this modifies the consistent_ flag of the
     /// interpreter.
     pub fn set_inputs(&mut self, inputs: &[TensorIndex]) -> Result<()> {
+        self.errors.flush();
         let interpreter = self.handle_mut();
         let ptr = inputs.as_ptr();
         let len = inputs.len() as size_t;
@@ -265,7 +724,7 @@ where
         if result == bindings::TfLiteStatus::kTfLiteOk {
             Ok(())
         } else {
-            Err(Error::internal_error("failed to set inputs"))
+            Err(self.reported_error("failed to set inputs".to_string()))
         }
     }
 
@@ -273,6 +732,7 @@ where
     /// Each index is bound check and this modifies the consistent_ flag of the
     /// interpreter.
     pub fn set_outputs(&mut self, outputs: &[TensorIndex]) -> Result<()> {
+        self.errors.flush();
         let interpreter = self.handle_mut();
         let ptr = outputs.as_ptr();
         let len = outputs.len() as size_t;
@@ -291,7 +751,7 @@ where
         if result == bindings::TfLiteStatus::kTfLiteOk {
             Ok(())
         } else {
-            Err(Error::internal_error("failed to set outputs"))
+            Err(self.reported_error("failed to set outputs".to_string()))
         }
     }
 
@@ -299,6 +759,7 @@ where
     /// Each index is bound check and this modifies the consistent_ flag of the
     /// interpreter.
     pub fn set_variables(&mut self, variables: &[TensorIndex]) -> Result<()> {
+        self.errors.flush();
         let interpreter = self.handle_mut();
         let ptr = variables.as_ptr();
         let len = variables.len() as size_t;
@@ -317,7 +778,7 @@ where
         if result == bindings::TfLiteStatus::kTfLiteOk {
             Ok(())
         } else {
-            Err(Error::internal_error("failed to set variables"))
+            Err(self.reported_error("failed to set variables".to_string()))
         }
     }
 
@@ -331,12 +792,13 @@ where
         quantization: QuantizationParams,
         is_variable: bool,
     ) -> Result<()> {
+        self.errors.flush();
         let interpreter = self.handle_mut();
 
         let name_ptr = name.as_ptr();
         let name_len = name.len() as size_t;
 
-        let dims: Vec<i32> = dims.iter().map(|x| *x as i32).collect();
+        let dims = context::to_dims(dims)?;
         let dims_ptr = dims.as_ptr();
         let dims_len = dims.len() as size_t;
 
@@ -361,7 +823,7 @@ where
         if result == bindings::TfLiteStatus::kTfLiteOk {
             Ok(())
         } else {
-            Err(Error::internal_error("failed to set tensor parameters"))
+            Err(self.reported_error("failed to set tensor parameters".to_string()))
         }
     }
 
@@ -384,10 +846,71 @@ where
         }
     }
 
+    fn tensor_inner_mut(
+        &mut self,
+        tensor_index: TensorIndex,
+    ) -> Option<&mut bindings::TfLiteTensor> {
+        let interpreter = self.handle_mut();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let ptr = unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                tensor_index as "int"
+            ] -> *mut bindings::TfLiteTensor as "TfLiteTensor*" {
+                return interpreter->tensor(tensor_index);
+            })
+        };
+        unsafe { ptr.as_mut() }
+    }
+
+    /// Index of the tensor of the main subgraph named `name`.
+    pub fn tensor_index(&self, name: &str) -> Option<TensorIndex> {
+        (0..self.tensors_size() as TensorIndex).find(|&index| {
+            self.tensor_inner(index).is_some_and(|tensor| {
+                !tensor.name.is_null()
+                    && unsafe { CStr::from_ptr(tensor.name) }.to_bytes() == name.as_bytes()
+            })
+        })
+    }
+
+    /// The data of the tensor named `name`, e.g. an intermediate layer to extract embeddings
+    /// from after `invoke`.
+    ///
+    /// Intermediate tensors share memory and are overwritten during an invocation unless the
+    /// interpreter was built with `InterpreterBuilder::preserve_all_tensors`; reading them
+    /// otherwise fails.
+    pub fn tensor_by_name<T>(&self, name: &str) -> Result<&[T]>
+    where
+        T: ElemKindOf,
+    {
+        let index = self
+            .tensor_index(name)
+            .ok_or_else(|| Error::InternalError(format!("no tensor named `{}`", name)))?;
+        let shared = self.tensor_inner(index).is_some_and(|tensor| {
+            tensor.allocation_type == bindings::TfLiteAllocationType::kTfLiteArenaRw
+        });
+        if shared && !self.inputs().contains(&index) && !self.outputs().contains(&index) {
+            return Err(Error::InternalError(format!(
+                "tensor `{}` is not preserved, build the interpreter with `preserve_all_tensors`",
+                name
+            )));
+        }
+        self.tensor_data(index)
+    }
+
     pub fn tensor_info(&self, tensor_index: TensorIndex) -> Option<TensorInfo> {
         Some(self.tensor_inner(tensor_index)?.into())
     }
 
+    /// Strides and contiguity of a tensor's data. Check `is_contiguous` before viewing
+    /// `tensor_buffer` as an n-dimensional array.
+    pub fn tensor_layout(&self, tensor_index: TensorIndex) -> Option<TensorLayout> {
+        Some(self.tensor_inner(tensor_index)?.into())
+    }
+
+    /// The data of a tensor as elements of `T`, failing unless `T` is the tensor's element
+    /// type, e.g. `f32`, `u8`, `i8`, `i32`, `i64`, `bool` or `context::F16`.
     pub fn tensor_data<T>(&self, tensor_index: TensorIndex) -> Result<&[T]>
     where
         T: ElemKindOf,
@@ -405,11 +928,9 @@ where
             )));
         }
 
+        check_aligned::<T>(tensor_index, unsafe { inner.data.raw_const } as usize)?;
         Ok(unsafe {
-            slice::from_raw_parts(
-                inner.data.raw_const as *const T,
-                inner.bytes / mem::size_of::<T>(),
-            )
+            raw_slice(inner.data.raw_const as *const T, inner.bytes / mem::size_of::<T>())
         })
     }
 
@@ -430,21 +951,51 @@ where
             )));
         }
 
-        Ok(unsafe {
-            slice::from_raw_parts_mut(inner.data.raw as *mut T, inner.bytes / mem::size_of::<T>())
-        })
+        check_aligned::<T>(tensor_index, unsafe { inner.data.raw_const } as usize)?;
+        Ok(unsafe { raw_slice_mut(inner.data.raw as *mut T, inner.bytes / mem::size_of::<T>()) })
     }
 
     pub fn tensor_buffer(&self, tensor_index: TensorIndex) -> Option<&[u8]> {
         let inner = self.tensor_inner(tensor_index)?;
 
-        Some(unsafe { slice::from_raw_parts(inner.data.raw_const as *mut u8, inner.bytes) })
+        Some(unsafe { raw_slice(inner.data.raw_const as *const u8, inner.bytes) })
     }
 
     pub fn tensor_buffer_mut(&mut self, tensor_index: TensorIndex) -> Option<&mut [u8]> {
         let inner = self.tensor_inner(tensor_index)?;
 
-        Some(unsafe { slice::from_raw_parts_mut(inner.data.raw as *mut u8, inner.bytes) })
+        Some(unsafe { raw_slice_mut(inner.data.raw as *mut u8, inner.bytes) })
+    }
+}
+
+// Constant tensors point into the model buffer, which need not be aligned for `T`; see
+// `Tensor::read` for reading them anyway.
+fn check_aligned<T>(tensor_index: TensorIndex, address: usize) -> Result<()> {
+    if address & (mem::align_of::<T>() - 1) == 0 {
+        Ok(())
+    } else {
+        Err(Error::InternalError(format!(
+            "tensor {} data is not aligned for {}-byte alignment",
+            tensor_index,
+            mem::align_of::<T>()
+        )))
+    }
+}
+
+// Dynamic tensors, e.g. outputs of WHILE, have no data until they are computed.
+unsafe fn raw_slice<'a, T>(data: *const T, len: usize) -> &'a [T] {
+    if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+unsafe fn raw_slice_mut<'a, T>(data: *mut T, len: usize) -> &'a mut [T] {
+    if data.is_null() {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(data, len)
     }
 }
 
@@ -452,11 +1003,13 @@ where
 mod tests {
     use super::*;
     use std::sync::Arc;
+    use std::thread;
 
     use crate::ops::builtin::BuiltinOpResolver;
 
     #[test]
     fn threadsafe_types() {
+        fn send<T: Send>(_t: &T) {}
         fn send_sync<T: Send + Sync>(_t: &T) {}
         let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite")
             .expect("Unable to build flatbuffer model");
@@ -466,6 +1019,261 @@ mod tests {
         let builder = InterpreterBuilder::new(model, resolver).expect("Not able to build builder");
         send_sync(&builder);
         let interpreter = builder.build().expect("Not able to build model");
-        send_sync(&interpreter);
+        send(&interpreter);
+    }
+
+    #[test]
+    fn unittest_shared_builder() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder =
+            InterpreterBuilder::new(&model, BuiltinOpResolver::default()).unwrap().into_shared();
+
+        let mut interpreters: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..16)
+                .map(|_| {
+                    let builder = builder.clone();
+                    s.spawn(move || builder.build_with_threads(1).unwrap())
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert_eq!(interpreters.len(), 16);
+        for interpreter in &mut interpreters {
+            interpreter.invoke().unwrap();
+        }
+        // The builder can still be used after the interpreters are dropped.
+        drop(interpreters);
+        builder.build().unwrap().invoke().unwrap();
+    }
+
+    #[test]
+    fn unittest_resize_input_tensor() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        let input = interpreter.inputs()[0];
+        let output = interpreter.outputs()[0];
+
+        interpreter.resize_input_tensor(input, &[2, 28, 28, 1]).unwrap();
+        interpreter.allocate_tensors().unwrap();
+        assert_eq!(interpreter.tensor_info(input).unwrap().dims, vec![2, 28, 28, 1]);
+        assert_eq!(interpreter.tensor_info(output).unwrap().dims, vec![2, 10]);
+        interpreter.invoke().unwrap();
+
+        assert!(interpreter.resize_input_tensor(output, &[1, 10]).is_err());
+        assert!(interpreter.resize_input_tensor(input, &[-1, 28, 28, 1]).is_err());
+    }
+
+    #[test]
+    fn unittest_num_threads() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build_with_threads(2).unwrap();
+        assert_eq!(interpreter.num_threads(), 2);
+
+        interpreter.set_num_threads(4).unwrap();
+        assert_eq!(interpreter.num_threads(), 4);
+        assert!(interpreter.set_num_threads(-2).is_err());
+        assert_eq!(interpreter.num_threads(), 4);
+        interpreter.allocate_tensors().unwrap();
+        interpreter.invoke().unwrap();
+    }
+
+    #[test]
+    fn unittest_invoke_cancellation() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+
+        interpreter.invoke_with_timeout(Duration::from_secs(60)).unwrap();
+
+        let token = CancellationToken::new();
+        interpreter.set_cancellation_token(token.clone());
+        token.cancel();
+        match interpreter.invoke() {
+            Err(Error::Cancelled) => {}
+            r => panic!("expected cancellation, got {:?}", r),
+        }
+
+        token.reset();
+        interpreter.invoke().unwrap();
+
+        // Timeouts neither cancel nor reset the token.
+        match interpreter.invoke_with_timeout(Duration::ZERO) {
+            Err(Error::Timeout(_)) => {}
+            r => panic!("expected a timeout, got {:?}", r),
+        }
+        assert!(!token.is_cancelled());
+        interpreter.invoke().unwrap();
+        token.cancel();
+        assert!(matches!(
+            interpreter.invoke_with_timeout(Duration::from_secs(60)),
+            Err(Error::Cancelled)
+        ));
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn unittest_delegate_fallback() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([] -> *mut bindings::TfLiteDelegate as "TfLiteDelegate*" {
+                auto delegate = new TfLiteDelegate(TfLiteDelegateCreate());
+                delegate->Prepare = [](TfLiteContext*, TfLiteDelegate*) { return kTfLiteError; };
+                return delegate;
+            })
+        };
+        let delegate = unsafe { Delegate::from_raw(handle, None) };
+
+        let input = interpreter.inputs()[0];
+        interpreter.tensor_data_mut::<u8>(input).unwrap()[0] = 42;
+        interpreter.set_allow_fp16_precision_for_fp32(true);
+        match interpreter.modify_graph_with_delegate(&delegate) {
+            Err(Error::DelegateFailed(DelegateFailure::Rejected)) => {}
+            r => panic!("expected a rejected delegate, got {:?}", r),
+        }
+        assert!(interpreter.delegates().is_empty());
+        assert_eq!(interpreter.tensor_data::<u8>(input).unwrap()[0], 42);
+        // The rebuilt interpreter keeps the settings of the old one.
+        assert!(interpreter.allow_fp16_precision_for_fp32());
+        interpreter.invoke().unwrap();
+    }
+
+    #[test]
+    fn unittest_while_loop() {
+        use std::ffi::CString;
+
+        use crate::model::stl::memory::UniquePtr;
+        use crate::model::stl::vector::VectorInsert;
+        use crate::model::{
+            BufferT, BuiltinOperator, BuiltinOptionsUnion, ConcatenationOptionsT, Model,
+            OperatorCodeT, OperatorT, SubGraphT, TensorT, TensorType, WhileOptionsT,
+        };
+
+        // A seq2seq-style decoding loop: `while i < 4 { i += 1; tokens = concat(tokens, i) }`,
+        // whose token output grows with every iteration.
+        let mut model = Model::default();
+        model.version = 3;
+        for &op in &[
+            BuiltinOperator::BuiltinOperator_WHILE,
+            BuiltinOperator::BuiltinOperator_LESS,
+            BuiltinOperator::BuiltinOperator_ADD,
+            BuiltinOperator::BuiltinOperator_CONCATENATION,
+        ] {
+            let mut code: UniquePtr<OperatorCodeT> = Default::default();
+            code.builtin_code = op;
+            code.version = 1;
+            model.operator_codes.push_back(code);
+        }
+        model.buffers.assign(vec![UniquePtr::<BufferT>::default(); 3]);
+        model.buffers[1].data.assign(4i32.to_le_bytes().to_vec());
+        model.buffers[2].data.assign(1i32.to_le_bytes().to_vec());
+
+        let tensor = |name: &str, typ, buffer| {
+            let mut tensor: UniquePtr<TensorT> = Default::default();
+            tensor.shape.assign(vec![1, 1]);
+            tensor.typ = typ;
+            tensor.buffer = buffer;
+            tensor.name.assign(&CString::new(name).unwrap());
+            tensor
+        };
+        let int32 = TensorType::TensorType_INT32;
+        let op = |opcode_index, inputs: Vec<i32>, outputs: Vec<i32>| {
+            let mut op: UniquePtr<OperatorT> = Default::default();
+            op.opcode_index = opcode_index;
+            op.inputs.assign(inputs);
+            op.outputs.assign(outputs);
+            op
+        };
+
+        let mut main: UniquePtr<SubGraphT> = Default::default();
+        for name in &["i", "tokens", "i_out", "tokens_out"] {
+            main.tensors.push_back(tensor(name, int32, 0));
+        }
+        let mut while_op = op(0, vec![0, 1], vec![2, 3]);
+        while_op.builtin_options = BuiltinOptionsUnion::WhileOptions();
+        let options: &mut WhileOptionsT = while_op.builtin_options.as_mut();
+        options.cond_subgraph_index = 1;
+        options.body_subgraph_index = 2;
+        main.operators.push_back(while_op);
+        main.inputs.assign(vec![0, 1]);
+        main.outputs.assign(vec![2, 3]);
+
+        let mut cond: UniquePtr<SubGraphT> = Default::default();
+        cond.tensors.push_back(tensor("i", int32, 0));
+        cond.tensors.push_back(tensor("tokens", int32, 0));
+        cond.tensors.push_back(tensor("limit", int32, 1));
+        cond.tensors.push_back(tensor("continue", TensorType::TensorType_BOOL, 0));
+        cond.operators.push_back(op(1, vec![0, 2], vec![3]));
+        cond.inputs.assign(vec![0, 1]);
+        cond.outputs.assign(vec![3]);
+
+        let mut body: UniquePtr<SubGraphT> = Default::default();
+        for (name, buffer) in &[("i", 0), ("tokens", 0), ("one", 2), ("next_i", 0), ("next", 0)] {
+            body.tensors.push_back(tensor(name, int32, *buffer));
+        }
+        body.operators.push_back(op(2, vec![0, 2], vec![3]));
+        let mut concat = op(3, vec![1, 3], vec![4]);
+        concat.builtin_options = BuiltinOptionsUnion::ConcatenationOptions();
+        let options: &mut ConcatenationOptionsT = concat.builtin_options.as_mut();
+        options.axis = 1;
+        body.operators.push_back(concat);
+        body.inputs.assign(vec![0, 1]);
+        body.outputs.assign(vec![3, 4]);
+
+        model.subgraphs.push_back(main);
+        model.subgraphs.push_back(cond);
+        model.subgraphs.push_back(body);
+
+        let model = FlatBufferModel::build_from_model(&model).unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        assert_eq!(interpreter.subgraphs_size(), 3);
+
+        for &(start, ref expected) in &[(0, vec![7, 1, 2, 3, 4]), (2, vec![7, 3, 4]), (4, vec![7])]
+        {
+            interpreter.tensor_data_mut::<i32>(0).unwrap()[0] = start;
+            interpreter.tensor_data_mut::<i32>(1).unwrap()[0] = 7;
+            interpreter.invoke().unwrap();
+            assert_eq!(interpreter.tensor_data::<i32>(2).unwrap(), &[4]);
+            assert_eq!(interpreter.tensor_info(3).unwrap().dims, vec![1, expected.len()]);
+            assert_eq!(interpreter.tensor_data::<i32>(3).unwrap(), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn unittest_tensor_by_name() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let interpreter =
+            InterpreterBuilder::new(&model, BuiltinOpResolver::default()).unwrap().build().unwrap();
+        let output = interpreter.outputs()[0];
+        let output_name = interpreter.tensor_info(output).unwrap().name;
+        assert_eq!(interpreter.tensor_index(&output_name), Some(output));
+        assert!(interpreter.tensor_by_name::<u8>("no such tensor").is_err());
+
+        let intermediate = (0..interpreter.tensors_size() as TensorIndex)
+            .find(|&index| {
+                interpreter.tensor_inner(index).unwrap().allocation_type
+                    == bindings::TfLiteAllocationType::kTfLiteArenaRw
+                    && !interpreter.inputs().contains(&index)
+                    && !interpreter.outputs().contains(&index)
+            })
+            .unwrap();
+        let name = interpreter.tensor_info(intermediate).unwrap().name;
+        assert!(interpreter.tensor_by_name::<u8>(&name).is_err());
+
+        let builder = InterpreterBuilder::new(&model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.preserve_all_tensors(true).build().unwrap();
+        let input = interpreter.inputs()[0];
+        interpreter.tensor_data_mut::<u8>(input).unwrap().iter_mut().for_each(|v| *v = 128);
+        interpreter.invoke().unwrap();
+        let features = interpreter.tensor_by_name::<u8>(&name).unwrap().to_vec();
+        assert!(!features.is_empty());
+        interpreter.invoke().unwrap();
+        assert_eq!(interpreter.tensor_by_name::<u8>(&name).unwrap(), &features[..]);
     }
 }
@@ -0,0 +1,177 @@
+//! TF Lite's NNAPI delegate, running models on the DSPs, NPUs and GPUs Android exposes
+//! through the Neural Networks API:
+//!
+//! ```ignore
+//! let options = NnapiDelegateOptions::default()
+//!     .with_accelerator_name("qti-dsp")
+//!     .with_execution_preference(NnapiExecutionPreference::SustainedSpeed);
+//! let nnapi = NnapiDelegate::new(&options)?;
+//! interpreter.modify_graph_with_delegate(&nnapi)?;
+//! ```
+//!
+//! Accelerator names are those of `ANeuralNetworksDevice_getName`, e.g. from
+//! `adb shell dumpsys android.hardware.neuralnetworks`. Naming an accelerator the device does
+//! not have fails `modify_graph_with_delegate`, leaving the model on the CPU.
+
+use std::ffi::CString;
+use std::ops::Deref;
+use std::ptr;
+
+use super::Delegate;
+use crate::bindings::TfLiteDelegate;
+use crate::{Error, Result};
+
+cpp! {{
+    #include "tensorflow/lite/delegates/nnapi/nnapi_delegate.h"
+}}
+
+/// What NNAPI compiles the model for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NnapiExecutionPreference {
+    /// NNAPI's default.
+    Undefined,
+    /// Prefers a low power consumption, e.g. for background work.
+    LowPower,
+    /// Favors a fast single answer.
+    FastSingleAnswer,
+    /// Favors throughput over repeated invocations, e.g. on camera frames.
+    SustainedSpeed,
+}
+
+impl NnapiExecutionPreference {
+    fn to_native(self) -> i32 {
+        match self {
+            NnapiExecutionPreference::Undefined => -1,
+            NnapiExecutionPreference::LowPower => 0,
+            NnapiExecutionPreference::FastSingleAnswer => 1,
+            NnapiExecutionPreference::SustainedSpeed => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NnapiDelegateOptions {
+    pub execution_preference: NnapiExecutionPreference,
+    /// Runs the model only on the named accelerator, instead of letting NNAPI choose.
+    pub accelerator_name: Option<String>,
+    /// Keeps NNAPI from falling back to its own CPU implementation, which is usually slower
+    /// than TF Lite's kernels. Needs Android 10.
+    pub disallow_nnapi_cpu: bool,
+    /// Allows fp16 arithmetic for fp32 models.
+    pub allow_fp16: bool,
+}
+
+impl Default for NnapiDelegateOptions {
+    fn default() -> Self {
+        Self {
+            execution_preference: NnapiExecutionPreference::Undefined,
+            accelerator_name: None,
+            disallow_nnapi_cpu: true,
+            allow_fp16: false,
+        }
+    }
+}
+
+impl NnapiDelegateOptions {
+    pub fn with_execution_preference(mut self, preference: NnapiExecutionPreference) -> Self {
+        self.execution_preference = preference;
+        self
+    }
+
+    pub fn with_accelerator_name(mut self, name: &str) -> Self {
+        self.accelerator_name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_allow_fp16(mut self, allow: bool) -> Self {
+        self.allow_fp16 = allow;
+        self
+    }
+}
+
+/// A `Delegate` created as a `tflite::StatefulNnApiDelegate`; apply it with
+/// `Interpreter::modify_graph_with_delegate`.
+#[derive(Clone)]
+pub struct NnapiDelegate(Delegate);
+
+unsafe extern "C" fn delete(handle: *mut TfLiteDelegate) {
+    #[allow(clippy::forget_copy, clippy::useless_transmute, deprecated)]
+    unsafe {
+        cpp!([handle as "TfLiteDelegate*"] {
+            delete static_cast<tflite::StatefulNnApiDelegate*>(handle);
+        })
+    };
+}
+
+impl NnapiDelegate {
+    pub fn new(options: &NnapiDelegateOptions) -> Result<Self> {
+        let accelerator_name = match &options.accelerator_name {
+            Some(name) => Some(CString::new(name.as_str()).map_err(|_| {
+                Error::InternalError(format!("accelerator name `{}` contains a NUL byte", name))
+            })?),
+            None => None,
+        };
+        // The delegate copies the name.
+        let accelerator_name_ptr = accelerator_name.as_ref().map_or(ptr::null(), |n| n.as_ptr());
+        let execution_preference = options.execution_preference.to_native();
+        let disallow_nnapi_cpu = options.disallow_nnapi_cpu;
+        let allow_fp16 = options.allow_fp16;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([
+                accelerator_name_ptr as "const char*",
+                execution_preference as "int",
+                disallow_nnapi_cpu as "bool",
+                allow_fp16 as "bool"
+            ] -> *mut TfLiteDelegate as "TfLiteDelegate*" {
+                tflite::StatefulNnApiDelegate::Options options;
+                options.execution_preference =
+                    static_cast<tflite::StatefulNnApiDelegate::Options::ExecutionPreference>(
+                        execution_preference);
+                options.accelerator_name = accelerator_name_ptr;
+                options.disallow_nnapi_cpu = disallow_nnapi_cpu;
+                options.allow_fp16 = allow_fp16;
+                return new tflite::StatefulNnApiDelegate(options);
+            })
+        };
+        if handle.is_null() {
+            return Err(Error::internal_error("failed to create NNAPI delegate"));
+        }
+        Ok(NnapiDelegate(unsafe { Delegate::from_raw(handle, Some(delete)) }))
+    }
+}
+
+impl Deref for NnapiDelegate {
+    type Target = Delegate;
+
+    fn deref(&self) -> &Delegate {
+        &self.0
+    }
+}
+
+impl From<NnapiDelegate> for Delegate {
+    fn from(delegate: NnapiDelegate) -> Self {
+        delegate.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_nnapi_options() {
+        let options = NnapiDelegateOptions::default();
+        assert!(options.accelerator_name.is_none() && options.disallow_nnapi_cpu);
+        assert_eq!(options.execution_preference.to_native(), -1);
+
+        let options = options
+            .with_accelerator_name("qti-dsp")
+            .with_execution_preference(NnapiExecutionPreference::SustainedSpeed)
+            .with_allow_fp16(true);
+        assert_eq!(options.accelerator_name.as_deref(), Some("qti-dsp"));
+        assert_eq!(options.execution_preference.to_native(), 2);
+        assert!(options.allow_fp16);
+    }
+}
@@ -0,0 +1,155 @@
+use std::ffi::CStr;
+use std::fmt;
+
+use super::hooks::OpContext;
+use super::TensorIndex;
+
+/// The first node of an invocation that produced NaN or infinite values, reported by
+/// `Error::NonFinite` when numeric checks are enabled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumericIssue {
+    pub node: usize,
+    pub subgraph: usize,
+    pub op_name: String,
+    /// The first output holding non-finite values.
+    pub tensor: TensorIndex,
+    pub tensor_name: String,
+    pub nan_count: usize,
+    pub inf_count: usize,
+}
+
+impl fmt::Display for NumericIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "node {} ({}) of subgraph {} produced {} NaN and {} infinite values in tensor {} `{}`",
+            self.node,
+            self.op_name,
+            self.subgraph,
+            self.nan_count,
+            self.inf_count,
+            self.tensor,
+            self.tensor_name
+        )
+    }
+}
+
+/// Number of NaN and infinite values in `values`.
+fn count_non_finite(values: &[f32]) -> (usize, usize) {
+    values
+        .iter()
+        .fold((0, 0), |(nan, inf), v| (nan + v.is_nan() as usize, inf + v.is_infinite() as usize))
+}
+
+/// Scans the float outputs of a node that has just run.
+pub(crate) fn check_outputs(op: &OpContext<'_>) -> Option<NumericIssue> {
+    op.outputs().iter().find_map(|&tensor| {
+        let (nan_count, inf_count) = count_non_finite(op.tensor_data::<f32>(tensor)?);
+        if nan_count + inf_count == 0 {
+            return None;
+        }
+        let name = op.tensor(tensor)?.name;
+        let tensor_name = if name.is_null() {
+            String::new()
+        } else {
+            unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+        };
+        Some(NumericIssue {
+            node: op.node,
+            subgraph: op.subgraph,
+            op_name: op.op_name.to_string(),
+            tensor,
+            tensor_name,
+            nan_count,
+            inf_count,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_count_non_finite() {
+        let values = [1.0, f32::NAN, f32::INFINITY, -f32::INFINITY, f32::MAX, f32::NAN];
+        assert_eq!(count_non_finite(&values), (2, 2));
+        assert_eq!(count_non_finite(&[0.0, -1.5]), (0, 0));
+
+        let issue = NumericIssue {
+            node: 3,
+            subgraph: 0,
+            op_name: "LOG".to_string(),
+            tensor: 7,
+            tensor_name: "log/out".to_string(),
+            nan_count: 2,
+            inf_count: 1,
+        };
+        assert_eq!(
+            issue.to_string(),
+            "node 3 (LOG) of subgraph 0 produced 2 NaN and 1 infinite values in tensor 7 `log/out`"
+        );
+    }
+
+    #[test]
+    fn unittest_numeric_checks() {
+        use std::ffi::CString;
+
+        use crate::model::stl::memory::UniquePtr;
+        use crate::model::stl::vector::VectorInsert;
+        use crate::model::{
+            BufferT, BuiltinOperator, Model, OperatorCodeT, OperatorT, SubGraphT, TensorT,
+            TensorType,
+        };
+        use crate::ops::builtin::BuiltinOpResolver;
+        use crate::{Error, FlatBufferModel, InterpreterBuilder};
+
+        let mut model = Model::default();
+        model.version = 3;
+        let mut log: UniquePtr<OperatorCodeT> = Default::default();
+        log.builtin_code = BuiltinOperator::BuiltinOperator_LOG;
+        log.version = 1;
+        model.operator_codes.push_back(log);
+        model.buffers.assign(vec![UniquePtr::<BufferT>::default(); 1]);
+
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        for name in &["input", "log_out"] {
+            let mut tensor: UniquePtr<TensorT> = Default::default();
+            tensor.shape.assign(vec![3]);
+            tensor.typ = TensorType::TensorType_FLOAT32;
+            tensor.name.assign(&CString::new(*name).unwrap());
+            subgraph.tensors.push_back(tensor);
+        }
+        let mut op: UniquePtr<OperatorT> = Default::default();
+        op.inputs.assign(vec![0]);
+        op.outputs.assign(vec![1]);
+        subgraph.operators.push_back(op);
+        subgraph.inputs.assign(vec![0]);
+        subgraph.outputs.assign(vec![1]);
+        model.subgraphs.push_back(subgraph);
+
+        let model = FlatBufferModel::build_from_model(&model).unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        interpreter.enable_numeric_checks();
+
+        interpreter.tensor_data_mut(0).unwrap().copy_from_slice(&[1.0f32, 2.0, 3.0]);
+        interpreter.invoke().unwrap();
+
+        interpreter.tensor_data_mut(0).unwrap().copy_from_slice(&[-1.0f32, 0.0, 3.0]);
+        match interpreter.invoke() {
+            Err(Error::NonFinite(issue)) => {
+                assert_eq!(
+                    (issue.node, issue.tensor, issue.nan_count, issue.inf_count),
+                    (0, 1, 1, 1)
+                );
+                assert_eq!(issue.op_name, "LOG");
+                assert_eq!(issue.tensor_name, "log_out");
+            }
+            r => panic!("expected non-finite values, got {:?}", r),
+        }
+
+        interpreter.disable_numeric_checks();
+        interpreter.invoke().unwrap();
+    }
+}
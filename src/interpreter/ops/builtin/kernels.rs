@@ -0,0 +1,526 @@
+//! Kernels of single builtin operators, for a `MutableOpResolver` registering only the
+//! operators a model uses. Each function references only its kernel, so with a static
+//! TensorFlow Lite library the linker drops the kernels no function is called for.
+
+use crate::bindings::TfLiteRegistration;
+use crate::model::BuiltinOperator;
+
+cpp! {{
+    #include "tensorflow/lite/kernels/builtin_op_kernels.h"
+
+    using namespace tflite::ops::builtin;
+}}
+
+/// The kernel of a builtin operator.
+#[derive(Clone, Copy, Debug)]
+pub struct BuiltinKernel {
+    pub op: BuiltinOperator,
+    pub registration: &'static TfLiteRegistration,
+}
+
+pub fn add() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_ADD();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_ADD,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn arg_max() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_ARG_MAX();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_ARG_MAX,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn average_pool_2d() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_AVERAGE_POOL_2D();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_AVERAGE_POOL_2D,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn concatenation() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_CONCATENATION();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_CONCATENATION,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn conv_2d() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_CONV_2D();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_CONV_2D,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn depthwise_conv_2d() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_DEPTHWISE_CONV_2D();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_DEPTHWISE_CONV_2D,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn dequantize() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_DEQUANTIZE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_DEQUANTIZE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn div() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_DIV();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_DIV,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn exp() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_EXP();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_EXP,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn fully_connected() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_FULLY_CONNECTED();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_FULLY_CONNECTED,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn gather() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_GATHER();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_GATHER,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn hard_swish() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_HARD_SWISH();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_HARD_SWISH,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn l2_normalization() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_L2_NORMALIZATION();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_L2_NORMALIZATION,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn leaky_relu() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_LEAKY_RELU();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_LEAKY_RELU,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn logistic() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_LOGISTIC();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_LOGISTIC,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn max_pool_2d() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_MAX_POOL_2D();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_MAX_POOL_2D,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn maximum() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_MAXIMUM();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_MAXIMUM,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn mean() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_MEAN();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_MEAN,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn minimum() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_MINIMUM();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_MINIMUM,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn mul() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_MUL();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_MUL,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn pack() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_PACK();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_PACK,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn pad() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_PAD();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_PAD,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn quantize() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_QUANTIZE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_QUANTIZE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn relu() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_RELU();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_RELU,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn relu6() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_RELU6();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_RELU6,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn reshape() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_RESHAPE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_RESHAPE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn resize_bilinear() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_RESIZE_BILINEAR();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_RESIZE_BILINEAR,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn resize_nearest_neighbor() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_RESIZE_NEAREST_NEIGHBOR();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_RESIZE_NEAREST_NEIGHBOR,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn shape() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_SHAPE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_SHAPE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn slice() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_SLICE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_SLICE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn softmax() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_SOFTMAX();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_SOFTMAX,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn split() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_SPLIT();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_SPLIT,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn squeeze() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_SQUEEZE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_SQUEEZE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn strided_slice() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_STRIDED_SLICE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_STRIDED_SLICE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn sub() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_SUB();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_SUB,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn tanh() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_TANH();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_TANH,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn transpose() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_TRANSPOSE();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_TRANSPOSE,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn transpose_conv() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_TRANSPOSE_CONV();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_TRANSPOSE_CONV,
+        registration: unsafe { &*registration },
+    }
+}
+
+pub fn unpack() -> BuiltinKernel {
+    #[allow(clippy::forget_copy, deprecated)]
+    let registration = unsafe {
+        cpp!([] -> *const TfLiteRegistration as "const TfLiteRegistration*" {
+            return Register_UNPACK();
+        })
+    };
+    BuiltinKernel {
+        op: BuiltinOperator::BuiltinOperator_UNPACK,
+        registration: unsafe { &*registration },
+    }
+}
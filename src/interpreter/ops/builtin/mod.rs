@@ -1,3 +1,6 @@
+pub mod kernels;
+mod mutable_resolver;
 mod resolver;
 
+pub use mutable_resolver::MutableOpResolver;
 pub use resolver::Resolver as BuiltinOpResolver;
@@ -0,0 +1,146 @@
+use std::mem;
+use std::ops::RangeInclusive;
+
+use super::kernels::BuiltinKernel;
+use super::resolver::CustomNames;
+use crate::bindings::tflite as bindings;
+use crate::bindings::TfLiteRegistration;
+use crate::interpreter::kernel::{self, CustomOp};
+use crate::interpreter::op_resolver::OpResolver;
+use crate::leak_tracking::{self, NativeObject};
+use crate::Result;
+
+cpp! {{
+    #include "tensorflow/lite/mutable_op_resolver.h"
+
+    using namespace tflite;
+}}
+
+/// A resolver knowing only the operators added to it, unlike `BuiltinOpResolver`, which
+/// links every builtin kernel into the program:
+///
+/// ```ignore
+/// let mut resolver = MutableOpResolver::new();
+/// resolver.add_builtin(kernels::conv_2d(), 1..=3);
+/// resolver.add_builtin(kernels::softmax(), 1..=2);
+/// resolver.add_custom_op::<Clip>("Clip", 1)?;
+/// ```
+///
+/// The operators and versions a model needs are in `Model::operator_codes`; building an
+/// interpreter for a model with others fails.
+pub struct MutableOpResolver {
+    handle: Box<bindings::OpResolver>,
+    custom_names: CustomNames,
+}
+
+impl Drop for MutableOpResolver {
+    #[allow(clippy::useless_transmute, clippy::forget_copy, deprecated)]
+    fn drop(&mut self) {
+        let handle = Box::into_raw(mem::take(&mut self.handle));
+        unsafe {
+            cpp!([handle as "MutableOpResolver*"] {
+                delete handle;
+            });
+        }
+        leak_tracking::destroyed(NativeObject::OpResolver);
+    }
+}
+
+impl Default for MutableOpResolver {
+    #[allow(clippy::forget_copy, deprecated)]
+    fn default() -> Self {
+        let handle = unsafe {
+            cpp!([] -> *mut bindings::OpResolver as "OpResolver*" {
+                return new MutableOpResolver();
+            })
+        };
+        let handle = unsafe { Box::from_raw(handle) };
+        leak_tracking::created(NativeObject::OpResolver);
+        Self { handle, custom_names: CustomNames::default() }
+    }
+}
+
+impl MutableOpResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `kernel` for the `versions` of its operator.
+    pub fn add_builtin(&mut self, kernel: BuiltinKernel, versions: RangeInclusive<i32>) {
+        let handle = self.handle.as_mut() as *mut bindings::OpResolver;
+        let op = kernel.op as i32;
+        let registration = kernel.registration as *const TfLiteRegistration;
+        let (min_version, max_version) = (*versions.start(), *versions.end());
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "MutableOpResolver*", op as "int",
+                  registration as "const TfLiteRegistration*",
+                  min_version as "int", max_version as "int"] {
+                handle->AddBuiltin(static_cast<tflite::BuiltinOperator>(op), registration,
+                                   min_version, max_version);
+            });
+        }
+    }
+
+    /// Adds the kernel of the custom operator `name`, replacing an earlier registration of
+    /// the same name and version. Fails for names with a NUL byte.
+    pub fn add_custom(
+        &mut self,
+        name: &str,
+        registration: &TfLiteRegistration,
+        version: i32,
+    ) -> Result<()> {
+        let name_ptr = self.custom_names.intern(name)?;
+        let handle = self.handle.as_mut() as *mut bindings::OpResolver;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "MutableOpResolver*", name_ptr as "const char*",
+                  registration as "const TfLiteRegistration*", version as "int"] {
+                handle->AddCustom(name_ptr, registration, version);
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds the custom operator `name` implemented by `T`.
+    pub fn add_custom_op<T: CustomOp>(&mut self, name: &str, version: i32) -> Result<()> {
+        self.add_custom(name, &kernel::registration::<T>(), version)
+    }
+}
+
+impl OpResolver for MutableOpResolver {
+    fn get_resolver_handle(&self) -> &bindings::OpResolver {
+        self.handle.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::kernels;
+    use super::*;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_mutable_op_resolver() {
+        let resolver = |with_softmax: bool| {
+            let mut resolver = MutableOpResolver::new();
+            resolver.add_builtin(kernels::average_pool_2d(), 1..=1);
+            resolver.add_builtin(kernels::conv_2d(), 1..=1);
+            resolver.add_builtin(kernels::depthwise_conv_2d(), 1..=1);
+            resolver.add_builtin(kernels::reshape(), 1..=1);
+            if with_softmax {
+                resolver.add_builtin(kernels::softmax(), 1..=1);
+            }
+            resolver
+        };
+        let model =
+            || FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model(), resolver(false)).unwrap();
+        assert!(builder.build().is_err());
+        let builder = InterpreterBuilder::new(model(), resolver(true)).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        interpreter.invoke().unwrap();
+    }
+}
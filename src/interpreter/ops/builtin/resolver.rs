@@ -1,7 +1,13 @@
+use std::ffi::CString;
 use std::mem;
+use std::os::raw::c_char;
 
 use crate::bindings::tflite as bindings;
+use crate::bindings::TfLiteRegistration;
+use crate::interpreter::kernel::{self, CustomOp};
 use crate::interpreter::op_resolver::OpResolver;
+use crate::leak_tracking::{self, NativeObject};
+use crate::{Error, Result};
 
 cpp! {{
     #include "tensorflow/lite/kernels/register.h"
@@ -11,6 +17,28 @@ cpp! {{
 
 pub struct Resolver {
     handle: Box<bindings::OpResolver>,
+    custom_names: CustomNames,
+}
+
+/// Names of the custom ops of a resolver, which their registrations point to.
+#[derive(Default)]
+pub(super) struct CustomNames(Vec<CString>);
+
+impl CustomNames {
+    /// `name` as a C string living as long as the resolver, stored once for all versions.
+    pub(super) fn intern(&mut self, name: &str) -> Result<*const c_char> {
+        if let Some(existing) =
+            self.0.iter().find(|existing| existing.as_bytes() == name.as_bytes())
+        {
+            return Ok(existing.as_ptr());
+        }
+        let name = CString::new(name).map_err(|_| {
+            Error::InternalError(format!("custom op name {:?} contains a NUL byte", name))
+        })?;
+        let name_ptr = name.as_ptr();
+        self.0.push(name);
+        Ok(name_ptr)
+    }
 }
 
 impl Drop for Resolver {
@@ -22,6 +50,36 @@ impl Drop for Resolver {
                 delete handle;
             });
         }
+        leak_tracking::destroyed(NativeObject::OpResolver);
+    }
+}
+
+impl Resolver {
+    /// Adds the kernel of the custom operator `name`, e.g. `tftext:WhitespaceTokenizer`,
+    /// replacing an earlier registration of the same name and version. Fails for names with a
+    /// NUL byte.
+    pub fn add_custom(
+        &mut self,
+        name: &str,
+        registration: &TfLiteRegistration,
+        version: i32,
+    ) -> Result<()> {
+        let name_ptr = self.custom_names.intern(name)?;
+        let handle = self.handle.as_mut() as *mut bindings::OpResolver;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "BuiltinOpResolver*", name_ptr as "const char*",
+                  registration as "const TfLiteRegistration*", version as "int"] {
+                handle->AddCustom(name_ptr, registration, version);
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds the custom operator `name` implemented by `T`.
+    pub fn add_custom_op<T: CustomOp>(&mut self, name: &str, version: i32) -> Result<()> {
+        self.add_custom(name, &kernel::registration::<T>(), version)
     }
 }
 
@@ -40,6 +98,29 @@ impl Default for Resolver {
             })
         };
         let handle = unsafe { Box::from_raw(handle) };
-        Self { handle }
+        leak_tracking::created(NativeObject::OpResolver);
+        #[allow(unused_mut)]
+        let mut resolver = Self { handle, custom_names: CustomNames::default() };
+        #[cfg(feature = "detection_postprocess")]
+        crate::ops::detection_postprocess::register(&mut resolver)
+            .expect("the detection postprocess op has a valid name");
+        resolver
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_custom_names() {
+        let mut names = CustomNames::default();
+        let clip = names.intern("Clip").unwrap();
+        assert_ne!(names.intern("Relu6").unwrap(), clip);
+        // Registering a name again, e.g. for another version, reuses the string.
+        assert_eq!(names.intern("Clip").unwrap(), clip);
+        assert_eq!(names.0.len(), 2);
+        assert!(names.intern("Cl\0ip").is_err());
+        assert_eq!(names.0.len(), 2);
     }
 }
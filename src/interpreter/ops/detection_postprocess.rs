@@ -0,0 +1,349 @@
+//! `TFLite_Detection_PostProcess`, the custom op ending SSD models exported by the TF Object
+//! Detection API: it decodes the box encodings against the anchors and runs fast or regular
+//! non-maximum suppression.
+//!
+//! With the `detection_postprocess` feature, `BuiltinOpResolver::default()` registers this
+//! kernel in place of the native one, so such models run even with a runtime built without it.
+
+use std::os::raw::{c_char, c_void};
+use std::panic;
+use std::{ptr, slice};
+
+use super::builtin::BuiltinOpResolver;
+use super::flexbuffers::Map;
+use crate::context::{int_array_as_slice, ElementKind};
+use crate::kernel::{
+    self, KernelContext, TfLiteContext, TfLiteNode, TfLiteRegistration, TfLiteStatus,
+};
+use crate::postprocess::{BoundingBox, DetectionPostProcess};
+use crate::{Error, Result, TensorIndex};
+
+pub const DETECTION_POSTPROCESS: &str = "TFLite_Detection_PostProcess";
+
+fn parse(options: &[u8]) -> Result<DetectionPostProcess> {
+    let map = Map::root(options).ok_or_else(|| {
+        Error::internal_error("TFLite_Detection_PostProcess options are not a FlexBuffers map")
+    })?;
+    let int = |key: &str, default: Option<i64>| {
+        map.get(key).and_then(|v| v.as_i64()).or(default).map(|v| v.max(0) as usize).ok_or_else(
+            || Error::InternalError(format!("TFLite_Detection_PostProcess has no `{}`", key)),
+        )
+    };
+    let float = |key: &str| {
+        map.get(key).and_then(|v| v.as_f64()).map(|v| v as f32).ok_or_else(|| {
+            Error::InternalError(format!("TFLite_Detection_PostProcess has no `{}`", key))
+        })
+    };
+    Ok(DetectionPostProcess {
+        max_detections: int("max_detections", None)?,
+        max_classes_per_detection: int("max_classes_per_detection", None)?,
+        detections_per_class: int("detections_per_class", Some(100))?,
+        use_regular_nms: map.get("use_regular_nms").and_then(|v| v.as_bool()).unwrap_or(false),
+        score_threshold: float("nms_score_threshold")?,
+        iou_threshold: float("nms_iou_threshold")?,
+        num_classes: int("num_classes", None)?,
+        scales: [float("y_scale")?, float("x_scale")?, float("h_scale")?, float("w_scale")?],
+    })
+}
+
+pub fn register(resolver: &mut BuiltinOpResolver) -> Result<()> {
+    resolver.add_custom(DETECTION_POSTPROCESS, &detection_postprocess(), 1)
+}
+
+pub fn detection_postprocess() -> TfLiteRegistration {
+    TfLiteRegistration {
+        init: Some(init),
+        free: Some(free),
+        prepare: Some(prepare),
+        invoke: Some(invoke),
+        profiling_string: None,
+        builtin_code: 0,
+        custom_name: ptr::null::<c_char>(),
+        version: 1,
+    }
+}
+
+unsafe extern "C" fn init(
+    _context: *mut TfLiteContext,
+    buffer: *const c_char,
+    length: usize,
+) -> *mut c_void {
+    let options =
+        if buffer.is_null() { &[][..] } else { slice::from_raw_parts(buffer as *const u8, length) };
+    let data = panic::catch_unwind(|| parse(options)).unwrap_or_else(|_| {
+        Err(Error::internal_error("failed to parse TFLite_Detection_PostProcess options"))
+    });
+    Box::into_raw(Box::new(data)) as *mut c_void
+}
+
+unsafe extern "C" fn free(_context: *mut TfLiteContext, buffer: *mut c_void) {
+    drop(Box::from_raw(buffer as *mut Result<DetectionPostProcess>));
+}
+
+unsafe fn run<F>(context: *mut TfLiteContext, node: *mut TfLiteNode, f: F) -> TfLiteStatus
+where
+    F: FnOnce(&mut KernelContext<'_>, &TfLiteNode, &DetectionPostProcess) -> Result<()>,
+{
+    let mut context = KernelContext::from_raw(context);
+    let data = &*((*node).user_data as *const Result<DetectionPostProcess>);
+    kernel::guard(&mut context, "TFLite_Detection_PostProcess", |context| match data {
+        Ok(options) => f(context, &*node, options),
+        Err(e) => Err(Error::InternalError(e.to_string())),
+    })
+}
+
+/// Inputs (box encodings, class predictions, anchors) and outputs (boxes, classes, scores,
+/// number of detections) of the node.
+fn tensors(node: &TfLiteNode) -> Result<([TensorIndex; 3], [TensorIndex; 4])> {
+    match (kernel::inputs(node), kernel::outputs(node)) {
+        (&[a, b, c], &[d, e, f, g]) => Ok(([a, b, c], [d, e, f, g])),
+        _ => Err(Error::internal_error(
+            "TFLite_Detection_PostProcess takes three inputs and four outputs",
+        )),
+    }
+}
+
+/// Values of a float32 or (u)int8 tensor, dequantized, and its dims.
+fn dequantize(context: &KernelContext<'_>, index: TensorIndex) -> Result<(Vec<f32>, Vec<i32>)> {
+    let tensor = context
+        .tensor(index)
+        .ok_or_else(|| Error::InternalError(format!("no tensor {}", index)))?;
+    let dims = unsafe { int_array_as_slice(&*tensor.dims) }.to_vec();
+    let buffer = context.buffer(index).unwrap_or_default();
+    let (scale, zero_point) = (tensor.params.scale, tensor.params.zero_point);
+    let values = match tensor.type_ {
+        ElementKind::kTfLiteFloat32 => buffer
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect(),
+        ElementKind::kTfLiteUInt8 => {
+            buffer.iter().map(|&v| (i32::from(v) - zero_point) as f32 * scale).collect()
+        }
+        ElementKind::kTfLiteInt8 => {
+            buffer.iter().map(|&v| (i32::from(v as i8) - zero_point) as f32 * scale).collect()
+        }
+        kind => {
+            return Err(Error::InternalError(format!(
+                "unsupported TFLite_Detection_PostProcess input type {:?}",
+                kind
+            )))
+        }
+    };
+    Ok((values, dims))
+}
+
+fn write(context: &mut KernelContext<'_>, index: TensorIndex, values: &[f32]) -> Result<()> {
+    let buffer = context
+        .buffer_mut(index)
+        .ok_or_else(|| Error::internal_error("output is not allocated"))?;
+    for (bytes, value) in buffer.chunks_exact_mut(4).zip(values) {
+        bytes.copy_from_slice(&value.to_ne_bytes());
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn prepare(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+    run(context, node, |context, node, options| {
+        let (_, outputs) = tensors(node)?;
+        let detections = (options.max_detections * options.max_classes_per_detection) as i32;
+        let shapes: [&[i32]; 4] = [&[1, detections, 4], &[1, detections], &[1, detections], &[1]];
+        for (&output, dims) in outputs.iter().zip(shapes.iter()) {
+            context
+                .tensor_mut(output)
+                .ok_or_else(|| Error::InternalError(format!("no output {}", output)))?
+                .type_ = ElementKind::kTfLiteFloat32;
+            context.resize_tensor(output, dims)?;
+        }
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn invoke(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+    run(context, node, |context, node, options| {
+        let ([encodings, predictions, anchors], outputs) = tensors(node)?;
+        let (encodings, encoding_dims) = dequantize(context, encodings)?;
+        let (scores, _) = dequantize(context, predictions)?;
+        let (anchors, anchor_dims) = dequantize(context, anchors)?;
+        let (boxes, stride) = match encoding_dims[..] {
+            [1, boxes, stride] if stride >= 4 => (boxes as usize, stride as usize),
+            _ => {
+                return Err(Error::InternalError(format!(
+                    "box encodings of dims {:?} are not [1, boxes, 4]",
+                    encoding_dims
+                )))
+            }
+        };
+        if anchor_dims[..] != [boxes as i32, 4] {
+            return Err(Error::InternalError(format!(
+                "anchors of dims {:?} do not match {} boxes",
+                anchor_dims, boxes
+            )));
+        }
+        let decoded: Vec<BoundingBox> = encodings
+            .chunks_exact(stride)
+            .zip(anchors.chunks_exact(4))
+            .map(|(encoding, anchor)| {
+                BoundingBox::from_center_size(encoding, anchor, options.scales)
+            })
+            .collect();
+        let detections = options.run(&decoded, &scores)?;
+
+        let size = options.max_detections * options.max_classes_per_detection;
+        let (mut boxes, mut classes, mut scores) =
+            (vec![0.0; 4 * size], vec![0.0; size], vec![0.0; size]);
+        let per_box = options.classes_per_box().max(1);
+        for (i, detection) in detections.iter().enumerate() {
+            let position = if options.use_regular_nms {
+                i
+            } else {
+                i / per_box * options.max_classes_per_detection + i % per_box
+            };
+            let bbox = &detection.bbox;
+            boxes[4 * position..4 * position + 4]
+                .copy_from_slice(&[bbox.ymin, bbox.xmin, bbox.ymax, bbox.xmax]);
+            classes[position] = detection.class as f32;
+            scores[position] = detection.score;
+        }
+        write(context, outputs[0], &boxes)?;
+        write(context, outputs[1], &classes)?;
+        write(context, outputs[2], &scores)?;
+        write(context, outputs[3], &[(detections.len() / per_box) as f32])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+    use crate::model::stl::memory::UniquePtr;
+    use crate::model::stl::vector::VectorInsert;
+    use crate::model::{
+        BufferT, BuiltinOperator, Model, OperatorCodeT, OperatorT, SubGraphT, TensorT, TensorType,
+    };
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    /// A FlexBuffers map of 4 byte floats.
+    fn flexbuffer_map(entries: &[(&str, f32)]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        let mut keys = Vec::new();
+        for (key, _) in entries {
+            keys.push(buffer.len());
+            buffer.extend_from_slice(key.as_bytes());
+            buffer.push(0);
+        }
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+        buffer.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        let key_vector = buffer.len();
+        for (i, key) in keys.iter().enumerate() {
+            buffer.extend_from_slice(&((key_vector + 4 * i - key) as u32).to_le_bytes());
+        }
+        let prefix = buffer.len() - key_vector;
+        for value in &[prefix as u32, 4, entries.len() as u32] {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        let map = buffer.len();
+        for (_, value) in entries {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer.extend(entries.iter().map(|_| 3 << 2 | 2));
+        buffer.extend_from_slice(&[(buffer.len() - map) as u8, 9 << 2 | 2, 1]);
+        buffer
+    }
+
+    #[test]
+    fn unittest_detection_postprocess_options() {
+        let mut entries = vec![
+            ("max_detections", 10.0),
+            ("max_classes_per_detection", 1.0),
+            ("nms_score_threshold", 0.5),
+            ("nms_iou_threshold", 0.6),
+            ("num_classes", 90.0),
+            ("y_scale", 10.0),
+            ("x_scale", 10.0),
+            ("h_scale", 5.0),
+            ("w_scale", 5.0),
+            ("use_regular_nms", 1.0),
+        ];
+        let options = parse(&flexbuffer_map(&entries)).unwrap();
+        assert_eq!(
+            options,
+            DetectionPostProcess {
+                score_threshold: 0.5,
+                use_regular_nms: true,
+                ..DetectionPostProcess::default()
+            }
+        );
+        entries.remove(0);
+        assert!(parse(&flexbuffer_map(&entries)).is_err());
+        assert!(parse(&[]).is_err());
+    }
+
+    #[test]
+    fn unittest_detection_postprocess_interpreter() {
+        let mut model = Model::default();
+        model.version = 3;
+        let mut code: UniquePtr<OperatorCodeT> = Default::default();
+        code.builtin_code = BuiltinOperator::BuiltinOperator_CUSTOM;
+        code.custom_code.assign(&CString::new(DETECTION_POSTPROCESS).unwrap());
+        code.version = 1;
+        model.operator_codes.push_back(code);
+        // Anchors as (ycenter, xcenter, height, width).
+        let mut anchors: UniquePtr<BufferT> = Default::default();
+        let values = [0.5f32, 0.5, 1.0, 1.0, 0.2, 0.2, 0.2, 0.2];
+        anchors.data.assign(values.iter().flat_map(|v| v.to_ne_bytes()));
+        model.buffers.assign(vec![Default::default(), anchors]);
+
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        let shapes: [&[i32]; 7] =
+            [&[1, 2, 4], &[1, 2, 3], &[2, 4], &[1, 2, 4], &[1, 2], &[1, 2], &[1]];
+        for (i, shape) in shapes.iter().enumerate() {
+            let mut tensor: UniquePtr<TensorT> = Default::default();
+            tensor.shape.assign(shape.iter().copied());
+            tensor.typ = TensorType::TensorType_FLOAT32;
+            tensor.buffer = if i == 2 { 1 } else { 0 };
+            subgraph.tensors.push_back(tensor);
+        }
+        let mut operator: UniquePtr<OperatorT> = Default::default();
+        operator.inputs.assign(vec![0, 1, 2]);
+        operator.outputs.assign(vec![3, 4, 5, 6]);
+        operator.custom_options.assign(flexbuffer_map(&[
+            ("max_detections", 2.0),
+            ("max_classes_per_detection", 1.0),
+            ("nms_score_threshold", 0.5),
+            ("nms_iou_threshold", 0.6),
+            ("num_classes", 2.0),
+            ("y_scale", 10.0),
+            ("x_scale", 10.0),
+            ("h_scale", 5.0),
+            ("w_scale", 5.0),
+        ]));
+        subgraph.operators.push_back(operator);
+        subgraph.inputs.assign(vec![0, 1]);
+        subgraph.outputs.assign(vec![3, 4, 5, 6]);
+        model.subgraphs.push_back(subgraph);
+
+        // The default resolver registers this kernel in place of the native one.
+        let model = FlatBufferModel::build_from_model(&model).unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        interpreter.allocate_tensors().unwrap();
+        // Zero encodings decode to the anchors; the first score of each box is the background.
+        interpreter.tensor_data_mut::<f32>(0).unwrap().iter_mut().for_each(|v| *v = 0.0);
+        interpreter
+            .tensor_data_mut::<f32>(1)
+            .unwrap()
+            .copy_from_slice(&[0.0, 0.9, 0.1, 0.0, 0.2, 0.8]);
+        interpreter.invoke().unwrap();
+
+        let close = |actual: &[f32], expected: &[f32]| {
+            actual.len() == expected.len()
+                && actual.iter().zip(expected).all(|(a, e)| (a - e).abs() < 1e-6)
+        };
+        let boxes = interpreter.tensor_data::<f32>(3).unwrap();
+        assert!(close(boxes, &[0.0, 0.0, 1.0, 1.0, 0.1, 0.1, 0.3, 0.3]), "{:?}", boxes);
+        assert_eq!(interpreter.tensor_data::<f32>(4).unwrap(), &[0.0, 1.0]);
+        assert!(close(interpreter.tensor_data::<f32>(5).unwrap(), &[0.9, 0.8]));
+        assert_eq!(interpreter.tensor_data::<f32>(6).unwrap(), &[2.0]);
+    }
+}
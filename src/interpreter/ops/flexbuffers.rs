@@ -0,0 +1,158 @@
+//! Reading scalar entries of the FlexBuffers maps that hold the options of custom operators.
+
+const TYPE_INT: u8 = 1;
+const TYPE_UINT: u8 = 2;
+const TYPE_FLOAT: u8 = 3;
+const TYPE_INDIRECT_INT: u8 = 6;
+const TYPE_INDIRECT_UINT: u8 = 7;
+const TYPE_INDIRECT_FLOAT: u8 = 8;
+const TYPE_MAP: u8 = 9;
+const TYPE_BOOL: u8 = 26;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Bool(bool),
+    /// Strings, vectors and other values without a scalar interpretation.
+    Other,
+}
+
+impl Value {
+    pub fn as_i64(self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(value),
+            Value::UInt(value) => Some(value as i64),
+            Value::Float(value) => Some(value as i64),
+            Value::Bool(value) => Some(value as i64),
+            Value::Other => None,
+        }
+    }
+
+    pub fn as_f64(self) -> Option<f64> {
+        match self {
+            Value::Float(value) => Some(value),
+            value => value.as_i64().map(|value| value as f64),
+        }
+    }
+
+    pub fn as_bool(self) -> Option<bool> {
+        self.as_i64().map(|value| value != 0)
+    }
+}
+
+/// A map at the root of a FlexBuffer, e.g. `custom_options` written by the converter.
+#[derive(Clone, Copy, Debug)]
+pub struct Map<'a> {
+    buffer: &'a [u8],
+    position: usize,
+    width: usize,
+    len: usize,
+    keys: usize,
+    key_width: usize,
+}
+
+fn uint(buffer: &[u8], position: usize, width: usize) -> Option<u64> {
+    let bytes = buffer.get(position..position.checked_add(width)?)?;
+    Some(bytes.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+}
+
+fn int(buffer: &[u8], position: usize, width: usize) -> Option<i64> {
+    let shift = 64 - 8 * width as u32;
+    Some(((uint(buffer, position, width)? << shift) as i64) >> shift)
+}
+
+fn float(buffer: &[u8], position: usize, width: usize) -> Option<f64> {
+    match width {
+        4 => Some(f64::from(f32::from_bits(uint(buffer, position, 4)? as u32))),
+        8 => Some(f64::from_bits(uint(buffer, position, 8)?)),
+        _ => None,
+    }
+}
+
+/// Position of the value an offset at `position` points back to.
+fn indirect(buffer: &[u8], position: usize, width: usize) -> Option<usize> {
+    position.checked_sub(uint(buffer, position, width)? as usize)
+}
+
+fn value(buffer: &[u8], position: usize, width: usize, packed_type: u8) -> Option<Value> {
+    let child_width = 1 << (packed_type & 3);
+    Some(match packed_type >> 2 {
+        TYPE_INT => Value::Int(int(buffer, position, width)?),
+        TYPE_UINT => Value::UInt(uint(buffer, position, width)?),
+        TYPE_FLOAT => Value::Float(float(buffer, position, width)?),
+        TYPE_BOOL => Value::Bool(uint(buffer, position, width)? != 0),
+        TYPE_INDIRECT_INT => {
+            Value::Int(int(buffer, indirect(buffer, position, width)?, child_width)?)
+        }
+        TYPE_INDIRECT_UINT => {
+            Value::UInt(uint(buffer, indirect(buffer, position, width)?, child_width)?)
+        }
+        TYPE_INDIRECT_FLOAT => {
+            Value::Float(float(buffer, indirect(buffer, position, width)?, child_width)?)
+        }
+        _ => Value::Other,
+    })
+}
+
+impl<'a> Map<'a> {
+    /// The root of `buffer` if it is a map.
+    pub fn root(buffer: &'a [u8]) -> Option<Self> {
+        let root_width = *buffer.last()? as usize;
+        let packed_type = *buffer.get(buffer.len().checked_sub(2)?)?;
+        if packed_type >> 2 != TYPE_MAP {
+            return None;
+        }
+        let root = buffer.len().checked_sub(2 + root_width)?;
+        let position = indirect(buffer, root, root_width)?;
+        let width = 1 << (packed_type & 3);
+        let prefix = |i: usize| uint(buffer, position.checked_sub(i * width)?, width);
+        let keys = position.checked_sub(3 * width)?.checked_sub(prefix(3)? as usize)?;
+        Some(Self {
+            buffer,
+            position,
+            width,
+            len: prefix(1)? as usize,
+            keys,
+            key_width: prefix(2)? as usize,
+        })
+    }
+
+    fn key(&self, i: usize) -> Option<&'a [u8]> {
+        let start = indirect(self.buffer, self.keys + i * self.key_width, self.key_width)?;
+        let len = self.buffer.get(start..)?.iter().position(|&byte| byte == 0)?;
+        Some(&self.buffer[start..start + len])
+    }
+
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let i = (0..self.len).find(|&i| self.key(i) == Some(key.as_bytes()))?;
+        let packed_type = *self.buffer.get(self.position + self.len * self.width + i)?;
+        value(self.buffer, self.position + i * self.width, self.width, packed_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_flexbuffers_map() {
+        // {"a": 3, "b": 1.5} with 4 byte map elements and 1 byte keys.
+        let mut buffer = b"a\0b\0".to_vec();
+        buffer.extend_from_slice(&[2, 5, 4]);
+        for prefix in &[2u32, 1, 2] {
+            buffer.extend_from_slice(&prefix.to_le_bytes());
+        }
+        buffer.extend_from_slice(&(-3i32).to_le_bytes());
+        buffer.extend_from_slice(&1.5f32.to_le_bytes());
+        buffer.extend_from_slice(&[TYPE_INT << 2, TYPE_FLOAT << 2 | 2, 10, TYPE_MAP << 2 | 2, 1]);
+
+        let map = Map::root(&buffer).unwrap();
+        assert_eq!(map.get("a"), Some(Value::Int(-3)));
+        assert_eq!(map.get("b").and_then(Value::as_f64), Some(1.5));
+        assert_eq!(map.get("b").and_then(Value::as_i64), Some(1));
+        assert_eq!(map.get("c"), None);
+        assert!(Map::root(&buffer[..30]).is_none());
+    }
+}
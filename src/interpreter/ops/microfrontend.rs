@@ -0,0 +1,701 @@
+//! The `AudioMicrofrontend` custom op of speech models such as the TensorFlow speech commands
+//! and micro speech examples: a fixed-point log-mel filterbank with noise reduction and
+//! optional per-channel energy normalization (PCAN).
+//!
+//! ```ignore
+//! let mut resolver = BuiltinOpResolver::default();
+//! ops::microfrontend::register(&mut resolver)?;
+//! let builder = InterpreterBuilder::new(model, resolver)?;
+//! ```
+//!
+//! `Frontend` computes the same features from streamed samples, e.g. for models that take
+//! spectrograms as input. It follows `tensorflow/lite/experimental/microfrontend` except for
+//! the FFT, which is computed in floating point and rounded to the 16-bit output of the
+//! fixed-point FFT, so features may differ from it by a unit.
+
+use std::f64::consts::PI;
+use std::os::raw::{c_char, c_void};
+use std::panic;
+use std::{ptr, slice};
+
+use super::builtin::BuiltinOpResolver;
+use super::flexbuffers::Map;
+use crate::context::{int_array_as_slice, to_dims, ElementKind};
+use crate::kernel::{
+    self, KernelContext, TfLiteContext, TfLiteNode, TfLiteRegistration, TfLiteStatus,
+};
+use crate::{Error, Result};
+
+pub const AUDIO_MICROFRONTEND: &str = "AudioMicrofrontend";
+
+const WINDOW_BITS: u32 = 12;
+const FILTERBANK_BITS: u32 = 12;
+const NOISE_REDUCTION_BITS: u32 = 14;
+const PCAN_SNR_BITS: u32 = 12;
+const PCAN_OUTPUT_BITS: u32 = 6;
+const WIDE_DYNAMIC_FUNCTION_BITS: u32 = 32;
+const LOG_SCALE_LOG2: u32 = 16;
+const LOG_SEGMENTS_LOG2: u32 = 7;
+const LOG_COEFF: u64 = 45426;
+
+/// `(log2(1 + x) - x) * 2^16` at `x = i / 128`, plus padding.
+#[rustfmt::skip]
+const LOG_LUT: [u16; 130] = [
+    0, 224, 442, 654, 861, 1063, 1259, 1450, 1636, 1817, 1992, 2163, 2329, 2490, 2646, 2797,
+    2944, 3087, 3224, 3358, 3487, 3611, 3732, 3848, 3960, 4068, 4172, 4272, 4368, 4460, 4549,
+    4633, 4714, 4791, 4864, 4934, 5001, 5063, 5123, 5178, 5231, 5280, 5326, 5368, 5408, 5444,
+    5477, 5507, 5533, 5557, 5578, 5595, 5610, 5622, 5631, 5637, 5640, 5641, 5638, 5633, 5626,
+    5615, 5602, 5586, 5568, 5547, 5524, 5498, 5470, 5439, 5406, 5370, 5332, 5291, 5249, 5203,
+    5156, 5106, 5054, 5000, 4944, 4885, 4825, 4762, 4697, 4630, 4561, 4490, 4416, 4341, 4264,
+    4184, 4103, 4020, 3935, 3848, 3759, 3668, 3575, 3481, 3384, 3286, 3186, 3084, 2981, 2875,
+    2768, 2659, 2549, 2437, 2323, 2207, 2090, 1971, 1851, 1729, 1605, 1480, 1353, 1224, 1094,
+    963, 830, 695, 559, 421, 282, 142, 0, 0,
+];
+
+/// Frontend parameters, with the defaults of the TensorFlow op.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrontendConfig {
+    pub window_size_ms: usize,
+    pub window_step_ms: usize,
+    pub num_channels: usize,
+    pub lower_band_limit: f32,
+    pub upper_band_limit: f32,
+    pub smoothing_bits: u32,
+    pub even_smoothing: f32,
+    pub odd_smoothing: f32,
+    pub min_signal_remaining: f32,
+    pub enable_pcan: bool,
+    pub pcan_strength: f32,
+    pub pcan_offset: f32,
+    pub gain_bits: i32,
+    pub enable_log: bool,
+    pub scale_shift: u32,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        Self {
+            window_size_ms: 25,
+            window_step_ms: 10,
+            num_channels: 32,
+            lower_band_limit: 125.0,
+            upper_band_limit: 7500.0,
+            smoothing_bits: 10,
+            even_smoothing: 0.025,
+            odd_smoothing: 0.06,
+            min_signal_remaining: 0.05,
+            enable_pcan: false,
+            pcan_strength: 0.95,
+            pcan_offset: 80.0,
+            gain_bits: 21,
+            enable_log: true,
+            scale_shift: 6,
+        }
+    }
+}
+
+/// Number of bits needed to represent `x`.
+fn most_significant_bit(x: u64) -> u32 {
+    64 - x.leading_zeros()
+}
+
+/// Rounded integer square root, saturating like the frontend's `Sqrt64`.
+fn sqrt_rounded(num: u64) -> u32 {
+    let (mut rest, mut root, mut bit) = (num, 0u64, 1u64 << 62);
+    while bit > num {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rest >= root + bit {
+            rest -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    let max = if num >> 32 == 0 { 0xFFFF } else { 0xFFFF_FFFF };
+    if rest > root && root != max {
+        root += 1;
+    }
+    root as u32
+}
+
+fn freq_to_mel(freq: f32) -> f32 {
+    (1127.0 * (f64::from(freq) / 700.0).ln_1p()) as f32
+}
+
+/// Magnitudes of the real FFT of `input` divided by its length, rounded to 16 bits like the
+/// output of the fixed-point `kiss_fftr`.
+fn fft(input: &[i16], fft_size: usize) -> Vec<(i32, i32)> {
+    let mut re: Vec<f64> = input.iter().map(|&v| f64::from(v)).collect();
+    re.resize(fft_size, 0.0);
+    let mut im = vec![0.0; fft_size];
+    let bits = fft_size.trailing_zeros();
+    for i in 0..fft_size {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if bits > 0 && i < j {
+            re.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= fft_size {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..fft_size).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+    let scale = |v: f64| (v / fft_size as f64).round().clamp(-32768.0, 32767.0) as i32;
+    (0..=fft_size / 2).map(|k| (scale(re[k]), scale(im[k]))).collect()
+}
+
+/// Triangular mel channel: the spectrum bins from `start` and their weights in Q12.
+#[derive(Clone, Debug)]
+struct Channel {
+    start: usize,
+    weights: Vec<u64>,
+    unweights: Vec<u64>,
+}
+
+/// Precomputed tables and running noise estimates of the frontend.
+#[derive(Clone, Debug)]
+pub struct Frontend {
+    config: FrontendConfig,
+    window_size: usize,
+    window_step: usize,
+    window: Vec<i64>,
+    input: Vec<i16>,
+    fft_size: usize,
+    // `num_channels + 1` channels; each accumulates the unweighted rest of the previous.
+    channels: Vec<Channel>,
+    start_index: usize,
+    end_index: usize,
+    even_smoothing: u64,
+    odd_smoothing: u64,
+    min_signal_remaining: u64,
+    estimate: Vec<u32>,
+    gain_lut: Vec<i16>,
+    snr_shift: i32,
+}
+
+impl Frontend {
+    pub fn new(config: FrontendConfig, sample_rate: usize) -> Result<Self> {
+        let window_size = config.window_size_ms * sample_rate / 1000;
+        let window_step = config.window_step_ms * sample_rate / 1000;
+        if window_size == 0 || window_step == 0 || config.num_channels == 0 {
+            return Err(Error::internal_error(
+                "window size, window step and channel count must be positive",
+            ));
+        }
+        if window_step > window_size {
+            return Err(Error::InternalError(format!(
+                "window step of {} samples exceeds the window size of {} samples",
+                window_step, window_size
+            )));
+        }
+        let arg = PI as f32 * 2.0 / window_size as f32;
+        let window = (0..window_size)
+            .map(|i| {
+                let value = 0.5 - 0.5 * f64::from(arg * (i as f32 + 0.5)).cos();
+                (value * f64::from(1 << WINDOW_BITS) + 0.5).floor() as i64
+            })
+            .collect();
+        let fft_size = window_size.next_power_of_two();
+
+        // Mel filterbank over the spectrum, excluding DC.
+        let spectrum_size = fft_size / 2 + 1;
+        let mel_low = freq_to_mel(config.lower_band_limit);
+        let mel_span = freq_to_mel(config.upper_band_limit) - mel_low;
+        let mel_spacing = mel_span / (config.num_channels + 1) as f32;
+        let centers: Vec<f32> =
+            (0..=config.num_channels).map(|i| mel_low + mel_spacing * (i + 1) as f32).collect();
+        let hz_per_bin = (0.5 * sample_rate as f64 / (spectrum_size as f64 - 1.0)) as f32;
+        let start_index =
+            (1.5 + f64::from(config.lower_band_limit) / f64::from(hz_per_bin)) as usize;
+        let mut end_index = 0;
+        let mut channels = Vec::new();
+        let mut start = start_index;
+        for (i, &center) in centers.iter().enumerate() {
+            let mut end = start;
+            while freq_to_mel(end as f32 * hz_per_bin) <= center {
+                end += 1;
+            }
+            let previous = if i == 0 { mel_low } else { centers[i - 1] };
+            let (weights, unweights) = (start..end)
+                .map(|bin| {
+                    let weight =
+                        (center - freq_to_mel(bin as f32 * hz_per_bin)) / (center - previous);
+                    let quantize =
+                        |w: f64| (w * f64::from(1 << FILTERBANK_BITS) + 0.5).floor() as i16;
+                    (quantize(f64::from(weight)) as u64, quantize(1.0 - f64::from(weight)) as u64)
+                })
+                .unzip();
+            channels.push(Channel { start, weights, unweights });
+            end_index = end_index.max(end);
+            start = end;
+        }
+        if end_index >= spectrum_size {
+            return Err(Error::InternalError(format!(
+                "upper band limit {} Hz is above the Nyquist frequency",
+                config.upper_band_limit
+            )));
+        }
+
+        let smoothing = |value: f32| (value * (1 << NOISE_REDUCTION_BITS) as f32) as u64;
+        let input_correction_bits =
+            most_significant_bit(fft_size as u64) as i32 - 1 - (FILTERBANK_BITS / 2) as i32;
+        let (gain_lut, snr_shift) = if config.enable_pcan {
+            pcan_gain_lut(&config, input_correction_bits)
+        } else {
+            (Vec::new(), 0)
+        };
+        Ok(Self {
+            even_smoothing: smoothing(config.even_smoothing),
+            odd_smoothing: smoothing(config.odd_smoothing),
+            min_signal_remaining: smoothing(config.min_signal_remaining),
+            estimate: vec![0; config.num_channels],
+            config,
+            window_size,
+            window_step,
+            window,
+            input: Vec::with_capacity(window_size),
+            fft_size,
+            channels,
+            start_index,
+            end_index,
+            gain_lut,
+            snr_shift,
+        })
+    }
+
+    pub fn config(&self) -> &FrontendConfig {
+        &self.config
+    }
+
+    /// Window length and step in samples.
+    pub fn window(&self) -> (usize, usize) {
+        (self.window_size, self.window_step)
+    }
+
+    /// Forgets buffered samples and noise estimates.
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.estimate.iter_mut().for_each(|estimate| *estimate = 0);
+    }
+
+    /// Consumes samples until a window is complete, returning the number of samples used and
+    /// the features of the window, if any.
+    pub fn process_samples(&mut self, samples: &[i16]) -> (usize, Option<Vec<u16>>) {
+        let used = samples.len().min(self.window_size - self.input.len());
+        self.input.extend_from_slice(&samples[..used]);
+        if self.input.len() < self.window_size {
+            return (used, None);
+        }
+
+        let windowed: Vec<i16> = self
+            .input
+            .iter()
+            .zip(&self.window)
+            .map(|(&sample, &coefficient)| {
+                ((i64::from(sample) * coefficient) >> WINDOW_BITS) as i16
+            })
+            .collect();
+        self.input.drain(..self.window_step);
+        let max_abs = windowed.iter().map(|v| v.unsigned_abs()).max().unwrap_or(0);
+        // `i16::MIN` has 16 significant bits and needs no shift.
+        let input_shift = 15u32.saturating_sub(most_significant_bit(u64::from(max_abs)));
+        let shifted: Vec<i16> =
+            windowed.iter().map(|&v| ((v as u16) << input_shift) as i16).collect();
+        let spectrum = fft(&shifted, self.fft_size);
+
+        let mut energy = vec![0u64; spectrum.len()];
+        for (bin, &(re, im)) in
+            spectrum.iter().enumerate().take(self.end_index).skip(self.start_index)
+        {
+            let (re, im) = (u64::from(re.unsigned_abs()), u64::from(im.unsigned_abs()));
+            energy[bin] = re * re + im * im;
+        }
+        let mut work = Vec::with_capacity(self.channels.len());
+        let weigh = |weights: &[u64], bins: &[u64]| {
+            weights.iter().zip(bins).fold(0u64, |sum, (w, e)| sum.saturating_add(w * e))
+        };
+        let mut carried = 0;
+        for channel in &self.channels {
+            let bins = &energy[channel.start..];
+            work.push(weigh(&channel.weights, bins).saturating_add(carried));
+            carried = weigh(&channel.unweights, bins);
+        }
+        let mut signal: Vec<u32> =
+            work[1..].iter().map(|&w| sqrt_rounded(w) >> input_shift).collect();
+
+        self.reduce_noise(&mut signal);
+        if self.config.enable_pcan {
+            for (value, &estimate) in signal.iter_mut().zip(&self.estimate) {
+                let gain = u64::from(wide_dynamic_function(estimate, &self.gain_lut) as u32);
+                *value = pcan_shrink(((u64::from(*value) * gain) >> self.snr_shift) as u32);
+            }
+        }
+        let correction_bits =
+            most_significant_bit(self.fft_size as u64) as i32 - 1 - (FILTERBANK_BITS / 2) as i32;
+        let features =
+            signal.into_iter().map(|value| self.log_scale(value, correction_bits)).collect();
+        (used, Some(features))
+    }
+
+    fn reduce_noise(&mut self, signal: &mut [u32]) {
+        let bits = self.config.smoothing_bits;
+        for (i, (value, estimate)) in signal.iter_mut().zip(&mut self.estimate).enumerate() {
+            let smoothing = if i % 2 == 0 { self.even_smoothing } else { self.odd_smoothing };
+            let scaled_up = *value << bits;
+            let mut new_estimate = ((u64::from(scaled_up) * smoothing
+                + u64::from(*estimate) * ((1 << NOISE_REDUCTION_BITS) - smoothing))
+                >> NOISE_REDUCTION_BITS) as u32;
+            *estimate = new_estimate;
+            new_estimate = new_estimate.min(scaled_up);
+            let floor =
+                ((u64::from(*value) * self.min_signal_remaining) >> NOISE_REDUCTION_BITS) as u32;
+            *value = ((scaled_up - new_estimate) >> bits).max(floor);
+        }
+    }
+
+    fn log_scale(&self, value: u32, correction_bits: i32) -> u16 {
+        let mut value = value;
+        if self.config.enable_log {
+            value = if correction_bits < 0 {
+                value >> -correction_bits
+            } else {
+                value << correction_bits
+            };
+            value = if value > 1 { log(value, self.config.scale_shift) } else { 0 };
+        }
+        value.min(0xFFFF) as u16
+    }
+}
+
+/// Natural logarithm in Q`scale_shift`, approximated from a piecewise linear `log2`.
+fn log(x: u32, scale_shift: u32) -> u32 {
+    let integer = most_significant_bit(u64::from(x)) - 1;
+    let mut fraction = i64::from(x) - (1i64 << integer);
+    if integer < LOG_SCALE_LOG2 {
+        fraction <<= LOG_SCALE_LOG2 - integer;
+    } else {
+        fraction >>= integer - LOG_SCALE_LOG2;
+    }
+    let segment = (fraction >> (LOG_SCALE_LOG2 - LOG_SEGMENTS_LOG2)) as usize;
+    let segment_unit = (1i64 << LOG_SCALE_LOG2) >> LOG_SEGMENTS_LOG2;
+    let (c0, c1) = (i64::from(LOG_LUT[segment]), i64::from(LOG_LUT[segment + 1]));
+    let relative = ((c1 - c0) * (fraction - segment_unit * segment as i64)) >> LOG_SCALE_LOG2;
+    let log2 = ((integer << LOG_SCALE_LOG2) as i64 + fraction + c0 + relative) as u32;
+    let round = 1u64 << (LOG_SCALE_LOG2 - 1);
+    let loge = ((LOG_COEFF * u64::from(log2) + round) >> LOG_SCALE_LOG2) as u32;
+    ((u64::from(loge << scale_shift) + round) >> LOG_SCALE_LOG2) as u32
+}
+
+/// Gain lookup table of PCAN, and the shift of the gained signal.
+fn pcan_gain_lut(config: &FrontendConfig, input_correction_bits: i32) -> (Vec<i16>, i32) {
+    let input_bits = config.smoothing_bits as i32 - input_correction_bits;
+    let lookup = |x: u32| {
+        let x = x as f32 / (1u64 << input_bits) as f32;
+        let gain = (1u64 << config.gain_bits) as f32
+            * (x + config.pcan_offset).powf(-config.pcan_strength);
+        if gain > f32::from(i16::MAX) {
+            i16::MAX
+        } else {
+            (gain + 0.5) as i16
+        }
+    };
+    let mut lut = vec![0i16; 4 * WIDE_DYNAMIC_FUNCTION_BITS as usize - 3];
+    lut[0] = lookup(0);
+    lut[1] = lookup(1);
+    for interval in 2..=WIDE_DYNAMIC_FUNCTION_BITS {
+        let x0 = 1u32 << (interval - 1);
+        let x1 = x0 + (x0 >> 1);
+        let x2 = if interval == WIDE_DYNAMIC_FUNCTION_BITS { x0 + (x0 - 1) } else { 2 * x0 };
+        let (y0, y1, y2) = (lookup(x0), lookup(x1), lookup(x2));
+        let (diff1, diff2) = (i32::from(y1) - i32::from(y0), i32::from(y2) - i32::from(y0));
+        let a1 = 4 * diff1 - diff2;
+        let base = 4 * interval as usize - 6;
+        lut[base] = y0;
+        lut[base + 1] = a1 as i16;
+        lut[base + 2] = (diff2 - a1) as i16;
+    }
+    (lut, config.gain_bits - input_correction_bits - PCAN_SNR_BITS as i32)
+}
+
+fn wide_dynamic_function(x: u32, lut: &[i16]) -> i16 {
+    if x <= 2 {
+        return lut[x as usize];
+    }
+    let interval = most_significant_bit(u64::from(x));
+    let lut = &lut[4 * interval as usize - 6..];
+    let fraction = if interval < 11 { x << (11 - interval) } else { x >> (interval - 11) } & 0x3FF;
+    let fraction = fraction as i32;
+    let mut result = (i32::from(lut[2]) * fraction) >> 5;
+    result += ((lut[1] as u32) << 5) as i32;
+    result *= fraction;
+    result = (result + (1 << 14)) >> 15;
+    (result + i32::from(lut[0])) as i16
+}
+
+fn pcan_shrink(x: u32) -> u32 {
+    if x < (2 << PCAN_SNR_BITS) {
+        (x * x) >> (2 + 2 * PCAN_SNR_BITS - PCAN_OUTPUT_BITS)
+    } else {
+        (x >> (PCAN_SNR_BITS - PCAN_OUTPUT_BITS)) - (1 << PCAN_OUTPUT_BITS)
+    }
+}
+
+/// Options of the op, stored as a FlexBuffers map in the model.
+struct OpData {
+    frontend: Result<Frontend>,
+    left_context: usize,
+    right_context: usize,
+    frame_stride: usize,
+    zero_padding: bool,
+    out_float: bool,
+    out_scale: i32,
+}
+
+impl OpData {
+    fn parse(options: &[u8]) -> Self {
+        let map = Map::root(options);
+        let int = |key: &str, default: i64| {
+            map.and_then(|map| map.get(key)).and_then(|v| v.as_i64()).unwrap_or(default)
+        };
+        let boolean = |key: &str, default: bool| {
+            map.and_then(|map| map.get(key)).and_then(|v| v.as_bool()).unwrap_or(default)
+        };
+        let float = |key: &str, default: f32| {
+            map.and_then(|map| map.get(key)).and_then(|v| v.as_f64()).map_or(default, |v| v as f32)
+        };
+        let default = FrontendConfig::default();
+        let config = FrontendConfig {
+            window_size_ms: int("window_size", default.window_size_ms as i64).max(0) as usize,
+            window_step_ms: int("window_step", default.window_step_ms as i64).max(0) as usize,
+            num_channels: int("num_channels", default.num_channels as i64).max(0) as usize,
+            lower_band_limit: float("lower_band_limit", default.lower_band_limit),
+            upper_band_limit: float("upper_band_limit", default.upper_band_limit),
+            smoothing_bits: int("smoothing_bits", default.smoothing_bits.into()).clamp(0, 16)
+                as u32,
+            even_smoothing: float("even_smoothing", default.even_smoothing),
+            odd_smoothing: float("odd_smoothing", default.odd_smoothing),
+            min_signal_remaining: float("min_signal_remaining", default.min_signal_remaining),
+            enable_pcan: boolean("enable_pcan", default.enable_pcan),
+            pcan_strength: float("pcan_strength", default.pcan_strength),
+            pcan_offset: float("pcan_offset", default.pcan_offset),
+            gain_bits: int("gain_bits", default.gain_bits.into()) as i32,
+            enable_log: boolean("enable_log", default.enable_log),
+            scale_shift: int("scale_shift", default.scale_shift.into()).clamp(0, 16) as u32,
+        };
+        let frontend = if map.is_some() {
+            Frontend::new(config, int("sample_rate", 16000).max(0) as usize)
+        } else {
+            Err(Error::internal_error("AudioMicrofrontend options are not a FlexBuffers map"))
+        };
+        Self {
+            frontend,
+            left_context: int("left_context", 0).max(0) as usize,
+            right_context: int("right_context", 0).max(0) as usize,
+            frame_stride: int("frame_stride", 1).max(1) as usize,
+            zero_padding: boolean("zero_padding", false),
+            out_float: boolean("out_float", false),
+            out_scale: int("out_scale", 1).max(1) as i32,
+        }
+    }
+
+    /// Number of windows in `samples` audio samples, and the number of output rows.
+    fn frames(&self, frontend: &Frontend, samples: usize) -> (usize, usize) {
+        let (size, step) = frontend.window();
+        let frames = if samples >= size { (samples - size) / step + 1 } else { 0 };
+        (frames, frames.div_ceil(self.frame_stride))
+    }
+
+    /// Stacks each `frame_stride`th frame with its left and right context.
+    fn stack(&self, frames: &[Vec<u16>], channels: usize) -> Vec<u16> {
+        let padding = vec![0; channels];
+        let mut output = Vec::new();
+        for anchor in (0..frames.len()).step_by(self.frame_stride) {
+            let first = anchor as isize - self.left_context as isize;
+            for frame in first..=(anchor + self.right_context) as isize {
+                let feature = if frame >= 0 && (frame as usize) < frames.len() {
+                    &frames[frame as usize]
+                } else if self.zero_padding {
+                    &padding
+                } else {
+                    &frames[(frame.max(0) as usize).min(frames.len() - 1)]
+                };
+                output.extend_from_slice(feature);
+            }
+        }
+        output
+    }
+}
+
+pub fn register(resolver: &mut BuiltinOpResolver) -> Result<()> {
+    resolver.add_custom(AUDIO_MICROFRONTEND, &audio_microfrontend(), 1)
+}
+
+pub fn audio_microfrontend() -> TfLiteRegistration {
+    TfLiteRegistration {
+        init: Some(init),
+        free: Some(free),
+        prepare: Some(prepare),
+        invoke: Some(invoke),
+        profiling_string: None,
+        builtin_code: 0,
+        custom_name: ptr::null::<c_char>(),
+        version: 1,
+    }
+}
+
+unsafe extern "C" fn init(
+    _context: *mut TfLiteContext,
+    buffer: *const c_char,
+    length: usize,
+) -> *mut c_void {
+    let options =
+        if buffer.is_null() { &[][..] } else { slice::from_raw_parts(buffer as *const u8, length) };
+    let data = panic::catch_unwind(|| OpData::parse(options)).unwrap_or_else(|_| OpData {
+        frontend: Err(Error::internal_error("failed to parse AudioMicrofrontend options")),
+        ..OpData::parse(&[])
+    });
+    Box::into_raw(Box::new(data)) as *mut c_void
+}
+
+unsafe extern "C" fn free(_context: *mut TfLiteContext, buffer: *mut c_void) {
+    drop(Box::from_raw(buffer as *mut OpData));
+}
+
+unsafe fn run<F>(context: *mut TfLiteContext, node: *mut TfLiteNode, f: F) -> TfLiteStatus
+where
+    F: FnOnce(&mut KernelContext<'_>, &TfLiteNode, &OpData) -> Result<()>,
+{
+    let mut context = KernelContext::from_raw(context);
+    let data = &*((*node).user_data as *const OpData);
+    kernel::guard(&mut context, "AudioMicrofrontend", |context| f(context, &*node, data))
+}
+
+fn frontend(data: &OpData) -> Result<&Frontend> {
+    data.frontend.as_ref().map_err(|e| Error::InternalError(e.to_string()))
+}
+
+/// Input and output of the node, checking the input is 1-D `int16` audio.
+fn tensors(context: &KernelContext<'_>, node: &TfLiteNode) -> Result<(i32, i32, usize)> {
+    let (input, output) = match (kernel::inputs(node), kernel::outputs(node)) {
+        ([input], [output]) => (*input, *output),
+        _ => return Err(Error::internal_error("AudioMicrofrontend takes one input and output")),
+    };
+    let tensor = context.tensor(input).ok_or_else(|| Error::internal_error("invalid input"))?;
+    let dims = unsafe { int_array_as_slice(&*tensor.dims) };
+    match (tensor.type_, dims) {
+        (ElementKind::kTfLiteInt16, &[samples]) => Ok((input, output, samples.max(0) as usize)),
+        (kind, dims) => Err(Error::InternalError(format!(
+            "input of {:?} with dims {:?} is not 1-D int16 audio",
+            kind, dims
+        ))),
+    }
+}
+
+unsafe extern "C" fn prepare(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+    run(context, node, |context, node, data| {
+        let frontend = frontend(data)?;
+        let (_, output, samples) = tensors(context, node)?;
+        let (_, rows) = data.frames(frontend, samples);
+        let width = frontend.config().num_channels * (1 + data.left_context + data.right_context);
+        let tensor = context.tensor_mut(output).unwrap();
+        tensor.type_ =
+            if data.out_float { ElementKind::kTfLiteFloat32 } else { ElementKind::kTfLiteInt32 };
+        context.resize_tensor(output, &to_dims(&[rows, width])?)
+    })
+}
+
+unsafe extern "C" fn invoke(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+    run(context, node, |context, node, data| {
+        let mut frontend = frontend(data)?.clone();
+        let (input, output, _) = tensors(context, node)?;
+        let audio: Vec<i16> = context
+            .buffer(input)
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|bytes| i16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect();
+
+        let mut frames = Vec::new();
+        let mut samples = &audio[..];
+        while !samples.is_empty() {
+            let (used, features) = frontend.process_samples(samples);
+            samples = &samples[used..];
+            frames.extend(features);
+        }
+        let stacked = data.stack(&frames, frontend.config().num_channels);
+
+        let buffer = context
+            .buffer_mut(output)
+            .ok_or_else(|| Error::internal_error("output is not allocated"))?;
+        for (bytes, &value) in buffer.chunks_exact_mut(4).zip(&stacked) {
+            let bytes_of = if data.out_float {
+                (f32::from(value) / data.out_scale as f32).to_ne_bytes()
+            } else {
+                (i32::from(value) / data.out_scale).to_ne_bytes()
+            };
+            bytes.copy_from_slice(&bytes_of);
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_microfrontend() {
+        assert_eq!(sqrt_rounded(0), 0);
+        assert_eq!(sqrt_rounded(15), 4);
+        assert_eq!(sqrt_rounded(12), 3);
+        assert_eq!(sqrt_rounded(u64::from(u32::MAX)), 0xFFFF);
+        assert_eq!(sqrt_rounded(1 << 40), 1 << 20);
+
+        // The end-to-end test of the TensorFlow frontend.
+        let config = FrontendConfig {
+            num_channels: 2,
+            lower_band_limit: 8.0,
+            upper_band_limit: 450.0,
+            enable_pcan: true,
+            ..FrontendConfig::default()
+        };
+        let mut frontend = Frontend::new(config.clone(), 1000).unwrap();
+        let audio: Vec<i16> = (0..44).map(|i| [0, 32767, 0, -32768][i % 4]).collect();
+        let (used, features) = frontend.process_samples(&audio);
+        assert_eq!(used, 25);
+        assert_eq!(features, Some(vec![479, 425]));
+        let (used, features) = frontend.process_samples(&audio[25..]);
+        assert_eq!((used, features), (10, Some(vec![436, 378])));
+
+        let step_past_window = FrontendConfig { window_step_ms: 30, ..config.clone() };
+        assert!(Frontend::new(step_past_window, 1000).is_err());
+        let no_overlap = FrontendConfig { window_step_ms: 25, ..config };
+        let mut frontend = Frontend::new(no_overlap, 1000).unwrap();
+        // Full-scale input has nothing to shift and a full-scale spectrum.
+        let loud = vec![i16::MIN; 50];
+        let (used, features) = frontend.process_samples(&loud);
+        assert!(used == 25 && features.is_some());
+        let (used, features) = frontend.process_samples(&loud[25..]);
+        assert!(used == 25 && features.is_some());
+
+        let data = OpData::parse(&[]);
+        assert!(data.frontend.is_err());
+        let data = OpData { left_context: 1, right_context: 1, ..data };
+        let frames = vec![vec![1, 1], vec![2, 2]];
+        assert_eq!(data.stack(&frames, 2), vec![1, 1, 1, 1, 2, 2, 1, 1, 2, 2, 2, 2]);
+    }
+}
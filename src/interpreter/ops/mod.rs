@@ -1 +1,10 @@
 pub mod builtin;
+#[cfg(feature = "detection_postprocess")]
+pub mod detection_postprocess;
+mod flexbuffers;
+pub mod microfrontend;
+pub mod plugin;
+pub mod reference;
+pub mod shim;
+#[cfg(feature = "text")]
+pub mod text;
@@ -0,0 +1,334 @@
+//! Custom operators compiled as separate shared libraries and loaded at runtime, so that
+//! they can be shipped independently of the program running the models:
+//!
+//! ```ignore
+//! let mut resolver = BuiltinOpResolver::default();
+//! for plugin in load_plugins("/opt/models/ops")? {
+//!     plugin.register(&mut resolver)?;
+//! }
+//! ```
+//!
+//! A plugin exports `tflite_op_plugin`, returning a `PluginDescriptor` of its operators; see
+//! `csrc/tflite_op_plugin.h` for C and C++ plugins. Rust plugins are `cdylib`s depending on
+//! this crate:
+//!
+//! ```ignore
+//! tflite::export_op_plugin!("audio_ops", ("Stft", 1, Stft), ("MelBank", 1, MelBank));
+//! ```
+//!
+//! The registrations are used as they are by the runtime, so plugins must be built against
+//! the TF Lite version of the program. Plugins are never unloaded, as interpreters may still
+//! run their kernels.
+
+use std::ffi::{CStr, OsStr};
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use super::builtin::{BuiltinOpResolver, MutableOpResolver};
+use crate::kernel::TfLiteRegistration;
+use crate::{Error, Result};
+
+/// Version of the layout of `PluginDescriptor` and `PluginOp`.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// The symbol a plugin exports, `const PluginDescriptor* tflite_op_plugin(void)`.
+pub const PLUGIN_ENTRY_POINT: &str = "tflite_op_plugin";
+
+/// A custom operator of a plugin.
+#[repr(C)]
+pub struct PluginOp {
+    pub name: *const c_char,
+    pub version: c_int,
+    pub registration: *const TfLiteRegistration,
+}
+
+/// The operators of a plugin, which must stay valid while the plugin is loaded.
+#[repr(C)]
+pub struct PluginDescriptor {
+    /// `PLUGIN_ABI_VERSION` of the plugin.
+    pub abi_version: u32,
+    /// `sizeof(TfLiteRegistration)` in the TF Lite headers of the plugin.
+    pub registration_size: usize,
+    pub name: *const c_char,
+    pub ops: *const PluginOp,
+    pub num_ops: usize,
+}
+
+/// Resolvers custom operators can be added to.
+pub trait CustomOpRegistry {
+    fn add_custom(
+        &mut self,
+        name: &str,
+        registration: &TfLiteRegistration,
+        version: i32,
+    ) -> Result<()>;
+}
+
+impl CustomOpRegistry for BuiltinOpResolver {
+    fn add_custom(
+        &mut self,
+        name: &str,
+        registration: &TfLiteRegistration,
+        version: i32,
+    ) -> Result<()> {
+        BuiltinOpResolver::add_custom(self, name, registration, version)
+    }
+}
+
+impl CustomOpRegistry for MutableOpResolver {
+    fn add_custom(
+        &mut self,
+        name: &str,
+        registration: &TfLiteRegistration,
+        version: i32,
+    ) -> Result<()> {
+        MutableOpResolver::add_custom(self, name, registration, version)
+    }
+}
+
+/// A loaded plugin.
+#[derive(Debug)]
+pub struct Plugin {
+    path: PathBuf,
+    name: String,
+    ops: Vec<(String, i32, TfLiteRegistration)>,
+}
+
+// The registrations only point to code and strings of the plugin, which is never unloaded.
+unsafe impl Send for Plugin {}
+unsafe impl Sync for Plugin {}
+
+impl Plugin {
+    /// Loads the plugin library at `path`.
+    #[cfg(unix)]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        use std::ffi::CString;
+
+        use crate::interpreter::delegate::dl;
+
+        type Entry = unsafe extern "C" fn() -> *const PluginDescriptor;
+
+        let path = path.as_ref();
+        let handle = dl::open(path, "op plugin")?;
+        let entry = CString::new(PLUGIN_ENTRY_POINT).expect("no NUL byte in the entry point");
+        let entry = dl::symbol(handle, &entry, "op plugin")?;
+        let entry = unsafe { mem::transmute::<*mut libc::c_void, Entry>(entry) };
+        let descriptor = unsafe { entry() };
+        if descriptor.is_null() {
+            return Err(Error::InternalError(format!(
+                "op plugin {} returned no descriptor",
+                path.display()
+            )));
+        }
+        unsafe { Self::from_descriptor(path, &*descriptor) }
+    }
+
+    /// Reads the operators of `descriptor`, checking that it matches this crate's ABI.
+    ///
+    /// # Safety
+    /// The pointers of `descriptor` must be valid, and stay valid for the rest of the program.
+    pub unsafe fn from_descriptor<P: AsRef<Path>>(
+        path: P,
+        descriptor: &PluginDescriptor,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let invalid = |what: String| {
+            Error::InternalError(format!("invalid op plugin {}: {}", path.display(), what))
+        };
+        if descriptor.abi_version != PLUGIN_ABI_VERSION {
+            return Err(invalid(format!(
+                "ABI version {}, expected {}",
+                descriptor.abi_version, PLUGIN_ABI_VERSION
+            )));
+        }
+        if descriptor.registration_size != mem::size_of::<TfLiteRegistration>() {
+            return Err(invalid(format!(
+                "built against another TF Lite version, TfLiteRegistration has {} bytes instead \
+                 of {}",
+                descriptor.registration_size,
+                mem::size_of::<TfLiteRegistration>()
+            )));
+        }
+        let string = |ptr: *const c_char, what: &str| {
+            if ptr.is_null() {
+                return Err(invalid(format!("missing {}", what)));
+            }
+            CStr::from_ptr(ptr)
+                .to_str()
+                .map(str::to_string)
+                .map_err(|_| invalid(format!("{} is not UTF-8", what)))
+        };
+        let name = string(descriptor.name, "name")?;
+        if descriptor.num_ops > 0 && descriptor.ops.is_null() {
+            return Err(invalid("missing ops".to_string()));
+        }
+        let ops = if descriptor.num_ops == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(descriptor.ops, descriptor.num_ops)
+        };
+        let ops = ops
+            .iter()
+            .map(|op| {
+                let op_name = string(op.name, "op name")?;
+                if op.registration.is_null() || op.version < 1 {
+                    return Err(invalid(format!("op `{}` v{}", op_name, op.version)));
+                }
+                Ok((op_name, op.version, *op.registration))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { path, name, ops })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names and versions of the operators.
+    pub fn ops(&self) -> impl Iterator<Item = (&str, i32)> {
+        self.ops.iter().map(|(name, version, _)| (name.as_str(), *version))
+    }
+
+    /// Adds the operators to `resolver`.
+    pub fn register<R: CustomOpRegistry>(&self, resolver: &mut R) -> Result<()> {
+        for (name, version, registration) in &self.ops {
+            resolver.add_custom(name, registration, *version)?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads the plugins of `dir`, the files with the shared library extension of the target, in
+/// name order. Fails if two plugins provide the same operator version.
+#[cfg(unix)]
+pub fn load_plugins<P: AsRef<Path>>(dir: P) -> Result<Vec<Plugin>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir.as_ref())? {
+        let path = entry?.path();
+        if path.is_file() && path.extension() == Some(OsStr::new(std::env::consts::DLL_EXTENSION)) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let plugins = paths.iter().map(Plugin::load).collect::<Result<Vec<_>>>()?;
+    check_unique_ops(&plugins)?;
+    Ok(plugins)
+}
+
+fn check_unique_ops(plugins: &[Plugin]) -> Result<()> {
+    for (i, plugin) in plugins.iter().enumerate() {
+        for (name, version) in plugin.ops() {
+            if let Some(other) =
+                plugins[..i].iter().find(|other| other.ops().any(|op| op == (name, version)))
+            {
+                return Err(Error::InternalError(format!(
+                    "op `{}` v{} is provided by both {} and {}",
+                    name,
+                    version,
+                    other.path.display(),
+                    plugin.path.display()
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Builds a `PluginDescriptor` of `ops`, names, versions and registrations, that lives for
+/// the rest of the program. Used by `export_op_plugin!`.
+pub fn leak_descriptor(
+    name: &str,
+    ops: Vec<(&str, i32, TfLiteRegistration)>,
+) -> &'static PluginDescriptor {
+    let leak_str = |s: &str| {
+        let s = std::ffi::CString::new(s).expect("plugin and op names contain no NUL byte");
+        Box::leak(s.into_boxed_c_str()).as_ptr()
+    };
+    let ops: Vec<PluginOp> = ops
+        .into_iter()
+        .map(|(op_name, version, registration)| PluginOp {
+            name: leak_str(op_name),
+            version,
+            registration: Box::leak(Box::new(registration)),
+        })
+        .collect();
+    let ops = Box::leak(ops.into_boxed_slice());
+    Box::leak(Box::new(PluginDescriptor {
+        abi_version: PLUGIN_ABI_VERSION,
+        registration_size: mem::size_of::<TfLiteRegistration>(),
+        name: leak_str(name),
+        ops: ops.as_ptr(),
+        num_ops: ops.len(),
+    }))
+}
+
+/// Exports `tflite_op_plugin` from a `cdylib`, describing custom operators implemented with
+/// `CustomOp`, as `(name, version, type)`:
+///
+/// ```ignore
+/// tflite::export_op_plugin!("audio_ops", ("Stft", 1, Stft), ("MelBank", 1, MelBank));
+/// ```
+#[macro_export]
+macro_rules! export_op_plugin {
+    ($name:expr, $(($op:expr, $version:expr, $kernel:ty)),+ $(,)?) => {
+        #[no_mangle]
+        pub extern "C" fn tflite_op_plugin() -> *const $crate::ops::plugin::PluginDescriptor {
+            use std::sync::atomic::{AtomicPtr, Ordering};
+            use std::sync::Once;
+
+            static INIT: Once = Once::new();
+            static DESCRIPTOR: AtomicPtr<$crate::ops::plugin::PluginDescriptor> =
+                AtomicPtr::new(std::ptr::null_mut());
+            INIT.call_once(|| {
+                let descriptor = $crate::ops::plugin::leak_descriptor(
+                    $name,
+                    vec![$(($op, $version, $crate::kernel::registration::<$kernel>())),+],
+                );
+                DESCRIPTOR.store(descriptor as *const _ as *mut _, Ordering::SeqCst);
+            });
+            DESCRIPTOR.load(Ordering::SeqCst)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::{CustomOp, KernelContext, TfLiteNode};
+
+    struct Identity;
+
+    impl CustomOp for Identity {
+        fn init(_options: &[u8]) -> Result<Self> {
+            Ok(Identity)
+        }
+
+        fn eval(&mut self, _context: &mut KernelContext<'_>, _node: &TfLiteNode) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unittest_op_plugins() {
+        let registration = crate::kernel::registration::<Identity>();
+        let descriptor = leak_descriptor("test_ops", vec![("Identity", 1, registration)]);
+        let plugin = unsafe { Plugin::from_descriptor("libtest_ops.so", descriptor) }.unwrap();
+        assert_eq!(plugin.name(), "test_ops");
+        assert_eq!(plugin.ops().collect::<Vec<_>>(), vec![("Identity", 1)]);
+
+        let other = leak_descriptor("other_ops", vec![("Identity", 1, registration)]);
+        let other = unsafe { Plugin::from_descriptor("libother_ops.so", other) }.unwrap();
+        let err = check_unique_ops(&[plugin, other]).unwrap_err();
+        assert!(err.to_string().contains("provided by both libtest_ops.so and libother_ops.so"));
+
+        let stale = PluginDescriptor { abi_version: 0, ..*leak_descriptor("stale", Vec::new()) };
+        let err = unsafe { Plugin::from_descriptor("libstale.so", &stale) }.unwrap_err();
+        assert!(err.to_string().contains("ABI version 0"));
+    }
+}
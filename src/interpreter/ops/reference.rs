@@ -0,0 +1,369 @@
+//! Straightforward float implementations of common builtin operators.
+//!
+//! They follow the semantics of the TFLite reference kernels (NHWC layout, `OHWI` conv
+//! filters, `1HWC` depthwise filters) without any optimization, and are meant for validating
+//! kernels and delegates, e.g. on a new target:
+//!
+//! ```ignore
+//! let (expected, _) = reference::conv_2d(input, [1, 32, 32, 3], filter, [8, 3, 3, 3], Some(bias), &params);
+//! let actual = interpreter.tensor_data::<f32>(output)?;
+//! assert!(reference::max_abs_diff(&expected, actual).unwrap() < 1e-4);
+//! ```
+
+/// Shape of an NHWC tensor or an OHWI filter.
+pub type Shape4 = [usize; 4];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    Same,
+    Valid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activation {
+    None,
+    Relu,
+    ReluN1To1,
+    Relu6,
+}
+
+impl Activation {
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            Activation::None => x,
+            Activation::Relu => x.max(0.0),
+            Activation::ReluN1To1 => x.clamp(-1.0, 1.0),
+            Activation::Relu6 => x.clamp(0.0, 6.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConvParams {
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+    pub padding: Padding,
+    pub activation: Activation,
+}
+
+impl Default for ConvParams {
+    fn default() -> Self {
+        Self {
+            stride: (1, 1),
+            dilation: (1, 1),
+            padding: Padding::Same,
+            activation: Activation::None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolParams {
+    pub filter: (usize, usize),
+    pub stride: (usize, usize),
+    pub padding: Padding,
+    pub activation: Activation,
+}
+
+/// Output size and leading padding along one spatial dimension, as computed by TFLite.
+fn output_size(
+    input: usize,
+    filter: usize,
+    stride: usize,
+    dilation: usize,
+    padding: Padding,
+) -> (usize, usize) {
+    let effective = (filter - 1) * dilation + 1;
+    let output = match padding {
+        Padding::Same => input.div_ceil(stride),
+        Padding::Valid => (input + stride).saturating_sub(effective) / stride,
+    };
+    let total = ((output.max(1) - 1) * stride + effective).saturating_sub(input);
+    (output, total / 2)
+}
+
+fn index(shape: &Shape4, a: usize, b: usize, c: usize, d: usize) -> usize {
+    ((a * shape[1] + b) * shape[2] + c) * shape[3] + d
+}
+
+/// Iterates the input positions under a filter tap, skipping padding.
+fn tap(
+    out: usize,
+    k: usize,
+    stride: usize,
+    dilation: usize,
+    pad: usize,
+    size: usize,
+) -> Option<usize> {
+    let position = (out * stride + k * dilation).checked_sub(pad)?;
+    if position < size {
+        Some(position)
+    } else {
+        None
+    }
+}
+
+/// 2D convolution of an NHWC `input` with an OHWI `filter`.
+pub fn conv_2d(
+    input: &[f32],
+    input_shape: Shape4,
+    filter: &[f32],
+    filter_shape: Shape4,
+    bias: Option<&[f32]>,
+    params: &ConvParams,
+) -> (Vec<f32>, Shape4) {
+    let [batches, in_h, in_w, in_c] = input_shape;
+    let [out_c, f_h, f_w, f_c] = filter_shape;
+    assert_eq!(in_c, f_c, "input and filter depths differ");
+    let (out_h, pad_h) = output_size(in_h, f_h, params.stride.0, params.dilation.0, params.padding);
+    let (out_w, pad_w) = output_size(in_w, f_w, params.stride.1, params.dilation.1, params.padding);
+    let output_shape = [batches, out_h, out_w, out_c];
+
+    let mut output = vec![0.0; output_shape.iter().product()];
+    for b in 0..batches {
+        for y in 0..out_h {
+            for x in 0..out_w {
+                for o in 0..out_c {
+                    let mut sum = bias.map_or(0.0, |bias| bias[o]);
+                    for ky in 0..f_h {
+                        let iy = match tap(y, ky, params.stride.0, params.dilation.0, pad_h, in_h) {
+                            Some(iy) => iy,
+                            None => continue,
+                        };
+                        for kx in 0..f_w {
+                            let ix =
+                                match tap(x, kx, params.stride.1, params.dilation.1, pad_w, in_w) {
+                                    Some(ix) => ix,
+                                    None => continue,
+                                };
+                            for c in 0..in_c {
+                                sum += input[index(&input_shape, b, iy, ix, c)]
+                                    * filter[index(&filter_shape, o, ky, kx, c)];
+                            }
+                        }
+                    }
+                    output[index(&output_shape, b, y, x, o)] = params.activation.apply(sum);
+                }
+            }
+        }
+    }
+    (output, output_shape)
+}
+
+/// Depthwise 2D convolution of an NHWC `input` with a `1HWC` filter holding
+/// `depth_multiplier` output channels per input channel.
+pub fn depthwise_conv_2d(
+    input: &[f32],
+    input_shape: Shape4,
+    filter: &[f32],
+    filter_shape: Shape4,
+    bias: Option<&[f32]>,
+    depth_multiplier: usize,
+    params: &ConvParams,
+) -> (Vec<f32>, Shape4) {
+    let [batches, in_h, in_w, in_c] = input_shape;
+    let [_, f_h, f_w, out_c] = filter_shape;
+    assert_eq!(in_c * depth_multiplier, out_c, "filter depth must be input depth * multiplier");
+    let (out_h, pad_h) = output_size(in_h, f_h, params.stride.0, params.dilation.0, params.padding);
+    let (out_w, pad_w) = output_size(in_w, f_w, params.stride.1, params.dilation.1, params.padding);
+    let output_shape = [batches, out_h, out_w, out_c];
+
+    let mut output = vec![0.0; output_shape.iter().product()];
+    for b in 0..batches {
+        for y in 0..out_h {
+            for x in 0..out_w {
+                for o in 0..out_c {
+                    let c = o / depth_multiplier;
+                    let mut sum = bias.map_or(0.0, |bias| bias[o]);
+                    for ky in 0..f_h {
+                        let iy = match tap(y, ky, params.stride.0, params.dilation.0, pad_h, in_h) {
+                            Some(iy) => iy,
+                            None => continue,
+                        };
+                        for kx in 0..f_w {
+                            let ix =
+                                match tap(x, kx, params.stride.1, params.dilation.1, pad_w, in_w) {
+                                    Some(ix) => ix,
+                                    None => continue,
+                                };
+                            sum += input[index(&input_shape, b, iy, ix, c)]
+                                * filter[index(&filter_shape, 0, ky, kx, o)];
+                        }
+                    }
+                    output[index(&output_shape, b, y, x, o)] = params.activation.apply(sum);
+                }
+            }
+        }
+    }
+    (output, output_shape)
+}
+
+/// Fully connected layer with `weights` of shape `[outputs, depth]`. The input is flattened
+/// into batches of `depth` values.
+pub fn fully_connected(
+    input: &[f32],
+    weights: &[f32],
+    outputs: usize,
+    bias: Option<&[f32]>,
+    activation: Activation,
+) -> Vec<f32> {
+    assert!(outputs > 0 && !weights.is_empty(), "invalid weights shape");
+    assert_eq!(weights.len() % outputs, 0, "invalid weights shape");
+    let depth = weights.len() / outputs;
+    assert_eq!(input.len() % depth, 0, "input size is not a multiple of the weights depth");
+    input
+        .chunks_exact(depth)
+        .flat_map(|row| {
+            weights.chunks_exact(depth).enumerate().map(move |(o, w)| {
+                let sum: f32 = row.iter().zip(w).map(|(x, w)| x * w).sum();
+                activation.apply(sum + bias.map_or(0.0, |bias| bias[o]))
+            })
+        })
+        .collect()
+}
+
+/// Softmax over the last dimension, of size `depth`.
+pub fn softmax(input: &[f32], depth: usize, beta: f32) -> Vec<f32> {
+    assert!(depth > 0, "depth must be positive");
+    assert_eq!(input.len() % depth, 0, "input size is not a multiple of depth");
+    input
+        .chunks_exact(depth)
+        .flat_map(|row| {
+            let max = row.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let exp: Vec<f32> = row.iter().map(|x| ((x - max) * beta).exp()).collect();
+            let sum: f32 = exp.iter().sum();
+            exp.into_iter().map(move |e| e / sum)
+        })
+        .collect()
+}
+
+fn pool(
+    input: &[f32],
+    input_shape: Shape4,
+    params: &PoolParams,
+    reduce: impl Fn(&mut dyn Iterator<Item = f32>) -> f32,
+) -> (Vec<f32>, Shape4) {
+    let [batches, in_h, in_w, channels] = input_shape;
+    let (out_h, pad_h) = output_size(in_h, params.filter.0, params.stride.0, 1, params.padding);
+    let (out_w, pad_w) = output_size(in_w, params.filter.1, params.stride.1, 1, params.padding);
+    let output_shape = [batches, out_h, out_w, channels];
+
+    let mut output = vec![0.0; output_shape.iter().product()];
+    for b in 0..batches {
+        for y in 0..out_h {
+            for x in 0..out_w {
+                for c in 0..channels {
+                    let mut values = (0..params.filter.0)
+                        .filter_map(|ky| tap(y, ky, params.stride.0, 1, pad_h, in_h))
+                        .flat_map(|iy| {
+                            (0..params.filter.1)
+                                .filter_map(move |kx| tap(x, kx, params.stride.1, 1, pad_w, in_w))
+                                .map(move |ix| input[index(&input_shape, b, iy, ix, c)])
+                        });
+                    let value = reduce(&mut values);
+                    output[index(&output_shape, b, y, x, c)] = params.activation.apply(value);
+                }
+            }
+        }
+    }
+    (output, output_shape)
+}
+
+/// Max pooling; padded positions are ignored.
+pub fn max_pool_2d(input: &[f32], input_shape: Shape4, params: &PoolParams) -> (Vec<f32>, Shape4) {
+    pool(input, input_shape, params, |values: &mut dyn Iterator<Item = f32>| {
+        values.fold(f32::NEG_INFINITY, f32::max)
+    })
+}
+
+/// Average pooling; like TFLite, padded positions are not counted.
+pub fn average_pool_2d(
+    input: &[f32],
+    input_shape: Shape4,
+    params: &PoolParams,
+) -> (Vec<f32>, Shape4) {
+    pool(input, input_shape, params, |values: &mut dyn Iterator<Item = f32>| {
+        let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+        if count == 0 {
+            0.0
+        } else {
+            sum / count as f32
+        }
+    })
+}
+
+/// The largest absolute difference between `expected` and `actual`, or `None` if their
+/// lengths differ. NaN in either operand yields NaN.
+pub fn max_abs_diff(expected: &[f32], actual: &[f32]) -> Option<f32> {
+    if expected.len() != actual.len() {
+        return None;
+    }
+    Some(expected.iter().zip(actual).fold(0.0, |max, (e, a)| {
+        let diff = (e - a).abs();
+        if diff.is_nan() || max < diff {
+            diff
+        } else {
+            max
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_reference_ops() {
+        // 3x3 input, 2x2 filter of ones: each output is the sum of a 2x2 window.
+        let input: Vec<f32> = (1..=9).map(|x| x as f32).collect();
+        let params = ConvParams { padding: Padding::Valid, ..Default::default() };
+        let (output, shape) =
+            conv_2d(&input, [1, 3, 3, 1], &[1.0; 4], [1, 2, 2, 1], Some(&[0.5]), &params);
+        assert_eq!(shape, [1, 2, 2, 1]);
+        assert_eq!(output, vec![12.5, 16.5, 24.5, 28.5]);
+
+        // SAME padding with stride 2 pads at the end only.
+        let params = ConvParams { stride: (2, 2), ..Default::default() };
+        let (output, shape) = conv_2d(&input, [1, 3, 3, 1], &[1.0; 4], [1, 2, 2, 1], None, &params);
+        assert_eq!(shape, [1, 2, 2, 1]);
+        assert_eq!(output, vec![12.0, 9.0, 15.0, 9.0]);
+
+        let (output, _) = depthwise_conv_2d(
+            &[1.0, 2.0],
+            [1, 1, 1, 2],
+            &[1.0, 10.0, 2.0, 20.0],
+            [1, 1, 1, 4],
+            None,
+            2,
+            &ConvParams::default(),
+        );
+        assert_eq!(output, vec![1.0, 10.0, 4.0, 40.0]);
+
+        let output = fully_connected(
+            &[1.0, 2.0],
+            &[1.0, 1.0, -1.0, 0.0],
+            2,
+            Some(&[0.0, 0.5]),
+            Activation::Relu,
+        );
+        assert_eq!(output, vec![3.0, 0.0]);
+
+        let output = softmax(&[1.0, 1.0, 0.0, 1000.0], 2, 1.0);
+        assert_eq!(output, vec![0.5, 0.5, 0.0, 1.0]);
+
+        let params = PoolParams {
+            filter: (2, 2),
+            stride: (2, 2),
+            padding: Padding::Same,
+            activation: Activation::None,
+        };
+        let (output, _) = max_pool_2d(&input, [1, 3, 3, 1], &params);
+        assert_eq!(output, vec![5.0, 6.0, 8.0, 9.0]);
+        let (output, _) = average_pool_2d(&input, [1, 3, 3, 1], &params);
+        assert_eq!(output, vec![3.0, 4.5, 7.5, 9.0]);
+
+        assert_eq!(max_abs_diff(&[1.0, 2.0], &[1.5, 1.0]), Some(1.0));
+        assert!(max_abs_diff(&[1.0], &[f32::NAN]).unwrap().is_nan());
+        assert_eq!(max_abs_diff(&[1.0], &[]), None);
+    }
+}
@@ -0,0 +1,395 @@
+//! Rust kernels standing in for builtin operators a TF Lite runtime lacks, so models from
+//! newer converters still run on older runtimes, e.g. a prebuilt library older than the schema
+//! the bindings were generated from:
+//!
+//! ```ignore
+//! let mut model = Model::from_file("model.tflite")?;
+//! let mut resolver = BuiltinOpResolver::default();
+//! let report = Shims::default().apply(&mut model, &mut resolver)?;
+//! if !report.missing.is_empty() {
+//!     return Err(format!("the runtime cannot run {:?}", report.missing).into());
+//! }
+//! let model = FlatBufferModel::build_from_model(&model)?;
+//! let builder = InterpreterBuilder::new(model, resolver)?;
+//! ```
+//!
+//! Each operator code the resolver has no kernel for, at its version, and that a shim covers
+//! becomes the custom operator `shim:<OPERATOR>`, e.g. `shim:RESIZE_NEAREST_NEIGHBOR`. Its
+//! operators carry their builtin options as custom options, encoded by the shim, and the shim
+//! is registered under that name.
+
+use std::ffi::CString;
+use std::ops::RangeInclusive;
+
+use super::plugin::CustomOpRegistry;
+use crate::bindings::tflite as bindings;
+use crate::context::{int_array_as_slice, ElementKind};
+use crate::interpreter::op_resolver::OpResolver;
+use crate::kernel::{self, CustomOp, KernelContext, TfLiteNode, TfLiteRegistration};
+use crate::model::stl::vector::{VectorInsert, VectorSlice};
+use crate::model::{
+    BuiltinOperator, BuiltinOptions, BuiltinOptionsUnion, Model, OperatorT,
+    ResizeNearestNeighborOptionsT,
+};
+use crate::{Error, Result, TensorIndex};
+
+cpp! {{
+    #include "tensorflow/lite/core/api/op_resolver.h"
+}}
+
+/// A Rust kernel for some versions of a builtin operator.
+pub struct Shim {
+    pub op: BuiltinOperator,
+    pub versions: RangeInclusive<i32>,
+    pub registration: TfLiteRegistration,
+    /// Encodes the builtin options of an operator as the custom options the kernel gets.
+    pub options: fn(&OperatorT) -> Vec<u8>,
+}
+
+impl Shim {
+    pub fn new<T: CustomOp>(
+        op: BuiltinOperator,
+        versions: RangeInclusive<i32>,
+        options: fn(&OperatorT) -> Vec<u8>,
+    ) -> Self {
+        Self { op, versions, registration: kernel::registration::<T>(), options }
+    }
+
+    /// The custom code of the operators the shim runs.
+    pub fn name(&self) -> String {
+        format!("shim:{}", format!("{:?}", self.op).trim_start_matches("BuiltinOperator_"))
+    }
+}
+
+/// The operator codes `Shims::apply` rewrote, and those still without a kernel.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShimReport {
+    pub shimmed: Vec<(BuiltinOperator, i32)>,
+    pub missing: Vec<(BuiltinOperator, i32)>,
+}
+
+/// A set of shims, by default those of this crate: `RESIZE_NEAREST_NEIGHBOR`.
+pub struct Shims {
+    shims: Vec<Shim>,
+}
+
+impl Default for Shims {
+    fn default() -> Self {
+        Self::empty().with_shim(Shim::new::<ResizeNearestNeighbor>(
+            BuiltinOperator::BuiltinOperator_RESIZE_NEAREST_NEIGHBOR,
+            1..=3,
+            resize_nearest_neighbor_options,
+        ))
+    }
+}
+
+impl Shims {
+    pub fn empty() -> Self {
+        Self { shims: Vec::new() }
+    }
+
+    /// Adds `shim`, taking precedence over earlier shims of the same versions.
+    pub fn with_shim(mut self, shim: Shim) -> Self {
+        self.shims.insert(0, shim);
+        self
+    }
+
+    fn find(&self, op: BuiltinOperator, version: i32) -> Option<&Shim> {
+        self.shims.iter().find(|shim| shim.op == op && shim.versions.contains(&version))
+    }
+
+    /// Rewrites the builtin operators of `model` that `resolver` has no kernel for into the
+    /// custom operators of their shims, and registers those on `resolver`, e.g. a
+    /// `BuiltinOpResolver` or a `MutableOpResolver`.
+    pub fn apply<R>(&self, model: &mut Model, resolver: &mut R) -> Result<ShimReport>
+    where
+        R: OpResolver + CustomOpRegistry,
+    {
+        let mut report = ShimReport::default();
+        let mut shimmed = Vec::new();
+        for index in 0..model.operator_codes.size() {
+            let code = &model.operator_codes[index];
+            let (op, version) = (code.builtin_code, code.version);
+            if op == BuiltinOperator::BuiltinOperator_CUSTOM || has_builtin(resolver, op, version) {
+                continue;
+            }
+            let shim = match self.find(op, version) {
+                Some(shim) => shim,
+                None => {
+                    report.missing.push((op, version));
+                    continue;
+                }
+            };
+            let name = shim.name();
+            let custom_code = CString::new(name.as_str()).map_err(|_| {
+                Error::InternalError(format!("shim name {:?} contains a NUL byte", name))
+            })?;
+            resolver.add_custom(&name, &shim.registration, version)?;
+            let code = &mut model.operator_codes[index];
+            code.builtin_code = BuiltinOperator::BuiltinOperator_CUSTOM;
+            code.custom_code.assign(&custom_code);
+            shimmed.push((index as u32, shim));
+            report.shimmed.push((op, version));
+        }
+
+        for subgraph in model.subgraphs.iter_mut() {
+            for operator in subgraph.operators.iter_mut() {
+                let shim = shimmed.iter().find(|(index, _)| *index == operator.opcode_index);
+                if let Some((_, shim)) = shim {
+                    let options = (shim.options)(operator);
+                    operator.custom_options.assign(options);
+                    operator.builtin_options = BuiltinOptionsUnion::default();
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Whether `resolver` has a kernel for `version` of `op`.
+pub fn has_builtin<R: OpResolver + ?Sized>(
+    resolver: &R,
+    op: BuiltinOperator,
+    version: i32,
+) -> bool {
+    let handle = resolver.get_resolver_handle() as *const bindings::OpResolver;
+    let op = op as i32;
+
+    #[allow(clippy::forget_copy, deprecated)]
+    unsafe {
+        cpp!([handle as "const tflite::OpResolver*", op as "int", version as "int"] -> bool as "bool" {
+            return handle->FindOp(static_cast<tflite::BuiltinOperator>(op), version) != nullptr;
+        })
+    }
+}
+
+fn resize_nearest_neighbor_options(operator: &OperatorT) -> Vec<u8> {
+    if operator.builtin_options.typ != BuiltinOptions::BuiltinOptions_ResizeNearestNeighborOptions {
+        return vec![0, 0];
+    }
+    let options: &ResizeNearestNeighborOptionsT = operator.builtin_options.as_ref();
+    vec![options.align_corners as u8, options.half_pixel_centers as u8]
+}
+
+/// `RESIZE_NEAREST_NEIGHBOR` of NHWC tensors of any type, like the reference kernel.
+struct ResizeNearestNeighbor {
+    align_corners: bool,
+    half_pixel_centers: bool,
+}
+
+/// The input coordinate of each output coordinate along an axis.
+fn nearest_coords(
+    input: usize,
+    output: usize,
+    align_corners: bool,
+    half_pixel_centers: bool,
+) -> Vec<usize> {
+    let scale = if align_corners && output > 1 {
+        (input as f32 - 1.0) / (output as f32 - 1.0)
+    } else {
+        input as f32 / output as f32
+    };
+    let offset = if half_pixel_centers { 0.5 } else { 0.0 };
+    (0..output)
+        .map(|i| {
+            let x = (i as f32 + offset) * scale;
+            let x = if align_corners { x.round() } else { x.floor() };
+            (x.max(0.0) as usize).min(input - 1)
+        })
+        .collect()
+}
+
+impl ResizeNearestNeighbor {
+    /// The output shape, once the size input is available.
+    fn output_dims(context: &KernelContext<'_>, node: &TfLiteNode) -> Result<Option<Vec<i32>>> {
+        let (input, size) = match kernel::inputs(node) {
+            &[input, size] => (input, size),
+            _ => return Err(Error::internal_error("expects an input and a size")),
+        };
+        let input = context.tensor(input).ok_or_else(|| Error::internal_error("no input"))?;
+        let dims = unsafe { int_array_as_slice(&*input.dims) };
+        if dims.len() != 4 {
+            return Err(Error::InternalError(format!("expects NHWC input, got dims {:?}", dims)));
+        }
+        let size_type = context.tensor(size).map(|tensor| tensor.type_);
+        if size_type != Some(ElementKind::kTfLiteInt32) {
+            return Err(Error::InternalError(format!("size must be int32, got {:?}", size_type)));
+        }
+        let size = match context.buffer(size) {
+            Some(size) if size.len() == 8 => size,
+            Some(_) => return Err(Error::internal_error("size must be 2 int32 values")),
+            None => return Ok(None),
+        };
+        let int = |i: usize| i32::from_ne_bytes([size[i], size[i + 1], size[i + 2], size[i + 3]]);
+        Ok(Some(vec![dims[0], int(0), int(4), dims[3]]))
+    }
+}
+
+impl CustomOp for ResizeNearestNeighbor {
+    fn init(options: &[u8]) -> Result<Self> {
+        match options {
+            &[align_corners, half_pixel_centers] => Ok(ResizeNearestNeighbor {
+                align_corners: align_corners != 0,
+                half_pixel_centers: half_pixel_centers != 0,
+            }),
+            _ => Err(Error::internal_error("expects 2 bytes of options")),
+        }
+    }
+
+    fn prepare(&mut self, context: &mut KernelContext<'_>, node: &mut TfLiteNode) -> Result<()> {
+        let output = kernel::outputs(node)[0];
+        match Self::output_dims(context, node)? {
+            Some(dims) => context.resize_tensor(output, &dims),
+            None => context.set_dynamic(output),
+        }
+    }
+
+    fn eval(&mut self, context: &mut KernelContext<'_>, node: &TfLiteNode) -> Result<()> {
+        let (input, output): (TensorIndex, TensorIndex) =
+            (kernel::inputs(node)[0], kernel::outputs(node)[0]);
+        let dims = Self::output_dims(context, node)?
+            .ok_or_else(|| Error::internal_error("size is not available"))?;
+        let (in_dims, kind) = {
+            let tensor = context.tensor(input).unwrap();
+            (unsafe { int_array_as_slice(&*tensor.dims) }.to_vec(), tensor.type_)
+        };
+        let out = context.tensor(output).ok_or_else(|| Error::internal_error("no output"))?;
+        if out.type_ != kind || kind == ElementKind::kTfLiteString {
+            return Err(Error::internal_error("output must have the numeric type of the input"));
+        }
+        if unsafe { int_array_as_slice(&*out.dims) } != dims.as_slice() {
+            context.resize_tensor(output, &dims)?;
+        }
+
+        let dim = |dims: &[i32], i: usize| dims[i].max(0) as usize;
+        let (batches, in_height, in_width) = (dim(&in_dims, 0), dim(&in_dims, 1), dim(&in_dims, 2));
+        let (depth, height, width) = (dim(&in_dims, 3), dim(&dims, 1), dim(&dims, 2));
+        if in_height == 0 || in_width == 0 {
+            return Err(Error::internal_error("input is empty"));
+        }
+        let rows = nearest_coords(in_height, height, self.align_corners, self.half_pixel_centers);
+        let cols = nearest_coords(in_width, width, self.align_corners, self.half_pixel_centers);
+
+        let source = context.buffer(input).ok_or_else(|| Error::internal_error("no input data"))?;
+        let element = source.len() / (batches * in_height * in_width * depth).max(1);
+        let pixel = depth * element;
+        let source = source.to_vec();
+        let target =
+            context.buffer_mut(output).ok_or_else(|| Error::internal_error("no output data"))?;
+        let mut offset = 0;
+        for b in 0..batches {
+            for &y in &rows {
+                for &x in &cols {
+                    let from = ((b * in_height + y) * in_width + x) * pixel;
+                    target[offset..offset + pixel].copy_from_slice(&source[from..from + pixel]);
+                    offset += pixel;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::stl::memory::UniquePtr;
+    use crate::model::{BufferT, OperatorCodeT, SubGraphT, TensorT, TensorType};
+    use crate::ops::builtin::{BuiltinOpResolver, MutableOpResolver};
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    /// A model resizing a 2x2 image to 4x4, with a size tensor of `size_type`.
+    fn resize_model(size_type: TensorType) -> Model {
+        let mut model = Model::default();
+        model.version = 3;
+        let mut code: UniquePtr<OperatorCodeT> = Default::default();
+        code.builtin_code = BuiltinOperator::BuiltinOperator_RESIZE_NEAREST_NEIGHBOR;
+        code.version = 1;
+        model.operator_codes.push_back(code);
+
+        let mut size: UniquePtr<BufferT> = Default::default();
+        size.data.assign(4i32.to_ne_bytes().iter().chain(&4i32.to_ne_bytes()).copied());
+        model.buffers.assign(vec![Default::default(), size]);
+
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        for (shape, typ, buffer) in [
+            (vec![1, 2, 2, 1], TensorType::TensorType_FLOAT32, 0),
+            (vec![2], size_type, 1),
+            (vec![1, 4, 4, 1], TensorType::TensorType_FLOAT32, 0),
+        ] {
+            let mut tensor: UniquePtr<TensorT> = Default::default();
+            tensor.shape.assign(shape);
+            tensor.typ = typ;
+            tensor.buffer = buffer;
+            subgraph.tensors.push_back(tensor);
+        }
+        let mut operator: UniquePtr<OperatorT> = Default::default();
+        operator.opcode_index = 0;
+        operator.inputs.assign(vec![0, 1]);
+        operator.outputs.assign(vec![2]);
+        subgraph.operators.push_back(operator);
+        subgraph.inputs.assign(vec![0]);
+        subgraph.outputs.assign(vec![2]);
+        model.subgraphs.push_back(subgraph);
+        model
+    }
+
+    #[test]
+    fn unittest_apply_shims() {
+        let resize = BuiltinOperator::BuiltinOperator_RESIZE_NEAREST_NEIGHBOR;
+        // The builtin resolver has the kernel, so nothing is rewritten.
+        let mut model = resize_model(TensorType::TensorType_INT32);
+        let report = Shims::default().apply(&mut model, &mut BuiltinOpResolver::default());
+        assert_eq!(report.unwrap(), ShimReport::default());
+        assert_eq!(model.operator_codes[0].builtin_code, resize);
+
+        let mut resolver = MutableOpResolver::new();
+        let report = Shims::empty().apply(&mut model, &mut resolver).unwrap();
+        assert_eq!(report, ShimReport { shimmed: vec![], missing: vec![(resize, 1)] });
+
+        let report = Shims::default().apply(&mut model, &mut resolver).unwrap();
+        assert_eq!(report, ShimReport { shimmed: vec![(resize, 1)], missing: vec![] });
+        let code = &model.operator_codes[0];
+        assert_eq!(code.builtin_code, BuiltinOperator::BuiltinOperator_CUSTOM);
+        assert_eq!(code.custom_code.c_str().to_str().unwrap(), "shim:RESIZE_NEAREST_NEIGHBOR");
+        assert_eq!(model.subgraphs[0].operators[0].custom_options.as_slice(), &[0, 0]);
+
+        let model = FlatBufferModel::build_from_model(&model).unwrap();
+        let mut interpreter = InterpreterBuilder::new(model, resolver).unwrap().build().unwrap();
+        interpreter.allocate_tensors().unwrap();
+        interpreter.tensor_data_mut::<f32>(0).unwrap().copy_from_slice(&[1.0, 2.0, 3.0, 4.0]);
+        interpreter.invoke().unwrap();
+        assert_eq!(
+            interpreter.tensor_data::<f32>(2).unwrap(),
+            &[
+                1.0, 1.0, 2.0, 2.0, 1.0, 1.0, 2.0, 2.0, //
+                3.0, 3.0, 4.0, 4.0, 3.0, 3.0, 4.0, 4.0,
+            ]
+        );
+
+        // The shim rejects a size tensor of another type.
+        let mut model = resize_model(TensorType::TensorType_FLOAT32);
+        let mut resolver = MutableOpResolver::new();
+        Shims::default().apply(&mut model, &mut resolver).unwrap();
+        let model = FlatBufferModel::build_from_model(&model).unwrap();
+        let mut interpreter = InterpreterBuilder::new(model, resolver).unwrap().build().unwrap();
+        assert!(interpreter.allocate_tensors().is_err());
+    }
+
+    #[test]
+    fn unittest_shims() {
+        assert_eq!(nearest_coords(2, 4, false, false), vec![0, 0, 1, 1]);
+        assert_eq!(nearest_coords(3, 5, true, false), vec![0, 1, 1, 2, 2]);
+        assert_eq!(nearest_coords(2, 4, false, true), vec![0, 0, 1, 1]);
+        assert_eq!(nearest_coords(4, 2, false, true), vec![1, 3]);
+        assert_eq!(nearest_coords(4, 2, false, false), vec![0, 2]);
+
+        let shims = Shims::default();
+        let shim = shims.find(BuiltinOperator::BuiltinOperator_RESIZE_NEAREST_NEIGHBOR, 3).unwrap();
+        assert_eq!(shim.name(), "shim:RESIZE_NEAREST_NEIGHBOR");
+        assert!(shims.find(BuiltinOperator::BuiltinOperator_RESIZE_NEAREST_NEIGHBOR, 4).is_none());
+        assert!(shims.find(BuiltinOperator::BuiltinOperator_CONV_2D, 1).is_none());
+        assert!(ResizeNearestNeighbor::init(&[1, 0]).unwrap().align_corners);
+        assert!(ResizeNearestNeighbor::init(&[]).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Kernels of the TF Text custom ops that converted NLP models use for preprocessing, so such
+//! models run without the Flex delegate (feature `text`):
+//!
+//! ```ignore
+//! let mut resolver = BuiltinOpResolver::default();
+//! ops::text::register(&mut resolver)?;
+//! let builder = InterpreterBuilder::new(model, resolver)?;
+//! ```
+//!
+//! Regular expressions are evaluated by C++ `std::regex` with ECMAScript syntax, which agrees
+//! with the RE2 syntax of TF Text for common delimiter patterns such as `\s+` or `[,.!?]`;
+//! RE2-only syntax like `\p{P}` is rejected.
+
+use std::os::raw::c_char;
+use std::ptr;
+
+use super::builtin::BuiltinOpResolver;
+use crate::context::{to_dim, ElementKind};
+use crate::kernel::{
+    self, KernelContext, TfLiteContext, TfLiteNode, TfLiteRegistration, TfLiteStatus,
+};
+use crate::{Error, Result, TensorIndex};
+
+cpp! {{
+    #include <regex>
+    #include <string>
+}}
+
+/// Splits strings on Unicode whitespace into a ragged tensor: the flat tokens, then `int64`
+/// row splits for each input dimension.
+pub const WHITESPACE_TOKENIZER: &str = "tftext:WhitespaceTokenizer";
+
+/// Splits strings on matches of a delimiter pattern, keeping delimiters that fully match a
+/// second pattern: outputs tokens, `int64` begin and end byte offsets and row splits.
+pub const REGEX_SPLIT_WITH_OFFSETS: &str = "RegexSplitWithOffsets";
+
+/// Adds all text kernels to `resolver`.
+pub fn register(resolver: &mut BuiltinOpResolver) -> Result<()> {
+    resolver.add_custom(WHITESPACE_TOKENIZER, &whitespace_tokenizer(), 1)?;
+    resolver.add_custom(REGEX_SPLIT_WITH_OFFSETS, &regex_split_with_offsets(), 1)
+}
+
+pub fn whitespace_tokenizer() -> TfLiteRegistration {
+    registration(invoke_whitespace_tokenizer)
+}
+
+pub fn regex_split_with_offsets() -> TfLiteRegistration {
+    registration(invoke_regex_split)
+}
+
+type Invoke = unsafe extern "C" fn(*mut TfLiteContext, *mut TfLiteNode) -> TfLiteStatus;
+
+fn registration(invoke: Invoke) -> TfLiteRegistration {
+    TfLiteRegistration {
+        init: None,
+        free: None,
+        prepare: Some(prepare),
+        invoke: Some(invoke),
+        profiling_string: None,
+        builtin_code: 0,
+        custom_name: ptr::null::<c_char>(),
+        version: 1,
+    }
+}
+
+/// Runs a kernel callback, reporting errors and panics to the interpreter.
+unsafe fn run<F>(context: *mut TfLiteContext, node: *mut TfLiteNode, f: F) -> TfLiteStatus
+where
+    F: FnOnce(&mut KernelContext<'_>, &TfLiteNode) -> Result<()>,
+{
+    let mut context = KernelContext::from_raw(context);
+    kernel::guard(&mut context, "text kernel", |context| f(context, &*node))
+}
+
+// Output sizes depend on the input strings, so all outputs are sized in `invoke`.
+unsafe extern "C" fn prepare(context: *mut TfLiteContext, node: *mut TfLiteNode) -> TfLiteStatus {
+    run(context, node, |context, node| {
+        for &output in kernel::outputs(node) {
+            context.set_dynamic(output)?;
+        }
+        Ok(())
+    })
+}
+
+fn input_strings<'a>(
+    context: &'a KernelContext<'_>,
+    node: &TfLiteNode,
+    i: usize,
+) -> Result<Vec<&'a [u8]>> {
+    let index = kernel::inputs(node).get(i).copied().unwrap_or(-1);
+    context
+        .strings(index)
+        .ok_or_else(|| Error::InternalError(format!("input {} is not a string tensor", i)))
+}
+
+fn output(node: &TfLiteNode, i: usize) -> Result<TensorIndex> {
+    kernel::outputs(node)
+        .get(i)
+        .copied()
+        .ok_or_else(|| Error::InternalError(format!("missing output {}", i)))
+}
+
+fn write_i64(context: &mut KernelContext<'_>, index: TensorIndex, values: &[i64]) -> Result<()> {
+    if context.tensor(index).map(|tensor| tensor.type_) != Some(ElementKind::kTfLiteInt64) {
+        return Err(Error::internal_error("output is not an int64 tensor"));
+    }
+    context.resize_tensor(index, &[to_dim(values.len())?])?;
+    let buffer =
+        context.buffer_mut(index).ok_or_else(|| Error::internal_error("output not allocated"))?;
+    for (bytes, value) in buffer.chunks_exact_mut(8).zip(values) {
+        bytes.copy_from_slice(&value.to_ne_bytes());
+    }
+    Ok(())
+}
+
+/// Byte ranges of the whitespace-separated tokens of `text`. Invalid UTF-8 is only split
+/// on ASCII whitespace.
+fn whitespace_tokens(text: &[u8]) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut boundary = |position: usize, whitespace: bool| match (start, whitespace) {
+        (None, false) => start = Some(position),
+        (Some(begin), true) => {
+            tokens.push((begin, position));
+            start = None;
+        }
+        _ => {}
+    };
+    match std::str::from_utf8(text) {
+        Ok(text) => text.char_indices().for_each(|(i, c)| boundary(i, c.is_whitespace())),
+        Err(_) => text.iter().enumerate().for_each(|(i, c)| boundary(i, c.is_ascii_whitespace())),
+    }
+    boundary(text.len(), true);
+    tokens
+}
+
+unsafe extern "C" fn invoke_whitespace_tokenizer(
+    context: *mut TfLiteContext,
+    node: *mut TfLiteNode,
+) -> TfLiteStatus {
+    run(context, node, |context, node| {
+        let input = kernel::inputs(node)[0];
+        let dims: Vec<i64> = context
+            .tensor(input)
+            .map(|tensor| unsafe { crate::context::int_array_as_slice(&*tensor.dims) })
+            .unwrap_or_default()
+            .iter()
+            .map(|&dim| i64::from(dim))
+            .collect();
+        if kernel::outputs(node).len() != dims.len() + 1 {
+            return Err(Error::InternalError(format!(
+                "expected {} outputs for a rank {} input",
+                dims.len() + 1,
+                dims.len()
+            )));
+        }
+
+        let strings = input_strings(context, node, 0)?;
+        let mut tokens: Vec<Vec<u8>> = Vec::new();
+        let mut splits = vec![0];
+        for text in &strings {
+            let ranges = whitespace_tokens(text).into_iter();
+            tokens.extend(ranges.map(|(start, end)| text[start..end].to_vec()));
+            splits.push(tokens.len() as i64);
+        }
+        let tokens: Vec<&[u8]> = tokens.iter().map(Vec::as_slice).collect();
+        context.write_strings(output(node, 0)?, &tokens, &[to_dim(tokens.len())?])?;
+
+        // Uniform row splits for the outer dimensions, e.g. `[0, 3, 6]` for a `[2, 3]` input.
+        let mut rows = 1;
+        for (i, pair) in dims.windows(2).enumerate() {
+            rows *= pair[0];
+            let uniform: Vec<i64> = (0..=rows).map(|row| row * pair[1]).collect();
+            write_i64(context, output(node, i + 1)?, &uniform)?;
+        }
+        if !dims.is_empty() {
+            write_i64(context, output(node, dims.len())?, &splits)?;
+        }
+        Ok(())
+    })
+}
+
+/// A delimiter match: byte range and whether it is kept as a token.
+type Delimiter = (usize, usize, bool);
+
+/// Finds the non-empty matches of `delimiter` in each of `texts`, and whether they fully
+/// match `keep`.
+fn find_delimiters(delimiter: &[u8], keep: &[u8], texts: &[&[u8]]) -> Result<Vec<Vec<Delimiter>>> {
+    let mut matches: Vec<Vec<Delimiter>> = vec![Vec::new(); texts.len()];
+    let matches_ptr = &mut matches;
+    let (delimiter_ptr, delimiter_len) = (delimiter.as_ptr(), delimiter.len());
+    let (keep_ptr, keep_len) = (keep.as_ptr(), keep.len());
+    let pointers: Vec<*const u8> = texts.iter().map(|text| text.as_ptr()).collect();
+    let lengths: Vec<usize> = texts.iter().map(|text| text.len()).collect();
+    let (pointers, lengths, count) = (pointers.as_ptr(), lengths.as_ptr(), texts.len());
+
+    #[allow(clippy::forget_copy, deprecated)]
+    let valid = unsafe {
+        cpp!([matches_ptr as "void*", delimiter_ptr as "const char*", delimiter_len as "size_t",
+              keep_ptr as "const char*", keep_len as "size_t", pointers as "const char* const*",
+              lengths as "const size_t*", count as "size_t"] -> bool as "bool" {
+            try {
+                std::regex delimiter(delimiter_ptr, delimiter_len);
+                std::regex keep(keep_ptr, keep_len);
+                for (size_t i = 0; i < count; ++i) {
+                    const char* begin = pointers[i];
+                    std::cregex_iterator it(begin, begin + lengths[i], delimiter), end;
+                    for (; it != end; ++it) {
+                        size_t start = it->position();
+                        size_t length = it->length();
+                        if (length == 0) {
+                            continue;
+                        }
+                        bool kept = keep_len > 0
+                            && std::regex_match(begin + start, begin + start + length, keep);
+                        rust!(Text_push_delimiter [
+                            matches_ptr: &mut Vec<Vec<Delimiter>> as "void*",
+                            i: usize as "size_t",
+                            start: usize as "size_t",
+                            length: usize as "size_t",
+                            kept: bool as "bool"
+                        ] {
+                            crate::panic_guard::abort_on_panic("Text_push_delimiter", || {
+                                matches_ptr[i].push((start, start + length, kept))
+                            });
+                        });
+                    }
+                }
+                return true;
+            } catch (const std::regex_error&) {
+                return false;
+            }
+        })
+    };
+    if valid {
+        Ok(matches)
+    } else {
+        Err(Error::InternalError(format!(
+            "invalid pattern `{}` or `{}`",
+            String::from_utf8_lossy(delimiter),
+            String::from_utf8_lossy(keep)
+        )))
+    }
+}
+
+/// Byte ranges of the tokens of a text of `len` bytes between `delimiters`, including the
+/// kept delimiters. Empty tokens are dropped.
+fn split_tokens(len: usize, delimiters: &[Delimiter]) -> Vec<(usize, usize)> {
+    let mut tokens = Vec::new();
+    let mut cursor = 0;
+    for &(start, end, keep) in delimiters {
+        if start > cursor {
+            tokens.push((cursor, start));
+        }
+        if keep {
+            tokens.push((start, end));
+        }
+        cursor = end;
+    }
+    if len > cursor {
+        tokens.push((cursor, len));
+    }
+    tokens
+}
+
+unsafe extern "C" fn invoke_regex_split(
+    context: *mut TfLiteContext,
+    node: *mut TfLiteNode,
+) -> TfLiteStatus {
+    run(context, node, |context, node| {
+        let strings = input_strings(context, node, 0)?;
+        let delimiter = input_strings(context, node, 1)?;
+        let keep = input_strings(context, node, 2)?;
+        let (delimiter, keep) = match (delimiter.first(), keep.first()) {
+            (Some(delimiter), Some(keep)) => (*delimiter, *keep),
+            _ => return Err(Error::internal_error("patterns must be scalar strings")),
+        };
+        let delimiters = find_delimiters(delimiter, keep, &strings)?;
+
+        let mut tokens: Vec<Vec<u8>> = Vec::new();
+        let (mut begins, mut ends, mut splits) = (Vec::new(), Vec::new(), vec![0]);
+        for (text, delimiters) in strings.iter().zip(&delimiters) {
+            for (start, end) in split_tokens(text.len(), delimiters) {
+                tokens.push(text[start..end].to_vec());
+                begins.push(start as i64);
+                ends.push(end as i64);
+            }
+            splits.push(tokens.len() as i64);
+        }
+        let tokens: Vec<&[u8]> = tokens.iter().map(Vec::as_slice).collect();
+        context.write_strings(output(node, 0)?, &tokens, &[to_dim(tokens.len())?])?;
+        write_i64(context, output(node, 1)?, &begins)?;
+        write_i64(context, output(node, 2)?, &ends)?;
+        write_i64(context, output(node, 3)?, &splits)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_text_ops() {
+        assert_eq!(
+            whitespace_tokens(b"  hello\tworld \xe2\x80\x83x "),
+            vec![(2, 7), (8, 13), (17, 18)]
+        );
+        assert_eq!(whitespace_tokens(b"\xff a"), vec![(0, 1), (2, 3)]);
+        assert!(whitespace_tokens(b" \n").is_empty());
+
+        // "a, b!" split on `\s|[,!]`, keeping punctuation.
+        let delimiters = [(1, 2, true), (2, 3, false), (4, 5, true)];
+        assert_eq!(split_tokens(5, &delimiters), vec![(0, 1), (1, 2), (3, 4), (4, 5)]);
+        assert_eq!(split_tokens(3, &[(0, 3, false)]), vec![]);
+    }
+}
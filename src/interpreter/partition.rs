@@ -0,0 +1,215 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_char;
+use std::slice;
+
+use libc::size_t;
+
+use super::context::{int_array_as_slice, TfLiteIntArray};
+use super::op_resolver::OpResolver;
+use super::Interpreter;
+
+cpp! {{
+    #include "tensorflow/lite/interpreter.h"
+    #include "tensorflow/lite/builtin_ops.h"
+    #include "tensorflow/lite/schema/schema_generated.h"
+
+    using namespace tflite;
+}}
+
+/// An operator of the original graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpInfo {
+    /// Node index in the interpreter.
+    pub node: usize,
+    /// Builtin operator name (e.g. `CONV_2D`) or custom op name.
+    pub name: String,
+}
+
+/// A run of consecutive execution plan entries handled either by one delegate kernel or by
+/// the CPU.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    pub delegated: bool,
+    /// Execution plan nodes executing this partition: the single delegate kernel node, or
+    /// the CPU nodes themselves.
+    pub plan_nodes: Vec<usize>,
+    /// Original operators in the partition.
+    pub ops: Vec<OpInfo>,
+}
+
+/// How the execution plan is split between delegates and the CPU.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DelegationReport {
+    pub partitions: Vec<Partition>,
+}
+
+impl DelegationReport {
+    /// Operators taken by delegates.
+    pub fn delegated_ops(&self) -> impl Iterator<Item = &OpInfo> {
+        self.partitions.iter().filter(|p| p.delegated).flat_map(|p| &p.ops)
+    }
+
+    /// Operators left on the CPU.
+    pub fn cpu_ops(&self) -> impl Iterator<Item = &OpInfo> {
+        self.partitions.iter().filter(|p| !p.delegated).flat_map(|p| &p.ops)
+    }
+
+    pub fn is_fully_delegated(&self) -> bool {
+        self.cpu_ops().next().is_none()
+    }
+}
+
+impl fmt::Display for DelegationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let delegated = self.delegated_ops().count();
+        let total = delegated + self.cpu_ops().count();
+        writeln!(
+            f,
+            "{}/{} ops delegated in {} partitions",
+            delegated,
+            total,
+            self.partitions.len()
+        )?;
+        for partition in &self.partitions {
+            let names: Vec<&str> = partition.ops.iter().map(|op| op.name.as_str()).collect();
+            let target = if partition.delegated { "delegate" } else { "cpu" };
+            writeln!(f, "  {:<8} {}", target, names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Node indices in execution order. Delegate kernels replace the nodes they took over.
+    pub fn execution_plan(&self) -> &[i32] {
+        let interpreter = self.handle();
+        let mut count: size_t = 0;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let ptr = unsafe {
+            cpp!([
+                interpreter as "const Interpreter*",
+                mut count as "size_t"
+            ] -> *const i32 as "const int*" {
+                const auto& plan = interpreter->execution_plan();
+                count = plan.size();
+                return plan.data();
+            })
+        };
+        unsafe { slice::from_raw_parts(ptr, count) }
+    }
+
+    /// Name of the operator at `node`, or `None` for an invalid index.
+    pub fn op_name(&self, node: usize) -> Option<String> {
+        let interpreter = self.handle();
+        let node = node as i32;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let name = unsafe {
+            cpp!([interpreter as "const Interpreter*", node as "int"]
+                  -> *const c_char as "const char*" {
+                const auto* pair = interpreter->node_and_registration(node);
+                if (pair == nullptr) {
+                    return nullptr;
+                }
+                const TfLiteRegistration& registration = pair->second;
+                if (registration.builtin_code == kTfLiteBuiltinCustom ||
+                    registration.builtin_code == kTfLiteBuiltinDelegate) {
+                    return registration.custom_name ? registration.custom_name : "DELEGATE";
+                }
+                return EnumNameBuiltinOperator(
+                    static_cast<BuiltinOperator>(registration.builtin_code));
+            })
+        };
+        if name.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned())
+        }
+    }
+
+    /// Original nodes replaced by the delegate kernel at `node`, or `None` if `node` is not
+    /// a delegate kernel.
+    fn delegated_nodes(&self, node: usize) -> Option<Vec<usize>> {
+        let interpreter = self.handle();
+        let node = node as i32;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let nodes = unsafe {
+            cpp!([interpreter as "const Interpreter*", node as "int"]
+                  -> *const TfLiteIntArray as "const TfLiteIntArray*" {
+                const auto* pair = interpreter->node_and_registration(node);
+                if (pair == nullptr || pair->second.builtin_code != kTfLiteBuiltinDelegate) {
+                    return nullptr;
+                }
+                const auto* params =
+                    reinterpret_cast<const TfLiteDelegateParams*>(pair->first.builtin_data);
+                return params ? params->nodes_to_replace : nullptr;
+            })
+        };
+        if nodes.is_null() {
+            None
+        } else {
+            Some(unsafe { int_array_as_slice(&*nodes) }.iter().map(|&n| n as usize).collect())
+        }
+    }
+
+    /// Reports which operators were taken by delegates and which remain on the CPU,
+    /// following the execution plan.
+    pub fn delegation_report(&self) -> DelegationReport {
+        let op = |node: usize| OpInfo { node, name: self.op_name(node).unwrap_or_default() };
+        let mut partitions: Vec<Partition> = Vec::new();
+        for &node in self.execution_plan() {
+            let node = node as usize;
+            match self.delegated_nodes(node) {
+                Some(nodes) => partitions.push(Partition {
+                    delegated: true,
+                    plan_nodes: vec![node],
+                    ops: nodes.into_iter().map(op).collect(),
+                }),
+                None => match partitions.last_mut() {
+                    Some(partition) if !partition.delegated => {
+                        partition.plan_nodes.push(node);
+                        partition.ops.push(op(node));
+                    }
+                    _ => partitions.push(Partition {
+                        delegated: false,
+                        plan_nodes: vec![node],
+                        ops: vec![op(node)],
+                    }),
+                },
+            }
+        }
+        DelegationReport { partitions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_delegation_report() {
+        let op = |node, name: &str| OpInfo { node, name: name.to_string() };
+        let report = DelegationReport {
+            partitions: vec![
+                Partition {
+                    delegated: true,
+                    plan_nodes: vec![3],
+                    ops: vec![op(0, "CONV_2D"), op(1, "RELU")],
+                },
+                Partition { delegated: false, plan_nodes: vec![2], ops: vec![op(2, "SOFTMAX")] },
+            ],
+        };
+        assert!(!report.is_fully_delegated());
+        assert_eq!(report.cpu_ops().collect::<Vec<_>>(), vec![&op(2, "SOFTMAX")]);
+        assert_eq!(
+            report.to_string(),
+            "2/3 ops delegated in 2 partitions\n  delegate CONV_2D, RELU\n  cpu      SOFTMAX\n"
+        );
+    }
+}
@@ -0,0 +1,329 @@
+//! What kernels fused and how the memory planner laid out tensors, to explain performance
+//! differences between nearly identical models:
+//!
+//! ```ignore
+//! interpreter.allocate_tensors()?;
+//! let plan = interpreter.plan_report();
+//! print!("{}", plan);
+//! for node in plan.fused() {
+//!     println!("{} #{} applies {:?}", node.name, node.node, node.fused_activation);
+//! }
+//! ```
+//!
+//! The report describes the plan but cannot be fed back: TF Lite's arena planner is internal
+//! to the interpreter, which has no API to import tensor offsets, so every interpreter plans
+//! again in `allocate_tensors`, together with the kernels' `prepare`. To cut cold starts,
+//! parse a model once and build interpreters with `InterpreterBuilder::into_shared`, and keep
+//! a `RunProfile` so tuning is not repeated.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::kernel::{self, TfLiteAllocationType, TfLiteNode};
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+use crate::AllocationKind;
+
+cpp! {{
+    #include "tensorflow/lite/interpreter.h"
+    #include "tensorflow/lite/builtin_ops.h"
+    #include "tensorflow/lite/c/builtin_op_data.h"
+
+    using namespace tflite;
+}}
+
+/// An activation a kernel applies to its output instead of a separate node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FusedActivation {
+    None,
+    Relu,
+    ReluN1To1,
+    Relu6,
+    Tanh,
+    SignBit,
+    Sigmoid,
+}
+
+impl FusedActivation {
+    pub(crate) fn from_raw(activation: i32) -> Option<Self> {
+        Some(match activation {
+            0 => FusedActivation::None,
+            1 => FusedActivation::Relu,
+            2 => FusedActivation::ReluN1To1,
+            3 => FusedActivation::Relu6,
+            4 => FusedActivation::Tanh,
+            5 => FusedActivation::SignBit,
+            6 => FusedActivation::Sigmoid,
+            _ => return None,
+        })
+    }
+}
+
+/// A node of the execution plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodePlan {
+    pub node: usize,
+    pub name: String,
+    /// `None` for operators without a fused activation option.
+    pub fused_activation: Option<FusedActivation>,
+    pub inputs: Vec<TensorIndex>,
+    pub outputs: Vec<TensorIndex>,
+    /// Scratch tensors the kernel requested in `prepare`.
+    pub temporaries: Vec<TensorIndex>,
+}
+
+/// Where the memory planner placed a tensor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TensorPlacement {
+    pub tensor: TensorIndex,
+    pub name: String,
+    pub allocation_type: TfLiteAllocationType,
+    /// Byte offset in the arena of `allocation_type`, for allocated arena tensors.
+    pub offset: Option<usize>,
+    pub bytes: usize,
+    /// Positions in the execution plan of the first and last node using the tensor.
+    pub lifetime: Option<(usize, usize)>,
+}
+
+impl TensorPlacement {
+    fn overlaps(&self, other: &TensorPlacement) -> bool {
+        match (self.offset, other.offset) {
+            (Some(a), Some(b)) if self.allocation_type == other.allocation_type => {
+                a < b + other.bytes && b < a + self.bytes
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Decisions of the kernels and the memory planner after `allocate_tensors`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlanReport {
+    /// Nodes in execution order.
+    pub nodes: Vec<NodePlan>,
+    pub tensors: Vec<TensorPlacement>,
+}
+
+impl PlanReport {
+    /// Nodes applying an activation themselves.
+    pub fn fused(&self) -> impl Iterator<Item = &NodePlan> {
+        self.nodes
+            .iter()
+            .filter(|node| node.fused_activation.is_some_and(|a| a != FusedActivation::None))
+    }
+
+    /// Bytes spanned by the tensors in the arena of `allocation_type`.
+    pub fn arena_bytes(&self, allocation_type: TfLiteAllocationType) -> usize {
+        self.tensors
+            .iter()
+            .filter(|t| t.allocation_type == allocation_type)
+            .filter_map(|t| t.offset.map(|offset| offset + t.bytes))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Bytes of the read-only tensors, the weights, used in place from the model buffer.
+    pub fn read_only_bytes(&self) -> usize {
+        self.tensors
+            .iter()
+            .filter(|t| t.allocation_type == TfLiteAllocationType::kTfLiteMmapRo)
+            .map(|t| t.bytes)
+            .sum()
+    }
+
+    /// Tensors reusing memory of `tensor` at other times of the invocation.
+    pub fn sharing(&self, tensor: TensorIndex) -> Vec<TensorIndex> {
+        let placement = match self.tensors.iter().find(|t| t.tensor == tensor) {
+            Some(placement) => placement,
+            None => return Vec::new(),
+        };
+        self.tensors
+            .iter()
+            .filter(|t| t.tensor != tensor && t.bytes > 0 && placement.overlaps(t))
+            .map(|t| t.tensor)
+            .collect()
+    }
+}
+
+impl fmt::Display for PlanReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} nodes, {} with fused activations, arena {} bytes, persistent arena {} bytes",
+            self.nodes.len(),
+            self.fused().count(),
+            self.arena_bytes(TfLiteAllocationType::kTfLiteArenaRw),
+            self.arena_bytes(TfLiteAllocationType::kTfLiteArenaRwPersistent)
+        )?;
+        for node in &self.nodes {
+            write!(f, "  #{:<4} {}", node.node, node.name)?;
+            match node.fused_activation {
+                Some(activation) if activation != FusedActivation::None => {
+                    writeln!(f, " + {:?}", activation)?
+                }
+                _ => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn node(&self, node: usize) -> Option<&TfLiteNode> {
+        let interpreter = self.handle();
+        let node = node as i32;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let ptr = unsafe {
+            cpp!([interpreter as "const Interpreter*", node as "int"]
+                  -> *const TfLiteNode as "const TfLiteNode*" {
+                const auto* pair = interpreter->node_and_registration(node);
+                return pair ? &pair->first : nullptr;
+            })
+        };
+        unsafe { ptr.as_ref() }
+    }
+
+    /// The activation fused into `node`, if its operator has the option.
+    fn fused_activation(&self, node: usize) -> Option<FusedActivation> {
+        self.builtin_params(node)?.fused_activation()
+    }
+
+    /// Read-only tensors whose data is paged in from the memory-mapped model file on use.
+    /// Empty unless the model was built from an `Mmap`; build interpreters from
+    /// `FlatBufferModel::copy_to_heap` to serve them from RAM instead.
+    pub fn mapped_weights(&self) -> Vec<TensorIndex> {
+        let model = self.model();
+        if model.allocation().kind() != AllocationKind::Mmap {
+            return Vec::new();
+        }
+        let buffer = model.buffer().as_ptr_range();
+        let (start, end) = (buffer.start as usize, buffer.end as usize);
+        (0..self.tensors_size() as TensorIndex)
+            .filter(|&index| {
+                self.tensor_inner(index).is_some_and(|tensor| {
+                    let address = unsafe { tensor.data.raw_const } as usize;
+                    tensor.allocation_type == TfLiteAllocationType::kTfLiteMmapRo
+                        && address >= start
+                        && address < end
+                })
+            })
+            .collect()
+    }
+
+    /// Reports fused activations of the execution plan nodes and the arena offsets and
+    /// lifetimes of the tensors. Offsets are only known after `allocate_tensors`.
+    pub fn plan_report(&self) -> PlanReport {
+        let mut nodes = Vec::new();
+        let mut lifetimes: BTreeMap<TensorIndex, (usize, usize)> = BTreeMap::new();
+        for (position, &node) in self.execution_plan().iter().enumerate() {
+            let node = node as usize;
+            let (inputs, outputs, temporaries) = match self.node(node) {
+                Some(raw) => (
+                    kernel::inputs(raw).to_vec(),
+                    kernel::outputs(raw).to_vec(),
+                    kernel::temporaries(raw).to_vec(),
+                ),
+                None => Default::default(),
+            };
+            for &tensor in inputs.iter().chain(&outputs).chain(&temporaries) {
+                let lifetime = lifetimes.entry(tensor).or_insert((position, position));
+                lifetime.1 = position;
+            }
+            nodes.push(NodePlan {
+                node,
+                name: self.op_name(node).unwrap_or_default(),
+                fused_activation: self.fused_activation(node),
+                inputs,
+                outputs,
+                temporaries,
+            });
+        }
+
+        let arena = |allocation_type| {
+            allocation_type == TfLiteAllocationType::kTfLiteArenaRw
+                || allocation_type == TfLiteAllocationType::kTfLiteArenaRwPersistent
+        };
+        let mut tensors = Vec::new();
+        let mut bases: BTreeMap<u32, usize> = BTreeMap::new();
+        for index in 0..self.tensors_size() as TensorIndex {
+            let tensor = match self.tensor_inner(index) {
+                Some(tensor) => tensor,
+                None => continue,
+            };
+            let address = unsafe { tensor.data.raw_const } as usize;
+            let offset = if arena(tensor.allocation_type) && address != 0 {
+                let base = bases.entry(tensor.allocation_type as u32).or_insert(address);
+                *base = (*base).min(address);
+                Some(address)
+            } else {
+                None
+            };
+            tensors.push(TensorPlacement {
+                tensor: index,
+                name: self.tensor_info(index).map(|info| info.name).unwrap_or_default(),
+                allocation_type: tensor.allocation_type,
+                offset,
+                bytes: tensor.bytes,
+                lifetime: lifetimes.get(&index).copied(),
+            });
+        }
+        for placement in &mut tensors {
+            let base = bases.get(&(placement.allocation_type as u32)).copied().unwrap_or(0);
+            placement.offset = placement.offset.map(|address| address - base);
+        }
+        PlanReport { nodes, tensors }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_plan_report() {
+        let node = |node, name: &str, activation| NodePlan {
+            node,
+            name: name.to_string(),
+            fused_activation: activation,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            temporaries: Vec::new(),
+        };
+        let tensor = |tensor, allocation_type, offset, bytes| TensorPlacement {
+            tensor,
+            name: String::new(),
+            allocation_type,
+            offset,
+            bytes,
+            lifetime: None,
+        };
+        let arena = TfLiteAllocationType::kTfLiteArenaRw;
+        let report = PlanReport {
+            nodes: vec![
+                node(0, "CONV_2D", Some(FusedActivation::Relu6)),
+                node(1, "ADD", Some(FusedActivation::None)),
+                node(2, "SOFTMAX", None),
+            ],
+            tensors: vec![
+                tensor(0, arena, Some(0), 64),
+                tensor(1, arena, Some(64), 32),
+                tensor(2, arena, Some(32), 64),
+                tensor(3, TfLiteAllocationType::kTfLiteMmapRo, None, 16),
+            ],
+        };
+        assert_eq!(report.fused().map(|node| node.node).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(report.arena_bytes(arena), 96);
+        assert_eq!(report.sharing(2), vec![0, 1]);
+        assert_eq!(report.sharing(3), Vec::<TensorIndex>::new());
+        assert_eq!(report.read_only_bytes(), 16);
+        assert_eq!(
+            report.to_string(),
+            "3 nodes, 1 with fused activations, arena 96 bytes, persistent arena 0 bytes\n  \
+             #0    CONV_2D + Relu6\n  #1    ADD\n  #2    SOFTMAX\n"
+        );
+    }
+}
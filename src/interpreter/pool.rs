@@ -0,0 +1,195 @@
+//! Interpreters of one model shared by the request threads of a server:
+//!
+//! ```ignore
+//! let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default())?.into_shared();
+//! let pool = Arc::new(InterpreterPool::new(&builder, num_cpus)?);
+//! // request threads
+//! let class = pool.with(|interpreter| classify(interpreter, &image))?;
+//! ```
+
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, PoisonError};
+
+use super::op_resolver::OpResolver;
+use super::{Interpreter, SharedInterpreterBuilder};
+use crate::{Error, Result};
+
+/// A fixed set of interpreters lent to one thread at a time. Threads wait when all of them
+/// are in use.
+pub struct InterpreterPool<'a, Op>
+where
+    Op: OpResolver,
+{
+    idle: Mutex<Vec<Interpreter<'a, Op>>>,
+    returned: Condvar,
+    size: usize,
+}
+
+/// An interpreter lent by an `InterpreterPool`, returned to it when dropped.
+pub struct PooledInterpreter<'p, 'a, Op>
+where
+    Op: OpResolver,
+{
+    pool: &'p InterpreterPool<'a, Op>,
+    interpreter: Option<Interpreter<'a, Op>>,
+}
+
+impl<'a, Op> InterpreterPool<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Builds `size` single-threaded interpreters, the usual setup when requests run in
+    /// parallel.
+    pub fn new(builder: &SharedInterpreterBuilder<'a, Op>, size: usize) -> Result<Self> {
+        Self::with_setup(builder, size, |interpreter| interpreter.set_num_threads(1))
+    }
+
+    /// Builds `size` interpreters prepared by `setup`, e.g. applying a `RunProfile`.
+    pub fn with_setup<F>(
+        builder: &SharedInterpreterBuilder<'a, Op>,
+        size: usize,
+        mut setup: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(&mut Interpreter<'a, Op>) -> Result<()>,
+    {
+        let interpreters = (0..size)
+            .map(|_| {
+                let mut interpreter = builder.build()?;
+                setup(&mut interpreter)?;
+                Ok(interpreter)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_interpreters(interpreters)
+    }
+
+    /// Lends `interpreters`, which should be of the same model.
+    pub fn from_interpreters(interpreters: Vec<Interpreter<'a, Op>>) -> Result<Self> {
+        if interpreters.is_empty() {
+            return Err(Error::internal_error(
+                "interpreter pool requires at least one interpreter",
+            ));
+        }
+        Ok(Self {
+            size: interpreters.len(),
+            idle: Mutex::new(interpreters),
+            returned: Condvar::new(),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of interpreters not lent at the moment.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Lends an interpreter, waiting for one to be returned if all are in use.
+    pub fn get(&self) -> PooledInterpreter<'_, 'a, Op> {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(interpreter) = idle.pop() {
+                return PooledInterpreter { pool: self, interpreter: Some(interpreter) };
+            }
+            idle = self.returned.wait(idle).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Lends an interpreter if one is idle.
+    pub fn try_get(&self) -> Option<PooledInterpreter<'_, 'a, Op>> {
+        let interpreter = self.idle.lock().unwrap_or_else(PoisonError::into_inner).pop()?;
+        Some(PooledInterpreter { pool: self, interpreter: Some(interpreter) })
+    }
+
+    /// Runs `f` on an interpreter of the pool, waiting for one if all are in use.
+    pub fn with<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Interpreter<'a, Op>) -> T,
+    {
+        f(&mut self.get())
+    }
+
+    /// The interpreters, once none is lent.
+    pub fn into_interpreters(self) -> Vec<Interpreter<'a, Op>> {
+        self.idle.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<'p, 'a, Op> Deref for PooledInterpreter<'p, 'a, Op>
+where
+    Op: OpResolver,
+{
+    type Target = Interpreter<'a, Op>;
+
+    fn deref(&self) -> &Self::Target {
+        self.interpreter.as_ref().expect("interpreter is lent until drop")
+    }
+}
+
+impl<'p, 'a, Op> DerefMut for PooledInterpreter<'p, 'a, Op>
+where
+    Op: OpResolver,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.interpreter.as_mut().expect("interpreter is lent until drop")
+    }
+}
+
+impl<'p, 'a, Op> Drop for PooledInterpreter<'p, 'a, Op>
+where
+    Op: OpResolver,
+{
+    fn drop(&mut self) {
+        if let Some(interpreter) = self.interpreter.take() {
+            self.pool.idle.lock().unwrap_or_else(PoisonError::into_inner).push(interpreter);
+            self.pool.returned.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_interpreter_pool() {
+        fn send_sync<T: Send + Sync>(_t: &T) {}
+
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder =
+            InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap().into_shared();
+        let pool = Arc::new(InterpreterPool::new(&builder, 2).unwrap());
+        send_sync(&pool);
+        assert_eq!((pool.size(), pool.idle()), (2, 2));
+
+        let first = pool.get();
+        let second = pool.try_get().unwrap();
+        assert!(pool.try_get().is_none());
+        let waiting = {
+            let pool = pool.clone();
+            thread::spawn(move || pool.with(|interpreter| interpreter.invoke()))
+        };
+        drop(first);
+        waiting.join().unwrap().unwrap();
+        drop(second);
+        assert_eq!(pool.idle(), 2);
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let pool = pool.clone();
+                thread::spawn(move || pool.with(|interpreter| interpreter.invoke()))
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+        assert!(InterpreterPool::<BuiltinOpResolver>::from_interpreters(Vec::new()).is_err());
+    }
+}
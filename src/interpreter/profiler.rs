@@ -0,0 +1,523 @@
+use std::cmp::Reverse;
+use std::ffi::CStr;
+use std::fmt::{self, Write};
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::time::Duration;
+use std::{fs, process};
+
+use super::op_resolver::OpResolver;
+use super::partition::DelegationReport;
+use super::stats::json_string;
+use super::Interpreter;
+use crate::Result;
+
+cpp! {{
+    #include "tensorflow/lite/interpreter.h"
+    #include "tensorflow/lite/profiling/buffered_profiler.h"
+
+    using namespace tflite;
+    using tflite::profiling::BufferedProfiler;
+}}
+
+/// Event buffer size used when `invoke_profiled` enables profiling itself.
+pub const DEFAULT_MAX_PROFILE_EVENTS: usize = 1024;
+
+/// A native `BufferedProfiler` installed in an interpreter.
+pub(crate) struct Profiler {
+    handle: *mut c_void,
+}
+
+// The profiler is only touched through the interpreter owning it.
+unsafe impl Send for Profiler {}
+unsafe impl Sync for Profiler {}
+
+impl Profiler {
+    fn new(max_events: usize) -> Self {
+        let max_events = max_events as u32;
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([max_events as "uint32_t"] -> *mut c_void as "void*" {
+                return new BufferedProfiler(max_events);
+            })
+        };
+        Self { handle }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut c_void {
+        self.handle
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        let handle = self.handle;
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([handle as "BufferedProfiler*"] {
+                delete handle;
+            });
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProfileEventKind {
+    /// A node of the execution plan, including delegate kernels.
+    Operator,
+    /// An operation reported from inside a delegate kernel.
+    DelegateOperator,
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileEvent {
+    pub tag: String,
+    pub kind: ProfileEventKind,
+    /// Node index for operator events.
+    pub node: i64,
+    pub subgraph: i64,
+    pub begin_us: u64,
+    pub end_us: u64,
+}
+
+impl ProfileEvent {
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.end_us.saturating_sub(self.begin_us))
+    }
+
+    fn category(&self) -> &'static str {
+        match self.kind {
+            ProfileEventKind::Operator => "operator",
+            ProfileEventKind::DelegateOperator => "delegate_operator",
+            ProfileEventKind::Other => "other",
+        }
+    }
+
+    fn is_plan_node(&self) -> bool {
+        self.kind == ProfileEventKind::Operator && self.subgraph == 0
+    }
+}
+
+/// Time spent in one partition of a `DelegationReport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionTiming {
+    /// Index into `DelegationReport::partitions`.
+    pub partition: usize,
+    pub delegated: bool,
+    pub duration: Duration,
+}
+
+/// Events recorded during one profiled invocation.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub events: Vec<ProfileEvent>,
+}
+
+impl Profile {
+    /// Total time spent in execution plan nodes of the primary subgraph.
+    pub fn total(&self) -> Duration {
+        self.events.iter().filter(|e| e.is_plan_node()).map(ProfileEvent::duration).sum()
+    }
+
+    /// Attributes node time to the partitions of `report`, telling delegate time apart
+    /// from CPU time in mixed-execution models.
+    pub fn partition_timings(&self, report: &DelegationReport) -> Vec<PartitionTiming> {
+        report
+            .partitions
+            .iter()
+            .enumerate()
+            .map(|(i, partition)| PartitionTiming {
+                partition: i,
+                delegated: partition.delegated,
+                duration: self
+                    .events
+                    .iter()
+                    .filter(|e| {
+                        e.is_plan_node() && partition.plan_nodes.contains(&(e.node as usize))
+                    })
+                    .map(ProfileEvent::duration)
+                    .sum(),
+            })
+            .collect()
+    }
+
+    /// Total time spent in delegate and CPU partitions, respectively.
+    pub fn delegate_cpu_split(&self, report: &DelegationReport) -> (Duration, Duration) {
+        self.partition_timings(report).iter().fold(
+            (Duration::default(), Duration::default()),
+            |(delegate, cpu), timing| {
+                if timing.delegated {
+                    (delegate + timing.duration, cpu)
+                } else {
+                    (delegate, cpu + timing.duration)
+                }
+            },
+        )
+    }
+}
+
+/// Time spent in one execution plan node over the invocations of an `OpProfile`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeTiming {
+    pub node: usize,
+    /// Builtin operator name (e.g. `CONV_2D`), custom op name or delegate kernel name.
+    pub name: String,
+    /// Invocations the node ran in.
+    pub runs: usize,
+    pub total: Duration,
+}
+
+impl NodeTiming {
+    pub fn average(&self) -> Duration {
+        self.total / self.runs.max(1) as u32
+    }
+}
+
+/// Per-op timings accumulated over profiled invocations, e.g. to pick the ops worth
+/// delegating:
+///
+/// ```ignore
+/// let ops = interpreter.profile_ops(50)?;
+/// for op in ops.slowest().iter().take(5) {
+///     println!("{} #{}: {} us", op.name, op.node, op.average().as_micros());
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpProfile {
+    invocations: usize,
+    ops: Vec<NodeTiming>,
+}
+
+impl OpProfile {
+    /// Adds the node events of the primary subgraph of one invocation.
+    pub fn add(&mut self, profile: &Profile) {
+        self.invocations += 1;
+        for event in profile.events.iter().filter(|e| e.is_plan_node()) {
+            let node = event.node as usize;
+            let position = match self.ops.binary_search_by_key(&node, |op| op.node) {
+                Ok(position) => position,
+                Err(position) => {
+                    let op = NodeTiming {
+                        node,
+                        name: event.tag.clone(),
+                        runs: 0,
+                        total: Duration::default(),
+                    };
+                    self.ops.insert(position, op);
+                    position
+                }
+            };
+            let op = &mut self.ops[position];
+            op.runs += 1;
+            op.total += event.duration();
+        }
+    }
+
+    pub fn invocations(&self) -> usize {
+        self.invocations
+    }
+
+    /// The timings of the nodes that ran, by node index.
+    pub fn ops(&self) -> &[NodeTiming] {
+        &self.ops
+    }
+
+    /// The timings by decreasing total time.
+    pub fn slowest(&self) -> Vec<&NodeTiming> {
+        let mut ops: Vec<&NodeTiming> = self.ops.iter().collect();
+        ops.sort_by_key(|op| Reverse(op.total));
+        ops
+    }
+
+    /// Total time per op name, by decreasing time, e.g. all `CONV_2D` nodes together.
+    pub fn totals_by_name(&self) -> Vec<(&str, Duration)> {
+        let mut totals: Vec<(&str, Duration)> = Vec::new();
+        for op in &self.ops {
+            match totals.iter_mut().find(|(name, _)| *name == op.name) {
+                Some((_, total)) => *total += op.total,
+                None => totals.push((&op.name, op.total)),
+            }
+        }
+        totals.sort_by_key(|&(_, total)| Reverse(total));
+        totals
+    }
+}
+
+impl fmt::Display for OpProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>5}  {:<24} {:>6} {:>10} {:>12}",
+            "node", "op", "runs", "avg us", "total us"
+        )?;
+        for op in self.slowest() {
+            writeln!(
+                f,
+                "{:>5}  {:<24} {:>6} {:>10} {:>12}",
+                op.node,
+                op.name,
+                op.runs,
+                op.average().as_micros(),
+                op.total.as_micros()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Profiles of successive invocations in the Chrome trace event format, for chrome://tracing
+/// and Perfetto:
+///
+/// ```ignore
+/// let mut trace = ChromeTrace::new("detector");
+/// for frame in frames {
+///     trace.add(&interpreter.invoke_profiled()?);
+/// }
+/// trace.save("detector.trace.json")?;
+/// ```
+///
+/// Each invocation is a slice named `Invoke` spanning its events, which nest below it.
+/// Timestamps are those of the TF Lite profiler, microseconds of the wall clock.
+#[derive(Clone, Debug)]
+pub struct ChromeTrace {
+    pid: u32,
+    tid: u64,
+    name: String,
+    invocations: usize,
+    events: Vec<String>,
+}
+
+impl ChromeTrace {
+    /// An empty trace on a thread track named `name` in the current process.
+    pub fn new(name: &str) -> Self {
+        Self {
+            pid: process::id(),
+            tid: 0,
+            name: name.to_string(),
+            invocations: 0,
+            events: Vec::new(),
+        }
+    }
+
+    /// Places the events on thread `tid` of process `pid`, e.g. next to the app's own trace.
+    pub fn with_ids(mut self, pid: u32, tid: u64) -> Self {
+        self.pid = pid;
+        self.tid = tid;
+        self
+    }
+
+    /// Number of invocations added.
+    pub fn invocations(&self) -> usize {
+        self.invocations
+    }
+
+    /// Adds the events of one invocation; profiles without events are skipped.
+    pub fn add(&mut self, profile: &Profile) {
+        let begin = profile.events.iter().map(|e| e.begin_us).min();
+        let end = profile.events.iter().map(|e| e.end_us).max();
+        if let (Some(begin), Some(end)) = (begin, end) {
+            self.invocations += 1;
+            self.push("\"Invoke\"", "invoke", begin, end, "{}".to_string());
+        }
+        for event in &profile.events {
+            let args = format!("{{\"node\":{},\"subgraph\":{}}}", event.node, event.subgraph);
+            let name = json_string(&event.tag);
+            self.push(&name, event.category(), event.begin_us, event.end_us, args);
+        }
+    }
+
+    fn push(&mut self, name: &str, category: &str, begin: u64, end: u64, args: String) {
+        self.events.push(format!(
+            "{{\"name\":{},\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":{},\
+             \"tid\":{},\"args\":{}}}",
+            name,
+            category,
+            begin,
+            end.saturating_sub(begin),
+            self.pid,
+            self.tid,
+            args
+        ));
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        let _ = write!(
+            json,
+            "\n{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":{},\"tid\":{},\
+             \"args\":{{\"name\":{}}}}}",
+            self.pid,
+            self.tid,
+            json_string(&self.name)
+        );
+        for event in &self.events {
+            json.push_str(",\n");
+            json.push_str(event);
+        }
+        json.push_str("\n],\"displayTimeUnit\":\"ms\"}\n");
+        json
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Installs a profiler recording up to `max_events` events per invocation.
+    pub fn enable_profiling(&mut self, max_events: usize) {
+        let old = self.profiler.replace(Profiler::new(max_events));
+        self.install_profiler();
+        drop(old);
+    }
+
+    pub fn disable_profiling(&mut self) {
+        let old = self.profiler.take();
+        self.install_profiler();
+        drop(old);
+    }
+
+    /// Invokes the interpreter `invocations` times with profiling and sums up the time of
+    /// each op.
+    pub fn profile_ops(&mut self, invocations: usize) -> Result<OpProfile> {
+        let mut ops = OpProfile::default();
+        for _ in 0..invocations {
+            ops.add(&self.invoke_profiled()?);
+        }
+        Ok(ops)
+    }
+
+    /// Invokes the interpreter and returns the recorded events, enabling profiling with
+    /// `DEFAULT_MAX_PROFILE_EVENTS` if needed.
+    pub fn invoke_profiled(&mut self) -> Result<Profile> {
+        if self.profiler.is_none() {
+            self.enable_profiling(DEFAULT_MAX_PROFILE_EVENTS);
+        }
+        let profiler = self.profiler.as_ref().map(Profiler::as_ptr).unwrap();
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([profiler as "BufferedProfiler*"] {
+                profiler->Reset();
+                profiler->StartProfiling();
+            });
+        }
+        let result = self.invoke();
+
+        let mut events = Vec::new();
+        let events_ptr = &mut events;
+        #[allow(clippy::forget_copy, deprecated)]
+        unsafe {
+            cpp!([profiler as "BufferedProfiler*", events_ptr as "void*"] {
+                profiler->StopProfiling();
+                for (const auto* event : profiler->GetProfileEvents()) {
+                    int kind = 2;
+                    if (event->event_type == Profiler::EventType::OPERATOR_INVOKE_EVENT) {
+                        kind = 0;
+                    } else if (event->event_type ==
+                               Profiler::EventType::DELEGATE_OPERATOR_INVOKE_EVENT) {
+                        kind = 1;
+                    }
+                    const char* tag = event->tag.c_str();
+                    int64_t node = event->event_metadata;
+                    int64_t subgraph = event->extra_event_metadata;
+                    uint64_t begin = event->begin_timestamp_us;
+                    uint64_t end = event->end_timestamp_us;
+                    rust!(Profiler_collect_event [
+                        events_ptr: &mut Vec<ProfileEvent> as "void*",
+                        tag: *const c_char as "const char*",
+                        kind: i32 as "int",
+                        node: i64 as "int64_t",
+                        subgraph: i64 as "int64_t",
+                        begin: u64 as "uint64_t",
+                        end: u64 as "uint64_t"
+                    ] {
+                        crate::panic_guard::abort_on_panic("Profiler_collect_event", || {
+                            let kind = match kind {
+                                0 => ProfileEventKind::Operator,
+                                1 => ProfileEventKind::DelegateOperator,
+                                _ => ProfileEventKind::Other,
+                            };
+                            let tag = unsafe { CStr::from_ptr(tag) }.to_string_lossy().into_owned();
+                            events_ptr.push(ProfileEvent {
+                                tag, kind, node, subgraph, begin_us: begin, end_us: end,
+                            });
+                        });
+                    });
+                }
+            });
+        }
+        result.map(|()| Profile { events })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OpInfo, Partition};
+
+    #[test]
+    fn unittest_partition_timings() {
+        let event = |node, begin_us, end_us| ProfileEvent {
+            tag: String::new(),
+            kind: ProfileEventKind::Operator,
+            node,
+            subgraph: 0,
+            begin_us,
+            end_us,
+        };
+        let mut inner = event(0, 0, 100);
+        inner.kind = ProfileEventKind::DelegateOperator;
+        let profile = Profile { events: vec![event(3, 0, 300), inner, event(2, 300, 350)] };
+
+        let partition = |delegated, plan_nodes: Vec<usize>| Partition {
+            delegated,
+            ops: plan_nodes.iter().map(|&node| OpInfo { node, name: String::new() }).collect(),
+            plan_nodes,
+        };
+        let report = DelegationReport {
+            partitions: vec![partition(true, vec![3]), partition(false, vec![2])],
+        };
+
+        assert_eq!(profile.total(), Duration::from_micros(350));
+        assert_eq!(
+            profile.delegate_cpu_split(&report),
+            (Duration::from_micros(300), Duration::from_micros(50))
+        );
+
+        let mut named = profile.clone();
+        named.events[0].tag = "TfLiteXNNPackDelegate".to_string();
+        named.events[2].tag = "SOFTMAX".to_string();
+        let mut ops = OpProfile::default();
+        ops.add(&named);
+        named.events[2].end_us = 450;
+        ops.add(&named);
+        assert_eq!(ops.invocations(), 2);
+        assert_eq!(ops.ops().iter().map(|op| op.node).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(ops.ops()[0].runs, 2);
+        assert_eq!(ops.ops()[0].average(), Duration::from_micros(100));
+        assert_eq!(ops.slowest()[0].name, "TfLiteXNNPackDelegate");
+        assert_eq!(
+            ops.totals_by_name(),
+            vec![
+                ("TfLiteXNNPackDelegate", Duration::from_micros(600)),
+                ("SOFTMAX", Duration::from_micros(200))
+            ]
+        );
+        assert!(ops.to_string().contains("    2  SOFTMAX"));
+
+        let mut trace = ChromeTrace::new("model \"a\"").with_ids(7, 2);
+        trace.add(&profile);
+        trace.add(&Profile::default());
+        assert_eq!(trace.invocations(), 1);
+        let json = trace.to_json();
+        assert!(json.contains("\"args\":{\"name\":\"model \\\"a\\\"\"}"));
+        assert!(json.contains(
+            "{\"name\":\"Invoke\",\"cat\":\"invoke\",\"ph\":\"X\",\"ts\":0,\"dur\":350,"
+        ));
+        assert!(json.contains("\"cat\":\"delegate_operator\",\"ph\":\"X\",\"ts\":0,\"dur\":100,\"pid\":7,\"tid\":2,\"args\":{\"node\":0,\"subgraph\":0}"));
+    }
+}
@@ -0,0 +1,220 @@
+//! Quantization parameters of tensors, and conversions between `f32` values and the `u8`,
+//! `i8` or `i16` data of quantized inputs and outputs:
+//!
+//! ```ignore
+//! interpreter.quantize_input(interpreter.inputs()[0], &image)?;
+//! interpreter.invoke()?;
+//! let scores = interpreter.dequantize_output(interpreter.outputs()[0])?;
+//! ```
+
+use super::context::{element_count, Element, TensorInfo};
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+use crate::bindings::{self, TfLiteAffineQuantization, TfLiteQuantizationType};
+use crate::context::int_array_as_slice;
+use crate::{Error, Result};
+
+/// Affine quantization of a tensor: `real = scale * (quantized - zero_point)`, with one scale
+/// and zero point per index of `quantized_dimension` for per-channel quantization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantizationInfo {
+    pub scale: Vec<f32>,
+    pub zero_point: Vec<i32>,
+    pub quantized_dimension: usize,
+}
+
+/// Element types of quantized tensors.
+pub trait Quantized: Element {
+    fn from_i32_saturating(value: i32) -> Self;
+    fn to_i32(self) -> i32;
+}
+
+macro_rules! quantized {
+    ($($t:ty),*) => {$(
+        impl Quantized for $t {
+            fn from_i32_saturating(value: i32) -> Self {
+                value.max(<$t>::MIN.into()).min(<$t>::MAX.into()) as $t
+            }
+
+            fn to_i32(self) -> i32 {
+                self.into()
+            }
+        }
+    )*};
+}
+
+quantized!(u8, i8, i16);
+
+impl QuantizationInfo {
+    pub fn per_tensor(scale: f32, zero_point: i32) -> Self {
+        Self { scale: vec![scale], zero_point: vec![zero_point], quantized_dimension: 0 }
+    }
+
+    pub fn is_per_channel(&self) -> bool {
+        self.scale.len() > 1
+    }
+
+    /// The parameters of a tensor, or `None` if it is not quantized.
+    pub(crate) fn of(tensor: &bindings::TfLiteTensor) -> Option<Self> {
+        let quantization = &tensor.quantization;
+        if quantization.type_ == TfLiteQuantizationType::kTfLiteAffineQuantization
+            && !quantization.params.is_null()
+        {
+            let affine = unsafe { &*(quantization.params as *const TfLiteAffineQuantization) };
+            if !affine.scale.is_null() && !affine.zero_point.is_null() {
+                let scale = unsafe { &*affine.scale };
+                let scale = unsafe { scale.data.as_slice(scale.size.max(0) as usize) }.to_vec();
+                let zero_point = unsafe { int_array_as_slice(&*affine.zero_point) }.to_vec();
+                if !scale.is_empty() && scale.len() == zero_point.len() {
+                    return Some(Self {
+                        scale,
+                        zero_point,
+                        quantized_dimension: affine.quantized_dimension.max(0) as usize,
+                    });
+                }
+            }
+        }
+        if tensor.params.scale != 0.0 {
+            Some(Self::per_tensor(tensor.params.scale, tensor.params.zero_point))
+        } else {
+            None
+        }
+    }
+
+    /// The number of channels and of consecutive elements per channel of a tensor with
+    /// `dims`, so that element `i` is in channel `(i / inner) % channels`.
+    fn channels(&self, dims: &[usize]) -> Result<(usize, usize)> {
+        let channels = self.scale.len();
+        if channels == 1 {
+            return Ok((1, 1));
+        }
+        if dims.get(self.quantized_dimension) != Some(&channels) {
+            return Err(Error::InternalError(format!(
+                "{} channels do not match dim {} of {:?}",
+                channels, self.quantized_dimension, dims
+            )));
+        }
+        Ok((channels, element_count(&dims[self.quantized_dimension + 1..])?.max(1)))
+    }
+
+    /// Quantizes `values`, the elements of a tensor with `dims`, rounding to nearest and
+    /// saturating like TF Lite's `QUANTIZE`.
+    pub fn quantize<T: Quantized>(&self, dims: &[usize], values: &[f32]) -> Result<Vec<T>> {
+        let (channels, inner) = self.channels(dims)?;
+        Ok(values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                let c = (i / inner) % channels;
+                let q = (value / self.scale[c]).round() as i64 + i64::from(self.zero_point[c]);
+                T::from_i32_saturating(q.max(i64::from(i32::MIN)).min(i64::from(i32::MAX)) as i32)
+            })
+            .collect())
+    }
+
+    /// Dequantizes `values`, the elements of a tensor with `dims`.
+    pub fn dequantize<T: Quantized>(&self, dims: &[usize], values: &[T]) -> Result<Vec<f32>> {
+        let (channels, inner) = self.channels(dims)?;
+        Ok(values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                let c = (i / inner) % channels;
+                (i64::from(value.to_i32()) - i64::from(self.zero_point[c])) as f32 * self.scale[c]
+            })
+            .collect())
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// The quantization of a tensor, `None` for float tensors.
+    pub fn tensor_quantization(
+        &self,
+        tensor_index: TensorIndex,
+    ) -> Result<Option<QuantizationInfo>> {
+        let inner = self.tensor_inner(tensor_index).ok_or_else(|| {
+            Error::InternalError(format!("invalid tensor index {}", tensor_index))
+        })?;
+        Ok(QuantizationInfo::of(inner))
+    }
+
+    /// Writes `values` into a tensor, quantizing them for `u8`, `i8` and `i16` tensors and
+    /// copying them into `f32` tensors.
+    pub fn quantize_input(&mut self, tensor_index: TensorIndex, values: &[f32]) -> Result<()> {
+        use bindings::TfLiteType::*;
+
+        let quantization = self.tensor_quantization(tensor_index)?;
+        let mut tensor = self.tensor_mut(tensor_index)?;
+        let info = tensor.info().clone();
+        let params = || {
+            quantization.as_ref().ok_or_else(|| {
+                Error::InternalError(format!("tensor {} is not quantized", tensor_index))
+            })
+        };
+        match info.element_kind {
+            kTfLiteFloat32 => tensor.write(values),
+            kTfLiteUInt8 => tensor.write(&params()?.quantize::<u8>(&info.dims, values)?),
+            kTfLiteInt8 => tensor.write(&params()?.quantize::<i8>(&info.dims, values)?),
+            kTfLiteInt16 => tensor.write(&params()?.quantize::<i16>(&info.dims, values)?),
+            _ => Err(unsupported(&info)),
+        }
+    }
+
+    /// The data of a tensor as `f32`, dequantizing `u8`, `i8` and `i16` tensors.
+    pub fn dequantize_output(&self, tensor_index: TensorIndex) -> Result<Vec<f32>> {
+        use bindings::TfLiteType::*;
+
+        let quantization = self.tensor_quantization(tensor_index)?;
+        let tensor = self.tensor(tensor_index)?;
+        let info = tensor.info();
+        let params = || {
+            quantization.as_ref().ok_or_else(|| {
+                Error::InternalError(format!("tensor {} is not quantized", tensor_index))
+            })
+        };
+        match info.element_kind {
+            kTfLiteFloat32 => Ok(tensor.read::<f32>()?.into_owned()),
+            kTfLiteUInt8 => params()?.dequantize(&info.dims, &tensor.read::<u8>()?),
+            kTfLiteInt8 => params()?.dequantize(&info.dims, &tensor.read::<i8>()?),
+            kTfLiteInt16 => params()?.dequantize(&info.dims, &tensor.read::<i16>()?),
+            _ => Err(unsupported(info)),
+        }
+    }
+}
+
+fn unsupported(info: &TensorInfo) -> Error {
+    Error::InternalError(format!(
+        "tensor `{}` of `{:?}` is neither f32 nor quantized to u8, i8 or i16",
+        info.name, info.element_kind
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_quantization_info() {
+        let params = QuantizationInfo::per_tensor(0.5, 10);
+        assert!(!params.is_per_channel());
+        let quantized = params.quantize::<u8>(&[4], &[0.0, 1.0, -10.0, 200.0]).unwrap();
+        assert_eq!(quantized, vec![10, 12, 0, 255]);
+        assert_eq!(params.dequantize(&[2], &[10u8, 13]).unwrap(), vec![0.0, 1.5]);
+
+        let params = QuantizationInfo {
+            scale: vec![1.0, 0.25],
+            zero_point: vec![0, 0],
+            quantized_dimension: 0,
+        };
+        assert!(params.is_per_channel());
+        let quantized = params.quantize::<i8>(&[2, 2], &[1.0, -2.0, 1.0, -2.0]).unwrap();
+        assert_eq!(quantized, vec![1, -2, 4, -8]);
+        assert_eq!(params.dequantize(&[2, 2], &quantized).unwrap(), vec![1.0, -2.0, 1.0, -2.0]);
+        assert!(params.quantize::<i8>(&[3, 2], &[0.0; 6]).is_err());
+
+        assert_eq!(i16::from_i32_saturating(40_000), i16::MAX);
+    }
+}
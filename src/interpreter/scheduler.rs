@@ -0,0 +1,400 @@
+//! Sharing limited compute between the models of a device, e.g. a wake-word model and photo
+//! tagging on the CPU of an assistant:
+//!
+//! ```ignore
+//! let mut scheduler = Scheduler::new();
+//! let cpu = scheduler.add_resource(1);
+//! let wake_word = scheduler.add_model(wake_word, cpu)?;
+//! let tagger = scheduler.add_model(tagger, cpu)?;
+//! // audio thread
+//! let request = RunRequest::new(10).with_timeout(Duration::from_millis(50));
+//! let heard = scheduler.run(wake_word, request, |interpreter| spot(interpreter, &audio))?;
+//! // gallery thread, preempted by the audio thread whenever it needs the CPU
+//! let tags = scheduler.run(tagger, RunRequest::new(0), |interpreter| {
+//!     tag(interpreter, &photo)
+//! })?;
+//! ```
+//!
+//! Callers run their jobs on their own threads, one at a time per model and at most the
+//! capacity of the resource of the model at once. Waiting requests start by priority, then
+//! earliest deadline, then arrival. A request that cannot start before its deadline fails with
+//! `Error::Timeout`. A waiting request preempts a running request of lower priority that holds
+//! what it needs: the running invocation is cancelled at the next op boundary and its job runs
+//! again from the start once its turn comes back, so jobs must be restartable. Having started
+//! once, a restarted job waits for its turn without a deadline.
+
+use std::cmp::Reverse;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use super::op_resolver::OpResolver;
+use super::{CancellationToken, Interpreter};
+use crate::{Error, Result};
+
+/// Priority and deadline of a `Scheduler::run` call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunRequest {
+    /// Higher runs first.
+    pub priority: i32,
+    /// Latest start of the job.
+    pub deadline: Option<Instant>,
+    /// Whether higher priority requests may cancel and restart the job.
+    pub preemptible: bool,
+}
+
+impl RunRequest {
+    pub fn new(priority: i32) -> Self {
+        Self { priority, deadline: None, preemptible: true }
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn with_preemptible(mut self, preemptible: bool) -> Self {
+        self.preemptible = preemptible;
+        self
+    }
+}
+
+struct Model<'a, Op>
+where
+    Op: OpResolver,
+{
+    interpreter: Mutex<Interpreter<'a, Op>>,
+    token: CancellationToken,
+    resource: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Waiting {
+    id: u64,
+    model: usize,
+    request: RunRequest,
+    // Preempted after starting, so past its start deadline.
+    restarted: bool,
+}
+
+impl Waiting {
+    /// Orders waiting requests, the first one starting first.
+    fn key(&self) -> impl Ord {
+        let deadline = self.request.deadline;
+        (Reverse(self.request.priority), deadline.is_none(), deadline, self.id)
+    }
+}
+
+struct Running {
+    model: usize,
+    request: RunRequest,
+    preempted: bool,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    waiting: Vec<Waiting>,
+    running: Vec<Running>,
+    in_use: Vec<usize>,
+}
+
+/// Frees the resource of a running job, also when the job panics.
+struct Slot<'s, 'a, Op>
+where
+    Op: OpResolver,
+{
+    scheduler: &'s Scheduler<'a, Op>,
+    model: usize,
+    released: bool,
+}
+
+impl<'s, 'a, Op> Slot<'s, 'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Returns whether the job was preempted.
+    fn release(&mut self) -> bool {
+        self.released = true;
+        self.scheduler.release(self.model)
+    }
+}
+
+impl<'s, 'a, Op> Drop for Slot<'s, 'a, Op>
+where
+    Op: OpResolver,
+{
+    fn drop(&mut self) {
+        if !self.released {
+            self.scheduler.release(self.model);
+        }
+    }
+}
+
+/// Runs jobs on a set of interpreters by priority and deadline; see the module documentation.
+pub struct Scheduler<'a, Op>
+where
+    Op: OpResolver,
+{
+    models: Vec<Model<'a, Op>>,
+    capacities: Vec<usize>,
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl<'a, Op> Default for Scheduler<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn default() -> Self {
+        Self {
+            models: Vec::new(),
+            capacities: Vec::new(),
+            state: Mutex::new(State::default()),
+            changed: Condvar::new(),
+        }
+    }
+}
+
+impl<'a, Op> Scheduler<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a resource running up to `capacity` jobs at once, e.g. the number of cores given
+    /// to models or 1 for an accelerator, and returns its index.
+    pub fn add_resource(&mut self, capacity: usize) -> usize {
+        self.capacities.push(capacity.max(1));
+        self.state.get_mut().unwrap_or_else(PoisonError::into_inner).in_use.push(0);
+        self.capacities.len() - 1
+    }
+
+    /// Adds an interpreter running on `resource` and returns its model index. The scheduler
+    /// installs its own cancellation token on the interpreter.
+    pub fn add_model(
+        &mut self,
+        mut interpreter: Interpreter<'a, Op>,
+        resource: usize,
+    ) -> Result<usize> {
+        if resource >= self.capacities.len() {
+            return Err(Error::InternalError(format!("no resource {}", resource)));
+        }
+        let token = CancellationToken::new();
+        interpreter.set_cancellation_token(token.clone());
+        self.models.push(Model { interpreter: Mutex::new(interpreter), token, resource });
+        Ok(self.models.len() - 1)
+    }
+
+    pub fn models(&self) -> usize {
+        self.models.len()
+    }
+
+    /// The interpreter of `model`, once its running job finished.
+    pub fn interpreter(&self, model: usize) -> Option<MutexGuard<'_, Interpreter<'a, Op>>> {
+        let model = self.models.get(model)?;
+        Some(model.interpreter.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `job` on the interpreter of `model` once `request` gets its turn, on the calling
+    /// thread. Fails with `Error::Timeout` if the deadline passes first. A preempted job is
+    /// run again, its invocation having returned `Error::Cancelled`.
+    pub fn run<T, F>(&self, model: usize, request: RunRequest, mut job: F) -> Result<T>
+    where
+        F: FnMut(&mut Interpreter<'a, Op>) -> Result<T>,
+    {
+        if model >= self.models.len() {
+            return Err(Error::InternalError(format!("no model {}", model)));
+        }
+        let submitted = Instant::now();
+        let mut state = self.lock();
+        let mut waiting = Waiting { id: state.next_id, model, request, restarted: false };
+        state.next_id += 1;
+        loop {
+            state.waiting.push(waiting);
+            drop(self.admit(state, &waiting, submitted)?);
+
+            let mut slot = Slot { scheduler: self, model, released: false };
+            let result = {
+                let mut interpreter =
+                    self.models[model].interpreter.lock().unwrap_or_else(PoisonError::into_inner);
+                job(&mut interpreter)
+            };
+            let preempted = slot.release();
+            match result {
+                Err(Error::Cancelled) if preempted => waiting.restarted = true,
+                result => return result,
+            }
+            state = self.lock();
+        }
+    }
+
+    /// Frees the resource of the running job of `model` and returns whether it was preempted.
+    fn release(&self, model: usize) -> bool {
+        let mut state = self.lock();
+        // Jobs of a model run one at a time.
+        let position = state.running.iter().position(|running| running.model == model).unwrap();
+        let running = state.running.swap_remove(position);
+        state.in_use[self.models[model].resource] -= 1;
+        self.changed.notify_all();
+        running.preempted
+    }
+
+    /// Waits until `waiting` can start and marks it running, or fails at its deadline.
+    fn admit<'s>(
+        &self,
+        mut state: MutexGuard<'s, State>,
+        waiting: &Waiting,
+        submitted: Instant,
+    ) -> Result<MutexGuard<'s, State>> {
+        let resource = self.models[waiting.model].resource;
+        let deadline = waiting.request.deadline.filter(|_| !waiting.restarted);
+        loop {
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                state.waiting.retain(|other| other.id != waiting.id);
+                self.changed.notify_all();
+                return Err(Error::Timeout(now - submitted));
+            }
+
+            let model_busy = state.running.iter().any(|running| running.model == waiting.model);
+            let resource_full = state.in_use[resource] >= self.capacities[resource];
+            // The first request that could start on the resource goes first.
+            let first = state
+                .waiting
+                .iter()
+                .filter(|other| self.models[other.model].resource == resource)
+                .filter(|other| !state.running.iter().any(|running| running.model == other.model))
+                .min_by_key(|other| other.key())
+                .map(|other| other.id);
+            if !model_busy && !resource_full && first == Some(waiting.id) {
+                state.waiting.retain(|other| other.id != waiting.id);
+                state.in_use[resource] += 1;
+                state.running.push(Running {
+                    model: waiting.model,
+                    request: waiting.request,
+                    preempted: false,
+                });
+                self.models[waiting.model].token.reset();
+                return Ok(state);
+            }
+            self.preempt_for(&mut state, waiting, model_busy);
+
+            state = match deadline {
+                Some(deadline) => {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    self.changed
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self.changed.wait(state).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    /// Cancels the running job holding what `waiting` needs if it has a lower priority: the
+    /// job of its model, or else the lowest priority job on its resource if that is full.
+    fn preempt_for(&self, state: &mut State, waiting: &Waiting, model_busy: bool) {
+        let resource = self.models[waiting.model].resource;
+        let on_resource = |running: &&mut Running| self.models[running.model].resource == resource;
+        // One preemption at a time frees what the waiting request needs.
+        if state.running.iter_mut().filter(on_resource).any(|running| running.preempted) {
+            return;
+        }
+        let victim = if model_busy {
+            state.running.iter_mut().find(|running| running.model == waiting.model)
+        } else if state.in_use[resource] >= self.capacities[resource] {
+            state
+                .running
+                .iter_mut()
+                .filter(on_resource)
+                .min_by_key(|running| running.request.priority)
+        } else {
+            None
+        };
+        if let Some(victim) = victim {
+            if victim.request.preemptible && victim.request.priority < waiting.request.priority {
+                victim.preempted = true;
+                self.models[victim.model].token.cancel();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_scheduler() {
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder =
+            InterpreterBuilder::new(&model, BuiltinOpResolver::default()).unwrap().into_shared();
+        let mut scheduler = Scheduler::new();
+        let cpu = scheduler.add_resource(1);
+        let tagger = scheduler.add_model(builder.build().unwrap(), cpu).unwrap();
+        let wake_word = scheduler.add_model(builder.build().unwrap(), cpu).unwrap();
+        assert!(scheduler.add_model(builder.build().unwrap(), 1).is_err());
+
+        let expired = RunRequest::new(0).with_deadline(Instant::now());
+        let result = scheduler.run(tagger, expired, |_| -> Result<()> { panic!("ran late") });
+        assert!(matches!(result, Err(Error::Timeout(_))));
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            scheduler.run(tagger, RunRequest::new(0), |_| -> Result<()> { panic!("job failed") })
+        }));
+        assert!(panicked.is_err());
+        let request = RunRequest::new(0).with_timeout(Duration::from_secs(1));
+        scheduler.run(wake_word, request, |_| Ok(())).unwrap();
+
+        let events = Mutex::new(Vec::new());
+        let (started, tagging) = mpsc::channel();
+        thread::scope(|s| {
+            let tagged = s.spawn(|| {
+                let mut attempts = 0;
+                // Restarts after its start deadline passed.
+                let request = RunRequest::new(0).with_timeout(Duration::from_millis(100));
+                scheduler.run(tagger, request, |interpreter| {
+                    attempts += 1;
+                    events.lock().unwrap().push(format!("tag {}", attempts));
+                    if attempts == 1 {
+                        started.send(()).unwrap();
+                        // Runs until preempted.
+                        loop {
+                            interpreter.invoke()?;
+                        }
+                    }
+                    interpreter.invoke().map(|()| attempts)
+                })
+            });
+            tagging.recv().unwrap();
+            let request = RunRequest::new(10).with_timeout(Duration::from_secs(10));
+            scheduler
+                .run(wake_word, request, |interpreter| {
+                    events.lock().unwrap().push("wake word".to_string());
+                    thread::sleep(Duration::from_millis(200));
+                    interpreter.invoke()
+                })
+                .unwrap();
+            assert_eq!(tagged.join().unwrap().unwrap(), 2);
+        });
+        assert_eq!(*events.lock().unwrap(), vec!["tag 1", "wake word", "tag 2"]);
+        assert!(scheduler.interpreter(tagger).unwrap().cancellation_token().is_some());
+    }
+}
@@ -0,0 +1,326 @@
+//! Running a candidate model in the shadow of the production model, to see how far its outputs
+//! diverge on real inputs before rolling it out:
+//!
+//! ```ignore
+//! let mut shadow = Shadow::new(production, candidate)?.with_sampling(10);
+//! for frame in frames {
+//!     fill(shadow.production_mut(), frame)?;
+//!     shadow.invoke()?;
+//!     use_result(shadow.production())?;
+//! }
+//! print!("{}", shadow.stats());
+//! ```
+//!
+//! Only the production model's result is returned; candidate failures are counted, not
+//! propagated. The candidate runs after the production model on the same thread, so sample a
+//! fraction of invocations where the added latency matters.
+
+use std::fmt;
+
+use super::context::ElementKind;
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+use crate::{Error, Result};
+
+/// How one output of the candidate diverged from the production model.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutputDivergence {
+    pub name: String,
+    /// Invocations whose outputs were compared.
+    pub compared: u64,
+    /// Invocations whose outputs had different lengths or were not comparable.
+    pub mismatched: u64,
+    pub max_abs_diff: f64,
+    sum_abs_diff: f64,
+    values: u64,
+    /// Invocations where both outputs had their largest value at the same position.
+    pub top1_agreements: u64,
+}
+
+impl OutputDivergence {
+    fn add(&mut self, production: Option<&[f64]>, candidate: Option<&[f64]>) {
+        let (production, candidate) = match (production, candidate) {
+            (Some(p), Some(c)) if p.len() == c.len() => (p, c),
+            _ => {
+                self.mismatched += 1;
+                return;
+            }
+        };
+        self.compared += 1;
+        for (p, c) in production.iter().zip(candidate) {
+            let diff = (p - c).abs();
+            self.max_abs_diff = self.max_abs_diff.max(diff);
+            self.sum_abs_diff += diff;
+            self.values += 1;
+        }
+        if argmax(production) == argmax(candidate) {
+            self.top1_agreements += 1;
+        }
+    }
+
+    pub fn mean_abs_diff(&self) -> f64 {
+        if self.values == 0 {
+            0.0
+        } else {
+            self.sum_abs_diff / self.values as f64
+        }
+    }
+
+    /// Share of compared invocations agreeing on the top-1 position, e.g. the class.
+    pub fn top1_agreement(&self) -> f64 {
+        if self.compared == 0 {
+            0.0
+        } else {
+            self.top1_agreements as f64 / self.compared as f64
+        }
+    }
+}
+
+fn argmax(values: &[f64]) -> Option<usize> {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(i, _)| i)
+}
+
+/// Divergence statistics accumulated by a `Shadow`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShadowStats {
+    /// Invocations of the production model.
+    pub invocations: u64,
+    /// Invocations the candidate ran in shadow.
+    pub shadowed: u64,
+    /// Shadowed invocations the candidate failed.
+    pub candidate_failures: u64,
+    /// One entry per output, in output order.
+    pub outputs: Vec<OutputDivergence>,
+}
+
+impl fmt::Display for ShadowStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} invocations, {} shadowed, {} candidate failures",
+            self.invocations, self.shadowed, self.candidate_failures
+        )?;
+        for output in &self.outputs {
+            writeln!(
+                f,
+                "  {}: max |diff| {:.6}, mean |diff| {:.6}, top-1 agreement {:.1}%, {} mismatched",
+                output.name,
+                output.max_abs_diff,
+                output.mean_abs_diff(),
+                100.0 * output.top1_agreement(),
+                output.mismatched
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A production interpreter whose inputs are replayed on a candidate interpreter.
+pub struct Shadow<'a, Op>
+where
+    Op: OpResolver,
+{
+    production: Interpreter<'a, Op>,
+    candidate: Interpreter<'a, Op>,
+    sampling: u64,
+    stats: ShadowStats,
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// The values of a float or quantized tensor, dequantized.
+    fn dequantized(&self, index: TensorIndex) -> Option<Vec<f64>> {
+        let tensor = self.tensor_inner(index)?;
+        let buffer = self.tensor_buffer(index)?;
+        let (scale, zero_point) =
+            (f64::from(tensor.params.scale), f64::from(tensor.params.zero_point));
+        Some(match tensor.type_ {
+            ElementKind::kTfLiteFloat32 => buffer
+                .chunks_exact(4)
+                .map(|b| f64::from(f32::from_ne_bytes([b[0], b[1], b[2], b[3]])))
+                .collect(),
+            ElementKind::kTfLiteUInt8 if scale > 0.0 => {
+                buffer.iter().map(|&v| (f64::from(v) - zero_point) * scale).collect()
+            }
+            ElementKind::kTfLiteInt8 if scale > 0.0 => {
+                buffer.iter().map(|&v| (f64::from(v as i8) - zero_point) * scale).collect()
+            }
+            ElementKind::kTfLiteInt32 => buffer
+                .chunks_exact(4)
+                .map(|b| f64::from(i32::from_ne_bytes([b[0], b[1], b[2], b[3]])))
+                .collect(),
+            _ => return None,
+        })
+    }
+
+    /// Fails unless the interpreter, the `role` of `other`, has the same inputs (element kinds
+    /// and dims) and as many outputs.
+    pub(crate) fn check_same_inputs(&self, other: &Interpreter<'_, Op>, role: &str) -> Result<()> {
+        if self.inputs().len() != other.inputs().len()
+            || self.outputs().len() != other.outputs().len()
+        {
+            return Err(Error::InternalError(format!(
+                "the {} must have as many inputs and outputs as the model it stands in for",
+                role
+            )));
+        }
+        for (&input, &other_input) in self.inputs().iter().zip(other.inputs()) {
+            match (self.tensor_info(input), other.tensor_info(other_input)) {
+                (Some(a), Some(b)) if a.element_kind == b.element_kind && a.dims == b.dims => {}
+                (a, b) => {
+                    return Err(Error::InternalError(format!(
+                        "{} input {:?} does not match input {:?}",
+                        role, a, b
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the input data of `other`, an interpreter with the same inputs.
+    pub(crate) fn copy_inputs_from(&mut self, other: &Interpreter<'_, Op>) -> Result<()> {
+        let inputs = self.inputs().to_vec();
+        for (&source, input) in other.inputs().iter().zip(inputs) {
+            let src = other
+                .tensor_buffer(source)
+                .ok_or_else(|| Error::internal_error("input is not allocated"))?;
+            let dst = self
+                .tensor_buffer_mut(input)
+                .ok_or_else(|| Error::internal_error("target input is not allocated"))?;
+            if dst.len() != src.len() {
+                return Err(Error::internal_error("target input has another size"));
+            }
+            dst.copy_from_slice(src);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Op> Shadow<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Both interpreters must have allocated tensors, the same inputs (element kinds and
+    /// dims) and as many outputs. Outputs are compared dequantized, so quantized outputs of
+    /// one model compare to float outputs of the other.
+    pub fn new(production: Interpreter<'a, Op>, candidate: Interpreter<'a, Op>) -> Result<Self> {
+        candidate.check_same_inputs(&production, "candidate")?;
+        let outputs = production
+            .outputs()
+            .iter()
+            .map(|&output| OutputDivergence {
+                name: production.tensor_info(output).map(|info| info.name).unwrap_or_default(),
+                ..OutputDivergence::default()
+            })
+            .collect();
+        Ok(Self {
+            production,
+            candidate,
+            sampling: 1,
+            stats: ShadowStats { outputs, ..ShadowStats::default() },
+        })
+    }
+
+    /// Shadows only every `every`-th invocation.
+    pub fn with_sampling(mut self, every: u64) -> Self {
+        self.sampling = every.max(1);
+        self
+    }
+
+    pub fn production(&self) -> &Interpreter<'a, Op> {
+        &self.production
+    }
+
+    /// The production interpreter, whose inputs are to be filled before `invoke`.
+    pub fn production_mut(&mut self) -> &mut Interpreter<'a, Op> {
+        &mut self.production
+    }
+
+    pub fn candidate(&self) -> &Interpreter<'a, Op> {
+        &self.candidate
+    }
+
+    pub fn stats(&self) -> &ShadowStats {
+        &self.stats
+    }
+
+    /// Returns the statistics so far and starts over.
+    pub fn take_stats(&mut self) -> ShadowStats {
+        let outputs = self
+            .stats
+            .outputs
+            .iter()
+            .map(|output| OutputDivergence { name: output.name.clone(), ..Default::default() })
+            .collect();
+        std::mem::replace(&mut self.stats, ShadowStats { outputs, ..ShadowStats::default() })
+    }
+
+    pub fn into_parts(self) -> (Interpreter<'a, Op>, Interpreter<'a, Op>) {
+        (self.production, self.candidate)
+    }
+
+    /// Invokes the production model and, if sampled, the candidate on the same inputs. The
+    /// result is that of the production model.
+    pub fn invoke(&mut self) -> Result<()> {
+        self.production.invoke()?;
+        self.stats.invocations += 1;
+        if !(self.stats.invocations - 1).is_multiple_of(self.sampling) {
+            return Ok(());
+        }
+        self.stats.shadowed += 1;
+        if self.invoke_candidate().is_err() {
+            self.stats.candidate_failures += 1;
+            return Ok(());
+        }
+        let outputs = self.production.outputs().iter().zip(self.candidate.outputs());
+        for ((&output, &candidate_output), divergence) in outputs.zip(&mut self.stats.outputs) {
+            divergence.add(
+                self.production.dequantized(output).as_deref(),
+                self.candidate.dequantized(candidate_output).as_deref(),
+            );
+        }
+        Ok(())
+    }
+
+    fn invoke_candidate(&mut self) -> Result<()> {
+        self.candidate.copy_inputs_from(&self.production)?;
+        self.candidate.invoke()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_shadow_stats() {
+        let mut divergence = OutputDivergence { name: "scores".to_string(), ..Default::default() };
+        divergence.add(Some(&[0.1, 0.7, 0.2]), Some(&[0.1, 0.5, 0.4]));
+        divergence.add(Some(&[0.6, 0.3, 0.1]), Some(&[0.2, 0.7, 0.1]));
+        divergence.add(Some(&[1.0]), Some(&[1.0, 2.0]));
+        divergence.add(Some(&[1.0]), None);
+        assert_eq!((divergence.compared, divergence.mismatched), (2, 2));
+        assert!((divergence.max_abs_diff - 0.4).abs() < 1e-12);
+        assert!((divergence.mean_abs_diff() - 1.2 / 6.0).abs() < 1e-12);
+        assert_eq!(divergence.top1_agreement(), 0.5);
+
+        let stats = ShadowStats {
+            invocations: 4,
+            shadowed: 3,
+            candidate_failures: 1,
+            outputs: vec![divergence],
+        };
+        assert_eq!(
+            stats.to_string(),
+            "4 invocations, 3 shadowed, 1 candidate failures\n  scores: max |diff| 0.400000, \
+             mean |diff| 0.200000, top-1 agreement 50.0%, 2 mismatched\n"
+        );
+    }
+}
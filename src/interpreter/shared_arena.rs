@@ -0,0 +1,148 @@
+//! Several models taking turns in one activation arena, for devices that cannot hold the
+//! arenas of all of them at once:
+//!
+//! ```ignore
+//! let arena = SharedArena::new(4 << 20);
+//! arena.admit(&mut detector)?;
+//! arena.admit(&mut classifier)?;
+//! let boxes = arena.run(&mut detector, |interpreter| {
+//!     fill(interpreter, &frame)?;
+//!     interpreter.invoke()?;
+//!     read_boxes(interpreter)
+//! })?;
+//! ```
+//!
+//! Between runs the interpreters hold no activation memory, so the peak is the largest
+//! arena instead of their sum. Inputs, outputs and intermediates live in that memory: fill
+//! the inputs and read the outputs inside `run`. Weights and persistent tensors, e.g. the
+//! state of recurrent layers, are kept.
+
+use std::sync::{Mutex, PoisonError};
+
+use super::kernel::TfLiteAllocationType;
+use super::op_resolver::OpResolver;
+use super::Interpreter;
+use crate::{Error, Result};
+
+cpp! {{
+    #include "tensorflow/lite/interpreter.h"
+
+    using namespace tflite;
+}}
+
+/// An activation memory budget shared by interpreters that run one at a time.
+#[derive(Debug)]
+pub struct SharedArena {
+    budget: usize,
+    // The largest arena admitted so far. Held while an arena is allocated, so that only one
+    // is at a time.
+    peak: Mutex<usize>,
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Frees the arena of inputs, outputs and intermediates until the next
+    /// `allocate_tensors`, which reacquires it without planning again.
+    pub fn release_non_persistent_memory(&mut self) -> Result<()> {
+        let interpreter = self.handle_mut();
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([interpreter as "Interpreter*"] -> bool as "bool" {
+                return interpreter->primary_subgraph().ReleaseNonPersistentMemory() == kTfLiteOk;
+            })
+        };
+        if r {
+            Ok(())
+        } else {
+            Err(Error::internal_error("failed to release non-persistent memory"))
+        }
+    }
+}
+
+fn check_budget(arena: usize, budget: usize) -> Result<()> {
+    if arena > budget {
+        Err(Error::InternalError(format!(
+            "the model needs a {} byte arena, the shared arena holds {}",
+            arena, budget
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+impl SharedArena {
+    /// A budget of `budget` bytes for the largest arena of any admitted interpreter.
+    pub fn new(budget: usize) -> Self {
+        Self { budget, peak: Mutex::new(0) }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// The largest arena of the interpreters admitted so far, the memory runs need.
+    pub fn peak(&self) -> usize {
+        *self.peak.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Plans `interpreter`'s tensors, checks that its arena fits the budget and releases
+    /// it. Admit every interpreter once after applying delegates and resizing inputs.
+    pub fn admit<Op: OpResolver>(&self, interpreter: &mut Interpreter<'_, Op>) -> Result<usize> {
+        let mut peak = self.peak.lock().unwrap_or_else(PoisonError::into_inner);
+        interpreter.allocate_tensors()?;
+        let arena = interpreter.plan_report().arena_bytes(TfLiteAllocationType::kTfLiteArenaRw);
+        let checked = check_budget(arena, self.budget);
+        interpreter.release_non_persistent_memory()?;
+        checked?;
+        *peak = (*peak).max(arena);
+        Ok(arena)
+    }
+
+    /// Runs `f` with `interpreter`'s arena allocated, waiting for runs of other interpreters.
+    /// `f` fills the inputs, invokes and reads the outputs; the arena is released afterwards.
+    pub fn run<'a, Op, T, F>(&self, interpreter: &mut Interpreter<'a, Op>, f: F) -> Result<T>
+    where
+        Op: OpResolver,
+        F: FnOnce(&mut Interpreter<'a, Op>) -> Result<T>,
+    {
+        let _turn = self.peak.lock().unwrap_or_else(PoisonError::into_inner);
+        interpreter.allocate_tensors()?;
+        let result = f(interpreter);
+        interpreter.release_non_persistent_memory()?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_shared_arena() {
+        assert!(check_budget(1024, 1024).is_ok());
+        assert!(check_budget(1025, 1024).is_err());
+
+        let build = |path| {
+            let model = FlatBufferModel::build_from_file(path).unwrap();
+            InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap().build().unwrap()
+        };
+        let mut first = build("data/MNISTnet_uint8_quant.tflite");
+        let mut second = build("data/MNISTnet_v2_uint8_quant.tflite");
+        let arena = SharedArena::new(16 << 20);
+        let sizes = [arena.admit(&mut first).unwrap(), arena.admit(&mut second).unwrap()];
+        assert_eq!(arena.peak(), sizes[0].max(sizes[1]));
+        for interpreter in [&mut first, &mut second] {
+            let output = arena.run(interpreter, |interpreter| {
+                interpreter.invoke()?;
+                Ok(interpreter.output(0)?.bytes().to_vec())
+            });
+            assert_eq!(output.unwrap().len(), 10);
+        }
+        assert!(SharedArena::new(16).admit(&mut first).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Running the SignatureDefs of a model, the named functions a SavedModel was converted with,
+//! e.g. `encode` and `decode` of an encoder/decoder pair:
+//!
+//! ```ignore
+//! let mut encoder = interpreter.signature_runner("encode")?;
+//! encoder.input_mut::<i32>("tokens")?.copy_from_slice(&tokens);
+//! encoder.invoke()?;
+//! let state = encoder.output::<f32>("state")?.to_vec();
+//! ```
+//!
+//! The signatures are read from the model, as the runtime the bindings are generated for
+//! predates TF Lite's own signature APIs. Each runs its own subgraph.
+
+use std::mem;
+use std::time::Instant;
+
+use libc::size_t;
+
+use super::context::{ElemKindOf, TensorInfo};
+use super::op_resolver::OpResolver;
+use super::{check_aligned, delegate, raw_slice, raw_slice_mut, Interpreter, TensorIndex};
+use crate::bindings;
+use crate::metadata::flatbuffer::Table;
+use crate::{Error, Result};
+
+/// A signature: named inputs and outputs of a subgraph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureDef {
+    pub key: String,
+    pub subgraph: usize,
+    /// Names and tensor indices, in the subgraph, of the inputs.
+    pub inputs: Vec<(String, TensorIndex)>,
+    pub outputs: Vec<(String, TensorIndex)>,
+}
+
+impl SignatureDef {
+    pub fn input(&self, name: &str) -> Option<TensorIndex> {
+        self.inputs.iter().find(|(n, _)| n == name).map(|&(_, index)| index)
+    }
+
+    pub fn output(&self, name: &str) -> Option<TensorIndex> {
+        self.outputs.iter().find(|(n, _)| n == name).map(|&(_, index)| index)
+    }
+}
+
+/// The `signature_defs` of a model buffer; empty for models converted without them.
+pub(crate) fn signature_defs(model_buffer: &[u8]) -> Result<Vec<SignatureDef>> {
+    let tensor_maps = |def: &Table<'_>, id: usize| -> Result<Vec<(String, TensorIndex)>> {
+        def.tables(id)?
+            .iter()
+            .map(|map| {
+                let name = map.string(0)?.unwrap_or_default().to_string();
+                Ok((name, map.u32(1, 0)? as TensorIndex))
+            })
+            .collect()
+    };
+    Table::root(model_buffer)?
+        .tables(7)?
+        .iter()
+        .map(|def| {
+            Ok(SignatureDef {
+                key: def.string(2)?.unwrap_or_default().to_string(),
+                subgraph: def.u32(4, 0)? as usize,
+                inputs: tensor_maps(def, 0)?,
+                outputs: tensor_maps(def, 1)?,
+            })
+        })
+        .collect()
+}
+
+/// Invokes one signature of an interpreter, addressing its tensors by name.
+pub struct SignatureRunner<'i, 'a, Op>
+where
+    Op: OpResolver,
+{
+    interpreter: &'i mut Interpreter<'a, Op>,
+    signature: SignatureDef,
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn signature_defs(&self) -> Result<Vec<SignatureDef>> {
+        signature_defs(self.model().buffer())
+    }
+
+    pub fn signature_keys(&self) -> Result<Vec<String>> {
+        Ok(self.signature_defs()?.into_iter().map(|def| def.key).collect())
+    }
+
+    /// A runner of the signature `key`, allocating the tensors of its subgraph.
+    pub fn signature_runner(&mut self, key: &str) -> Result<SignatureRunner<'_, 'a, Op>> {
+        let signature =
+            self.signature_defs()?.into_iter().find(|def| def.key == key).ok_or_else(|| {
+                Error::InternalError(format!("the model has no signature `{}`", key))
+            })?;
+        if signature.subgraph >= self.subgraphs_size() {
+            return Err(Error::InternalError(format!(
+                "signature `{}` runs missing subgraph {}",
+                key, signature.subgraph
+            )));
+        }
+        let mut runner = SignatureRunner { interpreter: self, signature };
+        if runner.signature.subgraph != 0 {
+            runner.allocate_tensors()?;
+        }
+        Ok(runner)
+    }
+
+    fn subgraph_tensor(
+        &self,
+        subgraph: usize,
+        index: TensorIndex,
+    ) -> Option<&bindings::TfLiteTensor> {
+        let interpreter = self.handle();
+        let subgraph = subgraph as size_t;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let ptr = unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                subgraph as "size_t",
+                index as "int"
+            ] -> *const bindings::TfLiteTensor as "const TfLiteTensor*" {
+                auto* graph = interpreter->subgraph(subgraph);
+                if (index < 0 || static_cast<size_t>(index) >= graph->tensors_size()) {
+                    return nullptr;
+                }
+                return graph->tensor(index);
+            })
+        };
+        if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { &*ptr })
+        }
+    }
+}
+
+impl<'i, 'a, Op> SignatureRunner<'i, 'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn signature(&self) -> &SignatureDef {
+        &self.signature
+    }
+
+    pub fn input_names(&self) -> impl Iterator<Item = &str> {
+        self.signature.inputs.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn output_names(&self) -> impl Iterator<Item = &str> {
+        self.signature.outputs.iter().map(|(name, _)| name.as_str())
+    }
+
+    fn input_index(&self, name: &str) -> Result<TensorIndex> {
+        self.signature.input(name).ok_or_else(|| {
+            Error::InternalError(format!(
+                "signature `{}` has no input `{}`",
+                self.signature.key, name
+            ))
+        })
+    }
+
+    fn output_index(&self, name: &str) -> Result<TensorIndex> {
+        self.signature.output(name).ok_or_else(|| {
+            Error::InternalError(format!(
+                "signature `{}` has no output `{}`",
+                self.signature.key, name
+            ))
+        })
+    }
+
+    fn tensor(&self, index: TensorIndex) -> Result<&bindings::TfLiteTensor> {
+        self.interpreter
+            .subgraph_tensor(self.signature.subgraph, index)
+            .ok_or_else(|| Error::InternalError(format!("invalid tensor index {}", index)))
+    }
+
+    fn checked_tensor<T: ElemKindOf>(&self, index: TensorIndex) -> Result<&bindings::TfLiteTensor> {
+        let tensor = self.tensor(index)?;
+        let info: TensorInfo = tensor.into();
+        if info.element_kind != T::elem_kind_of() {
+            return Err(Error::InternalError(format!(
+                "Invalid type reference of `{:?}` to the original type `{:?}`",
+                T::elem_kind_of(),
+                info.element_kind
+            )));
+        }
+        check_aligned::<T>(index, unsafe { tensor.data.raw_const } as usize)?;
+        Ok(tensor)
+    }
+
+    pub fn input_info(&self, name: &str) -> Result<TensorInfo> {
+        Ok(self.tensor(self.input_index(name)?)?.into())
+    }
+
+    pub fn output_info(&self, name: &str) -> Result<TensorInfo> {
+        Ok(self.tensor(self.output_index(name)?)?.into())
+    }
+
+    pub fn input<T: ElemKindOf>(&self, name: &str) -> Result<&[T]> {
+        let tensor = self.checked_tensor::<T>(self.input_index(name)?)?;
+        Ok(unsafe {
+            raw_slice(tensor.data.raw_const as *const T, tensor.bytes / mem::size_of::<T>())
+        })
+    }
+
+    pub fn input_mut<T: ElemKindOf>(&mut self, name: &str) -> Result<&mut [T]> {
+        let tensor = self.checked_tensor::<T>(self.input_index(name)?)?;
+        Ok(unsafe { raw_slice_mut(tensor.data.raw as *mut T, tensor.bytes / mem::size_of::<T>()) })
+    }
+
+    pub fn output<T: ElemKindOf>(&self, name: &str) -> Result<&[T]> {
+        let tensor = self.checked_tensor::<T>(self.output_index(name)?)?;
+        Ok(unsafe {
+            raw_slice(tensor.data.raw_const as *const T, tensor.bytes / mem::size_of::<T>())
+        })
+    }
+
+    /// Changes the dims of input `name`; takes effect on the next `allocate_tensors`.
+    pub fn resize_input(&mut self, name: &str, dims: &[i32]) -> Result<()> {
+        let index = self.input_index(name)?;
+        if self.signature.subgraph == 0 {
+            return self.interpreter.resize_input_tensor(index, dims);
+        }
+        if dims.iter().any(|&dim| dim < 0) {
+            return Err(Error::InternalError(format!("invalid input dims {:?}", dims)));
+        }
+        let interpreter = self.interpreter.handle_mut();
+        let subgraph = self.signature.subgraph as size_t;
+        let dims_ptr = dims.as_ptr();
+        let dims_len = dims.len() as size_t;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([
+                interpreter as "Interpreter*",
+                subgraph as "size_t",
+                index as "int",
+                dims_ptr as "const int*",
+                dims_len as "size_t"
+            ] -> bool as "bool" {
+                std::vector<int> dims(dims_ptr, dims_ptr + dims_len);
+                return interpreter->subgraph(subgraph)->ResizeInputTensor(index, dims) == kTfLiteOk;
+            })
+        };
+        if r {
+            Ok(())
+        } else {
+            Err(Error::InternalError(format!("failed to resize input `{}` to {:?}", name, dims)))
+        }
+    }
+
+    pub fn allocate_tensors(&mut self) -> Result<()> {
+        if self.signature.subgraph == 0 {
+            return self.interpreter.allocate_tensors();
+        }
+        let interpreter = self.interpreter.handle_mut();
+        let subgraph = self.signature.subgraph as size_t;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([interpreter as "Interpreter*", subgraph as "size_t"] -> bool as "bool" {
+                return interpreter->subgraph(subgraph)->AllocateTensors() == kTfLiteOk;
+            })
+        };
+        if r {
+            Ok(())
+        } else {
+            Err(Error::InternalError(format!(
+                "failed to allocate tensors of signature `{}`",
+                self.signature.key
+            )))
+        }
+    }
+
+    /// Runs the subgraph of the signature. The main subgraph runs like `Interpreter::invoke`,
+    /// with its hooks and cancellation.
+    pub fn invoke(&mut self) -> Result<()> {
+        let started = Instant::now();
+        let result = if self.signature.subgraph == 0 {
+            self.interpreter.invoke_graph()
+        } else {
+            self.invoke_subgraph()
+        };
+        self.interpreter.record_invoke(Some(&self.signature.key), started, &result);
+        result
+    }
+
+    fn invoke_subgraph(&mut self) -> Result<()> {
+        let _guards = delegate::lock_all(&self.interpreter.delegates);
+        let interpreter = &mut *self.interpreter.handle;
+        let subgraph = self.signature.subgraph as size_t;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let r = unsafe {
+            cpp!([interpreter as "Interpreter*", subgraph as "size_t"] -> bool as "bool" {
+                return interpreter->subgraph(subgraph)->Invoke() == kTfLiteOk;
+            })
+        };
+        if r {
+            Ok(())
+        } else {
+            Err(Error::InternalError(format!(
+                "failed to invoke signature `{}`",
+                self.signature.key
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::flatbuffer::builder::{finish, Value};
+
+    #[test]
+    fn unittest_signature_defs() {
+        let map = |name, index| Value::Table(vec![Some(Value::Str(name)), Some(Value::U32(index))]);
+        let def = |key, subgraph, inputs, outputs| {
+            Value::Table(vec![
+                Some(Value::Tables(inputs)),
+                Some(Value::Tables(outputs)),
+                Some(Value::Str(key)),
+                None,
+                Some(Value::U32(subgraph)),
+            ])
+        };
+        let mut model: Vec<Option<Value>> = (0..7).map(|_| None).collect();
+        model.push(Some(Value::Tables(vec![
+            def("encode", 0, vec![map("tokens", 0)], vec![map("state", 3)]),
+            def("decode", 1, vec![map("state", 0), map("step", 1)], vec![map("logits", 5)]),
+        ])));
+        let buffer = finish(&Value::Table(model), b"TFL3");
+
+        let defs = signature_defs(&buffer).unwrap();
+        assert_eq!(defs.len(), 2);
+        assert_eq!(defs[0].key, "encode");
+        assert_eq!(defs[0].outputs, vec![("state".to_string(), 3)]);
+        assert_eq!(defs[1].subgraph, 1);
+        assert_eq!(defs[1].input("step"), Some(1));
+        assert_eq!(defs[1].output("state"), None);
+
+        let buffer = finish(&Value::Table(vec![Some(Value::U32(3))]), b"TFL3");
+        assert!(signature_defs(&buffer).unwrap().is_empty());
+    }
+}
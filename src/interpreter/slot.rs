@@ -0,0 +1,172 @@
+//! Replacing the model behind a long-running service without dropping requests:
+//!
+//! ```ignore
+//! let slot = Arc::new(InterpreterSlot::new(model, BuiltinOpResolver::default())?);
+//! // request threads
+//! let class = slot.with(|interpreter| classify(interpreter, &image))?;
+//! // on a model update
+//! slot.swap_model_in_background(FlatBufferModel::build_from_file(path)?);
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use super::op_resolver::OpResolver;
+use super::{FlatBufferModel, Interpreter, InterpreterBuilder};
+use crate::Result;
+
+type Setup<Op> = dyn Fn(&mut Interpreter<'static, Op>) -> Result<()> + Send + Sync;
+
+struct Served<Op>
+where
+    Op: OpResolver,
+{
+    generation: u64,
+    interpreter: Mutex<Interpreter<'static, Op>>,
+}
+
+/// A serving handle for one interpreter whose model can be replaced while requests run.
+///
+/// Requests run on the interpreter that is current when they start. `swap_model` builds and
+/// sets up the new interpreter before switching, so requests neither wait for the build nor
+/// fail; the old interpreter is dropped once its last request finished.
+pub struct InterpreterSlot<Op>
+where
+    Op: OpResolver + Clone + 'static,
+{
+    resolver: Op,
+    setup: Box<Setup<Op>>,
+    current: Mutex<Arc<Served<Op>>>,
+    generations: AtomicU64,
+    // Serializes swaps, so the last one started is the one serving afterwards.
+    swap_lock: Mutex<()>,
+}
+
+impl<Op> InterpreterSlot<Op>
+where
+    Op: OpResolver + Clone + 'static,
+{
+    /// Serves `model` with interpreters whose tensors are allocated.
+    pub fn new(model: FlatBufferModel, resolver: Op) -> Result<Self> {
+        Self::with_setup(model, resolver, |interpreter| interpreter.allocate_tensors())
+    }
+
+    /// Serves `model` with interpreters prepared by `setup`, e.g. applying a `RunProfile`.
+    /// `setup` runs for every swapped in model, too.
+    pub fn with_setup<F>(model: FlatBufferModel, resolver: Op, setup: F) -> Result<Self>
+    where
+        F: Fn(&mut Interpreter<'static, Op>) -> Result<()> + Send + Sync + 'static,
+    {
+        let setup: Box<Setup<Op>> = Box::new(setup);
+        let interpreter = Self::prepare(&setup, model, resolver.clone())?;
+        Ok(Self {
+            resolver,
+            setup,
+            current: Mutex::new(Arc::new(Served {
+                generation: 0,
+                interpreter: Mutex::new(interpreter),
+            })),
+            generations: AtomicU64::new(0),
+            swap_lock: Mutex::new(()),
+        })
+    }
+
+    fn prepare(
+        setup: &Setup<Op>,
+        model: FlatBufferModel,
+        resolver: Op,
+    ) -> Result<Interpreter<'static, Op>> {
+        let mut interpreter = InterpreterBuilder::new(model, resolver)?.build()?;
+        setup(&mut interpreter)?;
+        Ok(interpreter)
+    }
+
+    fn served(&self) -> Arc<Served<Op>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Number of models swapped in so far; requests can compare it to detect an update.
+    pub fn generation(&self) -> u64 {
+        self.served().generation
+    }
+
+    /// Runs `f` on the current interpreter, waiting for other requests on it.
+    pub fn with<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut Interpreter<'static, Op>) -> T,
+    {
+        let served = self.served();
+        let mut interpreter = served.interpreter.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&mut interpreter)
+    }
+
+    /// Builds and sets up an interpreter for `model`, then makes it serve new requests and
+    /// returns its generation. The old model keeps serving if this fails.
+    pub fn swap_model(&self, model: FlatBufferModel) -> Result<u64> {
+        let _guard = self.swap_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        let interpreter = Self::prepare(&self.setup, model, self.resolver.clone())?;
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed) + 1;
+        let served = Arc::new(Served { generation, interpreter: Mutex::new(interpreter) });
+        let previous = {
+            let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+            std::mem::replace(&mut *current, served)
+        };
+        // Dropped outside the lock; requests still running on it keep it alive.
+        drop(previous);
+        Ok(generation)
+    }
+
+    /// `swap_model` on a new thread.
+    pub fn swap_model_in_background(
+        self: &Arc<Self>,
+        model: FlatBufferModel,
+    ) -> JoinHandle<Result<u64>> {
+        let slot = self.clone();
+        thread::spawn(move || slot.swap_model(model))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+
+    #[test]
+    fn unittest_interpreter_slot() {
+        let model = |path| FlatBufferModel::build_from_file(path).unwrap();
+        let slot = Arc::new(
+            InterpreterSlot::new(
+                model("data/MNISTnet_uint8_quant.tflite"),
+                Arc::new(BuiltinOpResolver::default()),
+            )
+            .unwrap(),
+        );
+        assert_eq!(slot.generation(), 0);
+        slot.with(|interpreter| interpreter.invoke()).unwrap();
+
+        // A request running during the swap finishes on the old interpreter.
+        let in_flight = slot.served();
+        let swap = slot.swap_model_in_background(model("data/MNISTnet_v2_uint8_quant.tflite"));
+        in_flight.interpreter.lock().unwrap().invoke().unwrap();
+        assert_eq!(swap.join().unwrap().unwrap(), 1);
+        assert_eq!(in_flight.generation, 0);
+        assert_eq!(slot.generation(), 1);
+        slot.with(|interpreter| interpreter.invoke()).unwrap();
+
+        // The old model keeps serving when the new one fails to set up.
+        let setups = AtomicU64::new(0);
+        let slot = InterpreterSlot::with_setup(
+            model("data/MNISTnet_uint8_quant.tflite"),
+            Arc::new(BuiltinOpResolver::default()),
+            move |interpreter| match setups.fetch_add(1, Ordering::Relaxed) {
+                0 => interpreter.allocate_tensors(),
+                _ => Err(crate::Error::internal_error("setup failed")),
+            },
+        )
+        .unwrap();
+        assert!(slot.swap_model(model("data/MNISTnet_v2_uint8_quant.tflite")).is_err());
+        assert_eq!(slot.generation(), 0);
+        slot.with(|interpreter| interpreter.invoke()).unwrap();
+    }
+}
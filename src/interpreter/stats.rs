@@ -0,0 +1,263 @@
+//! Per-tensor activation statistics accumulated across invocations.
+//!
+//! ```ignore
+//! let collector = ActivationCollector::default();
+//! interpreter.set_op_observer(collector.clone());
+//! for sample in samples {
+//!     fill(&mut interpreter, sample)?;
+//!     interpreter.invoke()?;
+//! }
+//! std::fs::write("activations.json", collector.snapshot().to_json())?;
+//! ```
+//!
+//! Quantized outputs are dequantized, so statistics of float and quantized models compare
+//! directly.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use super::context::ElementKind;
+use super::hooks::{OpContext, OpObserver};
+use super::TensorIndex;
+
+/// Running statistics of the values of one tensor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TensorStats {
+    pub name: String,
+    /// Node producing the tensor, and its operator.
+    pub node: usize,
+    pub op_name: String,
+    /// Number of values observed over all invocations.
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    mean: f64,
+    // Sum of squared differences from the mean (Welford).
+    m2: f64,
+}
+
+impl TensorStats {
+    fn new(name: String, node: usize, op_name: String) -> Self {
+        Self {
+            name,
+            node,
+            op_name,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    /// Adds the finite values of `values`.
+    pub fn add<I: IntoIterator<Item = f64>>(&mut self, values: I) {
+        for v in values.into_iter().filter(|v| v.is_finite()) {
+            self.count += 1;
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+            let delta = v - self.mean;
+            self.mean += delta / self.count as f64;
+            self.m2 += delta * (v - self.mean);
+        }
+    }
+
+    /// Combines statistics of the same tensor, e.g. collected by several interpreters.
+    pub fn merge(&mut self, other: &TensorStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.mean
+        }
+    }
+
+    /// Population standard deviation.
+    pub fn std(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+}
+
+/// Statistics of the outputs of every node, keyed by subgraph and tensor index.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActivationStats {
+    pub tensors: BTreeMap<(usize, TensorIndex), TensorStats>,
+}
+
+pub(super) fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+pub(crate) fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl ActivationStats {
+    pub fn get(&self, subgraph: usize, tensor: TensorIndex) -> Option<&TensorStats> {
+        self.tensors.get(&(subgraph, tensor))
+    }
+
+    pub fn merge(&mut self, other: &ActivationStats) {
+        for (key, stats) in &other.tensors {
+            match self.tensors.get_mut(key) {
+                Some(existing) => existing.merge(stats),
+                None => {
+                    self.tensors.insert(*key, stats.clone());
+                }
+            }
+        }
+    }
+
+    /// `{"tensors": [{"subgraph", "tensor", "name", "node", "op", "count", "min", "max",
+    /// "mean", "std"}, ...]}`, with `null` for statistics of tensors without values.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"tensors\":[");
+        for (i, ((subgraph, tensor), stats)) in self.tensors.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "\n{{\"subgraph\":{},\"tensor\":{},\"name\":{},\"node\":{},\"op\":{},\"count\":{},\
+                 \"min\":{},\"max\":{},\"mean\":{},\"std\":{}}}",
+                subgraph,
+                tensor,
+                json_string(&stats.name),
+                stats.node,
+                json_string(&stats.op_name),
+                stats.count,
+                json_number(stats.min),
+                json_number(stats.max),
+                json_number(stats.mean()),
+                json_number(stats.std())
+            );
+        }
+        json.push_str("\n]}\n");
+        json
+    }
+}
+
+/// An `OpObserver` accumulating `ActivationStats` of float and quantized node outputs.
+/// Clones share the statistics.
+#[derive(Clone, Debug, Default)]
+pub struct ActivationCollector {
+    stats: Arc<Mutex<ActivationStats>>,
+}
+
+impl ActivationCollector {
+    pub fn snapshot(&self) -> ActivationStats {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn reset(&self) {
+        *self.stats.lock().unwrap_or_else(PoisonError::into_inner) = ActivationStats::default();
+    }
+}
+
+/// The values of a float or quantized tensor, dequantized.
+fn values(op: &OpContext<'_>, index: TensorIndex) -> Option<Vec<f64>> {
+    let tensor = op.tensor(index)?;
+    let buffer = op.tensor_buffer(index)?;
+    let (scale, zero_point) = (f64::from(tensor.params.scale), f64::from(tensor.params.zero_point));
+    Some(match tensor.type_ {
+        ElementKind::kTfLiteFloat32 => {
+            op.tensor_data::<f32>(index)?.iter().map(|&v| f64::from(v)).collect()
+        }
+        ElementKind::kTfLiteUInt8 if scale > 0.0 => {
+            buffer.iter().map(|&v| (f64::from(v) - zero_point) * scale).collect()
+        }
+        ElementKind::kTfLiteInt8 if scale > 0.0 => {
+            buffer.iter().map(|&v| (f64::from(v as i8) - zero_point) * scale).collect()
+        }
+        _ => return None,
+    })
+}
+
+impl OpObserver for ActivationCollector {
+    fn after_op(&mut self, op: &OpContext<'_>) {
+        let mut stats = self.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        for &index in op.outputs() {
+            let values = match values(op, index) {
+                Some(values) => values,
+                None => continue,
+            };
+            stats
+                .tensors
+                .entry((op.subgraph, index))
+                .or_insert_with(|| {
+                    let name = op.tensor_info(index).map(|info| info.name).unwrap_or_default();
+                    TensorStats::new(name, op.node, op.op_name.to_string())
+                })
+                .add(values);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_activation_stats() {
+        let mut a = TensorStats::new("conv/out".to_string(), 2, "CONV_2D".to_string());
+        a.add(vec![1.0, 2.0, f64::NAN]);
+        let mut b = a.clone();
+        b.count = 0;
+        b.mean = 0.0;
+        b.m2 = 0.0;
+        b.add(vec![3.0, 4.0]);
+        a.merge(&b);
+        assert_eq!((a.count, a.min, a.max), (4, 1.0, 4.0));
+        assert!((a.mean() - 2.5).abs() < 1e-12);
+        assert!((a.std() - 1.25f64.sqrt()).abs() < 1e-12);
+
+        let mut stats = ActivationStats::default();
+        stats.tensors.insert((0, 7), a);
+        stats.tensors.insert((0, 9), TensorStats::new("a\"b".to_string(), 3, "RELU".to_string()));
+        assert_eq!(
+            stats.to_json(),
+            "{\"tensors\":[\n\
+             {\"subgraph\":0,\"tensor\":7,\"name\":\"conv/out\",\"node\":2,\"op\":\"CONV_2D\",\
+             \"count\":4,\"min\":1,\"max\":4,\"mean\":2.5,\"std\":1.118033988749895},\n\
+             {\"subgraph\":0,\"tensor\":9,\"name\":\"a\\\"b\",\"node\":3,\"op\":\"RELU\",\
+             \"count\":0,\"min\":null,\"max\":null,\"mean\":null,\"std\":null}\n]}\n"
+        );
+    }
+}
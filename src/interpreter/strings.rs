@@ -0,0 +1,183 @@
+//! Reading and writing `kTfLiteString` tensors, e.g. the text input of a model tokenizing in
+//! the graph:
+//!
+//! ```ignore
+//! let input = interpreter.inputs()[0];
+//! interpreter.resize_input_tensor(input, &[2])?;
+//! interpreter.allocate_tensors()?;
+//! interpreter.set_tensor_strings(input, &["hello world", "good night"].iter().collect())?;
+//! interpreter.invoke()?;
+//! let labels = interpreter.tensor_strs(interpreter.outputs()[0])?;
+//! ```
+
+use std::iter::FromIterator;
+use std::str;
+
+use super::context::{element_count, ElementKind, TensorInfo};
+use super::op_resolver::OpResolver;
+use super::{Interpreter, TensorIndex};
+use crate::bindings;
+use crate::kernel::{parse_strings, write_dynamic_strings};
+use crate::{Error, Result};
+
+/// Strings to write to a string tensor, packed by TF Lite's `tflite::DynamicBuffer`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StringBuffer {
+    data: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl StringBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<S: AsRef<[u8]>>(&mut self, string: S) {
+        self.data.extend_from_slice(string.as_ref());
+        self.ends.push(self.data.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        let starts = std::iter::once(0).chain(self.ends.iter().copied());
+        starts.zip(&self.ends).map(move |(start, &end)| &self.data[start..end])
+    }
+}
+
+impl<S: AsRef<[u8]>> FromIterator<S> for StringBuffer {
+    fn from_iter<I: IntoIterator<Item = S>>(strings: I) -> Self {
+        let mut buffer = StringBuffer::new();
+        strings.into_iter().for_each(|string| buffer.add(string));
+        buffer
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn string_tensor(&self, tensor_index: TensorIndex) -> Result<&bindings::TfLiteTensor> {
+        let tensor = self
+            .tensor_inner(tensor_index)
+            .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        if tensor.type_ != ElementKind::kTfLiteString {
+            return Err(Error::InternalError(format!(
+                "tensor {} is a `{:?}` tensor, not a string tensor",
+                tensor_index, tensor.type_
+            )));
+        }
+        Ok(tensor)
+    }
+
+    /// The strings of a `kTfLiteString` tensor, as bytes.
+    pub fn tensor_strings(&self, tensor_index: TensorIndex) -> Result<Vec<&[u8]>> {
+        self.string_tensor(tensor_index)?;
+        let buffer = self.tensor_buffer(tensor_index).unwrap_or(&[]);
+        if buffer.is_empty() {
+            return Ok(Vec::new());
+        }
+        parse_strings(buffer).ok_or_else(|| {
+            Error::InternalError(format!("tensor {} holds malformed strings", tensor_index))
+        })
+    }
+
+    /// The strings of a `kTfLiteString` tensor, failing unless all are UTF-8.
+    pub fn tensor_strs(&self, tensor_index: TensorIndex) -> Result<Vec<&str>> {
+        self.tensor_strings(tensor_index)?
+            .into_iter()
+            .map(|string| {
+                str::from_utf8(string).map_err(|e| {
+                    Error::InternalError(format!(
+                        "tensor {} holds non-UTF-8 strings: {}",
+                        tensor_index, e
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces the strings of a `kTfLiteString` tensor, one per element of its dims. Resize
+    /// an input with `resize_input_tensor` and `allocate_tensors` to change their count.
+    pub fn set_tensor_strings(
+        &mut self,
+        tensor_index: TensorIndex,
+        strings: &StringBuffer,
+    ) -> Result<()> {
+        let tensor = self.string_tensor(tensor_index)?;
+        let elements = element_count(&TensorInfo::from(tensor).dims)?;
+        if strings.len() != elements {
+            return Err(Error::InternalError(format!(
+                "tensor {} holds {} strings, got {}",
+                tensor_index,
+                elements,
+                strings.len()
+            )));
+        }
+        if tensor.allocation_type != bindings::TfLiteAllocationType::kTfLiteDynamic {
+            return Err(Error::InternalError(format!(
+                "string tensor {} is not dynamically allocated",
+                tensor_index
+            )));
+        }
+        let strings: Vec<&[u8]> = strings.iter().collect();
+        let tensor = self.tensor_inner_mut(tensor_index).unwrap();
+        write_dynamic_strings(tensor, &strings, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    use crate::model::stl::memory::UniquePtr;
+    use crate::model::stl::vector::VectorInsert;
+    use crate::model::{BufferT, Model, SubGraphT, TensorT, TensorType};
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_string_tensor() {
+        let buffer: StringBuffer = ["ab", "", "cde"].iter().collect();
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), vec![&b"ab"[..], b"", b"cde"]);
+
+        // A graph without operators whose string input is also its output.
+        let mut model = Model::default();
+        model.version = 3;
+        model.buffers.assign(vec![UniquePtr::<BufferT>::default()]);
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        let mut tensor: UniquePtr<TensorT> = Default::default();
+        tensor.shape.assign(vec![3]);
+        tensor.typ = TensorType::TensorType_STRING;
+        tensor.name.assign(&CString::new("text").unwrap());
+        subgraph.tensors.push_back(tensor);
+        subgraph.inputs.assign(vec![0]);
+        subgraph.outputs.assign(vec![0]);
+        model.subgraphs.push_back(subgraph);
+
+        let model = FlatBufferModel::build_from_model(&model).unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+        let mut interpreter = builder.build().unwrap();
+        interpreter.allocate_tensors().unwrap();
+        interpreter.set_tensor_strings(0, &buffer).unwrap();
+        interpreter.invoke().unwrap();
+        assert_eq!(interpreter.tensor_strs(0).unwrap(), vec!["ab", "", "cde"]);
+        assert_eq!(interpreter.tensor_info(0).unwrap().dims, vec![3]);
+
+        let two: StringBuffer = ["x", "y"].iter().collect();
+        assert!(interpreter.set_tensor_strings(0, &two).is_err());
+        interpreter.resize_input_tensor(0, &[2]).unwrap();
+        interpreter.allocate_tensors().unwrap();
+        interpreter.set_tensor_strings(0, &two).unwrap();
+        assert_eq!(interpreter.tensor_strings(0).unwrap(), vec![&b"x"[..], b"y"]);
+        assert!(interpreter.set_tensor_strings(1, &two).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Accounting invocations, e.g. for usage billing. Telemetry set on a builder reports every
+//! invocation of the interpreters it builds:
+//!
+//! ```ignore
+//! let telemetry = Telemetry::new("detector-v3", move |record: &InvokeRecord| {
+//!     usage.add(record.model, record.latency, record.status == InvokeStatus::Ok)
+//! });
+//! let builder = InterpreterBuilder::new(model, resolver)?.telemetry(telemetry).into_shared();
+//! ```
+//!
+//! The callback runs on the invoking thread right after each invocation, so it should only
+//! hand the record off, e.g. add to counters or send it to a channel.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::op_resolver::OpResolver;
+use super::Interpreter;
+use crate::{Error, Result};
+
+/// How an invocation ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvokeStatus {
+    Ok,
+    Cancelled,
+    TimedOut,
+    Failed,
+}
+
+impl InvokeStatus {
+    pub fn of(result: &Result<()>) -> Self {
+        match result {
+            Ok(()) => InvokeStatus::Ok,
+            Err(Error::Cancelled) => InvokeStatus::Cancelled,
+            Err(Error::Timeout(_)) => InvokeStatus::TimedOut,
+            Err(_) => InvokeStatus::Failed,
+        }
+    }
+}
+
+/// One invocation, as reported to `Telemetry`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvokeRecord<'r> {
+    /// The identifier the telemetry was created with.
+    pub model: &'r str,
+    /// The key of the signature run by a `SignatureRunner`, `None` for `Interpreter::invoke`.
+    pub signature: Option<&'r str>,
+    pub latency: Duration,
+    pub status: InvokeStatus,
+}
+
+type Callback = dyn Fn(&InvokeRecord<'_>) + Send + Sync;
+
+/// A callback receiving an `InvokeRecord` per invocation, tagged with a model identifier.
+/// Clones share the callback.
+#[derive(Clone)]
+pub struct Telemetry {
+    model: Arc<str>,
+    callback: Arc<Callback>,
+}
+
+impl Telemetry {
+    pub fn new<M, F>(model: M, callback: F) -> Self
+    where
+        M: Into<String>,
+        F: Fn(&InvokeRecord<'_>) + Send + Sync + 'static,
+    {
+        Self { model: model.into().into(), callback: Arc::new(callback) }
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn record(&self, signature: Option<&str>, started: Instant, result: &Result<()>) {
+        (self.callback)(&InvokeRecord {
+            model: &self.model,
+            signature,
+            latency: started.elapsed(),
+            status: InvokeStatus::of(result),
+        });
+    }
+}
+
+impl fmt::Debug for Telemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Telemetry").field("model", &self.model).finish()
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    /// Reports invocations to `telemetry`, replacing the telemetry set by the builder.
+    pub fn set_telemetry(&mut self, telemetry: Telemetry) {
+        self.telemetry = Some(telemetry);
+    }
+
+    pub fn clear_telemetry(&mut self) -> Option<Telemetry> {
+        self.telemetry.take()
+    }
+
+    pub fn telemetry(&self) -> Option<&Telemetry> {
+        self.telemetry.as_ref()
+    }
+
+    pub(crate) fn record_invoke(
+        &self,
+        signature: Option<&str>,
+        started: Instant,
+        result: &Result<()>,
+    ) {
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.record(signature, started, result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{CancellationToken, FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_telemetry() {
+        assert_eq!(
+            InvokeStatus::of(&Err(Error::Timeout(Duration::from_secs(1)))),
+            InvokeStatus::TimedOut
+        );
+        assert_eq!(InvokeStatus::of(&Err(Error::internal_error("oops"))), InvokeStatus::Failed);
+
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let telemetry = {
+            let records = records.clone();
+            Telemetry::new("mnist", move |record: &InvokeRecord| {
+                records.lock().unwrap().push((record.model.to_string(), record.status))
+            })
+        };
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default())
+            .unwrap()
+            .telemetry(telemetry)
+            .into_shared();
+        let (mut first, mut second) = (builder.build().unwrap(), builder.build().unwrap());
+        first.invoke().unwrap();
+        second.invoke().unwrap();
+
+        let token = CancellationToken::new();
+        second.set_cancellation_token(token.clone());
+        token.cancel();
+        assert!(second.invoke().is_err());
+        assert_eq!(second.clear_telemetry().unwrap().model(), "mnist");
+        assert!(second.invoke().is_err());
+
+        let ok = ("mnist".to_string(), InvokeStatus::Ok);
+        let cancelled = ("mnist".to_string(), InvokeStatus::Cancelled);
+        assert_eq!(*records.lock().unwrap(), vec![ok.clone(), ok, cancelled]);
+    }
+}
@@ -0,0 +1,307 @@
+//! Checked views of the tensors of an interpreter, so that reading inputs and outputs needs
+//! neither raw indices into `TfLiteTensor`s nor `unsafe`:
+//!
+//! ```ignore
+//! interpreter.input_mut(0)?.data_mut::<u8>()?.copy_from_slice(&image);
+//! interpreter.invoke()?;
+//! let scores = interpreter.output(0)?;
+//! println!("{} {:?}: {:?}", scores.name(), scores.dims(), scores.data::<u8>()?);
+//! ```
+//!
+//! Tensors in the arena of an interpreter are aligned for any element type, but constant
+//! tensors point into the model buffer and are only as aligned as it is, e.g. a model built
+//! with `FlatBufferModel::build_from_buffer` from a `Vec<u8>`. `data` fails on misaligned
+//! data instead of faulting on targets with strict alignment; `read` and `write` work at any
+//! alignment, copying when needed.
+
+use std::borrow::Cow;
+use std::ptr;
+
+use super::context::{ElemKindOf, Element, ElementKind, TensorInfo};
+use super::op_resolver::OpResolver;
+use super::{raw_slice, raw_slice_mut, Interpreter, TensorIndex};
+use crate::bindings;
+use crate::{Error, Result};
+
+/// A tensor of an interpreter, borrowed for reading.
+#[derive(Debug)]
+pub struct Tensor<'a> {
+    index: TensorIndex,
+    info: TensorInfo,
+    scale: f32,
+    zero_point: i32,
+    data: &'a [u8],
+}
+
+/// A tensor of an interpreter, borrowed for writing, e.g. an input before `invoke`.
+#[derive(Debug)]
+pub struct TensorMut<'a> {
+    index: TensorIndex,
+    info: TensorInfo,
+    scale: f32,
+    zero_point: i32,
+    data: &'a mut [u8],
+}
+
+/// Fails unless `bytes` holds a whole number of `T`.
+fn whole<T: Element>(bytes: &[u8]) -> Result<()> {
+    if bytes.chunks_exact(T::SIZE).remainder().is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InternalError(format!(
+            "{} bytes are not a whole number of {}-byte elements",
+            bytes.len(),
+            T::SIZE
+        )))
+    }
+}
+
+fn typed<T: ElemKindOf>(info: &TensorInfo) -> Result<()> {
+    if info.element_kind == T::elem_kind_of() {
+        Ok(())
+    } else {
+        Err(Error::InternalError(format!(
+            "Invalid type reference of `{:?}` to the original type `{:?}`",
+            T::elem_kind_of(),
+            info.element_kind
+        )))
+    }
+}
+
+macro_rules! tensor_accessors {
+    () => {
+        pub fn index(&self) -> TensorIndex {
+            self.index
+        }
+
+        pub fn info(&self) -> &TensorInfo {
+            &self.info
+        }
+
+        pub fn name(&self) -> &str {
+            &self.info.name
+        }
+
+        pub fn element_kind(&self) -> ElementKind {
+            self.info.element_kind
+        }
+
+        pub fn dims(&self) -> &[usize] {
+            &self.info.dims
+        }
+
+        /// Scale of a quantized tensor, 0 for float tensors.
+        pub fn scale(&self) -> f32 {
+            self.scale
+        }
+
+        pub fn zero_point(&self) -> i32 {
+            self.zero_point
+        }
+
+        /// The raw bytes, empty while tensors are not allocated.
+        pub fn bytes(&self) -> &[u8] {
+            self.data
+        }
+
+        /// The data as elements of `T`, which must be the element kind of the tensor.
+        pub fn data<T: ElemKindOf>(&self) -> Result<&[T]> {
+            typed::<T>(&self.info)?;
+            match unsafe { self.data.align_to::<T>() } {
+                ([], data, []) => Ok(data),
+                _ => Err(Error::internal_error("tensor data is not aligned")),
+            }
+        }
+
+        /// The data as elements of `T`, borrowed when aligned for `T` and copied otherwise.
+        pub fn read<T: Element>(&self) -> Result<Cow<'_, [T]>> {
+            typed::<T>(&self.info)?;
+            whole::<T>(self.data)?;
+            match unsafe { self.data.align_to::<T>() } {
+                ([], data, []) => Ok(Cow::Borrowed(data)),
+                _ => {
+                    Ok(Cow::Owned(self.data.chunks_exact(T::SIZE).map(T::from_ne_bytes).collect()))
+                }
+            }
+        }
+
+        /// The data encoded in little-endian order, e.g. to store it in a file.
+        pub fn to_le_bytes<T: Element>(&self) -> Result<Vec<u8>> {
+            typed::<T>(&self.info)?;
+            whole::<T>(self.data)?;
+            let mut bytes = vec![0; self.data.len()];
+            for (value, out) in self.data.chunks_exact(T::SIZE).zip(bytes.chunks_exact_mut(T::SIZE))
+            {
+                T::from_ne_bytes(value).write_le_bytes(out);
+            }
+            Ok(bytes)
+        }
+    };
+}
+
+impl<'a> Tensor<'a> {
+    tensor_accessors!();
+}
+
+impl<'a> TensorMut<'a> {
+    tensor_accessors!();
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        self.data
+    }
+
+    pub fn data_mut<T: ElemKindOf>(&mut self) -> Result<&mut [T]> {
+        typed::<T>(&self.info)?;
+        match unsafe { self.data.align_to_mut::<T>() } {
+            ([], data, []) => Ok(data),
+            _ => Err(Error::internal_error("tensor data is not aligned")),
+        }
+    }
+
+    /// Copies `values`, as many as the tensor holds, into the data at any alignment.
+    pub fn write<T: Element>(&mut self, values: &[T]) -> Result<()> {
+        typed::<T>(&self.info)?;
+        if values.len() * T::SIZE != self.data.len() {
+            return Err(Error::InternalError(format!(
+                "tensor {} holds {} bytes, got {} elements of {} bytes",
+                self.index,
+                self.data.len(),
+                values.len(),
+                T::SIZE
+            )));
+        }
+        unsafe {
+            ptr::copy_nonoverlapping(
+                values.as_ptr() as *const u8,
+                self.data.as_mut_ptr(),
+                self.data.len(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Fills the data from elements encoded in little-endian order, e.g. read from a file.
+    pub fn write_le_bytes<T: Element>(&mut self, bytes: &[u8]) -> Result<()> {
+        typed::<T>(&self.info)?;
+        whole::<T>(bytes)?;
+        if bytes.len() != self.data.len() {
+            return Err(Error::InternalError(format!(
+                "tensor {} holds {} bytes, got {}",
+                self.index,
+                self.data.len(),
+                bytes.len()
+            )));
+        }
+        for (value, out) in bytes.chunks_exact(T::SIZE).zip(self.data.chunks_exact_mut(T::SIZE)) {
+            unsafe { ptr::write_unaligned(out.as_mut_ptr() as *mut T, T::from_le_bytes(value)) };
+        }
+        Ok(())
+    }
+}
+
+impl<'a, Op> Interpreter<'a, Op>
+where
+    Op: OpResolver,
+{
+    fn checked_tensor(&self, index: TensorIndex) -> Result<&bindings::TfLiteTensor> {
+        self.tensor_inner(index)
+            .ok_or_else(|| Error::InternalError(format!("invalid tensor index {}", index)))
+    }
+
+    /// The tensor at `index` of the main subgraph.
+    pub fn tensor(&self, index: TensorIndex) -> Result<Tensor<'_>> {
+        let inner = self.checked_tensor(index)?;
+        Ok(Tensor {
+            index,
+            info: inner.into(),
+            scale: inner.params.scale,
+            zero_point: inner.params.zero_point,
+            data: unsafe { raw_slice(inner.data.raw_const as *const u8, inner.bytes) },
+        })
+    }
+
+    pub fn tensor_mut(&mut self, index: TensorIndex) -> Result<TensorMut<'_>> {
+        let inner = self.checked_tensor(index)?;
+        Ok(TensorMut {
+            index,
+            info: inner.into(),
+            scale: inner.params.scale,
+            zero_point: inner.params.zero_point,
+            data: unsafe { raw_slice_mut(inner.data.raw as *mut u8, inner.bytes) },
+        })
+    }
+
+    /// The `n`-th input, in the order of `inputs`.
+    pub fn input(&self, n: usize) -> Result<Tensor<'_>> {
+        self.tensor(position(self.inputs(), n, "input")?)
+    }
+
+    pub fn input_mut(&mut self, n: usize) -> Result<TensorMut<'_>> {
+        let index = position(self.inputs(), n, "input")?;
+        self.tensor_mut(index)
+    }
+
+    /// The `n`-th output, in the order of `outputs`.
+    pub fn output(&self, n: usize) -> Result<Tensor<'_>> {
+        self.tensor(position(self.outputs(), n, "output")?)
+    }
+}
+
+fn position(indices: &[TensorIndex], n: usize, what: &str) -> Result<TensorIndex> {
+    indices.get(n).copied().ok_or_else(|| {
+        Error::InternalError(format!("no {} {}, the model has {}", what, n, indices.len()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_tensor_view() {
+        assert_eq!(position(&[3, 7], 1, "input").unwrap(), 7);
+        assert!(position(&[3, 7], 2, "output").is_err());
+
+        let floats = [1.5f32, -2.0];
+        let bytes = unsafe { std::slice::from_raw_parts(floats.as_ptr() as *const u8, 8) };
+        let tensor = Tensor {
+            index: 3,
+            info: TensorInfo {
+                name: "logits".to_string(),
+                element_kind: ElementKind::kTfLiteFloat32,
+                dims: vec![1, 2],
+            },
+            scale: 0.0,
+            zero_point: 0,
+            data: bytes,
+        };
+        assert_eq!(tensor.data::<f32>().unwrap(), &floats);
+        assert!(tensor.data::<u8>().is_err());
+        assert_eq!((tensor.name(), tensor.dims()), ("logits", &[1, 2][..]));
+    }
+
+    #[test]
+    fn unittest_unaligned_tensor_data() {
+        let info = TensorInfo {
+            name: "embedding".to_string(),
+            element_kind: ElementKind::kTfLiteInt32,
+            dims: vec![2],
+        };
+        // Offset by one byte from a 4-byte aligned buffer, like a constant in a model buffer.
+        let mut buffer = [0u32; 3];
+        let bytes = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, 12) };
+        let mut tensor =
+            TensorMut { index: 0, info, scale: 0.0, zero_point: 0, data: &mut bytes[1..9] };
+        assert!(tensor.data::<i32>().is_err());
+
+        tensor.write(&[7i32, -2]).unwrap();
+        assert_eq!(&*tensor.read::<i32>().unwrap(), &[7, -2]);
+        assert!(tensor.write(&[7i32]).is_err());
+        assert!(tensor.read::<u8>().is_err());
+
+        let le = [1, 0, 0, 0, 0xfe, 0xff, 0xff, 0xff];
+        tensor.write_le_bytes::<i32>(&le).unwrap();
+        assert_eq!(&*tensor.read::<i32>().unwrap(), &[1, -2]);
+        assert_eq!(tensor.to_le_bytes::<i32>().unwrap(), le.to_vec());
+    }
+}
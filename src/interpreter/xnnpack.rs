@@ -0,0 +1,95 @@
+//! TF Lite's XNNPACK delegate, running float operators with XNNPACK's optimized CPU kernels,
+//! typically 2-4x faster than the builtin kernels for convolutional networks:
+//!
+//! ```ignore
+//! let xnnpack = XnnpackDelegate::new(4)?;
+//! interpreter.modify_graph_with_delegate(&xnnpack)?;
+//! interpreter.allocate_tensors()?;
+//! ```
+//!
+//! Operators XNNPACK does not support, e.g. most quantized ones in older releases, stay on
+//! the builtin kernels.
+//!
+//! With the `build` feature, the `xnnpack` feature builds TensorFlow Lite and XNNPACK with
+//! CMake into one static library; see the README for linking a library of your own instead.
+
+use std::ops::Deref;
+
+use super::Delegate;
+use crate::bindings::TfLiteDelegate;
+use crate::{Error, Result};
+
+cpp! {{
+    #include "tensorflow/lite/delegates/xnnpack/xnnpack_delegate.h"
+}}
+
+/// A `Delegate` created by `TfLiteXNNPackDelegateCreate`; apply it with
+/// `Interpreter::modify_graph_with_delegate`.
+#[derive(Clone)]
+pub struct XnnpackDelegate(Delegate);
+
+unsafe extern "C" fn delete(handle: *mut TfLiteDelegate) {
+    #[allow(clippy::forget_copy, clippy::useless_transmute, deprecated)]
+    unsafe {
+        cpp!([handle as "TfLiteDelegate*"] {
+            TfLiteXNNPackDelegateDelete(handle);
+        })
+    };
+}
+
+impl XnnpackDelegate {
+    /// A delegate with a pool of `num_threads` threads, or none for 0 or 1. The pool is
+    /// separate from the interpreter's threads, which only run the operators left to them.
+    pub fn new(num_threads: i32) -> Result<Self> {
+        if num_threads < 0 {
+            return Err(Error::InternalError(format!("invalid number of threads {}", num_threads)));
+        }
+        #[allow(clippy::forget_copy, deprecated)]
+        let handle = unsafe {
+            cpp!([num_threads as "int32_t"] -> *mut TfLiteDelegate as "TfLiteDelegate*" {
+                TfLiteXNNPackDelegateOptions options = TfLiteXNNPackDelegateOptionsDefault();
+                options.num_threads = num_threads;
+                return TfLiteXNNPackDelegateCreate(&options);
+            })
+        };
+        if handle.is_null() {
+            return Err(Error::internal_error("failed to create XNNPACK delegate"));
+        }
+        Ok(XnnpackDelegate(unsafe { Delegate::from_raw(handle, Some(delete)) }))
+    }
+}
+
+impl Deref for XnnpackDelegate {
+    type Target = Delegate;
+
+    fn deref(&self) -> &Delegate {
+        &self.0
+    }
+}
+
+impl From<XnnpackDelegate> for Delegate {
+    fn from(delegate: XnnpackDelegate) -> Self {
+        delegate.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_xnnpack_delegate() {
+        assert!(XnnpackDelegate::new(-1).is_err());
+
+        let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let mut interpreter =
+            InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap().build().unwrap();
+        let xnnpack = XnnpackDelegate::new(2).unwrap();
+        interpreter.modify_graph_with_delegate(&xnnpack).unwrap();
+        interpreter.allocate_tensors().unwrap();
+        interpreter.invoke().unwrap();
+        assert_eq!(interpreter.delegates().len(), 1);
+    }
+}
@@ -0,0 +1,507 @@
+//! Snapshots of what an interpreter does with a model on one device or build: tensor shapes,
+//! which operators were delegated, arena size and per-op timing. Diffing the snapshots of two
+//! devices shows why a model that is fast on a dev board is slow on a production SoC:
+//!
+//! ```ignore
+//! // On each device, with the same model and inputs:
+//! RunSnapshot::capture("rk3588 vendor image", &mut interpreter, 20)?.save("rk3588.json")?;
+//!
+//! let diff = SnapshotDiff::new(&RunSnapshot::load("devboard.json")?, &RunSnapshot::load("rk3588.json")?);
+//! print!("{}", diff);
+//! ```
+//!
+//! CPU operators are timed one by one. A delegate kernel is timed as a whole, under the range
+//! of nodes it took, e.g. `delegate #0-#41 (42 ops)`.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::kernel::TfLiteAllocationType;
+use crate::op_resolver::OpResolver;
+use crate::run_profile::{json, Value};
+use crate::{Error, Interpreter, OpInfo, ProfileEventKind, Result, TensorIndex};
+
+/// The element type and dims of a tensor.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TensorShape {
+    pub name: String,
+    /// Debug name of the element type, e.g. `kTfLiteUInt8`.
+    pub element_kind: String,
+    pub dims: Vec<usize>,
+}
+
+/// Where an operator of the graph ran.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpPlacement {
+    pub node: usize,
+    pub name: String,
+    pub delegated: bool,
+}
+
+/// Median time of a CPU operator or of a delegate kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpTiming {
+    /// `NAME #node` for CPU operators, `delegate #first-#last (n ops)` for delegate kernels.
+    pub label: String,
+    pub delegated: bool,
+    pub micros: u64,
+}
+
+/// The introspection data of an interpreter after profiled invocations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunSnapshot {
+    /// The device or build the snapshot was captured on.
+    pub label: String,
+    pub threads: i32,
+    pub invocations: usize,
+    /// Bytes of the read-write arena.
+    pub arena_bytes: usize,
+    /// Median time of the invocations.
+    pub total_micros: u64,
+    /// All tensors of the main subgraph, by index.
+    pub tensors: Vec<TensorShape>,
+    pub ops: Vec<OpPlacement>,
+    pub timings: Vec<OpTiming>,
+}
+
+fn median(mut samples: Vec<u64>) -> u64 {
+    samples.sort_unstable();
+    samples.get(samples.len() / 2).cloned().unwrap_or(0)
+}
+
+fn delegate_label(ops: &[OpInfo]) -> String {
+    match (ops.first(), ops.last()) {
+        (Some(first), Some(last)) => {
+            format!("delegate #{}-#{} ({} ops)", first.node, last.node, ops.len())
+        }
+        _ => "delegate (no ops)".to_string(),
+    }
+}
+
+impl RunSnapshot {
+    /// Invokes `interpreter` `invocations` times with profiling, on the inputs it holds, and
+    /// captures its state. The tensors must be allocated.
+    pub fn capture<Op: OpResolver>(
+        label: &str,
+        interpreter: &mut Interpreter<'_, Op>,
+        invocations: usize,
+    ) -> Result<Self> {
+        let report = interpreter.delegation_report();
+        let mut timed = Vec::new();
+        for partition in &report.partitions {
+            if partition.delegated {
+                if let Some(&node) = partition.plan_nodes.first() {
+                    timed.push((delegate_label(&partition.ops), true, node));
+                }
+            } else {
+                for (&node, op) in partition.plan_nodes.iter().zip(&partition.ops) {
+                    timed.push((format!("{} #{}", op.name, op.node), false, node));
+                }
+            }
+        }
+
+        let invocations = invocations.max(1);
+        let mut totals = Vec::with_capacity(invocations);
+        let mut samples = vec![Vec::with_capacity(invocations); timed.len()];
+        for _ in 0..invocations {
+            let profile = interpreter.invoke_profiled()?;
+            totals.push(profile.total().as_micros() as u64);
+            for ((_, _, node), samples) in timed.iter().zip(&mut samples) {
+                let micros = profile
+                    .events
+                    .iter()
+                    .filter(|e| e.kind == ProfileEventKind::Operator && e.subgraph == 0)
+                    .filter(|e| e.node as usize == *node)
+                    .map(|e| e.duration().as_micros() as u64)
+                    .sum();
+                samples.push(micros);
+            }
+        }
+
+        let tensors = (0..interpreter.tensors_size() as TensorIndex)
+            .filter_map(|index| interpreter.tensor_info(index))
+            .map(|info| TensorShape {
+                name: info.name,
+                element_kind: format!("{:?}", info.element_kind),
+                dims: info.dims,
+            })
+            .collect();
+        let ops = report
+            .partitions
+            .iter()
+            .flat_map(|partition| {
+                partition.ops.iter().map(move |op| OpPlacement {
+                    node: op.node,
+                    name: op.name.clone(),
+                    delegated: partition.delegated,
+                })
+            })
+            .collect();
+        let timings = timed
+            .into_iter()
+            .zip(samples)
+            .map(|((label, delegated, _), samples)| OpTiming {
+                label,
+                delegated,
+                micros: median(samples),
+            })
+            .collect();
+        Ok(Self {
+            label: label.to_string(),
+            threads: interpreter.num_threads(),
+            invocations,
+            arena_bytes: interpreter
+                .plan_report()
+                .arena_bytes(TfLiteAllocationType::kTfLiteArenaRw),
+            total_micros: median(totals),
+            tensors,
+            ops,
+            timings,
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        let string = |value: &str| Value::String(value.to_string());
+        let int = |value: u64| Value::Int(value.min(i64::MAX as u64) as i64);
+        let entry = |key: &str, value| (key.to_string(), value);
+        let tensors = self.tensors.iter().map(|tensor| {
+            Value::Table(vec![
+                entry("name", string(&tensor.name)),
+                entry("element_kind", string(&tensor.element_kind)),
+                entry("dims", Value::Array(tensor.dims.iter().map(|&d| int(d as u64)).collect())),
+            ])
+        });
+        let ops = self.ops.iter().map(|op| {
+            Value::Table(vec![
+                entry("node", int(op.node as u64)),
+                entry("name", string(&op.name)),
+                entry("delegated", Value::Bool(op.delegated)),
+            ])
+        });
+        let timings = self.timings.iter().map(|timing| {
+            Value::Table(vec![
+                entry("label", string(&timing.label)),
+                entry("delegated", Value::Bool(timing.delegated)),
+                entry("micros", int(timing.micros)),
+            ])
+        });
+        Value::Table(vec![
+            entry("label", string(&self.label)),
+            entry("threads", Value::Int(self.threads.into())),
+            entry("invocations", int(self.invocations as u64)),
+            entry("arena_bytes", int(self.arena_bytes as u64)),
+            entry("total_micros", int(self.total_micros)),
+            entry("tensors", Value::Array(tensors.collect())),
+            entry("ops", Value::Array(ops.collect())),
+            entry("timings", Value::Array(timings.collect())),
+        ])
+    }
+
+    fn from_value(value: &Value) -> Result<Self> {
+        let mut snapshot = Self::default();
+        for (key, value) in table(value, "snapshot")? {
+            match (key.as_str(), value) {
+                ("label", Value::String(label)) => snapshot.label = label.clone(),
+                ("threads", &Value::Int(threads)) => snapshot.threads = int(threads, key)?,
+                ("invocations", &Value::Int(n)) => snapshot.invocations = int(n, key)?,
+                ("arena_bytes", &Value::Int(bytes)) => snapshot.arena_bytes = int(bytes, key)?,
+                ("total_micros", &Value::Int(micros)) => snapshot.total_micros = int(micros, key)?,
+                ("tensors", Value::Array(tensors)) => {
+                    snapshot.tensors =
+                        tensors.iter().map(tensor_from_value).collect::<Result<_>>()?
+                }
+                ("ops", Value::Array(ops)) => {
+                    snapshot.ops = ops.iter().map(op_from_value).collect::<Result<_>>()?
+                }
+                ("timings", Value::Array(timings)) => {
+                    snapshot.timings =
+                        timings.iter().map(timing_from_value).collect::<Result<_>>()?
+                }
+                _ => return Err(invalid(key)),
+            }
+        }
+        Ok(snapshot)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Self::from_value(&json::parse(text)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        json::write(&self.to_value())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    fn tensor_key(&self, index: usize) -> String {
+        match self.tensors[index].name.as_str() {
+            "" => format!("#{}", index),
+            name => name.to_string(),
+        }
+    }
+}
+
+fn invalid(key: &str) -> Error {
+    Error::InternalError(format!("invalid run snapshot entry `{}`", key))
+}
+
+fn table<'v>(value: &'v Value, key: &str) -> Result<&'v [(String, Value)]> {
+    match value {
+        Value::Table(entries) => Ok(entries),
+        _ => Err(invalid(key)),
+    }
+}
+
+fn int<T: std::convert::TryFrom<i64>>(value: i64, key: &str) -> Result<T> {
+    T::try_from(value).map_err(|_| invalid(key))
+}
+
+fn tensor_from_value(value: &Value) -> Result<TensorShape> {
+    let mut tensor =
+        TensorShape { name: String::new(), element_kind: String::new(), dims: Vec::new() };
+    for (key, value) in table(value, "tensors")? {
+        match (key.as_str(), value) {
+            ("name", Value::String(name)) => tensor.name = name.clone(),
+            ("element_kind", Value::String(kind)) => tensor.element_kind = kind.clone(),
+            ("dims", Value::Array(dims)) => {
+                tensor.dims = dims
+                    .iter()
+                    .map(|dim| match dim {
+                        &Value::Int(dim) => int(dim, key),
+                        _ => Err(invalid(key)),
+                    })
+                    .collect::<Result<_>>()?
+            }
+            _ => return Err(invalid(key)),
+        }
+    }
+    Ok(tensor)
+}
+
+fn op_from_value(value: &Value) -> Result<OpPlacement> {
+    let mut op = OpPlacement { node: 0, name: String::new(), delegated: false };
+    for (key, value) in table(value, "ops")? {
+        match (key.as_str(), value) {
+            ("node", &Value::Int(node)) => op.node = int(node, key)?,
+            ("name", Value::String(name)) => op.name = name.clone(),
+            ("delegated", &Value::Bool(delegated)) => op.delegated = delegated,
+            _ => return Err(invalid(key)),
+        }
+    }
+    Ok(op)
+}
+
+fn timing_from_value(value: &Value) -> Result<OpTiming> {
+    let mut timing = OpTiming { label: String::new(), delegated: false, micros: 0 };
+    for (key, value) in table(value, "timings")? {
+        match (key.as_str(), value) {
+            ("label", Value::String(label)) => timing.label = label.clone(),
+            ("delegated", &Value::Bool(delegated)) => timing.delegated = delegated,
+            ("micros", &Value::Int(micros)) => timing.micros = int(micros, key)?,
+            _ => return Err(invalid(key)),
+        }
+    }
+    Ok(timing)
+}
+
+/// A value of snapshot `a` and of snapshot `b`, `None` where a snapshot has none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference<T> {
+    pub key: String,
+    pub a: Option<T>,
+    pub b: Option<T>,
+}
+
+/// Pairs the values of `a` and `b` by key, in the order of `a` then of the keys only in `b`.
+fn pair<T: Clone>(a: &[(String, T)], b: &[(String, T)]) -> Vec<Difference<T>> {
+    let find = |values: &[(String, T)], key: &str| {
+        values.iter().find(|(k, _)| k == key).map(|(_, value)| value.clone())
+    };
+    let mut pairs: Vec<Difference<T>> = a
+        .iter()
+        .map(|(key, value)| Difference {
+            key: key.clone(),
+            a: Some(value.clone()),
+            b: find(b, key),
+        })
+        .collect();
+    pairs.extend(
+        b.iter().filter(|(key, _)| find(a, key).is_none()).map(|(key, value)| Difference {
+            key: key.clone(),
+            a: None,
+            b: Some(value.clone()),
+        }),
+    );
+    pairs
+}
+
+/// How two `RunSnapshot`s differ.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub labels: (String, String),
+    pub threads: (i32, i32),
+    pub arena_bytes: (usize, usize),
+    pub total_micros: (u64, u64),
+    /// Tensors whose element type or dims differ, by name (`#index` for unnamed tensors).
+    pub tensors: Vec<Difference<TensorShape>>,
+    /// Operators delegated in one snapshot and not in the other, by `NAME #node`.
+    pub placements: Vec<Difference<bool>>,
+    /// All timings by label, the largest slowdown from `a` to `b` first and the timings of
+    /// only one snapshot last.
+    pub timings: Vec<Difference<u64>>,
+}
+
+impl SnapshotDiff {
+    pub fn new(a: &RunSnapshot, b: &RunSnapshot) -> Self {
+        let tensors = |snapshot: &RunSnapshot| -> Vec<(String, TensorShape)> {
+            (0..snapshot.tensors.len())
+                .map(|i| (snapshot.tensor_key(i), snapshot.tensors[i].clone()))
+                .collect()
+        };
+        let placements = |snapshot: &RunSnapshot| -> Vec<(String, bool)> {
+            snapshot
+                .ops
+                .iter()
+                .map(|op| (format!("{} #{}", op.name, op.node), op.delegated))
+                .collect()
+        };
+        let timings = |snapshot: &RunSnapshot| -> Vec<(String, u64)> {
+            snapshot.timings.iter().map(|timing| (timing.label.clone(), timing.micros)).collect()
+        };
+        let mut timings = pair(&timings(a), &timings(b));
+        timings.sort_by_key(|timing| match (timing.a, timing.b) {
+            (Some(a), Some(b)) => (0, a as i128 - b as i128),
+            _ => (1, 0),
+        });
+        Self {
+            labels: (a.label.clone(), b.label.clone()),
+            threads: (a.threads, b.threads),
+            arena_bytes: (a.arena_bytes, b.arena_bytes),
+            total_micros: (a.total_micros, b.total_micros),
+            tensors: pair(&tensors(a), &tensors(b))
+                .into_iter()
+                .filter(|tensor| tensor.a != tensor.b)
+                .collect(),
+            placements: pair(&placements(a), &placements(b))
+                .into_iter()
+                .filter(|op| op.a != op.b)
+                .collect(),
+            timings,
+        }
+    }
+
+    /// Whether both snapshots have the same tensor shapes and operator placement, so that
+    /// only timing differs.
+    pub fn same_graph(&self) -> bool {
+        self.tensors.is_empty() && self.placements.is_empty()
+    }
+}
+
+fn or_missing<T, F: Fn(&T) -> String>(value: &Option<T>, show: F) -> String {
+    value.as_ref().map(show).unwrap_or_else(|| "missing".to_string())
+}
+
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} -> {}", self.labels.0, self.labels.1)?;
+        writeln!(f, "threads: {} -> {}", self.threads.0, self.threads.1)?;
+        writeln!(f, "arena: {} -> {} bytes", self.arena_bytes.0, self.arena_bytes.1)?;
+        writeln!(f, "total: {} -> {} us", self.total_micros.0, self.total_micros.1)?;
+        if !self.tensors.is_empty() {
+            writeln!(f, "tensor shapes:")?;
+            let show = |tensor: &TensorShape| format!("{} {:?}", tensor.element_kind, tensor.dims);
+            for tensor in &self.tensors {
+                let (a, b) = (or_missing(&tensor.a, show), or_missing(&tensor.b, show));
+                writeln!(f, "  {}: {} -> {}", tensor.key, a, b)?;
+            }
+        }
+        if !self.placements.is_empty() {
+            writeln!(f, "op placement:")?;
+            let show = |&delegated: &bool| if delegated { "delegate" } else { "cpu" }.to_string();
+            for op in &self.placements {
+                let (a, b) = (or_missing(&op.a, show), or_missing(&op.b, show));
+                writeln!(f, "  {}: {} -> {}", op.key, a, b)?;
+            }
+        }
+        if !self.timings.is_empty() {
+            writeln!(f, "timings:")?;
+            for timing in &self.timings {
+                let show = |micros: &u64| format!("{} us", micros);
+                write!(
+                    f,
+                    "  {}: {} -> {}",
+                    timing.key,
+                    or_missing(&timing.a, show),
+                    or_missing(&timing.b, show)
+                )?;
+                match (timing.a, timing.b) {
+                    (Some(a), Some(b)) => writeln!(f, " ({:+} us)", b as i64 - a as i64)?,
+                    _ => writeln!(f)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(label: &str, dims: Vec<usize>, delegated: bool, micros: &[u64]) -> RunSnapshot {
+        let timing = |label: &str, micros| OpTiming { label: label.to_string(), delegated, micros };
+        RunSnapshot {
+            label: label.to_string(),
+            threads: 4,
+            invocations: 10,
+            arena_bytes: 1 << 20,
+            total_micros: micros.iter().sum(),
+            tensors: vec![TensorShape {
+                name: "input".to_string(),
+                element_kind: "kTfLiteUInt8".to_string(),
+                dims,
+            }],
+            ops: vec![
+                OpPlacement { node: 0, name: "CONV_2D".to_string(), delegated },
+                OpPlacement { node: 1, name: "SOFTMAX".to_string(), delegated: false },
+            ],
+            timings: if delegated {
+                vec![timing("delegate #0-#0 (1 ops)", micros[0]), timing("SOFTMAX #1", micros[1])]
+            } else {
+                vec![timing("CONV_2D #0", micros[0]), timing("SOFTMAX #1", micros[1])]
+            },
+        }
+    }
+
+    #[test]
+    fn unittest_snapshot_diff() {
+        let dev = snapshot("dev", vec![1, 28, 28, 1], true, &[100, 20]);
+        assert_eq!(RunSnapshot::from_json(&dev.to_json()).unwrap(), dev);
+        assert!(RunSnapshot::from_json("{\"threads\": \"4\"}").is_err());
+
+        let soc = snapshot("soc", vec![1, 28, 28], false, &[900, 30]);
+        let diff = SnapshotDiff::new(&dev, &soc);
+        assert!(!diff.same_graph());
+        assert_eq!(diff.tensors.len(), 1);
+        assert_eq!(diff.placements.len(), 1);
+        assert_eq!(diff.placements[0].key, "CONV_2D #0");
+        assert_eq!((diff.placements[0].a, diff.placements[0].b), (Some(true), Some(false)));
+        let keys: Vec<&str> = diff.timings.iter().map(|t| t.key.as_str()).collect();
+        assert_eq!(keys, vec!["SOFTMAX #1", "delegate #0-#0 (1 ops)", "CONV_2D #0"]);
+        let text = diff.to_string();
+        assert!(text.contains("  SOFTMAX #1: 20 us -> 30 us (+10 us)"), "{}", text);
+        assert!(text.contains("  CONV_2D #0: delegate -> cpu"), "{}", text);
+
+        let slower = snapshot("soc", vec![1, 28, 28, 1], true, &[150, 20]);
+        let diff = SnapshotDiff::new(&dev, &slower);
+        assert!(diff.same_graph());
+        assert_eq!(diff.timings[0].key, "delegate #0-#0 (1 ops)");
+    }
+}
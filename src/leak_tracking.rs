@@ -0,0 +1,108 @@
+//! Counts of native objects created and destroyed by the wrapper.
+//!
+//! Recording only happens with the `leak_tracking` feature, which is meant for tests:
+//! an unbalanced count points at a leaked or double-freed TFLite object.
+
+#[cfg(feature = "leak_tracking")]
+use std::cell::Cell;
+#[cfg(feature = "leak_tracking")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Kinds of native objects owned by wrapper types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NativeObject {
+    FlatBufferModel,
+    Interpreter,
+    OpResolver,
+    Delegate,
+    IntArray,
+}
+
+#[cfg(feature = "leak_tracking")]
+const KINDS: usize = 5;
+
+#[cfg(feature = "leak_tracking")]
+static CREATED: [AtomicUsize; KINDS] = [const { AtomicUsize::new(0) }; KINDS];
+#[cfg(feature = "leak_tracking")]
+static DESTROYED: [AtomicUsize; KINDS] = [const { AtomicUsize::new(0) }; KINDS];
+
+#[cfg(feature = "leak_tracking")]
+thread_local! {
+    static LOCAL: Cell<[isize; KINDS]> = const { Cell::new([0; KINDS]) };
+}
+
+#[cfg(feature = "leak_tracking")]
+fn update_local(object: NativeObject, delta: isize) {
+    LOCAL.with(|local| {
+        let mut counts = local.get();
+        counts[object as usize] += delta;
+        local.set(counts);
+    });
+}
+
+#[allow(unused_variables)]
+pub(crate) fn created(object: NativeObject) {
+    #[cfg(feature = "leak_tracking")]
+    {
+        CREATED[object as usize].fetch_add(1, Ordering::SeqCst);
+        update_local(object, 1);
+    }
+}
+
+#[allow(unused_variables)]
+pub(crate) fn destroyed(object: NativeObject) {
+    #[cfg(feature = "leak_tracking")]
+    {
+        let destroyed = DESTROYED[object as usize].fetch_add(1, Ordering::SeqCst) + 1;
+        if destroyed > CREATED[object as usize].load(Ordering::SeqCst) {
+            panic!("{:?} destroyed more often than created (double free?)", object);
+        }
+        update_local(object, -1);
+    }
+}
+
+/// Number of objects of kind `object` alive across all threads.
+#[cfg(feature = "leak_tracking")]
+pub fn live(object: NativeObject) -> usize {
+    // Load `DESTROYED` first so a concurrent create/destroy pair cannot underflow.
+    let destroyed = DESTROYED[object as usize].load(Ordering::SeqCst);
+    CREATED[object as usize].load(Ordering::SeqCst) - destroyed
+}
+
+/// Runs `f` and panics if objects it created on this thread were not destroyed on this
+/// thread by the time it returns. Counting per thread keeps concurrently running tests
+/// from disturbing each other.
+#[cfg(feature = "leak_tracking")]
+pub fn assert_balanced<R>(f: impl FnOnce() -> R) -> R {
+    let before = LOCAL.with(Cell::get);
+    let result = f();
+    let after = LOCAL.with(Cell::get);
+    let leaked: Vec<_> = [
+        NativeObject::FlatBufferModel,
+        NativeObject::Interpreter,
+        NativeObject::OpResolver,
+        NativeObject::Delegate,
+        NativeObject::IntArray,
+    ]
+    .iter()
+    .filter(|&&object| after[object as usize] != before[object as usize])
+    .map(|&object| (object, after[object as usize] - before[object as usize]))
+    .collect();
+    assert!(leaked.is_empty(), "unbalanced native objects: {:?}", leaked);
+    result
+}
+
+#[cfg(all(test, feature = "leak_tracking"))]
+mod tests {
+    use super::*;
+    use crate::context::IntArray;
+
+    #[test]
+    fn unittest_leak_tracking() {
+        let len = assert_balanced(|| IntArray::new(&[1, 2]).len());
+        assert_eq!(len, 2);
+
+        let leaked = std::panic::catch_unwind(|| assert_balanced(|| IntArray::new(&[3])));
+        assert!(leaked.is_err());
+    }
+}
@@ -1,14 +1,68 @@
-#![recursion_limit = "128"]
+#![recursion_limit = "2048"]
+// docs builds replace every `cpp!` closure with a panic
+#![cfg_attr(docs_only, allow(unused, unreachable_code))]
 
+#[cfg(not(docs_only))]
 #[macro_use]
 extern crate cpp;
 
+/// Stands in for the `cpp` crate's `cpp!` in docs builds, which compile no C++ and so have no
+/// closure metadata: C++ blocks expand to nothing and closures to a panic, which rustdoc only
+/// needs to type check.
+#[cfg(docs_only)]
+macro_rules! cpp {
+    ({ $($cpp:tt)* }) => {};
+    (unsafe $($tail:tt)*) => {
+        unsafe { cpp!($($tail)*) }
+    };
+    ([$($captures:tt)*] -> $ret:ty as $cpp_ret:tt { $($cpp:tt)* }) => {{
+        let ret: $ret = panic!("tflite was built for documentation only");
+        ret
+    }};
+    ([$($captures:tt)*] { $($cpp:tt)* }) => {
+        panic!("tflite was built for documentation only")
+    };
+}
+
 mod bindings;
+mod bundle;
+pub mod energy;
+pub mod ensemble;
 mod error;
+pub mod evaluation;
 mod interpreter;
+pub mod introspection;
+pub mod leak_tracking;
+pub mod metadata;
 pub mod model;
+mod panic_guard;
+pub mod pipeline;
+pub mod postprocess;
+pub mod preprocess;
+pub mod quantization;
+pub mod run_profile;
+#[cfg(feature = "signing")]
+pub mod signing;
+mod source;
+mod static_model;
+#[cfg(feature = "text")]
+pub mod text;
+pub mod thermal;
+pub mod tiling;
+#[cfg(feature = "watch")]
+pub mod watch;
+pub mod windowing;
 
-pub use error::Error;
+pub use bundle::{BundledModel, ModelBundle};
+pub use error::{DelegateFailure, Error};
 pub use interpreter::*;
+pub use source::{Allocation, AllocationKind, Mmap, ModelSource};
+pub use static_model::{verify_model, Aligned, StaticModel};
+#[cfg(feature = "macros")]
+pub use tflite_macros::tflite_model;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// Version of the TensorFlow sources the bindings were generated from, e.g. `2.3.1`, or
+/// `unknown` for headers without `version.h`.
+pub const TENSORFLOW_VERSION: &str = env!("TFLITE_RS_DETECTED_TF_VERSION");
@@ -0,0 +1,226 @@
+//! A minimal, bounds-checked reader for FlatBuffers tables, enough to walk
+//! the TFLite metadata schema without generated code.
+
+use std::convert::TryInto;
+
+use crate::{Error, Result};
+
+fn malformed() -> Error {
+    Error::internal_error("malformed metadata flatbuffer")
+}
+
+fn bytes<const N: usize>(buf: &[u8], pos: usize) -> Result<[u8; N]> {
+    buf.get(pos..pos + N).and_then(|b| b.try_into().ok()).ok_or_else(malformed)
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(bytes(buf, pos)?))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(bytes(buf, pos)?))
+}
+
+fn read_i32(buf: &[u8], pos: usize) -> Result<i32> {
+    Ok(i32::from_le_bytes(bytes(buf, pos)?))
+}
+
+fn read_f32(buf: &[u8], pos: usize) -> Result<f32> {
+    Ok(f32::from_le_bytes(bytes(buf, pos)?))
+}
+
+/// Follows the unsigned offset stored at `pos`.
+fn indirect(buf: &[u8], pos: usize) -> Result<usize> {
+    pos.checked_add(read_u32(buf, pos)? as usize).ok_or_else(malformed)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    pub(crate) fn root(buf: &'a [u8]) -> Result<Self> {
+        Self::at(buf, indirect(buf, 0)?)
+    }
+
+    fn at(buf: &'a [u8], pos: usize) -> Result<Self> {
+        let table = Self { buf, pos };
+        table.vtable()?;
+        Ok(table)
+    }
+
+    fn vtable(&self) -> Result<(usize, usize)> {
+        let vtable = (self.pos as i64) - i64::from(read_i32(self.buf, self.pos)?);
+        if vtable < 0 {
+            return Err(malformed());
+        }
+        let vtable = vtable as usize;
+        Ok((vtable, read_u16(self.buf, vtable)? as usize))
+    }
+
+    /// Absolute position of field `id`, or `None` if the field is absent.
+    fn field(&self, id: usize) -> Result<Option<usize>> {
+        let (vtable, len) = self.vtable()?;
+        let entry = 4 + 2 * id;
+        if entry + 2 > len {
+            return Ok(None);
+        }
+        match read_u16(self.buf, vtable + entry)? {
+            0 => Ok(None),
+            offset => Ok(Some(self.pos + offset as usize)),
+        }
+    }
+
+    pub(crate) fn u8(&self, id: usize, default: u8) -> Result<u8> {
+        match self.field(id)? {
+            Some(pos) => self.buf.get(pos).cloned().ok_or_else(malformed),
+            None => Ok(default),
+        }
+    }
+
+    pub(crate) fn u32(&self, id: usize, default: u32) -> Result<u32> {
+        match self.field(id)? {
+            Some(pos) => read_u32(self.buf, pos),
+            None => Ok(default),
+        }
+    }
+
+    pub(crate) fn string(&self, id: usize) -> Result<Option<&'a str>> {
+        match self.field(id)? {
+            Some(pos) => string_at(self.buf, indirect(self.buf, pos)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn table(&self, id: usize) -> Result<Option<Table<'a>>> {
+        match self.field(id)? {
+            Some(pos) => Table::at(self.buf, indirect(self.buf, pos)?).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `(start, len)` of the vector stored in field `id`.
+    fn vector(&self, id: usize) -> Result<Option<(usize, usize)>> {
+        match self.field(id)? {
+            Some(pos) => {
+                let start = indirect(self.buf, pos)?;
+                Ok(Some((start + 4, read_u32(self.buf, start)? as usize)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn f32s(&self, id: usize) -> Result<Vec<f32>> {
+        match self.vector(id)? {
+            Some((start, len)) => (0..len).map(|i| read_f32(self.buf, start + 4 * i)).collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub(crate) fn strings(&self, id: usize) -> Result<Vec<&'a str>> {
+        match self.vector(id)? {
+            Some((start, len)) => {
+                (0..len).map(|i| string_at(self.buf, indirect(self.buf, start + 4 * i)?)).collect()
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub(crate) fn tables(&self, id: usize) -> Result<Vec<Table<'a>>> {
+        match self.vector(id)? {
+            Some((start, len)) => {
+                (0..len).map(|i| Table::at(self.buf, indirect(self.buf, start + 4 * i)?)).collect()
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+fn string_at(buf: &[u8], pos: usize) -> Result<&str> {
+    let len = read_u32(buf, pos)? as usize;
+    let bytes = buf.get(pos + 4..pos + 4 + len).ok_or_else(malformed)?;
+    std::str::from_utf8(bytes).map_err(|_| malformed())
+}
+
+/// A forward-writing serializer used to build metadata fixtures in tests.
+#[cfg(test)]
+pub(crate) mod builder {
+    pub(crate) enum Value {
+        Table(Vec<Option<Value>>),
+        Str(&'static str),
+        F32s(Vec<f32>),
+        Tables(Vec<Value>),
+        U8(u8),
+        U32(u32),
+    }
+
+    pub(crate) fn finish(root: &Value, identifier: &[u8; 4]) -> Vec<u8> {
+        let mut buf = vec![0; 8];
+        buf[4..8].copy_from_slice(identifier);
+        let pos = write(&mut buf, root);
+        patch(&mut buf, 0, pos);
+        buf
+    }
+
+    fn patch(buf: &mut [u8], at: usize, target: usize) {
+        buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+    }
+
+    fn write(buf: &mut Vec<u8>, value: &Value) -> usize {
+        match value {
+            Value::Table(fields) => {
+                let vtable = buf.len();
+                buf.extend_from_slice(&(4 + 2 * fields.len() as u16).to_le_bytes());
+                buf.extend_from_slice(&(4 + 4 * fields.len() as u16).to_le_bytes());
+                for (i, field) in fields.iter().enumerate() {
+                    let offset = if field.is_some() { 4 + 4 * i as u16 } else { 0 };
+                    buf.extend_from_slice(&offset.to_le_bytes());
+                }
+                let table = buf.len();
+                buf.extend_from_slice(&((table - vtable) as i32).to_le_bytes());
+                buf.resize(table + 4 + 4 * fields.len(), 0);
+                for (i, field) in fields.iter().enumerate() {
+                    let at = table + 4 + 4 * i;
+                    match field {
+                        None => {}
+                        Some(Value::U8(x)) => buf[at] = *x,
+                        Some(Value::U32(x)) => buf[at..at + 4].copy_from_slice(&x.to_le_bytes()),
+                        Some(child) => {
+                            let pos = write(buf, child);
+                            patch(buf, at, pos);
+                        }
+                    }
+                }
+                table
+            }
+            Value::Str(s) => write_bytes(buf, s.as_bytes()),
+            Value::F32s(v) => {
+                let pos = buf.len();
+                buf.extend_from_slice(&(v.len() as u32).to_le_bytes());
+                v.iter().for_each(|x| buf.extend_from_slice(&x.to_le_bytes()));
+                pos
+            }
+            Value::Tables(v) => {
+                let pos = buf.len();
+                buf.extend_from_slice(&(v.len() as u32).to_le_bytes());
+                buf.resize(pos + 4 + 4 * v.len(), 0);
+                for (i, child) in v.iter().enumerate() {
+                    let child_pos = write(buf, child);
+                    patch(buf, pos + 4 + 4 * i, child_pos);
+                }
+                pos
+            }
+            Value::U8(_) | Value::U32(_) => unreachable!("scalars are stored inline"),
+        }
+    }
+
+    fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) -> usize {
+        let pos = buf.len();
+        buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        buf.extend_from_slice(bytes);
+        buf.push(0);
+        pos
+    }
+}
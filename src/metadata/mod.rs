@@ -0,0 +1,501 @@
+//! Reading the TFLite model metadata (`TFLITE_METADATA`) embedded in a model.
+
+pub(crate) mod flatbuffer;
+mod zip;
+
+use flatbuffer::Table;
+
+use crate::model::stl::vector::VectorSlice;
+use crate::model::Model;
+use crate::{FlatBufferModel, Result};
+
+/// Name of the model metadata entry which holds the `ModelMetadata` flatbuffer.
+pub const METADATA_NAME: &str = "TFLITE_METADATA";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub license: Option<String>,
+    pub subgraphs: Vec<SubGraphMetadata>,
+    pub associated_files: Vec<AssociatedFile>,
+    /// The oldest metadata parser version that reads all fields, e.g. `1.2.1`.
+    pub min_parser_version: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubGraphMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub inputs: Vec<TensorMetadata>,
+    pub outputs: Vec<TensorMetadata>,
+    pub associated_files: Vec<AssociatedFile>,
+    /// Processing shared by the inputs, e.g. the tokenizer of a text model.
+    pub input_process_units: Vec<ProcessUnit>,
+    pub output_process_units: Vec<ProcessUnit>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TensorMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub dimension_names: Vec<String>,
+    pub content: Option<ContentProperties>,
+    pub normalization: Option<NormalizationOptions>,
+    pub process_units: Vec<ProcessUnit>,
+    pub stats: Option<Stats>,
+    pub associated_files: Vec<AssociatedFile>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContentProperties {
+    Feature,
+    Image(ImageProperties),
+    BoundingBox,
+    Audio,
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Unknown,
+    Rgb,
+    Grayscale,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageProperties {
+    pub color_space: ColorSpace,
+    /// Default `(width, height)` of the image, if declared.
+    pub default_size: Option<(u32, u32)>,
+}
+
+/// Per-channel `(x - mean) / std` normalization. A single value applies to every channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NormalizationOptions {
+    pub mean: Vec<f32>,
+    pub std: Vec<f32>,
+}
+
+/// A processing step of a tensor or subgraph. Tokenizers name their vocabularies and models,
+/// which are associated files.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProcessUnit {
+    Normalization(NormalizationOptions),
+    BertTokenizer {
+        vocab_files: Vec<AssociatedFile>,
+    },
+    SentencePieceTokenizer {
+        models: Vec<AssociatedFile>,
+        vocab_files: Vec<AssociatedFile>,
+    },
+    RegexTokenizer {
+        delim_regex_pattern: String,
+        vocab_files: Vec<AssociatedFile>,
+    },
+    /// Score calibration, thresholding and later units, by union type.
+    Other(u8),
+}
+
+impl ProcessUnit {
+    pub fn associated_files(&self) -> Vec<&AssociatedFile> {
+        match self {
+            ProcessUnit::BertTokenizer { vocab_files }
+            | ProcessUnit::RegexTokenizer { vocab_files, .. } => vocab_files.iter().collect(),
+            ProcessUnit::SentencePieceTokenizer { models, vocab_files } => {
+                models.iter().chain(vocab_files).collect()
+            }
+            ProcessUnit::Normalization(_) | ProcessUnit::Other(_) => Vec::new(),
+        }
+    }
+
+    fn parse(unit: &Table<'_>) -> Result<Self> {
+        let kind = unit.u8(0, 0)?;
+        let options = match unit.table(1)? {
+            Some(options) => options,
+            None => return Ok(ProcessUnit::Other(kind)),
+        };
+        Ok(match kind {
+            PROCESS_UNIT_NORMALIZATION => ProcessUnit::Normalization(NormalizationOptions {
+                mean: options.f32s(0)?,
+                std: options.f32s(1)?,
+            }),
+            PROCESS_UNIT_BERT_TOKENIZER => {
+                ProcessUnit::BertTokenizer { vocab_files: AssociatedFile::parse_all(&options, 0)? }
+            }
+            PROCESS_UNIT_SENTENCE_PIECE_TOKENIZER => ProcessUnit::SentencePieceTokenizer {
+                models: AssociatedFile::parse_all(&options, 0)?,
+                vocab_files: AssociatedFile::parse_all(&options, 1)?,
+            },
+            PROCESS_UNIT_REGEX_TOKENIZER => ProcessUnit::RegexTokenizer {
+                delim_regex_pattern: options.string(0)?.unwrap_or_default().to_string(),
+                vocab_files: AssociatedFile::parse_all(&options, 1)?,
+            },
+            other => ProcessUnit::Other(other),
+        })
+    }
+
+    fn parse_all(table: &Table<'_>, id: usize) -> Result<Vec<Self>> {
+        table.tables(id)?.iter().map(Self::parse).collect()
+    }
+}
+
+/// A file packed into the model, described by the metadata.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssociatedFile {
+    pub name: String,
+    pub description: Option<String>,
+    pub kind: AssociatedFileType,
+    pub locale: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssociatedFileType {
+    Unknown,
+    Descriptions,
+    /// One label per line for the elements along the tensor's last axis.
+    TensorAxisLabels,
+    /// One label per line for the values of the tensor.
+    TensorValueLabels,
+    TensorAxisScoreCalibration,
+    Vocabulary,
+}
+
+impl From<u8> for AssociatedFileType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => AssociatedFileType::Descriptions,
+            2 => AssociatedFileType::TensorAxisLabels,
+            3 => AssociatedFileType::TensorValueLabels,
+            4 => AssociatedFileType::TensorAxisScoreCalibration,
+            5 => AssociatedFileType::Vocabulary,
+            _ => AssociatedFileType::Unknown,
+        }
+    }
+}
+
+impl AssociatedFile {
+    fn parse(table: &Table<'_>) -> Result<Self> {
+        Ok(Self {
+            name: table.string(0)?.unwrap_or_default().to_string(),
+            description: owned(table.string(1)?),
+            kind: table.u8(2, 0)?.into(),
+            locale: owned(table.string(3)?),
+        })
+    }
+
+    fn parse_all(table: &Table<'_>, id: usize) -> Result<Vec<Self>> {
+        table.tables(id)?.iter().map(Self::parse).collect()
+    }
+}
+
+/// Returns the contents of the associated file `name` packed into `model_buffer`,
+/// the raw bytes of a `.tflite` file.
+pub fn associated_file<'a>(model_buffer: &'a [u8], name: &str) -> Result<Option<&'a [u8]>> {
+    Ok(zip::entries(model_buffer)?.into_iter().find(|(n, _)| n == name).map(|(_, data)| data))
+}
+
+/// Returns the names and contents of every associated file packed into `model_buffer`.
+pub fn associated_files(model_buffer: &[u8]) -> Result<Vec<(String, &[u8])>> {
+    zip::entries(model_buffer)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub max: Vec<f32>,
+    pub min: Vec<f32>,
+}
+
+const PROCESS_UNIT_NORMALIZATION: u8 = 1;
+const PROCESS_UNIT_BERT_TOKENIZER: u8 = 4;
+const PROCESS_UNIT_SENTENCE_PIECE_TOKENIZER: u8 = 5;
+const PROCESS_UNIT_REGEX_TOKENIZER: u8 = 6;
+const CONTENT_FEATURE: u8 = 1;
+const CONTENT_IMAGE: u8 = 2;
+const CONTENT_BOUNDING_BOX: u8 = 3;
+const CONTENT_AUDIO: u8 = 4;
+
+fn owned(s: Option<&str>) -> Option<String> {
+    s.map(str::to_string)
+}
+
+impl ModelMetadata {
+    /// Parses a `ModelMetadata` flatbuffer.
+    pub fn from_buffer(buffer: &[u8]) -> Result<Self> {
+        let root = Table::root(buffer)?;
+        Ok(Self {
+            name: owned(root.string(0)?),
+            description: owned(root.string(1)?),
+            version: owned(root.string(2)?),
+            subgraphs: root
+                .tables(3)?
+                .iter()
+                .map(SubGraphMetadata::parse)
+                .collect::<Result<_>>()?,
+            author: owned(root.string(4)?),
+            license: owned(root.string(5)?),
+            associated_files: AssociatedFile::parse_all(&root, 6)?,
+            min_parser_version: owned(root.string(7)?),
+        })
+    }
+
+    /// Every associated file the metadata declares: of the model, its subgraphs, tensors and
+    /// process units.
+    pub fn all_associated_files(&self) -> Vec<&AssociatedFile> {
+        let mut files: Vec<&AssociatedFile> = self.associated_files.iter().collect();
+        for subgraph in &self.subgraphs {
+            files.extend(&subgraph.associated_files);
+            let units = subgraph.input_process_units.iter().chain(&subgraph.output_process_units);
+            files.extend(units.flat_map(ProcessUnit::associated_files));
+            for tensor in subgraph.inputs.iter().chain(&subgraph.outputs) {
+                files.extend(&tensor.associated_files);
+                files.extend(tensor.process_units.iter().flat_map(ProcessUnit::associated_files));
+            }
+        }
+        files
+    }
+
+    /// The first declared associated file of the given kind, e.g. the vocabulary of a text
+    /// model, wherever it is declared.
+    pub fn find_associated_file(&self, kind: AssociatedFileType) -> Option<&AssociatedFile> {
+        self.all_associated_files().into_iter().find(|file| file.kind == kind)
+    }
+
+    /// The contents of `file`, packed into `model_buffer`, the raw bytes of the model.
+    pub fn read_associated_file<'a>(
+        &self,
+        model_buffer: &'a [u8],
+        file: &AssociatedFile,
+    ) -> Result<&'a [u8]> {
+        associated_file(model_buffer, &file.name)?.ok_or_else(|| {
+            crate::Error::InternalError(format!("associated file `{}` is missing", file.name))
+        })
+    }
+
+    /// Reads the metadata embedded in `model`, or `None` if it has none.
+    pub fn from_model(model: &Model) -> Result<Option<Self>> {
+        match metadata_buffer(model) {
+            Some(buffer) => Self::from_buffer(buffer).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the metadata embedded in a loaded model, or `None` if it has none.
+    pub fn from_flatbuffer_model(model: &FlatBufferModel) -> Result<Option<Self>> {
+        let model = Model::from_buffer(model.buffer())
+            .ok_or_else(|| crate::Error::internal_error("failed to unpack the flatbuffer model"))?;
+        Self::from_model(&model)
+    }
+
+    /// Metadata of input `index` of the first subgraph.
+    pub fn input(&self, index: usize) -> Option<&TensorMetadata> {
+        self.subgraphs.first()?.inputs.get(index)
+    }
+
+    /// Metadata of output `index` of the first subgraph.
+    pub fn output(&self, index: usize) -> Option<&TensorMetadata> {
+        self.subgraphs.first()?.outputs.get(index)
+    }
+}
+
+fn metadata_buffer(model: &Model) -> Option<&[u8]> {
+    let metadata =
+        model.metadata.iter().find(|m| m.name.c_str().to_bytes() == METADATA_NAME.as_bytes())?;
+    model.buffers.get(metadata.buffer as usize).map(|buffer| buffer.data.as_slice())
+}
+
+impl SubGraphMetadata {
+    fn parse(table: &Table<'_>) -> Result<Self> {
+        Ok(Self {
+            name: owned(table.string(0)?),
+            description: owned(table.string(1)?),
+            inputs: table.tables(2)?.iter().map(TensorMetadata::parse).collect::<Result<_>>()?,
+            outputs: table.tables(3)?.iter().map(TensorMetadata::parse).collect::<Result<_>>()?,
+            associated_files: AssociatedFile::parse_all(table, 4)?,
+            input_process_units: ProcessUnit::parse_all(table, 5)?,
+            output_process_units: ProcessUnit::parse_all(table, 6)?,
+        })
+    }
+}
+
+impl TensorMetadata {
+    /// The first associated file of the given kind, e.g. the label file of a classifier output.
+    pub fn associated_file(&self, kind: AssociatedFileType) -> Option<&AssociatedFile> {
+        self.associated_files.iter().find(|file| file.kind == kind)
+    }
+
+    fn parse(table: &Table<'_>) -> Result<Self> {
+        let content = match table.table(3)? {
+            Some(content) => parse_content(&content)?,
+            None => None,
+        };
+
+        let process_units = ProcessUnit::parse_all(table, 4)?;
+        let normalization = process_units.iter().rev().find_map(|unit| match unit {
+            ProcessUnit::Normalization(options) => Some(options.clone()),
+            _ => None,
+        });
+
+        let stats = match table.table(5)? {
+            Some(stats) => Some(Stats { max: stats.f32s(0)?, min: stats.f32s(1)? }),
+            None => None,
+        };
+
+        Ok(Self {
+            name: owned(table.string(0)?),
+            description: owned(table.string(1)?),
+            dimension_names: table.strings(2)?.into_iter().map(str::to_string).collect(),
+            content,
+            normalization,
+            process_units,
+            stats,
+            associated_files: AssociatedFile::parse_all(table, 6)?,
+        })
+    }
+}
+
+fn parse_content(content: &Table<'_>) -> Result<Option<ContentProperties>> {
+    let properties = match content.u8(0, 0)? {
+        0 => return Ok(None),
+        CONTENT_FEATURE => ContentProperties::Feature,
+        CONTENT_IMAGE => {
+            let (color_space, default_size) = match content.table(1)? {
+                Some(image) => {
+                    let color_space = match image.u8(0, 0)? {
+                        1 => ColorSpace::Rgb,
+                        2 => ColorSpace::Grayscale,
+                        _ => ColorSpace::Unknown,
+                    };
+                    let default_size = match image.table(1)? {
+                        Some(size) => Some((size.u32(0, 0)?, size.u32(1, 0)?)),
+                        None => None,
+                    };
+                    (color_space, default_size)
+                }
+                None => (ColorSpace::Unknown, None),
+            };
+            ContentProperties::Image(ImageProperties { color_space, default_size })
+        }
+        CONTENT_BOUNDING_BOX => ContentProperties::BoundingBox,
+        CONTENT_AUDIO => ContentProperties::Audio,
+        other => ContentProperties::Unknown(other),
+    };
+    Ok(Some(properties))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::flatbuffer::builder::{finish, Value};
+    use super::*;
+
+    /// An image classifier's metadata: one RGB input normalized with mean/std 127.5.
+    pub(crate) fn image_classifier_metadata() -> Value {
+        let image = Value::Table(vec![
+            Some(Value::U8(1)),
+            Some(Value::Table(vec![Some(Value::U32(224)), Some(Value::U32(160))])),
+        ]);
+        let content = Value::Table(vec![Some(Value::U8(CONTENT_IMAGE)), Some(image)]);
+        let normalization = Value::Table(vec![
+            Some(Value::U8(PROCESS_UNIT_NORMALIZATION)),
+            Some(Value::Table(vec![
+                Some(Value::F32s(vec![127.5])),
+                Some(Value::F32s(vec![127.5])),
+            ])),
+        ]);
+        let input = Value::Table(vec![
+            Some(Value::Str("image")),
+            None,
+            None,
+            Some(content),
+            Some(Value::Tables(vec![normalization])),
+        ]);
+        let labels = Value::Table(vec![
+            Some(Value::Str("labels.txt")),
+            Some(Value::Str("ImageNet labels")),
+            Some(Value::U8(2)),
+        ]);
+        let output = Value::Table(vec![
+            Some(Value::Str("probability")),
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(Value::Tables(vec![labels])),
+        ]);
+        // Not an image model's, but where text models declare their vocabulary.
+        let vocab = Value::Table(vec![Some(Value::Str("vocab.txt")), None, Some(Value::U8(5))]);
+        let tokenizer = Value::Table(vec![
+            Some(Value::U8(PROCESS_UNIT_BERT_TOKENIZER)),
+            Some(Value::Table(vec![Some(Value::Tables(vec![vocab]))])),
+        ]);
+        let subgraph = Value::Table(vec![
+            None,
+            None,
+            Some(Value::Tables(vec![input])),
+            Some(Value::Tables(vec![output])),
+            None,
+            Some(Value::Tables(vec![tokenizer])),
+        ]);
+        Value::Table(vec![
+            Some(Value::Str("classifier")),
+            None,
+            Some(Value::Str("v1")),
+            Some(Value::Tables(vec![subgraph])),
+            None,
+            None,
+            None,
+            Some(Value::Str("1.2.1")),
+        ])
+    }
+
+    #[test]
+    fn unittest_parse_metadata() {
+        let buffer = finish(&image_classifier_metadata(), b"M001");
+        let metadata = ModelMetadata::from_buffer(&buffer).unwrap();
+        assert_eq!(metadata.name.as_deref(), Some("classifier"));
+        assert_eq!(metadata.version.as_deref(), Some("v1"));
+        assert_eq!(metadata.author, None);
+        assert_eq!(metadata.min_parser_version.as_deref(), Some("1.2.1"));
+
+        let input = metadata.input(0).unwrap();
+        assert_eq!(input.name.as_deref(), Some("image"));
+        assert_eq!(
+            input.content,
+            Some(ContentProperties::Image(ImageProperties {
+                color_space: ColorSpace::Rgb,
+                default_size: Some((224, 160)),
+            }))
+        );
+        assert_eq!(
+            input.normalization,
+            Some(NormalizationOptions { mean: vec![127.5], std: vec![127.5] })
+        );
+        let output = metadata.output(0).unwrap();
+        assert_eq!(output.name.as_deref(), Some("probability"));
+        assert_eq!(
+            output.associated_files,
+            vec![AssociatedFile {
+                name: "labels.txt".to_string(),
+                description: Some("ImageNet labels".to_string()),
+                kind: AssociatedFileType::TensorAxisLabels,
+                locale: None,
+            }]
+        );
+
+        assert_eq!(input.process_units.len(), 1);
+        let vocab = metadata.find_associated_file(AssociatedFileType::Vocabulary).unwrap();
+        assert_eq!(vocab.name, "vocab.txt");
+        assert!(matches!(
+            &metadata.subgraphs[0].input_process_units[..],
+            [ProcessUnit::BertTokenizer { vocab_files }] if vocab_files[0] == *vocab
+        ));
+        assert_eq!(metadata.all_associated_files().len(), 2);
+
+        assert!(ModelMetadata::from_buffer(&buffer[..buffer.len() / 2]).is_err());
+        let model = Model::from_file("data/MNISTnet_uint8_quant.tflite").unwrap();
+        assert_eq!(ModelMetadata::from_model(&model).unwrap(), None);
+    }
+}
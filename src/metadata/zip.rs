@@ -0,0 +1,141 @@
+//! Reads the uncompressed zip archive which the metadata populator appends to a model
+//! to pack its associated files.
+
+use std::convert::TryInto;
+
+use crate::{Error, Result};
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const CENTRAL_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const STORED: u16 = 0;
+
+fn malformed() -> Error {
+    Error::internal_error("malformed associated file archive")
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(buf.get(pos..pos + 2).ok_or_else(malformed)?.try_into().unwrap()))
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(buf.get(pos..pos + 4).ok_or_else(malformed)?.try_into().unwrap()))
+}
+
+/// Lists `(name, contents)` of every file in the archive at the end of `buf`.
+/// Returns an empty list if `buf` carries no archive.
+pub(crate) fn entries(buf: &[u8]) -> Result<Vec<(String, &[u8])>> {
+    // The end-of-central-directory record is 22 bytes plus a comment of up to 64 KiB.
+    let search_start = buf.len().saturating_sub(22 + 0xffff);
+    let eocd = match (search_start..buf.len().saturating_sub(21))
+        .rev()
+        .find(|&pos| read_u32(buf, pos).ok() == Some(END_OF_CENTRAL_DIRECTORY))
+    {
+        Some(pos) => pos,
+        None => return Ok(Vec::new()),
+    };
+
+    let count = read_u16(buf, eocd + 10)? as usize;
+    let mut pos = read_u32(buf, eocd + 16)? as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if read_u32(buf, pos)? != CENTRAL_DIRECTORY_ENTRY {
+            return Err(malformed());
+        }
+        let method = read_u16(buf, pos + 10)?;
+        let size = read_u32(buf, pos + 20)? as usize;
+        let name_len = read_u16(buf, pos + 28)? as usize;
+        let extra_len = read_u16(buf, pos + 30)? as usize;
+        let comment_len = read_u16(buf, pos + 32)? as usize;
+        let local = read_u32(buf, pos + 42)? as usize;
+        let name = buf.get(pos + 46..pos + 46 + name_len).ok_or_else(malformed)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+
+        if method != STORED {
+            return Err(Error::InternalError(format!(
+                "associated file `{}` uses unsupported compression method {}",
+                name, method
+            )));
+        }
+        if read_u32(buf, local)? != LOCAL_FILE_HEADER {
+            return Err(malformed());
+        }
+        let data =
+            local + 30 + read_u16(buf, local + 26)? as usize + read_u16(buf, local + 28)? as usize;
+        entries.push((name, buf.get(data..data + size).ok_or_else(malformed)?));
+
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Appends a stored zip archive holding `files` to `buf`.
+    pub(crate) fn append_archive(buf: &mut Vec<u8>, files: &[(&str, &[u8])]) {
+        let push16 = |buf: &mut Vec<u8>, x: u16| buf.extend_from_slice(&x.to_le_bytes());
+        let push32 = |buf: &mut Vec<u8>, x: u32| buf.extend_from_slice(&x.to_le_bytes());
+
+        let mut offsets = Vec::new();
+        for (name, data) in files {
+            offsets.push(buf.len() as u32);
+            push32(buf, LOCAL_FILE_HEADER);
+            push16(buf, 10);
+            push16(buf, 0);
+            push16(buf, STORED);
+            push32(buf, 0); // time and date
+            push32(buf, 0); // crc-32, not verified by the reader
+            push32(buf, data.len() as u32);
+            push32(buf, data.len() as u32);
+            push16(buf, name.len() as u16);
+            push16(buf, 0);
+            buf.extend_from_slice(name.as_bytes());
+            buf.extend_from_slice(data);
+        }
+
+        let directory = buf.len() as u32;
+        for ((name, data), offset) in files.iter().zip(offsets) {
+            push32(buf, CENTRAL_DIRECTORY_ENTRY);
+            push16(buf, 10);
+            push16(buf, 10);
+            push16(buf, 0);
+            push16(buf, STORED);
+            push32(buf, 0);
+            push32(buf, 0);
+            push32(buf, data.len() as u32);
+            push32(buf, data.len() as u32);
+            push16(buf, name.len() as u16);
+            push16(buf, 0);
+            push16(buf, 0);
+            push16(buf, 0);
+            push16(buf, 0);
+            push32(buf, 0);
+            push32(buf, offset);
+            buf.extend_from_slice(name.as_bytes());
+        }
+        let directory_len = buf.len() as u32 - directory;
+
+        push32(buf, END_OF_CENTRAL_DIRECTORY);
+        push16(buf, 0);
+        push16(buf, 0);
+        push16(buf, files.len() as u16);
+        push16(buf, files.len() as u16);
+        push32(buf, directory_len);
+        push32(buf, directory);
+        push16(buf, 0);
+    }
+
+    #[test]
+    fn unittest_zip_entries() {
+        let mut buf = b"model bytes".to_vec();
+        assert!(entries(&buf).unwrap().is_empty());
+
+        append_archive(&mut buf, &[("labels.txt", b"cat\ndog\n"), ("vocab.txt", b"a b")]);
+        let files = entries(&buf).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0], ("labels.txt".to_string(), &b"cat\ndog\n"[..]));
+        assert_eq!(files[1], ("vocab.txt".to_string(), &b"a b"[..]));
+    }
+}
@@ -0,0 +1,103 @@
+//! Byte-swapping of constant tensor data for big-endian targets.
+//!
+//! FlatBuffers scalar fields are decoded portably, but the contents of `Buffer.data` are
+//! raw little-endian bytes that kernels read in native order.
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+use super::{Model, TensorType};
+use crate::model::stl::vector::VectorSlice;
+
+/// Byte width of the scalars making up an element of `typ`, or `None` if the element
+/// data needs no swapping (single bytes) or has its own layout (strings).
+fn scalar_width(typ: TensorType) -> Option<usize> {
+    match typ {
+        TensorType::TensorType_FLOAT16 | TensorType::TensorType_INT16 => Some(2),
+        TensorType::TensorType_FLOAT32
+        | TensorType::TensorType_INT32
+        | TensorType::TensorType_COMPLEX64 => Some(4),
+        TensorType::TensorType_INT64 | TensorType::TensorType_FLOAT64 => Some(8),
+        _ => None,
+    }
+}
+
+/// Reverses the bytes of every `width`-byte scalar in `data`.
+pub(crate) fn swap_scalars(data: &mut [u8], width: usize) {
+    data.chunks_exact_mut(width).for_each(<[u8]>::reverse);
+}
+
+/// Swaps the `int32` header of a serialized string tensor: the string count followed by
+/// `count + 1` offsets. `from_le` tells whether `data` is currently little-endian.
+pub(crate) fn swap_string_header(data: &mut [u8], from_le: bool) {
+    let count = match data.get(..4) {
+        Some(bytes) => {
+            let bytes = bytes.try_into().unwrap();
+            if from_le {
+                i32::from_le_bytes(bytes)
+            } else {
+                i32::from_be_bytes(bytes)
+            }
+        }
+        None => return,
+    };
+    let header = 4 * (count.max(0) as usize + 2);
+    let header = header.min(data.len() / 4 * 4);
+    swap_scalars(&mut data[..header], 4);
+}
+
+impl Model {
+    /// Reverses the byte order of all constant tensor data, converting a little-endian
+    /// serialized model to the native order of a big-endian target or back.
+    /// `from_le` tells which order the buffers are in now.
+    ///
+    /// `FlatBufferModel::build_from_buffer` applies this automatically on big-endian targets.
+    pub fn byte_swap_buffers(&mut self, from_le: bool) {
+        let mut swapped = HashSet::new();
+        let mut tensors = Vec::new();
+        for subgraph in self.subgraphs.iter() {
+            for tensor in subgraph.tensors.iter() {
+                if swapped.insert(tensor.buffer) {
+                    tensors.push((tensor.buffer as usize, tensor.typ));
+                }
+            }
+        }
+        for (buffer, typ) in tensors {
+            if buffer >= self.buffers.size() {
+                continue;
+            }
+            let data = self.buffers[buffer].data.as_mut_slice();
+            match typ {
+                TensorType::TensorType_STRING => swap_string_header(data, from_le),
+                typ => {
+                    if let Some(width) = scalar_width(typ) {
+                        swap_scalars(data, width);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_swap_buffers() {
+        let mut data: Vec<u8> = [1.5f32, -2.0].iter().flat_map(|x| x.to_le_bytes()).collect();
+        swap_scalars(&mut data, 4);
+        assert_eq!(data[..4], 1.5f32.to_be_bytes());
+        assert_eq!(data[4..], (-2.0f32).to_be_bytes());
+
+        // Two strings "ab" and "c": count, three offsets, then the characters.
+        let mut data: Vec<u8> =
+            [2i32, 16, 18, 19].iter().flat_map(|x| x.to_le_bytes()).chain(*b"abc").collect();
+        swap_string_header(&mut data, true);
+        assert_eq!(data[..4], 2i32.to_be_bytes());
+        assert_eq!(data[12..16], 19i32.to_be_bytes());
+        assert_eq!(&data[16..], b"abc");
+        swap_string_header(&mut data, false);
+        assert_eq!(data[..4], 2i32.to_le_bytes());
+    }
+}
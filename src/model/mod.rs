@@ -1,5 +1,8 @@
 mod builtin_options;
 mod builtin_options_impl;
+mod endian;
+mod rewrite;
+mod sparsity;
 pub mod stl;
 
 use std::ffi::c_void;
@@ -18,11 +21,13 @@ use stl::vector::{
 
 pub use crate::bindings::flatbuffers::NativeTable;
 pub use crate::bindings::tflite::*;
-use crate::{Error, Result};
+use crate::{Error, ModelSource, Result};
 pub use builtin_options::{
     BuiltinOptionsUnion, ConcatEmbeddingsOptionsT, ReshapeOptionsT, SqueezeOptionsT,
 };
 pub use builtin_options_impl::*;
+pub use rewrite::{ChainMatch, OpCode, Replacement};
+pub use sparsity::{DimensionFormat, SparseExecution, SparseTensor, Sparsity};
 
 #[repr(C)]
 #[derive(Debug)]
@@ -271,7 +276,12 @@ impl Model {
     }
 
     pub fn from_file<P: AsRef<Path>>(filepath: P) -> Result<Self> {
-        Self::from_buffer(&fs::read(filepath)?)
+        Self::from_source(filepath.as_ref())
+    }
+
+    pub fn from_source<S: Into<ModelSource>>(source: S) -> Result<Self> {
+        let source = source.into().load()?;
+        Self::from_buffer(source.as_bytes().unwrap_or_default())
             .ok_or_else(|| Error::internal_error("failed to unpack the flatbuffer model"))
     }
 
@@ -288,7 +298,9 @@ impl Model {
                 uint8_t* ptr = fbb.GetBufferPointer();
                 size_t size = fbb.GetSize();
                 rust!(ModelT_to_file [ptr: *const u8 as "const uint8_t*", size: size_t as "size_t", buffer_ptr: &mut Vec<u8> as "void*"] {
-                    unsafe { buffer_ptr.extend_from_slice(&slice::from_raw_parts(ptr, size)) };
+                    crate::panic_guard::abort_on_panic("ModelT_to_file", || unsafe {
+                        buffer_ptr.extend_from_slice(&slice::from_raw_parts(ptr, size))
+                    });
                 });
             })
         }
@@ -306,6 +318,7 @@ mod tests {
     use super::*;
     use std::ffi::CString;
 
+    use crate::context::to_dim;
     use crate::model::stl::vector::{VectorErase, VectorExtract, VectorInsert, VectorSlice};
     use crate::ops::builtin::BuiltinOpResolver;
     use crate::{FlatBufferModel, InterpreterBuilder};
@@ -443,8 +456,8 @@ mod tests {
             }
             let mut operator = source_operator.clone();
             operator.opcode_index = 0;
-            let num_inputs = operator.inputs.len() as i32;
-            let num_outputs = operator.outputs.len() as i32;
+            let num_inputs = to_dim(operator.inputs.len()).unwrap();
+            let num_outputs = to_dim(operator.outputs.len()).unwrap();
             operator.inputs.assign(0..num_inputs);
             operator.outputs.assign(num_inputs..num_inputs + num_outputs);
             subgraph.operators.push_back(operator);
@@ -0,0 +1,345 @@
+//! Pattern-match-and-replace over the operators of a `Model`, e.g. to swap a chain of slow
+//! operators for a fused custom operator before deployment:
+//!
+//! ```ignore
+//! let mut model = Model::from_file("detector.tflite")?;
+//! let pattern = [OpCode::Builtin(BuiltinOperator_MUL), OpCode::Builtin(BuiltinOperator_ADD)];
+//! let replaced = model.replace_chains(&pattern, |_, _| {
+//!     Some(Replacement::new(OpCode::Custom("FusedMulAdd".to_string())))
+//! })?;
+//! model.to_file("detector-fused.tflite")?;
+//! ```
+//!
+//! A pattern is a chain: each operator consumes the output of the previous one, which must
+//! have a single output used by nothing but the next operator of the chain. Tensors only the
+//! replaced operators used stay in the model, unused.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+
+use super::stl::memory::UniquePtr;
+use super::stl::vector::{VectorExtract, VectorInsert, VectorSlice};
+use super::{BuiltinOperator, CustomOptionsFormat, Model, OperatorCodeT, OperatorT, SubGraphT};
+use crate::{Error, Result};
+
+/// An operator, by builtin code or custom code.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OpCode {
+    Builtin(BuiltinOperator),
+    Custom(String),
+}
+
+impl OpCode {
+    fn of(code: &OperatorCodeT) -> Self {
+        if code.builtin_code == BuiltinOperator::BuiltinOperator_CUSTOM {
+            OpCode::Custom(code.custom_code.c_str().to_string_lossy().into_owned())
+        } else {
+            OpCode::Builtin(code.builtin_code)
+        }
+    }
+}
+
+/// A chain of operators found in a subgraph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainMatch {
+    pub subgraph: usize,
+    /// Indices of the matched operators, in pattern order.
+    pub operators: Vec<usize>,
+    /// Tensors the chain reads that it does not produce, in order of first use.
+    pub inputs: Vec<i32>,
+    /// Outputs of the last operator.
+    pub outputs: Vec<i32>,
+}
+
+/// The operator replacing a match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replacement {
+    pub op: OpCode,
+    pub version: i32,
+    /// `None` to use the inputs of the match.
+    pub inputs: Option<Vec<i32>>,
+    /// Flexbuffer-encoded options of a custom operator.
+    pub custom_options: Vec<u8>,
+}
+
+impl Replacement {
+    pub fn new(op: OpCode) -> Self {
+        Self { op, version: 1, inputs: None, custom_options: Vec::new() }
+    }
+
+    pub fn with_inputs(mut self, inputs: Vec<i32>) -> Self {
+        self.inputs = Some(inputs);
+        self
+    }
+
+    pub fn with_custom_options(mut self, options: Vec<u8>) -> Self {
+        self.custom_options = options;
+        self
+    }
+}
+
+/// The parts of an operator matching looks at.
+struct OpView {
+    code: OpCode,
+    inputs: Vec<i32>,
+    outputs: Vec<i32>,
+}
+
+/// Finds non-overlapping chains of `pattern` among `ops`, in the order of their first operator.
+fn match_chains(ops: &[OpView], graph_outputs: &[i32], pattern: &[OpCode]) -> Vec<Vec<usize>> {
+    let mut consumers: HashMap<i32, Vec<usize>> = HashMap::new();
+    for (i, op) in ops.iter().enumerate() {
+        for &input in op.inputs.iter().filter(|&&input| input >= 0) {
+            consumers.entry(input).or_default().push(i);
+        }
+    }
+    let next = |i: usize| -> Option<usize> {
+        match (ops[i].outputs.as_slice(), graph_outputs) {
+            ([output], outputs) if !outputs.contains(output) => match consumers.get(output)?[..] {
+                [j] => Some(j),
+                _ => None,
+            },
+            _ => None,
+        }
+    };
+
+    let mut used = HashSet::new();
+    let mut chains = Vec::new();
+    if pattern.is_empty() {
+        return chains;
+    }
+    for start in 0..ops.len() {
+        if ops[start].code != pattern[0] {
+            continue;
+        }
+        let mut chain = vec![start];
+        for code in &pattern[1..] {
+            match next(*chain.last().unwrap()) {
+                Some(j) if ops[j].code == *code => chain.push(j),
+                _ => break,
+            }
+        }
+        if chain.len() == pattern.len() && chain.iter().all(|i| !used.contains(i)) {
+            used.extend(chain.iter().copied());
+            chains.push(chain);
+        }
+    }
+    chains
+}
+
+fn chain_inputs(ops: &[OpView], chain: &[usize]) -> Vec<i32> {
+    let produced: HashSet<i32> =
+        chain.iter().flat_map(|&i| ops[i].outputs.iter().copied()).collect();
+    let mut inputs = Vec::new();
+    for &input in chain.iter().flat_map(|&i| ops[i].inputs.iter()) {
+        if input >= 0 && !produced.contains(&input) && !inputs.contains(&input) {
+            inputs.push(input);
+        }
+    }
+    inputs
+}
+
+impl Model {
+    fn op_views(&self, subgraph: &SubGraphT) -> Result<Vec<OpView>> {
+        subgraph
+            .operators
+            .iter()
+            .map(|op| {
+                let code = self.operator_codes.get(op.opcode_index as usize).ok_or_else(|| {
+                    Error::InternalError(format!(
+                        "operator code {} out of range of {} operator codes",
+                        op.opcode_index,
+                        self.operator_codes.size()
+                    ))
+                })?;
+                Ok(OpView {
+                    code: OpCode::of(code),
+                    inputs: op.inputs.to_vec(),
+                    outputs: op.outputs.to_vec(),
+                })
+            })
+            .collect()
+    }
+
+    /// Chains of operators matching `pattern` in all subgraphs. Fails on operators referring
+    /// to missing operator codes.
+    pub fn find_chains(&self, pattern: &[OpCode]) -> Result<Vec<ChainMatch>> {
+        let mut matches = Vec::new();
+        for (index, subgraph) in self.subgraphs.iter().enumerate() {
+            let ops = self.op_views(subgraph)?;
+            for chain in match_chains(&ops, subgraph.outputs.as_slice(), pattern) {
+                matches.push(ChainMatch {
+                    subgraph: index,
+                    inputs: chain_inputs(&ops, &chain),
+                    outputs: ops[*chain.last().unwrap()].outputs.clone(),
+                    operators: chain,
+                });
+            }
+        }
+        Ok(matches)
+    }
+
+    /// The index of the operator code of `op` and `version`, added if missing. Fails for
+    /// custom names with a NUL byte.
+    pub fn operator_code_index(&mut self, op: &OpCode, version: i32) -> Result<u32> {
+        let existing = self
+            .operator_codes
+            .iter()
+            .position(|code| OpCode::of(code) == *op && code.version == version);
+        if let Some(index) = existing {
+            return Ok(index as u32);
+        }
+        let mut code: UniquePtr<OperatorCodeT> = Default::default();
+        match op {
+            OpCode::Builtin(builtin) => code.builtin_code = *builtin,
+            OpCode::Custom(name) => {
+                code.builtin_code = BuiltinOperator::BuiltinOperator_CUSTOM;
+                let name = CString::new(name.as_str()).map_err(|_| {
+                    Error::InternalError(format!("custom op name {:?} contains a NUL byte", name))
+                })?;
+                code.custom_code.assign(&name);
+            }
+        }
+        code.version = version;
+        self.operator_codes.push_back(code);
+        Ok((self.operator_codes.size() - 1) as u32)
+    }
+
+    /// Replaces each chain matching `pattern` by the operator `replace` returns for it, or
+    /// keeps it for `None`. The new operator takes the place of the last operator of the
+    /// chain and produces its outputs. Returns the number of chains replaced.
+    pub fn replace_chains<F>(&mut self, pattern: &[OpCode], mut replace: F) -> Result<usize>
+    where
+        F: FnMut(&Model, &ChainMatch) -> Option<Replacement>,
+    {
+        let mut replaced = 0;
+        let matches = self.find_chains(pattern)?;
+        for subgraph in 0..self.subgraphs.size() {
+            let mut replacements: HashMap<usize, UniquePtr<OperatorT>> = HashMap::new();
+            let mut removed = HashSet::new();
+            for chain in matches.iter().filter(|chain| chain.subgraph == subgraph) {
+                let replacement = match replace(self, chain) {
+                    Some(replacement) => replacement,
+                    None => continue,
+                };
+                let mut op: UniquePtr<OperatorT> = Default::default();
+                op.opcode_index = self.operator_code_index(&replacement.op, replacement.version)?;
+                op.inputs.assign(replacement.inputs.unwrap_or_else(|| chain.inputs.clone()));
+                op.outputs.assign(chain.outputs.iter().copied());
+                op.custom_options.assign(replacement.custom_options);
+                op.custom_options_format = CustomOptionsFormat::CustomOptionsFormat_FLEXBUFFERS;
+                removed.extend(chain.operators.iter().copied());
+                replacements.insert(*chain.operators.last().unwrap(), op);
+                replaced += 1;
+            }
+            if removed.is_empty() {
+                continue;
+            }
+            let operators = &mut self.subgraphs[subgraph].operators;
+            let old: Vec<_> = (0..operators.size()).map(|i| operators.extract(i)).collect();
+            let new: Vec<_> = old
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, op)| match replacements.remove(&i) {
+                    Some(replacement) => Some(replacement),
+                    None if removed.contains(&i) => None,
+                    None => Some(op),
+                })
+                .collect();
+            operators.assign(new);
+        }
+        Ok(replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_match_chains() {
+        let op = |code: BuiltinOperator, inputs: &[i32], outputs: &[i32]| OpView {
+            code: OpCode::Builtin(code),
+            inputs: inputs.to_vec(),
+            outputs: outputs.to_vec(),
+        };
+        let (mul, add) =
+            (BuiltinOperator::BuiltinOperator_MUL, BuiltinOperator::BuiltinOperator_ADD);
+        let ops = vec![
+            op(mul, &[0, 1], &[2]),
+            op(add, &[2, 3], &[4]),
+            // 6 is also read by the last operator, so this chain does not match.
+            op(mul, &[4, 1], &[6]),
+            op(add, &[6, 3], &[7]),
+            op(mul, &[7, 6], &[8]),
+            op(mul, &[8, 1], &[9]),
+            op(add, &[-1, 9], &[10]),
+        ];
+        let pattern = [OpCode::Builtin(mul), OpCode::Builtin(add)];
+        let chains = match_chains(&ops, &[10], &pattern);
+        assert_eq!(chains, vec![vec![0, 1], vec![5, 6]]);
+        assert_eq!(chain_inputs(&ops, &chains[0]), vec![0, 1, 3]);
+        assert_eq!(chain_inputs(&ops, &chains[1]), vec![8, 1]);
+        // A graph output between the operators breaks the chain.
+        assert_eq!(match_chains(&ops, &[2, 10], &pattern), vec![vec![5, 6]]);
+        assert!(match_chains(&ops, &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn unittest_replace_chains() {
+        let (mul, add, sub) = (
+            BuiltinOperator::BuiltinOperator_MUL,
+            BuiltinOperator::BuiltinOperator_ADD,
+            BuiltinOperator::BuiltinOperator_SUB,
+        );
+        let mut model = Model::default();
+        for &builtin in &[mul, add, sub] {
+            let mut code: UniquePtr<OperatorCodeT> = Default::default();
+            code.builtin_code = builtin;
+            code.version = 1;
+            model.operator_codes.push_back(code);
+        }
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        for (opcode_index, inputs, outputs) in
+            [(0, [0, 1], [2]), (1, [2, 3], [4]), (2, [4, 1], [5])].iter()
+        {
+            let mut op: UniquePtr<OperatorT> = Default::default();
+            op.opcode_index = *opcode_index;
+            op.inputs.assign(inputs.iter().copied());
+            op.outputs.assign(outputs.iter().copied());
+            subgraph.operators.push_back(op);
+        }
+        subgraph.inputs.assign(vec![0, 1, 3]);
+        subgraph.outputs.assign(vec![5]);
+        model.subgraphs.push_back(subgraph);
+
+        let pattern = [OpCode::Builtin(mul), OpCode::Builtin(add)];
+        let fused = OpCode::Custom("FusedMulAdd".to_string());
+        let replaced = model
+            .replace_chains(&pattern, |_, chain| {
+                assert_eq!(chain.operators, vec![0, 1]);
+                Some(Replacement::new(fused.clone()).with_custom_options(vec![1, 2]))
+            })
+            .unwrap();
+        assert_eq!(replaced, 1);
+
+        assert_eq!(model.operator_codes.size(), 4);
+        assert_eq!(OpCode::of(&model.operator_codes[3]), fused);
+        let operators = &model.subgraphs[0].operators;
+        assert_eq!(operators.size(), 2);
+        assert_eq!(operators[0].opcode_index, 3);
+        assert_eq!(operators[0].inputs.as_slice(), &[0, 1, 3]);
+        assert_eq!(operators[0].outputs.as_slice(), &[4]);
+        assert_eq!(operators[0].custom_options.as_slice(), &[1, 2]);
+        // The operator after the chain still reads its output.
+        assert_eq!(operators[1].opcode_index, 2);
+        assert_eq!(operators[1].inputs.as_slice(), &[4, 1]);
+        // The existing code is reused, not added again.
+        assert_eq!(model.operator_code_index(&fused, 1).unwrap(), 3);
+        assert!(model.operator_code_index(&OpCode::Custom("a\0b".to_string()), 1).is_err());
+
+        model.subgraphs[0].operators[1].opcode_index = 7;
+        assert!(model.find_chains(&pattern).is_err());
+        assert!(model.replace_chains(&pattern, |_, _| None).is_err());
+    }
+}
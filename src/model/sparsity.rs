@@ -0,0 +1,474 @@
+//! Sparse tensors of a model: which kernels run them, and densification.
+//!
+//! Sparse weights are about half the size of dense ones, but only a few kernels read them
+//! directly; other consumers need them densified by a `DENSIFY` operator at runtime, which
+//! costs the dense memory anyway. `Model::densify` does this once at load time:
+//!
+//! ```ignore
+//! let mut model = Model::from_file("pruned.tflite")?;
+//! for tensor in model.sparsity_report() {
+//!     println!("{} ({} of {} bytes): {:?}", tensor.name, tensor.sparse_bytes, tensor.dense_bytes, tensor.execution);
+//! }
+//! model.densify()?;
+//! let model = FlatBufferModel::build_from_model(&model)?;
+//! ```
+
+use std::os::raw::c_int;
+use std::slice;
+
+use super::stl::vector::{VectorErase, VectorInsert, VectorSlice};
+use super::{BuiltinOperator, Model, TensorT, TensorType};
+use crate::context::byte_size;
+use crate::{Error, Result};
+
+cpp! {{
+    #include <vector>
+
+    #include "tensorflow/lite/schema/schema_generated.h"
+
+    using namespace tflite;
+
+    static std::vector<int> sparse_index_vector(const SparseIndexVectorUnion& vector) {
+        std::vector<int> values;
+        if (const auto* v = vector.AsInt32Vector()) {
+            values.assign(v->values.begin(), v->values.end());
+        } else if (const auto* v = vector.AsUint16Vector()) {
+            values.assign(v->values.begin(), v->values.end());
+        } else if (const auto* v = vector.AsUint8Vector()) {
+            values.assign(v->values.begin(), v->values.end());
+        }
+        return values;
+    }
+}}
+
+/// Storage of one dimension of a sparse tensor, in traversal order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DimensionFormat {
+    Dense {
+        size: i32,
+    },
+    /// Compressed sparse row: the indices of the parent's `i`th element are
+    /// `indices[segments[i]..segments[i + 1]]`.
+    SparseCsr {
+        segments: Vec<i32>,
+        indices: Vec<i32>,
+    },
+}
+
+/// Sparsity parameters of a tensor, as in the TFLite schema.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sparsity {
+    /// Order in which the original dimensions, followed by the block dimensions, are stored.
+    pub traversal_order: Vec<i32>,
+    /// Original dimension of each block dimension.
+    pub block_map: Vec<i32>,
+    pub dim_metadata: Vec<DimensionFormat>,
+}
+
+impl Sparsity {
+    /// Reads the sparsity parameters of `tensor`, if it has any.
+    pub fn of(tensor: &TensorT) -> Option<Self> {
+        let tensor = tensor as *const TensorT;
+        let mut sparsity = Sparsity::default();
+        let sparsity_ptr = &mut sparsity;
+
+        #[allow(clippy::forget_copy, deprecated)]
+        let sparse = unsafe {
+            cpp!([tensor as "const TensorT*", sparsity_ptr as "void*"] -> bool as "bool" {
+                const auto* sparsity = tensor->sparsity.get();
+                if (!sparsity) {
+                    return false;
+                }
+                const int* traversal_order = sparsity->traversal_order.data();
+                size_t traversal_order_len = sparsity->traversal_order.size();
+                const int* block_map = sparsity->block_map.data();
+                size_t block_map_len = sparsity->block_map.size();
+                rust!(Sparsity_orders [
+                    sparsity_ptr: &mut Sparsity as "void*",
+                    traversal_order: *const c_int as "const int*",
+                    traversal_order_len: usize as "size_t",
+                    block_map: *const c_int as "const int*",
+                    block_map_len: usize as "size_t"
+                ] {
+                    crate::panic_guard::abort_on_panic("Sparsity_orders", || unsafe {
+                        sparsity_ptr.traversal_order = int_vec(traversal_order, traversal_order_len);
+                        sparsity_ptr.block_map = int_vec(block_map, block_map_len);
+                    });
+                });
+                for (const auto& dim : sparsity->dim_metadata) {
+                    bool dense = dim->format == DimensionType_DENSE;
+                    int size = dim->dense_size;
+                    std::vector<int> segments = sparse_index_vector(dim->array_segments);
+                    std::vector<int> indices = sparse_index_vector(dim->array_indices);
+                    const int* segments_ptr = segments.data();
+                    size_t segments_len = segments.size();
+                    const int* indices_ptr = indices.data();
+                    size_t indices_len = indices.size();
+                    rust!(Sparsity_push_dimension [
+                        sparsity_ptr: &mut Sparsity as "void*",
+                        dense: bool as "bool",
+                        size: i32 as "int",
+                        segments_ptr: *const c_int as "const int*",
+                        segments_len: usize as "size_t",
+                        indices_ptr: *const c_int as "const int*",
+                        indices_len: usize as "size_t"
+                    ] {
+                        crate::panic_guard::abort_on_panic("Sparsity_push_dimension", || {
+                            sparsity_ptr.dim_metadata.push(if dense {
+                                DimensionFormat::Dense { size }
+                            } else {
+                                DimensionFormat::SparseCsr {
+                                    segments: unsafe { int_vec(segments_ptr, segments_len) },
+                                    indices: unsafe { int_vec(indices_ptr, indices_len) },
+                                }
+                            });
+                        });
+                    });
+                }
+                return true;
+            })
+        };
+        if sparse {
+            Some(sparsity)
+        } else {
+            None
+        }
+    }
+
+    /// Expands `values`, the stored elements of `element_size` bytes each, into a dense
+    /// row-major buffer of `shape`. Unstored elements are zero.
+    pub fn densify(&self, shape: &[i32], values: &[u8], element_size: usize) -> Result<Vec<u8>> {
+        let invalid = |what: &str| Error::InternalError(format!("invalid sparse tensor: {}", what));
+        let rank = shape.len();
+        let levels = rank + self.block_map.len();
+        if self.traversal_order.len() != levels || self.dim_metadata.len() != levels {
+            return Err(invalid("rank mismatch"));
+        }
+        if element_size == 0 || shape.iter().any(|&d| d < 0) {
+            return Err(invalid("shape"));
+        }
+        if self.traversal_order.iter().any(|&d| d < 0 || d as usize >= levels)
+            || self.block_map.iter().any(|&d| d < 0 || d as usize >= rank)
+        {
+            return Err(invalid("traversal order or block map"));
+        }
+        let block_size = (0..self.block_map.len())
+            .map(|i| {
+                let level = self
+                    .traversal_order
+                    .iter()
+                    .position(|&d| d as usize == rank + i)
+                    .ok_or_else(|| invalid("block dimension missing from traversal order"))?;
+                match self.dim_metadata[level] {
+                    DimensionFormat::Dense { size } if size > 0 => Ok(size as usize),
+                    _ => Err(invalid("block dimensions must be dense")),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let dims: Vec<usize> = shape.iter().map(|&d| d as usize).collect();
+        let dense_bytes = byte_size(&dims, element_size)?;
+
+        let mut converter = Converter {
+            sparsity: self,
+            shape,
+            block_size: &block_size,
+            values,
+            element_size,
+            next: 0,
+            dense: vec![0; dense_bytes],
+            indices: vec![0; levels],
+        };
+        converter.populate(0, 0)?;
+        if converter.next * element_size != values.len() {
+            return Err(invalid("number of stored values"));
+        }
+        Ok(converter.dense)
+    }
+}
+
+unsafe fn int_vec(ptr: *const c_int, len: usize) -> Vec<i32> {
+    if len == 0 {
+        Vec::new()
+    } else {
+        slice::from_raw_parts(ptr, len).to_vec()
+    }
+}
+
+/// Port of TFLite's `FormatConverter::Populate`.
+struct Converter<'a> {
+    sparsity: &'a Sparsity,
+    shape: &'a [i32],
+    block_size: &'a [usize],
+    values: &'a [u8],
+    element_size: usize,
+    // Index of the next stored value.
+    next: usize,
+    dense: Vec<u8>,
+    indices: Vec<usize>,
+}
+
+impl<'a> Converter<'a> {
+    fn populate(&mut self, level: usize, parent: usize) -> Result<()> {
+        let invalid = |what: &str| Error::InternalError(format!("invalid sparse tensor: {}", what));
+        if level == self.indices.len() {
+            let rank = self.shape.len();
+            let order = &self.sparsity.traversal_order;
+            let mut dense_index = vec![0; rank];
+            let levels = order.iter().zip(&self.indices);
+            for (&dim, &index) in levels.clone().take(rank) {
+                dense_index[dim as usize] = index;
+            }
+            for (&dim, &index) in levels.skip(rank) {
+                let block = dim as usize - rank;
+                let dim = self.sparsity.block_map[block] as usize;
+                dense_index[dim] = dense_index[dim] * self.block_size[block] + index;
+            }
+            let mut offset = 0;
+            for (&index, &size) in dense_index.iter().zip(self.shape) {
+                if index >= size as usize {
+                    return Err(invalid("index out of bounds"));
+                }
+                offset = offset * size as usize + index;
+            }
+            let size = self.element_size;
+            let value = self
+                .values
+                .get(self.next * size..(self.next + 1) * size)
+                .ok_or_else(|| invalid("too few stored values"))?;
+            self.dense[offset * size..(offset + 1) * size].copy_from_slice(value);
+            self.next += 1;
+            return Ok(());
+        }
+
+        match &self.sparsity.dim_metadata[level] {
+            DimensionFormat::Dense { size } => {
+                let size = (*size).max(0) as usize;
+                for i in 0..size {
+                    self.indices[level] = i;
+                    self.populate(level + 1, parent * size + i)?;
+                }
+            }
+            DimensionFormat::SparseCsr { segments, indices } => {
+                let (start, end) = match (segments.get(parent), segments.get(parent + 1)) {
+                    (Some(&start), Some(&end)) if 0 <= start && start <= end => {
+                        (start as usize, end as usize)
+                    }
+                    _ => return Err(invalid("array segments")),
+                };
+                for i in start..end {
+                    let index = *indices.get(i).ok_or_else(|| invalid("array indices"))?;
+                    if index < 0 {
+                        return Err(invalid("array indices"));
+                    }
+                    self.indices[level] = index as usize;
+                    self.populate(level + 1, i)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the runtime executes the consumers of a sparse tensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SparseExecution {
+    /// All consumers have sparse kernels reading the compressed data.
+    SparseKernel,
+    /// A `DENSIFY` operator expands the tensor when the interpreter prepares.
+    Densified,
+    /// Some consumer has no sparse kernel and the tensor is not densified; the model only
+    /// runs after `Model::densify`.
+    Unsupported,
+}
+
+/// A sparse tensor of a model.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SparseTensor {
+    pub subgraph: usize,
+    pub tensor: usize,
+    pub name: String,
+    /// Size of the stored values.
+    pub sparse_bytes: usize,
+    /// Size once densified.
+    pub dense_bytes: usize,
+    /// Operators reading the tensor.
+    pub consumers: Vec<BuiltinOperator>,
+    pub execution: SparseExecution,
+}
+
+/// Operators with kernels reading sparse weights directly.
+fn has_sparse_kernel(op: BuiltinOperator) -> bool {
+    op == BuiltinOperator::BuiltinOperator_FULLY_CONNECTED
+}
+
+fn element_size(typ: TensorType) -> Option<usize> {
+    use TensorType::*;
+    Some(match typ {
+        TensorType_FLOAT32 | TensorType_INT32 => 4,
+        TensorType_FLOAT16 | TensorType_INT16 => 2,
+        TensorType_INT8 | TensorType_UINT8 | TensorType_BOOL => 1,
+        TensorType_INT64 | TensorType_COMPLEX64 => 8,
+        _ => return None,
+    })
+}
+
+impl Model {
+    /// Sparse tensors of all subgraphs, and how they are executed.
+    pub fn sparsity_report(&self) -> Vec<SparseTensor> {
+        let codes: Vec<BuiltinOperator> =
+            self.operator_codes.iter().map(|code| code.builtin_code).collect();
+        let mut report = Vec::new();
+        for (s, subgraph) in self.subgraphs.iter().enumerate() {
+            for (t, tensor) in subgraph.tensors.iter().enumerate() {
+                if Sparsity::of(tensor).is_none() {
+                    continue;
+                }
+                let consumers: Vec<BuiltinOperator> = subgraph
+                    .operators
+                    .iter()
+                    .filter(|op| op.inputs.contains(&(t as i32)))
+                    .map(|op| codes[op.opcode_index as usize])
+                    .collect();
+                let execution = if consumers.iter().all(|&op| has_sparse_kernel(op)) {
+                    SparseExecution::SparseKernel
+                } else if consumers.iter().all(|&op| op == BuiltinOperator::BuiltinOperator_DENSIFY)
+                {
+                    SparseExecution::Densified
+                } else {
+                    SparseExecution::Unsupported
+                };
+                let elements = tensor
+                    .shape
+                    .iter()
+                    .fold(1usize, |count, &d| count.saturating_mul(d.max(0) as usize));
+                report.push(SparseTensor {
+                    subgraph: s,
+                    tensor: t,
+                    name: tensor.name.c_str().to_string_lossy().into_owned(),
+                    sparse_bytes: self
+                        .buffers
+                        .get(tensor.buffer as usize)
+                        .map_or(0, |buffer| buffer.data.size()),
+                    dense_bytes: elements.saturating_mul(element_size(tensor.typ).unwrap_or(0)),
+                    consumers,
+                    execution,
+                });
+            }
+        }
+        report
+    }
+
+    /// Stores all constant sparse tensors densely and removes the `DENSIFY` operators
+    /// expanding them. Returns the number of densified tensors.
+    pub fn densify(&mut self) -> Result<usize> {
+        let codes: Vec<BuiltinOperator> =
+            self.operator_codes.iter().map(|code| code.builtin_code).collect();
+        let mut densified = 0;
+        for s in 0..self.subgraphs.size() {
+            // Read the outputs of DENSIFY operators from their inputs instead.
+            let densify_ops: Vec<(usize, i32, i32)> = self.subgraphs[s]
+                .operators
+                .iter()
+                .enumerate()
+                .filter(|(_, op)| {
+                    codes[op.opcode_index as usize] == BuiltinOperator::BuiltinOperator_DENSIFY
+                })
+                .map(|(i, op)| (i, op.inputs[0], op.outputs[0]))
+                .collect();
+            {
+                let subgraph = &mut self.subgraphs[s];
+                for &(_, input, output) in &densify_ops {
+                    for op in subgraph.operators.iter_mut() {
+                        op.inputs.iter_mut().filter(|i| **i == output).for_each(|i| *i = input);
+                    }
+                    subgraph.outputs.iter_mut().filter(|i| **i == output).for_each(|i| *i = input);
+                }
+                for &(i, _, _) in densify_ops.iter().rev() {
+                    subgraph.operators.erase(i);
+                }
+            }
+
+            for t in 0..self.subgraphs[s].tensors.size() {
+                let tensor = &self.subgraphs[s].tensors[t];
+                let sparsity = match Sparsity::of(tensor) {
+                    Some(sparsity) => sparsity,
+                    None => continue,
+                };
+                let name = tensor.name.c_str().to_string_lossy().into_owned();
+                let buffer = tensor.buffer as usize;
+                let values = match self.buffers.get(buffer) {
+                    Some(buffer) if buffer.data.size() > 0 => buffer.data.to_vec(),
+                    _ => {
+                        return Err(Error::InternalError(format!(
+                            "sparse tensor `{}` is not constant",
+                            name
+                        )))
+                    }
+                };
+                let element_size = element_size(tensor.typ).ok_or_else(|| {
+                    Error::InternalError(format!("unsupported type of sparse tensor `{}`", name))
+                })?;
+                let dense = sparsity.densify(&tensor.shape, &values, element_size)?;
+                self.buffers[buffer].data.assign(dense);
+
+                let tensor = &mut *self.subgraphs[s].tensors[t] as *mut TensorT;
+                #[allow(clippy::forget_copy, deprecated)]
+                unsafe {
+                    cpp!([tensor as "TensorT*"] {
+                        tensor->sparsity.reset();
+                    });
+                }
+                densified += 1;
+            }
+        }
+        Ok(densified)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn densify_i32(sparsity: &Sparsity, shape: &[i32], values: &[i32]) -> Vec<i32> {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_ne_bytes().to_vec()).collect();
+        let dense = sparsity.densify(shape, &bytes, 4).unwrap();
+        dense.chunks_exact(4).map(|b| i32::from_ne_bytes([b[0], b[1], b[2], b[3]])).collect()
+    }
+
+    #[test]
+    fn unittest_densify() {
+        // Dense rows, CSR columns.
+        let sparsity = Sparsity {
+            traversal_order: vec![0, 1],
+            block_map: vec![],
+            dim_metadata: vec![
+                DimensionFormat::Dense { size: 3 },
+                DimensionFormat::SparseCsr {
+                    segments: vec![0, 3, 3, 5],
+                    indices: vec![0, 2, 3, 0, 3],
+                },
+            ],
+        };
+        assert_eq!(
+            densify_i32(&sparsity, &[3, 4], &[6, 9, 8, 5, 7]),
+            vec![6, 0, 9, 8, 0, 0, 0, 0, 5, 0, 0, 7]
+        );
+        assert!(sparsity.densify(&[3, 4], &[0; 16], 4).is_err());
+
+        // 2x2 blocks, stored blocks in CSR.
+        let sparsity = Sparsity {
+            traversal_order: vec![0, 1, 2, 3],
+            block_map: vec![0, 1],
+            dim_metadata: vec![
+                DimensionFormat::Dense { size: 2 },
+                DimensionFormat::SparseCsr { segments: vec![0, 2, 3], indices: vec![0, 1, 1] },
+                DimensionFormat::Dense { size: 2 },
+                DimensionFormat::Dense { size: 2 },
+            ],
+        };
+        assert_eq!(
+            densify_i32(&sparsity, &[4, 4], &[1, 0, 0, 4, 2, 3, 0, 0, 5, 0, 0, 6]),
+            vec![1, 0, 2, 3, 0, 4, 0, 0, 0, 0, 5, 0, 0, 0, 0, 6]
+        );
+    }
+}
@@ -8,3 +8,11 @@ pub mod vector_impl;
 pub(crate) mod bindings {
     include!(concat!(env!("OUT_DIR"), "/stl_types.rs"));
 }
+
+// The opaque STL layouts are generated by bindgen for the target. Catch bindings generated
+// for a host with a different pointer width (e.g. 64-bit host, 32-bit ARM target).
+const _: () = {
+    use std::mem::size_of;
+    assert!(size_of::<bindings::root::rust::dummy_vector>() == 3 * size_of::<usize>());
+    assert!(size_of::<bindings::root::rust::unique_ptr_of_void>() == size_of::<usize>());
+};
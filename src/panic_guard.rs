@@ -0,0 +1,49 @@
+//! Keeping panics from unwinding into TF Lite's C++ frames, which is undefined behavior.
+//!
+//! Callbacks that can fail, e.g. kernels, catch panics and return `kTfLiteError` through
+//! `kernel::guard`. Callbacks with no way to report a failure, e.g. the `rust!` closures
+//! collecting results and delegate deleters, run under `abort_on_panic`.
+
+use std::any::Any;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The message of a panic payload, as printed by the default panic hook.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+/// Runs `f`, called from C++ as `callback`, aborting the process if it panics.
+pub(crate) fn abort_on_panic<T, F: FnOnce() -> T>(callback: &str, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let _ = writeln!(
+            std::io::stderr(),
+            "tflite: {} panicked inside TensorFlow Lite, aborting: {}",
+            callback,
+            panic_message(&*payload)
+        );
+        std::process::abort()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_panic_guard() {
+        let payload = panic::catch_unwind(|| panic!("bad tensor {}", 3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "bad tensor 3");
+        let payload = panic::catch_unwind(|| panic!("bad tensor")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "bad tensor");
+        let payload = panic::catch_unwind(|| panic::panic_any(3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "Box<dyn Any>");
+        assert_eq!(abort_on_panic("test", || 7), 7);
+    }
+}
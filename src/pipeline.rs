@@ -0,0 +1,189 @@
+//! Chaining interpreters so that the outputs of one stage feed the inputs of the next.
+
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result};
+
+/// Moves data from a stage's outputs into the next stage's inputs.
+pub type Transform<'a, Op> =
+    Box<dyn FnMut(&Interpreter<'a, Op>, &mut Interpreter<'a, Op>) -> Result<()> + Send + 'a>;
+
+struct Stage<'a, Op>
+where
+    Op: OpResolver,
+{
+    interpreter: Interpreter<'a, Op>,
+    // Applied before invoking this stage; `None` wires outputs to inputs one-to-one.
+    transform: Option<Transform<'a, Op>>,
+}
+
+/// A cascade of interpreters, e.g. detector → cropper → classifier.
+///
+/// Without a transform, the outputs of a stage are copied in order into the inputs of the
+/// next one, which requires matching counts, element kinds and dims.
+pub struct Pipeline<'a, Op>
+where
+    Op: OpResolver,
+{
+    stages: Vec<Stage<'a, Op>>,
+}
+
+impl<'a, Op> Pipeline<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn new(first: Interpreter<'a, Op>) -> Self {
+        Self { stages: vec![Stage { interpreter: first, transform: None }] }
+    }
+
+    /// Appends a stage whose inputs are wired directly to the outputs of the last stage.
+    pub fn then(mut self, next: Interpreter<'a, Op>) -> Result<Self> {
+        check_wiring(self.last(), &next)?;
+        self.stages.push(Stage { interpreter: next, transform: None });
+        Ok(self)
+    }
+
+    /// Appends a stage whose inputs are filled by `transform` from the last stage.
+    pub fn then_with<F>(mut self, transform: F, next: Interpreter<'a, Op>) -> Self
+    where
+        F: FnMut(&Interpreter<'a, Op>, &mut Interpreter<'a, Op>) -> Result<()> + Send + 'a,
+    {
+        self.stages.push(Stage { interpreter: next, transform: Some(Box::new(transform)) });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn stage(&self, index: usize) -> Option<&Interpreter<'a, Op>> {
+        self.stages.get(index).map(|stage| &stage.interpreter)
+    }
+
+    pub fn stage_mut(&mut self, index: usize) -> Option<&mut Interpreter<'a, Op>> {
+        self.stages.get_mut(index).map(|stage| &mut stage.interpreter)
+    }
+
+    /// The first stage, whose inputs are to be filled before `run`.
+    pub fn first_mut(&mut self) -> &mut Interpreter<'a, Op> {
+        &mut self.stages[0].interpreter
+    }
+
+    /// The last stage, whose outputs hold the result after `run`.
+    pub fn last(&self) -> &Interpreter<'a, Op> {
+        &self.stages[self.stages.len() - 1].interpreter
+    }
+
+    /// Invokes every stage in order, moving data between stages.
+    pub fn run(&mut self) -> Result<()> {
+        self.stages[0].interpreter.invoke()?;
+        for i in 1..self.stages.len() {
+            let (done, rest) = self.stages.split_at_mut(i);
+            let prev = &done[i - 1].interpreter;
+            let stage = &mut rest[0];
+            match &mut stage.transform {
+                Some(transform) => transform(prev, &mut stage.interpreter)?,
+                None => copy_outputs_to_inputs(prev, &mut stage.interpreter)?,
+            }
+            stage.interpreter.invoke()?;
+        }
+        Ok(())
+    }
+}
+
+fn check_wiring<Op>(prev: &Interpreter<'_, Op>, next: &Interpreter<'_, Op>) -> Result<()>
+where
+    Op: OpResolver,
+{
+    let (outputs, inputs) = (prev.outputs(), next.inputs());
+    if outputs.len() != inputs.len() {
+        return Err(Error::InternalError(format!(
+            "stage has {} outputs, but the next stage has {} inputs",
+            outputs.len(),
+            inputs.len()
+        )));
+    }
+    for (&output, &input) in outputs.iter().zip(inputs) {
+        let output = prev
+            .tensor_info(output)
+            .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        let input =
+            next.tensor_info(input).ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        if output.element_kind != input.element_kind || output.dims != input.dims {
+            return Err(Error::InternalError(format!(
+                "cannot wire output `{}` ({:?} {:?}) to input `{}` ({:?} {:?})",
+                output.name,
+                output.element_kind,
+                output.dims,
+                input.name,
+                input.element_kind,
+                input.dims
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn copy_outputs_to_inputs<Op>(
+    prev: &Interpreter<'_, Op>,
+    next: &mut Interpreter<'_, Op>,
+) -> Result<()>
+where
+    Op: OpResolver,
+{
+    check_wiring(prev, next)?;
+    let inputs = next.inputs().to_vec();
+    for (&output, input) in prev.outputs().iter().zip(inputs) {
+        let src = prev
+            .tensor_buffer(output)
+            .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        let dst = next
+            .tensor_buffer_mut(input)
+            .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+        dst.copy_from_slice(src);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::builtin::BuiltinOpResolver;
+    use crate::{FlatBufferModel, InterpreterBuilder};
+
+    #[test]
+    fn unittest_pipeline_transform() {
+        let resolver = BuiltinOpResolver::default();
+        let build = |path| -> Result<_> {
+            InterpreterBuilder::new(FlatBufferModel::build_from_file(path)?, &resolver)?.build()
+        };
+
+        let first = build("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let second = build("data/MNISTnet_v2_uint8_quant.tflite").unwrap();
+
+        // [1, 10] scores cannot be wired into a [1, 28, 28, 1] image.
+        assert!(Pipeline::new(first).then(second).is_err());
+
+        let first = build("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let second = build("data/MNISTnet_v2_uint8_quant.tflite").unwrap();
+        let mut pipeline = Pipeline::new(first).then_with(
+            |prev, next| {
+                let scores: &[u8] = prev.tensor_data(prev.outputs()[0])?;
+                let fill = scores.iter().cloned().max().unwrap_or(0);
+                let index = next.inputs()[0];
+                next.tensor_data_mut::<u8>(index)?.iter_mut().for_each(|x| *x = fill);
+                Ok(())
+            },
+            second,
+        );
+        pipeline.run().unwrap();
+        assert_eq!(pipeline.len(), 2);
+        assert_eq!(
+            pipeline.last().tensor_info(pipeline.last().outputs()[0]).unwrap().dims,
+            vec![1, 10]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::cmp::Ordering;
+
+use crate::metadata::{self, AssociatedFileType, ModelMetadata};
+use crate::op_resolver::OpResolver;
+use crate::{Error, FlatBufferModel, Interpreter, Result};
+
+/// Class labels, one per output element.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Labels(Vec<String>);
+
+impl Labels {
+    pub fn new(labels: Vec<String>) -> Self {
+        Labels(labels)
+    }
+
+    /// Parses a label file with one label per line. Trailing empty lines are ignored.
+    pub fn from_text(text: &str) -> Self {
+        let mut labels: Vec<String> = text.lines().map(|l| l.trim_end().to_string()).collect();
+        while labels.last().is_some_and(String::is_empty) {
+            labels.pop();
+        }
+        Labels(labels)
+    }
+
+    /// Loads the axis label file bundled for output `output` through the model metadata.
+    /// Returns `None` if the model declares no such file.
+    pub fn from_metadata(model: &FlatBufferModel, output: usize) -> Result<Option<Self>> {
+        let metadata = match ModelMetadata::from_flatbuffer_model(model)? {
+            Some(metadata) => metadata,
+            None => return Ok(None),
+        };
+        let file = match metadata
+            .output(output)
+            .and_then(|output| output.associated_file(AssociatedFileType::TensorAxisLabels))
+        {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let data = metadata::associated_file(model.buffer(), &file.name)?.ok_or_else(|| {
+            Error::InternalError(format!("associated file `{}` is missing", file.name))
+        })?;
+        Ok(Some(Self::from_text(&String::from_utf8_lossy(data))))
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.0.get(index).map(String::as_str)
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A scored class.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Category {
+    pub index: usize,
+    pub score: f32,
+    pub label: Option<String>,
+}
+
+/// Returns the `k` highest `(index, score)` pairs, best first. Ties keep index order.
+pub fn top_k<T>(scores: &[T], k: usize) -> Vec<(usize, f32)>
+where
+    T: Copy + Into<f32>,
+{
+    let mut scored: Vec<(usize, f32)> =
+        scores.iter().enumerate().map(|(i, &s)| (i, s.into())).collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    scored.truncate(k);
+    scored
+}
+
+/// Turns the first output of a classification model into labeled categories.
+#[derive(Clone, Debug, Default)]
+pub struct Classifier {
+    labels: Option<Labels>,
+}
+
+impl Classifier {
+    pub fn new(labels: Option<Labels>) -> Self {
+        Self { labels }
+    }
+
+    /// Creates a classifier using the label file bundled in the model metadata, if any.
+    pub fn from_model(model: &FlatBufferModel) -> Result<Self> {
+        Ok(Self { labels: Labels::from_metadata(model, 0)? })
+    }
+
+    /// The labels in use, e.g. for display.
+    pub fn labels(&self) -> Option<&Labels> {
+        self.labels.as_ref()
+    }
+
+    /// Ranks `scores` and attaches labels to the `k` best categories.
+    pub fn classify_scores<T>(&self, scores: &[T], k: usize) -> Vec<Category>
+    where
+        T: Copy + Into<f32>,
+    {
+        top_k(scores, k)
+            .into_iter()
+            .map(|(index, score)| Category {
+                index,
+                score,
+                label: self.labels.as_ref().and_then(|l| l.get(index)).map(str::to_string),
+            })
+            .collect()
+    }
+
+    /// Ranks the first output of `interpreter`, which must be `f32` or `u8`.
+    /// `u8` outputs are ranked by their raw values.
+    pub fn classify<Op>(&self, interpreter: &Interpreter<'_, Op>, k: usize) -> Result<Vec<Category>>
+    where
+        Op: OpResolver,
+    {
+        let index = *interpreter
+            .outputs()
+            .first()
+            .ok_or_else(|| Error::internal_error("model has no outputs"))?;
+        match interpreter.tensor_data::<f32>(index) {
+            Ok(scores) => Ok(self.classify_scores(scores, k)),
+            Err(_) => Ok(self.classify_scores(interpreter.tensor_data::<u8>(index)?, k)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_classify_scores() {
+        let labels = Labels::from_text("background\ncat\ndog\n\n");
+        assert_eq!(labels.len(), 3);
+
+        let classifier = Classifier::new(Some(labels));
+        let categories = classifier.classify_scores(&[0.1f32, 0.7, 0.2], 2);
+        assert_eq!(
+            categories,
+            vec![
+                Category { index: 1, score: 0.7, label: Some("cat".to_string()) },
+                Category { index: 2, score: 0.2, label: Some("dog".to_string()) },
+            ]
+        );
+        assert_eq!(top_k(&[3u8, 9, 9, 1], 3), vec![(1, 9.0), (2, 9.0), (0, 3.0)]);
+    }
+}
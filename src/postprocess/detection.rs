@@ -0,0 +1,240 @@
+use std::cmp::Ordering;
+
+use crate::{Error, Result};
+
+/// An axis-aligned box in the `[ymin, xmin, ymax, xmax]` order of TFLite detection models.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingBox {
+    pub ymin: f32,
+    pub xmin: f32,
+    pub ymax: f32,
+    pub xmax: f32,
+}
+
+impl BoundingBox {
+    pub fn new(ymin: f32, xmin: f32, ymax: f32, xmax: f32) -> Self {
+        Self { ymin, xmin, ymax, xmax }
+    }
+
+    pub fn area(&self) -> f32 {
+        (self.ymax - self.ymin).max(0.0) * (self.xmax - self.xmin).max(0.0)
+    }
+
+    /// Intersection over union, 0 for disjoint or empty boxes.
+    pub fn iou(&self, other: &BoundingBox) -> f32 {
+        let intersection = BoundingBox::new(
+            self.ymin.max(other.ymin),
+            self.xmin.max(other.xmin),
+            self.ymax.min(other.ymax),
+            self.xmax.min(other.xmax),
+        )
+        .area();
+        let union = self.area() + other.area() - intersection;
+        if union > 0.0 {
+            intersection / union
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A scored box of class `class`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    pub class: usize,
+    pub score: f32,
+    pub bbox: BoundingBox,
+}
+
+impl BoundingBox {
+    /// Decodes the `[ycenter, xcenter, h, w]` encoding of SSD models relative to `anchor` of the
+    /// same layout, dividing the encoding by `scales`.
+    pub fn from_center_size(encoding: &[f32], anchor: &[f32], scales: [f32; 4]) -> Self {
+        let ycenter = encoding[0] / scales[0] * anchor[2] + anchor[0];
+        let xcenter = encoding[1] / scales[1] * anchor[3] + anchor[1];
+        let half_h = 0.5 * (encoding[2] / scales[2]).exp() * anchor[2];
+        let half_w = 0.5 * (encoding[3] / scales[3]).exp() * anchor[3];
+        BoundingBox::new(ycenter - half_h, xcenter - half_w, ycenter + half_h, xcenter + half_w)
+    }
+}
+
+/// Greedy non-maximum suppression: indices of up to `max_detections` boxes scoring at least
+/// `score_threshold`, by decreasing score, none overlapping a better one by more than
+/// `iou_threshold`.
+pub fn non_max_suppression(
+    boxes: &[BoundingBox],
+    scores: &[f32],
+    max_detections: usize,
+    score_threshold: f32,
+    iou_threshold: f32,
+) -> Vec<usize> {
+    let mut candidates: Vec<usize> =
+        (0..scores.len().min(boxes.len())).filter(|&i| scores[i] >= score_threshold).collect();
+    candidates.sort_by(|&a, &b| scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal));
+    let mut selected: Vec<usize> = Vec::new();
+    for candidate in candidates {
+        if selected.len() >= max_detections {
+            break;
+        }
+        if selected.iter().all(|&i| boxes[i].iou(&boxes[candidate]) <= iou_threshold) {
+            selected.push(candidate);
+        }
+    }
+    selected
+}
+
+/// Options of the `TFLite_Detection_PostProcess` op ending SSD models. The defaults are those
+/// of the TF Object Detection API export script.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectionPostProcess {
+    pub max_detections: usize,
+    pub max_classes_per_detection: usize,
+    /// Boxes kept per class by regular NMS.
+    pub detections_per_class: usize,
+    /// Suppresses per class instead of once over the best class of each box.
+    pub use_regular_nms: bool,
+    pub score_threshold: f32,
+    pub iou_threshold: f32,
+    /// Classes without the background class.
+    pub num_classes: usize,
+    /// Divisors of the `[y, x, h, w]` box encodings.
+    pub scales: [f32; 4],
+}
+
+impl Default for DetectionPostProcess {
+    fn default() -> Self {
+        Self {
+            max_detections: 10,
+            max_classes_per_detection: 1,
+            detections_per_class: 100,
+            use_regular_nms: false,
+            score_threshold: 1e-8,
+            iou_threshold: 0.6,
+            num_classes: 90,
+            scales: [10.0, 10.0, 5.0, 5.0],
+        }
+    }
+}
+
+impl DetectionPostProcess {
+    /// Detections per selected box of fast NMS; regular NMS yields single detections.
+    pub fn classes_per_box(&self) -> usize {
+        if self.use_regular_nms {
+            1
+        } else {
+            self.max_classes_per_detection.min(self.num_classes)
+        }
+    }
+
+    /// Detections among decoded `boxes` with rows of `scores`, whose trailing `num_classes`
+    /// columns are the class scores (a leading background column is skipped).
+    ///
+    /// Fast NMS yields `classes_per_box` detections of each selected box in a row, best class
+    /// first; regular NMS yields detections by decreasing score.
+    pub fn run(&self, boxes: &[BoundingBox], scores: &[f32]) -> Result<Vec<Detection>> {
+        let columns = if boxes.is_empty() { 0 } else { scores.len() / boxes.len() };
+        if columns < self.num_classes || columns * boxes.len() != scores.len() {
+            return Err(Error::InternalError(format!(
+                "{} scores do not hold {} classes for each of {} boxes",
+                scores.len(),
+                self.num_classes,
+                boxes.len()
+            )));
+        }
+        let offset = columns - self.num_classes;
+        let class_scores = |i: usize| &scores[i * columns + offset..(i + 1) * columns];
+        let nms = |scores: &[f32], max_detections| {
+            non_max_suppression(
+                boxes,
+                scores,
+                max_detections,
+                self.score_threshold,
+                self.iou_threshold,
+            )
+        };
+
+        let mut detections = Vec::new();
+        if self.use_regular_nms {
+            let mut per_class = vec![0.0; boxes.len()];
+            for class in 0..self.num_classes {
+                for (i, score) in per_class.iter_mut().enumerate() {
+                    *score = class_scores(i)[class];
+                }
+                for i in nms(&per_class, self.detections_per_class) {
+                    detections.push(Detection { class, score: per_class[i], bbox: boxes[i] });
+                }
+            }
+            detections.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+            detections.truncate(self.max_detections);
+        } else {
+            let per_box = self.classes_per_box();
+            if per_box == 0 {
+                return Ok(detections);
+            }
+            let top_classes: Vec<Vec<usize>> = (0..boxes.len())
+                .map(|i| {
+                    let scores = class_scores(i);
+                    let mut classes: Vec<usize> = (0..scores.len()).collect();
+                    classes.sort_by(|&a, &b| {
+                        scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal)
+                    });
+                    classes.truncate(per_box);
+                    classes
+                })
+                .collect();
+            let max_scores: Vec<f32> =
+                top_classes.iter().enumerate().map(|(i, top)| class_scores(i)[top[0]]).collect();
+            for i in nms(&max_scores, self.max_detections) {
+                for &class in &top_classes[i] {
+                    detections.push(Detection {
+                        class,
+                        score: class_scores(i)[class],
+                        bbox: boxes[i],
+                    });
+                }
+            }
+        }
+        Ok(detections)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_iou() {
+        let a = BoundingBox::new(0.0, 0.0, 1.0, 1.0);
+        let b = BoundingBox::new(0.0, 0.5, 1.0, 1.5);
+        assert_eq!(a.iou(&a), 1.0);
+        assert!((a.iou(&b) - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(a.iou(&BoundingBox::new(2.0, 2.0, 3.0, 3.0)), 0.0);
+
+        let anchor = [0.5, 0.5, 0.2, 0.4];
+        let decoded = BoundingBox::from_center_size(&[1.0, -1.0, 0.0, 0.0], &anchor, [10.0; 4]);
+        assert!((decoded.ymin - 0.42).abs() < 1e-6 && (decoded.xmax - 0.66).abs() < 1e-6);
+
+        // Boxes 0 and 1 overlap; box 2 is apart. Columns: background, class 0, class 1.
+        let boxes = [a, BoundingBox::new(0.0, 0.1, 1.0, 1.1), BoundingBox::new(2.0, 2.0, 3.0, 3.0)];
+        let scores = [0.0, 0.9, 0.1, 0.0, 0.2, 0.8, 0.0, 0.3, 0.4];
+        let options = DetectionPostProcess {
+            max_detections: 3,
+            score_threshold: 0.25,
+            iou_threshold: 0.5,
+            num_classes: 2,
+            ..DetectionPostProcess::default()
+        };
+        let classes_and_boxes = |detections: Vec<Detection>| {
+            let boxes = &boxes;
+            detections
+                .iter()
+                .map(|d| (d.class, boxes.iter().position(|b| *b == d.bbox).unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(classes_and_boxes(options.run(&boxes, &scores).unwrap()), [(0, 0), (1, 2)]);
+        let regular = DetectionPostProcess { use_regular_nms: true, ..options.clone() };
+        let detections = regular.run(&boxes, &scores).unwrap();
+        assert_eq!(classes_and_boxes(detections), [(0, 0), (1, 1), (1, 2)]);
+        assert!(options.run(&boxes, &scores[1..]).is_err());
+    }
+}
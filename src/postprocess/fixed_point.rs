@@ -0,0 +1,141 @@
+//! Post-processing of quantized `u8`/`i8` outputs in integer arithmetic, for targets without
+//! an FPU. Quantization is monotonic for the positive scales TF Lite uses, so ranking raw
+//! values ranks scores; thresholds are converted to raw values once, from the bits of the
+//! scale.
+
+use std::cmp::Reverse;
+
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result};
+
+/// Raw values of quantized outputs.
+pub trait QuantizedValue: Copy + Ord {
+    fn to_i32(self) -> i32;
+}
+
+impl QuantizedValue for u8 {
+    fn to_i32(self) -> i32 {
+        i32::from(self)
+    }
+}
+
+impl QuantizedValue for i8 {
+    fn to_i32(self) -> i32 {
+        i32::from(self)
+    }
+}
+
+/// Index of the highest score, the first one on ties.
+pub fn argmax_quantized<T: QuantizedValue>(scores: &[T]) -> Option<usize> {
+    scores.iter().enumerate().min_by_key(|&(_, &s)| Reverse(s)).map(|(i, _)| i)
+}
+
+/// The `k` highest `(index, raw score)` pairs, best first. Ties keep index order.
+pub fn top_k_quantized<T: QuantizedValue>(scores: &[T], k: usize) -> Vec<(usize, T)> {
+    let mut scored: Vec<(usize, T)> = scores.iter().copied().enumerate().collect();
+    scored.sort_by_key(|&(_, s)| Reverse(s));
+    scored.truncate(k);
+    scored
+}
+
+/// A score threshold as the smallest raw value that reaches it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuantizedThreshold {
+    min: i32,
+}
+
+/// `a / b` rounded up, for `b > 0`.
+fn div_ceil(a: i128, b: i128) -> i128 {
+    a.div_euclid(b) + i128::from(a.rem_euclid(b) != 0)
+}
+
+impl QuantizedThreshold {
+    /// The threshold `numerator / denominator` for an output quantized with `scale` and
+    /// `zero_point`, i.e. `(raw - zero_point) * scale >= numerator / denominator`.
+    pub fn new(numerator: i32, denominator: i32, scale: f32, zero_point: i32) -> Result<Self> {
+        if denominator <= 0 {
+            return Err(Error::internal_error("the threshold denominator must be positive"));
+        }
+        // scale = mantissa * 2^exponent, decoded without floating point.
+        let bits = scale.to_bits();
+        let (exponent, fraction) = ((bits >> 23) & 0xff, i128::from(bits & 0x7f_ffff));
+        let (mantissa, exponent) = match exponent {
+            0 => (fraction, -149),
+            _ => (fraction | 0x80_0000, exponent as i32 - 150),
+        };
+        if bits >> 31 != 0 || mantissa == 0 || (bits >> 23) & 0xff == 0xff || exponent < -90 {
+            return Err(Error::InternalError(format!("unsupported output scale {}", scale)));
+        }
+        let (numerator, denominator) = (i128::from(numerator), i128::from(denominator));
+        // Past 2^60 the divisor exceeds any numerator, which rounds the same.
+        let steps = if exponent <= 0 {
+            div_ceil(numerator << -exponent, denominator * mantissa)
+        } else {
+            div_ceil(numerator, (denominator * mantissa) << exponent.min(60))
+        };
+        let min =
+            (i128::from(zero_point) + steps).clamp(i128::from(i32::MIN), i128::from(i32::MAX));
+        Ok(Self { min: min as i32 })
+    }
+
+    /// The threshold for output `n` of `interpreter`, from its quantization parameters.
+    pub fn for_output<Op>(
+        interpreter: &Interpreter<'_, Op>,
+        n: usize,
+        numerator: i32,
+        denominator: i32,
+    ) -> Result<Self>
+    where
+        Op: OpResolver,
+    {
+        let output = interpreter.output(n)?;
+        Self::new(numerator, denominator, output.scale(), output.zero_point())
+    }
+
+    /// The smallest raw value reaching the threshold; may be out of range of the raw type.
+    pub fn min_raw(&self) -> i32 {
+        self.min
+    }
+
+    pub fn passes<T: QuantizedValue>(&self, value: T) -> bool {
+        value.to_i32() >= self.min
+    }
+
+    /// `(index, raw score)` of the scores reaching the threshold, in index order.
+    pub fn filter<'a, T: QuantizedValue>(
+        &'a self,
+        scores: &'a [T],
+    ) -> impl Iterator<Item = (usize, T)> + 'a {
+        scores.iter().copied().enumerate().filter(move |&(_, s)| self.passes(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_fixed_point_postprocess() {
+        let scores = [3u8, 200, 17, 200, 90];
+        assert_eq!(argmax_quantized(&scores), Some(1));
+        assert_eq!(argmax_quantized::<i8>(&[]), None);
+        assert_eq!(top_k_quantized(&scores, 3), vec![(1, 200), (3, 200), (4, 90)]);
+        assert_eq!(top_k_quantized(&[-5i8, 7, -128], 5), vec![(1, 7), (0, -5), (2, -128)]);
+
+        // 0.5 at scale 1/256: raw 128.
+        let half = QuantizedThreshold::new(1, 2, 1.0 / 256.0, 0).unwrap();
+        assert_eq!(half.min_raw(), 128);
+        assert_eq!(half.filter(&scores).collect::<Vec<_>>(), vec![(1, 200), (3, 200)]);
+        assert_eq!(QuantizedThreshold::new(1, 2, 1.0 / 256.0, -128).unwrap().min_raw(), 0);
+        // 0.1 is slightly above a tenth as f32, so 0.5 is reached at raw 5.
+        assert_eq!(QuantizedThreshold::new(1, 2, 0.1, 0).unwrap().min_raw(), 5);
+        assert_eq!(QuantizedThreshold::new(3, 10, 0.25, 10).unwrap().min_raw(), 12);
+        assert_eq!(QuantizedThreshold::new(-1, 1, 0.5, 0).unwrap().min_raw(), -2);
+        assert_eq!(QuantizedThreshold::new(1, 1, 1.0e20, 0).unwrap().min_raw(), 1);
+
+        assert!(QuantizedThreshold::new(1, 0, 0.5, 0).is_err());
+        assert!(QuantizedThreshold::new(1, 2, 0.0, 0).is_err());
+        assert!(QuantizedThreshold::new(1, 2, -0.5, 0).is_err());
+        assert!(QuantizedThreshold::new(1, 2, f32::NAN, 0).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+//! Helpers for interpreting output tensors.
+
+mod classification;
+mod detection;
+mod fixed_point;
+
+pub use classification::{top_k, Category, Classifier, Labels};
+pub use detection::{non_max_suppression, BoundingBox, Detection, DetectionPostProcess};
+pub use fixed_point::{argmax_quantized, top_k_quantized, QuantizedThreshold, QuantizedValue};
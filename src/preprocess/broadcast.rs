@@ -0,0 +1,94 @@
+use crate::{Error, Result};
+
+/// Copies `src` of shape `src_shape` into `dst` of shape `dst_shape` with NumPy-style
+/// broadcasting: shapes are right-aligned, missing leading dims are treated as 1, and
+/// dims of size 1 in `src` are replicated.
+pub fn broadcast<T>(
+    src: &[T],
+    src_shape: &[usize],
+    dst: &mut [T],
+    dst_shape: &[usize],
+) -> Result<()>
+where
+    T: Copy,
+{
+    check_len(src, src_shape)?;
+    check_len(dst, dst_shape)?;
+    if src_shape.len() > dst_shape.len() {
+        return Err(shape_mismatch(src_shape, dst_shape));
+    }
+
+    let pad = dst_shape.len() - src_shape.len();
+    let mut aligned = vec![1; pad];
+    aligned.extend_from_slice(src_shape);
+    if aligned.iter().zip(dst_shape).any(|(&s, &d)| s != d && s != 1) {
+        return Err(shape_mismatch(src_shape, dst_shape));
+    }
+    if dst.is_empty() {
+        return Ok(());
+    }
+
+    // Broadcast dims get a zero stride so the same source elements are read repeatedly.
+    let mut strides = vec![0; aligned.len()];
+    let mut acc = 1;
+    for i in (0..aligned.len()).rev() {
+        strides[i] = if aligned[i] == 1 { 0 } else { acc };
+        acc *= aligned[i];
+    }
+    copy_dim(src, dst, dst_shape, &strides);
+    Ok(())
+}
+
+fn copy_dim<T: Copy>(src: &[T], dst: &mut [T], dst_shape: &[usize], strides: &[usize]) {
+    match dst_shape.len() {
+        0 => dst[0] = src[0],
+        1 if strides[0] == 0 => dst.iter_mut().for_each(|x| *x = src[0]),
+        1 => dst.copy_from_slice(&src[..dst.len()]),
+        _ => {
+            let chunk = dst.len() / dst_shape[0];
+            for (i, out) in dst.chunks_mut(chunk).enumerate() {
+                copy_dim(&src[i * strides[0]..], out, &dst_shape[1..], &strides[1..]);
+            }
+        }
+    }
+}
+
+fn check_len<T>(data: &[T], shape: &[usize]) -> Result<()> {
+    let expected: usize = shape.iter().product();
+    if data.len() != expected {
+        return Err(Error::InternalError(format!(
+            "data has {} elements, but shape {:?} requires {}",
+            data.len(),
+            shape,
+            expected
+        )));
+    }
+    Ok(())
+}
+
+fn shape_mismatch(src_shape: &[usize], dst_shape: &[usize]) -> Error {
+    Error::InternalError(format!("shape {:?} cannot be broadcast to {:?}", src_shape, dst_shape))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_broadcast() {
+        // A single 2x2 image replicated into a batch of 3.
+        let mut dst = [0u8; 12];
+        broadcast(&[1, 2, 3, 4], &[2, 2], &mut dst, &[3, 2, 2]).unwrap();
+        assert_eq!(dst, [1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4]);
+
+        // Per-row and per-column values.
+        let mut dst = [0i32; 6];
+        broadcast(&[1, 2], &[2, 1], &mut dst, &[2, 3]).unwrap();
+        assert_eq!(dst, [1, 1, 1, 2, 2, 2]);
+        broadcast(&[1, 2, 3], &[1, 3], &mut dst, &[2, 3]).unwrap();
+        assert_eq!(dst, [1, 2, 3, 1, 2, 3]);
+
+        assert!(broadcast(&[1, 2], &[2], &mut dst, &[2, 3]).is_err());
+        assert!(broadcast(&[1, 2, 3], &[2], &mut dst, &[2, 3]).is_err());
+    }
+}
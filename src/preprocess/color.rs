@@ -0,0 +1,158 @@
+use crate::{Error, Result};
+
+/// Pixel formats of 8-bit camera and image frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+    Rgba,
+    Bgra,
+    /// Planar YUV 4:2:0: a Y plane followed by quarter-size U and V planes.
+    I420,
+    /// Semi-planar YUV 4:2:0: a Y plane followed by an interleaved UV plane.
+    Nv12,
+    /// Semi-planar YUV 4:2:0 with VU ordering, the Android camera default.
+    Nv21,
+}
+
+impl PixelFormat {
+    /// Number of bytes of a `width x height` frame in this format.
+    pub fn frame_len(self, width: usize, height: usize) -> usize {
+        let chroma = width.div_ceil(2) * height.div_ceil(2);
+        match self {
+            PixelFormat::Rgb | PixelFormat::Bgr => width * height * 3,
+            PixelFormat::Rgba | PixelFormat::Bgra => width * height * 4,
+            PixelFormat::I420 | PixelFormat::Nv12 | PixelFormat::Nv21 => {
+                width * height + 2 * chroma
+            }
+        }
+    }
+}
+
+/// A borrowed 8-bit frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame<'a> {
+    pub data: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    pub format: PixelFormat,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(data: &'a [u8], width: usize, height: usize, format: PixelFormat) -> Result<Self> {
+        let expected = format.frame_len(width, height);
+        if data.len() < expected {
+            return Err(Error::InternalError(format!(
+                "{:?} frame of {}x{} requires {} bytes, got {}",
+                format,
+                width,
+                height,
+                expected,
+                data.len()
+            )));
+        }
+        Ok(Self { data, width, height, format })
+    }
+
+    /// Converts the frame to packed RGB, calling `write(pixel_index, [r, g, b])` per pixel
+    /// in row-major order. Used to write straight into tensors of any element type.
+    pub fn for_each_rgb<F>(&self, mut write: F)
+    where
+        F: FnMut(usize, [u8; 3]),
+    {
+        let (w, h, data) = (self.width, self.height, self.data);
+        match self.format {
+            PixelFormat::Rgb => data[..w * h * 3]
+                .chunks_exact(3)
+                .enumerate()
+                .for_each(|(i, p)| write(i, [p[0], p[1], p[2]])),
+            PixelFormat::Bgr => data[..w * h * 3]
+                .chunks_exact(3)
+                .enumerate()
+                .for_each(|(i, p)| write(i, [p[2], p[1], p[0]])),
+            PixelFormat::Rgba => data[..w * h * 4]
+                .chunks_exact(4)
+                .enumerate()
+                .for_each(|(i, p)| write(i, [p[0], p[1], p[2]])),
+            PixelFormat::Bgra => data[..w * h * 4]
+                .chunks_exact(4)
+                .enumerate()
+                .for_each(|(i, p)| write(i, [p[2], p[1], p[0]])),
+            PixelFormat::I420 | PixelFormat::Nv12 | PixelFormat::Nv21 => {
+                let cw = w.div_ceil(2);
+                let (luma, chroma) = data.split_at(w * h);
+                for y in 0..h {
+                    for x in 0..w {
+                        let c = (y / 2) * cw + x / 2;
+                        let (u, v) = match self.format {
+                            PixelFormat::I420 => {
+                                let plane = cw * h.div_ceil(2);
+                                (chroma[c], chroma[plane + c])
+                            }
+                            PixelFormat::Nv12 => (chroma[2 * c], chroma[2 * c + 1]),
+                            _ => (chroma[2 * c + 1], chroma[2 * c]),
+                        };
+                        write(y * w + x, yuv_to_rgb(luma[y * w + x], u, v));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Converts the frame into packed RGB in `dst`, which must hold `width * height * 3` bytes.
+    pub fn to_rgb(&self, dst: &mut [u8]) -> Result<()> {
+        let expected = self.width * self.height * 3;
+        if dst.len() != expected {
+            return Err(Error::InternalError(format!(
+                "RGB output of {}x{} requires {} bytes, got {}",
+                self.width,
+                self.height,
+                expected,
+                dst.len()
+            )));
+        }
+        self.for_each_rgb(|i, rgb| dst[i * 3..i * 3 + 3].copy_from_slice(&rgb));
+        Ok(())
+    }
+}
+
+/// BT.601 limited-range YUV to RGB conversion in fixed point.
+pub fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (i32::from(y) - 16);
+    let d = i32::from(u) - 128;
+    let e = i32::from(v) - 128;
+    let clamp = |x: i32| ((x + 128) >> 8).clamp(0, 255) as u8;
+    [clamp(c + 409 * e), clamp(c - 100 * d - 208 * e), clamp(c + 516 * d)]
+}
+
+/// Swaps the first and third channel of every pixel in place (RGB ↔ BGR).
+pub fn swap_red_blue(data: &mut [u8], channels: usize) {
+    data.chunks_exact_mut(channels).for_each(|p| p.swap(0, 2));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_color_conversion() {
+        let bgra = [10, 20, 30, 255, 40, 50, 60, 255];
+        let mut rgb = [0u8; 6];
+        Frame::new(&bgra, 2, 1, PixelFormat::Bgra).unwrap().to_rgb(&mut rgb).unwrap();
+        assert_eq!(rgb, [30, 20, 10, 60, 50, 40]);
+
+        swap_red_blue(&mut rgb, 3);
+        assert_eq!(rgb, [10, 20, 30, 40, 50, 60]);
+
+        assert_eq!(yuv_to_rgb(16, 128, 128), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(235, 128, 128), [255, 255, 255]);
+
+        // 2x2 frame: four luma samples sharing one chroma pair.
+        let nv21 = [16, 235, 235, 16, 128, 128];
+        let mut rgb = [0u8; 12];
+        Frame::new(&nv21, 2, 2, PixelFormat::Nv21).unwrap().to_rgb(&mut rgb).unwrap();
+        assert_eq!(rgb, [0, 0, 0, 255, 255, 255, 255, 255, 255, 0, 0, 0]);
+
+        assert!(Frame::new(&nv21[..5], 2, 2, PixelFormat::Nv12).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{Error, Result};
+
+/// Memory layout of 4-D image data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Batch, height, width, channels; the layout TensorFlow Lite models expect.
+    #[default]
+    Nhwc,
+    /// Batch, channels, height, width; as produced by PyTorch-style pipelines.
+    Nchw,
+}
+
+impl Layout {
+    /// Reorders a 4-D shape given in this layout to NHWC.
+    pub fn to_nhwc_shape(self, shape: [usize; 4]) -> [usize; 4] {
+        match self {
+            Layout::Nhwc => shape,
+            Layout::Nchw => [shape[0], shape[2], shape[3], shape[1]],
+        }
+    }
+}
+
+/// Transposes `src` of shape `[n, c, h, w]` into `dst` laid out as `[n, h, w, c]`.
+pub fn nchw_to_nhwc<T: Copy>(src: &[T], dst: &mut [T], shape: [usize; 4]) -> Result<()> {
+    let [n, c, h, w] = shape;
+    check_len(src, dst, shape)?;
+    let plane = c * h * w;
+    for b in 0..n {
+        transpose(&src[b * plane..(b + 1) * plane], &mut dst[b * plane..(b + 1) * plane], c, h * w);
+    }
+    Ok(())
+}
+
+/// Transposes `src` of shape `[n, h, w, c]` into `dst` laid out as `[n, c, h, w]`.
+pub fn nhwc_to_nchw<T: Copy>(src: &[T], dst: &mut [T], shape: [usize; 4]) -> Result<()> {
+    let [n, h, w, c] = shape;
+    check_len(src, dst, shape)?;
+    let plane = c * h * w;
+    for b in 0..n {
+        transpose(&src[b * plane..(b + 1) * plane], &mut dst[b * plane..(b + 1) * plane], h * w, c);
+    }
+    Ok(())
+}
+
+/// Cache-blocked transpose of a row-major `rows x cols` matrix.
+fn transpose<T: Copy>(src: &[T], dst: &mut [T], rows: usize, cols: usize) {
+    const BLOCK: usize = 32;
+    for r0 in (0..rows).step_by(BLOCK) {
+        for c0 in (0..cols).step_by(BLOCK) {
+            for r in r0..(r0 + BLOCK).min(rows) {
+                for c in c0..(c0 + BLOCK).min(cols) {
+                    dst[c * rows + r] = src[r * cols + c];
+                }
+            }
+        }
+    }
+}
+
+fn check_len<T>(src: &[T], dst: &[T], shape: [usize; 4]) -> Result<()> {
+    let expected: usize = shape.iter().product();
+    if src.len() != expected || dst.len() != expected {
+        return Err(Error::InternalError(format!(
+            "layout conversion of shape {:?} requires {} elements, got {} and {}",
+            shape,
+            expected,
+            src.len(),
+            dst.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_layout_roundtrip() {
+        let shape = [2, 3, 4, 5];
+        let nchw: Vec<u32> = (0..120).collect();
+        let mut nhwc = vec![0; 120];
+        nchw_to_nhwc(&nchw, &mut nhwc, shape).unwrap();
+
+        // element (b, c, y, x) moves to (b, y, x, c)
+        let (b, c, y, x) = (1, 2, 3, 4);
+        assert_eq!(nhwc[((b * 4 + y) * 5 + x) * 3 + c], nchw[((b * 3 + c) * 4 + y) * 5 + x]);
+
+        let mut back = vec![0; 120];
+        nhwc_to_nchw(&nhwc, &mut back, Layout::Nchw.to_nhwc_shape(shape)).unwrap();
+        assert_eq!(back, nchw);
+
+        assert!(nchw_to_nhwc(&nchw[1..], &mut nhwc, shape).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Helpers for filling input tensors.
+
+mod broadcast;
+mod color;
+mod layout;
+mod quantized;
+
+pub use broadcast::broadcast;
+pub use color::{swap_red_blue, yuv_to_rgb, Frame, PixelFormat};
+pub use layout::{nchw_to_nhwc, nhwc_to_nchw, Layout};
+pub use quantized::{set_input_frame_quantized, QuantizationTable};
+
+use crate::bindings::TfLiteType;
+use crate::context::{ElemKindOf, TensorInfo};
+use crate::metadata::{
+    ColorSpace, ContentProperties, ModelMetadata, NormalizationOptions, TensorMetadata,
+};
+use crate::op_resolver::OpResolver;
+use crate::{Error, FlatBufferModel, Interpreter, Result, TensorIndex};
+
+/// Copies `data` of shape `shape` into the tensor at `tensor_index`, broadcasting it to the
+/// tensor's dims when `shape` is smaller but compatible, e.g. one image for a batch-N model.
+pub fn set_input<T, Op>(
+    interpreter: &mut Interpreter<'_, Op>,
+    tensor_index: TensorIndex,
+    data: &[T],
+    shape: &[usize],
+) -> Result<()>
+where
+    T: ElemKindOf + Copy,
+    Op: OpResolver,
+{
+    set_input_with_layout(interpreter, tensor_index, data, shape, Layout::Nhwc)
+}
+
+/// Like `set_input`, but `data` and `shape` may be given in NCHW layout.
+/// NCHW data must be 4-D and is transposed to NHWC before broadcasting.
+pub fn set_input_with_layout<T, Op>(
+    interpreter: &mut Interpreter<'_, Op>,
+    tensor_index: TensorIndex,
+    data: &[T],
+    shape: &[usize],
+    layout: Layout,
+) -> Result<()>
+where
+    T: ElemKindOf + Copy,
+    Op: OpResolver,
+{
+    if layout == Layout::Nchw {
+        if shape.len() != 4 {
+            return Err(Error::InternalError(format!("NCHW input must be 4-D, got {:?}", shape)));
+        }
+        let shape = [shape[0], shape[1], shape[2], shape[3]];
+        let mut nhwc = data.to_vec();
+        nchw_to_nhwc(data, &mut nhwc, shape)?;
+        return set_input(interpreter, tensor_index, &nhwc, &layout.to_nhwc_shape(shape));
+    }
+
+    let dims = interpreter
+        .tensor_info(tensor_index)
+        .ok_or_else(|| Error::internal_error("invalid tensor index"))?
+        .dims;
+    broadcast(data, shape, interpreter.tensor_data_mut(tensor_index)?, &dims)
+}
+
+/// Image preprocessing applied when writing frames into input tensors.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImagePreprocessing {
+    /// Per-channel `(x - mean) / std` applied to `f32` inputs. Quantized `u8` inputs
+    /// receive raw pixel values, since their normalization is folded into quantization;
+    /// `QuantizationTable` applies it to quantized inputs, too.
+    pub normalization: Option<NormalizationOptions>,
+    /// Write a single luma channel instead of RGB.
+    pub grayscale: bool,
+}
+
+impl ImagePreprocessing {
+    /// Derives preprocessing from the normalization and color space declared in metadata.
+    pub fn from_metadata(metadata: &TensorMetadata) -> Self {
+        let grayscale = match &metadata.content {
+            Some(ContentProperties::Image(image)) => image.color_space == ColorSpace::Grayscale,
+            _ => false,
+        };
+        Self { normalization: metadata.normalization.clone(), grayscale }
+    }
+
+    /// Reads the preprocessing of input `input` from the metadata embedded in `model`.
+    /// Models without metadata get the default (no normalization, RGB).
+    pub fn for_input(model: &FlatBufferModel, input: usize) -> Result<Self> {
+        let metadata = ModelMetadata::from_flatbuffer_model(model)?;
+        Ok(metadata
+            .as_ref()
+            .and_then(|m| m.input(input))
+            .map(Self::from_metadata)
+            .unwrap_or_default())
+    }
+
+    fn normalize(&self, channel: usize, value: u8) -> f32 {
+        let value = f32::from(value);
+        match &self.normalization {
+            Some(NormalizationOptions { mean, std }) if !mean.is_empty() && !std.is_empty() => {
+                (value - mean[channel % mean.len()]) / std[channel % std.len()]
+            }
+            _ => value,
+        }
+    }
+
+    fn channels(&self) -> usize {
+        if self.grayscale {
+            1
+        } else {
+            3
+        }
+    }
+
+    /// The channel values written for an RGB pixel, and how many there are.
+    fn pixel(&self, rgb: [u8; 3]) -> ([u8; 3], usize) {
+        if self.grayscale {
+            let [r, g, b] = rgb;
+            let luma = (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+            ([luma as u8, 0, 0], 1)
+        } else {
+            (rgb, 3)
+        }
+    }
+}
+
+/// The info of the tensor at `tensor_index`, checking that its dims fit `frame`.
+fn frame_input_info<Op>(
+    interpreter: &Interpreter<'_, Op>,
+    tensor_index: TensorIndex,
+    frame: &Frame<'_>,
+    channels: usize,
+) -> Result<TensorInfo>
+where
+    Op: OpResolver,
+{
+    let info = interpreter
+        .tensor_info(tensor_index)
+        .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+    if info.dims != [1, frame.height, frame.width, channels] {
+        return Err(Error::InternalError(format!(
+            "{}x{}x{} frame does not match input dims {:?}",
+            frame.width, frame.height, channels, info.dims
+        )));
+    }
+    Ok(info)
+}
+
+/// Converts `frame` to RGB and writes it straight into the tensor at `tensor_index`, which
+/// must have dims `[1, height, width, 3]` and be `u8` or `f32` (raw 0-255 values).
+pub fn set_input_frame<Op>(
+    interpreter: &mut Interpreter<'_, Op>,
+    tensor_index: TensorIndex,
+    frame: &Frame<'_>,
+) -> Result<()>
+where
+    Op: OpResolver,
+{
+    set_input_frame_with(interpreter, tensor_index, frame, &ImagePreprocessing::default())
+}
+
+/// Like `set_input_frame`, applying `preprocessing` while writing. Grayscale preprocessing
+/// expects dims `[1, height, width, 1]`.
+pub fn set_input_frame_with<Op>(
+    interpreter: &mut Interpreter<'_, Op>,
+    tensor_index: TensorIndex,
+    frame: &Frame<'_>,
+    preprocessing: &ImagePreprocessing,
+) -> Result<()>
+where
+    Op: OpResolver,
+{
+    let info = frame_input_info(interpreter, tensor_index, frame, preprocessing.channels())?;
+    match info.element_kind {
+        TfLiteType::kTfLiteUInt8 => {
+            let data = interpreter.tensor_data_mut::<u8>(tensor_index)?;
+            frame.for_each_rgb(|i, rgb| {
+                let (values, n) = preprocessing.pixel(rgb);
+                data[i * n..(i + 1) * n].copy_from_slice(&values[..n]);
+            });
+            Ok(())
+        }
+        TfLiteType::kTfLiteFloat32 => {
+            let data = interpreter.tensor_data_mut::<f32>(tensor_index)?;
+            frame.for_each_rgb(|i, rgb| {
+                let (values, n) = preprocessing.pixel(rgb);
+                for (c, d) in data[i * n..(i + 1) * n].iter_mut().enumerate() {
+                    *d = preprocessing.normalize(c, values[c]);
+                }
+            });
+            Ok(())
+        }
+        kind => Err(Error::InternalError(format!("unsupported input type `{:?}`", kind))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::ImageProperties;
+
+    #[test]
+    fn unittest_preprocessing_from_metadata() {
+        let metadata = TensorMetadata {
+            content: Some(ContentProperties::Image(ImageProperties {
+                color_space: ColorSpace::Grayscale,
+                default_size: None,
+            })),
+            normalization: Some(NormalizationOptions { mean: vec![127.5], std: vec![127.5] }),
+            ..Default::default()
+        };
+        let preprocessing = ImagePreprocessing::from_metadata(&metadata);
+        assert!(preprocessing.grayscale);
+
+        #[allow(clippy::float_cmp)]
+        {
+            assert_eq!(preprocessing.normalize(0, 0), -1.0);
+            assert_eq!(preprocessing.normalize(2, 255), 1.0);
+            assert_eq!(ImagePreprocessing::default().normalize(1, 255), 255.0);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use super::{frame_input_info, Frame, ImagePreprocessing};
+use crate::bindings::TfLiteType;
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result, TensorIndex};
+
+/// Per-channel tables from 8-bit pixel values to the quantized values of an input tensor,
+/// normalizing and quantizing in one lookup instead of going through `f32`.
+///
+/// Build it once per input and reuse it for every frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantizationTable {
+    preprocessing: ImagePreprocessing,
+    kind: TfLiteType,
+    // The bytes to write, i.e. two's complement for `i8` tensors.
+    channels: Vec<[u8; 256]>,
+}
+
+impl QuantizationTable {
+    /// Tables for a `u8` or `i8` tensor quantized with `scale` and `zero_point`, writing
+    /// `quantize(normalize(pixel))` rounded to nearest and saturated.
+    pub fn new(
+        preprocessing: &ImagePreprocessing,
+        kind: TfLiteType,
+        scale: f32,
+        zero_point: i32,
+    ) -> Result<Self> {
+        let (min, max) = match kind {
+            TfLiteType::kTfLiteUInt8 => (0, 255),
+            TfLiteType::kTfLiteInt8 => (-128, 127),
+            kind => {
+                return Err(Error::InternalError(format!(
+                    "quantized frames need a `u8` or `i8` input, got `{:?}`",
+                    kind
+                )))
+            }
+        };
+        if !(scale > 0.0 && scale.is_finite()) {
+            return Err(Error::InternalError(format!("invalid quantization scale {}", scale)));
+        }
+        let channels = (0..preprocessing.channels())
+            .map(|channel| {
+                let mut table = [0u8; 256];
+                for (value, entry) in table.iter_mut().enumerate() {
+                    let real = preprocessing.normalize(channel, value as u8);
+                    let quantized = (real / scale).round() as i64 + i64::from(zero_point);
+                    *entry = quantized.clamp(min, max) as u8;
+                }
+                table
+            })
+            .collect();
+        Ok(Self { preprocessing: preprocessing.clone(), kind, channels })
+    }
+
+    /// Tables for the tensor at `tensor_index` of `interpreter`, from its quantization
+    /// parameters.
+    pub fn for_input<Op>(
+        interpreter: &Interpreter<'_, Op>,
+        tensor_index: TensorIndex,
+        preprocessing: &ImagePreprocessing,
+    ) -> Result<Self>
+    where
+        Op: OpResolver,
+    {
+        let tensor = interpreter.tensor(tensor_index)?;
+        Self::new(preprocessing, tensor.element_kind(), tensor.scale(), tensor.zero_point())
+    }
+
+    /// The byte written for `value` of `channel`.
+    pub fn quantize(&self, channel: usize, value: u8) -> u8 {
+        self.channels[channel % self.channels.len()][usize::from(value)]
+    }
+}
+
+/// Converts `frame` and writes it quantized into the tensor at `tensor_index`, whose element
+/// type must be the one `table` was built for and whose dims must be `[1, height, width, 3]`,
+/// or `[1, height, width, 1]` for grayscale preprocessing.
+pub fn set_input_frame_quantized<Op>(
+    interpreter: &mut Interpreter<'_, Op>,
+    tensor_index: TensorIndex,
+    frame: &Frame<'_>,
+    table: &QuantizationTable,
+) -> Result<()>
+where
+    Op: OpResolver,
+{
+    let preprocessing = &table.preprocessing;
+    let info = frame_input_info(interpreter, tensor_index, frame, preprocessing.channels())?;
+    if info.element_kind != table.kind {
+        return Err(Error::InternalError(format!(
+            "table for `{:?}` used for a `{:?}` input",
+            table.kind, info.element_kind
+        )));
+    }
+    let data = interpreter
+        .tensor_buffer_mut(tensor_index)
+        .ok_or_else(|| Error::internal_error("invalid tensor index"))?;
+    frame.for_each_rgb(|i, rgb| {
+        let (values, n) = preprocessing.pixel(rgb);
+        for (c, d) in data[i * n..(i + 1) * n].iter_mut().enumerate() {
+            *d = table.quantize(c, values[c]);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::NormalizationOptions;
+
+    #[test]
+    fn unittest_quantization_table() {
+        // MobileNet-style input: (x - 127.5) / 127.5 quantized with scale 1/128, zero point 128
+        // is close to the raw pixel value.
+        let preprocessing = ImagePreprocessing {
+            normalization: Some(NormalizationOptions { mean: vec![127.5], std: vec![127.5] }),
+            grayscale: false,
+        };
+        let table =
+            QuantizationTable::new(&preprocessing, TfLiteType::kTfLiteUInt8, 1.0 / 128.0, 128)
+                .unwrap();
+        assert_eq!(table.quantize(0, 0), 0);
+        assert_eq!(table.quantize(1, 64), 64);
+        assert_eq!(table.quantize(2, 255), 255);
+
+        let table = QuantizationTable::new(&preprocessing, TfLiteType::kTfLiteInt8, 1.0 / 128.0, 0)
+            .unwrap();
+        assert_eq!(table.quantize(0, 0) as i8, -128);
+        assert_eq!(table.quantize(0, 255) as i8, 127);
+
+        // Raw 0-255 values into a tensor with a coarser scale saturate.
+        let raw = ImagePreprocessing::default();
+        let table = QuantizationTable::new(&raw, TfLiteType::kTfLiteUInt8, 0.5, 10).unwrap();
+        assert_eq!((table.quantize(0, 3), table.quantize(0, 200)), (16, 255));
+
+        assert!(QuantizationTable::new(&raw, TfLiteType::kTfLiteFloat32, 1.0, 0).is_err());
+        assert!(QuantizationTable::new(&raw, TfLiteType::kTfLiteUInt8, 0.0, 0).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::model::stl::vector::{VectorInsert, VectorSlice};
+use crate::model::{Model, TensorType};
+use crate::op_resolver::OpResolver;
+use crate::{Error, FlatBufferModel, Interpreter, InterpreterBuilder, Result};
+
+const HEADER: &str = "# tflite-rs calibration v1";
+
+/// Observed value range of a tensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TensorRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl TensorRange {
+    fn update(&mut self, values: &[f32]) {
+        for &v in values.iter().filter(|v| v.is_finite()) {
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+        }
+    }
+}
+
+/// Value ranges of the float tensors of a model's primary subgraph, keyed by tensor index.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Calibration {
+    pub ranges: BTreeMap<i32, TensorRange>,
+    /// Tensor names, for readability of the calibration file.
+    pub names: BTreeMap<i32, String>,
+}
+
+impl Calibration {
+    pub fn get(&self, tensor: i32) -> Option<&TensorRange> {
+        self.ranges.get(&tensor)
+    }
+
+    /// One `index<TAB>min<TAB>max<TAB>name` line per tensor.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for (index, range) in &self.ranges {
+            let name = self.names.get(index).map(String::as_str).unwrap_or_default();
+            text.push_str(&format!("{}\t{:e}\t{:e}\t{}\n", index, range.min, range.max, name));
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some(HEADER) {
+            return Err(Error::internal_error("not a calibration file"));
+        }
+        let mut calibration = Calibration::default();
+        for line in lines.filter(|l| !l.trim().is_empty() && !l.starts_with('#')) {
+            let invalid = || Error::InternalError(format!("invalid calibration line `{}`", line));
+            let mut fields = line.splitn(4, '\t');
+            let mut next = || fields.next().ok_or_else(invalid);
+            let index: i32 = next()?.parse().map_err(|_| invalid())?;
+            let min = next()?.parse().map_err(|_| invalid())?;
+            let max = next()?.parse().map_err(|_| invalid())?;
+            let name = fields.next().unwrap_or_default();
+            calibration.ranges.insert(index, TensorRange { min, max });
+            if !name.is_empty() {
+                calibration.names.insert(index, name.to_string());
+            }
+        }
+        Ok(calibration)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(fs::write(path, self.to_text())?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_text(&fs::read_to_string(path)?)
+    }
+}
+
+/// Records the ranges of every float tensor produced by an operator, and of the inputs,
+/// while running representative inputs through a float model.
+///
+/// Intermediate tensors share arena memory, so the calibrator runs a copy of the model in
+/// which they are all outputs.
+pub struct Calibrator<'a, Op>
+where
+    Op: OpResolver,
+{
+    interpreter: Interpreter<'a, Op>,
+    // Interpreter tensor index of each observed tensor of the model.
+    observed: Vec<i32>,
+    calibration: Calibration,
+}
+
+impl<'a, Op> Calibrator<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn new(model: &Model, resolver: Op) -> Result<Self> {
+        let mut model = model.clone();
+        let mut observed = Vec::new();
+        let mut names = BTreeMap::new();
+        {
+            let subgraph = model
+                .subgraphs
+                .as_mut_slice()
+                .first_mut()
+                .ok_or_else(|| Error::internal_error("model has no subgraphs"))?;
+            let produced: Vec<i32> =
+                subgraph.operators.iter().flat_map(|op| op.outputs.as_slice().to_vec()).collect();
+            for &index in subgraph.inputs.as_slice().iter().chain(&produced) {
+                let tensor = &subgraph.tensors[index as usize];
+                if tensor.typ == TensorType::TensorType_FLOAT32 && !observed.contains(&index) {
+                    observed.push(index);
+                    names.insert(index, tensor.name.c_str().to_string_lossy().into_owned());
+                }
+            }
+            if observed.is_empty() {
+                return Err(Error::internal_error("model has no float tensors to calibrate"));
+            }
+            for &index in &produced {
+                if observed.contains(&index) && !subgraph.outputs.as_slice().contains(&index) {
+                    subgraph.outputs.push_back(index);
+                }
+            }
+        }
+
+        let model = FlatBufferModel::build_from_model(&model)?;
+        let interpreter = InterpreterBuilder::new(model, resolver)?.build()?;
+        Ok(Self {
+            interpreter,
+            observed,
+            calibration: Calibration { ranges: BTreeMap::new(), names },
+        })
+    }
+
+    /// Runs one representative sample, given as one slice per model input.
+    pub fn add_sample(&mut self, inputs: &[&[f32]]) -> Result<()> {
+        let input_indices = self.interpreter.inputs().to_vec();
+        if inputs.len() != input_indices.len() {
+            return Err(Error::InternalError(format!(
+                "expected {} inputs, got {}",
+                input_indices.len(),
+                inputs.len()
+            )));
+        }
+        for (&index, data) in input_indices.iter().zip(inputs) {
+            let tensor = self.interpreter.tensor_data_mut::<f32>(index)?;
+            if tensor.len() != data.len() {
+                return Err(Error::internal_error("input size does not match the model"));
+            }
+            tensor.copy_from_slice(data);
+        }
+        self.interpreter.invoke()?;
+
+        for &index in &self.observed {
+            let values = self.interpreter.tensor_data::<f32>(index)?;
+            self.calibration
+                .ranges
+                .entry(index)
+                .or_insert(TensorRange { min: f32::INFINITY, max: f32::NEG_INFINITY })
+                .update(values);
+        }
+        Ok(())
+    }
+
+    /// The ranges observed so far.
+    pub fn calibration(&self) -> &Calibration {
+        &self.calibration
+    }
+
+    pub fn finish(self) -> Calibration {
+        self.calibration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_calibration_text() {
+        let mut range = TensorRange { min: f32::INFINITY, max: f32::NEG_INFINITY };
+        range.update(&[0.5, -1.25, f32::NAN, 3.0]);
+        assert_eq!(range, TensorRange { min: -1.25, max: 3.0 });
+
+        let mut calibration = Calibration::default();
+        calibration.ranges.insert(7, range);
+        calibration.ranges.insert(2, TensorRange { min: 0.0, max: 6.0 });
+        calibration.names.insert(7, "conv1/Relu\tx".to_string());
+
+        let parsed = Calibration::from_text(&calibration.to_text()).unwrap();
+        assert_eq!(parsed, calibration);
+        assert!(Calibration::from_text("7\t0\t1\n").is_err());
+    }
+}
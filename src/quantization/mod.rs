@@ -0,0 +1,7 @@
+//! Post-training quantization of float models.
+
+mod calibration;
+mod quantize;
+
+pub use calibration::{Calibration, Calibrator, TensorRange};
+pub use quantize::{quantize_model, QuantizeOptions};
@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use super::{Calibration, TensorRange};
+use crate::model::stl::memory::UniquePtr;
+use crate::model::stl::vector::{VectorExtract, VectorInsert, VectorSlice};
+use crate::model::{
+    BufferT, BuiltinOperator, BuiltinOptionsUnion, Model, ModelT, OperatorCodeT, OperatorT,
+    QuantizationParametersT, SubGraphT, TensorT, TensorType,
+};
+use crate::{Error, Result};
+
+/// Options of `quantize_model`.
+#[derive(Clone, Debug)]
+pub struct QuantizeOptions {
+    /// Quantize convolution weights with one scale per output channel.
+    pub per_channel: bool,
+    /// Keep float inputs and outputs, converting them with QUANTIZE and DEQUANTIZE operators.
+    /// Otherwise the model's inputs and outputs become int8.
+    pub float_interface: bool,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self { per_channel: true, float_interface: true }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct QuantParams {
+    scale: Vec<f32>,
+    zero_point: Vec<i64>,
+    dimension: i32,
+}
+
+impl QuantParams {
+    fn per_tensor(scale: f32, zero_point: i64) -> Self {
+        Self { scale: vec![scale], zero_point: vec![zero_point], dimension: 0 }
+    }
+
+    fn to_native(&self) -> UniquePtr<QuantizationParametersT> {
+        let mut params: UniquePtr<QuantizationParametersT> = Default::default();
+        params.scale.assign(self.scale.iter().copied());
+        params.zero_point.assign(self.zero_point.iter().copied());
+        params.quantized_dimension = self.dimension;
+        params
+    }
+}
+
+/// Asymmetric int8 parameters covering `range`, which is widened to include zero so that
+/// zero padding is exact.
+fn activation_params(range: TensorRange) -> QuantParams {
+    let min = range.min.min(0.0);
+    let max = range.max.max(0.0);
+    let scale = if max > min { (max - min) / 255.0 } else { 1.0 };
+    let zero_point = (-128.0 - min / scale).round().clamp(-128.0, 127.0);
+    QuantParams::per_tensor(scale, zero_point as i64)
+}
+
+/// Symmetric int8 quantization of `values`, with one scale per index of dimension `axis`
+/// of `shape`, or a single scale if `axis` is `None`.
+fn quantize_weights(values: &[f32], shape: &[i32], axis: Option<usize>) -> (Vec<i8>, QuantParams) {
+    let (channels, inner) = match axis {
+        Some(axis) if axis < shape.len() => {
+            let inner =
+                shape[axis + 1..].iter().fold(1usize, |n, &d| n.saturating_mul(d.max(1) as usize));
+            (shape[axis].max(1) as usize, inner)
+        }
+        _ => (1, values.len().max(1)),
+    };
+    let channel = |i: usize| (i / inner) % channels;
+
+    let mut max_abs = vec![0f32; channels];
+    for (i, v) in values.iter().enumerate() {
+        max_abs[channel(i)] = max_abs[channel(i)].max(v.abs());
+    }
+    let scale: Vec<f32> = max_abs.iter().map(|&m| if m > 0.0 { m / 127.0 } else { 1.0 }).collect();
+    let quantized = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v / scale[channel(i)]).round().clamp(-127.0, 127.0) as i8)
+        .collect();
+    let params = QuantParams {
+        zero_point: vec![0; channels],
+        scale,
+        dimension: axis.filter(|_| channels > 1).unwrap_or(0) as i32,
+    };
+    (quantized, params)
+}
+
+/// Quantizes a bias to int32 with scale `input_scale * weight_scale` per channel.
+fn quantize_bias(
+    values: &[f32],
+    input_scale: f32,
+    weights: &QuantParams,
+) -> (Vec<i32>, QuantParams) {
+    let scale: Vec<f32> = weights.scale.iter().map(|s| s * input_scale).collect();
+    let quantized = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (f64::from(*v) / f64::from(scale[i % scale.len()])).round() as i32)
+        .collect();
+    let params = QuantParams { zero_point: vec![0; scale.len()], scale, dimension: 0 };
+    (quantized, params)
+}
+
+/// The minimum operator version with int8 support, or `None` if the operator cannot be
+/// quantized.
+fn int8_version(op: BuiltinOperator) -> Option<i32> {
+    use BuiltinOperator::*;
+    Some(match op {
+        BuiltinOperator_CONV_2D | BuiltinOperator_DEPTHWISE_CONV_2D => 3,
+        BuiltinOperator_FULLY_CONNECTED => 4,
+        BuiltinOperator_ADD
+        | BuiltinOperator_SUB
+        | BuiltinOperator_MUL
+        | BuiltinOperator_AVERAGE_POOL_2D
+        | BuiltinOperator_MAX_POOL_2D
+        | BuiltinOperator_SOFTMAX
+        | BuiltinOperator_LOGISTIC
+        | BuiltinOperator_TANH
+        | BuiltinOperator_RELU
+        | BuiltinOperator_RELU6
+        | BuiltinOperator_PAD
+        | BuiltinOperator_MEAN
+        | BuiltinOperator_DEQUANTIZE => 2,
+        BuiltinOperator_RESHAPE | BuiltinOperator_SQUEEZE | BuiltinOperator_QUANTIZE => 1,
+        _ => return None,
+    })
+}
+
+/// Output parameters fixed by the int8 kernels, or copied from the input.
+fn output_params(op: BuiltinOperator, input: Option<&QuantParams>) -> Option<QuantParams> {
+    use BuiltinOperator::*;
+    match op {
+        BuiltinOperator_SOFTMAX | BuiltinOperator_LOGISTIC => {
+            Some(QuantParams::per_tensor(1.0 / 256.0, -128))
+        }
+        BuiltinOperator_TANH => Some(QuantParams::per_tensor(1.0 / 128.0, 0)),
+        BuiltinOperator_RESHAPE
+        | BuiltinOperator_SQUEEZE
+        | BuiltinOperator_MAX_POOL_2D
+        | BuiltinOperator_AVERAGE_POOL_2D
+        | BuiltinOperator_PAD => input.cloned(),
+        _ => None,
+    }
+}
+
+fn float_data(bytes: &[u8]) -> Vec<f32> {
+    bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn opcode_index(model: &mut ModelT, op: BuiltinOperator, version: i32) -> u32 {
+    if let Some(index) = model.operator_codes.iter().position(|code| code.builtin_code == op) {
+        let code = &mut model.operator_codes[index];
+        code.version = code.version.max(version);
+        return index as u32;
+    }
+    let mut code: UniquePtr<OperatorCodeT> = Default::default();
+    code.builtin_code = op;
+    code.version = version;
+    model.operator_codes.push_back(code);
+    model.operator_codes.size() as u32 - 1
+}
+
+/// Adds a float tensor mirroring the int8 tensor `index`, which is renamed with an `_int8`
+/// suffix so the float tensor keeps the original name.
+fn add_float_tensor(model: &mut ModelT, subgraph: usize, index: i32) -> i32 {
+    model.buffers.push_back(UniquePtr::<BufferT>::default());
+    let buffer = model.buffers.size() as u32 - 1;
+
+    let subgraph = &mut model.subgraphs[subgraph];
+    let quantized = &mut subgraph.tensors[index as usize];
+    let name = quantized.name.c_str().to_owned();
+    let mut tensor: UniquePtr<TensorT> = Default::default();
+    tensor.shape.assign(quantized.shape.iter().copied());
+    tensor.typ = TensorType::TensorType_FLOAT32;
+    tensor.buffer = buffer;
+    tensor.name.assign(&name);
+    let mut renamed = name.into_bytes();
+    renamed.extend_from_slice(b"_int8");
+    quantized.name.assign(&CString::new(renamed).unwrap());
+
+    subgraph.tensors.push_back(tensor);
+    subgraph.tensors.size() as i32 - 1
+}
+
+fn conversion_op(
+    opcode_index: u32,
+    input: i32,
+    output: i32,
+    quantize: bool,
+) -> UniquePtr<OperatorT> {
+    let mut op: UniquePtr<OperatorT> = Default::default();
+    op.opcode_index = opcode_index;
+    op.builtin_options = if quantize {
+        BuiltinOptionsUnion::QuantizeOptions()
+    } else {
+        BuiltinOptionsUnion::DequantizeOptions()
+    };
+    op.inputs.assign(vec![input]);
+    op.outputs.assign(vec![output]);
+    op
+}
+
+/// Converts a float model to full integer quantization: int8 activations with the ranges
+/// of `calibration`, symmetric int8 weights and int32 biases.
+///
+/// Only the primary subgraph is quantized. Fails if it contains an operator without an int8
+/// kernel or a float tensor missing from `calibration`.
+pub fn quantize_model(
+    model: &Model,
+    calibration: &Calibration,
+    options: &QuantizeOptions,
+) -> Result<Model> {
+    use BuiltinOperator::*;
+
+    let mut quantized = model.clone();
+    let model: &mut ModelT = &mut quantized;
+    if model.subgraphs.size() != 1 {
+        return Err(Error::internal_error("only models with a single subgraph can be quantized"));
+    }
+
+    let codes: Vec<BuiltinOperator> =
+        model.operator_codes.iter().map(|code| code.builtin_code).collect();
+    let subgraph: &SubGraphT = &model.subgraphs[0];
+    let ops: Vec<(BuiltinOperator, Vec<i32>, Vec<i32>)> = subgraph
+        .operators
+        .iter()
+        .map(|op| (codes[op.opcode_index as usize], op.inputs.to_vec(), op.outputs.to_vec()))
+        .collect();
+    for (code, _, _) in &ops {
+        if int8_version(*code).is_none() || *code == BuiltinOperator_QUANTIZE {
+            return Err(Error::InternalError(format!("cannot quantize {:?} operators", code)));
+        }
+    }
+
+    let is_float = |index: i32| {
+        index >= 0 && subgraph.tensors[index as usize].typ == TensorType::TensorType_FLOAT32
+    };
+    let constant = |index: i32| {
+        let buffer = subgraph.tensors[index as usize].buffer as usize;
+        buffer != 0 && model.buffers.get(buffer).is_some_and(|b| b.data.size() > 0)
+    };
+    let calibrated = |index: i32| {
+        calibration.get(index).copied().map(activation_params).ok_or_else(|| {
+            let name = subgraph.tensors[index as usize].name.c_str().to_string_lossy();
+            Error::InternalError(format!("no calibration for tensor {} `{}`", index, name))
+        })
+    };
+
+    // Activation parameters, following operator order so that outputs can inherit the
+    // parameters of their inputs.
+    let mut params: HashMap<i32, QuantParams> = HashMap::new();
+    for &index in subgraph.inputs.iter().filter(|&&i| is_float(i)) {
+        params.insert(index, calibrated(index)?);
+    }
+    for (code, inputs, outputs) in &ops {
+        let input = inputs.first().and_then(|i| params.get(i)).cloned();
+        for &index in outputs.iter().filter(|&&i| is_float(i)) {
+            let output = match output_params(*code, input.as_ref()) {
+                Some(output) => output,
+                None => calibrated(index)?,
+            };
+            params.insert(index, output);
+        }
+    }
+
+    // Constant data, by tensor index.
+    let mut int8_data: HashMap<i32, Vec<i8>> = HashMap::new();
+    let mut int32_data: HashMap<i32, Vec<i32>> = HashMap::new();
+    for (code, inputs, _) in &ops {
+        let weight_axis = match code {
+            BuiltinOperator_CONV_2D if options.per_channel => Some(Some(0)),
+            BuiltinOperator_DEPTHWISE_CONV_2D if options.per_channel => Some(Some(3)),
+            BuiltinOperator_CONV_2D
+            | BuiltinOperator_DEPTHWISE_CONV_2D
+            | BuiltinOperator_FULLY_CONNECTED => Some(None),
+            _ => None,
+        };
+        for (position, &index) in inputs.iter().enumerate() {
+            if !is_float(index) || !constant(index) || params.contains_key(&index) {
+                continue;
+            }
+            let tensor = &subgraph.tensors[index as usize];
+            let values = float_data(&model.buffers[tensor.buffer as usize].data);
+            match (weight_axis, position) {
+                (Some(axis), 1) => {
+                    let (data, weights) = quantize_weights(&values, &tensor.shape, axis);
+                    int8_data.insert(index, data);
+                    params.insert(index, weights);
+                }
+                (Some(_), 2) => {
+                    let input_scale = params.get(&inputs[0]).map(|p| p.scale[0]);
+                    let weights = params.get(&inputs[1]);
+                    let (input_scale, weights) = input_scale.zip(weights).ok_or_else(|| {
+                        Error::internal_error("bias without quantized input and weights")
+                    })?;
+                    let (data, bias) = quantize_bias(&values, input_scale, weights);
+                    int32_data.insert(index, data);
+                    params.insert(index, bias);
+                }
+                _ => {
+                    let range =
+                        values.iter().fold(TensorRange { min: 0.0, max: 0.0 }, |range, &v| {
+                            TensorRange { min: range.min.min(v), max: range.max.max(v) }
+                        });
+                    let constant = activation_params(range);
+                    let (scale, zero_point) = (constant.scale[0], constant.zero_point[0] as f32);
+                    let data = values
+                        .iter()
+                        .map(|v| (v / scale + zero_point).round().clamp(-128.0, 127.0) as i8)
+                        .collect();
+                    int8_data.insert(index, data);
+                    params.insert(index, constant);
+                }
+            }
+        }
+    }
+
+    let graph_inputs: Vec<i32> = subgraph.inputs.iter().copied().filter(|&i| is_float(i)).collect();
+    let graph_outputs: Vec<i32> =
+        subgraph.outputs.iter().copied().filter(|&i| is_float(i)).collect();
+    if let Some(index) = (0..subgraph.tensors.size() as i32).find(|&i| {
+        is_float(i) && !params.contains_key(&i) && ops.iter().any(|op| op.1.contains(&i))
+    }) {
+        return Err(Error::InternalError(format!("cannot quantize tensor {}", index)));
+    }
+
+    // Rewrite tensors and their buffers.
+    let buffers: Vec<(usize, Vec<u8>)> = int8_data
+        .iter()
+        .map(|(&index, data)| (index, data.iter().map(|&v| v as u8).collect()))
+        .chain(int32_data.iter().map(|(&index, data)| {
+            (index, data.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect())
+        }))
+        .map(|(index, bytes)| (model.subgraphs[0].tensors[index as usize].buffer as usize, bytes))
+        .collect();
+    for (buffer, bytes) in buffers {
+        model.buffers[buffer].data.assign(bytes);
+    }
+    {
+        let subgraph = &mut model.subgraphs[0];
+        for (&index, tensor_params) in &params {
+            let tensor = &mut subgraph.tensors[index as usize];
+            tensor.typ = if int32_data.contains_key(&index) {
+                TensorType::TensorType_INT32
+            } else {
+                TensorType::TensorType_INT8
+            };
+            tensor.quantization = tensor_params.to_native();
+        }
+    }
+    for code in model.operator_codes.iter_mut() {
+        if let Some(version) = int8_version(code.builtin_code) {
+            code.version = code.version.max(version);
+        }
+    }
+
+    if options.float_interface {
+        let mut quantize_ops = Vec::new();
+        if !graph_inputs.is_empty() {
+            let quantize = opcode_index(model, BuiltinOperator_QUANTIZE, 1);
+            for &index in &graph_inputs {
+                let float = add_float_tensor(model, 0, index);
+                quantize_ops.push(conversion_op(quantize, float, index, true));
+                let inputs = &mut model.subgraphs[0].inputs;
+                let position = inputs.iter().position(|&i| i == index).unwrap();
+                inputs[position] = float;
+            }
+        }
+        let mut dequantize_ops = Vec::new();
+        if !graph_outputs.is_empty() {
+            let dequantize = opcode_index(model, BuiltinOperator_DEQUANTIZE, 2);
+            for &index in &graph_outputs {
+                let float = add_float_tensor(model, 0, index);
+                dequantize_ops.push(conversion_op(dequantize, index, float, false));
+                let outputs = &mut model.subgraphs[0].outputs;
+                let position = outputs.iter().position(|&i| i == index).unwrap();
+                outputs[position] = float;
+            }
+        }
+
+        let operators = &mut model.subgraphs[0].operators;
+        let existing: Vec<_> = (0..operators.size()).map(|i| operators.extract(i)).collect();
+        operators.assign(quantize_ops.into_iter().chain(existing).chain(dequantize_ops));
+    }
+
+    Ok(quantized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_quantization_params() {
+        let params = activation_params(TensorRange { min: -1.0, max: 3.0 });
+        assert!((params.scale[0] - 4.0 / 255.0).abs() < 1e-7);
+        assert_eq!(params.zero_point, vec![-64]);
+
+        // Ranges are widened to include zero.
+        let params = activation_params(TensorRange { min: 2.0, max: 6.0 });
+        assert_eq!(params.zero_point, vec![-128]);
+
+        let values = [0.5, -1.0, 0.25, 2.0, -4.0, 1.0];
+        let (data, weights) = quantize_weights(&values, &[2, 3], Some(0));
+        assert_eq!(weights.scale, vec![1.0 / 127.0, 4.0 / 127.0]);
+        assert_eq!(data, vec![64, -127, 32, 64, -127, 32]);
+
+        let (data, per_tensor) = quantize_weights(&values, &[2, 3], None);
+        assert_eq!(per_tensor.scale, vec![4.0 / 127.0]);
+        assert_eq!(data[4], -127);
+
+        let (bias, params) = quantize_bias(&[0.1, -0.2], 0.5, &weights);
+        assert_eq!(params.scale, vec![0.5 / 127.0, 2.0 / 127.0]);
+        assert_eq!(bias, vec![25, -13]);
+    }
+
+    fn float_tensor(shape: Vec<i32>, buffer: u32, name: &str) -> UniquePtr<TensorT> {
+        let mut tensor: UniquePtr<TensorT> = Default::default();
+        tensor.shape.assign(shape);
+        tensor.typ = TensorType::TensorType_FLOAT32;
+        tensor.buffer = buffer;
+        tensor.name.assign(&CString::new(name).unwrap());
+        tensor
+    }
+
+    #[test]
+    fn unittest_quantize_fully_connected() {
+        use crate::ops::builtin::BuiltinOpResolver;
+        use crate::quantization::Calibrator;
+        use crate::{FlatBufferModel, InterpreterBuilder};
+
+        let weights = [0.5f32, -0.25, 1.0, 0.75, -1.5, 0.2, 0.3, -0.1, 0.05, 0.6, -0.7, 0.9];
+        let bias = [0.1f32, -0.2, 0.3];
+        let mut model = Model::default();
+        model.version = 3;
+        {
+            let mut fc: UniquePtr<OperatorCodeT> = Default::default();
+            fc.builtin_code = BuiltinOperator::BuiltinOperator_FULLY_CONNECTED;
+            fc.version = 1;
+            model.operator_codes.push_back(fc);
+        }
+        model.buffers.assign(vec![UniquePtr::<BufferT>::default(); 3]);
+        model.buffers[1].data.assign(weights.iter().flat_map(|w| w.to_le_bytes().to_vec()));
+        model.buffers[2].data.assign(bias.iter().flat_map(|b| b.to_le_bytes().to_vec()));
+
+        let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+        subgraph.tensors.push_back(float_tensor(vec![1, 4], 0, "input"));
+        subgraph.tensors.push_back(float_tensor(vec![3, 4], 1, "weights"));
+        subgraph.tensors.push_back(float_tensor(vec![3], 2, "bias"));
+        subgraph.tensors.push_back(float_tensor(vec![1, 3], 0, "output"));
+        let mut fc: UniquePtr<OperatorT> = Default::default();
+        fc.builtin_options = BuiltinOptionsUnion::FullyConnectedOptions();
+        fc.opcode_index = 0;
+        fc.inputs.assign(vec![0, 1, 2]);
+        fc.outputs.assign(vec![3]);
+        subgraph.operators.push_back(fc);
+        subgraph.inputs.assign(vec![0]);
+        subgraph.outputs.assign(vec![3]);
+        model.subgraphs.push_back(subgraph);
+
+        let samples: Vec<[f32; 4]> =
+            (0..16).map(|i| [i as f32 / 16.0, -0.5, 1.0 - i as f32 / 8.0, 0.25]).collect();
+        let mut calibrator = Calibrator::new(&model, BuiltinOpResolver::default()).unwrap();
+        for sample in &samples {
+            calibrator.add_sample(&[sample]).unwrap();
+        }
+        let calibration = calibrator.finish();
+
+        let quantized = quantize_model(&model, &calibration, &Default::default()).unwrap();
+        assert!(quantize_model(&model, &Calibration::default(), &Default::default()).is_err());
+
+        let run = |model: &Model, input: &[f32; 4]| {
+            let model = FlatBufferModel::build_from_model(model).unwrap();
+            let builder = InterpreterBuilder::new(model, BuiltinOpResolver::default()).unwrap();
+            let mut interpreter = builder.build().unwrap();
+            interpreter.allocate_tensors().unwrap();
+            let index = interpreter.inputs()[0];
+            interpreter.tensor_data_mut(index).unwrap().copy_from_slice(input);
+            interpreter.invoke().unwrap();
+            let index = interpreter.outputs()[0];
+            interpreter.tensor_data::<f32>(index).unwrap().to_vec()
+        };
+        for sample in &samples {
+            let expected = run(&model, sample);
+            let actual = run(&quantized, sample);
+            for (e, a) in expected.iter().zip(&actual) {
+                assert!((e - a).abs() < 0.05, "{:?} != {:?}", expected, actual);
+            }
+        }
+    }
+}
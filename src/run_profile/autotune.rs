@@ -0,0 +1,232 @@
+//! Picking the fastest `RunProfile` on the running device, e.g. at the first launch of an app:
+//!
+//! ```ignore
+//! let builder = InterpreterBuilder::new(&model, resolver)?.into_shared();
+//! let report = Autotuner::new()
+//!     .with_delegates(vec![DelegateSpec::Gpu { library: "libtensorflowlite_gpu_delegate.so".into() }])
+//!     .with_fp16(true)
+//!     .run(&builder)?;
+//! if let Some(profile) = report.best() {
+//!     profile.save(cache_dir.join("detector.profile.toml"))?;
+//! }
+//! ```
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use super::{DelegateSpec, RunProfile};
+use crate::kernel::TfLiteAllocationType;
+use crate::op_resolver::OpResolver;
+use crate::{Error, Result, SharedInterpreterBuilder};
+
+/// Configurations to try and how long to measure each.
+#[derive(Clone, Debug)]
+pub struct Autotuner {
+    threads: Vec<i32>,
+    delegates: Vec<Vec<DelegateSpec>>,
+    fp16: bool,
+    warmup: usize,
+    runs: usize,
+}
+
+/// A measured configuration.
+#[derive(Debug)]
+pub struct Trial {
+    pub profile: RunProfile,
+    /// Median latency of `invoke`, or why the configuration could not run, e.g. because the
+    /// delegate library is missing on this device.
+    pub latency: Result<Duration>,
+}
+
+/// All trials of an `Autotuner` run, in the order they ran.
+#[derive(Debug, Default)]
+pub struct TuningReport {
+    pub trials: Vec<Trial>,
+}
+
+/// 1, 2, 4, .. threads up to `cores`, and `cores`.
+fn thread_candidates(cores: usize) -> Vec<i32> {
+    let cores = cores.max(1) as i32;
+    let mut threads: Vec<i32> =
+        std::iter::successors(Some(1), |&n| Some(n * 2)).take_while(|&n| n < cores).collect();
+    threads.push(cores);
+    threads
+}
+
+impl Default for Autotuner {
+    fn default() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        Self {
+            threads: thread_candidates(cores),
+            delegates: vec![Vec::new()],
+            fp16: false,
+            warmup: 2,
+            runs: 10,
+        }
+    }
+}
+
+impl Autotuner {
+    /// Tries the CPU alone with 1, 2, 4, .. threads up to the number of cores.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_threads(mut self, threads: Vec<i32>) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Also tries the model with `delegates` applied, with each thread count for the ops
+    /// left to the CPU.
+    pub fn with_delegates(mut self, delegates: Vec<DelegateSpec>) -> Self {
+        self.delegates.push(delegates);
+        self
+    }
+
+    /// Also tries each configuration with fp16 precision allowed. This trades accuracy for
+    /// speed; only enable it for models known to tolerate it.
+    pub fn with_fp16(mut self, fp16: bool) -> Self {
+        self.fp16 = fp16;
+        self
+    }
+
+    /// Invocations before and during the measurement of each configuration.
+    pub fn with_runs(mut self, warmup: usize, runs: usize) -> Self {
+        self.warmup = warmup;
+        self.runs = runs.max(1);
+        self
+    }
+
+    fn measure<Op: OpResolver>(
+        &self,
+        builder: &SharedInterpreterBuilder<'_, Op>,
+        profile: &mut RunProfile,
+        delegates: &[crate::Delegate],
+    ) -> Result<Duration> {
+        let mut interpreter = builder.build_with_threads(profile.threads.unwrap_or(-1))?;
+        profile.apply(&mut interpreter, delegates)?;
+        for _ in 0..self.warmup {
+            interpreter.invoke()?;
+        }
+        let mut latencies = Vec::with_capacity(self.runs);
+        for _ in 0..self.runs {
+            let start = Instant::now();
+            interpreter.invoke()?;
+            latencies.push(start.elapsed());
+        }
+        latencies.sort();
+        profile.arena_hint =
+            Some(interpreter.plan_report().arena_bytes(TfLiteAllocationType::kTfLiteArenaRw));
+        Ok(latencies[latencies.len() / 2])
+    }
+
+    /// Measures every combination of delegates, thread count and fp16 precision on inputs as
+    /// allocated. Configurations that fail are recorded in the report instead of failing the
+    /// run.
+    pub fn run<Op: OpResolver>(
+        &self,
+        builder: &SharedInterpreterBuilder<'_, Op>,
+    ) -> Result<TuningReport> {
+        let precisions: &[bool] = if self.fp16 { &[false, true] } else { &[false] };
+        let mut report = TuningReport::default();
+        for delegates in &self.delegates {
+            for &allow_fp16 in precisions {
+                let base = RunProfile {
+                    delegates: delegates.clone(),
+                    allow_fp16,
+                    ..RunProfile::default()
+                };
+                // Loaded once for all thread counts; fp16 changes the GPU delegate options.
+                let loaded = base.load_delegates();
+                for &threads in &self.threads {
+                    let mut profile = RunProfile { threads: Some(threads), ..base.clone() };
+                    let latency = match &loaded {
+                        Ok(loaded) => self.measure(builder, &mut profile, loaded),
+                        Err(Error::InternalError(message)) => {
+                            Err(Error::InternalError(message.clone()))
+                        }
+                        Err(e) => Err(Error::InternalError(e.to_string())),
+                    };
+                    report.trials.push(Trial { profile, latency });
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl TuningReport {
+    /// The fastest trial that ran.
+    pub fn fastest(&self) -> Option<&Trial> {
+        self.trials
+            .iter()
+            .filter(|trial| trial.latency.is_ok())
+            .min_by_key(|trial| trial.latency.as_ref().ok().copied())
+    }
+
+    /// The profile of the fastest trial, with the arena it needed as `arena_hint`.
+    pub fn best(&self) -> Option<&RunProfile> {
+        self.fastest().map(|trial| &trial.profile)
+    }
+}
+
+impl fmt::Display for TuningReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for trial in &self.trials {
+            let profile = &trial.profile;
+            let delegates: Vec<&str> = profile
+                .delegates
+                .iter()
+                .map(|delegate| match delegate {
+                    DelegateSpec::Gpu { library } | DelegateSpec::External { library, .. } => {
+                        library.as_str()
+                    }
+                })
+                .collect();
+            write!(
+                f,
+                "{:>2} threads{} {}: ",
+                profile.threads.unwrap_or(-1),
+                if profile.allow_fp16 { " fp16" } else { "" },
+                if delegates.is_empty() { "cpu".to_string() } else { delegates.join("+") }
+            )?;
+            match &trial.latency {
+                Ok(latency) => writeln!(f, "{:.3} ms", latency.as_secs_f64() * 1000.0)?,
+                Err(e) => writeln!(f, "failed: {}", e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_autotune_report() {
+        assert_eq!(thread_candidates(0), vec![1]);
+        assert_eq!(thread_candidates(4), vec![1, 2, 4]);
+        assert_eq!(thread_candidates(6), vec![1, 2, 4, 6]);
+
+        let trial = |threads, delegates: Vec<DelegateSpec>, latency: Result<u64>| Trial {
+            profile: RunProfile { threads: Some(threads), delegates, ..RunProfile::default() },
+            latency: latency.map(Duration::from_micros),
+        };
+        let gpu = DelegateSpec::Gpu { library: "gpu.so".to_string() };
+        let report = TuningReport {
+            trials: vec![
+                trial(1, Vec::new(), Ok(4000)),
+                trial(4, Vec::new(), Ok(1500)),
+                trial(1, vec![gpu], Err(Error::internal_error("gpu.so: not found"))),
+            ],
+        };
+        assert_eq!(report.best().and_then(|profile| profile.threads), Some(4));
+        let text = report.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[..2], [" 1 threads cpu: 4.000 ms", " 4 threads cpu: 1.500 ms"]);
+        assert!(lines[2].starts_with(" 1 threads gpu.so: failed: "));
+        assert!(TuningReport::default().best().is_none());
+    }
+}
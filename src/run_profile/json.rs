@@ -0,0 +1,211 @@
+//! Reading and writing the JSON subset of run profiles and run snapshots: objects, arrays,
+//! strings, integers and booleans.
+
+use super::Value;
+use crate::interpreter::json_string;
+use crate::{Error, Result};
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> Error {
+        Error::InternalError(format!("invalid JSON at byte {}: {}", self.position, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.text[self.position..].starts_with(keyword);
+        if found {
+            self.position += keyword.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                let mut entries = Vec::new();
+                if self.peek() == Some('}') {
+                    self.position += 1;
+                    return Ok(Value::Table(entries));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    if self.peek() == Some(',') {
+                        self.position += 1;
+                    } else {
+                        self.expect('}')?;
+                        return Ok(Value::Table(entries));
+                    }
+                }
+            }
+            Some('[') => {
+                self.position += 1;
+                let mut values = Vec::new();
+                if self.peek() == Some(']') {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    if self.peek() == Some(',') {
+                        self.position += 1;
+                    } else {
+                        self.expect(']')?;
+                        return Ok(Value::Array(values));
+                    }
+                }
+            }
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') if self.keyword("true") => Ok(Value::Bool(true)),
+            Some('f') if self.keyword("false") => Ok(Value::Bool(false)),
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let rest = &self.text[self.position..];
+                let len = rest
+                    .char_indices()
+                    .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && c == '-')))
+                    .map_or(rest.len(), |(i, _)| i);
+                if rest[len..].starts_with(['.', 'e', 'E']) {
+                    return Err(self.error("only integers are supported"));
+                }
+                let value = rest[..len].parse().map_err(|_| self.error("invalid integer"))?;
+                self.position += len;
+                Ok(Value::Int(value))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        let mut chars = self.text[self.position..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position += i + 1;
+                    return Ok(string);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(std::char::from_u32)
+                                .ok_or_else(|| self.error("invalid unicode escape"))?
+                        }
+                        Some(c) if c == '"' || c == '\\' || c == '/' => c,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+}
+
+pub(crate) fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser { text, position: 0 };
+    let value = parser.value()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+fn write_value(json: &mut String, value: &Value, indent: usize) {
+    let pad = |json: &mut String, indent| json.extend(std::iter::repeat_n("  ", indent));
+    match value {
+        Value::Bool(value) => json.push_str(if *value { "true" } else { "false" }),
+        Value::Int(value) => json.push_str(&value.to_string()),
+        Value::String(value) => json.push_str(&json_string(value)),
+        Value::Array(values) if values.is_empty() => json.push_str("[]"),
+        Value::Array(values) => {
+            json.push('[');
+            for (i, value) in values.iter().enumerate() {
+                json.push_str(if i == 0 { "\n" } else { ",\n" });
+                pad(json, indent + 1);
+                write_value(json, value, indent + 1);
+            }
+            json.push('\n');
+            pad(json, indent);
+            json.push(']');
+        }
+        Value::Table(entries) if entries.is_empty() => json.push_str("{}"),
+        Value::Table(entries) => {
+            json.push('{');
+            for (i, (key, value)) in entries.iter().enumerate() {
+                json.push_str(if i == 0 { "\n" } else { ",\n" });
+                pad(json, indent + 1);
+                json.push_str(&json_string(key));
+                json.push_str(": ");
+                write_value(json, value, indent + 1);
+            }
+            json.push('\n');
+            pad(json, indent);
+            json.push('}');
+        }
+    }
+}
+
+pub(crate) fn write(value: &Value) -> String {
+    let mut json = String::new();
+    write_value(&mut json, value, 0);
+    json.push('\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_json() {
+        let value = parse(r#" {"a": [1, -2, true], "b": {"c": "x\"é\n"}, "d": []} "#);
+        let expected = Value::Table(vec![
+            ("a".to_string(), Value::Array(vec![Value::Int(1), Value::Int(-2), Value::Bool(true)])),
+            (
+                "b".to_string(),
+                Value::Table(vec![("c".to_string(), Value::String("x\"é\n".to_string()))]),
+            ),
+            ("d".to_string(), Value::Array(Vec::new())),
+        ]);
+        assert_eq!(value.unwrap(), expected);
+        assert_eq!(parse(&write(&expected)).unwrap(), expected);
+        assert!(parse("{\"a\": 1.5}").is_err());
+        assert!(parse("{\"a\": 1} x").is_err());
+        assert!(parse("[1,").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Tuned per-model interpreter settings, shipped as TOML or JSON next to the model:
+//!
+//! ```toml
+//! threads = 4
+//! allow_fp16 = true
+//! arena_hint = 1048576
+//!
+//! [[delegates]]
+//! kind = "gpu"
+//! library = "libtensorflowlite_gpu_delegate.so"
+//!
+//! [[delegates]]
+//! kind = "external"
+//! library = "libedgetpu.so.1"
+//! options = { device = "usb:0" }
+//! ```
+//!
+//! ```ignore
+//! let profile = RunProfile::load("detector.profile.toml")?;
+//! let mut interpreter = profile.build(InterpreterBuilder::new(&model, resolver)?)?;
+//! ```
+//!
+//! `Autotuner` measures candidate settings on the running device to produce a profile.
+
+mod autotune;
+pub(crate) mod json;
+mod toml;
+
+use std::fs;
+use std::path::Path;
+
+use crate::kernel::TfLiteAllocationType;
+use crate::op_resolver::OpResolver;
+use crate::{Delegate, Error, Interpreter, InterpreterBuilder, Result};
+
+pub use autotune::{Autotuner, Trial, TuningReport};
+
+/// A parsed JSON or TOML document.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Bool(bool),
+    Int(i64),
+    String(String),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+/// A delegate to load when applying a `RunProfile`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DelegateSpec {
+    /// TFLite's GPU delegate library, with fp16 precision if the profile allows it.
+    Gpu { library: String },
+    /// A library implementing the external delegate interface, e.g. `libedgetpu.so.1`.
+    External { library: String, options: Vec<(String, String)> },
+}
+
+/// Interpreter settings tuned for one model on one device.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunProfile {
+    /// `None` leaves the TF Lite default.
+    pub threads: Option<i32>,
+    /// Applied in order; ops no delegate takes run on the CPU.
+    pub delegates: Vec<DelegateSpec>,
+    /// Lets float CPU kernels and delegates compute in fp16.
+    pub allow_fp16: bool,
+    /// Arena bytes the profile was tuned with. TF Lite sizes its arena itself, so this is a
+    /// check: applying the profile fails if the model needs more, e.g. because the profile
+    /// was tuned for another version of the model.
+    pub arena_hint: Option<usize>,
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InternalError(format!("invalid run profile: {}", message))
+}
+
+fn string(value: &Value, key: &str) -> Result<String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        _ => Err(invalid(&format!("`{}` is not a string", key))),
+    }
+}
+
+impl DelegateSpec {
+    fn from_value(value: &Value) -> Result<Self> {
+        let entries = match value {
+            Value::Table(entries) => entries,
+            _ => return Err(invalid("delegates must be tables")),
+        };
+        let mut kind = None;
+        let mut library = None;
+        let mut options = Vec::new();
+        for (key, value) in entries {
+            match (key.as_str(), value) {
+                ("kind", value) => kind = Some(string(value, key)?),
+                ("library", value) => library = Some(string(value, key)?),
+                ("options", Value::Table(entries)) => {
+                    for (key, value) in entries {
+                        options.push((key.clone(), string(value, key)?));
+                    }
+                }
+                _ => return Err(invalid(&format!("unknown delegate key `{}`", key))),
+            }
+        }
+        let library = library.ok_or_else(|| invalid("delegate without `library`"))?;
+        match kind.as_deref() {
+            Some("gpu") if options.is_empty() => Ok(DelegateSpec::Gpu { library }),
+            Some("gpu") => Err(invalid("the gpu delegate takes no options")),
+            Some("external") => Ok(DelegateSpec::External { library, options }),
+            _ => Err(invalid("delegate `kind` must be `gpu` or `external`")),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let string = |value: &str| Value::String(value.to_string());
+        match self {
+            DelegateSpec::Gpu { library } => Value::Table(vec![
+                ("kind".to_string(), string("gpu")),
+                ("library".to_string(), string(library)),
+            ]),
+            DelegateSpec::External { library, options } => Value::Table(vec![
+                ("kind".to_string(), string("external")),
+                ("library".to_string(), string(library)),
+                (
+                    "options".to_string(),
+                    Value::Table(options.iter().map(|(k, v)| (k.clone(), string(v))).collect()),
+                ),
+            ]),
+        }
+    }
+}
+
+impl RunProfile {
+    fn from_value(value: &Value) -> Result<Self> {
+        let entries = match value {
+            Value::Table(entries) => entries,
+            _ => return Err(invalid("not a table")),
+        };
+        let mut profile = RunProfile::default();
+        for (key, value) in entries {
+            match (key.as_str(), value) {
+                ("threads", &Value::Int(threads))
+                    if threads >= -1 && threads <= i64::from(i32::MAX) =>
+                {
+                    profile.threads = Some(threads as i32)
+                }
+                ("allow_fp16", &Value::Bool(allow)) => profile.allow_fp16 = allow,
+                ("arena_hint", &Value::Int(bytes)) if bytes >= 0 => {
+                    profile.arena_hint = Some(bytes as usize)
+                }
+                ("delegates", Value::Array(delegates)) => {
+                    profile.delegates =
+                        delegates.iter().map(DelegateSpec::from_value).collect::<Result<_>>()?
+                }
+                _ => return Err(invalid(&format!("unknown key or invalid value of `{}`", key))),
+            }
+        }
+        Ok(profile)
+    }
+
+    fn to_value(&self) -> Value {
+        let mut entries = Vec::new();
+        if let Some(threads) = self.threads {
+            entries.push(("threads".to_string(), Value::Int(threads.into())));
+        }
+        entries.push(("allow_fp16".to_string(), Value::Bool(self.allow_fp16)));
+        if let Some(bytes) = self.arena_hint {
+            entries.push(("arena_hint".to_string(), Value::Int(bytes as i64)));
+        }
+        if !self.delegates.is_empty() {
+            let delegates = self.delegates.iter().map(DelegateSpec::to_value).collect();
+            entries.push(("delegates".to_string(), Value::Array(delegates)));
+        }
+        Value::Table(entries)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self> {
+        Self::from_value(&json::parse(text)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        json::write(&self.to_value())
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Self::from_value(&toml::parse(text)?)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::write(&self.to_value())
+    }
+
+    /// Reads a `.json` file as JSON and any other file as TOML.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let text = fs::read_to_string(&path)?;
+        if is_json(path.as_ref()) {
+            Self::from_json(&text)
+        } else {
+            Self::from_toml(&text)
+        }
+    }
+
+    /// Writes JSON to a `.json` file and TOML to any other file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let text = if is_json(path.as_ref()) { self.to_json() } else { self.to_toml() };
+        Ok(fs::write(path, text)?)
+    }
+
+    /// Loads the delegates of the profile. They can be applied to any number of interpreters.
+    pub fn load_delegates(&self) -> Result<Vec<Delegate>> {
+        self.delegates.iter().map(|spec| self.load_delegate(spec)).collect()
+    }
+
+    #[cfg(unix)]
+    fn load_delegate(&self, spec: &DelegateSpec) -> Result<Delegate> {
+        use crate::{GpuDelegateOptions, GpuPrecision};
+
+        match spec {
+            DelegateSpec::Gpu { library } => {
+                let precision =
+                    if self.allow_fp16 { GpuPrecision::Fp16 } else { GpuPrecision::Full };
+                let options = GpuDelegateOptions::default().with_precision(precision);
+                Delegate::load_gpu(library, &options)
+            }
+            DelegateSpec::External { library, options } => {
+                let options: Vec<(&str, &str)> =
+                    options.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+                Delegate::load_external(library, &options)
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn load_delegate(&self, _spec: &DelegateSpec) -> Result<Delegate> {
+        Err(Error::internal_error("delegate libraries are only supported on Unix"))
+    }
+
+    /// Applies the settings and `delegates` (from `load_delegates`) to `interpreter` and
+    /// allocates its tensors.
+    pub fn apply<Op: OpResolver>(
+        &self,
+        interpreter: &mut Interpreter<'_, Op>,
+        delegates: &[Delegate],
+    ) -> Result<()> {
+        if let Some(threads) = self.threads {
+            interpreter.set_num_threads(threads)?;
+        }
+        interpreter.set_allow_fp16_precision_for_fp32(self.allow_fp16);
+        for delegate in delegates {
+            interpreter.modify_graph_with_delegate(delegate)?;
+        }
+        interpreter.allocate_tensors()?;
+        if let Some(hint) = self.arena_hint {
+            let arena = interpreter.plan_report().arena_bytes(TfLiteAllocationType::kTfLiteArenaRw);
+            if arena > hint {
+                return Err(Error::InternalError(format!(
+                    "the model needs a {} byte arena, the run profile was tuned for {}",
+                    arena, hint
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds an interpreter with the profile applied.
+    pub fn build<'a, Op: OpResolver>(
+        &self,
+        builder: InterpreterBuilder<'a, Op>,
+    ) -> Result<Interpreter<'a, Op>> {
+        let delegates = self.load_delegates()?;
+        let mut interpreter = match self.threads {
+            Some(threads) => builder.build_with_threads(threads)?,
+            None => builder.build()?,
+        };
+        self.apply(&mut interpreter, &delegates)?;
+        Ok(interpreter)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_run_profile() {
+        let profile = RunProfile {
+            threads: Some(4),
+            delegates: vec![
+                DelegateSpec::Gpu { library: "libtensorflowlite_gpu_delegate.so".to_string() },
+                DelegateSpec::External {
+                    library: "libedgetpu.so.1".to_string(),
+                    options: vec![("device".to_string(), "usb:0".to_string())],
+                },
+            ],
+            allow_fp16: true,
+            arena_hint: Some(1 << 20),
+        };
+        assert_eq!(RunProfile::from_json(&profile.to_json()).unwrap(), profile);
+        assert_eq!(RunProfile::from_toml(&profile.to_toml()).unwrap(), profile);
+        let text = "threads = 4\nallow_fp16 = true\narena_hint = 1048576\n\n[[delegates]]\n\
+                    kind = \"gpu\"\nlibrary = \"libtensorflowlite_gpu_delegate.so\"\n\n\
+                    [[delegates]]\nkind = \"external\"\nlibrary = \"libedgetpu.so.1\"\n\
+                    options = { device = \"usb:0\" }\n";
+        assert_eq!(RunProfile::from_toml(text).unwrap(), profile);
+
+        assert_eq!(RunProfile::from_json("{}").unwrap(), RunProfile::default());
+        assert!(RunProfile::from_json(r#"{"thread": 4}"#).is_err());
+        assert!(RunProfile::from_toml("threads = -2").is_err());
+        assert!(RunProfile::from_toml("[[delegates]]\nkind = \"npu\"\nlibrary = \"x.so\"").is_err());
+    }
+}
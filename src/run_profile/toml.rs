@@ -0,0 +1,301 @@
+//! Reading and writing the TOML subset of run profiles: `key = value` pairs, `[table]` and
+//! `[[array]]` headers, basic and literal strings, integers, booleans, single-line arrays and
+//! inline tables.
+
+use super::Value;
+use crate::interpreter::json_string;
+use crate::{Error, Result};
+
+struct Line<'a> {
+    number: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn error(&self, message: &str) -> Error {
+        Error::InternalError(format!("invalid TOML on line {}: {}", self.number, message))
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.text = self.text.trim_start();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        let found = self.text.starts_with(c);
+        if found {
+            self.text = &self.text[1..];
+        }
+        found
+    }
+
+    fn key(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        if self.text.starts_with('"') || self.text.starts_with('\'') {
+            return self.string();
+        }
+        let len = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.text.len());
+        if len == 0 {
+            return Err(self.error("expected a key"));
+        }
+        let key = self.text[..len].to_string();
+        self.text = &self.text[len..];
+        Ok(key)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        if self.text.starts_with('\'') {
+            let end = self.text[1..].find('\'').ok_or_else(|| self.error("unterminated string"))?;
+            let string = self.text[1..end + 1].to_string();
+            self.text = &self.text[end + 2..];
+            return Ok(string);
+        }
+        // Basic strings escape like JSON strings.
+        let mut end = 1;
+        let mut escaped = false;
+        for (i, c) in self.text.char_indices().skip(1) {
+            match c {
+                '"' if !escaped => {
+                    end = i + 1;
+                    break;
+                }
+                '\\' => escaped = !escaped,
+                _ => escaped = false,
+            }
+        }
+        if end == 1 {
+            return Err(self.error("unterminated string"));
+        }
+        let string =
+            super::json::parse(&self.text[..end]).map_err(|_| self.error("invalid string"));
+        self.text = &self.text[end..];
+        match string? {
+            Value::String(string) => Ok(string),
+            _ => Err(self.error("invalid string")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        if self.text.starts_with('"') || self.text.starts_with('\'') {
+            return Ok(Value::String(self.string()?));
+        }
+        if self.eat('[') {
+            let mut values = Vec::new();
+            while !self.eat(']') {
+                values.push(self.value()?);
+                if !self.eat(',') && !self.text.trim_start().starts_with(']') {
+                    return Err(self.error("expected `,` or `]`"));
+                }
+            }
+            return Ok(Value::Array(values));
+        }
+        if self.eat('{') {
+            let mut entries = Vec::new();
+            while !self.eat('}') {
+                let key = self.key()?;
+                if !self.eat('=') {
+                    return Err(self.error("expected `=`"));
+                }
+                entries.push((key, self.value()?));
+                if !self.eat(',') && !self.text.trim_start().starts_with('}') {
+                    return Err(self.error("expected `,` or `}`"));
+                }
+            }
+            return Ok(Value::Table(entries));
+        }
+        let len = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '+'))
+            .unwrap_or(self.text.len());
+        let token = &self.text[..len];
+        let value = match token {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => Value::Int(
+                token.replace('_', "").parse().map_err(|_| self.error("expected a value"))?,
+            ),
+        };
+        self.text = &self.text[len..];
+        Ok(value)
+    }
+
+    /// Whether only whitespace and a comment are left.
+    fn is_done(&self) -> bool {
+        let rest = self.text.trim_start();
+        rest.is_empty() || rest.starts_with('#')
+    }
+}
+
+/// The table at the end of `path`, creating tables along the way; `[[array]]` path elements
+/// refer to the last table of the array.
+fn table<'v>(
+    root: &'v mut Vec<(String, Value)>,
+    path: &[String],
+) -> Option<&'v mut Vec<(String, Value)>> {
+    let mut table = root;
+    for key in path {
+        if !table.iter().any(|(k, _)| k == key) {
+            table.push((key.clone(), Value::Table(Vec::new())));
+        }
+        let value = &mut table.iter_mut().find(|(k, _)| k == key)?.1;
+        table = match value {
+            Value::Table(entries) => entries,
+            Value::Array(values) => match values.last_mut()? {
+                Value::Table(entries) => entries,
+                _ => return None,
+            },
+            _ => return None,
+        };
+    }
+    Some(table)
+}
+
+pub(super) fn parse(text: &str) -> Result<Value> {
+    let mut root = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    for (i, text) in text.lines().enumerate() {
+        let mut line = Line { number: i + 1, text };
+        if line.is_done() {
+            continue;
+        }
+        if line.eat('[') {
+            let array = line.eat('[');
+            let mut path = vec![line.key()?];
+            while line.eat('.') {
+                path.push(line.key()?);
+            }
+            if !line.eat(']') || (array && !line.eat(']')) || !line.is_done() {
+                return Err(line.error("invalid table header"));
+            }
+            let (last, parent) = path.split_last().unwrap();
+            let parent = table(&mut root, parent).ok_or_else(|| line.error("not a table"))?;
+            if array {
+                match parent.iter_mut().find(|(k, _)| k == last) {
+                    Some((_, Value::Array(values))) => values.push(Value::Table(Vec::new())),
+                    Some(_) => return Err(line.error("not an array of tables")),
+                    None => {
+                        parent.push((last.clone(), Value::Array(vec![Value::Table(Vec::new())])))
+                    }
+                }
+            } else if parent.iter().any(|(k, _)| k == last) {
+                return Err(line.error("table defined twice"));
+            }
+            current = path;
+            continue;
+        }
+        let key = line.key()?;
+        if !line.eat('=') {
+            return Err(line.error("expected `=`"));
+        }
+        let value = line.value()?;
+        if !line.is_done() {
+            return Err(line.error("trailing characters"));
+        }
+        let table = table(&mut root, &current).ok_or_else(|| line.error("not a table"))?;
+        if table.iter().any(|(k, _)| *k == key) {
+            return Err(line.error("key defined twice"));
+        }
+        table.push((key, value));
+    }
+    Ok(Value::Table(root))
+}
+
+fn key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        json_string(key)
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Bool(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::String(value) => json_string(value),
+        Value::Array(values) => {
+            format!("[{}]", values.iter().map(inline).collect::<Vec<_>>().join(", "))
+        }
+        Value::Table(entries) if entries.is_empty() => "{}".to_string(),
+        Value::Table(entries) => {
+            let entries: Vec<String> =
+                entries.iter().map(|(k, v)| format!("{} = {}", key(k), inline(v))).collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+    }
+}
+
+fn is_table_array(value: &Value) -> bool {
+    match value {
+        Value::Array(values) => {
+            !values.is_empty() && values.iter().all(|v| matches!(v, Value::Table(_)))
+        }
+        _ => false,
+    }
+}
+
+/// Writes `value`, a table, with arrays of tables at the top level as `[[array]]` sections.
+pub(super) fn write(value: &Value) -> String {
+    let entries = match value {
+        Value::Table(entries) => entries,
+        value => return inline(value),
+    };
+    let mut toml = String::new();
+    for (k, v) in entries.iter().filter(|(_, v)| !is_table_array(v)) {
+        toml.push_str(&format!("{} = {}\n", key(k), inline(v)));
+    }
+    for (k, v) in entries.iter().filter(|(_, v)| is_table_array(v)) {
+        if let Value::Array(tables) = v {
+            for table in tables {
+                toml.push_str(&format!("\n[[{}]]\n", key(k)));
+                if let Value::Table(entries) = table {
+                    for (k, v) in entries {
+                        toml.push_str(&format!("{} = {}\n", key(k), inline(v)));
+                    }
+                }
+            }
+        }
+    }
+    toml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_toml() {
+        let text = "# tuned on device A\nthreads = 4 # comment\nname = 'raw \\ string'\n\n\
+                    [[delegates]]\nkind = \"gpu\"\n\n[[delegates]]\n\
+                    options = { device = \"usb:0\", \"two words\" = [1, 2] }\n";
+        let table = |entries: Vec<(&str, Value)>| {
+            Value::Table(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+        };
+        let expected = table(vec![
+            ("threads", Value::Int(4)),
+            ("name", Value::String("raw \\ string".to_string())),
+            (
+                "delegates",
+                Value::Array(vec![
+                    table(vec![("kind", Value::String("gpu".to_string()))]),
+                    table(vec![(
+                        "options",
+                        table(vec![
+                            ("device", Value::String("usb:0".to_string())),
+                            ("two words", Value::Array(vec![Value::Int(1), Value::Int(2)])),
+                        ]),
+                    )]),
+                ]),
+            ),
+        ]);
+        assert_eq!(parse(text).unwrap(), expected);
+        assert_eq!(parse(&write(&expected)).unwrap(), expected);
+        assert!(parse("threads = 4\nthreads = 5\n").is_err());
+        assert!(parse("threads = four\n").is_err());
+        assert!(parse("[[delegates]\n").is_err());
+    }
+}
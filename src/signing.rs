@@ -0,0 +1,134 @@
+//! Refusing models that were not signed by a trusted key, e.g. over-the-air updates. A model
+//! file `detector.tflite` comes with `detector.tflite.sig`, the raw 64-byte ed25519 signature
+//! of its bytes:
+//!
+//! ```ignore
+//! let verifier = ModelVerifier::new(&include_bytes!("models.pub"))?;
+//! let model = verifier.load("/data/models/detector.tflite")?;
+//! ```
+//!
+//! Signatures are checked over the whole file before TF Lite parses any of it.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::{Error, FlatBufferModel, ModelSource, Result};
+
+/// Extension appended to a model path to find its detached signature.
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// The ed25519 public keys whose signatures are accepted. Several keys allow rotating them.
+#[derive(Clone, Debug)]
+pub struct ModelVerifier {
+    keys: Vec<VerifyingKey>,
+}
+
+/// Path of the detached signature of the model at `path`: `path` with `.sig` appended.
+pub fn signature_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut path = path.as_ref().as_os_str().to_os_string();
+    path.push(".");
+    path.push(SIGNATURE_EXTENSION);
+    PathBuf::from(path)
+}
+
+fn rejected(reason: String) -> Error {
+    Error::SignatureRejected(reason)
+}
+
+impl ModelVerifier {
+    /// Accepts signatures of the 32-byte ed25519 public key `key`.
+    pub fn new(key: &[u8; 32]) -> Result<Self> {
+        Self { keys: Vec::new() }.with_key(key)
+    }
+
+    /// Also accepts signatures of `key`.
+    pub fn with_key(mut self, key: &[u8; 32]) -> Result<Self> {
+        let key = VerifyingKey::from_bytes(key)
+            .map_err(|e| Error::InternalError(format!("invalid ed25519 public key: {}", e)))?;
+        self.keys.push(key);
+        Ok(self)
+    }
+
+    /// Checks that `signature` is a signature of `model` by one of the keys.
+    pub fn verify(&self, model: &[u8], signature: &[u8]) -> Result<()> {
+        let signature = Signature::from_slice(signature)
+            .map_err(|_| rejected(format!("a signature has 64 bytes, got {}", signature.len())))?;
+        if self.keys.iter().any(|key| key.verify_strict(model, &signature).is_ok()) {
+            Ok(())
+        } else {
+            Err(rejected("no trusted key signed the model".to_string()))
+        }
+    }
+
+    /// Builds a model from `source` once `signature` checked out.
+    pub fn build<S: Into<ModelSource>>(
+        &self,
+        source: S,
+        signature: &[u8],
+    ) -> Result<FlatBufferModel> {
+        let source = source.into().load()?;
+        self.verify(source.as_bytes().unwrap_or_default(), signature)?;
+        FlatBufferModel::build(source)
+    }
+
+    /// Reads the model at `path` and builds it if `signature_path(path)` holds a valid
+    /// signature of it. A missing signature rejects the model.
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> Result<FlatBufferModel> {
+        let path = path.as_ref();
+        let signature = match fs::read(signature_path(path)) {
+            Ok(signature) => signature,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(rejected(format!("{} is not signed", path.display())));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.build(fs::read(path)?, &signature).map_err(|e| match e {
+            Error::SignatureRejected(reason) => rejected(format!("{}: {}", path.display(), reason)),
+            e => e,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    #[test]
+    fn unittest_model_verifier() {
+        let model = fs::read("data/MNISTnet_uint8_quant.tflite").unwrap();
+        let (release, rotated) =
+            (SigningKey::from_bytes(&[1; 32]), SigningKey::from_bytes(&[2; 32]));
+        let signature = release.sign(&model).to_bytes();
+
+        let verifier = ModelVerifier::new(&release.verifying_key().to_bytes()).unwrap();
+        verifier.verify(&model, &signature).unwrap();
+        let mut tampered = model.clone();
+        tampered[100] ^= 1;
+        assert!(matches!(verifier.verify(&tampered, &signature), Err(Error::SignatureRejected(_))));
+        assert!(matches!(
+            verifier.verify(&model, &signature[..63]),
+            Err(Error::SignatureRejected(_))
+        ));
+
+        let by_rotated = rotated.sign(&model).to_bytes();
+        assert!(verifier.verify(&model, &by_rotated).is_err());
+        let verifier = verifier.with_key(&rotated.verifying_key().to_bytes()).unwrap();
+        verifier.verify(&model, &by_rotated).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("tflite-signing-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mnist.tflite");
+        fs::write(&path, &model).unwrap();
+        let err = verifier.load(&path).err().unwrap();
+        assert!(err.to_string().contains("is not signed"), "{}", err);
+        fs::write(signature_path(&path), &signature[..]).unwrap();
+        assert_eq!(signature_path(&path), dir.join("mnist.tflite.sig"));
+        let loaded = verifier.load(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.unwrap().buffer(), &model[..]);
+    }
+}
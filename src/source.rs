@@ -0,0 +1,290 @@
+//! Where a model comes from.
+
+use std::fs::{self, File};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{Error, Result};
+
+/// A read-only memory map of a model file. Falls back to reading the file on non-Unix
+/// targets.
+pub struct Mmap {
+    #[cfg(unix)]
+    ptr: *mut libc::c_void,
+    #[cfg(unix)]
+    len: usize,
+    #[cfg(not(unix))]
+    data: Vec<u8>,
+}
+
+// The mapping is read-only and private.
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    #[cfg(unix)]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(Self { ptr: std::ptr::null_mut(), len });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        use std::io::Read;
+
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        Ok(Self { data })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        #[cfg(unix)]
+        {
+            if self.len == 0 {
+                return &[];
+            }
+            unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+        }
+        #[cfg(not(unix))]
+        {
+            &self.data
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+impl std::fmt::Debug for Mmap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Mmap").field("len", &self.as_slice().len()).finish()
+    }
+}
+
+/// A serialized model, accepted by `FlatBufferModel::build` and `Model::from_source`.
+#[derive(Debug)]
+pub enum ModelSource {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+    Static(&'static [u8]),
+    Mmap(Mmap),
+    /// An allocation already backing another model, used without copying.
+    Shared(Allocation),
+    /// A range of another allocation, e.g. a model of a `ModelBundle`, used without copying.
+    Part(Allocation, Range<usize>),
+}
+
+impl Default for ModelSource {
+    fn default() -> Self {
+        ModelSource::Bytes(Vec::new())
+    }
+}
+
+impl ModelSource {
+    /// Resolves `Path` by reading the file; other sources are returned as is.
+    pub fn load(self) -> Result<Self> {
+        match self {
+            ModelSource::Path(path) => Ok(ModelSource::Bytes(fs::read(path)?)),
+            source => Ok(source),
+        }
+    }
+
+    /// The model bytes, or `None` for a `Path` that was not loaded yet.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ModelSource::Path(_) => None,
+            ModelSource::Bytes(bytes) => Some(bytes),
+            ModelSource::Static(bytes) => Some(bytes),
+            ModelSource::Mmap(mmap) => Some(mmap.as_slice()),
+            ModelSource::Shared(allocation) => Some(allocation.as_slice()),
+            ModelSource::Part(allocation, range) => allocation.as_slice().get(range.clone()),
+        }
+    }
+
+    /// The model bytes as an owned buffer, copying unless the source already owns one.
+    pub fn into_bytes(self) -> Result<Vec<u8>> {
+        match self.load()? {
+            ModelSource::Bytes(bytes) => Ok(bytes),
+            ModelSource::Shared(allocation) => match Arc::try_unwrap(allocation.0) {
+                Ok(source) => source.into_bytes(),
+                Err(shared) => Ok(shared.as_bytes().unwrap_or_default().to_vec()),
+            },
+            source => Ok(source.as_bytes().unwrap_or_default().to_vec()),
+        }
+    }
+}
+
+impl From<PathBuf> for ModelSource {
+    fn from(path: PathBuf) -> Self {
+        ModelSource::Path(path)
+    }
+}
+
+impl<'a> From<&'a Path> for ModelSource {
+    fn from(path: &'a Path) -> Self {
+        ModelSource::Path(path.to_path_buf())
+    }
+}
+
+impl From<Vec<u8>> for ModelSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        ModelSource::Bytes(bytes)
+    }
+}
+
+impl From<&'static [u8]> for ModelSource {
+    fn from(bytes: &'static [u8]) -> Self {
+        ModelSource::Static(bytes)
+    }
+}
+
+/// For `include_bytes!`, whose bytes are used in place.
+impl<const N: usize> From<&'static [u8; N]> for ModelSource {
+    fn from(bytes: &'static [u8; N]) -> Self {
+        ModelSource::Static(bytes)
+    }
+}
+
+impl From<Mmap> for ModelSource {
+    fn from(mmap: Mmap) -> Self {
+        ModelSource::Mmap(mmap)
+    }
+}
+
+impl From<Allocation> for ModelSource {
+    fn from(allocation: Allocation) -> Self {
+        ModelSource::Shared(allocation)
+    }
+}
+
+/// How the bytes of an `Allocation` are backed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationKind {
+    Heap,
+    Static,
+    Mmap,
+}
+
+/// The loaded bytes of a model, shared by reference count. Pass a clone to
+/// `FlatBufferModel::build` to back several models with the same memory.
+#[derive(Clone, Debug)]
+pub struct Allocation(Arc<ModelSource>);
+
+impl Default for Allocation {
+    fn default() -> Self {
+        Allocation(Arc::new(ModelSource::default()))
+    }
+}
+
+impl Allocation {
+    /// Loads `source`. Sharing an existing allocation returns it as is.
+    pub fn new<S: Into<ModelSource>>(source: S) -> Result<Self> {
+        match source.into().load()? {
+            ModelSource::Shared(allocation) => Ok(allocation),
+            source => Ok(Allocation(Arc::new(source))),
+        }
+    }
+
+    pub fn kind(&self) -> AllocationKind {
+        match *self.0 {
+            ModelSource::Static(_) => AllocationKind::Static,
+            ModelSource::Mmap(_) => AllocationKind::Mmap,
+            ModelSource::Part(ref allocation, _) => allocation.kind(),
+            _ => AllocationKind::Heap,
+        }
+    }
+
+    pub fn base(&self) -> *const u8 {
+        self.as_slice().as_ptr()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_bytes().unwrap_or_default()
+    }
+
+    /// The bytes of `range` as an allocation sharing this one.
+    pub fn slice(&self, range: Range<usize>) -> Result<Allocation> {
+        if range.start > range.end || range.end > self.bytes() {
+            return Err(Error::InternalError(format!(
+                "range {:?} out of an allocation of {} bytes",
+                range,
+                self.bytes()
+            )));
+        }
+        Ok(Allocation(Arc::new(ModelSource::Part(self.clone(), range))))
+    }
+
+    /// Number of handles to this allocation, including models built from it.
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// Whether both handles refer to the same memory.
+    pub fn ptr_eq(&self, other: &Allocation) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_model_source() {
+        let path = Path::new("data/MNISTnet_uint8_quant.tflite");
+        let bytes = fs::read(path).unwrap();
+
+        let mmap = ModelSource::from(Mmap::open(path).unwrap());
+        assert_eq!(mmap.as_bytes(), Some(&bytes[..]));
+
+        let loaded = ModelSource::from(path).load().unwrap();
+        assert_eq!(loaded.as_bytes(), Some(&bytes[..]));
+
+        static HEADER: [u8; 4] = [1, 2, 3, 4];
+        assert_eq!(ModelSource::from(&HEADER[..]).into_bytes().unwrap(), HEADER.to_vec());
+        let embedded = Allocation::new(&HEADER).unwrap();
+        assert_eq!((embedded.kind(), embedded.base()), (AllocationKind::Static, HEADER.as_ptr()));
+
+        let allocation = Allocation::new(Mmap::open(path).unwrap()).unwrap();
+        assert_eq!(allocation.kind(), AllocationKind::Mmap);
+        let shared = Allocation::new(allocation.clone()).unwrap();
+        assert!(shared.ptr_eq(&allocation));
+        assert_eq!(shared.ref_count(), 2);
+        assert_eq!(ModelSource::from(shared).into_bytes().unwrap(), bytes);
+
+        let part = allocation.slice(4..8).unwrap();
+        assert_eq!((part.kind(), part.as_slice()), (AllocationKind::Mmap, &bytes[4..8]));
+        assert_eq!(allocation.ref_count(), 2);
+        assert!(allocation.slice(4..bytes.len() + 1).is_err());
+    }
+}
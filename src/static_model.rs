@@ -0,0 +1,203 @@
+//! Models embedded at compile time with `include_model!`.
+
+use crate::{FlatBufferModel, ModelSource, Result};
+
+/// A model embedded in the binary by `include_model!`, validated at compile time.
+#[derive(Clone, Copy, Debug)]
+pub struct StaticModel {
+    bytes: &'static [u8],
+}
+
+impl StaticModel {
+    /// Wraps `bytes`, panicking (at compile time in a const context) if they do not pass
+    /// `verify_model`.
+    pub const fn new(bytes: &'static [u8]) -> Self {
+        if let Err(reason) = verify_model(bytes) {
+            panic!("{}", reason);
+        }
+        Self { bytes }
+    }
+
+    pub const fn bytes(&self) -> &'static [u8] {
+        self.bytes
+    }
+
+    /// Builds a model using the embedded bytes in place.
+    pub fn build(&self) -> Result<FlatBufferModel> {
+        FlatBufferModel::build(self.bytes)
+    }
+}
+
+impl From<StaticModel> for ModelSource {
+    fn from(model: StaticModel) -> Self {
+        ModelSource::Static(model.bytes)
+    }
+}
+
+#[doc(hidden)]
+#[repr(C, align(16))]
+pub struct Aligned<B: ?Sized>(pub B);
+
+/// Embeds a `.tflite` file as a `StaticModel`. The path is resolved like `include_bytes!`.
+///
+/// The flatbuffer structure (file identifier, root table, schema version, subgraph and
+/// buffer vectors) is checked while compiling, so a corrupted or wrong-schema file fails
+/// the build instead of failing on the device.
+///
+/// ```ignore
+/// static MNIST: tflite::StaticModel = tflite::include_model!("../data/MNISTnet_uint8_quant.tflite");
+/// ```
+#[macro_export]
+macro_rules! include_model {
+    ($path:expr) => {{
+        const ALIGNED: &$crate::Aligned<[u8]> = &$crate::Aligned(*include_bytes!($path));
+        const MODEL: $crate::StaticModel = $crate::StaticModel::new(&ALIGNED.0);
+        MODEL
+    }};
+}
+
+const SCHEMA_VERSION: u32 = 3;
+
+const fn read_u16(bytes: &[u8], pos: usize) -> u16 {
+    bytes[pos] as u16 | (bytes[pos + 1] as u16) << 8
+}
+
+const fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    bytes[pos] as u32
+        | (bytes[pos + 1] as u32) << 8
+        | (bytes[pos + 2] as u32) << 16
+        | (bytes[pos + 3] as u32) << 24
+}
+
+/// Position of the table referenced by the offset at `pos` and of its vtable.
+const fn table(bytes: &[u8], pos: usize) -> std::result::Result<(usize, usize), &'static str> {
+    if pos + 4 > bytes.len() {
+        return Err("table offset out of bounds");
+    }
+    let table = pos + read_u32(bytes, pos) as usize;
+    if table + 4 > bytes.len() {
+        return Err("table out of bounds");
+    }
+    let vtable = table as i64 - read_u32(bytes, table) as i32 as i64;
+    if vtable < 0 || vtable as usize + 4 > bytes.len() {
+        return Err("vtable out of bounds");
+    }
+    let vtable = vtable as usize;
+    if vtable + read_u16(bytes, vtable) as usize > bytes.len()
+        || table + read_u16(bytes, vtable + 2) as usize > bytes.len()
+    {
+        return Err("table out of bounds");
+    }
+    Ok((table, vtable))
+}
+
+/// Absolute position of field `id` of a table, 0 if absent.
+const fn field(bytes: &[u8], (table, vtable): (usize, usize), id: usize) -> usize {
+    let entry = 4 + 2 * id;
+    if entry + 2 > read_u16(bytes, vtable) as usize {
+        return 0;
+    }
+    match read_u16(bytes, vtable + entry) {
+        0 => 0,
+        offset => table + offset as usize,
+    }
+}
+
+/// Checks that the vector referenced at `pos` lies within `bytes`, returning the position
+/// of its first element and its length.
+const fn vector(bytes: &[u8], pos: usize) -> std::result::Result<(usize, usize), &'static str> {
+    if pos + 4 > bytes.len() {
+        return Err("vector offset out of bounds");
+    }
+    let start = pos + read_u32(bytes, pos) as usize;
+    if start + 4 > bytes.len() {
+        return Err("vector out of bounds");
+    }
+    let len = read_u32(bytes, start) as usize;
+    if start + 4 + len > bytes.len() {
+        return Err("vector elements out of bounds");
+    }
+    Ok((start + 4, len))
+}
+
+/// Structural validation of a TFLite flatbuffer, usable in const contexts. Checks the
+/// file identifier, schema version, subgraphs and that every weight buffer lies within
+/// `bytes`; `FlatBufferModel::build` still runs the full verifier.
+pub const fn verify_model(bytes: &[u8]) -> std::result::Result<(), &'static str> {
+    if bytes.len() < 8 {
+        return Err("model file is too small");
+    }
+    if bytes[4] != b'T' || bytes[5] != b'F' || bytes[6] != b'L' || bytes[7] != b'3' {
+        return Err("missing `TFL3` file identifier, not a TFLite model");
+    }
+    let model = match table(bytes, 0) {
+        Ok(model) => model,
+        Err(e) => return Err(e),
+    };
+
+    let version = field(bytes, model, 0);
+    if version == 0 || version + 4 > bytes.len() || read_u32(bytes, version) != SCHEMA_VERSION {
+        return Err("unsupported schema version");
+    }
+
+    let subgraphs = field(bytes, model, 2);
+    if subgraphs == 0 {
+        return Err("model has no subgraphs");
+    }
+    match vector(bytes, subgraphs) {
+        Ok((_, 0)) => return Err("model has no subgraphs"),
+        Ok(_) => {}
+        Err(e) => return Err(e),
+    }
+
+    let buffers = field(bytes, model, 4);
+    if buffers == 0 {
+        return Ok(());
+    }
+    let (start, len) = match vector(bytes, buffers) {
+        Ok(vector) => vector,
+        Err(e) => return Err(e),
+    };
+    if start + 4 * len > bytes.len() {
+        return Err("buffer table out of bounds");
+    }
+    let mut i = 0;
+    while i < len {
+        let buffer = match table(bytes, start + 4 * i) {
+            Ok(buffer) => buffer,
+            Err(e) => return Err(e),
+        };
+        let data = field(bytes, buffer, 0);
+        if data != 0 {
+            if let Err(e) = vector(bytes, data) {
+                return Err(e);
+            }
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static MNIST: StaticModel = include_model!("../data/MNISTnet_uint8_quant.tflite");
+
+    #[test]
+    fn unittest_verify_model() {
+        let bytes = std::fs::read("data/MNISTnet_uint8_quant.tflite").unwrap();
+        assert_eq!(MNIST.bytes(), &bytes[..]);
+        assert_eq!(MNIST.bytes().as_ptr() as usize % 16, 0);
+
+        let mut corrupted = bytes.clone();
+        corrupted[4..8].copy_from_slice(b"TFL2");
+        assert!(verify_model(&corrupted).is_err());
+
+        let mut corrupted = bytes.clone();
+        corrupted[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(verify_model(&corrupted).is_err());
+
+        assert!(verify_model(&bytes[..bytes.len() / 2]).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Tokenizers feeding BERT-style NLP models (feature `text`).
+//!
+//! Text is split into WordPiece or SentencePiece tokens, framed with `[CLS]` and `[SEP]`,
+//! padded to the sequence length of the model and written into its int32 inputs:
+//!
+//! ```ignore
+//! let model = FlatBufferModel::build_from_file("mobilebert.tflite")?;
+//! let encoder = BertEncoder::new(WordPieceTokenizer::from_metadata(&model, true)?, 384)?;
+//! let inputs = BertInputs::detect(&interpreter)?;
+//! encoder.encode_pair(question, context).write(&mut interpreter, &inputs)?;
+//! interpreter.invoke()?;
+//! ```
+
+mod sentencepiece;
+mod wordpiece;
+
+pub use sentencepiece::SentencePieceTokenizer;
+pub use wordpiece::WordPieceTokenizer;
+
+use crate::context::ElementKind;
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result, TensorIndex};
+
+/// Splits text into vocabulary tokens.
+pub trait Tokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+
+    fn token_id(&self, token: &str) -> Option<i32>;
+
+    /// Id of the token out-of-vocabulary text maps to.
+    fn unknown_id(&self) -> Option<i32>;
+
+    /// Token ids of `text`; tokens without an id map to the unknown id or are dropped.
+    fn encode(&self, text: &str) -> Vec<i32> {
+        let unknown = self.unknown_id();
+        self.tokenize(text).iter().filter_map(|token| self.token_id(token).or(unknown)).collect()
+    }
+}
+
+/// The inputs of one sequence, all of the sequence length.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Encoding {
+    pub input_ids: Vec<i32>,
+    /// 1 for tokens, 0 for padding.
+    pub attention_mask: Vec<i32>,
+    /// 0 for the first segment (and padding), 1 for the second.
+    pub segment_ids: Vec<i32>,
+    /// Whether tokens were dropped to fit the sequence length.
+    pub truncated: bool,
+}
+
+impl Encoding {
+    /// Copies the encoding into the input tensors of `interpreter`.
+    pub fn write<Op: OpResolver>(
+        &self,
+        interpreter: &mut Interpreter<'_, Op>,
+        inputs: &BertInputs,
+    ) -> Result<()> {
+        let tensors = [
+            (Some(inputs.input_ids), &self.input_ids),
+            (inputs.attention_mask, &self.attention_mask),
+            (inputs.segment_ids, &self.segment_ids),
+        ];
+        for (tensor, values) in tensors.iter() {
+            if let Some(tensor) = *tensor {
+                let data = interpreter.tensor_data_mut::<i32>(tensor)?;
+                if data.len() != values.len() {
+                    return Err(Error::InternalError(format!(
+                        "input {} holds {} tokens, the encoding {}",
+                        tensor,
+                        data.len(),
+                        values.len()
+                    )));
+                }
+                data.copy_from_slice(values);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Frames token ids as BERT expects: `[CLS] a [SEP]` or `[CLS] a [SEP] b [SEP]`, padded to
+/// `max_seq_len`.
+#[derive(Clone, Debug)]
+pub struct BertEncoder<T: Tokenizer> {
+    tokenizer: T,
+    max_seq_len: usize,
+    cls: i32,
+    sep: i32,
+    pad: i32,
+}
+
+impl<T: Tokenizer> BertEncoder<T> {
+    /// Looks up `[CLS]`, `[SEP]` and `[PAD]` (or SentencePiece's `<pad>`) in the vocabulary.
+    pub fn new(tokenizer: T, max_seq_len: usize) -> Result<Self> {
+        let id = |tokens: &[&str]| {
+            tokens.iter().find_map(|token| tokenizer.token_id(token)).ok_or_else(|| {
+                Error::InternalError(format!("vocabulary has no {} token", tokens[0]))
+            })
+        };
+        let (cls, sep, pad) = (id(&["[CLS]"])?, id(&["[SEP]"])?, id(&["[PAD]", "<pad>"])?);
+        if max_seq_len < 3 {
+            return Err(Error::internal_error("sequence length must be at least 3"));
+        }
+        Ok(Self { tokenizer, max_seq_len, cls, sep, pad })
+    }
+
+    pub fn with_special_tokens(mut self, cls: i32, sep: i32, pad: i32) -> Self {
+        self.cls = cls;
+        self.sep = sep;
+        self.pad = pad;
+        self
+    }
+
+    pub fn tokenizer(&self) -> &T {
+        &self.tokenizer
+    }
+
+    pub fn encode(&self, text: &str) -> Encoding {
+        let mut ids = self.tokenizer.encode(text);
+        let truncated = ids.len() > self.max_seq_len - 2;
+        ids.truncate(self.max_seq_len - 2);
+        self.frame(&ids, None, truncated)
+    }
+
+    /// Encodes a pair like question and context, truncating the longer of them first.
+    pub fn encode_pair(&self, first: &str, second: &str) -> Encoding {
+        let (mut a, mut b) = (self.tokenizer.encode(first), self.tokenizer.encode(second));
+        let truncated = a.len() + b.len() > self.max_seq_len - 3;
+        while a.len() + b.len() > self.max_seq_len - 3 {
+            if a.len() > b.len() {
+                a.pop();
+            } else {
+                b.pop();
+            }
+        }
+        self.frame(&a, Some(&b), truncated)
+    }
+
+    fn frame(&self, a: &[i32], b: Option<&[i32]>, truncated: bool) -> Encoding {
+        let mut input_ids = Vec::with_capacity(self.max_seq_len);
+        input_ids.push(self.cls);
+        input_ids.extend_from_slice(a);
+        input_ids.push(self.sep);
+        let first_len = input_ids.len();
+        if let Some(b) = b {
+            input_ids.extend_from_slice(b);
+            input_ids.push(self.sep);
+        }
+        let len = input_ids.len();
+        input_ids.resize(self.max_seq_len, self.pad);
+        let attention_mask = (0..self.max_seq_len).map(|i| (i < len) as i32).collect();
+        let segment_ids =
+            (0..self.max_seq_len).map(|i| (first_len <= i && i < len) as i32).collect();
+        Encoding { input_ids, attention_mask, segment_ids, truncated }
+    }
+}
+
+/// The int32 input tensors of a BERT-style model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BertInputs {
+    pub input_ids: TensorIndex,
+    pub attention_mask: Option<TensorIndex>,
+    pub segment_ids: Option<TensorIndex>,
+}
+
+impl BertInputs {
+    /// Finds the inputs by the names TF Hub and Model Maker models use (e.g. `input_word_ids`,
+    /// `input_mask`, `input_type_ids`), falling back to the order ids, mask, segment ids.
+    pub fn detect<Op: OpResolver>(interpreter: &Interpreter<'_, Op>) -> Result<Self> {
+        let mut inputs = Vec::new();
+        for &index in interpreter.inputs() {
+            let info = interpreter
+                .tensor_info(index)
+                .ok_or_else(|| Error::InternalError(format!("no input {}", index)))?;
+            if info.element_kind != ElementKind::kTfLiteInt32 {
+                return Err(Error::InternalError(format!(
+                    "input `{}` is {:?}, not int32",
+                    info.name, info.element_kind
+                )));
+            }
+            inputs.push((index, info.name.to_lowercase()));
+        }
+        let find = |matches: &dyn Fn(&str) -> bool| {
+            inputs.iter().find(|(_, name)| matches(name)).map(|&(index, _)| index)
+        };
+        let is_segment = |name: &str| name.contains("segment") || name.contains("type");
+        let input_ids =
+            find(&|name| (name.contains("ids") || name.contains("word")) && !is_segment(name));
+        if let Some(input_ids) = input_ids {
+            return Ok(Self {
+                input_ids,
+                attention_mask: find(&|name| name.contains("mask")),
+                segment_ids: find(&is_segment),
+            });
+        }
+        let mut by_order = inputs.iter().map(|&(index, _)| index);
+        Ok(Self {
+            input_ids: by_order
+                .next()
+                .ok_or_else(|| Error::internal_error("model has no inputs"))?,
+            attention_mask: by_order.next(),
+            segment_ids: by_order.next(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_bert_encoder() {
+        let vocab = "[PAD]\n[UNK]\n[CLS]\n[SEP]\nwhat\nis\nit\n?\n";
+        let encoder = BertEncoder::new(WordPieceTokenizer::from_vocab(vocab, true), 8).unwrap();
+        let encoding = encoder.encode("What is it?");
+        assert_eq!(encoding.input_ids, vec![2, 4, 5, 6, 7, 3, 0, 0]);
+        assert_eq!(encoding.attention_mask, vec![1, 1, 1, 1, 1, 1, 0, 0]);
+        assert_eq!(encoding.segment_ids, vec![0; 8]);
+        assert!(!encoding.truncated);
+
+        // 2 + 4 tokens truncated to 5: the longer context loses its last tokens.
+        let encoding = encoder.encode_pair("what is", "it is it ?");
+        assert_eq!(encoding.input_ids, vec![2, 4, 5, 3, 6, 5, 6, 3]);
+        assert_eq!(encoding.segment_ids, vec![0, 0, 0, 0, 1, 1, 1, 1]);
+        assert_eq!(encoding.attention_mask, vec![1; 8]);
+        assert!(encoding.truncated);
+
+        let no_pad = WordPieceTokenizer::from_vocab("[CLS]\n[SEP]\n", true);
+        assert!(BertEncoder::new(no_pad, 8).is_err());
+    }
+}
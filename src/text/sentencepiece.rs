@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use super::Tokenizer;
+use crate::{Error, Result};
+
+/// Marks word starts in SentencePiece pieces, replacing spaces.
+const WORD_START: char = '\u{2581}';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PieceKind {
+    Normal,
+    Unknown,
+    Control,
+    UserDefined,
+    Unused,
+    Byte,
+}
+
+/// A SentencePiece unigram model, e.g. the `30k-clean.model` of ALBERT, segmenting text
+/// into the pieces of highest total score.
+///
+/// The model's normalization rules (usually NFKC) are not applied, only whitespace is
+/// collapsed; pass normalized text for inputs outside of ASCII.
+#[derive(Clone, Debug)]
+pub struct SentencePieceTokenizer {
+    pieces: Vec<(String, f32, PieceKind)>,
+    ids: HashMap<String, i32>,
+    unknown: Option<i32>,
+    max_piece_chars: usize,
+    lowercase: bool,
+}
+
+/// Reads protobuf wire format, just enough of it for `ModelProto`.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Fixed32([u8; 4]),
+    Bytes(&'a [u8]),
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) =
+                self.data.split_first().ok_or_else(|| Error::internal_error("truncated varint"))?;
+            self.data = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::internal_error("varint is too long"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if len > self.data.len() {
+            return Err(Error::internal_error("truncated protobuf field"));
+        }
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+
+    /// The next field number and value.
+    fn field(&mut self) -> Result<Option<(u64, Value<'a>)>> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => Value::Bytes(self.take(8)?),
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(self.take(4)?);
+                Value::Fixed32(bytes)
+            }
+            wire_type => {
+                return Err(Error::InternalError(format!(
+                    "unsupported protobuf wire type {}",
+                    wire_type
+                )))
+            }
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+impl SentencePieceTokenizer {
+    /// Parses a serialized SentencePiece `ModelProto` (a `.model` file).
+    pub fn from_model(model: &[u8], lowercase: bool) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut reader = Reader { data: model };
+        while let Some((field, value)) = reader.field()? {
+            match (field, value) {
+                (1, Value::Bytes(piece)) => pieces.push(parse_piece(piece)?),
+                (2, Value::Bytes(trainer_spec)) => {
+                    let mut spec = Reader { data: trainer_spec };
+                    while let Some((field, value)) = spec.field()? {
+                        // `model_type`: only UNIGRAM (1) scores pieces for segmentation.
+                        if let (3, Value::Varint(model_type)) = (field, value) {
+                            if model_type != 1 {
+                                return Err(Error::InternalError(format!(
+                                    "unsupported SentencePiece model type {}, only unigram \
+                                     models are supported",
+                                    model_type
+                                )));
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if pieces.is_empty() {
+            return Err(Error::internal_error("SentencePiece model has no pieces"));
+        }
+        let ids = pieces
+            .iter()
+            .enumerate()
+            .map(|(id, (piece, _, _))| (piece.clone(), id as i32))
+            .collect();
+        let unknown = pieces.iter().position(|p| p.2 == PieceKind::Unknown).map(|id| id as i32);
+        let max_piece_chars = pieces.iter().map(|p| p.0.chars().count()).max().unwrap_or(1);
+        Ok(Self { pieces, ids, unknown, max_piece_chars, lowercase })
+    }
+
+    /// Best segmentation of `text` by the Viterbi algorithm over piece scores. Characters
+    /// no piece covers become the unknown piece.
+    fn segment(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        let unknown_score =
+            self.pieces.iter().map(|p| p.1).fold(f32::INFINITY, f32::min).min(0.0) - 10.0;
+        // best[i]: score of the best segmentation of `chars[..i]` and where its last piece
+        // starts.
+        let mut best = vec![(f32::NEG_INFINITY, 0); chars.len() + 1];
+        best[0].0 = 0.0;
+        for start in 0..chars.len() {
+            let score = best[start].0;
+            if score == f32::NEG_INFINITY {
+                continue;
+            }
+            let mut covered = false;
+            let end_max = (start + self.max_piece_chars).min(chars.len());
+            for end in start + 1..=end_max {
+                let piece: String = chars[start..end].iter().collect();
+                let piece_score = match self.ids.get(&piece).map(|&id| &self.pieces[id as usize]) {
+                    Some((_, score, PieceKind::Normal)) => *score,
+                    // Always matched whole, preferred over normal pieces.
+                    Some((_, _, PieceKind::UserDefined)) => 0.0,
+                    _ => continue,
+                };
+                covered |= end == start + 1;
+                if score + piece_score > best[end].0 {
+                    best[end] = (score + piece_score, start);
+                }
+            }
+            if !covered && score + unknown_score > best[start + 1].0 {
+                best[start + 1] = (score + unknown_score, start);
+            }
+        }
+
+        let mut pieces = Vec::new();
+        let mut end = chars.len();
+        while end > 0 {
+            let start = best[end].1;
+            pieces.push(chars[start..end].iter().collect());
+            end = start;
+        }
+        pieces.reverse();
+        pieces
+    }
+}
+
+fn parse_piece(data: &[u8]) -> Result<(String, f32, PieceKind)> {
+    let (mut piece, mut score, mut kind) = (String::new(), 0.0, PieceKind::Normal);
+    let mut reader = Reader { data };
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (1, Value::Bytes(bytes)) => piece = String::from_utf8_lossy(bytes).into_owned(),
+            (2, Value::Fixed32(bytes)) => score = f32::from_le_bytes(bytes),
+            (3, Value::Varint(value)) => {
+                kind = match value {
+                    2 => PieceKind::Unknown,
+                    3 => PieceKind::Control,
+                    4 => PieceKind::UserDefined,
+                    5 => PieceKind::Unused,
+                    6 => PieceKind::Byte,
+                    _ => PieceKind::Normal,
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((piece, score, kind))
+}
+
+impl Tokenizer for SentencePieceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut normalized = String::new();
+        for word in text.split_whitespace() {
+            normalized.push(WORD_START);
+            if self.lowercase {
+                normalized.extend(word.chars().flat_map(char::to_lowercase));
+            } else {
+                normalized.push_str(word);
+            }
+        }
+        let unknown = self.unknown.map(|id| self.pieces[id as usize].0.as_str());
+        self.segment(&normalized)
+            .into_iter()
+            .map(|piece| match unknown {
+                Some(unknown) if !self.ids.contains_key(&piece) => unknown.to_string(),
+                _ => piece,
+            })
+            .collect()
+    }
+
+    fn token_id(&self, token: &str) -> Option<i32> {
+        self.ids.get(token).copied()
+    }
+
+    fn unknown_id(&self) -> Option<i32> {
+        self.unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(fields: &[(u64, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &(field, bytes) in fields {
+            data.push((field << 3 | 2) as u8);
+            data.push(bytes.len() as u8);
+            data.extend_from_slice(bytes);
+        }
+        data
+    }
+
+    fn piece(text: &str, score: f32, kind: u8) -> Vec<u8> {
+        let mut data = message(&[(1, text.as_bytes())]);
+        data.push(2 << 3 | 5);
+        data.extend_from_slice(&score.to_le_bytes());
+        data.extend_from_slice(&[3 << 3, kind]);
+        data
+    }
+
+    #[test]
+    fn unittest_sentencepiece() {
+        let pieces = [
+            piece("<pad>", 0.0, 3),
+            piece("<unk>", 0.0, 2),
+            piece("[CLS]", 0.0, 3),
+            piece("▁", -2.0, 1),
+            piece("▁hello", -3.0, 1),
+            piece("▁he", -1.0, 1),
+            piece("llo", -1.5, 1),
+            piece("▁world", -4.0, 1),
+            piece("s", -2.0, 1),
+        ];
+        let mut model = message(&pieces.iter().map(|p| (1, p.as_slice())).collect::<Vec<_>>());
+        let tokenizer = SentencePieceTokenizer::from_model(&model, true).unwrap();
+        // "▁he" + "llo" scores -2.5, better than "▁hello".
+        assert_eq!(
+            tokenizer.tokenize("  Hello worlds!"),
+            vec!["▁he", "llo", "▁world", "s", "<unk>"]
+        );
+        assert_eq!(tokenizer.encode("hello x"), vec![5, 6, 3, 1]);
+        assert_eq!(tokenizer.token_id("[CLS]"), Some(2));
+
+        // A BPE trainer spec.
+        model.extend_from_slice(&message(&[(2, &[3 << 3, 2])]));
+        assert!(SentencePieceTokenizer::from_model(&model, true).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use super::Tokenizer;
+use crate::metadata::{AssociatedFileType, ModelMetadata};
+use crate::{Error, FlatBufferModel, Result};
+
+/// BERT's tokenizer: basic splitting on whitespace and punctuation, then greedy
+/// longest-match-first WordPiece with `##` continuation pieces.
+///
+/// Unlike the reference implementation, accents are not stripped when lowercasing, since
+/// that requires Unicode decomposition tables; uncased models may see `[UNK]` for accented
+/// words.
+#[derive(Clone, Debug)]
+pub struct WordPieceTokenizer {
+    vocab: HashMap<String, i32>,
+    lowercase: bool,
+    unknown: String,
+    max_word_chars: usize,
+}
+
+impl WordPieceTokenizer {
+    /// Takes a `vocab.txt` with one token per line; the line number is the token id.
+    pub fn from_vocab(vocab: &str, lowercase: bool) -> Self {
+        let vocab = vocab
+            .lines()
+            .enumerate()
+            .map(|(id, token)| (token.trim_end().to_string(), id as i32))
+            .collect();
+        Self { vocab, lowercase, unknown: "[UNK]".to_string(), max_word_chars: 100 }
+    }
+
+    /// Loads the vocabulary bundled with `model` through its metadata.
+    pub fn from_metadata(model: &FlatBufferModel, lowercase: bool) -> Result<Self> {
+        let metadata = ModelMetadata::from_flatbuffer_model(model)?
+            .ok_or_else(|| Error::internal_error("model has no metadata"))?;
+        // The vocabulary is usually that of the tokenizer of the inputs, but may be declared
+        // anywhere.
+        let file = metadata
+            .find_associated_file(AssociatedFileType::Vocabulary)
+            .ok_or_else(|| Error::internal_error("model metadata declares no vocabulary"))?;
+        let data = metadata.read_associated_file(model.buffer(), file)?;
+        Ok(Self::from_vocab(&String::from_utf8_lossy(data), lowercase))
+    }
+
+    pub fn with_unknown_token(mut self, token: &str) -> Self {
+        self.unknown = token.to_string();
+        self
+    }
+
+    /// Splits on whitespace, around punctuation and around CJK characters, which BERT
+    /// treats as words of their own.
+    fn basic_tokens(&self, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        for c in text.chars() {
+            if c == '\u{0}' || c == '\u{fffd}' || (c.is_control() && !c.is_whitespace()) {
+                continue;
+            }
+            if c.is_whitespace() {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            } else if is_punctuation(c) || is_cjk(c) {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                words.push(c.to_string());
+            } else if self.lowercase {
+                word.extend(c.to_lowercase());
+            } else {
+                word.push(c);
+            }
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+        words
+    }
+
+    fn word_pieces(&self, word: &str, pieces: &mut Vec<String>) {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.len() > self.max_word_chars {
+            pieces.push(self.unknown.clone());
+            return;
+        }
+        let start_len = pieces.len();
+        let mut start = 0;
+        while start < chars.len() {
+            let piece = (start + 1..=chars.len()).rev().find_map(|end| {
+                let mut piece: String = chars[start..end].iter().collect();
+                if start > 0 {
+                    piece.insert_str(0, "##");
+                }
+                if self.vocab.contains_key(&piece) {
+                    Some((piece, end))
+                } else {
+                    None
+                }
+            });
+            match piece {
+                Some((piece, end)) => {
+                    pieces.push(piece);
+                    start = end;
+                }
+                None => {
+                    pieces.truncate(start_len);
+                    pieces.push(self.unknown.clone());
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation()
+        || (!c.is_ascii() && !c.is_alphanumeric() && !c.is_whitespace() && !c.is_control())
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x4E00..=0x9FFF
+        | 0x3400..=0x4DBF
+        | 0x20000..=0x2A6DF
+        | 0x2A700..=0x2B73F
+        | 0x2B740..=0x2B81F
+        | 0x2B820..=0x2CEAF
+        | 0xF900..=0xFAFF
+        | 0x2F800..=0x2FA1F)
+}
+
+impl Tokenizer for WordPieceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        let mut pieces = Vec::new();
+        for word in self.basic_tokens(text) {
+            self.word_pieces(&word, &mut pieces);
+        }
+        pieces
+    }
+
+    fn token_id(&self, token: &str) -> Option<i32> {
+        self.vocab.get(token).copied()
+    }
+
+    fn unknown_id(&self) -> Option<i32> {
+        self.token_id(&self.unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_wordpiece() {
+        let vocab = "[PAD]\n[UNK]\n[CLS]\n[SEP]\nun\n##want\n##ed\nwant\n,\nrunning\n##s\n中\n";
+        let tokenizer = WordPieceTokenizer::from_vocab(vocab, true);
+        assert_eq!(
+            tokenizer.tokenize("UNwanted,running\tRunnings 中x"),
+            vec!["un", "##want", "##ed", ",", "running", "running", "##s", "中", "[UNK]"]
+        );
+        assert_eq!(tokenizer.encode("unwanted wantX"), vec![4, 5, 6, 1]);
+        assert_eq!(tokenizer.token_id("[SEP]"), Some(3));
+    }
+}
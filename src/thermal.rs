@@ -0,0 +1,226 @@
+//! Thermal zones and sustained throughput, for benchmarks on devices that throttle.
+//!
+//! Short bursts run at the boost clock; after tens of seconds mobile SoCs throttle and
+//! throughput can drop by half. Measure both, starting from a cool device:
+//!
+//! ```ignore
+//! let monitor = ThermalMonitor::detect()?;
+//! monitor.cool_down(45.0, Duration::from_secs(300))?;
+//! let trace = ThroughputTrace::record(Duration::from_secs(60), Duration::from_secs(1), Some(&monitor), || {
+//!     interpreter.invoke()
+//! })?;
+//! println!("peak {:.1}/s, sustained {:.1}/s", trace.peak(), trace.sustained());
+//! ```
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Error, Result};
+
+/// A Linux thermal zone (`/sys/class/thermal/thermal_zone<N>`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThermalZone {
+    /// Sensor type, e.g. `x86_pkg_temp` or `cpu-thermal`.
+    pub kind: String,
+    path: PathBuf,
+}
+
+impl ThermalZone {
+    pub const SYSFS: &'static str = "/sys/class/thermal";
+
+    pub fn all() -> Result<Vec<Self>> {
+        let mut zones: Vec<Self> = fs::read_dir(Self::SYSFS)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("thermal_zone"))
+            })
+            .map(|path| Self {
+                kind: fs::read_to_string(path.join("type"))
+                    .map(|kind| kind.trim().to_string())
+                    .unwrap_or_default(),
+                path: path.join("temp"),
+            })
+            .collect();
+        zones.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(zones)
+    }
+
+    /// Temperature in degrees Celsius.
+    pub fn temperature(&self) -> Result<f64> {
+        let millidegrees: i64 = fs::read_to_string(&self.path)?.trim().parse().map_err(|e| {
+            Error::InternalError(format!("unexpected content of {}: {}", self.path.display(), e))
+        })?;
+        Ok(millidegrees as f64 / 1000.0)
+    }
+}
+
+/// The hottest of a set of thermal zones.
+#[derive(Clone, Debug)]
+pub struct ThermalMonitor {
+    zones: Vec<ThermalZone>,
+}
+
+impl ThermalMonitor {
+    /// Monitors all readable thermal zones.
+    pub fn detect() -> Result<Self> {
+        let zones: Vec<ThermalZone> =
+            ThermalZone::all()?.into_iter().filter(|zone| zone.temperature().is_ok()).collect();
+        if zones.is_empty() {
+            return Err(Error::internal_error("no readable thermal zones found"));
+        }
+        Ok(Self { zones })
+    }
+
+    pub fn zones(&self) -> &[ThermalZone] {
+        &self.zones
+    }
+
+    /// Highest temperature of all zones in degrees Celsius.
+    pub fn max_temperature(&self) -> Result<f64> {
+        self.zones.iter().try_fold(f64::NEG_INFINITY, |max, zone| Ok(max.max(zone.temperature()?)))
+    }
+
+    /// Sleeps until all zones are below `celsius`, returning the time waited, or fails after
+    /// `timeout`.
+    pub fn cool_down(&self, celsius: f64, timeout: Duration) -> Result<Duration> {
+        let start = Instant::now();
+        loop {
+            let temperature = self.max_temperature()?;
+            if temperature < celsius {
+                return Ok(start.elapsed());
+            }
+            if start.elapsed() >= timeout {
+                return Err(Error::InternalError(format!(
+                    "still at {:.1}°C after cooling down for {:?}",
+                    temperature, timeout
+                )));
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+}
+
+/// Invocations completed in one window of a `ThroughputTrace`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThroughputWindow {
+    /// Offset of the end of the window from the start of the trace.
+    pub end: Duration,
+    pub invocations: usize,
+    pub elapsed: Duration,
+    /// Highest zone temperature at the end of the window, if monitored.
+    pub temperature: Option<f64>,
+}
+
+impl ThroughputWindow {
+    /// Invocations per second.
+    pub fn throughput(&self) -> f64 {
+        self.invocations as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Throughput over time of back-to-back invocations.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThroughputTrace {
+    pub windows: Vec<ThroughputWindow>,
+}
+
+impl ThroughputTrace {
+    /// Calls `invoke` back to back for `duration`, recording throughput per `window`.
+    pub fn record<F>(
+        duration: Duration,
+        window: Duration,
+        monitor: Option<&ThermalMonitor>,
+        mut invoke: F,
+    ) -> Result<Self>
+    where
+        F: FnMut() -> Result<()>,
+    {
+        let start = Instant::now();
+        let mut trace = Self::default();
+        while start.elapsed() < duration {
+            let window_start = Instant::now();
+            let mut invocations = 0;
+            while window_start.elapsed() < window {
+                invoke()?;
+                invocations += 1;
+            }
+            trace.windows.push(ThroughputWindow {
+                end: start.elapsed(),
+                invocations,
+                elapsed: window_start.elapsed(),
+                temperature: monitor.map(ThermalMonitor::max_temperature).transpose()?,
+            });
+        }
+        Ok(trace)
+    }
+
+    /// Highest throughput of any window.
+    pub fn peak(&self) -> f64 {
+        self.windows.iter().map(ThroughputWindow::throughput).fold(0.0, f64::max)
+    }
+
+    /// Throughput over the last third of the trace, once throttling has settled.
+    pub fn sustained(&self) -> f64 {
+        let tail = &self.windows[self.windows.len() - self.windows.len().div_ceil(3)..];
+        let invocations: usize = tail.iter().map(|window| window.invocations).sum();
+        let elapsed: Duration = tail.iter().map(|window| window.elapsed).sum();
+        if elapsed.is_zero() {
+            0.0
+        } else {
+            invocations as f64 / elapsed.as_secs_f64()
+        }
+    }
+
+    pub fn max_temperature(&self) -> Option<f64> {
+        self.windows.iter().filter_map(|window| window.temperature).reduce(f64::max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_throughput_trace() {
+        let window = |invocations, temperature| ThroughputWindow {
+            end: Duration::default(),
+            invocations,
+            elapsed: Duration::from_secs(1),
+            temperature,
+        };
+        let trace = ThroughputTrace {
+            windows: vec![
+                window(100, Some(40.0)),
+                window(90, Some(55.0)),
+                window(60, Some(70.0)),
+                window(50, None),
+                window(50, Some(68.0)),
+            ],
+        };
+        assert_eq!(trace.peak(), 100.0);
+        assert_eq!(trace.sustained(), 50.0);
+        assert_eq!(trace.max_temperature(), Some(70.0));
+        assert_eq!(ThroughputTrace::default().sustained(), 0.0);
+
+        let mut calls = 0;
+        let trace = ThroughputTrace::record(
+            Duration::from_millis(30),
+            Duration::from_millis(10),
+            None,
+            || {
+                calls += 1;
+                thread::sleep(Duration::from_millis(1));
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert!(trace.windows.len() >= 3);
+        assert_eq!(trace.windows.iter().map(|w| w.invocations).sum::<usize>(), calls);
+        assert!(trace.max_temperature().is_none());
+    }
+}
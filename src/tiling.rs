@@ -0,0 +1,280 @@
+//! Running image models on images larger than their input, tile by tile.
+//!
+//! The image is split into overlapping tiles of the input size, each tile is invoked
+//! separately and the outputs are stitched back together. Where tiles overlap, outputs are
+//! blended with weights that fall off linearly towards the tile edges, hiding the seams
+//! caused by the missing context at tile borders:
+//!
+//! ```ignore
+//! // A 4x super-resolution model with a [1, 128, 128, 3] input.
+//! let (upscaled, (height, width, channels)) =
+//!     tiling::run_tiled(&mut interpreter, &image, (1080, 1920, 3), 16)?;
+//! ```
+//!
+//! Segmentation outputs are blended as logits; take the argmax after stitching.
+
+use crate::context::{ElemKindOf, ElementKind};
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result};
+
+/// Top-left corner of a tile in input pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub y: usize,
+    pub x: usize,
+}
+
+/// Tiles covering an image, overlapping by at least `overlap` pixels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileGrid {
+    pub image: (usize, usize),
+    pub tile: (usize, usize),
+    pub overlap: usize,
+    pub tiles: Vec<Tile>,
+}
+
+/// Tile offsets along one axis. The last tile is aligned to the end of the image; an image
+/// smaller than a tile gets a single tile, padded at the end.
+fn offsets(length: usize, tile: usize, overlap: usize) -> Vec<usize> {
+    if length <= tile {
+        return vec![0];
+    }
+    let step = tile - overlap;
+    let count = (length - overlap).div_ceil(step);
+    (0..count).map(|i| (i * step).min(length - tile)).collect()
+}
+
+impl TileGrid {
+    /// Tiles of `tile` (height, width) covering `image` (height, width).
+    pub fn new(image: (usize, usize), tile: (usize, usize), overlap: usize) -> Result<Self> {
+        if image.0 == 0 || image.1 == 0 || tile.0 == 0 || tile.1 == 0 {
+            return Err(Error::internal_error("image and tile sizes must be positive"));
+        }
+        if overlap >= tile.0 || overlap >= tile.1 {
+            return Err(Error::InternalError(format!(
+                "overlap {} must be smaller than the {}x{} tile",
+                overlap, tile.0, tile.1
+            )));
+        }
+        let ys = offsets(image.0, tile.0, overlap);
+        let xs = offsets(image.1, tile.1, overlap);
+        let tiles = ys.iter().flat_map(|&y| xs.iter().map(move |&x| Tile { y, x })).collect();
+        Ok(Self { image, tile, overlap, tiles })
+    }
+
+    /// Copies the pixels of `tile` from `image` (row-major, `channels` interleaved) into
+    /// `out`, replicating the last row and column where the tile extends past the image.
+    pub fn extract<T: Copy>(&self, image: &[T], channels: usize, tile: Tile, out: &mut [T]) {
+        let (height, width) = self.image;
+        let (tile_height, tile_width) = self.tile;
+        assert_eq!(image.len(), height * width * channels);
+        assert_eq!(out.len(), tile_height * tile_width * channels);
+        for ty in 0..tile_height {
+            let y = (tile.y + ty).min(height - 1);
+            for tx in 0..tile_width {
+                let x = (tile.x + tx).min(width - 1);
+                let src = (y * width + x) * channels;
+                let dst = (ty * tile_width + tx) * channels;
+                out[dst..dst + channels].copy_from_slice(&image[src..src + channels]);
+            }
+        }
+    }
+}
+
+/// Blending weight of position `p` in a tile of `size` whose edges fade over `ramp`.
+fn ramp_weight(p: usize, size: usize, ramp: usize) -> f32 {
+    if ramp == 0 {
+        return 1.0;
+    }
+    let from_edge = p.min(size - 1 - p) as f32 + 0.5;
+    (from_edge / ramp as f32).min(1.0)
+}
+
+/// Accumulates tile outputs into one output image with overlap blending.
+#[derive(Clone, Debug)]
+pub struct Stitcher {
+    grid: TileGrid,
+    /// Output pixels per input pixel, e.g. 4 for 4x super-resolution.
+    scale: usize,
+    channels: usize,
+    sum: Vec<f32>,
+    weight: Vec<f32>,
+}
+
+impl Stitcher {
+    pub fn new(grid: TileGrid, scale: usize, channels: usize) -> Self {
+        let pixels = grid.image.0 * scale * grid.image.1 * scale;
+        Self { grid, scale, channels, sum: vec![0.0; pixels * channels], weight: vec![0.0; pixels] }
+    }
+
+    /// Output dims (height, width, channels).
+    pub fn dims(&self) -> (usize, usize, usize) {
+        (self.grid.image.0 * self.scale, self.grid.image.1 * self.scale, self.channels)
+    }
+
+    /// Adds the output of `tile`, of the tile size times `scale`.
+    pub fn add(&mut self, tile: Tile, output: &[f32]) {
+        let (height, width, channels) = self.dims();
+        let (tile_height, tile_width) =
+            (self.grid.tile.0 * self.scale, self.grid.tile.1 * self.scale);
+        let ramp = self.grid.overlap * self.scale;
+        assert_eq!(output.len(), tile_height * tile_width * channels);
+        for ty in 0..tile_height {
+            let y = tile.y * self.scale + ty;
+            if y >= height {
+                break;
+            }
+            let wy = ramp_weight(ty, tile_height, ramp);
+            for tx in 0..tile_width {
+                let x = tile.x * self.scale + tx;
+                if x >= width {
+                    break;
+                }
+                let w = wy * ramp_weight(tx, tile_width, ramp);
+                let pixel = y * width + x;
+                self.weight[pixel] += w;
+                let src = (ty * tile_width + tx) * channels;
+                for (sum, &value) in self.sum[pixel * channels..(pixel + 1) * channels]
+                    .iter_mut()
+                    .zip(&output[src..src + channels])
+                {
+                    *sum += w * value;
+                }
+            }
+        }
+    }
+
+    /// The blended output, row-major with interleaved channels.
+    pub fn finish(self) -> Vec<f32> {
+        let channels = self.channels;
+        let mut output = self.sum;
+        for (pixel, &weight) in output.chunks_exact_mut(channels).zip(&self.weight) {
+            if weight > 0.0 {
+                pixel.iter_mut().for_each(|value| *value /= weight);
+            }
+        }
+        output
+    }
+}
+
+/// Runs `interpreter` over `image` of dims (height, width, channels) in tiles of its first
+/// input's size, overlapping by `overlap` input pixels, and stitches its first output.
+///
+/// The input must have dims `[1, tile_height, tile_width, channels]` and the type of `T`;
+/// the output `[1, tile_height * s, tile_width * s, out_channels]` for an integer scale `s`
+/// and type `f32`, `u8` or `i8` (blended as raw values). Returns the stitched output and
+/// its dims.
+pub fn run_tiled<T, Op>(
+    interpreter: &mut Interpreter<'_, Op>,
+    image: &[T],
+    dims: (usize, usize, usize),
+    overlap: usize,
+) -> Result<(Vec<f32>, (usize, usize, usize))>
+where
+    T: ElemKindOf + Copy,
+    Op: OpResolver,
+{
+    let (height, width, channels) = dims;
+    if image.len() != height * width * channels {
+        return Err(Error::InternalError(format!(
+            "image of {} values does not match dims {:?}",
+            image.len(),
+            dims
+        )));
+    }
+    let input = *interpreter
+        .inputs()
+        .first()
+        .ok_or_else(|| Error::internal_error("model has no inputs"))?;
+    let output = *interpreter
+        .outputs()
+        .first()
+        .ok_or_else(|| Error::internal_error("model has no outputs"))?;
+    let input_dims = interpreter.tensor_info(input).map(|info| info.dims).unwrap_or_default();
+    let output_info =
+        interpreter.tensor_info(output).ok_or_else(|| Error::internal_error("no output"))?;
+    let output_dims = &output_info.dims;
+    if input_dims.len() != 4 || input_dims[0] != 1 || input_dims[3] != channels {
+        return Err(Error::InternalError(format!(
+            "input dims {:?} are not [1, height, width, {}]",
+            input_dims, channels
+        )));
+    }
+    let tile = (input_dims[1], input_dims[2]);
+    let scale = output_dims.get(1).copied().unwrap_or(0) / tile.0;
+    if output_dims.len() != 4
+        || output_dims[0] != 1
+        || scale == 0
+        || output_dims[1] != tile.0 * scale
+        || output_dims[2] != tile.1 * scale
+    {
+        return Err(Error::InternalError(format!(
+            "output dims {:?} are not an integer multiple of the {}x{} tile",
+            output_dims, tile.0, tile.1
+        )));
+    }
+
+    let grid = TileGrid::new((height, width), tile, overlap)?;
+    let mut stitcher = Stitcher::new(grid.clone(), scale, output_dims[3]);
+    let mut values = Vec::new();
+    for &tile in &grid.tiles {
+        grid.extract(image, channels, tile, interpreter.tensor_data_mut::<T>(input)?);
+        interpreter.invoke()?;
+        values.clear();
+        match output_info.element_kind {
+            ElementKind::kTfLiteFloat32 => {
+                values.extend_from_slice(interpreter.tensor_data::<f32>(output)?)
+            }
+            ElementKind::kTfLiteUInt8 => {
+                values.extend(interpreter.tensor_data::<u8>(output)?.iter().map(|&v| f32::from(v)))
+            }
+            ElementKind::kTfLiteInt8 => values.extend(
+                interpreter
+                    .tensor_buffer(output)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|&v| f32::from(v as i8)),
+            ),
+            kind => {
+                return Err(Error::InternalError(format!("unsupported output type {:?}", kind)))
+            }
+        }
+        stitcher.add(tile, &values);
+    }
+    let dims = stitcher.dims();
+    Ok((stitcher.finish(), dims))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_tiling() {
+        assert_eq!(offsets(10, 4, 1), vec![0, 3, 6]);
+        assert_eq!(offsets(11, 4, 1), vec![0, 3, 6, 7]);
+        assert_eq!(offsets(3, 4, 1), vec![0]);
+        assert!(TileGrid::new((8, 8), (4, 4), 4).is_err());
+
+        // A 2x "upscaling" identity: every tile output repeats its input pixels.
+        let (height, width) = (7, 9);
+        let image: Vec<f32> = (0..height * width).map(|v| v as f32).collect();
+        let grid = TileGrid::new((height, width), (4, 4), 2).unwrap();
+        let mut stitcher = Stitcher::new(grid.clone(), 2, 1);
+        let mut tile_input = vec![0.0; 16];
+        for &tile in &grid.tiles {
+            grid.extract(&image, 1, tile, &mut tile_input);
+            let output: Vec<f32> =
+                (0..64).map(|i| tile_input[(i / 8 / 2) * 4 + (i % 8) / 2]).collect();
+            stitcher.add(tile, &output);
+        }
+        assert_eq!(stitcher.dims(), (14, 18, 1));
+        let output = stitcher.finish();
+        for y in 0..14 {
+            for x in 0..18 {
+                let expected = image[(y / 2) * width + x / 2];
+                assert!((output[y * 18 + x] - expected).abs() < 1e-4, "({}, {})", y, x);
+            }
+        }
+    }
+}
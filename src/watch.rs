@@ -0,0 +1,216 @@
+//! Reloading a served model when its file changes, e.g. after an over-the-air update:
+//!
+//! ```ignore
+//! let slot = Arc::new(InterpreterSlot::new(model, Arc::new(BuiltinOpResolver::default()))?);
+//! let _watcher = ModelWatcher::new("/data/models/detector.tflite")
+//!     .on_reload(|path, result| match result {
+//!         Ok(generation) => log::info!("{} is now generation {}", path.display(), generation),
+//!         Err(e) => log::warn!("kept the previous model, {} failed: {}", path.display(), e),
+//!     })
+//!     .watch(slot.clone());
+//! ```
+//!
+//! The file is polled, so it works on any file system. Replace it atomically (write a
+//! temporary file and rename it) where possible; a file that is written in place is only
+//! loaded once its size and modification time stopped changing for one interval.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::op_resolver::OpResolver;
+#[cfg(feature = "signing")]
+use crate::signing::{signature_path, ModelVerifier};
+use crate::{verify_model, Error, FlatBufferModel, InterpreterSlot, Result};
+
+type ReloadCallback = dyn Fn(&Path, &Result<u64>) + Send;
+
+/// Watches a model file and swaps it into an `InterpreterSlot` when it changes.
+pub struct ModelWatcher {
+    path: PathBuf,
+    interval: Duration,
+    on_reload: Option<Box<ReloadCallback>>,
+    #[cfg(feature = "signing")]
+    verifier: Option<ModelVerifier>,
+}
+
+/// A running `ModelWatcher`; dropping it stops watching.
+pub struct WatchHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// Size and modification time of a file.
+type Stamp = (u64, Option<SystemTime>);
+
+/// Decides when a changed file is ready to load.
+struct Poller {
+    path: PathBuf,
+    // The version last loaded or rejected; a rejected file is retried once it changes again.
+    current: Option<Stamp>,
+    pending: Option<Stamp>,
+}
+
+impl Poller {
+    fn new(path: PathBuf) -> Self {
+        let mut poller = Self { path, current: None, pending: None };
+        poller.current = poller.stamp();
+        poller
+    }
+
+    fn stamp(&self) -> Option<Stamp> {
+        let metadata = fs::metadata(&self.path).ok()?;
+        Some((metadata.len(), metadata.modified().ok()))
+    }
+
+    /// Whether the file changed and stayed unchanged since the previous poll.
+    fn poll(&mut self) -> bool {
+        // A missing file is being replaced.
+        let stamp = match self.stamp() {
+            Some(stamp) if Some(stamp) != self.current => stamp,
+            _ => {
+                self.pending = None;
+                return false;
+            }
+        };
+        if self.pending == Some(stamp) {
+            self.current = Some(stamp);
+            self.pending = None;
+            true
+        } else {
+            self.pending = Some(stamp);
+            false
+        }
+    }
+}
+
+impl ModelWatcher {
+    /// Reads and verifies the model, so that a truncated, corrupted or unsigned update is
+    /// rejected before TF Lite parses it.
+    fn load(&self) -> Result<FlatBufferModel> {
+        let path = &self.path;
+        let bytes = fs::read(path)?;
+        #[cfg(feature = "signing")]
+        {
+            if let Some(verifier) = &self.verifier {
+                let signature = fs::read(signature_path(path)).map_err(|e| {
+                    Error::SignatureRejected(format!("no signature of {}: {}", path.display(), e))
+                })?;
+                verifier.verify(&bytes, &signature)?;
+            }
+        }
+        verify_model(&bytes).map_err(|e| {
+            Error::InternalError(format!("{} is not a valid model: {}", path.display(), e))
+        })?;
+        FlatBufferModel::build_from_buffer(bytes)
+    }
+}
+
+impl ModelWatcher {
+    pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            interval: Self::DEFAULT_INTERVAL,
+            on_reload: None,
+            #[cfg(feature = "signing")]
+            verifier: None,
+        }
+    }
+
+    /// How often the file is checked. A change is loaded after two checks saw it unchanged.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Called after each reload attempt with the new generation of the slot, or why the
+    /// update was rejected and the previous model kept serving.
+    pub fn on_reload<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Path, &Result<u64>) + Send + 'static,
+    {
+        self.on_reload = Some(Box::new(callback));
+        self
+    }
+
+    /// Only loads updates signed by a key of `verifier`, with the signature next to the model
+    /// (see `signing::signature_path`). Replace the signature before the model.
+    #[cfg(feature = "signing")]
+    pub fn with_verifier(mut self, verifier: ModelVerifier) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Starts watching on a new thread. Updates are verified, then built and set up by
+    /// `slot.swap_model`; if any step fails the slot keeps serving its current model.
+    pub fn watch<Op>(self, slot: Arc<InterpreterSlot<Op>>) -> WatchHandle
+    where
+        Op: OpResolver + Clone + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            let mut poller = Poller::new(self.path.clone());
+            while stopped.recv_timeout(self.interval) == Err(RecvTimeoutError::Timeout) {
+                if !poller.poll() {
+                    continue;
+                }
+                let result = self.load().and_then(|model| slot.swap_model(model));
+                if let Some(callback) = &self.on_reload {
+                    callback(&self.path, &result);
+                }
+            }
+        });
+        WatchHandle { stop: Some(stop), thread: Some(thread) }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_model_watcher() {
+        let dir = std::env::temp_dir().join(format!("tflite-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.tflite");
+        fs::write(&path, b"first").unwrap();
+
+        let mut poller = Poller::new(path.clone());
+        assert!(!poller.poll());
+        fs::write(&path, b"second version").unwrap();
+        // Loaded once it stopped changing.
+        assert!(!poller.poll());
+        assert!(poller.poll());
+        assert!(!poller.poll());
+        fs::remove_file(&path).unwrap();
+        assert!(!poller.poll());
+        fs::write(&path, b"third version").unwrap();
+        assert!(!poller.poll());
+        assert!(poller.poll());
+
+        assert!(ModelWatcher::new(&path).load().is_err());
+        assert!(ModelWatcher::new(dir.join("missing.tflite")).load().is_err());
+        #[cfg(feature = "signing")]
+        {
+            let path = Path::new("data/MNISTnet_uint8_quant.tflite");
+            let watcher =
+                ModelWatcher::new(path).with_verifier(ModelVerifier::new(&[0; 32]).unwrap());
+            assert!(matches!(watcher.load(), Err(Error::SignatureRejected(_))));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,339 @@
+//! Running 1-D signal models over a stream in sliding windows.
+//!
+//! ```ignore
+//! // A model classifying 2 s of 3-axis accelerometer data at 50 Hz, every 0.5 s.
+//! let config = WindowConfig::new(100, 25).with_normalization(WindowNormalization::ZScore);
+//! let mut runner = WindowedRunner::new(interpreter, config, 3)?;
+//! for chunk in sensor_chunks {
+//!     for output in runner.push(&chunk)? {
+//!         println!("window at sample {}: {:?}", output.start, output.values);
+//!     }
+//! }
+//! // Score the tail, zero-padded, then start over for the next recording.
+//! runner.flush()?;
+//! runner.reset()?;
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::context::{element_count, ElementKind};
+use crate::op_resolver::OpResolver;
+use crate::{Error, Interpreter, Result, TensorIndex};
+
+/// Normalization applied to each window, per channel, before it is written to the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowNormalization {
+    None,
+    /// Subtract the mean and divide by the standard deviation (plus `epsilon`).
+    ZScore,
+    /// Scale to `[0, 1]`; constant channels become 0.
+    MinMax,
+}
+
+/// What `flush` does with samples not covered by a full window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TailPolicy {
+    /// Discard them.
+    Drop,
+    /// Emit one last window padded with zeros (before normalization).
+    ZeroPad,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig {
+    /// Window length in samples (frames of all channels).
+    pub size: usize,
+    /// Samples between the starts of consecutive windows.
+    pub hop: usize,
+    pub normalization: WindowNormalization,
+    pub tail: TailPolicy,
+    pub epsilon: f32,
+}
+
+impl WindowConfig {
+    pub fn new(size: usize, hop: usize) -> Self {
+        Self {
+            size,
+            hop,
+            normalization: WindowNormalization::None,
+            tail: TailPolicy::ZeroPad,
+            epsilon: 1e-6,
+        }
+    }
+
+    pub fn with_normalization(mut self, normalization: WindowNormalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    pub fn with_tail(mut self, tail: TailPolicy) -> Self {
+        self.tail = tail;
+        self
+    }
+}
+
+/// A window cut from the stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    /// Index of the first sample in the stream since the last reset.
+    pub start: usize,
+    /// `size * channels` values, interleaved, normalized.
+    pub values: Vec<f32>,
+}
+
+/// Cuts an interleaved multi-channel stream into normalized windows.
+#[derive(Clone, Debug)]
+pub struct SlidingWindow {
+    config: WindowConfig,
+    channels: usize,
+    buffer: VecDeque<f32>,
+    // Stream index of the first buffered sample.
+    buffer_start: usize,
+    // Stream index of the next window.
+    next_start: usize,
+    // Samples received since the last reset.
+    received: usize,
+    // End of the last emitted window.
+    covered: usize,
+}
+
+impl SlidingWindow {
+    pub fn new(config: WindowConfig, channels: usize) -> Result<Self> {
+        if config.size == 0 || config.hop == 0 || channels == 0 {
+            return Err(Error::internal_error("window size, hop and channels must be positive"));
+        }
+        Ok(Self {
+            config,
+            channels,
+            buffer: VecDeque::new(),
+            buffer_start: 0,
+            next_start: 0,
+            received: 0,
+            covered: 0,
+        })
+    }
+
+    pub fn config(&self) -> &WindowConfig {
+        &self.config
+    }
+
+    /// Appends interleaved `samples` and returns the windows completed by them.
+    pub fn push(&mut self, samples: &[f32]) -> Result<Vec<Window>> {
+        let frames = samples.len() / self.channels;
+        if frames * self.channels != samples.len() {
+            return Err(Error::InternalError(format!(
+                "{} values are not whole samples of {} channels",
+                samples.len(),
+                self.channels
+            )));
+        }
+        self.buffer.extend(samples);
+        self.received += frames;
+
+        let mut windows = Vec::new();
+        while self.next_start + self.config.size <= self.received {
+            windows.push(self.cut(self.config.size));
+            self.covered = self.next_start + self.config.size;
+            self.advance();
+        }
+        Ok(windows)
+    }
+
+    /// Returns the last, zero-padded window over samples no full window covered yet,
+    /// depending on `TailPolicy`, and restarts windowing after them.
+    pub fn flush(&mut self) -> Option<Window> {
+        let pending = self.received.saturating_sub(self.next_start);
+        let window = if pending == 0
+            || self.received <= self.covered
+            || self.config.tail == TailPolicy::Drop
+        {
+            None
+        } else {
+            Some(self.cut(pending))
+        };
+        self.buffer.clear();
+        self.buffer_start = self.received;
+        self.next_start = self.received;
+        self.covered = self.received;
+        window
+    }
+
+    /// Forgets all buffered samples and restarts stream indices at 0.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.buffer_start = 0;
+        self.next_start = 0;
+        self.received = 0;
+        self.covered = 0;
+    }
+
+    /// The window at `next_start` with `available` samples, zero-padded to the window size.
+    fn cut(&self, available: usize) -> Window {
+        let offset = (self.next_start - self.buffer_start) * self.channels;
+        let mut values: Vec<f32> =
+            self.buffer.iter().skip(offset).take(available * self.channels).copied().collect();
+        values.resize(self.config.size * self.channels, 0.0);
+        normalize(&mut values, self.channels, self.config.normalization, self.config.epsilon);
+        Window { start: self.next_start, values }
+    }
+
+    fn advance(&mut self) {
+        self.next_start += self.config.hop;
+        let drop = (self.next_start.min(self.received) - self.buffer_start) * self.channels;
+        self.buffer.drain(..drop);
+        self.buffer_start = self.next_start.min(self.received);
+    }
+}
+
+fn normalize(
+    values: &mut [f32],
+    channels: usize,
+    normalization: WindowNormalization,
+    epsilon: f32,
+) {
+    let frames = values.len() / channels;
+    for channel in 0..channels {
+        let channel_values = || values.iter().skip(channel).step_by(channels);
+        let (a, b) = match normalization {
+            WindowNormalization::None => return,
+            WindowNormalization::ZScore => {
+                let mean = channel_values().sum::<f32>() / frames as f32;
+                let variance =
+                    channel_values().map(|v| (v - mean) * (v - mean)).sum::<f32>() / frames as f32;
+                (mean, variance.sqrt() + epsilon)
+            }
+            WindowNormalization::MinMax => {
+                let min = channel_values().copied().fold(f32::INFINITY, f32::min);
+                let max = channel_values().copied().fold(f32::NEG_INFINITY, f32::max);
+                (min, if max > min { max - min } else { f32::INFINITY })
+            }
+        };
+        values.iter_mut().skip(channel).step_by(channels).for_each(|v| *v = (*v - a) / b);
+    }
+}
+
+/// The first output of an invocation on one window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowOutput {
+    /// Index of the first sample of the window in the stream.
+    pub start: usize,
+    pub values: Vec<f32>,
+}
+
+/// Feeds the windows of a stream to an interpreter whose first input is `f32` with
+/// `size * channels` elements, e.g. `[1, size, channels]`, and whose first output is `f32`.
+pub struct WindowedRunner<'a, Op>
+where
+    Op: OpResolver,
+{
+    interpreter: Interpreter<'a, Op>,
+    input: TensorIndex,
+    output: TensorIndex,
+    window: SlidingWindow,
+}
+
+impl<'a, Op> WindowedRunner<'a, Op>
+where
+    Op: OpResolver,
+{
+    pub fn new(
+        interpreter: Interpreter<'a, Op>,
+        config: WindowConfig,
+        channels: usize,
+    ) -> Result<Self> {
+        let input = *interpreter
+            .inputs()
+            .first()
+            .ok_or_else(|| Error::internal_error("model has no inputs"))?;
+        let output = *interpreter
+            .outputs()
+            .first()
+            .ok_or_else(|| Error::internal_error("model has no outputs"))?;
+        let info = interpreter
+            .tensor_info(input)
+            .ok_or_else(|| Error::InternalError(format!("no input tensor {}", input)))?;
+        let elements = element_count(&info.dims)?;
+        if info.element_kind != ElementKind::kTfLiteFloat32 || elements != config.size * channels {
+            return Err(Error::InternalError(format!(
+                "input {:?} of {:?} does not hold windows of {} samples of {} channels",
+                info.dims, info.element_kind, config.size, channels
+            )));
+        }
+        let window = SlidingWindow::new(config, channels)?;
+        Ok(Self { interpreter, input, output, window })
+    }
+
+    pub fn interpreter(&self) -> &Interpreter<'a, Op> {
+        &self.interpreter
+    }
+
+    pub fn into_interpreter(self) -> Interpreter<'a, Op> {
+        self.interpreter
+    }
+
+    /// Appends interleaved `samples` and invokes the model on every completed window.
+    pub fn push(&mut self, samples: &[f32]) -> Result<Vec<WindowOutput>> {
+        self.window.push(samples)?.into_iter().map(|window| self.run(window)).collect()
+    }
+
+    /// Invokes the model on the tail of the stream, see `SlidingWindow::flush`.
+    pub fn flush(&mut self) -> Result<Option<WindowOutput>> {
+        self.window.flush().map(|window| self.run(window)).transpose()
+    }
+
+    /// Starts a new stream: drops buffered samples and resets the model's variable
+    /// tensors, e.g. recurrent state.
+    pub fn reset(&mut self) -> Result<()> {
+        self.window.reset();
+        self.interpreter.reset_variable_tensors()
+    }
+
+    fn run(&mut self, window: Window) -> Result<WindowOutput> {
+        self.interpreter.tensor_data_mut::<f32>(self.input)?.copy_from_slice(&window.values);
+        self.interpreter.invoke()?;
+        Ok(WindowOutput {
+            start: window.start,
+            values: self.interpreter.tensor_data::<f32>(self.output)?.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_sliding_window() {
+        let mut window = SlidingWindow::new(WindowConfig::new(4, 2), 1).unwrap();
+        let starts = |windows: Vec<Window>| windows.iter().map(|w| w.start).collect::<Vec<_>>();
+        assert_eq!(starts(window.push(&[0.0, 1.0, 2.0]).unwrap()), vec![]);
+        let windows = window.push(&[3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert_eq!(starts(windows.clone()), vec![0, 2]);
+        assert_eq!(windows[1].values, vec![2.0, 3.0, 4.0, 5.0]);
+        let tail = window.flush().unwrap();
+        assert_eq!((tail.start, tail.values), (4, vec![4.0, 5.0, 6.0, 0.0]));
+        assert!(window.flush().is_none());
+        assert_eq!(starts(window.push(&[7.0; 4]).unwrap()), vec![7]);
+
+        // The tail was covered by the last window.
+        let mut window = SlidingWindow::new(WindowConfig::new(4, 2), 1).unwrap();
+        assert_eq!(starts(window.push(&[0.0; 4]).unwrap()), vec![0]);
+        assert!(window.flush().is_none());
+        window.reset();
+        assert!(window.push(&[0.0; 3]).unwrap().is_empty());
+
+        let config = WindowConfig::new(2, 2).with_normalization(WindowNormalization::ZScore);
+        let mut window = SlidingWindow::new(config, 2).unwrap();
+        assert!(window.push(&[1.0]).is_err());
+        let windows = window.push(&[1.0, 5.0, 3.0, 5.0]).unwrap();
+        let expected = [-1.0, 0.0, 1.0, 0.0];
+        assert!(windows[0].values.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-5));
+
+        let config = WindowConfig::new(3, 1)
+            .with_normalization(WindowNormalization::MinMax)
+            .with_tail(TailPolicy::Drop);
+        let mut window = SlidingWindow::new(config, 1).unwrap();
+        assert_eq!(window.push(&[2.0, 4.0, 6.0, 6.0]).unwrap()[0].values, vec![0.0, 0.5, 1.0]);
+        assert!(window.flush().is_none());
+    }
+}
@@ -0,0 +1,242 @@
+//! End-to-end example pipelines: preprocessing, running and post-processing, on the MNIST
+//! model of `data/` and on small detection and keyword spotting models built here, so that
+//! the models cannot drift. Run with `cargo test --features example_pipelines`.
+
+use std::ffi::CString;
+use std::fs::File;
+use std::io::Read;
+
+use tflite::metadata::NormalizationOptions;
+use tflite::model::stl::memory::UniquePtr;
+use tflite::model::stl::vector::{VectorInsert, VectorSlice};
+use tflite::model::{
+    BufferT, BuiltinOperator, BuiltinOptionsUnion, Model, OperatorCodeT, OperatorT,
+    SoftmaxOptionsT, SubGraphT, TensorT, TensorType,
+};
+use tflite::ops::builtin::BuiltinOpResolver;
+use tflite::postprocess::{BoundingBox, Classifier, DetectionPostProcess, Labels};
+use tflite::preprocess::{set_input_frame_with, Frame, ImagePreprocessing, PixelFormat};
+use tflite::windowing::{TailPolicy, WindowConfig, WindowNormalization, WindowedRunner};
+use tflite::{FlatBufferModel, InterpreterBuilder, Result};
+
+/// A `FULLY_CONNECTED` layer on the model input, optionally followed by `SOFTMAX`.
+struct Dense {
+    name: &'static str,
+    weights: Vec<f32>,
+    bias: Vec<f32>,
+    softmax: bool,
+}
+
+/// A float model of `Dense` heads on one input of dims `input`, with an output per head.
+fn dense_model(input: &[i32], heads: &[Dense]) -> Result<FlatBufferModel> {
+    let mut model = Model::default();
+    model.version = 3;
+    for &code in &[
+        BuiltinOperator::BuiltinOperator_FULLY_CONNECTED,
+        BuiltinOperator::BuiltinOperator_SOFTMAX,
+    ] {
+        let mut operator_code: UniquePtr<OperatorCodeT> = Default::default();
+        operator_code.builtin_code = code;
+        operator_code.version = 1;
+        model.operator_codes.push_back(operator_code);
+    }
+    // Buffer 0 is the empty buffer of non-constant tensors.
+    model.buffers.push_back(UniquePtr::<BufferT>::default());
+
+    let mut subgraph: UniquePtr<SubGraphT> = Default::default();
+    let mut add_tensor = |name: &str, shape: Vec<i32>, data: Option<&[f32]>| {
+        let mut tensor: UniquePtr<TensorT> = Default::default();
+        tensor.shape.assign(shape);
+        tensor.typ = TensorType::TensorType_FLOAT32;
+        tensor.name.assign(&CString::new(name).unwrap());
+        if let Some(data) = data {
+            let mut buffer: UniquePtr<BufferT> = Default::default();
+            buffer.data.assign(data.iter().flat_map(|v| v.to_le_bytes().to_vec()));
+            tensor.buffer = model.buffers.size() as u32;
+            model.buffers.push_back(buffer);
+        }
+        subgraph.tensors.push_back(tensor);
+        subgraph.tensors.size() as i32 - 1
+    };
+
+    let input_tensor = add_tensor("input", input.to_vec(), None);
+    let depth: i32 = input.iter().skip(1).product();
+    let mut layers = Vec::new();
+    let mut outputs = Vec::new();
+    for head in heads {
+        let units = head.bias.len() as i32;
+        let weights =
+            add_tensor(&format!("{}/weights", head.name), vec![units, depth], Some(&head.weights));
+        let bias = add_tensor(&format!("{}/bias", head.name), vec![units], Some(&head.bias));
+        let logits = add_tensor(&format!("{}/logits", head.name), vec![1, units], None);
+        layers.push((0, vec![input_tensor, weights, bias], logits));
+        if head.softmax {
+            let scores = add_tensor(head.name, vec![1, units], None);
+            layers.push((1, vec![logits], scores));
+            outputs.push(scores);
+        } else {
+            outputs.push(logits);
+        }
+    }
+    for (opcode_index, inputs, output) in layers {
+        let mut operator: UniquePtr<OperatorT> = Default::default();
+        operator.opcode_index = opcode_index;
+        operator.inputs.assign(inputs);
+        operator.outputs.assign(vec![output]);
+        operator.builtin_options = if opcode_index == 0 {
+            BuiltinOptionsUnion::FullyConnectedOptions()
+        } else {
+            let mut options = BuiltinOptionsUnion::SoftmaxOptions();
+            AsMut::<SoftmaxOptionsT>::as_mut(&mut options).beta = 1.0;
+            options
+        };
+        subgraph.operators.push_back(operator);
+    }
+    subgraph.inputs.assign(vec![input_tensor]);
+    subgraph.outputs.assign(outputs);
+    model.subgraphs.push_back(subgraph);
+    FlatBufferModel::build_from_model(&model)
+}
+
+/// Recognizes handwritten digits: grayscale frames in, labeled top-3 digits out.
+#[test]
+fn image_classification_pipeline() -> Result<()> {
+    let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite")?;
+    let mut interpreter = InterpreterBuilder::new(&model, BuiltinOpResolver::default())?.build()?;
+    interpreter.allocate_tensors()?;
+    let input = interpreter.inputs()[0];
+
+    let names = "zero\none\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\n";
+    let classifier = Classifier::new(Some(Labels::from_text(names)));
+    let preprocessing = ImagePreprocessing { grayscale: true, ..Default::default() };
+
+    let mut digits = File::open("data/mnist10.bin")?;
+    let mut digit = vec![0u8; 28 * 28];
+    for i in 0..10 {
+        digits.read_exact(&mut digit)?;
+        // As a camera would deliver it: gray pixels in RGB, which preprocessing turns back
+        // into the luma channel the model takes.
+        let rgb: Vec<u8> = digit.iter().flat_map(|&v| vec![v, v, v]).collect();
+        let frame = Frame::new(&rgb, 28, 28, PixelFormat::Rgb)?;
+        set_input_frame_with(&mut interpreter, input, &frame, &preprocessing)?;
+        interpreter.invoke()?;
+
+        let top = classifier.classify(&interpreter, 3)?;
+        assert_eq!(top.len(), 3);
+        assert_eq!(top[0].index, i);
+        assert_eq!(top[0].label.as_deref(), names.lines().nth(i));
+    }
+    Ok(())
+}
+
+/// Finds bright objects in a 4x4 image with an SSD-like head: one anchor per quadrant, box
+/// encodings and scores out, decoded and suppressed in Rust.
+#[test]
+fn object_detection_pipeline() -> Result<()> {
+    let anchors = [
+        [0.25, 0.25, 0.5, 0.5],
+        [0.25, 0.75, 0.5, 0.5],
+        [0.75, 0.25, 0.5, 0.5],
+        [0.75, 0.75, 0.5, 0.5],
+    ];
+    // The score of an anchor is the mean brightness of its quadrant.
+    let mut score_weights = vec![0.0; 4 * 16];
+    for pixel in 0..16 {
+        let (y, x) = (pixel / 4, pixel % 4);
+        score_weights[(y / 2 * 2 + x / 2) * 16 + pixel] = 0.25;
+    }
+    let model = dense_model(
+        &[1, 4, 4, 1],
+        &[
+            Dense {
+                name: "box_encodings",
+                weights: vec![0.0; 16 * 16],
+                bias: vec![0.0; 16],
+                softmax: false,
+            },
+            Dense { name: "scores", weights: score_weights, bias: vec![0.0; 4], softmax: false },
+        ],
+    )?;
+    let mut interpreter = InterpreterBuilder::new(model, BuiltinOpResolver::default())?.build()?;
+    interpreter.allocate_tensors()?;
+
+    // A white square in the top right quadrant.
+    let mut rgb = vec![0u8; 4 * 4 * 3];
+    for &pixel in &[2, 3, 6, 7] {
+        rgb[pixel * 3..pixel * 3 + 3].copy_from_slice(&[255, 255, 255]);
+    }
+    let preprocessing = ImagePreprocessing {
+        normalization: Some(NormalizationOptions { mean: vec![0.0], std: vec![255.0] }),
+        grayscale: true,
+    };
+    let input = interpreter.inputs()[0];
+    set_input_frame_with(
+        &mut interpreter,
+        input,
+        &Frame::new(&rgb, 4, 4, PixelFormat::Rgb)?,
+        &preprocessing,
+    )?;
+    interpreter.invoke()?;
+
+    let outputs = interpreter.outputs().to_vec();
+    let encodings: &[f32] = interpreter.tensor_data(outputs[0])?;
+    let scores: &[f32] = interpreter.tensor_data(outputs[1])?;
+    let boxes: Vec<BoundingBox> = encodings
+        .chunks(4)
+        .zip(&anchors)
+        .map(|(encoding, anchor)| {
+            BoundingBox::from_center_size(encoding, anchor, [10.0, 10.0, 5.0, 5.0])
+        })
+        .collect();
+    let postprocess =
+        DetectionPostProcess { num_classes: 1, score_threshold: 0.5, ..Default::default() };
+    let detections = postprocess.run(&boxes, scores)?;
+
+    assert_eq!(detections.len(), 1);
+    assert_eq!(detections[0].class, 0);
+    assert!((detections[0].score - 1.0).abs() < 1e-5);
+    let bbox = detections[0].bbox;
+    for (actual, expected) in
+        [bbox.ymin, bbox.xmin, bbox.ymax, bbox.xmax].iter().zip(&[0.0, 0.5, 0.5, 1.0])
+    {
+        assert!((actual - expected).abs() < 1e-5, "{:?}", bbox);
+    }
+    Ok(())
+}
+
+/// Spots a keyword, an alternating pattern, in a stream of audio samples scored in windows.
+#[test]
+fn keyword_spotting_pipeline() -> Result<()> {
+    let template: Vec<f32> = (0..8).map(|i| if i % 2 == 0 { 1.0 } else { -1.0 }).collect();
+    let weights = [vec![0.0; 8], template].concat();
+    let model = dense_model(
+        &[1, 8, 1],
+        &[Dense { name: "scores", weights, bias: vec![0.5, 0.0], softmax: true }],
+    )?;
+    let mut interpreter = InterpreterBuilder::new(model, BuiltinOpResolver::default())?.build()?;
+    interpreter.allocate_tensors()?;
+
+    let config = WindowConfig::new(8, 8)
+        .with_normalization(WindowNormalization::ZScore)
+        .with_tail(TailPolicy::Drop);
+    let mut runner = WindowedRunner::new(interpreter, config, 1)?;
+    let classifier = Classifier::new(Some(Labels::from_text("silence\nkeyword\n")));
+
+    // Background hum, then the keyword, then a partial window the tail policy drops.
+    let hum: Vec<f32> = (0..8).map(|i| 0.01 * i as f32).collect();
+    let keyword: Vec<f32> = (0..8).map(|i| if i % 2 == 0 { 0.3 } else { -0.3 }).collect();
+    let mut outputs = runner.push(&hum)?;
+    outputs.extend(runner.push(&keyword)?);
+    outputs.extend(runner.push(&[0.0; 3])?);
+    assert!(runner.flush()?.is_none());
+
+    let spotted: Vec<(usize, String)> = outputs
+        .iter()
+        .map(|output| {
+            let top = classifier.classify_scores(&output.values, 1);
+            (output.start, top[0].label.clone().unwrap())
+        })
+        .collect();
+    assert_eq!(spotted, vec![(0, "silence".to_string()), (8, "keyword".to_string())]);
+    Ok(())
+}
@@ -58,3 +58,98 @@ fn mobilenetv2_mnist() -> Result<()> {
     let buf = fs::read("data/MNISTnet_v2_uint8_quant.tflite")?;
     test_mnist(&FlatBufferModel::build_from_buffer(buf)?)
 }
+
+#[test]
+fn mnist_shared_allocation() -> Result<()> {
+    let model = FlatBufferModel::build(tflite::Mmap::open("data/MNISTnet_uint8_quant.tflite")?)?;
+    assert_eq!(model.allocation().kind(), tflite::AllocationKind::Mmap);
+    assert_eq!(model.native_allocation().as_ptr(), model.allocation().base());
+
+    let shared = FlatBufferModel::build(model.allocation().clone())?;
+    assert!(shared.allocation().ptr_eq(model.allocation()));
+    assert_eq!(shared.native_allocation().as_ptr(), model.allocation().base());
+    test_mnist(&model)?;
+    test_mnist(&shared)
+}
+
+#[test]
+fn mnist_reports_allocation_errors() -> Result<()> {
+    let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite")?;
+    let resolver = BuiltinOpResolver::default();
+    let mut interpreter = InterpreterBuilder::new(&model, &resolver)?.build()?;
+    let input = interpreter.inputs()[0];
+
+    // The first convolution expects a single channel.
+    interpreter.resize_input_tensor(input, &[1, 28, 28, 3])?;
+    let err = interpreter.allocate_tensors().unwrap_err().to_string();
+    assert!(err.starts_with("`failed to allocate tensors: "), "{}", err);
+
+    interpreter.resize_input_tensor(input, &[1, 28, 28, 1])?;
+    interpreter.allocate_tensors()?;
+    assert!(interpreter.take_diagnostics().diagnostics.is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "leak_tracking")]
+#[test]
+fn mnist_releases_native_objects() -> Result<()> {
+    tflite::leak_tracking::assert_balanced(|| {
+        test_mnist(&FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite")?)
+    })
+}
+
+#[cfg(feature = "macros")]
+#[tflite::tflite_model("data/MNISTnet_uint8_quant.tflite")]
+struct Mnist;
+
+#[cfg(feature = "macros")]
+#[test]
+fn mnist_typed_bindings() -> Result<()> {
+    let model = FlatBufferModel::build_from_buffer(Mnist::<BuiltinOpResolver>::MODEL.to_vec())?;
+    let resolver = BuiltinOpResolver::default();
+    let mut interpreter = InterpreterBuilder::new(&model, &resolver)?.build()?;
+    interpreter.allocate_tensors()?;
+    let mut mnist = Mnist::new(interpreter)?;
+
+    let mut input_file = File::open("data/mnist10.bin")?;
+    for i in 0..10 {
+        let mut image = [0u8; 784];
+        input_file.read_exact(&mut image)?;
+        mnist.set_input(&image)?;
+        mnist.invoke()?;
+
+        let output: &[u8; 10] = mnist.output();
+        let guess = output.iter().enumerate().max_by(|x, y| x.1.cmp(y.1)).unwrap().0;
+        assert_eq!(i, guess);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn mnist_ndarray_views() -> Result<()> {
+    use ndarray::{Array4, Axis};
+
+    let model = FlatBufferModel::build_from_file("data/MNISTnet_uint8_quant.tflite")?;
+    let resolver = BuiltinOpResolver::default();
+    let mut interpreter = InterpreterBuilder::new(&model, &resolver)?.build()?;
+    interpreter.allocate_tensors()?;
+    let (input, output) = (interpreter.inputs()[0], interpreter.outputs()[0]);
+
+    let mut input_file = File::open("data/mnist10.bin")?;
+    for i in 0..10 {
+        let mut image = vec![0u8; 784];
+        input_file.read_exact(&mut image)?;
+        let image = Array4::from_shape_vec((1, 28, 28, 1), image).unwrap();
+        interpreter.copy_from_array(input, &image)?;
+        assert_eq!(interpreter.tensor_view::<u8>(input)?.shape(), &[1, 28, 28, 1]);
+        interpreter.invoke()?;
+
+        let scores = interpreter.tensor_view::<u8>(output)?;
+        let scores = scores.index_axis(Axis(0), 0);
+        let guess = scores.iter().enumerate().max_by(|x, y| x.1.cmp(y.1)).unwrap().0;
+        assert_eq!(i, guess);
+    }
+    assert!(interpreter.copy_from_array(input, &Array4::<u8>::zeros((1, 28, 28, 3))).is_err());
+    Ok(())
+}
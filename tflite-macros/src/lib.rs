@@ -0,0 +1,240 @@
+//! `#[tflite_model("model.tflite")]` generates a wrapper around an interpreter with typed,
+//! named input setters and output getters for the given model. Use it through the `tflite`
+//! crate with the `macros` feature.
+
+extern crate proc_macro;
+
+mod schema;
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use proc_macro::{TokenStream, TokenTree};
+
+use schema::{read_model, Tensor};
+
+/// Generates typed bindings for a model. The path is relative to the crate root.
+///
+/// ```ignore
+/// #[tflite::tflite_model("data/MNISTnet_uint8_quant.tflite")]
+/// pub struct Mnist;
+///
+/// let mut mnist = Mnist::new(builder.build()?)?;
+/// mnist.set_input(&image)?; // &[u8; 784]
+/// mnist.invoke()?;
+/// let scores: &[u8; 10] = mnist.output();
+/// ```
+///
+/// Tensors of types other than `f32`, `u8` and `i32` get no accessor.
+#[proc_macro_attribute]
+pub fn tflite_model(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand(attr, item) {
+        Ok(tokens) => tokens,
+        Err(message) => format!("compile_error!({:?});", message).parse().unwrap(),
+    }
+}
+
+fn expand(attr: TokenStream, item: TokenStream) -> Result<TokenStream, String> {
+    let path = match attr.into_iter().collect::<Vec<_>>().as_slice() {
+        [TokenTree::Literal(lit)] => {
+            let lit = lit.to_string();
+            lit.strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .map(str::to_string)
+                .ok_or("expected a string literal path")?
+        }
+        _ => return Err("expected `#[tflite_model(\"path/to/model.tflite\")]`".to_string()),
+    };
+    let (visibility, name) = parse_unit_struct(item)?;
+
+    let root = std::env::var("CARGO_MANIFEST_DIR").map(PathBuf::from).unwrap_or_default();
+    let path = root.join(path);
+    let bytes =
+        std::fs::read(&path).map_err(|e| format!("cannot read `{}`: {}", path.display(), e))?;
+    let model = read_model(&bytes).map_err(|e| format!("`{}`: {}", path.display(), e))?;
+
+    Ok(generate(&visibility, &name, &path.to_string_lossy(), &model.inputs, &model.outputs)
+        .parse()
+        .unwrap())
+}
+
+/// Accepts `[pub[(..)]] struct Name;`, returning the visibility and name.
+fn parse_unit_struct(item: TokenStream) -> Result<(String, String), String> {
+    let tokens: Vec<TokenTree> = item.into_iter().collect();
+    let position = tokens
+        .iter()
+        .position(|t| matches!(t, TokenTree::Ident(i) if i.to_string() == "struct"))
+        .ok_or("`tflite_model` must be applied to a unit struct")?;
+    let visibility: Vec<String> = tokens[..position]
+        .iter()
+        .filter(|t| !matches!(t, TokenTree::Punct(p) if p.as_char() == '#'))
+        .filter(|t| !matches!(t, TokenTree::Group(g) if g.delimiter() == proc_macro::Delimiter::Bracket))
+        .map(ToString::to_string)
+        .collect();
+    match &tokens[position + 1..] {
+        [TokenTree::Ident(name), TokenTree::Punct(semi)] if semi.as_char() == ';' => {
+            Ok((visibility.join(" "), name.to_string()))
+        }
+        _ => Err("`tflite_model` must be applied to a unit struct like `struct Model;`".into()),
+    }
+}
+
+/// Turns a tensor name into a unique snake_case identifier.
+fn identifier(name: &str, index: usize, taken: &mut Vec<String>) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect();
+    ident = ident.trim_matches('_').to_string();
+    while ident.contains("__") {
+        ident = ident.replace("__", "_");
+    }
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident = format!("tensor_{}", ident);
+    }
+    if taken.contains(&ident) {
+        ident = format!("{}_{}", ident, index);
+    }
+    taken.push(ident.clone());
+    ident
+}
+
+fn generate(
+    visibility: &str,
+    name: &str,
+    path: &str,
+    inputs: &[Tensor],
+    outputs: &[Tensor],
+) -> String {
+    let mut out = String::new();
+    let mut methods = String::new();
+    let mut checks = String::new();
+
+    let mut taken = Vec::new();
+    let single = inputs.len() == 1;
+    for (i, tensor) in inputs.iter().enumerate() {
+        let ident =
+            if single { "input".to_string() } else { identifier(&tensor.name, i, &mut taken) };
+        write_check(&mut checks, "inputs", i, tensor);
+        if let Some(ty) = tensor.rust_type() {
+            let len = tensor.len();
+            write!(
+                methods,
+                "/// Writes input {i} `{tname}` with shape {shape:?}.
+                pub fn set_{ident}(&mut self, data: &[{ty}; {len}]) -> ::tflite::Result<()> {{
+                    let index = self.interpreter.inputs()[{i}];
+                    self.interpreter.tensor_data_mut::<{ty}>(index)?.copy_from_slice(data);
+                    Ok(())
+                }}
+                /// Mutable access to input {i} `{tname}`.
+                pub fn {ident}_mut(&mut self) -> &mut [{ty}; {len}] {{
+                    let index = self.interpreter.inputs()[{i}];
+                    let data = self.interpreter.tensor_data_mut::<{ty}>(index).unwrap();
+                    ::std::convert::TryInto::try_into(data).unwrap()
+                }}\n",
+                i = i,
+                tname = tensor.name,
+                shape = tensor.shape,
+                ident = ident,
+                ty = ty,
+                len = len,
+            )
+            .unwrap();
+        }
+    }
+
+    let mut taken = Vec::new();
+    let single = outputs.len() == 1;
+    for (i, tensor) in outputs.iter().enumerate() {
+        let ident =
+            if single { "output".to_string() } else { identifier(&tensor.name, i, &mut taken) };
+        write_check(&mut checks, "outputs", i, tensor);
+        if let Some(ty) = tensor.rust_type() {
+            write!(
+                methods,
+                "/// Reads output {i} `{tname}` with shape {shape:?}.
+                pub fn {ident}(&self) -> &[{ty}; {len}] {{
+                    let index = self.interpreter.outputs()[{i}];
+                    let data = self.interpreter.tensor_data::<{ty}>(index).unwrap();
+                    ::std::convert::TryInto::try_into(data).unwrap()
+                }}\n",
+                i = i,
+                tname = tensor.name,
+                shape = tensor.shape,
+                ident = ident,
+                ty = ty,
+                len = tensor.len(),
+            )
+            .unwrap();
+        }
+    }
+
+    write!(
+        out,
+        "/// Typed bindings for `{path}`.
+        {vis} struct {name}<'a, Op: ::tflite::op_resolver::OpResolver> {{
+            interpreter: ::tflite::Interpreter<'a, Op>,
+        }}
+
+        impl<'a, Op: ::tflite::op_resolver::OpResolver> {name}<'a, Op> {{
+            /// The model the bindings were generated from.
+            pub const MODEL: &'static [u8] = include_bytes!({path:?});
+
+            /// Wraps `interpreter`, checking that its tensors match the model.
+            pub fn new(interpreter: ::tflite::Interpreter<'a, Op>) -> ::tflite::Result<Self> {{
+                if interpreter.inputs().len() != {ninputs} || interpreter.outputs().len() != {noutputs} {{
+                    return Err(::tflite::Error::internal_error(
+                        \"interpreter inputs/outputs do not match the model\"));
+                }}
+                {checks}
+                Ok(Self {{ interpreter }})
+            }}
+
+            pub fn invoke(&mut self) -> ::tflite::Result<()> {{
+                self.interpreter.invoke()
+            }}
+
+            pub fn interpreter(&self) -> &::tflite::Interpreter<'a, Op> {{
+                &self.interpreter
+            }}
+
+            pub fn interpreter_mut(&mut self) -> &mut ::tflite::Interpreter<'a, Op> {{
+                &mut self.interpreter
+            }}
+
+            pub fn into_inner(self) -> ::tflite::Interpreter<'a, Op> {{
+                self.interpreter
+            }}
+
+            {methods}
+        }}",
+        path = path,
+        vis = visibility,
+        name = name,
+        ninputs = inputs.len(),
+        noutputs = outputs.len(),
+        checks = checks,
+        methods = methods,
+    )
+    .unwrap();
+    out
+}
+
+fn write_check(out: &mut String, list: &str, i: usize, tensor: &Tensor) {
+    write!(
+        out,
+        "{{
+            let info = interpreter.tensor_info(interpreter.{list}()[{i}])
+                .ok_or_else(|| ::tflite::Error::internal_error(\"invalid tensor index\"))?;
+            if info.dims != [{dims}] {{
+                return Err(::tflite::Error::InternalError(format!(
+                    \"{list} {i} has dims {{:?}}, the model declares {shape:?}\", info.dims)));
+            }}
+        }}\n",
+        list = list,
+        i = i,
+        dims = tensor.shape.iter().map(|d| format!("{}usize", d)).collect::<Vec<_>>().join(", "),
+        shape = tensor.shape,
+    )
+    .unwrap();
+}
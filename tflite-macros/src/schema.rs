@@ -0,0 +1,131 @@
+//! Just enough of the TFLite flatbuffer schema to find the primary subgraph's inputs and
+//! outputs.
+
+use std::convert::TryInto;
+
+pub struct Tensor {
+    pub name: String,
+    pub shape: Vec<usize>,
+    /// `TensorType` of the schema.
+    pub typ: u8,
+}
+
+impl Tensor {
+    pub fn rust_type(&self) -> Option<&'static str> {
+        match self.typ {
+            0 => Some("f32"),
+            2 => Some("i32"),
+            3 => Some("u8"),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+}
+
+pub struct ModelIo {
+    pub inputs: Vec<Tensor>,
+    pub outputs: Vec<Tensor>,
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn u16(&self, pos: usize) -> Result<u16, String> {
+        let bytes = self.0.get(pos..pos + 2).ok_or("truncated model")?;
+        Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32(&self, pos: usize) -> Result<u32, String> {
+        let bytes = self.0.get(pos..pos + 4).ok_or("truncated model")?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn table(&self, pos: usize) -> Result<usize, String> {
+        Ok(pos + self.u32(pos)? as usize)
+    }
+
+    /// Absolute position of field `id` of the table at `table`.
+    fn field(&self, table: usize, id: usize) -> Result<Option<usize>, String> {
+        let vtable = (table as i64 - i64::from(self.u32(table)? as i32)) as usize;
+        if 4 + 2 * id + 2 > self.u16(vtable)? as usize {
+            return Ok(None);
+        }
+        match self.u16(vtable + 4 + 2 * id)? {
+            0 => Ok(None),
+            offset => Ok(Some(table + offset as usize)),
+        }
+    }
+
+    /// `(first element, length)` of the vector in field `id`.
+    fn vector(&self, table: usize, id: usize) -> Result<(usize, usize), String> {
+        match self.field(table, id)? {
+            Some(pos) => {
+                let start = self.table(pos)?;
+                Ok((start + 4, self.u32(start)? as usize))
+            }
+            None => Ok((0, 0)),
+        }
+    }
+
+    fn i32s(&self, table: usize, id: usize) -> Result<Vec<i32>, String> {
+        let (start, len) = self.vector(table, id)?;
+        (0..len).map(|i| self.u32(start + 4 * i).map(|v| v as i32)).collect()
+    }
+
+    fn string(&self, table: usize, id: usize) -> Result<String, String> {
+        let (start, len) = self.vector(table, id)?;
+        let bytes = self.0.get(start..start + len).ok_or("truncated model")?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+pub fn read_model(bytes: &[u8]) -> Result<ModelIo, String> {
+    if bytes.get(4..8) != Some(&b"TFL3"[..]) {
+        return Err("not a TFLite model".to_string());
+    }
+    let reader = Reader(bytes);
+    let model = reader.table(0)?;
+    let (subgraphs, count) = reader.vector(model, 2)?;
+    if count == 0 {
+        return Err("model has no subgraphs".to_string());
+    }
+    let subgraph = reader.table(subgraphs)?;
+    let (tensors, num_tensors) = reader.vector(subgraph, 0)?;
+
+    let tensor = |index: i32| -> Result<Tensor, String> {
+        let index = index as usize;
+        if index >= num_tensors {
+            return Err(format!("tensor index {} out of range", index));
+        }
+        let table = reader.table(tensors + 4 * index)?;
+        let shape = reader.i32s(table, 0)?.into_iter().map(|d| d.max(0) as usize).collect();
+        let typ = match reader.field(table, 1)? {
+            Some(pos) => *bytes.get(pos).ok_or("truncated model")?,
+            None => 0,
+        };
+        Ok(Tensor { name: reader.string(table, 3)?, shape, typ })
+    };
+
+    Ok(ModelIo {
+        inputs: reader.i32s(subgraph, 1)?.into_iter().map(tensor).collect::<Result<_, _>>()?,
+        outputs: reader.i32s(subgraph, 2)?.into_iter().map(tensor).collect::<Result<_, _>>()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unittest_read_model() {
+        let bytes = std::fs::read("../data/MNISTnet_uint8_quant.tflite").unwrap();
+        let model = read_model(&bytes).unwrap();
+        assert_eq!(model.inputs.len(), 1);
+        assert_eq!(model.inputs[0].shape, vec![1, 28, 28, 1]);
+        assert_eq!(model.inputs[0].rust_type(), Some("u8"));
+        assert_eq!(model.outputs[0].shape, vec![1, 10]);
+    }
+}